@@ -2,16 +2,82 @@ use crate::command::AppState;
 use mirrorx_core::{
     api::{
         config::{
-            entity::{domain::Domain, history::Record, kv::Theme},
-            LocalStorage,
+            device_name::{device_name, set_device_name, validate_device_name},
+            device_tag::{device_tag, set_device_tag, validate_device_tag},
+            entity::{
+                allow_list::AllowedDevice, connection_history::ConnectionHistoryEntry,
+                domain::Domain, history::Record, kv::Theme,
+            },
+            LocalStorage, Storage,
+        },
+        endpoint::{
+            bandwidth::{
+                bandwidth_aggressiveness, set_bandwidth_aggressiveness, BandwidthAggressiveness,
+            },
+            call_timeout::{call_timeouts, set_call_timeouts, CallTimeouts},
+            capabilities::preferred_video_codec,
+            client_cache::{reconnect_policy, set_reconnect_policy, ReconnectPolicy},
+            connect_retry::{connect_retry_config, set_connect_retry_config, ConnectRetryConfig},
+            heartbeat::{heartbeat_config, set_heartbeat_config, HeartbeatConfig},
+            idle::{idle_timeout_config, set_idle_timeout_config, IdleTimeoutConfig},
+            packet::{max_frame_length, sanitize_max_frame_length, set_max_frame_length},
+            path_preference::{path_preference, set_path_preference, PathPreference},
+            rekey::{rekey_policy, set_rekey_policy, RekeyPolicy},
+            session_limit::{max_incoming_sessions, set_max_incoming_sessions},
+            session_token::session_tokens,
+            socket_buffer::{set_socket_buffer_config, socket_buffer_config, SocketBufferConfig},
+        },
+        signaling::{
+            access_code::{access_mode, set_access_mode, AccessMode},
+            circuit_breaker::CircuitBreakerConfig,
+            http_message::Response,
+            identity::{
+                device_identity, mutual_auth_config, set_device_identity, set_mutual_auth_config,
+                DeviceIdentity, MutualAuthConfig,
+            },
+            transport::SignalingTransport,
+        },
+    },
+    component::{
+        desktop::{
+            monitor::{get_active_monitors, select_primary_monitor},
+            virtual_display::{
+                set_virtual_display_command, virtual_display_command, VirtualDisplayCommand,
+            },
+        },
+        fs::{
+            browse::{file_browse_policy, set_file_browse_policy, FileBrowsePolicy},
+            cache::{
+                cache_config, enforce_cache_budget, set_cache_config, set_default_cache_root,
+                CacheConfig,
+            },
+            window::{file_chunk_size, set_file_chunk_size},
+        },
+        input::coalesce::{input_coalesce_window, set_input_coalesce_window},
+        video_decoder::{
+            budget::{media_budget_bytes, set_media_budget_bytes},
+            rate_limit::{decode_fps_cap, set_decode_fps_cap},
+            thumbnail::{set_thumbnail_config, thumbnail_config, ThumbnailConfig},
+        },
+        video_encoder::{
+            adapter::{pin_graphics_card, select_encoder_config},
+            media_config::{MediaConfig, QualityPreset},
+            preprocess::{privacy_regions, set_privacy_regions, PrivacyRegion},
+            validate::{validate_media_config, MediaConfigReport},
         },
-        signaling::http_message::Response,
     },
     core_error,
     error::CoreResult,
+    utility::{
+        error_event::{
+            error_event_window, set_error_event_window, MAX_ERROR_EVENT_WINDOW,
+            MIN_ERROR_EVENT_WINDOW,
+        },
+        thread_priority::{media_thread_priority, set_media_thread_priority},
+    },
 };
 use serde::{Deserialize, Serialize};
-use std::net::SocketAddr;
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tauri::{
     http::Uri, AppHandle, CustomMenuItem, Manager, State, SystemTrayMenu, SystemTrayMenuItem,
     Window,
@@ -34,6 +100,18 @@ pub async fn config_init(
     let storage = LocalStorage::new(config_dir.join("mirrorx.db"))?;
     let domain_count = storage.domain().get_domain_count()?;
 
+    if let Some(cache_dir) = app_handle.path_resolver().app_cache_dir() {
+        tracing::info!(path = ?cache_dir, "cache dir");
+        set_default_cache_root(cache_dir);
+    }
+
+    apply_local_settings(&storage)?;
+
+    // files of an earlier run may exceed a budget lowered since
+    if let Err(err) = enforce_cache_budget() {
+        tracing::warn!(?err, "enforce cache budget failed");
+    }
+
     let mut storage_guard = app_state.storage.lock().await;
     *storage_guard = Some(storage);
     drop(storage_guard);
@@ -51,6 +129,75 @@ pub async fn config_init(
     Ok(())
 }
 
+/// Loads the settings that live in process wide state rather than being read
+/// from storage on demand.
+pub fn apply_local_settings(storage: &LocalStorage) -> CoreResult<()> {
+    pin_graphics_card(storage.kv().get_encoder_graphics_card()?);
+
+    if let Some(limit) = storage.kv().get_max_incoming_sessions()? {
+        set_max_incoming_sessions(limit);
+    }
+
+    if let Some(max_frame_length) = storage.kv().get_max_frame_length()? {
+        set_max_frame_length(max_frame_length);
+    }
+
+    set_privacy_regions(storage.kv().get_privacy_regions()?);
+    set_idle_timeout_config(storage.kv().get_idle_timeout_config()?);
+    set_file_browse_policy(storage.kv().get_file_browse_policy()?);
+    set_access_mode(storage.kv().get_access_mode()?);
+    set_device_name(storage.kv().get_device_name()?);
+    set_device_tag(storage.kv().get_device_tag()?);
+    set_bandwidth_aggressiveness(storage.kv().get_bandwidth_aggressiveness()?);
+    set_path_preference(storage.kv().get_path_preference()?);
+    set_connect_retry_config(storage.kv().get_connect_retry_config()?);
+    set_heartbeat_config(storage.kv().get_heartbeat_config()?);
+    set_call_timeouts(storage.kv().get_call_timeouts()?);
+    set_rekey_policy(storage.kv().get_rekey_policy()?);
+    set_socket_buffer_config(storage.kv().get_socket_buffer_config()?);
+    set_media_thread_priority(storage.kv().get_media_thread_priority()?.unwrap_or(false));
+    set_virtual_display_command(storage.kv().get_virtual_display_command()?);
+    set_thumbnail_config(storage.kv().get_thumbnail_config()?);
+    set_cache_config(storage.kv().get_cache_config()?);
+    set_mutual_auth_config(storage.kv().get_mutual_auth_config()?);
+    set_device_identity(Some(Arc::new(DeviceIdentity::load_or_generate(
+        storage.kv(),
+    )?)));
+
+    if let Some(chunk_size) = storage.kv().get_file_chunk_size()? {
+        set_file_chunk_size(chunk_size);
+    }
+
+    if let Some(window_ms) = storage.kv().get_input_coalesce_window_ms()? {
+        set_input_coalesce_window(Duration::from_millis(window_ms));
+    }
+
+    if let Some(fps_cap) = storage.kv().get_decode_fps_cap()? {
+        set_decode_fps_cap(fps_cap);
+    }
+
+    if let Some(budget_mb) = storage.kv().get_media_budget_mb()? {
+        set_media_budget_bytes(budget_mb * 1024 * 1024);
+    }
+
+    if let Some(window_secs) = storage.kv().get_session_rejoin_window_secs()? {
+        session_tokens().set_window(Duration::from_secs(window_secs));
+    }
+
+    if let Some(window_secs) = storage.kv().get_error_event_window_secs()? {
+        set_error_event_window(Duration::from_secs(window_secs));
+    }
+
+    if let Some(max_duration_secs) = storage.kv().get_reconnect_max_duration_secs()? {
+        set_reconnect_policy(ReconnectPolicy {
+            max_duration: Duration::from_secs(max_duration_secs),
+            ..reconnect_policy()
+        });
+    }
+
+    Ok(())
+}
+
 #[tauri::command]
 #[tracing::instrument(skip(app_state))]
 pub async fn config_domain_get(app_state: State<'_, AppState>) -> CoreResult<Domain> {
@@ -155,6 +302,9 @@ pub async fn config_domain_delete(id: i64, app_state: State<'_, AppState>) -> Co
     let domain = storage.domain().get_domain_by_id(id)?;
     storage.domain().delete_domain(id)?;
     storage.history().delete_domain_related(&domain.name)?;
+    storage
+        .connection_history()
+        .delete_domain_related(&domain.name)?;
 
     Ok(())
 }
@@ -187,7 +337,7 @@ pub struct ConfigDomainUpdateRequest {
     pub update_type: ConfigDomainUpdateType,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ConfigDomainUpdateType {
     SetPrimary,
@@ -195,6 +345,19 @@ pub enum ConfigDomainUpdateType {
     Remarks(String),
 }
 
+// the request is recorded by the tracing span, the new password stays out
+impl std::fmt::Debug for ConfigDomainUpdateType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigDomainUpdateType::SetPrimary => f.write_str("SetPrimary"),
+            ConfigDomainUpdateType::Password(_) => f.write_str("Password(..)"),
+            ConfigDomainUpdateType::Remarks(remarks) => {
+                f.debug_tuple("Remarks").field(remarks).finish()
+            }
+        }
+    }
+}
+
 #[tauri::command]
 #[tracing::instrument(skip(app_state))]
 pub async fn config_domain_update(
@@ -349,16 +512,1070 @@ pub async fn config_theme_set(app_state: State<'_, AppState>, theme: Theme) -> C
 
 #[tauri::command]
 #[tracing::instrument(skip(app_state))]
-pub async fn config_history_get(
+pub async fn config_media_get(app_state: State<'_, AppState>) -> CoreResult<MediaConfig> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    Ok(storage.kv().get_media_config()?.unwrap_or_default())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_media_set(
     app_state: State<'_, AppState>,
-    time_range: Option<(i64, i64)>,
-) -> CoreResult<Vec<Record>> {
+    media_config: MediaConfig,
+) -> CoreResult<()> {
     let Some(ref storage) = *app_state.storage.lock().await else {
         return Err(core_error!("storage not initialize"));
     };
 
-    tracing::info!(?time_range, "query");
-    let records = storage.history().query(time_range)?;
+    // fields edited after choosing a preset no longer describe that preset,
+    // the pointer, FEC, the x264 knobs, the content hint, the shared
+    // window, the slices and the change only mode aren't part of any preset
+    let mut media_config = media_config.sanitize();
+    let preset_config = MediaConfig {
+        include_cursor: media_config.include_cursor,
+        fec_level: media_config.fec_level,
+        x264_preset: media_config.x264_preset.clone(),
+        x264_tune: media_config.x264_tune.clone(),
+        content_hint: media_config.content_hint,
+        capture_window: media_config.capture_window,
+        slices_per_frame: media_config.slices_per_frame,
+        change_only: media_config.change_only,
+        change_threshold: media_config.change_threshold,
+        ..MediaConfig::from_preset(media_config.preset)
+    };
+    if media_config != preset_config {
+        media_config.preset = QualityPreset::Custom;
+    }
 
-    Ok(records)
+    storage.kv().set_media_config(&media_config)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_media_preset_set(
+    app_state: State<'_, AppState>,
+    preset: QualityPreset,
+) -> CoreResult<MediaConfig> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let stored_config = storage.kv().get_media_config()?.unwrap_or_default();
+
+    let media_config = MediaConfig {
+        include_cursor: stored_config.include_cursor,
+        fec_level: stored_config.fec_level,
+        x264_preset: stored_config.x264_preset,
+        x264_tune: stored_config.x264_tune,
+        content_hint: stored_config.content_hint,
+        capture_window: stored_config.capture_window,
+        slices_per_frame: stored_config.slices_per_frame,
+        change_only: stored_config.change_only,
+        change_threshold: stored_config.change_threshold,
+        ..MediaConfig::from_preset(preset)
+    };
+    storage.kv().set_media_config(&media_config)?;
+
+    Ok(media_config)
+}
+
+/// Checks a media config against the encoder and the monitor sessions
+/// would use, the primary one unless `monitor_id` is given, without
+/// capturing anything.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn config_media_validate(
+    media_config: MediaConfig,
+    monitor_id: Option<String>,
+) -> CoreResult<MediaConfigReport> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    tokio::task::spawn_blocking(move || {
+        let report = get_active_monitors(false).and_then(|monitors| {
+            let monitor = match monitor_id {
+                Some(monitor_id) => monitors
+                    .into_iter()
+                    .find(|monitor| monitor.id == monitor_id)
+                    .ok_or_else(|| core_error!("monitor not found"))?,
+                None => select_primary_monitor(monitors)?,
+            };
+
+            Ok(validate_media_config(
+                &media_config,
+                monitor.width as i32,
+                monitor.height as i32,
+                &select_encoder_config(&preferred_video_codec()),
+            ))
+        });
+
+        let _ = tx.send(report);
+    });
+
+    rx.await?
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_encoder_graphics_card_get(
+    app_state: State<'_, AppState>,
+) -> CoreResult<Option<String>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().get_encoder_graphics_card()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_encoder_graphics_card_set(
+    app_state: State<'_, AppState>,
+    graphics_card_id: Option<String>,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage
+        .kv()
+        .set_encoder_graphics_card(graphics_card_id.as_deref())?;
+    pin_graphics_card(graphics_card_id);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_max_incoming_sessions_get() -> usize {
+    max_incoming_sessions()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_max_incoming_sessions_set(
+    app_state: State<'_, AppState>,
+    limit: usize,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_max_incoming_sessions(limit)?;
+    set_max_incoming_sessions(limit);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_max_frame_length_get() -> usize {
+    max_frame_length()
+}
+
+/// Longest frame in bytes connections accept, a keyframe of a large multi
+/// monitor capture may need more than the default. Applies to connections
+/// opened afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_max_frame_length_set(
+    app_state: State<'_, AppState>,
+    max_frame_length: usize,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let max_frame_length = sanitize_max_frame_length(max_frame_length);
+    storage.kv().set_max_frame_length(max_frame_length)?;
+    set_max_frame_length(max_frame_length);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_privacy_regions_get() -> Vec<PrivacyRegion> {
+    privacy_regions()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_privacy_regions_set(
+    app_state: State<'_, AppState>,
+    regions: Vec<PrivacyRegion>,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_privacy_regions(&regions)?;
+    set_privacy_regions(regions);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_idle_timeout_get() -> IdleTimeoutConfig {
+    idle_timeout_config()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_idle_timeout_set(
+    app_state: State<'_, AppState>,
+    config: IdleTimeoutConfig,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_idle_timeout_config(&config)?;
+    set_idle_timeout_config(config);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_input_coalesce_get() -> u64 {
+    input_coalesce_window().as_millis() as u64
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_input_coalesce_set(
+    app_state: State<'_, AppState>,
+    window_ms: u64,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    // longer than a few frames the pointer visibly lags behind the hand
+    if window_ms > 100 {
+        return Err(core_error!("input coalesce window must not exceed 100ms"));
+    }
+
+    storage.kv().set_input_coalesce_window_ms(window_ms)?;
+    set_input_coalesce_window(Duration::from_millis(window_ms));
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_decode_fps_cap_get() -> u32 {
+    decode_fps_cap()
+}
+
+/// Zero renders every frame the remote side sends.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_decode_fps_cap_set(
+    app_state: State<'_, AppState>,
+    fps_cap: u32,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_decode_fps_cap(fps_cap)?;
+    set_decode_fps_cap(fps_cap);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_thumbnail_get() -> ThumbnailConfig {
+    thumbnail_config()
+}
+
+/// Thumbnails of the remote screens, emitted with the `session_thumbnail`
+/// event. Applies to the next thumbnail of every session.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_thumbnail_set(
+    app_state: State<'_, AppState>,
+    config: ThumbnailConfig,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let config = config.sanitize();
+    storage.kv().set_thumbnail_config(&config)?;
+    set_thumbnail_config(config);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_cache_get() -> CacheConfig {
+    cache_config()
+}
+
+/// Where frame dumps and other files written on their own go, and how much
+/// they may take. A new root applies to files written afterwards, a lower
+/// budget evicts right away.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_cache_set(
+    app_state: State<'_, AppState>,
+    config: CacheConfig,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let config = config.sanitize();
+    storage.kv().set_cache_config(&config)?;
+    set_cache_config(config);
+
+    enforce_cache_budget()?;
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_mutual_auth_get() -> MutualAuthConfig {
+    mutual_auth_config()
+}
+
+/// Applies to visits started afterwards, both to visit and to be visited.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_mutual_auth_set(
+    app_state: State<'_, AppState>,
+    config: MutualAuthConfig,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let config = config.sanitize();
+    storage.kv().set_mutual_auth_config(&config)?;
+    set_mutual_auth_config(config);
+
+    Ok(())
+}
+
+/// Base64 of the public key of this device, added to the trusted devices
+/// of the devices it visits or is visited by.
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_device_public_key_get() -> CoreResult<String> {
+    let Some(identity) = device_identity() else {
+        return Err(core_error!("device identity not initialize"));
+    };
+
+    Ok(base64::encode(identity.public_key()))
+}
+
+/// Bytes per block of a file transfer.
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_file_chunk_size_get() -> usize {
+    file_chunk_size()
+}
+
+/// Clamped to what transfers support. Applies to transfers started
+/// afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_file_chunk_size_set(
+    app_state: State<'_, AppState>,
+    chunk_size: usize,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    set_file_chunk_size(chunk_size);
+    storage.kv().set_file_chunk_size(file_chunk_size())?;
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_session_rejoin_get() -> u64 {
+    session_tokens().window().as_secs()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_session_rejoin_set(
+    app_state: State<'_, AppState>,
+    window_secs: u64,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    // a leaked token stays usable that long after its session dropped
+    if window_secs > 600 {
+        return Err(core_error!("session rejoin window must not exceed 600s"));
+    }
+
+    storage.kv().set_session_rejoin_window_secs(window_secs)?;
+    session_tokens().set_window(Duration::from_secs(window_secs));
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_error_event_window_get() -> u64 {
+    error_event_window().as_secs()
+}
+
+/// Repeats of an error within the window reach the UI as one `error_event`
+/// with their count.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_error_event_window_set(
+    app_state: State<'_, AppState>,
+    window_secs: u64,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let window = Duration::from_secs(window_secs);
+    if window < MIN_ERROR_EVENT_WINDOW || window > MAX_ERROR_EVENT_WINDOW {
+        return Err(core_error!(
+            "error event window must be within {}s and {}s",
+            MIN_ERROR_EVENT_WINDOW.as_secs(),
+            MAX_ERROR_EVENT_WINDOW.as_secs()
+        ));
+    }
+
+    storage.kv().set_error_event_window_secs(window_secs)?;
+    set_error_event_window(window);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_reconnect_max_duration_get() -> u64 {
+    reconnect_policy().max_duration.as_secs()
+}
+
+/// How long a dead connection is reconnected before it's given up with a
+/// `connection_lost` event.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_reconnect_max_duration_set(
+    app_state: State<'_, AppState>,
+    max_duration_secs: u64,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    if max_duration_secs == 0 || max_duration_secs > 600 {
+        return Err(core_error!(
+            "reconnect max duration must be within 1s and 600s"
+        ));
+    }
+
+    storage
+        .kv()
+        .set_reconnect_max_duration_secs(max_duration_secs)?;
+    set_reconnect_policy(ReconnectPolicy {
+        max_duration: Duration::from_secs(max_duration_secs),
+        ..reconnect_policy()
+    });
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_signaling_transport_get(
+    app_state: State<'_, AppState>,
+) -> CoreResult<SignalingTransport> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().get_signaling_transport()
+}
+
+/// Takes effect when signaling connects the next time, reconnect with
+/// `signaling_connect(force: true)` to switch right away.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_signaling_transport_set(
+    app_state: State<'_, AppState>,
+    transport: SignalingTransport,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_signaling_transport(transport)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_signaling_circuit_breaker_get(
+    app_state: State<'_, AppState>,
+) -> CoreResult<CircuitBreakerConfig> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().get_signaling_circuit_breaker()
+}
+
+/// Applies to the next calls of the connected signaling client too.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_signaling_circuit_breaker_set(
+    app_state: State<'_, AppState>,
+    config: CircuitBreakerConfig,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let config = config.sanitize();
+    storage.kv().set_signaling_circuit_breaker(config)?;
+
+    if let Some((_, ref signaling_client)) = *app_state.signaling_client.lock().await {
+        signaling_client.set_circuit_breaker_config(config);
+    }
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_bandwidth_aggressiveness_get() -> BandwidthAggressiveness {
+    bandwidth_aggressiveness()
+}
+
+/// Applies to sessions started afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_bandwidth_aggressiveness_set(
+    app_state: State<'_, AppState>,
+    aggressiveness: BandwidthAggressiveness,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_bandwidth_aggressiveness(aggressiveness)?;
+    set_bandwidth_aggressiveness(aggressiveness);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_connect_retry_get() -> ConnectRetryConfig {
+    connect_retry_config()
+}
+
+/// Attempts and backoff of the connects to remote devices, for LAN
+/// devices and through the endpoints server. Applies to connections opened
+/// afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_connect_retry_set(
+    app_state: State<'_, AppState>,
+    config: ConnectRetryConfig,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let config = config.sanitize();
+    storage.kv().set_connect_retry_config(config)?;
+    set_connect_retry_config(config);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_heartbeat_get() -> HeartbeatConfig {
+    heartbeat_config()
+}
+
+/// Probe interval of the connections and the intervals in a row without
+/// hearing from the remote side before one is closed. Applies to
+/// connections opened afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_heartbeat_set(
+    app_state: State<'_, AppState>,
+    config: HeartbeatConfig,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let config = config.sanitize();
+    storage.kv().set_heartbeat_config(config)?;
+    set_heartbeat_config(config);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_call_timeouts_get() -> CallTimeouts {
+    call_timeouts()
+}
+
+/// How long the handshake, the media start, the calls and the key
+/// rotations of a connection wait for the remote side. Applies to
+/// connections opened afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_call_timeouts_set(
+    app_state: State<'_, AppState>,
+    timeouts: CallTimeouts,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let timeouts = timeouts.sanitize();
+    storage.kv().set_call_timeouts(timeouts)?;
+    set_call_timeouts(timeouts);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_rekey_get() -> RekeyPolicy {
+    rekey_policy()
+}
+
+/// How long and for how many bytes the session keys of a connection are
+/// used before they're rotated. Applies to connections opened afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_rekey_set(
+    app_state: State<'_, AppState>,
+    policy: RekeyPolicy,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let policy = policy.sanitize();
+    storage.kv().set_rekey_policy(policy)?;
+    set_rekey_policy(policy);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_path_preference_get() -> PathPreference {
+    path_preference()
+}
+
+/// Pins the path of the connections opened afterwards, the session info
+/// reports the path a session took and why.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_path_preference_set(
+    app_state: State<'_, AppState>,
+    preference: PathPreference,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_path_preference(preference)?;
+    set_path_preference(preference);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_socket_buffer_get() -> SocketBufferConfig {
+    socket_buffer_config()
+}
+
+/// Applies to connections opened afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_socket_buffer_set(
+    app_state: State<'_, AppState>,
+    config: SocketBufferConfig,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let config = config.sanitize();
+    storage.kv().set_socket_buffer_config(config)?;
+    set_socket_buffer_config(config);
+
+    Ok(())
+}
+
+/// Memory budget of the video of a session in MiB, zero sizes it from the
+/// resolution.
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_media_budget_get() -> u64 {
+    media_budget_bytes() / 1024 / 1024
+}
+
+/// Applies to sessions started afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_media_budget_set(
+    app_state: State<'_, AppState>,
+    budget_mb: u64,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_media_budget_mb(budget_mb)?;
+    set_media_budget_bytes(budget_mb * 1024 * 1024);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_media_thread_priority_get() -> bool {
+    media_thread_priority()
+}
+
+/// Runs the capture and encode threads of shared screens at the highest
+/// priority, applies to sessions started afterwards. Whether the system
+/// allowed it is reported per session with the `thread_priority` event.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_media_thread_priority_set(
+    app_state: State<'_, AppState>,
+    enabled: bool,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_media_thread_priority(enabled)?;
+    set_media_thread_priority(enabled);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_virtual_display_command_get() -> Option<VirtualDisplayCommand> {
+    virtual_display_command()
+}
+
+/// Command adding a virtual display when a visitor asks for the screen of
+/// this device while no display is attached, `None` reports the missing
+/// display instead. Only used on Windows.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_virtual_display_command_set(
+    app_state: State<'_, AppState>,
+    command: Option<VirtualDisplayCommand>,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_virtual_display_command(command.as_ref())?;
+    set_virtual_display_command(command);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_chat_history_get(app_state: State<'_, AppState>) -> CoreResult<bool> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    Ok(storage.kv().get_chat_history_enabled()?.unwrap_or(false))
+}
+
+/// Turning the history off stops writing chat messages, the ones written
+/// stay until they are deleted.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_chat_history_set(
+    app_state: State<'_, AppState>,
+    enabled: bool,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_chat_history_enabled(enabled)
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_file_browse_get() -> FileBrowsePolicy {
+    file_browse_policy()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_file_browse_set(
+    app_state: State<'_, AppState>,
+    policy: FileBrowsePolicy,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    if policy.allowed {
+        match policy.root {
+            Some(ref root) if root.is_dir() => {}
+            _ => return Err(core_error!("exposed root must be an existing directory")),
+        }
+    }
+
+    storage.kv().set_file_browse_policy(&policy)?;
+    set_file_browse_policy(policy);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_access_mode_get() -> AccessMode {
+    access_mode()
+}
+
+/// Whether visits open with the device password, the one-time code or
+/// either. Applies to visits answered afterwards.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_access_mode_set(
+    app_state: State<'_, AppState>,
+    mode: AccessMode,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_access_mode(mode)?;
+    set_access_mode(mode);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_device_name_get() -> String {
+    device_name()
+}
+
+/// The name this device shows to other devices, `None` goes back to the
+/// host name.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_device_name_set(
+    app_state: State<'_, AppState>,
+    name: Option<String>,
+) -> CoreResult<()> {
+    let name = name.as_deref().map(validate_device_name).transpose()?;
+
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_device_name(name.as_deref())?;
+    set_device_name(name);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn config_device_tag_get() -> Option<String> {
+    device_tag()
+}
+
+/// The tag other devices on the LAN can filter this one by, `None` removes
+/// it.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_device_tag_set(
+    app_state: State<'_, AppState>,
+    tag: Option<String>,
+) -> CoreResult<()> {
+    let tag = tag.as_deref().map(validate_device_tag).transpose()?;
+
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_device_tag(tag.as_deref())?;
+    set_device_tag(tag);
+
+    Ok(())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_history_get(
+    app_state: State<'_, AppState>,
+    time_range: Option<(i64, i64)>,
+) -> CoreResult<Vec<Record>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    tracing::info!(?time_range, "query");
+    let records = storage.history().query(time_range)?;
+
+    Ok(records)
+}
+
+/// Devices connected to before, pinned ones first, then the latest.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_connection_history_get(
+    app_state: State<'_, AppState>,
+) -> CoreResult<Vec<ConnectionHistoryEntry>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.connection_history().query()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_connection_history_pin(
+    app_state: State<'_, AppState>,
+    remote: String,
+    pinned: bool,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage
+        .connection_history()
+        .set_pinned(&remote.replace('-', ""), pinned)
+}
+
+/// An empty nickname removes it.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_connection_history_rename(
+    app_state: State<'_, AppState>,
+    remote: String,
+    nickname: Option<String>,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage
+        .connection_history()
+        .set_nickname(&remote.replace('-', ""), nickname.as_deref())
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_connection_history_delete(
+    app_state: State<'_, AppState>,
+    remote: String,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage
+        .connection_history()
+        .delete(&remote.replace('-', ""))
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_allow_list_get(
+    app_state: State<'_, AppState>,
+) -> CoreResult<Vec<AllowedDevice>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.allow_list().query()
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_allow_list_add(
+    app_state: State<'_, AppState>,
+    device_id: String,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage
+        .allow_list()
+        .add(device_id.replace('-', "").parse()?)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_allow_list_remove(
+    app_state: State<'_, AppState>,
+    device_id: String,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage
+        .allow_list()
+        .remove(device_id.replace('-', "").parse()?)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_allow_list_mode_get(app_state: State<'_, AppState>) -> CoreResult<bool> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    Ok(storage.kv().get_allow_list_enabled()?.unwrap_or(false))
+}
+
+/// While enabled only the devices on the allow list may visit, whoever
+/// else knows the password is rejected.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn config_allow_list_mode_set(
+    app_state: State<'_, AppState>,
+    enabled: bool,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.kv().set_allow_list_enabled(enabled)
 }
@@ -0,0 +1,398 @@
+use super::AppState;
+use crate::window::create_desktop_window;
+use mirrorx_core::{
+    api::{
+        config::Storage,
+        endpoint::{
+            chat::{chat, subscribe_chat_message, ChatEntry, CHAT_HISTORY_LIMIT},
+            clipboard::{clipboard_endpoint_ids, clipboard_sync, set_clipboard_sync},
+            id::EndPointID,
+            network_info::NetworkInfo,
+            sessions::desktop_sessions,
+            stats::EndPointStatsSnapshot,
+            swap::{self, swap_endpoint_ids, SwapOutcome},
+        },
+    },
+    bail_core,
+    component::{
+        desktop::window::{
+            list_windows, set_shared_window, shared_window, window_info, WindowInfo,
+        },
+        fs::cache::{cache_dir, enforce_cache_budget},
+        video_decoder::frame_dump::{
+            start_frame_dump, stop_frame_dump, FrameDumpLimits, FrameDumpSummary,
+        },
+        video_encoder::{content_hint::ContentHint, media_config::MediaConfig},
+    },
+    core_error,
+    error::{CoreError, CoreResult},
+};
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager, State};
+use tauri_egui::EguiPluginHandle;
+
+/// Moves input delivery to the session of the remote device, the other
+/// sessions keep rendering. LAN sessions are addressed by their remote ip.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_session_focus(remote_device_id: String) -> CoreResult<()> {
+    let endpoint_id = find_endpoint_id(desktop_sessions().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    desktop_sessions().focus(endpoint_id)
+}
+
+/// Tunes the encoder of the remote device for text or motion, or lets it
+/// detect the content. Takes effect with the next keyframe.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_content_hint_set(
+    remote_device_id: String,
+    content_hint: ContentHint,
+) -> CoreResult<()> {
+    let endpoint_id = find_endpoint_id(desktop_sessions().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    desktop_sessions().set_content_hint(endpoint_id, content_hint)
+}
+
+/// Stops the video of the remote device while the session keeps running,
+/// e.g. when its window is minimized.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_media_pause(remote_device_id: String) -> CoreResult<()> {
+    let endpoint_id = find_endpoint_id(desktop_sessions().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    desktop_sessions().set_media_paused(endpoint_id, true)
+}
+
+/// Starts the video of the remote device again, beginning with a keyframe.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_media_resume(remote_device_id: String) -> CoreResult<()> {
+    let endpoint_id = find_endpoint_id(desktop_sessions().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    desktop_sessions().set_media_paused(endpoint_id, false)
+}
+
+/// Records the encoded video the session receives into the cache, for
+/// decode problems that don't reproduce elsewhere. The dump holds the
+/// remote screen unredacted, so the user confirms with
+/// `accept_screen_content` after a warning. Returns the path of the stream.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_frame_dump_start(
+    remote_device_id: String,
+    limits: FrameDumpLimits,
+    accept_screen_content: bool,
+) -> CoreResult<PathBuf> {
+    if !accept_screen_content {
+        bail_core!("frame dump records the remote screen, it needs the user's consent");
+    }
+
+    let endpoint_id = find_endpoint_id(desktop_sessions().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    let codec = desktop_sessions()
+        .network_info(endpoint_id)?
+        .video_codec
+        .ok_or_else(|| core_error!("desktop session receives no video"))?;
+
+    let stream_path = start_frame_dump(endpoint_id, codec, &cache_dir("frame_dumps")?, limits)?;
+
+    // the running dump is kept, older ones make room for it
+    if let Err(err) = enforce_cache_budget() {
+        tracing::warn!(?err, "enforce cache budget failed");
+    }
+
+    Ok(stream_path)
+}
+
+/// Ends the frame dump of a session early, dumps also end at their limits
+/// and with the session, see the `frame_dump_finished` event.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_frame_dump_stop(
+    remote_device_id: String,
+) -> CoreResult<Option<FrameDumpSummary>> {
+    let endpoint_id = find_endpoint_id(desktop_sessions().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    stop_frame_dump(&endpoint_id)
+}
+
+/// Path, encryption, latency and throughput of a running desktop session,
+/// cheap enough to poll for an info panel.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_session_network_info(remote_device_id: String) -> CoreResult<NetworkInfo> {
+    let endpoint_id = find_endpoint_id(desktop_sessions().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    desktop_sessions().network_info(endpoint_id)
+}
+
+/// Round trip, bitrates and frame drops of a running desktop session, for
+/// the connection overlay to poll.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_session_stats(remote_device_id: String) -> CoreResult<EndPointStatsSnapshot> {
+    let endpoint_id = find_endpoint_id(desktop_sessions().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    desktop_sessions().stats(endpoint_id)
+}
+
+/// The name the remote device of a running desktop session shows to others,
+/// `None` until it told.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_session_remote_name(remote_device_id: String) -> CoreResult<Option<String>> {
+    let endpoint_id = find_endpoint_id(desktop_sessions().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    desktop_sessions().remote_device_name(endpoint_id)
+}
+
+/// The windows of this device that can be shared in place of the monitor.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_windows_list() -> CoreResult<Vec<WindowInfo>> {
+    list_windows()
+}
+
+/// The window this device shares in place of the monitor, `None` when it
+/// shares the monitor or the window was closed.
+#[tauri::command]
+#[tracing::instrument]
+pub fn desktop_shared_window_get() -> CoreResult<Option<WindowInfo>> {
+    Ok(shared_window().and_then(window_info))
+}
+
+/// Shares a window of this device in place of the monitor with the sessions
+/// started afterwards, `None` shares the monitor again.
+#[tauri::command]
+#[tracing::instrument]
+pub async fn desktop_shared_window_set(window_id: Option<u64>) -> CoreResult<()> {
+    if let Some(window_id) = window_id {
+        if window_info(window_id).is_none() {
+            return Err(CoreError::WindowClosed);
+        }
+    }
+
+    set_shared_window(window_id);
+    Ok(())
+}
+
+/// Sends a chat message to the remote device of a running desktop session,
+/// either side of the session may write.
+#[tauri::command]
+#[tracing::instrument(skip(app_state, text))]
+pub async fn desktop_chat_send(
+    app_state: State<'_, AppState>,
+    remote_device_id: String,
+    text: String,
+) -> CoreResult<ChatEntry> {
+    let endpoint_id = find_endpoint_id(chat().endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    let entry = chat().send(endpoint_id, text).await?;
+
+    if let Some(ref storage) = *app_state.storage.lock().await {
+        persist_chat_entry(storage, &entry);
+    }
+
+    Ok(entry)
+}
+
+/// Messages exchanged with the remote device since this app started.
+#[tauri::command]
+#[tracing::instrument]
+pub fn desktop_chat_history_get(remote_device_id: String) -> Vec<ChatEntry> {
+    match find_endpoint_id(chat().endpoint_ids(), &remote_device_id) {
+        Some(endpoint_id) => chat().history(&endpoint_id),
+        None => Vec::new(),
+    }
+}
+
+/// Messages kept in the storage while chat history was enabled.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn desktop_chat_records_get(
+    app_state: State<'_, AppState>,
+    remote_device_id: String,
+) -> CoreResult<Vec<ChatEntry>> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.chat().query(
+        &remote_device_id.replace('-', ""),
+        CHAT_HISTORY_LIMIT as u32,
+    )
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn desktop_chat_records_delete(
+    app_state: State<'_, AppState>,
+    remote_device_id: String,
+) -> CoreResult<()> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    storage.chat().delete(&remote_device_id.replace('-', ""))
+}
+
+/// Whether the desktop session with the remote device syncs the clipboard.
+#[tauri::command]
+#[tracing::instrument]
+pub fn desktop_clipboard_sync_get(remote_device_id: String) -> bool {
+    find_endpoint_id(clipboard_endpoint_ids(), &remote_device_id)
+        .map_or(false, |endpoint_id| clipboard_sync(&endpoint_id))
+}
+
+/// Turns the clipboard sync of the desktop session with the remote device
+/// on or off, sessions start without. Text copied on one side can be
+/// pasted on the other while both sides have it on.
+#[tauri::command]
+#[tracing::instrument]
+pub fn desktop_clipboard_sync_set(remote_device_id: String, enabled: bool) -> CoreResult<()> {
+    let endpoint_id = find_endpoint_id(clipboard_endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    set_clipboard_sync(&endpoint_id, enabled)
+}
+
+/// Asks the remote device to swap the desktop session, the side sharing its
+/// screen starts watching and the other way round. Waits until the remote
+/// user answers. The remote device may control this one afterwards only
+/// with `allow_input`. Returns `declined`, `hosting` or `viewing`.
+#[tauri::command]
+#[tracing::instrument(skip(app_state, egui_plugin))]
+pub async fn desktop_swap_request(
+    app_state: State<'_, AppState>,
+    egui_plugin: State<'_, EguiPluginHandle>,
+    remote_device_id: String,
+    allow_input: bool,
+) -> CoreResult<&'static str> {
+    let endpoint_id = find_endpoint_id(swap_endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    let media_config = load_media_config(&app_state).await?;
+    let outcome = swap::request_swap(endpoint_id, allow_input, media_config).await?;
+    open_swapped_window(&egui_plugin, &remote_device_id, outcome)
+}
+
+/// Answers the swap request of the remote device, `allow_input` as in
+/// `desktop_swap_request`.
+#[tauri::command]
+#[tracing::instrument(skip(app_state, egui_plugin))]
+pub async fn desktop_swap_respond(
+    app_state: State<'_, AppState>,
+    egui_plugin: State<'_, EguiPluginHandle>,
+    remote_device_id: String,
+    accepted: bool,
+    allow_input: bool,
+) -> CoreResult<&'static str> {
+    let endpoint_id = find_endpoint_id(swap_endpoint_ids(), &remote_device_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    let media_config = load_media_config(&app_state).await?;
+    let outcome = swap::respond_swap(endpoint_id, accepted, allow_input, media_config).await?;
+    open_swapped_window(&egui_plugin, &remote_device_id, outcome)
+}
+
+async fn load_media_config(app_state: &State<'_, AppState>) -> CoreResult<MediaConfig> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    Ok(storage.kv().get_media_config()?.unwrap_or_default())
+}
+
+/// Opens a desktop window when this side starts watching without one, a
+/// window left from watching before gets the frames again by itself.
+fn open_swapped_window(
+    egui_plugin: &EguiPluginHandle,
+    remote_device_id: &str,
+    outcome: SwapOutcome,
+) -> CoreResult<&'static str> {
+    let (session, render_frame_rx) = match outcome {
+        SwapOutcome::Declined => return Ok("declined"),
+        SwapOutcome::Hosting => return Ok("hosting"),
+        SwapOutcome::Viewing(None) => return Ok("viewing"),
+        SwapOutcome::Viewing(Some(viewer)) => viewer,
+    };
+
+    let window_label = format!("MirrorX {}", remote_device_id);
+
+    if let Err(err) = egui_plugin.create_window(
+        window_label.clone(),
+        Box::new(move |cc| {
+            if let Some(gl_context) = cc.gl.as_ref() {
+                Box::new(create_desktop_window(
+                    cc,
+                    gl_context.clone(),
+                    session,
+                    render_frame_rx,
+                ))
+            } else {
+                panic!("get gl context failed");
+            }
+        }),
+        window_label,
+        tauri_egui::eframe::NativeOptions::default(),
+    ) {
+        tracing::error!(?err, "create desktop window failed");
+        return Err(core_error!("create remote desktop window failed"));
+    }
+
+    Ok("viewing")
+}
+
+/// Passes the messages of the remote devices on to the UI.
+pub async fn serve_chat_message(handle: AppHandle) {
+    let mut rx = subscribe_chat_message();
+
+    loop {
+        match rx.recv().await {
+            Ok(entry) => {
+                if let Some(ref storage) = *handle.state::<AppState>().storage.lock().await {
+                    persist_chat_entry(storage, &entry);
+                }
+
+                let _ = handle.emit_all("chat_message", entry);
+            }
+            Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+        }
+    }
+}
+
+/// Writes the message to the storage when the user enabled chat history.
+fn persist_chat_entry<S: Storage>(storage: &S, entry: &ChatEntry) {
+    if !matches!(storage.kv().get_chat_history_enabled(), Ok(Some(true))) {
+        return;
+    }
+
+    if let Err(err) = storage.chat().create(entry) {
+        tracing::error!(?err, "persist chat message failed");
+    }
+}
+
+fn find_endpoint_id(endpoint_ids: Vec<EndPointID>, remote_device_id: &str) -> Option<EndPointID> {
+    let device_id = remote_device_id.replace('-', "").parse::<i64>().ok();
+
+    endpoint_ids
+        .into_iter()
+        .find(|endpoint_id| match endpoint_id {
+            EndPointID::DeviceID {
+                remote_device_id, ..
+            } => Some(*remote_device_id) == device_id,
+            EndPointID::LANID { remote_ip, .. } => remote_ip.to_string() == remote_device_id,
+        })
+}
@@ -1,15 +1,27 @@
-use super::AppState;
+use super::{
+    signaling::{reconnect_file_manager, visit_file_manager},
+    AppState,
+};
 use mirrorx_core::{
-    api::endpoint::message::{
-        EndPointCallRequest, EndPointDownloadFileReply, EndPointDownloadFileRequest,
-        EndPointFileTransferError, EndPointMessage, EndPointSendFileReply, EndPointSendFileRequest,
-        EndPointVisitDirectoryRequest, EndPointVisitDirectoryResponse,
+    api::{
+        endpoint::message::{
+            EndPointCallRequest, EndPointDownloadFileReply, EndPointDownloadFileRequest,
+            EndPointFileTransferCancel, EndPointFileTransferError, EndPointListDirectoryRequest,
+            EndPointListDirectoryResponse, EndPointMessage, EndPointSendFileReply,
+            EndPointSendFileRequest, EndPointVisitDirectoryRequest, EndPointVisitDirectoryResponse,
+            FileTransferCancelReason,
+        },
+        signaling::visit_failure::VisitFailure,
     },
-    component::fs::transfer::{
-        create_file_append_session, query_transferred_bytes_count, send_file_to_remote,
+    component::fs::{
+        browse::DirectoryListing,
+        transfer::{
+            cancel_file_transfer, create_file_append_session, file_content_hash,
+            partial_file_state, query_transferred_bytes_count, send_file_to_remote,
+        },
     },
     core_error,
-    error::CoreResult,
+    error::{CoreError, CoreResult},
 };
 use rayon::prelude::*;
 use serde::Serialize;
@@ -39,10 +51,8 @@ pub async fn file_manager_visit_remote(
 ) -> CoreResult<DirectoryResult> {
     let client = app_state
         .files_endpoints
-        .lock()
-        .await
-        .get(&remote_device_id)
-        .ok_or_else(|| core_error!("remote file manager not exist"))?;
+        .get_or_reconnect(&remote_device_id)
+        .await?;
 
     let reply: EndPointVisitDirectoryResponse = client
         .call(EndPointCallRequest::VisitDirectoryRequest(
@@ -73,6 +83,33 @@ pub async fn file_manager_visit_remote(
     Ok(DirectoryResult { path, entries })
 }
 
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_list_remote(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: String,
+    path: PathBuf,
+    offset: u32,
+    limit: u32,
+) -> CoreResult<DirectoryListing> {
+    let client = app_state
+        .files_endpoints
+        .get_or_reconnect(&remote_device_id)
+        .await?;
+
+    let reply: EndPointListDirectoryResponse = client
+        .call(EndPointCallRequest::ListDirectoryRequest(
+            EndPointListDirectoryRequest {
+                path,
+                offset,
+                limit,
+            },
+        ))
+        .await?;
+
+    Ok(reply.listing)
+}
+
 #[tauri::command]
 #[tracing::instrument]
 pub async fn file_manager_visit_local(path: Option<PathBuf>) -> CoreResult<DirectoryResult> {
@@ -128,28 +165,29 @@ pub async fn file_manager_send_file(
 
     let meta = local_path.metadata()?;
     let size = meta.len();
+    let hash = file_content_hash(&local_path).await?;
 
     let id = uuid::Uuid::new_v4().to_string();
 
     let client = app_state
         .files_endpoints
-        .lock()
-        .await
-        .get(&remote_device_id)
-        .ok_or_else(|| core_error!("remote file manager not exist"))?;
+        .get_or_reconnect(&remote_device_id)
+        .await?;
 
-    let _: EndPointSendFileReply = client
+    // the remote side has the file up to the offset from an earlier transfer
+    let reply: EndPointSendFileReply = client
         .call(EndPointCallRequest::SendFileRequest(
             EndPointSendFileRequest {
                 id: id.clone(),
                 filename,
                 path: remote_path,
                 size,
+                hash,
             },
         ))
         .await?;
 
-    send_file_to_remote(id.clone(), client, &local_path).await?;
+    send_file_to_remote(id.clone(), client, &local_path, reply.offset).await?;
 
     Ok((id, size))
 }
@@ -167,24 +205,37 @@ pub async fn file_manager_download_file(
     }
 
     let id = uuid::Uuid::new_v4().to_string();
+    let (hash, offset) = match partial_file_state(&local_path).await {
+        Some((hash, offset)) => (Some(hash), offset),
+        None => (None, 0),
+    };
 
     let client = app_state
         .files_endpoints
-        .lock()
-        .await
-        .get(&remote_device_id)
-        .ok_or_else(|| core_error!("remote file manager not exist"))?;
+        .get_or_reconnect(&remote_device_id)
+        .await?;
 
     let reply: EndPointDownloadFileReply = client
         .call(EndPointCallRequest::DownloadFileRequest(
             EndPointDownloadFileRequest {
                 id: id.clone(),
                 path: remote_path,
+                hash,
+                offset,
             },
         ))
         .await?;
 
-    if let Err(err) = create_file_append_session(id.clone(), &local_path).await {
+    if let Err(err) = create_file_append_session(
+        client.clone(),
+        id.clone(),
+        &local_path,
+        &reply.hash,
+        reply.offset,
+        reply.size,
+    )
+    .await
+    {
         let _ = client
             .send(&EndPointMessage::FileTransferError(
                 EndPointFileTransferError { id: id.clone() },
@@ -197,7 +248,74 @@ pub async fn file_manager_download_file(
     Ok((id, reply.size))
 }
 
+/// Connects to a remote file manager again after reconnecting gave up, the
+/// password isn't kept from the first visit.
+#[tauri::command]
+#[tracing::instrument(skip(app_state, password))]
+pub async fn file_manager_reconnect(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: String,
+    password: String,
+) -> Result<(), VisitFailure> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize").into());
+    };
+
+    let Some((_, ref signaling_client)) = *app_state.signaling_client.lock().await else {
+        return Err(core_error!("signaling client not initialize").into());
+    };
+
+    let remote_device_id_num = remote_device_id
+        .replace('-', "")
+        .parse()
+        .map_err(CoreError::from)?;
+    let local_device_id = storage.domain().get_primary_domain()?.device_id;
+
+    let client = visit_file_manager(
+        signaling_client,
+        local_device_id,
+        remote_device_id_num,
+        password,
+    )
+    .await?;
+
+    app_state
+        .files_endpoints
+        .insert(remote_device_id, client, reconnect_file_manager())
+        .await;
+
+    Ok(())
+}
+
 #[tauri::command]
 pub async fn file_manager_query_transferred_bytes_count(id: String) -> u64 {
     query_transferred_bytes_count(&id)
 }
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn file_manager_cancel_transfer(
+    app_state: tauri::State<'_, AppState>,
+    remote_device_id: String,
+    id: String,
+    keep_partial: bool,
+) -> CoreResult<()> {
+    if !cancel_file_transfer(&id, FileTransferCancelReason::UserCancelled, keep_partial).await {
+        return Ok(());
+    }
+
+    let client = app_state
+        .files_endpoints
+        .get_or_reconnect(&remote_device_id)
+        .await?;
+
+    client
+        .send(&EndPointMessage::FileTransferCancel(
+            EndPointFileTransferCancel {
+                id,
+                reason: FileTransferCancelReason::UserCancelled,
+                keep_partial,
+            },
+        ))
+        .await
+}
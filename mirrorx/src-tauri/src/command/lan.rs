@@ -1,9 +1,14 @@
 use crate::{command::AppState, window::create_desktop_window};
 use mirrorx_core::{
-    api::endpoint::{create_desktop_active_endpoint_client, id::EndPointID, EndPointStream},
+    api::{
+        config::Storage,
+        endpoint::{
+            connect_attempt::connect_attempts, id::EndPointID, session::Session, EndPointStream,
+        },
+    },
     component::lan::{
-        discover::{Discover, Node},
-        server::Server,
+        discover::{filter_nodes, merge_nicknames, Discover, Node, NodeFilter},
+        server::{Server, LAN_SERVER_PORT},
     },
     core_error,
     error::CoreResult,
@@ -31,16 +36,22 @@ pub async fn lan_init(app_state: tauri::State<'_, AppState>, force: bool) -> Cor
 }
 
 #[tauri::command]
-#[tracing::instrument(skip(egui_plugin))]
+#[tracing::instrument(skip(app_state, egui_plugin))]
 pub async fn lan_connect(
+    app_state: tauri::State<'_, AppState>,
     egui_plugin: tauri::State<'_, EguiPluginHandle>,
     addr: String,
 ) -> CoreResult<()> {
+    let media_config = match *app_state.storage.lock().await {
+        Some(ref storage) => storage.kv().get_media_config()?.unwrap_or_default(),
+        None => return Err(core_error!("storage not initialize")),
+    };
+
     let local_ip = get_lan_ip().await?;
     let remote_ip: IpAddr = addr
         .parse()
         .map_err(|_| core_error!("parse addr to IpAddr failed"))?;
-    let remote_addr = SocketAddr::new(remote_ip, 48001);
+    let remote_addr = SocketAddr::new(remote_ip, LAN_SERVER_PORT);
     let window_label = format!("MirrorX {}", remote_ip);
 
     let endpoint_id = EndPointID::LANID {
@@ -48,13 +59,17 @@ pub async fn lan_connect(
         remote_ip,
     };
 
-    let (client, render_frame_rx) = create_desktop_active_endpoint_client(
-        endpoint_id,
-        None,
-        EndPointStream::ActiveTCP(remote_addr),
-        None,
-    )
-    .await?;
+    let attempt = connect_attempts().begin(&addr);
+    let (session, render_frame_rx) = attempt
+        .run(Session::start(
+            endpoint_id,
+            None,
+            EndPointStream::ActiveTCP(remote_addr),
+            None,
+            media_config,
+        ))
+        .await?;
+    drop(attempt);
 
     if let Err(err) = egui_plugin.create_window(
         window_label.clone(),
@@ -63,8 +78,7 @@ pub async fn lan_connect(
                 Box::new(create_desktop_window(
                     cc,
                     gl_context.clone(),
-                    endpoint_id,
-                    client,
+                    session,
                     render_frame_rx,
                 ))
             } else {
@@ -81,9 +95,23 @@ pub async fn lan_connect(
         return Err(core_error!("create remote desktop window failed"));
     }
 
+    if let Some(ref storage) = *app_state.storage.lock().await {
+        let remote = remote_ip.to_string();
+        if let Err(err) = storage.connection_history().record(&remote, None) {
+            tracing::error!(?err, "record connection history failed");
+        }
+    }
+
     Ok(())
 }
 
+/// Aborts a LAN connection still being made, `false` when there's none.
+#[tauri::command]
+#[tracing::instrument]
+pub fn lan_connect_cancel(addr: String) -> bool {
+    connect_attempts().cancel(&addr)
+}
+
 #[tauri::command]
 #[tracing::instrument(skip(app_state))]
 pub async fn lan_nodes_list(app_state: tauri::State<'_, AppState>) -> CoreResult<Vec<Node>> {
@@ -94,7 +122,10 @@ pub async fn lan_nodes_list(app_state: tauri::State<'_, AppState>) -> CoreResult
             return Err(core_error!("lan discover is empty"))
         };
 
-    Ok(discover.nodes_snapshot())
+    let mut nodes = discover.nodes_snapshot();
+    merge_history_nicknames(&app_state, &mut nodes).await;
+
+    Ok(nodes)
 }
 
 #[tauri::command]
@@ -111,19 +142,50 @@ pub async fn lan_nodes_search(
         };
 
     let mut nodes = discover.nodes_snapshot();
-    let nodes_count = nodes.len();
+    merge_history_nicknames(&app_state, &mut nodes).await;
 
-    for i in 0..nodes_count {
-        let hostname = &nodes[i].host_name;
-        let ip = nodes[i].addr.to_string();
-        if !(hostname.contains(&keyword) || ip.contains(&keyword)) {
-            nodes.remove(i);
-        }
-    }
+    nodes.retain(|node| {
+        node.host_name.contains(&keyword)
+            || matches!(node.device_name, Some(ref device_name) if device_name.contains(&keyword))
+            || node.addr.to_string().contains(&keyword)
+            || matches!(node.nickname, Some(ref nickname) if nickname.contains(&keyword))
+    });
 
     Ok(nodes)
 }
 
+/// Narrows the devices by the start of their name and by their tag.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn lan_nodes_filter(
+    app_state: tauri::State<'_, AppState>,
+    filter: NodeFilter,
+) -> CoreResult<Vec<Node>> {
+    let Some((ref discover, _)) = *app_state
+        .lan_components
+        .lock()
+        .await else {
+            return Err(core_error!("lan discover is empty"))
+        };
+
+    let mut nodes = discover.nodes_snapshot();
+    merge_history_nicknames(&app_state, &mut nodes).await;
+
+    Ok(filter_nodes(nodes, &filter))
+}
+
+/// Known devices show the nickname the user gave them.
+async fn merge_history_nicknames(app_state: &tauri::State<'_, AppState>, nodes: &mut [Node]) {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return;
+    };
+
+    match storage.connection_history().nicknames() {
+        Ok(nicknames) => merge_nicknames(nodes, &nicknames),
+        Err(err) => tracing::error!(?err, "read connection history nicknames failed"),
+    }
+}
+
 #[tauri::command]
 #[tracing::instrument(skip(app_state))]
 pub async fn lan_discoverable_set(
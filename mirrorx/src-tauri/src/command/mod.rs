@@ -1,22 +1,37 @@
 pub mod config;
+pub mod desktop;
 pub mod file_manager;
 pub mod lan;
 pub mod signaling;
 pub mod utility;
 
 use mirrorx_core::{
-    api::{config::LocalStorage, endpoint::client::EndPointClient, signaling::SignalingClient},
+    api::{
+        config::LocalStorage,
+        endpoint::{client::close_all_clients, client_cache::EndPointClientCache},
+        signaling::{key_pool::stop_key_pool, SignalingClient},
+    },
     component::lan::{discover::Discover, server::Server},
 };
-use moka::future::{Cache, CacheBuilder};
-use std::sync::Arc;
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
 use tauri::async_runtime::Mutex;
 
+/// Longest the teardown on quit may take, whatever isn't done by then is
+/// left to the process exit.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Share of the timeout the endpoints get to close their streams.
+const CLOSE_ENDPOINTS_TIMEOUT: Duration = Duration::from_secs(1);
+
 pub struct AppState {
     storage: Mutex<Option<LocalStorage>>,
     signaling_client: Mutex<Option<(i64, SignalingClient)>>,
     lan_components: Mutex<Option<(Discover, Server)>>,
-    files_endpoints: Mutex<Cache<String, Arc<EndPointClient>>>,
+    files_endpoints: EndPointClientCache,
+    shut_down: AtomicBool,
 }
 
 impl AppState {
@@ -25,7 +40,46 @@ impl AppState {
             storage: Mutex::new(None),
             signaling_client: Mutex::new(None),
             lan_components: Mutex::new(None),
-            files_endpoints: Mutex::new(CacheBuilder::new(64).build()),
+            files_endpoints: EndPointClientCache::new(64),
+            shut_down: AtomicBool::new(false),
+        }
+    }
+
+    /// Tears everything down before the app quits, so the remote sides see
+    /// a closed connection instead of a reset and the LAN devices drop this
+    /// one right away. Only the first call does anything, and it returns
+    /// within [`SHUTDOWN_TIMEOUT`].
+    pub async fn shutdown(&self) {
+        if self.shut_down.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        tracing::info!("app state shutdown");
+
+        if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.teardown())
+            .await
+            .is_err()
+        {
+            tracing::warn!("app state shutdown timeout");
+        }
+    }
+
+    async fn teardown(&self) {
+        self.files_endpoints.clear();
+        let closed = close_all_clients(CLOSE_ENDPOINTS_TIMEOUT).await;
+        tracing::info!(?closed, "endpoint clients closed");
+
+        if let Some((_, mut signaling_client)) = self.signaling_client.lock().await.take() {
+            signaling_client.close().await;
+        }
+        stop_key_pool();
+
+        if let Some((discover, server)) = self.lan_components.lock().await.take() {
+            tokio::join!(discover.shutdown(), server.shutdown());
         }
+
+        // storage writes commit right away and hold the lock meanwhile, once
+        // it's taken nothing is left to write
+        drop(self.storage.lock().await.take());
     }
 }
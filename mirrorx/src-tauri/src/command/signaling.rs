@@ -2,16 +2,36 @@ use super::AppState;
 use crate::window::create_desktop_window;
 use mirrorx_core::{
     api::{
+        config::Storage,
         endpoint::{
-            create_desktop_active_endpoint_client, create_file_manager_active_endpoint_client,
-            id::EndPointID, EndPointStream,
+            client::EndPointClient,
+            client_cache::{Reconnect, ReconnectFuture},
+            connect_attempt::connect_attempts,
+            create_file_manager_active_endpoint_client,
+            id::EndPointID,
+            network_info::NetworkPath,
+            path_preference::{path_preference, select_path},
+            session::Session,
+            EndPointStream,
+        },
+        signaling::{
+            access_code::{one_time_codes, OneTimeCode},
+            circuit_breaker::CircuitBreakerStats,
+            key_pool::{key_pool, KeyPoolStats},
+            outbound_queue::OutboundQueueStats,
+            transport::SignalingTransport,
+            visit_failure::VisitFailure,
+            SignalingClient, VisitReply,
         },
-        signaling::{http_message::Response, SignalingClient},
     },
     core_error,
-    error::CoreResult,
+    error::{CoreError, CoreResult},
+};
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
+    sync::Arc,
+    time::Instant,
 };
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs};
 use tauri::http::Uri;
 use tauri_egui::EguiPluginHandle;
 
@@ -29,8 +49,9 @@ pub async fn signaling_connect(
 
     let primary_domain = storage.domain().get_primary_domain()?;
 
-    if let Some((current_domain_id, _)) = *current_signaling {
-        if current_domain_id == primary_domain.id && !force {
+    // a client kept from a failed attempt isn't subscribed yet
+    if let Some((current_domain_id, ref client)) = *current_signaling {
+        if current_domain_id == primary_domain.id && !force && client.active_transport().is_some() {
             return Ok(());
         }
     }
@@ -75,20 +96,35 @@ pub async fn signaling_connect(
         return Err(core_error!("invalid domain addr"));
     };
 
-    let mut client = SignalingClient::new(primary_domain.addr)?;
+    // keep the client of the same domain so messages queued while the
+    // connection was down are flushed by the new subscription
+    let mut client = match current_signaling.take() {
+        Some((current_domain_id, client)) if current_domain_id == primary_domain.id => client,
+        current => {
+            *current_signaling = current;
+            SignalingClient::new(primary_domain.addr)?
+        }
+    };
+
+    client.set_transport(storage.kv().get_signaling_transport()?);
+    client.set_circuit_breaker_config(storage.kv().get_signaling_circuit_breaker()?);
 
-    client
+    let subscribed = client
         .subscribe(
             addrs,
             primary_domain.device_id,
             &primary_domain.finger_print,
             storage.clone(),
         )
-        .await?;
+        .await;
 
-    *current_signaling = Some((primary_domain.id, client));
+    // a failed attempt keeps the client of the domain and its queued messages
+    // for the next one
+    if subscribed.is_ok() || current_signaling.is_none() {
+        *current_signaling = Some((primary_domain.id, client));
+    }
 
-    Ok(())
+    subscribed
 }
 
 #[tauri::command]
@@ -100,7 +136,7 @@ pub async fn signaling_visit(
     remote_device_id: String,
     password: String,
     visit_desktop: bool,
-) -> CoreResult<()> {
+) -> Result<(), VisitFailure> {
     let window_label = if visit_desktop {
         format!("Desktop:{}", remote_device_id)
     } else {
@@ -114,33 +150,42 @@ pub async fn signaling_visit(
     };
 
     let Some(ref storage) = *app_state.storage.lock().await else {
-        return Err(core_error!("storage not initialize"));
+        return Err(core_error!("storage not initialize").into());
     };
 
-    let Some((_,ref signaling_client)) = *app_state.signaling_client.lock().await else {
-        return Err(core_error!("storage not initialize"));
+    let Some((_, ref signaling_client)) = *app_state.signaling_client.lock().await else {
+        return Err(core_error!("storage not initialize").into());
     };
 
-    let remote_device_id_num = remote_device_id.replace('-', "").parse()?;
+    let remote_device_id_num = remote_device_id
+        .replace('-', "")
+        .parse()
+        .map_err(CoreError::from)?;
     let primary_domain = storage.domain().get_primary_domain()?;
     let local_device_id = primary_domain.device_id;
-    let resp = signaling_client
-        .visit(
+    let media_config = storage.kv().get_media_config()?.unwrap_or_default();
+
+    // a visit is relayed by the endpoints server, don't bother the remote
+    // device when that's ruled out
+    select_path(path_preference(), &[NetworkPath::Relayed])?;
+
+    // the user may cancel until the connection is up
+    let attempt = connect_attempts().begin(&remote_device_id);
+
+    let VisitReply {
+        endpoint_addr,
+        visit_credentials,
+        opening_key,
+        sealing_key,
+    } = attempt
+        .run(signaling_client.visit_with_retry(
             primary_domain.device_id,
             remote_device_id_num,
             password,
             visit_desktop,
-        )
+        ))
         .await?;
 
-    let (endpoint_addr, visit_credentials, opening_key, sealing_key) = match resp {
-        Response::Message(result) => match result {
-            Ok(v) => v,
-            Err(reason) => return Err(core_error!("Visit Failed ({:?})", reason)),
-        },
-        Response::Error(err) => return Err(core_error!("Visit Failed ({:?})", err)),
-    };
-
     let endpoint_addr: SocketAddr = endpoint_addr
         .parse()
         .map_err(|_| core_error!("parse endpoint addr failed"))?;
@@ -153,13 +198,16 @@ pub async fn signaling_visit(
     };
 
     if visit_desktop {
-        let (client, render_frame_rx) = create_desktop_active_endpoint_client(
-            endpoint_id,
-            Some((opening_key, sealing_key)),
-            EndPointStream::ActiveTCP(endpoint_addr),
-            Some(visit_credentials),
-        )
-        .await?;
+        let (session, render_frame_rx) = attempt
+            .run(Session::start(
+                endpoint_id,
+                Some((opening_key, sealing_key)),
+                EndPointStream::ActiveTCP(endpoint_addr),
+                Some(visit_credentials),
+                media_config,
+            ))
+            .await?;
+        drop(attempt);
 
         if let Err(err) = egui_plugin.create_window(
             window_label,
@@ -168,8 +216,7 @@ pub async fn signaling_visit(
                     Box::new(create_desktop_window(
                         cc,
                         gl_context.clone(),
-                        endpoint_id,
-                        client,
+                        session,
                         render_frame_rx,
                     ))
                 } else {
@@ -183,22 +230,22 @@ pub async fn signaling_visit(
             },
         ) {
             tracing::error!(?err, "create desktop window failed");
-            return Err(core_error!("create remote desktop window failed"));
+            return Err(core_error!("create remote desktop window failed").into());
         }
     } else {
-        let client = create_file_manager_active_endpoint_client(
-            endpoint_id,
-            Some((opening_key, sealing_key)),
-            EndPointStream::ActiveTCP(endpoint_addr),
-            Some(visit_credentials),
-        )
-        .await?;
+        let client = attempt
+            .run(create_file_manager_active_endpoint_client(
+                endpoint_id,
+                Some((opening_key, sealing_key)),
+                EndPointStream::ActiveTCP(endpoint_addr),
+                Some(visit_credentials),
+            ))
+            .await?;
+        drop(attempt);
 
         app_state
             .files_endpoints
-            .lock()
-            .await
-            .insert(remote_device_id.clone(), client)
+            .insert(remote_device_id.clone(), client, reconnect_file_manager())
             .await;
 
         let (tx, rx) = tokio::sync::oneshot::channel();
@@ -227,12 +274,10 @@ pub async fn signaling_visit(
         if let Some(err) = create_result {
             app_state
                 .files_endpoints
-                .lock()
-                .await
                 .invalidate(&remote_device_id)
                 .await;
             tracing::error!(?err, "create file manager window failed");
-            return Err(core_error!("create remote file manager window failed"));
+            return Err(core_error!("create remote file manager window failed").into());
         }
     }
 
@@ -240,5 +285,125 @@ pub async fn signaling_visit(
         .history()
         .create(remote_device_id_num, &primary_domain.name);
 
+    if let Err(err) = storage.connection_history().record(
+        &remote_device_id_num.to_string(),
+        Some(&primary_domain.name),
+    ) {
+        tracing::error!(?err, "record connection history failed");
+    }
+
     Ok(())
 }
+
+/// Aborts a visit still connecting to the remote device, `false` when there's
+/// none. The visit fails with a cancelled error.
+#[tauri::command]
+#[tracing::instrument]
+pub fn signaling_visit_cancel(remote_device_id: String) -> bool {
+    connect_attempts().cancel(&remote_device_id)
+}
+
+/// The password of a visit isn't kept, a file manager connection that broke
+/// waits for the user to enter it for [`visit_file_manager`].
+pub(crate) fn reconnect_file_manager() -> Reconnect {
+    Arc::new(|| -> ReconnectFuture { Box::pin(async { Err(CoreError::ReconnectNeedsPassword) }) })
+}
+
+/// Visits the file manager of the remote device again, for a cached
+/// connection that broke meanwhile.
+pub(crate) async fn visit_file_manager(
+    signaling_client: &SignalingClient,
+    local_device_id: i64,
+    remote_device_id: i64,
+    password: String,
+) -> CoreResult<Arc<EndPointClient>> {
+    let VisitReply {
+        endpoint_addr,
+        visit_credentials,
+        opening_key,
+        sealing_key,
+    } = signaling_client
+        .visit_with_retry(local_device_id, remote_device_id, password, false)
+        .await?;
+
+    let endpoint_addr: SocketAddr = endpoint_addr
+        .parse()
+        .map_err(|_| core_error!("parse endpoint addr failed"))?;
+
+    create_file_manager_active_endpoint_client(
+        EndPointID::DeviceID {
+            local_device_id,
+            remote_device_id,
+        },
+        Some((opening_key, sealing_key)),
+        EndPointStream::ActiveTCP(endpoint_addr),
+        Some(visit_credentials),
+    )
+    .await
+}
+
+/// Transport of the running subscribe connection, `None` while signaling
+/// isn't connected.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn signaling_active_transport(
+    app_state: tauri::State<'_, AppState>,
+) -> CoreResult<Option<SignalingTransport>> {
+    match *app_state.signaling_client.lock().await {
+        Some((_, ref signaling_client)) => Ok(signaling_client.active_transport()),
+        None => Ok(None),
+    }
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn signaling_outbound_queue_stats(
+    app_state: tauri::State<'_, AppState>,
+) -> CoreResult<OutboundQueueStats> {
+    let Some((_, ref signaling_client)) = *app_state.signaling_client.lock().await else {
+        return Err(core_error!("signaling client not initialize"));
+    };
+
+    Ok(signaling_client.outbound_queue_stats().await)
+}
+
+/// Whether calls to the signaling server fail fast after repeated
+/// failures, and for how long yet.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn signaling_circuit_breaker_stats(
+    app_state: tauri::State<'_, AppState>,
+) -> CoreResult<CircuitBreakerStats> {
+    let Some((_, ref signaling_client)) = *app_state.signaling_client.lock().await else {
+        return Err(core_error!("signaling client not initialize"));
+    };
+
+    Ok(signaling_client.circuit_breaker_stats())
+}
+
+/// How many visit keys are generated ahead, and how often visits found one.
+#[tauri::command]
+#[tracing::instrument]
+pub fn signaling_key_pool_stats() -> KeyPoolStats {
+    key_pool().stats(Instant::now())
+}
+
+/// Generates the one-time code to show, the previous code stops working.
+#[tauri::command]
+#[tracing::instrument]
+pub fn signaling_one_time_code_rotate() -> OneTimeCode {
+    one_time_codes().rotate(Instant::now())
+}
+
+/// The code shown, `None` once it expired or a visit used it.
+#[tauri::command]
+#[tracing::instrument]
+pub fn signaling_one_time_code_get() -> Option<OneTimeCode> {
+    one_time_codes().current(Instant::now())
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn signaling_one_time_code_clear() {
+    one_time_codes().clear()
+}
@@ -1,4 +1,32 @@
-use mirrorx_core::{error::CoreResult, utility::os::GraphicsCards};
+use crate::command::{config::apply_local_settings, signaling::signaling_connect, AppState};
+use mirrorx_core::{
+    api::{
+        config::{
+            bundle::{ImportMode, ImportSummary},
+            integrity::{IntegrityReport, RepairReport},
+            LocalStorage, Storage,
+        },
+        diagnose::{
+            begin_diagnosis, cancel_diagnosis, Diagnosis, DiagnosticCheck, DiagnosticReport,
+            DIAGNOSIS_TIMEOUT,
+        },
+        endpoint::{client::encode_latencies, id::EndPointID},
+        signaling::{http_message::Response, SignalingClient},
+    },
+    component::{
+        fs::cache::{CacheEviction, CacheUsage},
+        lan::server::LAN_SERVER_PORT,
+        video_encoder::encode_timing::{encode_trace_enabled, set_encode_trace, EncodeLatency},
+    },
+    core_error,
+    error::{CoreError, CoreResult},
+    utility::{lan_ip::get_lan_ip, os::GraphicsCards, permission::PlatformPermissions},
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+};
 
 #[tauri::command]
 #[tracing::instrument]
@@ -28,6 +56,281 @@ pub fn utility_enum_graphics_cards() -> CoreResult<Vec<GraphicsCards>> {
     mirrorx_core::utility::os::enum_graphics_cards()
 }
 
+#[tauri::command]
+#[tracing::instrument]
+pub fn utility_check_platform_permissions(prompt: bool) -> PlatformPermissions {
+    mirrorx_core::utility::permission::check_platform_permissions(prompt)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_handle))]
+pub fn utility_check_storage_integrity(
+    app_handle: tauri::AppHandle,
+) -> CoreResult<IntegrityReport> {
+    mirrorx_core::api::config::integrity::check_storage_integrity(storage_path(&app_handle)?)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_handle, app_state))]
+pub async fn utility_repair_storage(
+    app_handle: tauri::AppHandle,
+    app_state: tauri::State<'_, AppState>,
+) -> CoreResult<RepairReport> {
+    let db_path = storage_path(&app_handle)?;
+
+    // release every connection to the database before swapping the file,
+    // the signaling client holds one too and has to reconnect afterwards
+    let mut signaling_guard = app_state.signaling_client.lock().await;
+    let mut storage_guard = app_state.storage.lock().await;
+    let signaling_connected = signaling_guard.take().is_some();
+    storage_guard.take();
+
+    let report = mirrorx_core::api::config::integrity::repair_storage(&db_path);
+
+    let storage = LocalStorage::new(&db_path)?;
+    apply_local_settings(&storage)?;
+    *storage_guard = Some(storage);
+
+    drop(storage_guard);
+    drop(signaling_guard);
+
+    if signaling_connected {
+        if let Err(err) = signaling_connect(app_state, true).await {
+            tracing::error!(?err, "reconnect signaling after repair failed");
+        }
+    }
+
+    report
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state, passphrase))]
+pub async fn utility_export_settings(
+    app_state: tauri::State<'_, AppState>,
+    passphrase: String,
+) -> CoreResult<String> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    mirrorx_core::api::config::bundle::export_settings(storage, &passphrase)
+}
+
+#[tauri::command]
+#[tracing::instrument(skip(app_state, bundle, passphrase))]
+pub async fn utility_import_settings(
+    app_state: tauri::State<'_, AppState>,
+    bundle: String,
+    passphrase: String,
+    mode: ImportMode,
+) -> CoreResult<ImportSummary> {
+    let Some(ref storage) = *app_state.storage.lock().await else {
+        return Err(core_error!("storage not initialize"));
+    };
+
+    let summary =
+        mirrorx_core::api::config::bundle::import_settings(storage, &bundle, &passphrase, mode)?;
+    apply_local_settings(storage)?;
+
+    Ok(summary)
+}
+
+/// Size of the cache against its budget.
+#[tauri::command]
+#[tracing::instrument]
+pub fn utility_cache_usage() -> CoreResult<CacheUsage> {
+    mirrorx_core::component::fs::cache::cache_usage()
+}
+
+/// Removes the cached files, those in use, like a running frame dump, stay.
+#[tauri::command]
+#[tracing::instrument]
+pub fn utility_cache_clear() -> CoreResult<CacheEviction> {
+    mirrorx_core::component::fs::cache::clear_cache()
+}
+
+/// Checks one after another what a connection to `target` needs, and
+/// reports the outcome of each check with what to do about a failing one.
+/// `target` is a device id or the LAN address of a device, a LAN target is
+/// probed with a connection it sees closing right away. Takes at most
+/// [`DIAGNOSIS_TIMEOUT`], a diagnosis started meanwhile cancels it.
+#[tauri::command]
+#[tracing::instrument(skip(app_state))]
+pub async fn utility_diagnose_connectivity(
+    app_state: tauri::State<'_, AppState>,
+    target: String,
+) -> CoreResult<DiagnosticReport> {
+    let lan_target = target.parse::<IpAddr>().ok();
+    if lan_target.is_none() {
+        target
+            .replace('-', "")
+            .parse::<i64>()
+            .map_err(CoreError::from)?;
+    }
+
+    let primary_domain = match *app_state.storage.lock().await {
+        Some(ref storage) => storage.domain().get_primary_domain()?,
+        None => return Err(core_error!("storage not initialize")),
+    };
+
+    let running = begin_diagnosis();
+    let mut diagnosis = Diagnosis::new(&target, running.token(), DIAGNOSIS_TIMEOUT);
+
+    diagnosis
+        .run(DiagnosticCheck::SignalingReachable, &[], async {
+            // a client of its own, an open circuit breaker of the running one
+            // would fail the call without asking the server
+            let client = SignalingClient::new(primary_domain.addr.clone())?;
+            match client.identity().await? {
+                Response::Message(identity) => {
+                    Ok(format!("signaling server of {} answered", identity.domain))
+                }
+                Response::Error(err) => Err(err.into()),
+            }
+        })
+        .await;
+
+    diagnosis
+        .run(
+            DiagnosticCheck::DeviceRegistered,
+            &[DiagnosticCheck::SignalingReachable],
+            async {
+                match *app_state.signaling_client.lock().await {
+                    Some((domain_id, ref client)) if domain_id == primary_domain.id => {
+                        match client.active_transport() {
+                            Some(transport) => Ok(format!(
+                                "device {} is subscribed over {:?}",
+                                primary_domain.device_id, transport
+                            )),
+                            None => Err(core_error!(
+                                "this device isn't subscribed to the signaling server"
+                            )),
+                        }
+                    }
+                    _ => Err(core_error!(
+                        "this device isn't connected to the primary domain"
+                    )),
+                }
+            },
+        )
+        .await;
+
+    diagnosis
+        .run(DiagnosticCheck::PlatformPermissions, &[], async {
+            let permissions = mirrorx_core::utility::permission::check_platform_permissions(false);
+            permissions.ensure_screen_capture()?;
+            permissions.ensure_accessibility()?;
+            Ok(format!(
+                "screen capture {:?}, accessibility {:?}",
+                permissions.screen_capture, permissions.accessibility
+            ))
+        })
+        .await;
+
+    diagnosis
+        .run(DiagnosticCheck::LanServerListening, &[], async {
+            let lan_ip = get_lan_ip().await?;
+            if app_state.lan_components.lock().await.is_none() {
+                return Err(core_error!("LAN discovery isn't running"));
+            }
+            Ok(format!("listening on {}:{}", lan_ip, LAN_SERVER_PORT))
+        })
+        .await;
+
+    diagnosis
+        .run(
+            DiagnosticCheck::LanDiscovery,
+            &[DiagnosticCheck::LanServerListening],
+            async {
+                let Some((ref discover, _)) = *app_state.lan_components.lock().await else {
+                    return Err(core_error!("LAN discovery isn't running"));
+                };
+
+                if !discover.discoverable() {
+                    return Err(core_error!("this device isn't discoverable"));
+                }
+
+                let nodes = discover.nodes_snapshot();
+                if let Some(lan_target) = lan_target {
+                    if !nodes.iter().any(|node| node.addr == lan_target) {
+                        return Err(core_error!("the target doesn't broadcast its presence"));
+                    }
+                }
+
+                Ok(format!("{} devices found", nodes.len()))
+            },
+        )
+        .await;
+
+    match lan_target {
+        Some(lan_target) => {
+            diagnosis
+                .run(DiagnosticCheck::TargetReachable, &[], async {
+                    let addr = SocketAddr::new(lan_target, LAN_SERVER_PORT);
+                    tokio::net::TcpStream::connect(addr).await?;
+                    Ok(format!("{} accepts connections", addr))
+                })
+                .await;
+        }
+        None => diagnosis.skip(
+            DiagnosticCheck::TargetReachable,
+            "a device id is reached with a visit, which needs its password, the signaling checks cover the way there",
+        ),
+    }
+
+    Ok(diagnosis.finish())
+}
+
+/// Cancels the running diagnosis, `false` when there's none.
+#[tauri::command]
+#[tracing::instrument]
+pub fn utility_diagnose_connectivity_cancel() -> bool {
+    cancel_diagnosis()
+}
+
+/// Encode latency of the recent frames of the sessions watching this
+/// device, by the remote device id, or ip for LAN sessions.
+#[tauri::command]
+#[tracing::instrument]
+pub fn utility_encode_latency_get() -> HashMap<String, EncodeLatency> {
+    encode_latencies()
+        .into_iter()
+        .map(|(endpoint_id, latency)| {
+            let remote = match endpoint_id {
+                EndPointID::DeviceID {
+                    remote_device_id, ..
+                } => remote_device_id.to_string(),
+                EndPointID::LANID { remote_ip, .. } => remote_ip.to_string(),
+            };
+
+            (remote, latency)
+        })
+        .collect()
+}
+
+#[tauri::command]
+#[tracing::instrument]
+pub fn utility_encode_trace_get() -> bool {
+    encode_trace_enabled()
+}
+
+/// Logs the timing of every encoded frame under the
+/// `mirrorx_core::encode_trace` target until turned off again.
+#[tauri::command]
+#[tracing::instrument]
+pub fn utility_encode_trace_set(enabled: bool) {
+    set_encode_trace(enabled);
+}
+
+fn storage_path(app_handle: &tauri::AppHandle) -> CoreResult<PathBuf> {
+    let config_dir = app_handle
+        .path_resolver()
+        .app_config_dir()
+        .ok_or(core_error!("read app dir from path resolver failed"))?;
+
+    Ok(config_dir.join("mirrorx.db"))
+}
+
 #[tauri::command]
 #[tracing::instrument(skip(window))]
 pub fn utility_hide_macos_zoom_button(window: tauri::Window) {
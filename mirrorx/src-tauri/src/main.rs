@@ -10,7 +10,11 @@ mod window;
 #[cfg(target_os = "macos")]
 use tauri::Icon;
 
-use tauri::{App, Manager, SystemTray, SystemTrayEvent, WindowEvent};
+use mirrorx_core::component::video_decoder::{
+    fallback::subscribe_decoder_fallback, frame_dump::subscribe_frame_dump_finished,
+    thumbnail::subscribe_session_thumbnail,
+};
+use tauri::{App, AppHandle, Manager, SystemTray, SystemTrayEvent, WindowEvent};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, EnvFilter};
 
 #[cfg(target_os = "macos")]
@@ -65,6 +69,15 @@ async fn main() {
     });
 }
 
+/// Closes the sessions, signaling and LAN discovery before the process
+/// exits, the teardown is bounded so quitting never hangs.
+fn quit(app_handle: AppHandle) {
+    tauri::async_runtime::spawn(async move {
+        app_handle.state::<command::AppState>().shutdown().await;
+        std::process::exit(0)
+    });
+}
+
 fn build_app() -> App {
     let tray = SystemTray::new();
     #[cfg(target_os = "macos")]
@@ -85,7 +98,7 @@ fn build_app() -> App {
             }
             if let SystemTrayEvent::MenuItemClick { id, .. } = event {
                 match id.as_str() {
-                    "quit" => std::process::exit(0),
+                    "quit" => quit(app.clone()),
                     "show" => app.windows().values().for_each(|window| {
                         let _ = window.show();
                     }),
@@ -105,13 +118,265 @@ fn build_app() -> App {
             }
 
             if event.menu_item_id() == "quit" {
-                std::process::exit(0)
+                quit(event.window().app_handle())
             }
         })
         .setup(|app| {
             app.wry_plugin(tauri_egui::EguiPluginBuilder::new(app.handle()));
             let app_name = app.package_info().name.clone();
 
+            mirrorx_core::api::endpoint::handshake_token::serve_handshake_token_cleanup();
+            mirrorx_core::api::signaling::key_pool::serve_key_pool();
+            mirrorx_core::utility::error_event::serve_error_events();
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx =
+                    mirrorx_core::component::fs::transfer::subscribe_file_transfer_cancelled();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("file_transfer_cancelled", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx =
+                    mirrorx_core::component::fs::transfer::subscribe_file_transfer_complete();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("file_transfer_complete", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx =
+                    mirrorx_core::component::fs::transfer::subscribe_file_transfer_progress();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("file_transfer_progress", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx =
+                    mirrorx_core::component::video_encoder::adapter::subscribe_encoder_fallback();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("encoder_fallback", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx =
+                    mirrorx_core::component::desktop::capture_error::subscribe_capture_escalation();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("capture_escalation", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = mirrorx_core::utility::thread_priority::subscribe_thread_priority();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("thread_priority", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = mirrorx_core::api::endpoint::idle::subscribe_idle_timeout();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("idle_timeout", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx =
+                    mirrorx_core::component::lan::discover_event::subscribe_discover_events();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("lan_discover_event", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = mirrorx_core::api::endpoint::disconnect::subscribe_remote_disconnect();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("remote_disconnect", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = subscribe_session_thumbnail();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(thumbnail) => {
+                            let _ = handle.emit_all("session_thumbnail", thumbnail);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = subscribe_frame_dump_finished();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(summary) => {
+                            let _ = handle.emit_all("frame_dump_finished", summary);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = subscribe_decoder_fallback();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(fallback) => {
+                            let _ = handle.emit_all("decoder_fallback", fallback);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = mirrorx_core::utility::error_event::subscribe_error_event();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("error_event", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = mirrorx_core::api::endpoint::client_cache::subscribe_connection_lost();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("connection_lost", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = mirrorx_core::api::endpoint::heartbeat::subscribe_heartbeat_state();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("heartbeat_state", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
+            tokio::spawn(command::desktop::serve_chat_message(app.handle()));
+
+            let handle = app.handle();
+            tokio::spawn(async move {
+                let mut rx = mirrorx_core::api::endpoint::swap::subscribe_swap_request();
+
+                loop {
+                    match rx.recv().await {
+                        Ok(event) => {
+                            let _ = handle.emit_all("desktop_swap_request", event);
+                        }
+                        Err(tokio::sync::broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+
             let handle = app.handle();
             std::thread::spawn(move || {
                 let builder = tauri::WindowBuilder::new(
@@ -173,23 +438,144 @@ fn build_app() -> App {
             command::config::config_language_set,
             command::config::config_theme_get,
             command::config::config_theme_set,
+            command::config::config_media_get,
+            command::config::config_media_set,
+            command::config::config_media_preset_set,
+            command::config::config_media_validate,
+            command::config::config_encoder_graphics_card_get,
+            command::config::config_encoder_graphics_card_set,
+            command::config::config_max_incoming_sessions_get,
+            command::config::config_max_incoming_sessions_set,
+            command::config::config_max_frame_length_get,
+            command::config::config_max_frame_length_set,
+            command::config::config_privacy_regions_get,
+            command::config::config_privacy_regions_set,
+            command::config::config_idle_timeout_get,
+            command::config::config_idle_timeout_set,
+            command::config::config_input_coalesce_get,
+            command::config::config_input_coalesce_set,
+            command::config::config_decode_fps_cap_get,
+            command::config::config_decode_fps_cap_set,
+            command::config::config_thumbnail_get,
+            command::config::config_thumbnail_set,
+            command::config::config_cache_get,
+            command::config::config_cache_set,
+            command::config::config_mutual_auth_get,
+            command::config::config_mutual_auth_set,
+            command::config::config_device_public_key_get,
+            command::config::config_file_chunk_size_get,
+            command::config::config_file_chunk_size_set,
+            command::config::config_session_rejoin_get,
+            command::config::config_session_rejoin_set,
+            command::config::config_error_event_window_get,
+            command::config::config_error_event_window_set,
+            command::config::config_reconnect_max_duration_get,
+            command::config::config_reconnect_max_duration_set,
+            command::config::config_bandwidth_aggressiveness_get,
+            command::config::config_bandwidth_aggressiveness_set,
+            command::config::config_connect_retry_get,
+            command::config::config_connect_retry_set,
+            command::config::config_heartbeat_get,
+            command::config::config_heartbeat_set,
+            command::config::config_call_timeouts_get,
+            command::config::config_call_timeouts_set,
+            command::config::config_rekey_get,
+            command::config::config_rekey_set,
+            command::config::config_path_preference_get,
+            command::config::config_path_preference_set,
+            command::config::config_socket_buffer_get,
+            command::config::config_socket_buffer_set,
+            command::config::config_media_budget_get,
+            command::config::config_media_budget_set,
+            command::config::config_media_thread_priority_get,
+            command::config::config_media_thread_priority_set,
+            command::config::config_virtual_display_command_get,
+            command::config::config_virtual_display_command_set,
+            command::config::config_chat_history_get,
+            command::config::config_chat_history_set,
+            command::config::config_signaling_transport_get,
+            command::config::config_signaling_transport_set,
+            command::config::config_signaling_circuit_breaker_get,
+            command::config::config_signaling_circuit_breaker_set,
+            command::config::config_file_browse_get,
+            command::config::config_file_browse_set,
+            command::config::config_access_mode_get,
+            command::config::config_access_mode_set,
+            command::config::config_device_name_get,
+            command::config::config_device_name_set,
+            command::config::config_device_tag_get,
+            command::config::config_device_tag_set,
             command::config::config_history_get,
+            command::config::config_connection_history_get,
+            command::config::config_connection_history_pin,
+            command::config::config_connection_history_rename,
+            command::config::config_connection_history_delete,
+            command::config::config_allow_list_get,
+            command::config::config_allow_list_add,
+            command::config::config_allow_list_remove,
+            command::config::config_allow_list_mode_get,
+            command::config::config_allow_list_mode_set,
+            command::desktop::desktop_session_focus,
+            command::desktop::desktop_content_hint_set,
+            command::desktop::desktop_media_pause,
+            command::desktop::desktop_media_resume,
+            command::desktop::desktop_frame_dump_start,
+            command::desktop::desktop_frame_dump_stop,
+            command::desktop::desktop_session_network_info,
+            command::desktop::desktop_session_stats,
+            command::desktop::desktop_session_remote_name,
+            command::desktop::desktop_windows_list,
+            command::desktop::desktop_shared_window_get,
+            command::desktop::desktop_shared_window_set,
+            command::desktop::desktop_chat_send,
+            command::desktop::desktop_chat_history_get,
+            command::desktop::desktop_chat_records_get,
+            command::desktop::desktop_chat_records_delete,
+            command::desktop::desktop_clipboard_sync_get,
+            command::desktop::desktop_clipboard_sync_set,
+            command::desktop::desktop_swap_request,
+            command::desktop::desktop_swap_respond,
             command::lan::lan_init,
             command::lan::lan_connect,
+            command::lan::lan_connect_cancel,
             command::lan::lan_nodes_list,
             command::lan::lan_nodes_search,
+            command::lan::lan_nodes_filter,
             command::lan::lan_discoverable_get,
             command::lan::lan_discoverable_set,
             command::signaling::signaling_connect,
             command::signaling::signaling_visit,
+            command::signaling::signaling_visit_cancel,
+            command::signaling::signaling_outbound_queue_stats,
+            command::signaling::signaling_circuit_breaker_stats,
+            command::signaling::signaling_key_pool_stats,
+            command::signaling::signaling_active_transport,
+            command::signaling::signaling_one_time_code_rotate,
+            command::signaling::signaling_one_time_code_get,
+            command::signaling::signaling_one_time_code_clear,
             command::file_manager::file_manager_visit_remote,
             command::file_manager::file_manager_visit_local,
+            command::file_manager::file_manager_list_remote,
             command::file_manager::file_manager_send_file,
             command::file_manager::file_manager_download_file,
             command::file_manager::file_manager_query_transferred_bytes_count,
+            command::file_manager::file_manager_cancel_transfer,
+            command::file_manager::file_manager_reconnect,
             command::utility::utility_generate_random_password,
             command::utility::utility_detect_os_platform,
             command::utility::utility_enum_graphics_cards,
+            command::utility::utility_check_platform_permissions,
+            command::utility::utility_check_storage_integrity,
+            command::utility::utility_repair_storage,
+            command::utility::utility_export_settings,
+            command::utility::utility_import_settings,
+            command::utility::utility_cache_usage,
+            command::utility::utility_cache_clear,
+            command::utility::utility_diagnose_connectivity,
+            command::utility::utility_diagnose_connectivity_cancel,
+            command::utility::utility_encode_latency_get,
+            command::utility::utility_encode_trace_get,
+            command::utility::utility_encode_trace_set,
             command::utility::utility_hide_macos_zoom_button,
         ])
         .build(tauri::generate_context!())
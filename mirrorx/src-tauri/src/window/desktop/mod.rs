@@ -5,15 +5,26 @@ use self::render::Render;
 use egui_extras::RetainedImage;
 use mirrorx_core::{
     api::endpoint::{
-        client::EndPointClient,
-        id::EndPointID,
-        message::{EndPointInput, EndPointMessage, InputEvent, KeyboardEvent, MouseEvent},
+        idle::SessionRole,
+        message::{InputEvent, MouseEvent},
+        session::Session,
+    },
+    component::{
+        input::{
+            coalesce::{input_coalesce_window, InputCoalescer},
+            coordinate::ViewMapping,
+            encoding::KeyEncoder,
+            key::MouseKey,
+        },
+        video_encoder::content_hint::ContentMode,
     },
-    component::input::key::MouseKey,
     DesktopDecodeFrame,
 };
 use state::State;
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tauri_egui::{
     eframe::glow::{self, Context},
     egui::{
@@ -30,17 +41,18 @@ pub struct DesktopWindow {
     desktop_render: Arc<Mutex<Render>>,
     icon_maximize: RetainedImage,
     icon_scale: RetainedImage,
+    input_coalescer: InputCoalescer,
+    key_encoder: KeyEncoder,
 }
 
 impl DesktopWindow {
     #[allow(clippy::too_many_arguments)]
     pub fn new(
-        endpoint_id: EndPointID,
         gl_context: Arc<Context>,
-        client: Arc<EndPointClient>,
+        session: Arc<Session>,
         render_frame_rx: tokio::sync::mpsc::Receiver<DesktopDecodeFrame>,
     ) -> Self {
-        let state = State::new(endpoint_id, client, render_frame_rx);
+        let state = State::new(session, render_frame_rx);
 
         let desktop_render =
             Render::new(gl_context.as_ref()).expect("create desktop render failed");
@@ -59,6 +71,8 @@ impl DesktopWindow {
                 "fa_arrows-left-right-to-line",
                 egui_extras::image::load_svg_bytes(ICON_SCALE_BYTES).unwrap(),
             ),
+            input_coalescer: InputCoalescer::new(input_coalesce_window()),
+            key_encoder: KeyEncoder::default(),
         }
     }
 
@@ -113,6 +127,8 @@ impl DesktopWindow {
 
     fn build_desktop_texture(&mut self, ui: &mut Ui) {
         if let Some(frame) = self.state.current_frame() {
+            let (frame_width, frame_height) = (frame.width as f32, frame.height as f32);
+
             // when client area bigger than original desktop frame, disable scale button
             self.state.set_desktop_frame_scalable(
                 ui.available_width() < frame.width as _
@@ -159,8 +175,15 @@ impl DesktopWindow {
 
                             let input = ui.ctx().input();
                             let events = input.events.as_slice();
-                            let left_top = view_port.left_top();
-                            self.emit_input(events, move |pos| Some(pos + left_top.to_vec2()));
+                            let mapping =
+                                ViewMapping::native(view_port.left_top().x, view_port.left_top().y);
+                            let view_origin = Vec2::new(left, top);
+                            self.emit_input(events, move |pos| {
+                                let pos = pos - view_origin;
+                                mapping
+                                    .normalize(pos.x, pos.y, frame_width, frame_height)
+                                    .map(|(x, y)| Pos2::new(x, y))
+                            });
                         });
                 });
             } else {
@@ -174,8 +197,6 @@ impl DesktopWindow {
                     (available_height * aspect_ratio, available_height)
                 };
 
-                let scale_ratio = desktop_size.0 / (frame.width as f32);
-
                 let space_around_image = Vec2::new(
                     (available_width - desktop_size.0) / 2.0,
                     (available_height - desktop_size.1) / 2.0,
@@ -205,19 +226,16 @@ impl DesktopWindow {
 
                 let input = ui.ctx().input();
                 let events = input.events.as_slice();
+                let mapping = ViewMapping::scale_to_fit(
+                    available_width,
+                    available_height,
+                    frame_width,
+                    frame_height,
+                );
                 self.emit_input(events, move |pos| {
-                    if (space_around_image.x <= pos.x
-                        && pos.x <= space_around_image.x + desktop_size.0)
-                        && (space_around_image.y <= pos.y
-                            && pos.y <= space_around_image.y + desktop_size.1)
-                    {
-                        Some(Pos2::new(
-                            (pos.x - space_around_image.x).max(0.0) / scale_ratio,
-                            (pos.y - space_around_image.y).max(0.0) / scale_ratio,
-                        ))
-                    } else {
-                        None
-                    }
+                    mapping
+                        .normalize(pos.x, pos.y, frame_width, frame_height)
+                        .map(|(x, y)| Pos2::new(x, y))
                 });
             }
         } else {
@@ -235,7 +253,7 @@ impl DesktopWindow {
 
     fn build_toolbar(&mut self, ui: &mut Ui) {
         // put the toolbar at central top
-        let (mut rect, _) = ui.allocate_at_least(Vec2::new(220.0, 35.0), Sense::click());
+        let (mut rect, _) = ui.allocate_at_least(Vec2::new(280.0, 35.0), Sense::click());
         rect.set_center(Pos2::new(ui.max_rect().width() / 2.0, 50.0));
 
         ui.allocate_ui_at_rect(rect, |ui| {
@@ -261,12 +279,36 @@ impl DesktopWindow {
 
                         ui.separator();
 
+                        self.build_toolbar_button_cursor(ui);
+
+                        ui.separator();
+
                         // FPS
 
+                        let session = self.state.session();
+                        let decode_stats = session.decode_stats();
+                        let encoder = match session.x264_settings() {
+                            Some((preset, tune)) => format!("libx264 {preset}/{tune}"),
+                            None => session.encoder_graphics_card().unwrap_or_default(),
+                        };
+                        let content = match session.content_mode() {
+                            Some(ContentMode::Text) => "text",
+                            Some(ContentMode::Motion) => "motion",
+                            None => "default",
+                        };
                         ui.label(
                             RichText::new(self.desktop_render.lock().frame_rate().to_string())
                                 .font(FontId::monospace(24.0)), // FontFamily::Name("LiquidCrystal".into()))),
-                        );
+                        )
+                        .on_hover_text(format!(
+                            "{} frames dropped, {} decoded but not shown\n{} dropped over memory budget, {} MiB buffered\nencoder: {}\ncontent: {}",
+                            decode_stats.dropped_frames,
+                            decode_stats.unpresented_frames,
+                            decode_stats.budget_dropped_frames + decode_stats.budget_dropped_renders,
+                            decode_stats.buffered_bytes / 1024 / 1024,
+                            encoder,
+                            content
+                        ));
                     })
                 })
         });
@@ -295,16 +337,71 @@ impl DesktopWindow {
             }
         });
     }
+
+    fn build_toolbar_button_cursor(&mut self, ui: &mut Ui) {
+        let session = self.state.session();
+        let include_cursor = session.include_cursor();
+
+        if ui
+            .selectable_label(include_cursor, "Cursor")
+            .on_hover_text("Show remote cursor")
+            .clicked()
+        {
+            if let Err(err) = session.set_include_cursor(!include_cursor) {
+                tracing::error!(?err, "toggle remote cursor failed");
+            }
+        }
+    }
 }
 
 impl DesktopWindow {
     fn emit_input(
         &mut self,
         events: &[tauri_egui::egui::Event],
+        // maps a window position to the normalized frame position under it
         pos_calc_fn: impl Fn(Pos2) -> Option<Pos2>,
     ) {
+        let session = self.state.session();
+
+        // the session swapped, this device shares its screen now
+        if session.client().role() == Some(SessionRole::Host) {
+            return;
+        }
+
+        // the pointer entered or the keyboard went to this window, so the
+        // user works with this session now
+        if !session.is_focused() && events.iter().any(is_user_input) {
+            if let Err(err) = session.focus() {
+                tracing::error!(?err, "focus desktop session failed");
+            }
+        }
+
         let mut input_commands = Vec::new();
+
+        let input_encoding = session.input_encoding();
+        if self.key_encoder.encoding() != input_encoding {
+            input_commands.extend(
+                self.key_encoder
+                    .set_encoding(input_encoding)
+                    .into_iter()
+                    .map(InputEvent::Keyboard),
+            );
+        }
+
         for event in events.iter() {
+            // a key waiting for its text goes out before anything else
+            if !matches!(
+                event,
+                tauri_egui::egui::Event::RawKeyInput { .. } | tauri_egui::egui::Event::Text(_)
+            ) {
+                input_commands.extend(
+                    self.key_encoder
+                        .flush()
+                        .into_iter()
+                        .map(InputEvent::Keyboard),
+                );
+            }
+
             match event {
                 tauri_egui::egui::Event::PointerMoved(pos) => {
                     if let Some(mouse_pos) = pos_calc_fn(*pos) {
@@ -352,34 +449,56 @@ impl DesktopWindow {
                 tauri_egui::egui::Event::RawKeyInput { key, pressed } => {
                     tracing::info!(?key, "raw key");
 
-                    let keyboard_event = if *pressed {
-                        KeyboardEvent::KeyDown(*key)
-                    } else {
-                        KeyboardEvent::KeyUp(*key)
-                    };
-
-                    input_commands.push(InputEvent::Keyboard(keyboard_event))
+                    input_commands.extend(
+                        self.key_encoder
+                            .key(*key, *pressed)
+                            .into_iter()
+                            .map(InputEvent::Keyboard),
+                    );
+                }
+                tauri_egui::egui::Event::Text(text) => {
+                    input_commands.extend(
+                        self.key_encoder
+                            .text(text)
+                            .into_iter()
+                            .map(InputEvent::Keyboard),
+                    );
                 }
                 _ => {}
             }
         }
 
-        if input_commands.is_empty() {
+        // the text of a key comes in the same frame
+        input_commands.extend(
+            self.key_encoder
+                .flush()
+                .into_iter()
+                .map(InputEvent::Keyboard),
+        );
+
+        // called every frame, so held mouse moves go out once their window ends
+        let Some(input) = self.input_coalescer.push(input_commands, Instant::now()) else {
             return;
-        }
+        };
 
-        if let Err(err) = self
-            .state
-            .endpoint_client()
-            .try_send(&EndPointMessage::InputCommand(EndPointInput {
-                events: input_commands,
-            }))
-        {
+        // sessions in the background keep rendering but don't take input
+        if let Err(err) = session.send_input(input) {
             tracing::error!(?err, "send input event failed");
         }
     }
 }
 
+fn is_user_input(event: &tauri_egui::egui::Event) -> bool {
+    matches!(
+        event,
+        tauri_egui::egui::Event::PointerMoved(_)
+            | tauri_egui::egui::Event::PointerButton { .. }
+            | tauri_egui::egui::Event::Scroll(_)
+            | tauri_egui::egui::Event::RawKeyInput { .. }
+            | tauri_egui::egui::Event::Text(_)
+    )
+}
+
 impl tauri_egui::eframe::App for DesktopWindow {
     fn update(&mut self, ctx: &tauri_egui::egui::Context, _: &mut tauri_egui::eframe::Frame) {
         let update_instant = std::time::Instant::now();
@@ -403,5 +522,7 @@ impl tauri_egui::eframe::App for DesktopWindow {
         if let Some(gl) = gl {
             self.desktop_render.lock().destroy(gl);
         }
+
+        self.state.session().close();
     }
 }
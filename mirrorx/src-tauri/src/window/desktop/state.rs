@@ -1,6 +1,6 @@
 use crate::utility::format_device_id;
 use mirrorx_core::{
-    api::endpoint::{client::EndPointClient, id::EndPointID},
+    api::endpoint::{id::EndPointID, session::Session},
     DesktopDecodeFrame,
 };
 use std::sync::Arc;
@@ -8,7 +8,7 @@ use tokio::sync::mpsc::Receiver;
 
 pub struct State {
     format_remote_device_id: String,
-    endpoint_client: Arc<EndPointClient>,
+    session: Arc<Session>,
     desktop_frame_scaled: bool,
     desktop_frame_scalable: bool,
     // last_error: Option<CoreError>,
@@ -18,11 +18,10 @@ pub struct State {
 
 impl State {
     pub fn new(
-        endpoint_id: EndPointID,
-        client: Arc<EndPointClient>,
+        session: Arc<Session>,
         render_frame_rx: tokio::sync::mpsc::Receiver<DesktopDecodeFrame>,
     ) -> Self {
-        let format_remote_device_id = match endpoint_id {
+        let format_remote_device_id = match session.endpoint_id() {
             EndPointID::DeviceID {
                 remote_device_id: remote,
                 ..
@@ -34,7 +33,7 @@ impl State {
 
         Self {
             format_remote_device_id,
-            endpoint_client: client,
+            session,
             desktop_frame_scaled: true,
             desktop_frame_scalable: true,
             // last_error: None,
@@ -47,8 +46,8 @@ impl State {
         self.format_remote_device_id.as_ref()
     }
 
-    pub fn endpoint_client(&self) -> Arc<EndPointClient> {
-        self.endpoint_client.clone()
+    pub fn session(&self) -> Arc<Session> {
+        self.session.clone()
     }
 
     pub fn desktop_frame_scaled(&self) -> bool {
@@ -1,10 +1,7 @@
 mod desktop;
 
 use self::desktop::DesktopWindow;
-use mirrorx_core::{
-    api::endpoint::{client::EndPointClient, id::EndPointID},
-    DesktopDecodeFrame,
-};
+use mirrorx_core::{api::endpoint::session::Session, DesktopDecodeFrame};
 use once_cell::sync::Lazy;
 use std::{collections::HashMap, sync::Arc};
 use tauri_egui::{
@@ -52,15 +49,14 @@ static MONOSPACE_FONTS: Lazy<HashMap<&str, &[u8]>> = Lazy::new(|| {
 pub fn create_desktop_window(
     cc: &CreationContext,
     gl_context: Arc<tauri_egui::eframe::glow::Context>,
-    endpoint_id: EndPointID,
-    client: Arc<EndPointClient>,
+    session: Arc<Session>,
     render_frame_rx: tokio::sync::mpsc::Receiver<DesktopDecodeFrame>,
 ) -> DesktopWindow {
     set_fonts(&cc.egui_ctx);
 
     // cc.egui_ctx.set_debug_on_hover(true);
 
-    crate::window::desktop::DesktopWindow::new(endpoint_id, gl_context, client, render_frame_rx)
+    crate::window::desktop::DesktopWindow::new(gl_context, session, render_frame_rx)
 }
 
 fn set_fonts(ctx: &tauri_egui::egui::Context) {
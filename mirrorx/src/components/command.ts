@@ -1,5 +1,11 @@
 import { invoke } from '@tauri-apps/api';
-import type { Directory, Domain, HistoryRecord, LanDiscoverNode } from '$lib/components/types';
+import type {
+	ConnectionHistoryEntry,
+	Directory,
+	Domain,
+	HistoryRecord,
+	LanDiscoverNode
+} from '$lib/components/types';
 
 export function invoke_config_init(): Promise<void> {
 	return invoke('config_init');
@@ -61,6 +67,28 @@ export function invoke_config_history_get(
 	return invoke('config_history_get', { timeRange: time_range });
 }
 
+export function invoke_config_connection_history_get(): Promise<Array<ConnectionHistoryEntry>> {
+	return invoke('config_connection_history_get');
+}
+
+export function invoke_config_connection_history_pin(
+	remote: string,
+	pinned: boolean
+): Promise<void> {
+	return invoke('config_connection_history_pin', { remote, pinned });
+}
+
+export function invoke_config_connection_history_rename(
+	remote: string,
+	nickname: string | null
+): Promise<void> {
+	return invoke('config_connection_history_rename', { remote, nickname });
+}
+
+export function invoke_config_connection_history_delete(remote: string): Promise<void> {
+	return invoke('config_connection_history_delete', { remote });
+}
+
 export function invoke_lan_init(force: boolean): Promise<void> {
 	return invoke('lan_init', { force });
 }
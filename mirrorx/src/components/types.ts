@@ -18,6 +18,7 @@ export interface LanDiscoverNode {
 	addr: string;
 	os: string;
 	os_version: string;
+	nickname: string | null;
 }
 
 export interface HistoryRecord {
@@ -27,6 +28,15 @@ export interface HistoryRecord {
 	timestamp: number;
 }
 
+export interface ConnectionHistoryEntry {
+	remote: string;
+	domain: string | null;
+	nickname: string | null;
+	pinned: boolean;
+	connect_count: number;
+	last_connected: number;
+}
+
 export interface Directory {
 	path: string;
 	entries: Array<Entry>;
@@ -52,3 +62,8 @@ export interface FileTransferItem {
 	succeed_at: number;
 	failed_at: number;
 }
+
+export interface VisitFailure {
+	message: string;
+	recovery: 'retry_later' | 'reenter_password' | 'none';
+}
@@ -0,0 +1,190 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mirrorx_core::{
+    api::signaling::key_exchange::{
+        key_agreement, ActiveKeyExchange, KeyDerivation, KeyDirection, REPLY_KEY_BITS,
+    },
+    utility::nonce_value::NonceValue,
+};
+use rand::rngs::OsRng;
+use ring::{
+    aead::{Aad, Algorithm, BoundKey, OpeningKey, SealingKey, UnboundKey, NONCE_LEN},
+    agreement::{EphemeralPrivateKey, UnparsedPublicKey, X25519},
+    rand::{SecureRandom, SystemRandom},
+};
+use rsa::RsaPrivateKey;
+
+const FRAME_SIZES: [usize; 3] = [64 * 1024, 1024 * 1024, 8 * 1024 * 1024];
+
+const PASSWORD: &str = "bench password";
+
+/// The keys of one direction of an endpoint connection, as a visit hands
+/// them to the passive and the active device.
+fn session_keys(
+    reply_private_key: &RsaPrivateKey,
+) -> (SealingKey<NonceValue>, OpeningKey<NonceValue>) {
+    let exchange =
+        ActiveKeyExchange::new(&mut OsRng, reply_private_key.clone(), 1, PASSWORD).unwrap();
+
+    let (reply, passive_sealing_key, _) = key_agreement(
+        &mut OsRng,
+        PASSWORD,
+        1,
+        &exchange.password_salt,
+        exchange.secret.clone(),
+        &exchange.secret_nonce,
+    )
+    .unwrap();
+
+    let (active_opening_key, _) = exchange.open_reply(&reply).unwrap();
+
+    (passive_sealing_key, active_opening_key)
+}
+
+/// Keys derived the way [`KeyDerivation::LATEST`] derives session keys,
+/// for an algorithm the key exchange doesn't choose yet.
+fn derived_keys(algorithm: &'static Algorithm) -> (SealingKey<NonceValue>, OpeningKey<NonceValue>) {
+    let rng = SystemRandom::new();
+
+    let mut key_material = [0u8; 32];
+    rng.fill(&mut key_material).unwrap();
+
+    let mut nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut nonce).unwrap();
+
+    let key = KeyDerivation::LATEST
+        .derive(&key_material, &nonce, KeyDirection::PassiveToActive)
+        .unwrap();
+
+    let sealing_key = SealingKey::new(
+        UnboundKey::new(algorithm, &key).unwrap(),
+        NonceValue::new(nonce),
+    );
+
+    let opening_key = OpeningKey::new(
+        UnboundKey::new(algorithm, &key).unwrap(),
+        NonceValue::new(nonce),
+    );
+
+    (sealing_key, opening_key)
+}
+
+/// Same operations as the endpoint write and read loops: seal the frame in
+/// place with an empty aad, then open it in place.
+fn bench_aead(c: &mut Criterion) {
+    let reply_private_key = RsaPrivateKey::new(&mut OsRng, REPLY_KEY_BITS).unwrap();
+
+    for name in ["aes_256_gcm", "chacha20_poly1305"] {
+        let key_pair = || match name {
+            "aes_256_gcm" => session_keys(&reply_private_key),
+            _ => derived_keys(&ring::aead::CHACHA20_POLY1305),
+        };
+
+        let mut group = c.benchmark_group(format!("aead/{name}"));
+
+        for frame_size in FRAME_SIZES {
+            group.throughput(Throughput::Bytes(frame_size as u64));
+
+            let (mut sealing_key, _) = key_pair();
+            let mut buffer = vec![0u8; frame_size];
+            group.bench_with_input(BenchmarkId::new("seal", frame_size), &frame_size, |b, _| {
+                b.iter(|| {
+                    buffer.truncate(frame_size);
+                    sealing_key
+                        .seal_in_place_append_tag(Aad::empty(), &mut buffer)
+                        .unwrap();
+                })
+            });
+
+            let (mut sealing_key, mut opening_key) = key_pair();
+            group.bench_with_input(BenchmarkId::new("open", frame_size), &frame_size, |b, _| {
+                b.iter_batched_ref(
+                    || {
+                        let mut buffer = vec![0u8; frame_size];
+                        sealing_key
+                            .seal_in_place_append_tag(Aad::empty(), &mut buffer)
+                            .unwrap();
+                        buffer
+                    },
+                    |buffer| {
+                        opening_key.open_in_place(Aad::empty(), buffer).unwrap();
+                    },
+                    criterion::BatchSize::LargeInput,
+                )
+            });
+        }
+
+        group.finish();
+    }
+}
+
+fn bench_key_exchange(c: &mut Criterion) {
+    let rng = SystemRandom::new();
+
+    let mut active_nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut active_nonce).unwrap();
+
+    let mut passive_nonce = [0u8; NONCE_LEN];
+    rng.fill(&mut passive_nonce).unwrap();
+
+    // the reply key is generated once like a pooled one
+    let reply_private_key = RsaPrivateKey::new(&mut OsRng, REPLY_KEY_BITS).unwrap();
+
+    let mut group = c.benchmark_group("key_exchange");
+
+    // the ephemeral agreement and the derivation of both session keys done
+    // by each side of a visit
+    group.bench_function("x25519_derive", |b| {
+        b.iter(|| {
+            let local_private_key = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
+            let remote_private_key = EphemeralPrivateKey::generate(&X25519, &rng).unwrap();
+            let remote_public_key = remote_private_key.compute_public_key().unwrap();
+
+            ring::agreement::agree_ephemeral(
+                local_private_key,
+                &UnparsedPublicKey::new(&X25519, remote_public_key.as_ref()),
+                ring::error::Unspecified,
+                |key_material| {
+                    let sealing_key = KeyDerivation::LATEST.derive(
+                        key_material,
+                        &active_nonce,
+                        KeyDirection::ActiveToPassive,
+                    )?;
+                    let opening_key = KeyDerivation::LATEST.derive(
+                        key_material,
+                        &passive_nonce,
+                        KeyDirection::PassiveToActive,
+                    )?;
+
+                    Ok((sealing_key, opening_key))
+                },
+            )
+            .unwrap()
+        })
+    });
+
+    // the whole visit exchange, password sealing and the encrypted reply
+    // included
+    group.sample_size(10);
+    group.bench_function("visit", |b| {
+        b.iter(|| {
+            let exchange =
+                ActiveKeyExchange::new(&mut OsRng, reply_private_key.clone(), 1, PASSWORD).unwrap();
+
+            let (reply, _, _) = key_agreement(
+                &mut OsRng,
+                PASSWORD,
+                1,
+                &exchange.password_salt,
+                exchange.secret.clone(),
+                &exchange.secret_nonce,
+            )
+            .unwrap();
+
+            exchange.open_reply(&reply).unwrap()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_aead, bench_key_exchange);
+criterion_main!(benches);
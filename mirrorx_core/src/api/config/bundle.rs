@@ -0,0 +1,264 @@
+use super::{entity::kv::Theme, Storage};
+use crate::{
+    api::{endpoint::idle::IdleTimeoutConfig, signaling::identity::MutualAuthConfig},
+    component::video_encoder::{media_config::MediaConfig, preprocess::PrivacyRegion},
+    core_error,
+    error::{CoreError, CoreResult},
+    utility::nonce_value::NonceValue,
+};
+use hmac::Hmac;
+use rand::{rngs::OsRng, RngCore};
+use ring::aead::{BoundKey, OpeningKey, SealingKey, UnboundKey};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{collections::BTreeMap, str::FromStr};
+
+/// Format of the bundle envelope and its payload. Settings are carried by
+/// key, so adding a setting doesn't change the format.
+pub const SETTINGS_BUNDLE_VERSION: u32 = 1;
+
+const PBKDF2_ROUNDS: u32 = 100_000;
+
+/// Settings carried between devices. The device identity, its password and
+/// keys live in the domains table and never go into a bundle, neither does
+/// the private key the device signs visits with, or settings tied to the
+/// hardware or the file system of one device, like the pinned graphics card
+/// or the exposed directory. The trusted devices come along.
+const EXPORTED_SETTINGS: [&str; 11] = [
+    "language",
+    "theme",
+    "media_config",
+    "max_incoming_sessions",
+    "privacy_regions",
+    "idle_timeout_config",
+    "input_coalesce_window_ms",
+    "session_rejoin_window_secs",
+    "chat_history_enabled",
+    "mutual_auth_config",
+    "allow_list_enabled",
+];
+
+/// The allow list has a table of its own, it's carried as a setting holding
+/// the JSON array of the allowed device ids.
+const ALLOW_LIST: &str = "allow_list";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportMode {
+    /// Keeps the settings this device has and only adds the missing ones.
+    Merge,
+    /// Takes every setting of the bundle and resets the ones it lacks.
+    Replace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ImportSummary {
+    /// Version of the app the bundle was exported by.
+    pub app_version: String,
+    pub imported: Vec<String>,
+    /// Settings of the bundle not taken because this device has them.
+    pub kept: Vec<String>,
+    /// Settings reset because the bundle lacks them.
+    pub reset: Vec<String>,
+    /// Settings of the bundle this app doesn't know or can't read, usually
+    /// from an app of another version.
+    pub skipped: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SettingsBundle {
+    version: u32,
+    app_version: String,
+    salt: String,
+    nonce: String,
+    payload: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SettingsPayload {
+    settings: BTreeMap<String, String>,
+}
+
+/// Exports the settings as a bundle sealed with the passphrase.
+pub fn export_settings<S: Storage>(storage: &S, passphrase: &str) -> CoreResult<String> {
+    if passphrase.is_empty() {
+        return Err(core_error!("settings bundle passphrase is empty"));
+    }
+
+    let mut settings = BTreeMap::new();
+    for key in EXPORTED_SETTINGS {
+        if let Some(value) = storage.kv().get(key)? {
+            settings.insert(key.to_string(), value);
+        }
+    }
+
+    let allowed_devices: Vec<i64> = storage
+        .allow_list()
+        .query()?
+        .into_iter()
+        .map(|device| device.device_id)
+        .collect();
+    if !allowed_devices.is_empty() {
+        settings.insert(
+            ALLOW_LIST.to_string(),
+            serde_json::to_string(&allowed_devices)?,
+        );
+    }
+
+    let mut payload = serde_json::to_vec(&SettingsPayload { settings })?;
+
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut nonce = [0u8; ring::aead::NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+
+    let mut sealing_key = SealingKey::new(
+        UnboundKey::new(&ring::aead::AES_256_GCM, &derive_key(passphrase, &salt))?,
+        NonceValue::new(nonce),
+    );
+
+    sealing_key.seal_in_place_append_tag(
+        ring::aead::Aad::from(SETTINGS_BUNDLE_VERSION.to_le_bytes()),
+        &mut payload,
+    )?;
+
+    let bundle = SettingsBundle {
+        version: SETTINGS_BUNDLE_VERSION,
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        salt: base64::encode(salt),
+        nonce: base64::encode(nonce),
+        payload: base64::encode(payload),
+    };
+
+    Ok(serde_json::to_string(&bundle)?)
+}
+
+/// Opens a bundle and writes its settings to the storage. The process wide
+/// settings have to be reloaded from the storage afterwards.
+pub fn import_settings<S: Storage>(
+    storage: &S,
+    bundle: &str,
+    passphrase: &str,
+    mode: ImportMode,
+) -> CoreResult<ImportSummary> {
+    let bundle: SettingsBundle = serde_json::from_str(bundle)?;
+
+    if bundle.version > SETTINGS_BUNDLE_VERSION {
+        return Err(CoreError::SettingsBundleUnsupportedVersion(bundle.version));
+    }
+
+    let salt = base64::decode(bundle.salt)?;
+    let nonce = base64::decode(bundle.nonce)?;
+    let mut payload = base64::decode(bundle.payload)?;
+
+    let nonce: [u8; ring::aead::NONCE_LEN] = nonce
+        .try_into()
+        .map_err(|_| CoreError::SettingsBundleInvalidPassphrase)?;
+
+    let mut opening_key = OpeningKey::new(
+        UnboundKey::new(&ring::aead::AES_256_GCM, &derive_key(passphrase, &salt))?,
+        NonceValue::new(nonce),
+    );
+
+    let payload = opening_key
+        .open_in_place(
+            ring::aead::Aad::from(bundle.version.to_le_bytes()),
+            &mut payload,
+        )
+        .map_err(|_| CoreError::SettingsBundleInvalidPassphrase)?;
+
+    let payload: SettingsPayload = serde_json::from_slice(payload)?;
+
+    let mut summary = ImportSummary {
+        app_version: bundle.app_version,
+        imported: Vec::new(),
+        kept: Vec::new(),
+        reset: Vec::new(),
+        skipped: Vec::new(),
+    };
+
+    for (key, value) in payload.settings.iter() {
+        let known = key == ALLOW_LIST || EXPORTED_SETTINGS.contains(&key.as_str());
+        if !known || !is_valid_setting(key, value) {
+            tracing::warn!(?key, "skip setting of settings bundle");
+            summary.skipped.push(key.clone());
+            continue;
+        }
+
+        // merged, the devices this one allows stay on its list
+        if key == ALLOW_LIST {
+            let device_ids: Vec<i64> = serde_json::from_str(value)?;
+            if mode == ImportMode::Replace {
+                retain_allow_list(storage, &device_ids)?;
+            }
+            for device_id in device_ids {
+                storage.allow_list().add(device_id)?;
+            }
+
+            summary.imported.push(key.clone());
+            continue;
+        }
+
+        if mode == ImportMode::Merge && storage.kv().get(key)?.is_some() {
+            summary.kept.push(key.clone());
+            continue;
+        }
+
+        storage.kv().set(key, value)?;
+        summary.imported.push(key.clone());
+    }
+
+    if mode == ImportMode::Replace {
+        for key in EXPORTED_SETTINGS {
+            if !summary.imported.iter().any(|imported| imported == key)
+                && storage.kv().get(key)?.is_some()
+            {
+                storage.kv().remove(key)?;
+                summary.reset.push(key.to_string());
+            }
+        }
+
+        let allow_list_imported = summary.imported.iter().any(|key| key == ALLOW_LIST);
+        if !allow_list_imported && !storage.allow_list().query()?.is_empty() {
+            retain_allow_list(storage, &[])?;
+            summary.reset.push(ALLOW_LIST.to_string());
+        }
+    }
+
+    Ok(summary)
+}
+
+/// Keeps only the devices of `kept` on the allow list.
+fn retain_allow_list<S: Storage>(storage: &S, kept: &[i64]) -> CoreResult<()> {
+    for device in storage.allow_list().query()? {
+        if !kept.contains(&device.device_id) {
+            storage.allow_list().remove(device.device_id)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn derive_key(passphrase: &str, salt: &[u8]) -> [u8; 32] {
+    let mut key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, PBKDF2_ROUNDS, &mut key);
+    key
+}
+
+/// Whether this app reads the value, an app of another version may have
+/// stored it in a different shape.
+fn is_valid_setting(key: &str, value: &str) -> bool {
+    match key {
+        "theme" => Theme::from_str(value).is_ok(),
+        "media_config" => serde_json::from_str::<MediaConfig>(value).is_ok(),
+        "max_incoming_sessions" => value.parse::<usize>().is_ok(),
+        "privacy_regions" => serde_json::from_str::<Vec<PrivacyRegion>>(value).is_ok(),
+        "idle_timeout_config" => serde_json::from_str::<IdleTimeoutConfig>(value).is_ok(),
+        "input_coalesce_window_ms" | "session_rejoin_window_secs" => value.parse::<u64>().is_ok(),
+        "chat_history_enabled" | "allow_list_enabled" => value.parse::<bool>().is_ok(),
+        "mutual_auth_config" => serde_json::from_str::<MutualAuthConfig>(value).is_ok(),
+        ALLOW_LIST => serde_json::from_str::<Vec<i64>>(value).is_ok(),
+        _ => true,
+    }
+}
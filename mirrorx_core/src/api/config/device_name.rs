@@ -0,0 +1,85 @@
+use crate::{core_error, error::CoreResult};
+use once_cell::sync::Lazy;
+use std::{ffi::OsStr, sync::RwLock};
+
+/// Longest device name in characters, other devices show it in lists and
+/// window titles.
+pub const MAX_DEVICE_NAME_CHARS: usize = 64;
+
+/// Shown when not even the host name is readable.
+const FALLBACK_DEVICE_NAME: &str = "MirrorX";
+
+static DEVICE_NAME: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+/// The name this device shows to others, the one the user gave it or the
+/// host name.
+pub fn device_name() -> String {
+    let configured = DEVICE_NAME.read().ok().and_then(|name| name.clone());
+    configured.unwrap_or_else(default_device_name)
+}
+
+/// `None` goes back to the host name. Applies to the next LAN broadcast
+/// and to connections opened afterwards.
+pub fn set_device_name(name: Option<String>) {
+    if let Ok(mut device_name) = DEVICE_NAME.write() {
+        *device_name = name;
+    }
+}
+
+/// The host name, trimmed to a device name.
+pub fn default_device_name() -> String {
+    hostname::get()
+        .ok()
+        .and_then(|host_name| convert_host_name_to_string(&host_name).ok())
+        .and_then(|host_name| sanitize_device_name(&host_name))
+        .unwrap_or_else(|| FALLBACK_DEVICE_NAME.to_string())
+}
+
+/// Checks a name the user entered. Whitespace around it is dropped, what's
+/// left mustn't be empty, contain control characters or be longer than
+/// [`MAX_DEVICE_NAME_CHARS`].
+pub fn validate_device_name(name: &str) -> CoreResult<String> {
+    let name = name.trim();
+
+    if name.is_empty() {
+        return Err(core_error!("device name is empty"));
+    }
+
+    if name.chars().any(char::is_control) {
+        return Err(core_error!("device name contains control characters"));
+    }
+
+    if name.chars().count() > MAX_DEVICE_NAME_CHARS {
+        return Err(core_error!(
+            "device name is longer than {} characters",
+            MAX_DEVICE_NAME_CHARS
+        ));
+    }
+
+    Ok(name.to_string())
+}
+
+/// Makes a name of another device or the host name safe to show: control
+/// characters are dropped and it's cut to [`MAX_DEVICE_NAME_CHARS`]. `None`
+/// when nothing is left.
+pub fn sanitize_device_name(name: &str) -> Option<String> {
+    let name: String = name.chars().filter(|c| !c.is_control()).collect();
+    let name: String = name.trim().chars().take(MAX_DEVICE_NAME_CHARS).collect();
+
+    let name = name.trim_end();
+    (!name.is_empty()).then(|| name.to_string())
+}
+
+pub(crate) fn convert_host_name_to_string(v: &OsStr) -> CoreResult<String> {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::ffi::OsStrExt;
+
+        let result: Vec<u16> = v.encode_wide().collect();
+        String::from_utf16(&result)
+            .map_err(|err| core_error!("convert host name to string failed ({:?})", err))
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    Ok(v.to_string_lossy().to_string())
+}
@@ -0,0 +1,60 @@
+use crate::{core_error, error::CoreResult};
+use once_cell::sync::Lazy;
+use std::sync::RwLock;
+
+/// Longest device tag in characters, it's a short label like a floor or a
+/// team rather than a name.
+pub const MAX_DEVICE_TAG_CHARS: usize = 32;
+
+static DEVICE_TAG: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+/// The tag this device shows to others on the LAN, `None` until the user
+/// gives it one.
+pub fn device_tag() -> Option<String> {
+    DEVICE_TAG.read().ok().and_then(|tag| tag.clone())
+}
+
+/// `None` removes the tag. Applies to the next LAN broadcast.
+pub fn set_device_tag(tag: Option<String>) {
+    if let Ok(mut device_tag) = DEVICE_TAG.write() {
+        *device_tag = tag;
+    }
+}
+
+/// Checks a tag the user entered. Whitespace around it is dropped, what's
+/// left mustn't be empty, contain whitespace or control characters or be
+/// longer than [`MAX_DEVICE_TAG_CHARS`].
+pub fn validate_device_tag(tag: &str) -> CoreResult<String> {
+    let tag = tag.trim();
+
+    if tag.is_empty() {
+        return Err(core_error!("device tag is empty"));
+    }
+
+    if tag.chars().any(|c| c.is_control() || c.is_whitespace()) {
+        return Err(core_error!(
+            "device tag contains whitespace or control characters"
+        ));
+    }
+
+    if tag.chars().count() > MAX_DEVICE_TAG_CHARS {
+        return Err(core_error!(
+            "device tag is longer than {} characters",
+            MAX_DEVICE_TAG_CHARS
+        ));
+    }
+
+    Ok(tag.to_string())
+}
+
+/// Makes the tag of another device safe to show, `None` when nothing is
+/// left of it.
+pub fn sanitize_device_tag(tag: &str) -> Option<String> {
+    let tag: String = tag
+        .chars()
+        .filter(|c| !c.is_control() && !c.is_whitespace())
+        .take(MAX_DEVICE_TAG_CHARS)
+        .collect();
+
+    (!tag.is_empty()).then_some(tag)
+}
@@ -0,0 +1,97 @@
+use crate::error::CoreResult;
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, OptionalExtension, Row};
+use serde::Serialize;
+
+/// A device allowed to visit while allow-list mode is enabled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AllowedDevice {
+    pub device_id: i64,
+    pub timestamp: i64,
+}
+
+pub struct AllowListRepository {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl AllowListRepository {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    pub fn ensure_table(&self) -> CoreResult<()> {
+        let conn = self.pool.get()?;
+
+        const CREATE_TABLE_COMMAND: &str = r"
+        CREATE TABLE IF NOT EXISTS allow_list(
+            id INTEGER PRIMARY KEY,
+            device_id INTEGER NOT NULL UNIQUE,
+            timestamp INTEGER NOT NULL
+        )";
+
+        conn.execute(CREATE_TABLE_COMMAND, [])?;
+
+        Ok(())
+    }
+
+    /// Adding a device on the list already keeps it as it is.
+    pub fn add(&self, device_id: i64) -> CoreResult<()> {
+        const COMMAND: &str =
+            r"INSERT INTO allow_list(device_id, timestamp) VALUES(?, ?) ON CONFLICT DO NOTHING";
+
+        let timestamp = chrono::Utc::now().timestamp();
+
+        let _ = self
+            .pool
+            .get()?
+            .execute(COMMAND, params![device_id, timestamp])?;
+
+        Ok(())
+    }
+
+    pub fn remove(&self, device_id: i64) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM allow_list WHERE device_id = ?";
+
+        let _ = self.pool.get()?.execute(COMMAND, params![device_id])?;
+
+        Ok(())
+    }
+
+    pub fn contains(&self, device_id: i64) -> CoreResult<bool> {
+        const COMMAND: &str = r"SELECT 1 FROM allow_list WHERE device_id = ? LIMIT 1";
+
+        let found = self
+            .pool
+            .get()?
+            .query_row(COMMAND, params![device_id], |_| Ok(()))
+            .optional()?;
+
+        Ok(found.is_some())
+    }
+
+    /// The latest added first.
+    pub fn query(&self) -> CoreResult<Vec<AllowedDevice>> {
+        const COMMAND: &str =
+            r"SELECT device_id, timestamp FROM allow_list ORDER BY timestamp DESC, id DESC";
+
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(COMMAND)?;
+        let rows = stmt.query_and_then([], parse_allowed_device)?;
+
+        let mut devices = Vec::new();
+        for row in rows {
+            devices.push(row?);
+        }
+
+        Ok(devices)
+    }
+}
+
+fn parse_allowed_device(row: &Row) -> CoreResult<AllowedDevice> {
+    Ok(AllowedDevice {
+        device_id: row.get(0)?,
+        timestamp: row.get(1)?,
+    })
+}
@@ -0,0 +1,100 @@
+use crate::{
+    api::endpoint::chat::{ChatDirection, ChatEntry},
+    core_error,
+    error::CoreResult,
+};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Row};
+
+/// Chat messages kept past the session, only written when the user enabled
+/// chat history.
+pub struct ChatRepository {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl ChatRepository {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    pub fn ensure_table(&self) -> CoreResult<()> {
+        let conn = self.pool.get()?;
+
+        const CREATE_TABLE_COMMAND: &str = r"
+        CREATE TABLE IF NOT EXISTS chat(
+            id INTEGER PRIMARY KEY,
+            remote TEXT NOT NULL,
+            direction TEXT NOT NULL,
+            text TEXT NOT NULL,
+            timestamp INTEGER NOT NULL
+        )";
+
+        conn.execute(CREATE_TABLE_COMMAND, [])?;
+
+        const CREATE_INDEX_COMMAND: &str = r"
+        CREATE INDEX IF NOT EXISTS idx_chat_remote ON chat(remote, timestamp)";
+
+        conn.execute(CREATE_INDEX_COMMAND, [])?;
+
+        Ok(())
+    }
+
+    pub fn create(&self, entry: &ChatEntry) -> CoreResult<()> {
+        const COMMAND: &str =
+            r"INSERT INTO chat(remote, direction, text, timestamp) VALUES(?, ?, ?, ?)";
+
+        let direction = match entry.direction {
+            ChatDirection::Sent => "sent",
+            ChatDirection::Received => "received",
+        };
+
+        let _ = self.pool.get()?.execute(
+            COMMAND,
+            params![entry.remote, direction, entry.text, entry.timestamp],
+        )?;
+
+        Ok(())
+    }
+
+    /// The latest messages exchanged with the remote device, oldest first.
+    pub fn query(&self, remote: &str, limit: u32) -> CoreResult<Vec<ChatEntry>> {
+        const COMMAND: &str = r"SELECT remote, direction, text, timestamp FROM (SELECT * FROM chat WHERE remote = ? ORDER BY timestamp DESC, id DESC LIMIT ?) ORDER BY timestamp ASC, id ASC";
+
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(COMMAND)?;
+        let rows = stmt.query_and_then(params![remote, limit], parse_chat_entry)?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(row?);
+        }
+
+        Ok(entries)
+    }
+
+    pub fn delete(&self, remote: &str) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM chat WHERE remote = ?";
+
+        let _ = self.pool.get()?.execute(COMMAND, params![remote])?;
+
+        Ok(())
+    }
+}
+
+fn parse_chat_entry(row: &Row) -> CoreResult<ChatEntry> {
+    let direction: String = row.get(1)?;
+    let direction = match direction.as_str() {
+        "sent" => ChatDirection::Sent,
+        "received" => ChatDirection::Received,
+        _ => return Err(core_error!("unknown chat direction ({})", direction)),
+    };
+
+    Ok(ChatEntry {
+        remote: row.get(0)?,
+        direction,
+        text: row.get(2)?,
+        timestamp: row.get(3)?,
+    })
+}
@@ -0,0 +1,160 @@
+use crate::{core_error, error::CoreResult};
+use r2d2::Pool;
+use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::{params, Row};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Most unpinned devices kept, the ones connected to longest ago go first.
+pub const CONNECTION_HISTORY_LIMIT: u32 = 100;
+
+/// Longest nickname in characters.
+pub const NICKNAME_MAX_CHARS: usize = 64;
+
+/// A device connected to before, offered for reconnecting with one click.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConnectionHistoryEntry {
+    /// Device id of the remote device, or its ip for LAN connections.
+    pub remote: String,
+    /// Domain the device id belongs to, `None` for LAN connections.
+    pub domain: Option<String>,
+    pub nickname: Option<String>,
+    /// Pinned devices stay on top and are never pruned.
+    pub pinned: bool,
+    pub connect_count: u32,
+    pub last_connected: i64,
+}
+
+pub struct ConnectionHistoryRepository {
+    pool: Pool<SqliteConnectionManager>,
+}
+
+impl ConnectionHistoryRepository {
+    pub fn new(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool }
+    }
+
+    pub fn ensure_table(&self) -> CoreResult<()> {
+        let conn = self.pool.get()?;
+
+        const CREATE_TABLE_COMMAND: &str = r"
+        CREATE TABLE IF NOT EXISTS connection_history(
+            id INTEGER PRIMARY KEY,
+            remote TEXT NOT NULL UNIQUE,
+            domain TEXT,
+            nickname TEXT,
+            pinned INTEGER NOT NULL DEFAULT 0,
+            connect_count INTEGER NOT NULL DEFAULT 0,
+            last_connected INTEGER NOT NULL
+        )";
+
+        conn.execute(CREATE_TABLE_COMMAND, [])?;
+
+        Ok(())
+    }
+
+    /// Records a connection to the remote device and prunes the devices
+    /// beyond [`CONNECTION_HISTORY_LIMIT`].
+    pub fn record(&self, remote: &str, domain: Option<&str>) -> CoreResult<()> {
+        self.record_at(remote, domain, chrono::Utc::now().timestamp())
+    }
+
+    pub fn record_at(&self, remote: &str, domain: Option<&str>, timestamp: i64) -> CoreResult<()> {
+        const COMMAND: &str = r"INSERT INTO connection_history(remote, domain, connect_count, last_connected) VALUES(?, ?, 1, ?) ON CONFLICT(remote) DO UPDATE SET domain = excluded.domain, connect_count = connect_count + 1, last_connected = excluded.last_connected";
+
+        const PRUNE_COMMAND: &str = r"DELETE FROM connection_history WHERE pinned = 0 AND id NOT IN (SELECT id FROM connection_history WHERE pinned = 0 ORDER BY last_connected DESC, id DESC LIMIT ?)";
+
+        let conn = self.pool.get()?;
+        let _ = conn.execute(COMMAND, params![remote, domain, timestamp])?;
+        let _ = conn.execute(PRUNE_COMMAND, params![CONNECTION_HISTORY_LIMIT])?;
+
+        Ok(())
+    }
+
+    /// Pinned devices first, then the latest connected.
+    pub fn query(&self) -> CoreResult<Vec<ConnectionHistoryEntry>> {
+        const COMMAND: &str = r"SELECT remote, domain, nickname, pinned, connect_count, last_connected FROM connection_history ORDER BY pinned DESC, last_connected DESC, id DESC";
+
+        let conn = self.pool.get()?;
+
+        let mut stmt = conn.prepare(COMMAND)?;
+        let rows = stmt.query_and_then([], parse_entry)?;
+
+        let mut entries = Vec::new();
+        for row in rows {
+            entries.push(row?);
+        }
+
+        Ok(entries)
+    }
+
+    /// Nicknames by remote device id or ip.
+    pub fn nicknames(&self) -> CoreResult<HashMap<String, String>> {
+        Ok(self
+            .query()?
+            .into_iter()
+            .filter_map(|entry| Some((entry.remote, entry.nickname?)))
+            .collect())
+    }
+
+    /// Blank nicknames remove the nickname.
+    pub fn set_nickname(&self, remote: &str, nickname: Option<&str>) -> CoreResult<()> {
+        const COMMAND: &str = r"UPDATE connection_history SET nickname = ? WHERE remote = ?";
+
+        let nickname = nickname
+            .map(str::trim)
+            .filter(|nickname| !nickname.is_empty());
+        if let Some(nickname) = nickname {
+            if nickname.chars().count() > NICKNAME_MAX_CHARS {
+                return Err(core_error!(
+                    "nickname is longer than {} characters",
+                    NICKNAME_MAX_CHARS
+                ));
+            }
+        }
+
+        self.update(COMMAND, params![nickname, remote])
+    }
+
+    pub fn set_pinned(&self, remote: &str, pinned: bool) -> CoreResult<()> {
+        const COMMAND: &str = r"UPDATE connection_history SET pinned = ? WHERE remote = ?";
+
+        self.update(COMMAND, params![pinned, remote])
+    }
+
+    pub fn delete(&self, remote: &str) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM connection_history WHERE remote = ?";
+
+        let _ = self.pool.get()?.execute(COMMAND, params![remote])?;
+
+        Ok(())
+    }
+
+    pub fn delete_domain_related(&self, domain: &str) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM connection_history WHERE domain = ?";
+
+        let _ = self.pool.get()?.execute(COMMAND, params![domain])?;
+
+        Ok(())
+    }
+
+    fn update(&self, command: &str, params: impl rusqlite::Params) -> CoreResult<()> {
+        let updated = self.pool.get()?.execute(command, params)?;
+        if updated == 0 {
+            return Err(core_error!("device not in connection history"));
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_entry(row: &Row) -> CoreResult<ConnectionHistoryEntry> {
+    Ok(ConnectionHistoryEntry {
+        remote: row.get(0)?,
+        domain: row.get(1)?,
+        nickname: row.get(2)?,
+        pinned: row.get(3)?,
+        connect_count: row.get(4)?,
+        last_connected: row.get(5)?,
+    })
+}
@@ -4,7 +4,7 @@ use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::{params, OptionalExtension, Row};
 use serde::Serialize;
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Clone, Serialize)]
 pub struct Domain {
     pub id: i64,
     pub name: String,
@@ -18,6 +18,23 @@ pub struct Domain {
     pub remarks: String,
 }
 
+// the password opens visits to this device, keep it out of logs
+impl std::fmt::Debug for Domain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Domain")
+            .field("id", &self.id)
+            .field("name", &self.name)
+            .field("addr", &self.addr)
+            .field("signaling_port", &self.signaling_port)
+            .field("subscribe_port", &self.subscribe_port)
+            .field("is_primary", &self.is_primary)
+            .field("device_id", &self.device_id)
+            .field("finger_print", &self.finger_print)
+            .field("remarks", &self.remarks)
+            .finish_non_exhaustive()
+    }
+}
+
 pub struct DomainRepository {
     pool: Pool<SqliteConnectionManager>,
 }
@@ -1,4 +1,24 @@
-use crate::{core_error, error::CoreResult};
+use crate::{
+    api::{
+        endpoint::{
+            bandwidth::BandwidthAggressiveness, call_timeout::CallTimeouts,
+            connect_retry::ConnectRetryConfig, heartbeat::HeartbeatConfig, idle::IdleTimeoutConfig,
+            path_preference::PathPreference, rekey::RekeyPolicy, socket_buffer::SocketBufferConfig,
+        },
+        signaling::{
+            access_code::AccessMode, circuit_breaker::CircuitBreakerConfig,
+            identity::MutualAuthConfig, transport::SignalingTransport,
+        },
+    },
+    component::{
+        desktop::virtual_display::VirtualDisplayCommand,
+        fs::{browse::FileBrowsePolicy, cache::CacheConfig},
+        video_decoder::thumbnail::ThumbnailConfig,
+        video_encoder::{media_config::MediaConfig, preprocess::PrivacyRegion},
+    },
+    core_error,
+    error::CoreResult,
+};
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use rusqlite::OptionalExtension;
@@ -82,7 +102,449 @@ impl KVRepository {
         }
     }
 
-    fn set(&self, key: &str, value: &str) -> CoreResult<()> {
+    pub fn set_media_config(&self, value: &MediaConfig) -> CoreResult<()> {
+        let value = serde_json::to_string(value)?;
+        self.set("media_config", &value)
+    }
+
+    pub fn get_media_config(&self) -> CoreResult<Option<MediaConfig>> {
+        match self.get("media_config")? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_encoder_graphics_card(&self, value: Option<&str>) -> CoreResult<()> {
+        self.set("encoder_graphics_card", value.unwrap_or_default())
+    }
+
+    pub fn get_encoder_graphics_card(&self) -> CoreResult<Option<String>> {
+        Ok(self
+            .get("encoder_graphics_card")?
+            .filter(|value| !value.is_empty()))
+    }
+
+    pub fn set_max_incoming_sessions(&self, value: usize) -> CoreResult<()> {
+        self.set("max_incoming_sessions", &value.to_string())
+    }
+
+    pub fn get_max_incoming_sessions(&self) -> CoreResult<Option<usize>> {
+        match self.get("max_incoming_sessions")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_max_frame_length(&self, value: usize) -> CoreResult<()> {
+        self.set("max_frame_length", &value.to_string())
+    }
+
+    pub fn get_max_frame_length(&self) -> CoreResult<Option<usize>> {
+        match self.get("max_frame_length")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_privacy_regions(&self, value: &[PrivacyRegion]) -> CoreResult<()> {
+        let value = serde_json::to_string(value)?;
+        self.set("privacy_regions", &value)
+    }
+
+    pub fn get_privacy_regions(&self) -> CoreResult<Vec<PrivacyRegion>> {
+        match self.get("privacy_regions")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(Vec::new()),
+        }
+    }
+
+    pub fn set_idle_timeout_config(&self, value: &IdleTimeoutConfig) -> CoreResult<()> {
+        let value = serde_json::to_string(value)?;
+        self.set("idle_timeout_config", &value)
+    }
+
+    pub fn get_idle_timeout_config(&self) -> CoreResult<IdleTimeoutConfig> {
+        match self.get("idle_timeout_config")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(IdleTimeoutConfig::default()),
+        }
+    }
+
+    pub fn set_signaling_transport(&self, value: SignalingTransport) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("signaling_transport", &value)
+    }
+
+    pub fn get_signaling_transport(&self) -> CoreResult<SignalingTransport> {
+        match self.get("signaling_transport")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(SignalingTransport::default()),
+        }
+    }
+
+    pub fn set_signaling_circuit_breaker(&self, value: CircuitBreakerConfig) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("signaling_circuit_breaker", &value)
+    }
+
+    pub fn get_signaling_circuit_breaker(&self) -> CoreResult<CircuitBreakerConfig> {
+        match self.get("signaling_circuit_breaker")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(CircuitBreakerConfig::default()),
+        }
+    }
+
+    pub fn set_input_coalesce_window_ms(&self, value: u64) -> CoreResult<()> {
+        self.set("input_coalesce_window_ms", &value.to_string())
+    }
+
+    pub fn get_input_coalesce_window_ms(&self) -> CoreResult<Option<u64>> {
+        match self.get("input_coalesce_window_ms")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_decode_fps_cap(&self, value: u32) -> CoreResult<()> {
+        self.set("decode_fps_cap", &value.to_string())
+    }
+
+    pub fn get_decode_fps_cap(&self) -> CoreResult<Option<u32>> {
+        match self.get("decode_fps_cap")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_thumbnail_config(&self, value: &ThumbnailConfig) -> CoreResult<()> {
+        let value = serde_json::to_string(value)?;
+        self.set("thumbnail_config", &value)
+    }
+
+    pub fn get_thumbnail_config(&self) -> CoreResult<ThumbnailConfig> {
+        match self.get("thumbnail_config")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(ThumbnailConfig::default()),
+        }
+    }
+
+    pub fn set_cache_config(&self, value: &CacheConfig) -> CoreResult<()> {
+        let value = serde_json::to_string(value)?;
+        self.set("cache_config", &value)
+    }
+
+    pub fn get_cache_config(&self) -> CoreResult<CacheConfig> {
+        match self.get("cache_config")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(CacheConfig::default()),
+        }
+    }
+
+    pub fn set_file_chunk_size(&self, value: usize) -> CoreResult<()> {
+        self.set("file_chunk_size", &value.to_string())
+    }
+
+    pub fn get_file_chunk_size(&self) -> CoreResult<Option<usize>> {
+        match self.get("file_chunk_size")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_session_rejoin_window_secs(&self, value: u64) -> CoreResult<()> {
+        self.set("session_rejoin_window_secs", &value.to_string())
+    }
+
+    pub fn get_session_rejoin_window_secs(&self) -> CoreResult<Option<u64>> {
+        match self.get("session_rejoin_window_secs")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_error_event_window_secs(&self, value: u64) -> CoreResult<()> {
+        self.set("error_event_window_secs", &value.to_string())
+    }
+
+    pub fn get_error_event_window_secs(&self) -> CoreResult<Option<u64>> {
+        match self.get("error_event_window_secs")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_reconnect_max_duration_secs(&self, value: u64) -> CoreResult<()> {
+        self.set("reconnect_max_duration_secs", &value.to_string())
+    }
+
+    pub fn get_reconnect_max_duration_secs(&self) -> CoreResult<Option<u64>> {
+        match self.get("reconnect_max_duration_secs")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_chat_history_enabled(&self, value: bool) -> CoreResult<()> {
+        self.set("chat_history_enabled", &value.to_string())
+    }
+
+    pub fn get_chat_history_enabled(&self) -> CoreResult<Option<bool>> {
+        match self.get("chat_history_enabled")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// Zero sizes the budget from the resolution of the session.
+    pub fn set_media_budget_mb(&self, value: u64) -> CoreResult<()> {
+        self.set("media_budget_mb", &value.to_string())
+    }
+
+    pub fn get_media_budget_mb(&self) -> CoreResult<Option<u64>> {
+        match self.get("media_budget_mb")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_media_thread_priority(&self, value: bool) -> CoreResult<()> {
+        self.set("media_thread_priority", &value.to_string())
+    }
+
+    pub fn get_media_thread_priority(&self) -> CoreResult<Option<bool>> {
+        match self.get("media_thread_priority")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_virtual_display_command(
+        &self,
+        value: Option<&VirtualDisplayCommand>,
+    ) -> CoreResult<()> {
+        match value {
+            Some(value) => {
+                let value = serde_json::to_string(value)?;
+                self.set("virtual_display_command", &value)
+            }
+            None => self.remove("virtual_display_command"),
+        }
+    }
+
+    pub fn get_virtual_display_command(&self) -> CoreResult<Option<VirtualDisplayCommand>> {
+        match self.get("virtual_display_command")? {
+            Some(value) => Ok(Some(serde_json::from_str(&value)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_bandwidth_aggressiveness(&self, value: BandwidthAggressiveness) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("bandwidth_aggressiveness", &value)
+    }
+
+    pub fn get_bandwidth_aggressiveness(&self) -> CoreResult<BandwidthAggressiveness> {
+        match self.get("bandwidth_aggressiveness")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(BandwidthAggressiveness::default()),
+        }
+    }
+
+    pub fn set_connect_retry_config(&self, value: ConnectRetryConfig) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("connect_retry_config", &value)
+    }
+
+    pub fn get_connect_retry_config(&self) -> CoreResult<ConnectRetryConfig> {
+        match self.get("connect_retry_config")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(ConnectRetryConfig::default()),
+        }
+    }
+
+    pub fn set_heartbeat_config(&self, value: HeartbeatConfig) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("heartbeat_config", &value)
+    }
+
+    pub fn get_heartbeat_config(&self) -> CoreResult<HeartbeatConfig> {
+        match self.get("heartbeat_config")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(HeartbeatConfig::default()),
+        }
+    }
+
+    pub fn set_call_timeouts(&self, value: CallTimeouts) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("call_timeouts", &value)
+    }
+
+    pub fn get_call_timeouts(&self) -> CoreResult<CallTimeouts> {
+        match self.get("call_timeouts")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(CallTimeouts::default()),
+        }
+    }
+
+    pub fn set_rekey_policy(&self, value: RekeyPolicy) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("rekey_policy", &value)
+    }
+
+    pub fn get_rekey_policy(&self) -> CoreResult<RekeyPolicy> {
+        match self.get("rekey_policy")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(RekeyPolicy::default()),
+        }
+    }
+
+    pub fn set_path_preference(&self, value: PathPreference) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("path_preference", &value)
+    }
+
+    pub fn get_path_preference(&self) -> CoreResult<PathPreference> {
+        match self.get("path_preference")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(PathPreference::default()),
+        }
+    }
+
+    pub fn set_socket_buffer_config(&self, value: SocketBufferConfig) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("socket_buffer_config", &value)
+    }
+
+    pub fn get_socket_buffer_config(&self) -> CoreResult<SocketBufferConfig> {
+        match self.get("socket_buffer_config")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(SocketBufferConfig::default()),
+        }
+    }
+
+    pub fn set_file_browse_policy(&self, value: &FileBrowsePolicy) -> CoreResult<()> {
+        let value = serde_json::to_string(value)?;
+        self.set("file_browse_policy", &value)
+    }
+
+    pub fn get_file_browse_policy(&self) -> CoreResult<FileBrowsePolicy> {
+        match self.get("file_browse_policy")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(FileBrowsePolicy::default()),
+        }
+    }
+
+    pub fn set_access_mode(&self, value: AccessMode) -> CoreResult<()> {
+        let value = serde_json::to_string(&value)?;
+        self.set("access_mode", &value)
+    }
+
+    pub fn get_access_mode(&self) -> CoreResult<AccessMode> {
+        match self.get("access_mode")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(AccessMode::default()),
+        }
+    }
+
+    /// `None` goes back to the host name.
+    pub fn set_device_name(&self, value: Option<&str>) -> CoreResult<()> {
+        match value {
+            Some(value) => self.set("device_name", value),
+            None => self.remove("device_name"),
+        }
+    }
+
+    pub fn get_device_name(&self) -> CoreResult<Option<String>> {
+        self.get("device_name")
+    }
+
+    pub fn set_device_tag(&self, value: Option<&str>) -> CoreResult<()> {
+        match value {
+            Some(value) => self.set("device_tag", value),
+            None => self.remove("device_tag"),
+        }
+    }
+
+    pub fn get_device_tag(&self) -> CoreResult<Option<String>> {
+        self.get("device_tag")
+    }
+
+    /// The PKCS#8 of the device identity key pair.
+    pub fn set_device_identity_key(&self, value: &[u8]) -> CoreResult<()> {
+        self.set("device_identity_key", &base64::encode(value))
+    }
+
+    pub fn get_device_identity_key(&self) -> CoreResult<Option<Vec<u8>>> {
+        match self.get("device_identity_key")? {
+            Some(value) => match base64::decode(value) {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    /// While enabled only the devices on the allow list may visit.
+    pub fn set_allow_list_enabled(&self, value: bool) -> CoreResult<()> {
+        self.set("allow_list_enabled", &value.to_string())
+    }
+
+    pub fn get_allow_list_enabled(&self) -> CoreResult<Option<bool>> {
+        match self.get("allow_list_enabled")? {
+            Some(value) => match value.parse() {
+                Ok(value) => Ok(Some(value)),
+                Err(err) => Err(core_error!("{}", err)),
+            },
+            None => Ok(None),
+        }
+    }
+
+    pub fn set_mutual_auth_config(&self, value: &MutualAuthConfig) -> CoreResult<()> {
+        let value = serde_json::to_string(value)?;
+        self.set("mutual_auth_config", &value)
+    }
+
+    pub fn get_mutual_auth_config(&self) -> CoreResult<MutualAuthConfig> {
+        match self.get("mutual_auth_config")? {
+            Some(value) => Ok(serde_json::from_str(&value)?),
+            None => Ok(MutualAuthConfig::default()),
+        }
+    }
+
+    pub(crate) fn set(&self, key: &str, value: &str) -> CoreResult<()> {
         const COMMAND: &str =
             r"INSERT INTO kv(key, value) VALUES(?, ?) ON CONFLICT DO UPDATE SET value = ?";
 
@@ -91,7 +553,7 @@ impl KVRepository {
         Ok(())
     }
 
-    fn get(&self, key: &str) -> CoreResult<Option<String>> {
+    pub(crate) fn get(&self, key: &str) -> CoreResult<Option<String>> {
         const COMMAND: &str = r"SELECT value FROM kv WHERE key = ? LIMIT 1";
 
         let value = self
@@ -102,4 +564,12 @@ impl KVRepository {
 
         Ok(value)
     }
+
+    pub(crate) fn remove(&self, key: &str) -> CoreResult<()> {
+        const COMMAND: &str = r"DELETE FROM kv WHERE key = ?";
+
+        let _ = self.pool.get()?.execute(COMMAND, [key])?;
+
+        Ok(())
+    }
 }
@@ -1,3 +1,6 @@
+pub mod allow_list;
+pub mod chat;
+pub mod connection_history;
 pub mod domain;
 pub mod history;
 pub mod kv;
@@ -0,0 +1,177 @@
+use super::LocalStorage;
+use crate::{core_error, error::CoreResult};
+use rusqlite::{Connection, OpenFlags};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+
+const TABLES: [&str; 6] = [
+    "domains",
+    "kv",
+    "history",
+    "chat",
+    "connection_history",
+    "allow_list",
+];
+
+#[derive(Debug, Serialize)]
+pub struct IntegrityReport {
+    pub ok: bool,
+    /// Rows returned by `PRAGMA integrity_check`, a single "ok" when healthy.
+    pub messages: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TableRepairReport {
+    pub table: String,
+    pub recovered: u64,
+    pub lost: u64,
+    /// Set when the table couldn't be read at all, `lost` is unknown then.
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RepairReport {
+    pub backup_path: PathBuf,
+    pub tables: Vec<TableRepairReport>,
+}
+
+pub fn check_storage_integrity<P>(db_path: P) -> CoreResult<IntegrityReport>
+where
+    P: AsRef<Path>,
+{
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+    let messages = stmt
+        .query_map([], |row| row.get::<_, String>(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(IntegrityReport {
+        ok: messages.len() == 1 && messages[0] == "ok",
+        messages,
+    })
+}
+
+/// Copies every readable row of the database into a fresh one and swaps it in.
+/// The original file is kept next to it as a backup first.
+///
+/// Nothing may hold a connection to the database while repairing.
+pub fn repair_storage<P>(db_path: P) -> CoreResult<RepairReport>
+where
+    P: AsRef<Path>,
+{
+    let db_path = db_path.as_ref();
+    let file_name = db_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or(core_error!("invalid storage path"))?;
+
+    let timestamp = chrono::Local::now().format("%Y%m%d%H%M%S");
+    let backup_path = db_path.with_file_name(format!("{file_name}.{timestamp}.bak"));
+    let repaired_path = db_path.with_file_name(format!("{file_name}.repair"));
+
+    std::fs::copy(db_path, &backup_path)?;
+
+    if repaired_path.exists() {
+        std::fs::remove_file(&repaired_path)?;
+    }
+
+    // create the tables with the current schema
+    drop(LocalStorage::new(&repaired_path)?);
+
+    let conn = Connection::open(&repaired_path)?;
+    conn.execute(
+        "ATTACH DATABASE ? AS old",
+        [backup_path.to_string_lossy().as_ref()],
+    )?;
+
+    let tables = TABLES
+        .iter()
+        .map(|table| recover_table(&conn, table))
+        .collect();
+
+    conn.execute("DETACH DATABASE old", [])?;
+    drop(conn);
+
+    std::fs::rename(&repaired_path, db_path)?;
+
+    tracing::info!(?backup_path, ?tables, "storage repaired");
+
+    Ok(RepairReport {
+        backup_path,
+        tables,
+    })
+}
+
+fn recover_table(conn: &Connection, table: &str) -> TableRepairReport {
+    let mut report = TableRepairReport {
+        table: table.to_string(),
+        recovered: 0,
+        lost: 0,
+        error: None,
+    };
+
+    // the backup may be of an older version, only the columns both schemas
+    // have are copied and the others get their defaults
+    let columns = match (
+        table_columns(conn, "main", table),
+        table_columns(conn, "old", table),
+    ) {
+        (Ok(columns), Ok(old_columns)) => columns
+            .into_iter()
+            .filter(|column| old_columns.contains(column))
+            .map(|column| format!("\"{column}\""))
+            .collect::<Vec<_>>()
+            .join(", "),
+        (Err(err), _) | (_, Err(err)) => {
+            tracing::error!(?err, ?table, "read table columns failed");
+            report.error = Some(err.to_string());
+            return report;
+        }
+    };
+
+    // a table added after the backup was made has nothing to recover
+    if columns.is_empty() {
+        return report;
+    }
+
+    // rows are copied one by one so a damaged page only loses the rows on it
+    let row_ids = match conn
+        .prepare(&format!("SELECT rowid FROM old.{table}"))
+        .and_then(|mut stmt| {
+            let row_ids = stmt
+                .query_map([], |row| row.get::<_, i64>(0))?
+                .collect::<Result<Vec<_>, _>>();
+            row_ids
+        }) {
+        Ok(row_ids) => row_ids,
+        Err(err) => {
+            tracing::error!(?err, ?table, "read table row ids failed");
+            report.error = Some(err.to_string());
+            return report;
+        }
+    };
+
+    let command = format!(
+        "INSERT OR IGNORE INTO main.{table} ({columns}) SELECT {columns} FROM old.{table} WHERE rowid = ?"
+    );
+
+    for row_id in row_ids {
+        match conn.execute(&command, [row_id]) {
+            Ok(_) => report.recovered += 1,
+            Err(err) => {
+                tracing::warn!(?err, ?table, ?row_id, "recover row failed");
+                report.lost += 1;
+            }
+        }
+    }
+
+    report
+}
+
+fn table_columns(conn: &Connection, schema: &str, table: &str) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA {schema}.table_info({table})"))?;
+    let columns = stmt
+        .query_map([], |row| row.get::<_, String>("name"))?
+        .collect::<Result<Vec<_>, _>>();
+    columns
+}
@@ -1,15 +1,36 @@
+pub mod bundle;
+pub mod device_name;
+pub mod device_tag;
 pub mod entity;
+pub mod integrity;
 
-use self::entity::{domain::DomainRepository, history::HistoryRepository, kv::KVRepository};
+use self::entity::{
+    allow_list::AllowListRepository, chat::ChatRepository,
+    connection_history::ConnectionHistoryRepository, domain::DomainRepository,
+    history::HistoryRepository, kv::KVRepository,
+};
 use crate::error::CoreResult;
+use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
 use std::{path::Path, sync::Arc};
 
+pub trait Storage: Clone + Send + Sync + 'static {
+    fn domain(&self) -> &DomainRepository;
+    fn kv(&self) -> &KVRepository;
+    fn history(&self) -> &HistoryRepository;
+    fn chat(&self) -> &ChatRepository;
+    fn connection_history(&self) -> &ConnectionHistoryRepository;
+    fn allow_list(&self) -> &AllowListRepository;
+}
+
 #[derive(Clone)]
 pub struct LocalStorage {
     domain: Arc<DomainRepository>,
     kv: Arc<KVRepository>,
     history: Arc<HistoryRepository>,
+    chat: Arc<ChatRepository>,
+    connection_history: Arc<ConnectionHistoryRepository>,
+    allow_list: Arc<AllowListRepository>,
 }
 
 impl LocalStorage {
@@ -18,33 +39,71 @@ impl LocalStorage {
         P: AsRef<Path>,
     {
         let manager = SqliteConnectionManager::file(db_path);
-        let pool = r2d2::Pool::new(manager)?;
+        let pool = Pool::new(manager)?;
+        LocalStorage::from_pool(pool)
+    }
+
+    /// Creates a storage backed by an in-memory sqlite database, nothing is written to disk.
+    ///
+    /// Every sqlite in-memory connection is a separate database, so the pool only holds one.
+    pub fn memory() -> CoreResult<LocalStorage> {
+        let manager = SqliteConnectionManager::memory();
+        let pool = Pool::builder().max_size(1).build(manager)?;
+        LocalStorage::from_pool(pool)
+    }
 
+    fn from_pool(pool: Pool<SqliteConnectionManager>) -> CoreResult<LocalStorage> {
         let domain_repository = DomainRepository::new(pool.clone());
         domain_repository.ensure_table()?;
 
         let kv_repository = KVRepository::new(pool.clone());
         kv_repository.ensure_table()?;
 
-        let history_repository = HistoryRepository::new(pool);
+        let history_repository = HistoryRepository::new(pool.clone());
         history_repository.ensure_table()?;
 
+        let chat_repository = ChatRepository::new(pool.clone());
+        chat_repository.ensure_table()?;
+
+        let connection_history_repository = ConnectionHistoryRepository::new(pool.clone());
+        connection_history_repository.ensure_table()?;
+
+        let allow_list_repository = AllowListRepository::new(pool);
+        allow_list_repository.ensure_table()?;
+
         Ok(Self {
             domain: Arc::new(domain_repository),
             kv: Arc::new(kv_repository),
             history: Arc::new(history_repository),
+            chat: Arc::new(chat_repository),
+            connection_history: Arc::new(connection_history_repository),
+            allow_list: Arc::new(allow_list_repository),
         })
     }
+}
 
-    pub fn domain(&self) -> &DomainRepository {
+impl Storage for LocalStorage {
+    fn domain(&self) -> &DomainRepository {
         &self.domain
     }
 
-    pub fn kv(&self) -> &KVRepository {
+    fn kv(&self) -> &KVRepository {
         &self.kv
     }
 
-    pub fn history(&self) -> &HistoryRepository {
+    fn history(&self) -> &HistoryRepository {
         &self.history
     }
+
+    fn chat(&self) -> &ChatRepository {
+        &self.chat
+    }
+
+    fn connection_history(&self) -> &ConnectionHistoryRepository {
+        &self.connection_history
+    }
+
+    fn allow_list(&self) -> &AllowListRepository {
+        &self.allow_list
+    }
 }
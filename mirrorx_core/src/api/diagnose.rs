@@ -0,0 +1,279 @@
+use crate::{
+    api::endpoint::connect_attempt::cancellable,
+    component::lan::{discover::LAN_DISCOVER_PORT, server::LAN_SERVER_PORT},
+    error::{CoreError, CoreResult},
+};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+use tokio_util::sync::CancellationToken;
+
+/// How long a whole diagnosis may take, checks left by then are skipped.
+pub const DIAGNOSIS_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How long a single check may take.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+static NEXT_DIAGNOSIS_ID: AtomicU64 = AtomicU64::new(0);
+
+static RUNNING_DIAGNOSIS: Lazy<Mutex<Option<(u64, CancellationToken)>>> =
+    Lazy::new(|| Mutex::new(None));
+
+/// The steps of a connectivity diagnosis, in the order they run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticCheck {
+    SignalingReachable,
+    DeviceRegistered,
+    PlatformPermissions,
+    LanServerListening,
+    LanDiscovery,
+    TargetReachable,
+}
+
+impl DiagnosticCheck {
+    /// What the user can do about the check failing.
+    pub fn hint(&self, err: &CoreError) -> String {
+        match (self, err) {
+            (_, CoreError::SignalingUnavailable) => String::from(
+                "The signaling server failed repeatedly, calls to it are paused for a while. Try again later.",
+            ),
+            (DiagnosticCheck::SignalingReachable, _) => String::from(
+                "Check the internet connection and the address of the domain. A proxy or firewall may block the signaling server, the WebSocket transport gets through most proxies.",
+            ),
+            (DiagnosticCheck::DeviceRegistered, _) => String::from(
+                "Reconnect to the domain. If that fails too, remove the domain and add it again to register this device anew.",
+            ),
+            (DiagnosticCheck::PlatformPermissions, _) => String::from(
+                "Grant MirrorX screen recording and accessibility in the privacy settings of the system, then restart the app.",
+            ),
+            (DiagnosticCheck::LanServerListening, _) => String::from(
+                "Restart LAN discovery. Another app may hold the LAN ports, or this device has no LAN address.",
+            ),
+            (DiagnosticCheck::LanDiscovery, _) => format!(
+                "Make this device discoverable, put both devices in the same network and let UDP port {LAN_DISCOVER_PORT} through the firewall."
+            ),
+            (DiagnosticCheck::TargetReachable, _) => format!(
+                "Make sure the target runs MirrorX with LAN discovery on, and its firewall lets TCP port {LAN_SERVER_PORT} through."
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticStatus {
+    Passed,
+    Failed,
+    /// Not run, because a check before it failed, or the diagnosis was
+    /// cancelled or ran out of time.
+    Skipped,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiagnosticStep {
+    pub check: DiagnosticCheck,
+    pub status: DiagnosticStatus,
+    /// What the check found, or why it failed or was skipped.
+    pub detail: String,
+    /// What to do about a failed check.
+    pub hint: Option<String>,
+    pub elapsed_ms: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiagnosticReport {
+    pub target: String,
+    /// No check failed, and the diagnosis ran to the end.
+    pub passed: bool,
+    pub cancelled: bool,
+    pub timed_out: bool,
+    pub steps: Vec<DiagnosticStep>,
+}
+
+/// Runs the checks of a diagnosis one after another, each bounded by
+/// [`CHECK_TIMEOUT`] and the time left of the diagnosis.
+pub struct Diagnosis {
+    target: String,
+    token: CancellationToken,
+    deadline: Instant,
+    timed_out: bool,
+    steps: Vec<DiagnosticStep>,
+}
+
+impl Diagnosis {
+    pub fn new(target: &str, token: CancellationToken, timeout: Duration) -> Self {
+        Self {
+            target: target.to_string(),
+            token,
+            deadline: Instant::now() + timeout,
+            timed_out: false,
+            steps: Vec::new(),
+        }
+    }
+
+    /// Runs a check unless a check it depends on didn't pass. A passing
+    /// probe returns what it found. Returns whether the check passed.
+    pub async fn run<F>(
+        &mut self,
+        check: DiagnosticCheck,
+        depends_on: &[DiagnosticCheck],
+        probe: F,
+    ) -> bool
+    where
+        F: Future<Output = CoreResult<String>>,
+    {
+        if let Some(reason) = self.skip_reason(depends_on) {
+            self.skip(check, reason);
+            return false;
+        }
+
+        let started_at = Instant::now();
+        let timeout = CHECK_TIMEOUT.min(self.deadline.saturating_duration_since(started_at));
+
+        let result = match tokio::time::timeout(timeout, cancellable(&self.token, probe)).await {
+            Ok(result) => result,
+            Err(_) => {
+                self.timed_out = Instant::now() >= self.deadline;
+                Err(CoreError::Timeout)
+            }
+        };
+
+        let (status, detail, hint) = match result {
+            Ok(detail) => (DiagnosticStatus::Passed, detail, None),
+            Err(CoreError::Cancelled) if self.token.is_cancelled() => (
+                DiagnosticStatus::Skipped,
+                String::from("the diagnosis was cancelled"),
+                None,
+            ),
+            Err(err) => (
+                DiagnosticStatus::Failed,
+                err.to_string(),
+                Some(check.hint(&err)),
+            ),
+        };
+
+        tracing::info!(?check, ?status, ?detail, "diagnostic check finished");
+
+        self.steps.push(DiagnosticStep {
+            check,
+            status,
+            detail,
+            hint,
+            elapsed_ms: started_at.elapsed().as_millis() as u64,
+        });
+
+        status == DiagnosticStatus::Passed
+    }
+
+    /// Records a check that can't run for the target.
+    pub fn skip(&mut self, check: DiagnosticCheck, reason: impl Into<String>) {
+        self.steps.push(DiagnosticStep {
+            check,
+            status: DiagnosticStatus::Skipped,
+            detail: reason.into(),
+            hint: None,
+            elapsed_ms: 0,
+        });
+    }
+
+    pub fn finish(self) -> DiagnosticReport {
+        let cancelled = self.token.is_cancelled();
+
+        DiagnosticReport {
+            passed: !cancelled
+                && !self.timed_out
+                && self
+                    .steps
+                    .iter()
+                    .all(|step| step.status != DiagnosticStatus::Failed),
+            cancelled,
+            timed_out: self.timed_out,
+            target: self.target,
+            steps: self.steps,
+        }
+    }
+
+    fn skip_reason(&mut self, depends_on: &[DiagnosticCheck]) -> Option<String> {
+        if self.token.is_cancelled() {
+            return Some(String::from("the diagnosis was cancelled"));
+        }
+
+        if Instant::now() >= self.deadline {
+            self.timed_out = true;
+            return Some(String::from("the diagnosis ran out of time"));
+        }
+
+        depends_on
+            .iter()
+            .find(|check| {
+                !self
+                    .steps
+                    .iter()
+                    .any(|step| step.check == **check && step.status == DiagnosticStatus::Passed)
+            })
+            .map(|check| format!("needs the {:?} check to pass", check))
+    }
+}
+
+/// The diagnosis [`cancel_diagnosis`] cancels, until dropped.
+pub struct RunningDiagnosis {
+    id: u64,
+    token: CancellationToken,
+}
+
+impl RunningDiagnosis {
+    pub fn token(&self) -> CancellationToken {
+        self.token.clone()
+    }
+}
+
+impl Drop for RunningDiagnosis {
+    fn drop(&mut self) {
+        let mut running = RUNNING_DIAGNOSIS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        // a newer diagnosis may have taken the slot
+        if matches!(*running, Some((id, _)) if id == self.id) {
+            running.take();
+        }
+    }
+}
+
+/// Starts a diagnosis, the one still running is cancelled.
+pub fn begin_diagnosis() -> RunningDiagnosis {
+    let id = NEXT_DIAGNOSIS_ID.fetch_add(1, Ordering::Relaxed);
+    let token = CancellationToken::new();
+
+    let mut running = RUNNING_DIAGNOSIS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if let Some((_, previous)) = running.replace((id, token.clone())) {
+        previous.cancel();
+    }
+
+    RunningDiagnosis { id, token }
+}
+
+/// Cancels the running diagnosis, false when there's none.
+pub fn cancel_diagnosis() -> bool {
+    match RUNNING_DIAGNOSIS
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+    {
+        Some((_, token)) => {
+            token.cancel();
+            true
+        }
+        None => false,
+    }
+}
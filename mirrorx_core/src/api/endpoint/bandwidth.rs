@@ -0,0 +1,377 @@
+use super::{
+    client::EndPointClient,
+    idle::SessionRole,
+    message::{EndPointMessage, EndPointNetworkReport, SessionFeature},
+    socket_buffer::record_bdp,
+};
+use crate::utility::config_cell::ConfigCell;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+/// Lowest estimate, matches the lowest bitrate a media config allows.
+pub const MIN_ESTIMATE_BPS: u64 = 100 * 1000;
+
+pub const MAX_ESTIMATE_BPS: u64 = 200 * 1000 * 1000;
+
+/// How often the send queue is sampled.
+pub const BANDWIDTH_SAMPLE_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How often a watching session tells the host what arrives.
+pub const NETWORK_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A round trip probe goes out every this many samples.
+const RTT_PROBE_EVERY: u32 = 4;
+
+/// Data waiting longer than this in the send queue means the link can't
+/// keep up with the sender.
+const QUEUE_DELAY_LIMIT: Duration = Duration::from_millis(50);
+
+/// The estimate only ramps while the sender uses most of it, an idle sender
+/// proves nothing about the link.
+const RAMP_HEADROOM: f64 = 1.25;
+
+/// Weight of the newest estimate in the smoothed one.
+const SMOOTHING: f64 = 0.25;
+
+/// Shortest pause between two back offs, the queue needs time to drain.
+const MIN_BACKOFF_HOLD: Duration = Duration::from_millis(500);
+
+static BANDWIDTH_AGGRESSIVENESS: ConfigCell<BandwidthAggressiveness> =
+    ConfigCell::new(BandwidthAggressiveness::default);
+
+static PROBE_EPOCH: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// How eagerly the estimate follows the link. Every level ramps up slowly
+/// and backs off at once, they differ in how slow and how far.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BandwidthAggressiveness {
+    /// Grows 4% a second and backs off to 70% of the drain rate, for shared
+    /// or metered links.
+    Conservative,
+    /// Grows 8% a second and backs off to 80% of the drain rate.
+    #[default]
+    Balanced,
+    /// Grows 15% a second and backs off to 85% of the drain rate, for links
+    /// the session has for itself.
+    Aggressive,
+}
+
+impl BandwidthAggressiveness {
+    /// Share the estimate grows by per second without congestion.
+    fn ramp_per_sec(self) -> f64 {
+        match self {
+            BandwidthAggressiveness::Conservative => 0.04,
+            BandwidthAggressiveness::Balanced => 0.08,
+            BandwidthAggressiveness::Aggressive => 0.15,
+        }
+    }
+
+    /// Share of the drain rate kept on congestion.
+    fn backoff(self) -> f64 {
+        match self {
+            BandwidthAggressiveness::Conservative => 0.7,
+            BandwidthAggressiveness::Balanced => 0.8,
+            BandwidthAggressiveness::Aggressive => 0.85,
+        }
+    }
+}
+
+pub fn set_bandwidth_aggressiveness(aggressiveness: BandwidthAggressiveness) {
+    BANDWIDTH_AGGRESSIVENESS.set(aggressiveness);
+}
+
+pub fn bandwidth_aggressiveness() -> BandwidthAggressiveness {
+    BANDWIDTH_AGGRESSIVENESS.get()
+}
+
+/// Payload of a round trip probe, echoed back unchanged by the remote side.
+pub fn rtt_probe_timestamp() -> u64 {
+    PROBE_EPOCH.elapsed().as_micros() as u64
+}
+
+pub fn rtt_probe_elapsed(timestamp: u64) -> Duration {
+    PROBE_EPOCH
+        .elapsed()
+        .saturating_sub(Duration::from_micros(timestamp))
+}
+
+/// Estimates the bandwidth available towards the remote side from how fast
+/// the send queue drains and how the round trip develops. A growing queue or
+/// a round trip well above the lowest one seen means the link is full, the
+/// estimate then drops below the measured drain rate. Otherwise it ramps up
+/// a few percent a second.
+#[derive(Debug)]
+pub struct BandwidthEstimator {
+    aggressiveness: BandwidthAggressiveness,
+    estimate_bps: f64,
+    smoothed_bps: f64,
+    min_rtt: Option<Duration>,
+    smoothed_rtt: Option<Duration>,
+    last_queued_bytes: u64,
+    last_sample_at: Option<Instant>,
+    hold_until: Option<Instant>,
+}
+
+impl BandwidthEstimator {
+    pub fn new(aggressiveness: BandwidthAggressiveness, initial_bps: u64) -> Self {
+        let initial_bps = initial_bps.clamp(MIN_ESTIMATE_BPS, MAX_ESTIMATE_BPS) as f64;
+
+        Self {
+            aggressiveness,
+            estimate_bps: initial_bps,
+            smoothed_bps: initial_bps,
+            min_rtt: None,
+            smoothed_rtt: None,
+            last_queued_bytes: 0,
+            last_sample_at: None,
+            hold_until: None,
+        }
+    }
+
+    pub fn estimated_bps(&self) -> u64 {
+        self.smoothed_bps as u64
+    }
+
+    pub fn smoothed_rtt(&self) -> Option<Duration> {
+        self.smoothed_rtt
+    }
+
+    pub fn on_rtt(&mut self, rtt: Duration) {
+        self.min_rtt = Some(self.min_rtt.map_or(rtt, |min_rtt| min_rtt.min(rtt)));
+        self.smoothed_rtt = Some(
+            self.smoothed_rtt
+                .map_or(rtt, |smoothed_rtt| (smoothed_rtt * 7 + rtt) / 8),
+        );
+    }
+
+    /// Takes a sample of the send queue, `drained_bytes` were written to the
+    /// connection since the last sample and `queued_bytes` still wait. The
+    /// first sample only starts the clock. Returns the smoothed estimate.
+    pub fn on_sample(&mut self, drained_bytes: u64, queued_bytes: u64, now: Instant) -> u64 {
+        let Some(last_sample_at) = self.last_sample_at.replace(now) else {
+            self.last_queued_bytes = queued_bytes;
+            return self.estimated_bps();
+        };
+
+        let elapsed = now.saturating_duration_since(last_sample_at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return self.estimated_bps();
+        }
+
+        let drain_bps = drained_bytes as f64 * 8.0 / elapsed;
+        let queue_delay = queued_bytes as f64 * 8.0 / self.estimate_bps;
+        let queue_growing = queued_bytes > self.last_queued_bytes;
+        self.last_queued_bytes = queued_bytes;
+
+        let queue_congested = queue_delay > QUEUE_DELAY_LIMIT.as_secs_f64();
+        let congested = (queue_congested && queue_growing) || self.rtt_congested();
+        let holding = matches!(self.hold_until, Some(hold_until) if now < hold_until);
+
+        if congested && !holding {
+            // the drain rate is what the link carries while it is full
+            let backoff_bps = drain_bps * self.aggressiveness.backoff();
+            self.estimate_bps = self
+                .estimate_bps
+                .min(backoff_bps)
+                .max(MIN_ESTIMATE_BPS as f64);
+
+            let hold = self.smoothed_rtt.map_or(MIN_BACKOFF_HOLD, |smoothed_rtt| {
+                (smoothed_rtt * 2).max(MIN_BACKOFF_HOLD)
+            });
+            self.hold_until = Some(now + hold);
+
+            tracing::info!(estimate_bps = ?self.estimate_bps, "bandwidth congested, back off");
+        } else if !congested
+            && !holding
+            && !queue_congested
+            && self.estimate_bps < drain_bps * RAMP_HEADROOM
+        {
+            self.estimate_bps = (self.estimate_bps
+                * (1.0 + self.aggressiveness.ramp_per_sec() * elapsed))
+                .min(MAX_ESTIMATE_BPS as f64);
+        }
+
+        self.smoothed_bps += (self.estimate_bps - self.smoothed_bps) * SMOOTHING;
+        self.estimated_bps()
+    }
+
+    /// Whether the round trip rose well above the lowest seen, the extra
+    /// time is spent in queues along the path.
+    fn rtt_congested(&self) -> bool {
+        match (self.min_rtt, self.smoothed_rtt) {
+            (Some(min_rtt), Some(smoothed_rtt)) => {
+                smoothed_rtt > min_rtt * 3 / 2 + Duration::from_millis(10)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Samples the send queue of a session that sends video and publishes the
+/// estimate in its stats, where the video encoder picks it up. Exits with
+/// the session, or when it stops sending video after a swap.
+pub(crate) fn spawn_bandwidth_estimator(client: Arc<EndPointClient>, initial_bps: u64) {
+    tokio::spawn(async move {
+        let mut estimator = BandwidthEstimator::new(bandwidth_aggressiveness(), initial_bps);
+        let mut ticker = tokio::time::interval(BANDWIDTH_SAMPLE_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let media_token = client.media_token();
+        let mut last_sent_bytes = client.raw_stats().sent_bytes();
+        let mut samples = 0u32;
+
+        loop {
+            tokio::select! {
+                _ = media_token.cancelled() => return,
+                _ = ticker.tick() => {}
+            }
+
+            let stats = client.raw_stats();
+
+            if let Some(rtt) = stats.take_rtt() {
+                estimator.on_rtt(rtt);
+            }
+
+            let sent_bytes = stats.sent_bytes();
+            let estimated_bps = estimator.on_sample(
+                sent_bytes.saturating_sub(last_sent_bytes),
+                stats.queued_bytes(),
+                Instant::now(),
+            );
+            last_sent_bytes = sent_bytes;
+            stats.set_estimated_bps(estimated_bps);
+
+            if let Some(rtt) = stats.last_rtt() {
+                record_bdp(client.endpoint_id(), estimated_bps, rtt);
+            }
+
+            samples = samples.wrapping_add(1);
+            if samples.is_multiple_of(RTT_PROBE_EVERY) {
+                // a full queue delays the probe as much as the video
+                let _ = client.try_send(&EndPointMessage::RttProbe(rtt_probe_timestamp()));
+            }
+        }
+    });
+}
+
+/// Probes the round trip of a watching session as often as the sending side
+/// does, so both sides can tell the latency of the path. Exits with the
+/// session, or when it starts sending video after a swap.
+pub(crate) fn spawn_rtt_prober(client: Arc<EndPointClient>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(BANDWIDTH_SAMPLE_INTERVAL * RTT_PROBE_EVERY);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                _ = client.closed() => return,
+                _ = ticker.tick() => {}
+            }
+
+            if client.role() != Some(SessionRole::Viewer) {
+                return;
+            }
+
+            let _ = client.try_send(&EndPointMessage::RttProbe(rtt_probe_timestamp()));
+        }
+    });
+}
+
+/// Turns the counters of a watching session into the reports for the host,
+/// every report covers the time since the sample before.
+#[derive(Debug, Default)]
+pub struct NetworkReporter {
+    last: Option<ReportSample>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ReportSample {
+    at: Instant,
+    received_bytes: u64,
+    video_frames: u64,
+    lost_frames: u64,
+}
+
+impl NetworkReporter {
+    /// Takes the totals received so far, `None` for the first sample which
+    /// only starts the clock.
+    pub fn sample(
+        &mut self,
+        received_bytes: u64,
+        video_frames: u64,
+        lost_frames: u64,
+        now: Instant,
+    ) -> Option<EndPointNetworkReport> {
+        let sample = ReportSample {
+            at: now,
+            received_bytes,
+            video_frames,
+            lost_frames,
+        };
+
+        let last = self.last.replace(sample)?;
+
+        let elapsed = now.saturating_duration_since(last.at).as_secs_f64();
+        if elapsed <= 0.0 {
+            return None;
+        }
+
+        let received_bytes = received_bytes.saturating_sub(last.received_bytes);
+        let video_frames = video_frames.saturating_sub(last.video_frames);
+        let lost_frames = lost_frames.saturating_sub(last.lost_frames);
+
+        let loss_permille = match video_frames + lost_frames {
+            0 => 0,
+            sent_frames => (lost_frames * 1000 / sent_frames) as u16,
+        };
+
+        Some(EndPointNetworkReport {
+            received_bps: (received_bytes as f64 * 8.0 / elapsed) as u64,
+            loss_permille,
+        })
+    }
+}
+
+/// Reports what a watching session receives to a host that adapts its
+/// encoder to it. Exits with the session, or when it starts sending video
+/// after a swap.
+pub(crate) fn spawn_network_reporter(client: Arc<EndPointClient>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(NETWORK_REPORT_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let mut reporter = NetworkReporter::default();
+
+        loop {
+            tokio::select! {
+                _ = client.closed() => return,
+                _ = ticker.tick() => {}
+            }
+
+            if client.role() != Some(SessionRole::Viewer) {
+                return;
+            }
+
+            let stats = client.raw_stats();
+            let Some(report) = reporter.sample(
+                stats.received_bytes(),
+                stats.video_frames(),
+                stats.fec_lost_frames(),
+                Instant::now(),
+            ) else {
+                continue;
+            };
+
+            if !client.supports(SessionFeature::NetworkReport) {
+                continue;
+            }
+
+            let _ = client.try_send(&EndPointMessage::NetworkReport(report));
+        }
+    });
+}
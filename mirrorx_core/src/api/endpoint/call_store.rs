@@ -0,0 +1,108 @@
+use crate::{
+    core_error,
+    error::{CoreError, CoreResult},
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+use std::{
+    sync::atomic::{AtomicU16, Ordering},
+    time::Duration,
+};
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio_util::sync::CancellationToken;
+
+/// Pending calls waiting for their reply, keyed by call id.
+#[derive(Debug, Default)]
+pub struct CallStore {
+    next_call_id: AtomicU16,
+    pending: DashMap<u16, Sender<Vec<u8>>>,
+    close_token: CancellationToken,
+}
+
+/// A registered call. Its entry is removed from the store when it's dropped,
+/// whichever way the call ends.
+pub struct PendingCall<'a> {
+    store: &'a CallStore,
+    call_id: u16,
+    tx: Sender<Vec<u8>>,
+    rx: Receiver<Vec<u8>>,
+}
+
+impl CallStore {
+    pub fn register(&self) -> CoreResult<PendingCall<'_>> {
+        if self.is_closed() {
+            return Err(CoreError::OutgoingMessageChannelDisconnect);
+        }
+
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
+
+        // call ids wrap around, skip the ones still waiting for a reply
+        for _ in 0..=u16::MAX {
+            let call_id = self.next_call_id.fetch_add(1, Ordering::SeqCst);
+
+            if let Entry::Vacant(entry) = self.pending.entry(call_id) {
+                entry.insert(tx.clone());
+
+                return Ok(PendingCall {
+                    store: self,
+                    call_id,
+                    tx,
+                    rx,
+                });
+            }
+        }
+
+        Err(core_error!("too many pending calls"))
+    }
+
+    /// Hands the reply to the waiting call. Replies to calls that already
+    /// ended are dropped.
+    pub fn reply(&self, call_id: u16, reply: Vec<u8>) {
+        match self.pending.remove(&call_id) {
+            Some((_, tx)) => {
+                let _ = tx.try_send(reply);
+            }
+            None => tracing::warn!(?call_id, "drop reply of finished call"),
+        }
+    }
+
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Fails the waiting calls and the ones registered afterwards, no reply
+    /// arrives once the connection is gone.
+    pub fn close(&self) {
+        self.close_token.cancel();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.close_token.is_cancelled()
+    }
+}
+
+impl PendingCall<'_> {
+    pub fn call_id(&self) -> u16 {
+        self.call_id
+    }
+
+    pub async fn recv(&mut self, timeout: Duration) -> CoreResult<Vec<u8>> {
+        tokio::select! {
+            biased;
+            reply = tokio::time::timeout(timeout, self.rx.recv()) => reply
+                .map_err(|_| CoreError::Timeout)?
+                .ok_or(CoreError::Timeout),
+            _ = self.store.close_token.cancelled() => {
+                Err(CoreError::OutgoingMessageChannelDisconnect)
+            }
+        }
+    }
+}
+
+impl Drop for PendingCall<'_> {
+    fn drop(&mut self) {
+        // the id may already belong to a newer call once the reply removed it
+        self.store
+            .pending
+            .remove_if(&self.call_id, |_, tx| tx.same_channel(&self.tx));
+    }
+}
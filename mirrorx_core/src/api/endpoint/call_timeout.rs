@@ -0,0 +1,118 @@
+use crate::{
+    error::{CoreError, CoreResult},
+    utility::config_cell::ConfigCell,
+};
+use serde::{Deserialize, Serialize};
+use std::{fmt::Display, future::Future, time::Duration};
+
+pub const MIN_CALL_TIMEOUT_MS: u64 = 1000;
+
+pub const MAX_CALL_TIMEOUT_MS: u64 = 10 * 60 * 1000;
+
+static CALL_TIMEOUTS: ConfigCell<CallTimeouts> = ConfigCell::new(CallTimeouts::default);
+
+/// The operations of a connection that wait for the remote side to answer.
+/// The heartbeat has its own interval and failure threshold, see
+/// [`HeartbeatConfig`](super::heartbeat::HeartbeatConfig).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CallOperation {
+    /// The handshake and the capability exchange right after connecting.
+    Handshake,
+    /// Negotiating the media of a desktop session, the host may have to
+    /// start capturing first.
+    MediaStart,
+    /// A request of the file manager.
+    Call,
+    /// A rotation of the session keys.
+    Rekey,
+}
+
+impl Display for CallOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operation = match self {
+            CallOperation::Handshake => "handshake",
+            CallOperation::MediaStart => "media start",
+            CallOperation::Call => "call",
+            CallOperation::Rekey => "rekey",
+        };
+
+        f.write_str(operation)
+    }
+}
+
+/// How long each operation of a connection waits for the remote side. A
+/// session across continents needs longer to get going than one in the
+/// same network.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CallTimeouts {
+    pub handshake_ms: u64,
+    pub media_start_ms: u64,
+    pub call_ms: u64,
+    pub rekey_ms: u64,
+}
+
+impl Default for CallTimeouts {
+    fn default() -> Self {
+        Self {
+            handshake_ms: 30 * 1000,
+            media_start_ms: 60 * 1000,
+            call_ms: 60 * 1000,
+            rekey_ms: 30 * 1000,
+        }
+    }
+}
+
+impl CallTimeouts {
+    pub fn sanitize(self) -> Self {
+        let clamp = |timeout_ms: u64| timeout_ms.clamp(MIN_CALL_TIMEOUT_MS, MAX_CALL_TIMEOUT_MS);
+
+        Self {
+            handshake_ms: clamp(self.handshake_ms),
+            media_start_ms: clamp(self.media_start_ms),
+            call_ms: clamp(self.call_ms),
+            rekey_ms: clamp(self.rekey_ms),
+        }
+    }
+
+    pub fn timeout(&self, operation: CallOperation) -> Duration {
+        let timeout_ms = match operation {
+            CallOperation::Handshake => self.handshake_ms,
+            CallOperation::MediaStart => self.media_start_ms,
+            CallOperation::Call => self.call_ms,
+            CallOperation::Rekey => self.rekey_ms,
+        };
+
+        Duration::from_millis(timeout_ms)
+    }
+
+    /// Waits for `future` at most the timeout of `operation`, fails with
+    /// [`CoreError::CallTimeout`] naming it.
+    pub async fn wait<T>(
+        &self,
+        operation: CallOperation,
+        future: impl Future<Output = T>,
+    ) -> CoreResult<T> {
+        wait_for(operation, self.timeout(operation), future).await
+    }
+}
+
+/// Waits for `future` at most `timeout`, for a call site that knows better
+/// than the configured timeout of `operation`.
+pub async fn wait_for<T>(
+    operation: CallOperation,
+    timeout: Duration,
+    future: impl Future<Output = T>,
+) -> CoreResult<T> {
+    tokio::time::timeout(timeout, future)
+        .await
+        .map_err(|_| CoreError::CallTimeout { operation })
+}
+
+pub fn set_call_timeouts(timeouts: CallTimeouts) {
+    CALL_TIMEOUTS.set(timeouts.sanitize());
+}
+
+pub fn call_timeouts() -> CallTimeouts {
+    CALL_TIMEOUTS.get()
+}
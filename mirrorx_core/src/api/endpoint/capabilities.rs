@@ -0,0 +1,243 @@
+use super::{
+    message::{
+        EndPointAudioParams, EndPointCapabilities, EndPointMediaLimits, SessionFeature, VideoCodec,
+    },
+    packet::max_frame_length,
+};
+use crate::{
+    api::config::device_name::device_name,
+    component::{
+        audio::{codec::OPUS_SAMPLE_RATES, player::default_output_config},
+        desktop::monitor::Monitor,
+        input::encoding::InputEncoding,
+        video_decoder::video_decoder::decoder_available,
+        video_encoder::{
+            adapter::software_encoder_config, media_config::MediaConfig, validate::MAX_FRAME_SIDE,
+            video_encoder::encoder_available,
+        },
+    },
+};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+/// The AEAD sealing the packets of encrypted connections.
+pub const AES_256_GCM: &str = "AES-256-GCM";
+
+/// Video codecs this app encodes and decodes, preferred first. HEVC needs
+/// about half the bitrate of H264 for the same picture.
+const VIDEO_CODECS: [VideoCodec; 2] = [VideoCodec::Hevc, VideoCodec::H264];
+
+/// The codecs the ffmpeg of this build encodes and decodes, H264 always. A
+/// session falls back to H264 when either side lacks HEVC.
+static AVAILABLE_VIDEO_CODECS: Lazy<Vec<VideoCodec>> = Lazy::new(|| {
+    VIDEO_CODECS
+        .iter()
+        .filter(|codec| {
+            **codec == VideoCodec::H264
+                || (decoder_available(codec) && encoder_available(&*software_encoder_config(codec)))
+        })
+        .cloned()
+        .collect()
+});
+
+/// Highest frame rate the encoder of this app produces.
+pub const MAX_FRAME_RATE: u8 = 60;
+
+/// Input encodings this app injects, preferred first.
+const INPUT_ENCODINGS: [InputEncoding; 2] = [InputEncoding::Unicode, InputEncoding::Scancode];
+
+const FEATURES: [SessionFeature; 8] = [
+    SessionFeature::Audio,
+    SessionFeature::Input,
+    SessionFeature::FileTransfer,
+    SessionFeature::Chat,
+    SessionFeature::Swap,
+    SessionFeature::Fec,
+    SessionFeature::Clipboard,
+    SessionFeature::NetworkReport,
+];
+
+/// What this app supports, sent to the remote side right after the
+/// handshake.
+pub fn local_capabilities() -> EndPointCapabilities {
+    EndPointCapabilities {
+        video_codecs: AVAILABLE_VIDEO_CODECS
+            .iter()
+            .map(|codec| video_codec_name(codec).to_string())
+            .collect(),
+        aeads: vec![AES_256_GCM.to_string()],
+        features: FEATURES.to_vec(),
+        max_frame_length: max_frame_length() as u32,
+        device_name: device_name(),
+        input_encodings: INPUT_ENCODINGS
+            .iter()
+            .map(|encoding| encoding.name().to_string())
+            .collect(),
+    }
+}
+
+/// The codec sessions this app opens prefer, if the remote side knows it.
+pub fn preferred_video_codec() -> VideoCodec {
+    AVAILABLE_VIDEO_CODECS.first().cloned().unwrap_or_default()
+}
+
+/// The frame rate and frame size this app encodes and decodes at most.
+pub fn local_media_limits() -> EndPointMediaLimits {
+    EndPointMediaLimits {
+        max_frame_rate: MAX_FRAME_RATE,
+        max_width: MAX_FRAME_SIDE as u16,
+        max_height: MAX_FRAME_SIDE as u16,
+    }
+}
+
+/// The lower limit of both sides for each, at least a frame per second.
+pub fn agree_media_limits(
+    local: &EndPointMediaLimits,
+    remote: &EndPointMediaLimits,
+) -> EndPointMediaLimits {
+    EndPointMediaLimits {
+        max_frame_rate: local.max_frame_rate.min(remote.max_frame_rate).max(1),
+        max_width: local.max_width.min(remote.max_width),
+        max_height: local.max_height.min(remote.max_height),
+    }
+}
+
+/// Lowers the frame rate and the scale of `media_config` until the frames
+/// captured from `monitor` stay within `limits`, as far as the smallest
+/// scale allows.
+pub fn fit_media_config(
+    media_config: MediaConfig,
+    monitor: &Monitor,
+    limits: &EndPointMediaLimits,
+) -> MediaConfig {
+    let mut media_config = media_config.sanitize();
+    media_config.frame_rate = media_config.frame_rate.min(limits.max_frame_rate);
+
+    for (side, max_side) in [
+        (monitor.width, limits.max_width),
+        (monitor.height, limits.max_height),
+    ] {
+        if side > max_side {
+            let percent = u32::from(max_side) * 100 / u32::from(side);
+            media_config.scale_percent = media_config.scale_percent.min(percent as u8);
+        }
+    }
+
+    media_config.sanitize()
+}
+
+/// The format the default output device of this app plays, `None` without
+/// one.
+pub fn local_audio_params() -> Option<EndPointAudioParams> {
+    match default_output_config() {
+        Ok(config) => Some(EndPointAudioParams {
+            sample_rate: config.sample_rate().0,
+            channels: config.channels().min(u8::MAX as u16) as u8,
+        }),
+        Err(err) => {
+            tracing::warn!(?err, "no audio output, ask for a session without audio");
+            None
+        }
+    }
+}
+
+/// The format the host encodes the audio of a viewer playing `viewer` in:
+/// the lowest rate of the codec at or above the rate of the viewer, so it
+/// never upsamples, and at most two channels.
+pub fn agree_audio_params(viewer: &EndPointAudioParams) -> EndPointAudioParams {
+    let sample_rate = OPUS_SAMPLE_RATES
+        .iter()
+        .copied()
+        .find(|sample_rate| *sample_rate >= viewer.sample_rate)
+        .unwrap_or(OPUS_SAMPLE_RATES[OPUS_SAMPLE_RATES.len() - 1]);
+
+    EndPointAudioParams {
+        sample_rate,
+        channels: viewer.channels.clamp(1, 2),
+    }
+}
+
+/// What both sides of a session support, the same on either side.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SessionProfile {
+    /// Codecs both sides know, in the order the active side prefers them.
+    pub video_codecs: Vec<VideoCodec>,
+    pub aead: Option<String>,
+    pub features: Vec<SessionFeature>,
+    pub max_frame_length: u32,
+    /// How the viewer sends keystrokes, scancodes unless both sides know
+    /// better.
+    pub input_encoding: InputEncoding,
+}
+
+impl SessionProfile {
+    pub fn supports(&self, feature: SessionFeature) -> bool {
+        self.features.contains(&feature)
+    }
+}
+
+/// Intersects the capabilities of both sides. The preferences of the active
+/// side win, so both sides agree on the same profile. Codecs, AEADs and
+/// features only one side knows are left out, that's how newer versions
+/// stay compatible.
+pub fn agree_profile(
+    active: &EndPointCapabilities,
+    passive: &EndPointCapabilities,
+) -> SessionProfile {
+    let video_codecs = active
+        .video_codecs
+        .iter()
+        .filter(|name| passive.video_codecs.contains(name))
+        .filter_map(|name| parse_video_codec(name))
+        .collect();
+
+    let aead = active
+        .aeads
+        .iter()
+        .find(|name| passive.aeads.contains(name))
+        .cloned();
+
+    let mut features = Vec::new();
+    for feature in &active.features {
+        if *feature != SessionFeature::Unknown
+            && passive.features.contains(feature)
+            && !features.contains(feature)
+        {
+            features.push(*feature);
+        }
+    }
+
+    let input_encoding = active
+        .input_encodings
+        .iter()
+        .filter(|name| passive.input_encodings.contains(name))
+        .find_map(|name| InputEncoding::parse(name))
+        .unwrap_or_default();
+
+    SessionProfile {
+        video_codecs,
+        aead,
+        features,
+        max_frame_length: active.max_frame_length.min(passive.max_frame_length),
+        input_encoding,
+    }
+}
+
+pub(crate) fn video_codec_name(codec: &VideoCodec) -> &'static str {
+    match codec {
+        VideoCodec::H264 => "h264",
+        VideoCodec::Hevc => "hevc",
+        VideoCodec::VP8 => "vp8",
+        VideoCodec::VP9 => "vp9",
+    }
+}
+
+fn parse_video_codec(name: &str) -> Option<VideoCodec> {
+    match name {
+        "h264" => Some(VideoCodec::H264),
+        "hevc" => Some(VideoCodec::Hevc),
+        "vp8" => Some(VideoCodec::VP8),
+        "vp9" => Some(VideoCodec::VP9),
+        _ => None,
+    }
+}
@@ -0,0 +1,171 @@
+use super::{client::EndPointClient, id::EndPointID, message::EndPointMessage};
+use crate::{
+    core_error,
+    error::{CoreError, CoreResult},
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{collections::VecDeque, sync::Arc};
+
+/// Messages kept per remote device, the oldest go first.
+pub const CHAT_HISTORY_LIMIT: usize = 200;
+
+/// Longest message in bytes, chat is for short notes, not for pasting files.
+pub const MAX_CHAT_MESSAGE_LEN: usize = 4096;
+
+static CHAT: Lazy<Chat> = Lazy::new(|| Chat::new(CHAT_HISTORY_LIMIT));
+
+static CHAT_MESSAGE_TX: Lazy<tokio::sync::broadcast::Sender<ChatEntry>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(64).0);
+
+pub fn chat() -> &'static Chat {
+    &CHAT
+}
+
+/// Yields the messages the remote devices send.
+pub fn subscribe_chat_message() -> tokio::sync::broadcast::Receiver<ChatEntry> {
+    CHAT_MESSAGE_TX.subscribe()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChatDirection {
+    Sent,
+    Received,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChatEntry {
+    /// Device id of the remote side, or its ip for LAN sessions.
+    pub remote: String,
+    pub direction: ChatDirection,
+    pub text: String,
+    /// Unix timestamp in milliseconds, taken by the sending side.
+    pub timestamp: i64,
+}
+
+/// Text messages between the two sides of desktop sessions. Both the viewer
+/// and the host can write while the session is up, the history of a remote
+/// device outlives its sessions so a reconnect doesn't lose the thread.
+#[derive(Debug)]
+pub struct Chat {
+    limit: usize,
+    peers: DashMap<EndPointID, Arc<EndPointClient>>,
+    history: DashMap<EndPointID, VecDeque<ChatEntry>>,
+}
+
+impl Chat {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            limit,
+            peers: DashMap::new(),
+            history: DashMap::new(),
+        }
+    }
+
+    pub(crate) fn attach(&self, client: Arc<EndPointClient>) {
+        self.peers.insert(client.endpoint_id(), client);
+    }
+
+    /// Only drops the client if it is still the attached one, a newer
+    /// connection to the same endpoint may have replaced it.
+    pub(crate) fn detach(&self, client: &Arc<EndPointClient>) {
+        self.peers
+            .remove_if(&client.endpoint_id(), |_, peer| Arc::ptr_eq(peer, client));
+    }
+
+    pub fn endpoint_ids(&self) -> Vec<EndPointID> {
+        self.peers.iter().map(|entry| *entry.key()).collect()
+    }
+
+    pub async fn send(&self, endpoint_id: EndPointID, text: String) -> CoreResult<ChatEntry> {
+        validate_chat_text(&text)?;
+
+        let client = match self.peers.get(&endpoint_id) {
+            Some(entry) => entry.value().clone(),
+            None => return Err(CoreError::OutgoingMessageChannelDisconnect),
+        };
+
+        let timestamp = chrono::Utc::now().timestamp_millis();
+
+        client
+            .send(&EndPointMessage::ChatMessage {
+                text: text.clone(),
+                timestamp,
+            })
+            .await?;
+
+        let entry = ChatEntry {
+            remote: remote_label(&endpoint_id),
+            direction: ChatDirection::Sent,
+            text,
+            timestamp,
+        };
+
+        self.record(endpoint_id, entry.clone());
+        Ok(entry)
+    }
+
+    pub(crate) fn receive(&self, endpoint_id: EndPointID, text: String, timestamp: i64) {
+        if let Err(err) = validate_chat_text(&text) {
+            tracing::warn!(?endpoint_id, ?err, "drop chat message");
+            return;
+        }
+
+        let entry = ChatEntry {
+            remote: remote_label(&endpoint_id),
+            direction: ChatDirection::Received,
+            text,
+            timestamp,
+        };
+
+        self.record(endpoint_id, entry.clone());
+        let _ = CHAT_MESSAGE_TX.send(entry);
+    }
+
+    pub fn record(&self, endpoint_id: EndPointID, entry: ChatEntry) {
+        let mut history = self.history.entry(endpoint_id).or_default();
+        history.push_back(entry);
+
+        while history.len() > self.limit {
+            history.pop_front();
+        }
+    }
+
+    /// Messages exchanged with the endpoint, oldest first.
+    pub fn history(&self, endpoint_id: &EndPointID) -> Vec<ChatEntry> {
+        self.history
+            .get(endpoint_id)
+            .map(|history| history.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn clear(&self, endpoint_id: &EndPointID) {
+        self.history.remove(endpoint_id);
+    }
+}
+
+fn validate_chat_text(text: &str) -> CoreResult<()> {
+    if text.trim().is_empty() {
+        return Err(core_error!("chat message is empty"));
+    }
+
+    if text.len() > MAX_CHAT_MESSAGE_LEN {
+        return Err(core_error!(
+            "chat message is too long (max {} bytes)",
+            MAX_CHAT_MESSAGE_LEN
+        ));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn remote_label(endpoint_id: &EndPointID) -> String {
+    match endpoint_id {
+        EndPointID::DeviceID {
+            remote_device_id, ..
+        } => remote_device_id.to_string(),
+        EndPointID::LANID { remote_ip, .. } => remote_ip.to_string(),
+    }
+}
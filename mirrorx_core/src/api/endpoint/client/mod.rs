@@ -3,48 +3,153 @@ mod udp;
 
 use self::{tcp::serve_tcp, udp::serve_udp};
 use super::{
-    handlers::negotiate_desktop_params::handle_negotiate_desktop_params_request, id::EndPointID,
-    message::*, EndPointStream,
+    bandwidth::{rtt_probe_elapsed, spawn_network_reporter, spawn_rtt_prober},
+    call_store::CallStore,
+    call_timeout::{call_timeouts, CallOperation, CallTimeouts},
+    capabilities::{
+        agree_profile, fit_media_config, local_audio_params, local_capabilities,
+        local_media_limits, SessionProfile,
+    },
+    chat::{chat, remote_label},
+    clipboard,
+    connect_retry::{connect_retry_config, connect_with_retry},
+    disconnect,
+    handlers::negotiate_desktop_params::handle_negotiate_desktop_params_request,
+    handshake::HandshakeFailure,
+    heartbeat::{heartbeat_config, spawn_heartbeat, HeartbeatState},
+    id::EndPointID,
+    idle::{spawn_idle_watcher, IdleTracker, SessionRole},
+    media_pause::MediaPause,
+    message::*,
+    network_info::{NetworkInfo, NetworkPath, Transport},
+    packet::{deserialize_packet, max_frame_length, serialize_packet},
+    path_preference::{path_preference, select_path, PathReason},
+    rekey::{rekey_policy, spawn_rekey_timer, KeyRotation},
+    session_limit::IncomingSessionPermit,
+    session_token::session_tokens,
+    socket_buffer::{apply_socket_buffers, SocketBuffers},
+    stats::{EndPointStats, EndPointStatsSnapshot},
+    swap::{
+        self, report_swap_request, SwapRequestEvent, SwapState, ViewerSinks, SWAP_REPLY_TIMEOUT,
+    },
+    EndPointStream,
 };
 use crate::{
-    api::endpoint::handlers::{
-        fs_download_file::handle_download_file_request, fs_send_file::handle_send_file_request,
-        fs_visit_directory::handle_visit_directory_request, input::handle_input,
-        negotiate_finished::handle_negotiate_finished_request,
+    api::{
+        config::device_name::sanitize_device_name,
+        endpoint::handlers::{
+            fs_download_file::handle_download_file_request,
+            fs_list_directory::handle_list_directory_request,
+            fs_send_file::handle_send_file_request,
+            fs_visit_directory::handle_visit_directory_request, input::handle_input,
+            negotiate_finished::handle_negotiate_finished_request, video_frame::VideoDecodeMessage,
+        },
     },
-    call,
+    bail_core, call,
     component::{
-        desktop::monitor::Monitor,
-        fs::transfer::{append_file_block, delete_file_append_session},
+        desktop::{capture_mode::CaptureMode, monitor::Monitor, window::CapturedWindow},
+        fec::{FecDecoder, FecEvent},
+        fs::transfer::{
+            ack_file_block, append_file_block, cancel_endpoint_file_transfers,
+            cancel_file_transfer, delete_file_append_session,
+        },
+        parameter_sets::ParameterSetCache,
+        slice::SliceAssembler,
+        video_decoder::budget::media_budget,
+        video_encoder::{
+            content_hint::{ContentHint, ContentMode},
+            encode_timing::{EncodeLatency, EncodeTimings},
+            media_config::MediaConfig,
+        },
     },
     core_error,
     error::{CoreError, CoreResult},
     utility::{
-        bincode::{bincode_deserialize, bincode_serialize},
+        bincode::bincode_deserialize,
+        error_event::{report_error_event, ErrorSource},
         nonce_value::NonceValue,
     },
 };
 use bytes::Bytes;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
 use ring::aead::{OpeningKey, SealingKey};
-use scopeguard::defer;
 use serde::de::DeserializeOwned;
+use socket2::SockRef;
 use std::{
     fmt::Display,
     ops::Deref,
-    sync::{atomic::AtomicU16, Arc},
-    time::Duration,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, MutexGuard, PoisonError, Weak,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{error::TrySendError, Sender},
+    oneshot, RwLock,
 };
-use tokio::sync::{mpsc::Sender, RwLock};
+use tokio_util::sync::CancellationToken;
 
+/// How long a read loop waits for the keys of a rotation to be handed
+/// over locally.
 const RECV_MESSAGE_TIMEOUT: Duration = Duration::from_secs(30);
 
+static OPEN_CLIENTS: Lazy<OpenClients> = Lazy::new(OpenClients::default);
+
 #[derive(Debug, Clone)]
 pub struct EndPointClient {
     endpoint_id: EndPointID,
+    /// Whether this side opened the connection.
+    active: bool,
+    profile: Arc<std::sync::Mutex<Option<SessionProfile>>>,
+    remote_device_name: Arc<std::sync::Mutex<Option<String>>>,
     monitor: Arc<RwLock<Option<Arc<Monitor>>>>,
+    /// Graphics card the video of the session is encoded with, a host keeps
+    /// the one it told the viewer.
+    encoder_graphics_card: Arc<std::sync::Mutex<Option<String>>>,
+    video_codec: Arc<std::sync::Mutex<Option<VideoCodec>>>,
+    /// Format of the audio this side sends as host, `None` without audio.
+    audio_params: Arc<std::sync::Mutex<Option<EndPointAudioParams>>>,
+    transport: Transport,
+    path_reason: PathReason,
+    encrypted: bool,
+    socket_buffers: SocketBuffers,
+    /// Read once the connection was opened.
+    call_timeouts: CallTimeouts,
+    stats: Arc<EndPointStats>,
+    media_error: Arc<std::sync::Mutex<Option<MediaErrorReason>>>,
+    idle_tracker: Arc<IdleTracker>,
+    include_cursor: Arc<AtomicBool>,
+    content_hint: Arc<std::sync::Mutex<ContentHint>>,
+    content_mode: Arc<std::sync::Mutex<Option<ContentMode>>>,
+    capture_mode: Arc<std::sync::Mutex<Option<CaptureMode>>>,
+    /// The window this side shares, input is mapped onto it.
+    captured_window: CapturedWindow,
+    /// The latest report of the viewer not yet taken by the encoder.
+    network_report: Arc<std::sync::Mutex<Option<EndPointNetworkReport>>>,
+    /// Why the remote side closed the connection, `None` while open or when
+    /// it went away without saying.
+    remote_disconnect: Arc<std::sync::Mutex<Option<DisconnectReason>>>,
+    role: Arc<std::sync::Mutex<Option<SessionRole>>>,
+    input_allowed: Arc<AtomicBool>,
+    has_decode_sinks: Arc<AtomicBool>,
+    media_token: Arc<std::sync::Mutex<CancellationToken>>,
+    media_pause: Arc<MediaPause>,
+    parameter_sets: Arc<ParameterSetCache>,
+    encode_timings: Arc<EncodeTimings>,
+    /// Sequence of the video frames this side sends, it runs on when the
+    /// encoder is recreated so the viewer doesn't take them for old ones.
+    video_frame_seq: Arc<AtomicU64>,
+    swap: Arc<std::sync::Mutex<SwapState>>,
+    heartbeat_state: Arc<tokio::sync::watch::Sender<HeartbeatState>>,
+    key_rotation: Option<Arc<KeyRotation>>,
+    /// Longest frame the remote side accepts, this side's limit until its
+    /// capabilities arrive.
+    frame_limit: Arc<AtomicUsize>,
+    close_token: CancellationToken,
     tx: Sender<Vec<u8>>,
-    call_id: Arc<AtomicU16>,
-    call_store: Arc<moka::sync::Cache<u16, Sender<Vec<u8>>>>,
+    call_store: Arc<CallStore>,
 }
 
 impl EndPointClient {
@@ -52,9 +157,10 @@ impl EndPointClient {
         endpoint_id: EndPointID,
         stream_key: Option<(OpeningKey<NonceValue>, SealingKey<NonceValue>)>,
         stream: EndPointStream,
-        video_frame_tx: Sender<EndPointVideoFrame>,
+        video_frame_tx: Sender<VideoDecodeMessage>,
         audio_frame_tx: Sender<EndPointAudioFrame>,
         visit_credentials: Option<Vec<u8>>,
+        media_config: MediaConfig,
     ) -> CoreResult<Arc<EndPointClient>> {
         EndPointClient::create(
             true,
@@ -64,6 +170,8 @@ impl EndPointClient {
             Some(video_frame_tx),
             Some(audio_frame_tx),
             visit_credentials,
+            Some(media_config),
+            None,
         )
         .await
     }
@@ -82,6 +190,8 @@ impl EndPointClient {
             None,
             None,
             visit_credentials,
+            None,
+            None,
         )
         .await
     }
@@ -91,6 +201,7 @@ impl EndPointClient {
         key_pair: Option<(OpeningKey<NonceValue>, SealingKey<NonceValue>)>,
         stream: EndPointStream,
         visit_credentials: Option<Vec<u8>>,
+        session_permit: IncomingSessionPermit,
     ) -> CoreResult<()> {
         let _ = EndPointClient::create(
             false,
@@ -100,6 +211,8 @@ impl EndPointClient {
             None,
             None,
             visit_credentials,
+            None,
+            Some(session_permit),
         )
         .await?;
         Ok(())
@@ -111,23 +224,59 @@ impl EndPointClient {
         endpoint_id: EndPointID,
         key_pair: Option<(OpeningKey<NonceValue>, SealingKey<NonceValue>)>,
         stream: EndPointStream,
-        video_frame_tx: Option<Sender<EndPointVideoFrame>>,
+        video_frame_tx: Option<Sender<VideoDecodeMessage>>,
         audio_frame_tx: Option<Sender<EndPointAudioFrame>>,
         visit_credentials: Option<Vec<u8>>,
+        media_config: Option<MediaConfig>,
+        session_permit: Option<IncomingSessionPermit>,
     ) -> CoreResult<Arc<EndPointClient>> {
-        let (opening_key, sealing_key) = match key_pair {
-            Some((opening_key, sealing_key)) => (Some(opening_key), Some(sealing_key)),
-            None => (None, None),
+        let encrypted = key_pair.is_some();
+        let (key_rotation, opening_key, sealing_key) = match key_pair {
+            Some((opening_key, sealing_key)) => {
+                let (key_rotation, opening_key, sealing_key) =
+                    KeyRotation::new(active, opening_key, sealing_key);
+                (
+                    Some(Arc::new(key_rotation)),
+                    Some(opening_key),
+                    Some(sealing_key),
+                )
+            }
+            None => (None, None, None),
         };
 
+        // a LAN id connects to the remote ip, a device id through the
+        // endpoints server
+        let path_reason = if active {
+            select_path(path_preference(), &[NetworkPath::from(&endpoint_id)])?.1
+        } else {
+            PathReason::Incoming
+        };
+
+        let stats = Arc::new(EndPointStats::default());
+        let call_timeouts = call_timeouts();
+        let close_token = CancellationToken::new();
+
+        let context = ConnectionContext {
+            call_timeouts,
+            stats: stats.clone(),
+            close_token: close_token.clone(),
+        };
+
+        // a failed or cancelled create stops the loops it started so far,
+        // they don't wait for the remote side to close the stream
+        let unwind = close_token.clone().drop_guard();
+
+        let transport = match stream {
+            EndPointStream::ActiveTCP(_) | EndPointStream::PassiveTCP(_) => Transport::Tcp,
+            EndPointStream::ActiveUDP(_) | EndPointStream::PassiveUDP { .. } => Transport::Udp,
+        };
+
+        let socket_buffers;
         let (tx, mut rx) = match stream {
             EndPointStream::ActiveTCP(addr) => {
-                let stream = tokio::time::timeout(
-                    Duration::from_secs(10),
-                    tokio::net::TcpStream::connect(addr),
-                )
-                .await
-                .map_err(|_| CoreError::Timeout)??;
+                let policy = connect_retry_config().policy(&endpoint_id);
+                let (stream, buffers) = connect_with_retry(addr, &endpoint_id, policy).await?;
+                socket_buffers = buffers;
 
                 serve_tcp(
                     stream,
@@ -135,59 +284,178 @@ impl EndPointClient {
                     sealing_key,
                     opening_key,
                     visit_credentials,
+                    context,
                 )
                 .await?
             }
             EndPointStream::ActiveUDP(_) => panic!("not support yet"),
             EndPointStream::PassiveTCP(stream) => {
+                socket_buffers = apply_socket_buffers(SockRef::from(&stream), &endpoint_id);
+
                 serve_tcp(
                     stream,
                     endpoint_id,
                     sealing_key,
                     opening_key,
                     visit_credentials,
+                    context,
                 )
                 .await?
             }
             EndPointStream::PassiveUDP { socket, .. } => {
+                socket_buffers = apply_socket_buffers(SockRef::from(&socket), &endpoint_id);
+
                 serve_udp(
                     socket,
                     endpoint_id,
                     sealing_key,
                     opening_key,
                     visit_credentials,
+                    context,
                 )
                 .await?
             }
         };
 
-        // active endpoint should start negotiate with passive endpoint
-        let primary_monitor = if active && video_frame_tx.is_some() && audio_frame_tx.is_some() {
-            let params = serve_active_negotiate(&tx, &mut rx).await?;
-            Some(Arc::new(params.primary_monitor))
-        } else {
-            None
-        };
+        // both sides advertise what they support before anything else
+        let capabilities_buffer =
+            serialize_packet(&EndPointMessage::Capabilities(local_capabilities()))?;
+        tx.send(capabilities_buffer)
+            .await
+            .map_err(|_| CoreError::OutgoingMessageChannelDisconnect)?;
+
+        let include_cursor = media_config
+            .as_ref()
+            .is_none_or(|media_config| media_config.include_cursor);
+
+        let content_hint = media_config
+            .as_ref()
+            .map_or_else(ContentHint::default, |media_config| {
+                media_config.content_hint
+            });
 
-        let call_store = moka::sync::CacheBuilder::new(32)
-            .time_to_live(Duration::from_secs(60))
-            .build();
+        let fec_level = media_config
+            .as_ref()
+            .map_or(0, |media_config| media_config.fec_level);
+
+        // active endpoint should start negotiate with passive endpoint, the
+        // profile of other connections arrives in the message loop
+        let (primary_monitor, encoder_graphics_card, video_codec, profile, remote_device_name) =
+            match media_config {
+                Some(media_config)
+                    if active && video_frame_tx.is_some() && audio_frame_tx.is_some() =>
+                {
+                    let (profile, remote_device_name) =
+                        serve_active_capabilities(&mut rx, &call_timeouts).await?;
+                    let params = serve_active_negotiate(
+                        endpoint_id,
+                        &tx,
+                        &mut rx,
+                        media_config,
+                        profile.video_codecs.clone(),
+                        &call_timeouts,
+                    )
+                    .await?;
+                    (
+                        Some(Arc::new(params.primary_monitor)),
+                        params.encoder_graphics_card,
+                        Some(params.video_codec),
+                        Some(profile),
+                        remote_device_name,
+                    )
+                }
+                _ => (None, None, None, None, None),
+            };
+
+        let desktop_viewer = active && video_frame_tx.is_some();
 
         let client = Arc::new(EndPointClient {
             endpoint_id,
+            active,
+            profile: Arc::new(std::sync::Mutex::new(profile)),
+            remote_device_name: Arc::new(std::sync::Mutex::new(remote_device_name)),
             monitor: Arc::new(RwLock::new(primary_monitor)),
+            encoder_graphics_card: Arc::new(std::sync::Mutex::new(encoder_graphics_card)),
+            video_codec: Arc::new(std::sync::Mutex::new(video_codec)),
+            audio_params: Arc::new(std::sync::Mutex::new(None)),
+            transport,
+            path_reason,
+            encrypted,
+            socket_buffers,
+            call_timeouts,
+            stats,
+            media_error: Arc::new(std::sync::Mutex::new(None)),
+            idle_tracker: Arc::new(IdleTracker::default()),
+            include_cursor: Arc::new(AtomicBool::new(include_cursor)),
+            content_hint: Arc::new(std::sync::Mutex::new(content_hint)),
+            content_mode: Arc::new(std::sync::Mutex::new(None)),
+            capture_mode: Arc::new(std::sync::Mutex::new(None)),
+            captured_window: CapturedWindow::default(),
+            network_report: Arc::new(std::sync::Mutex::new(None)),
+            remote_disconnect: Arc::new(std::sync::Mutex::new(None)),
+            role: Arc::new(std::sync::Mutex::new(
+                desktop_viewer.then_some(SessionRole::Viewer),
+            )),
+            input_allowed: Arc::new(AtomicBool::new(false)),
+            has_decode_sinks: Arc::new(AtomicBool::new(desktop_viewer)),
+            media_token: Arc::new(std::sync::Mutex::new(close_token.child_token())),
+            media_pause: Arc::new(MediaPause::default()),
+            parameter_sets: Arc::new(ParameterSetCache::default()),
+            encode_timings: Arc::new(EncodeTimings::default()),
+            video_frame_seq: Arc::new(AtomicU64::new(0)),
+            swap: Arc::new(std::sync::Mutex::new(SwapState::default())),
+            heartbeat_state: Arc::new(tokio::sync::watch::channel(HeartbeatState::Connected).0),
+            key_rotation,
+            frame_limit: Arc::new(AtomicUsize::new(max_frame_length())),
+            close_token,
             tx,
-            call_id: Arc::new(AtomicU16::new(0)),
-            call_store: Arc::new(call_store),
+            call_store: Arc::new(CallStore::default()),
         });
 
-        handle_message(client.clone(), rx, video_frame_tx, audio_frame_tx);
+        OPEN_CLIENTS.register(&client);
+
+        watch_outgoing_channel(
+            endpoint_id,
+            client.tx.clone(),
+            client.close_token.clone(),
+            client.call_store.clone(),
+        );
+
+        handle_message(
+            client.clone(),
+            rx,
+            video_frame_tx,
+            audio_frame_tx,
+            session_permit,
+            fec_level,
+        );
+
+        spawn_heartbeat(&client, heartbeat_config());
+
+        // one side keeps time, rotations of both sides at once would clash
+        if active && encrypted {
+            spawn_rekey_timer(&client, rekey_policy());
+        }
+
+        if desktop_viewer {
+            chat().attach(client.clone());
+            clipboard::attach(client.clone());
+            swap::attach(client.clone());
+            spawn_idle_watcher(client.clone(), SessionRole::Viewer);
+            spawn_rtt_prober(client.clone());
+            spawn_network_reporter(client.clone());
+        }
 
+        unwind.disarm();
         Ok(client)
     }
 }
 
 impl EndPointClient {
+    pub fn endpoint_id(&self) -> EndPointID {
+        self.endpoint_id
+    }
+
     pub async fn monitor(&self) -> Option<Arc<Monitor>> {
         (*self.monitor.read().await).clone()
     }
@@ -195,50 +463,683 @@ impl EndPointClient {
     pub async fn set_monitor(&self, monitor: Monitor) {
         (*self.monitor.write().await) = Some(Arc::new(monitor))
     }
+
+    /// Graphics card the remote side encodes video with, `None` for the
+    /// software encoder or when this endpoint doesn't receive video.
+    pub fn encoder_graphics_card(&self) -> Option<String> {
+        self.encoder_graphics_card
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn set_encoder_graphics_card(&self, graphics_card: Option<String>) {
+        *self
+            .encoder_graphics_card
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = graphics_card;
+    }
+
+    /// Tells the viewer the host encodes on another graphics card than the
+    /// one it was told, `None` after falling back to the software encoder.
+    pub fn announce_encoder_graphics_card(&self, graphics_card: Option<String>) {
+        {
+            let mut current = self
+                .encoder_graphics_card
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner);
+
+            if *current == graphics_card {
+                return;
+            }
+
+            *current = graphics_card.clone();
+        }
+
+        if let Err(err) = self.try_send(&EndPointMessage::EncoderGraphicsCard(graphics_card)) {
+            tracing::error!(?err, "send encoder graphics card failed");
+        }
+    }
+
+    /// Codec of the video of the session in either direction, `None` until
+    /// it was negotiated.
+    pub fn video_codec(&self) -> Option<VideoCodec> {
+        self.video_codec
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn set_video_codec(&self, video_codec: VideoCodec) {
+        *self
+            .video_codec
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(video_codec);
+    }
+
+    pub(crate) fn audio_params(&self) -> Option<EndPointAudioParams> {
+        *self
+            .audio_params
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub(crate) fn set_audio_params(&self, audio_params: Option<EndPointAudioParams>) {
+        *self
+            .audio_params
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = audio_params;
+    }
+
+    pub fn stats(&self) -> EndPointStatsSnapshot {
+        self.stats.snapshot()
+    }
+
+    pub(crate) fn raw_stats(&self) -> &EndPointStats {
+        &self.stats
+    }
+
+    pub fn network_info(&self) -> NetworkInfo {
+        let stats = self.stats.snapshot();
+
+        NetworkInfo {
+            path: NetworkPath::from(&self.endpoint_id),
+            path_reason: self.path_reason,
+            transport: self.transport,
+            aead: self.encrypted.then_some("AES-256-GCM"),
+            rtt_ms: self.stats.last_rtt().map(|rtt| rtt.as_secs_f64() * 1000.0),
+            video_codec: self.video_codec(),
+            capture_mode: self.capture_mode(),
+            estimated_bps: stats.estimated_bps,
+            sent_bytes: self.stats.sent_bytes(),
+            video_frame_bytes: stats.video_frame_bytes,
+            media_paused: self.media_pause.is_paused(),
+            encode_latency: self.encode_timings.latency(),
+        }
+    }
+
+    /// The buffer sizes of the socket of the connection as the system
+    /// reported them once it was opened.
+    pub fn socket_buffers(&self) -> SocketBuffers {
+        self.socket_buffers
+    }
+
+    /// How long the operations of the connection wait for the remote side.
+    pub fn call_timeouts(&self) -> CallTimeouts {
+        self.call_timeouts
+    }
+
+    /// Smoothed bandwidth available towards the remote side, zero until
+    /// measured. Only the side that sends video measures it.
+    pub fn estimated_bps(&self) -> u64 {
+        self.stats.estimated_bps()
+    }
+
+    /// Closes the connection, the remote side sees it closed as well.
+    pub fn close(&self) {
+        self.close_token.cancel();
+    }
+
+    /// Closes the connection on purpose, the write loop sends a
+    /// `Disconnect` with the reason and waits briefly for it to go out
+    /// before closing the stream. The remote side takes that as a graceful
+    /// exit rather than a broken connection.
+    pub fn close_with(&self, reason: DisconnectReason) {
+        if self.is_closed() {
+            return;
+        }
+
+        if let Err(err) = self.try_send(&EndPointMessage::Disconnect { reason }) {
+            tracing::warn!(?err, ?reason, "queue disconnect failed, close right away");
+        }
+
+        self.close();
+    }
+
+    /// Why the remote side closed the connection, `None` while it's open
+    /// or when the remote side crashed or the network broke.
+    pub fn remote_disconnect(&self) -> Option<DisconnectReason> {
+        *self
+            .remote_disconnect
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub async fn closed(&self) {
+        self.close_token.cancelled().await
+    }
+
+    /// Whether the connection was closed by either side or broke.
+    pub fn is_closed(&self) -> bool {
+        self.close_token.is_cancelled()
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        self.idle_tracker.idle_for()
+    }
+
+    /// Resets the idle timer of this session and the remote one.
+    pub async fn keep_alive(&self) -> CoreResult<()> {
+        self.send(&EndPointMessage::KeepAlive).await
+    }
+
+    /// Whether the captured frames of this session contain the pointer.
+    pub fn include_cursor(&self) -> bool {
+        self.include_cursor.load(Ordering::Relaxed)
+    }
+
+    /// Switches the pointer in the remote frames on or off while the session
+    /// is running.
+    pub fn set_include_cursor(&self, include_cursor: bool) -> CoreResult<()> {
+        self.try_send(&EndPointMessage::IncludeCursor(include_cursor))?;
+        self.include_cursor.store(include_cursor, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// What the captured frames of this session show, the encoder is tuned
+    /// for it.
+    pub fn content_hint(&self) -> ContentHint {
+        *self
+            .content_hint
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Switches the encoder tuning of the remote side while the session is
+    /// running, it takes effect with the next keyframe.
+    pub fn set_content_hint(&self, content_hint: ContentHint) -> CoreResult<()> {
+        self.try_send(&EndPointMessage::ContentHint(content_hint))?;
+        self.store_content_hint(content_hint);
+        Ok(())
+    }
+
+    fn store_content_hint(&self, content_hint: ContentHint) {
+        *self
+            .content_hint
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = content_hint;
+    }
+
+    /// The content the remote encoder is tuned for as it last reported,
+    /// `None` while it encodes with the media config as is.
+    pub fn content_mode(&self) -> Option<ContentMode> {
+        *self
+            .content_mode
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// How the video of the session is captured, by whichever side sends it.
+    /// `None` until the capture started.
+    pub fn capture_mode(&self) -> Option<CaptureMode> {
+        *self
+            .capture_mode
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// The report the viewer sent since the last call, if any.
+    pub(crate) fn take_network_report(&self) -> Option<EndPointNetworkReport> {
+        self.network_report
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .take()
+    }
+
+    /// Takes how this side captures the video it sends, and tells the
+    /// viewer when it changed.
+    pub(crate) fn report_capture_mode(&self, capture_mode: CaptureMode) -> CoreResult<()> {
+        let previous = self
+            .capture_mode
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .replace(capture_mode);
+
+        if previous == Some(capture_mode) {
+            return Ok(());
+        }
+
+        tracing::info!(?capture_mode, "capture mode changed");
+        self.blocking_send(&EndPointMessage::CaptureModeChanged(capture_mode))
+    }
+
+    /// Asks the remote side to stop capturing and sending video, e.g. while
+    /// the viewer is minimized. Input and audio keep running.
+    pub fn pause_media(&self) -> CoreResult<()> {
+        self.try_send(&EndPointMessage::PauseMedia)?;
+        self.media_pause.pause();
+        Ok(())
+    }
+
+    /// Asks the remote side to send video again, starting with a keyframe.
+    pub fn resume_media(&self) -> CoreResult<()> {
+        self.try_send(&EndPointMessage::ResumeMedia)?;
+        self.media_pause.resume();
+        Ok(())
+    }
+
+    /// Asks the remote side to encode its next frame as a keyframe.
+    pub fn request_keyframe(&self) -> CoreResult<()> {
+        self.try_send(&EndPointMessage::RequestKeyFrame)
+    }
+
+    pub fn media_paused(&self) -> bool {
+        self.media_pause.is_paused()
+    }
+
+    pub(crate) fn media_pause(&self) -> &MediaPause {
+        &self.media_pause
+    }
+
+    /// Parameter sets of the video this side encodes.
+    pub(crate) fn parameter_sets(&self) -> &ParameterSetCache {
+        &self.parameter_sets
+    }
+
+    /// Encode timings of the recent frames of the video this side sends.
+    pub(crate) fn encode_timings(&self) -> &EncodeTimings {
+        &self.encode_timings
+    }
+
+    /// Where the capture found the window this side shares, if any.
+    pub(crate) fn captured_window(&self) -> &CapturedWindow {
+        &self.captured_window
+    }
+
+    /// Takes the `seq` of the next video frame this side sends.
+    pub(crate) fn next_video_frame_seq(&self) -> u64 {
+        self.video_frame_seq.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// The last error the remote side reported about the media stream.
+    pub fn media_error(&self) -> Option<CoreError> {
+        self.media_error
+            .lock()
+            .ok()
+            .and_then(|reason| reason.map(CoreError::from))
+    }
+
+    /// Tells the remote side why the media stream can't be provided. Never
+    /// blocks, so it's usable from both the capture threads and async tasks.
+    pub fn report_media_error(&self, reason: MediaErrorReason) {
+        tracing::error!(?reason, "report media error to remote endpoint");
+        if let Err(err) = self.try_send(&EndPointMessage::Error { reason }) {
+            tracing::error!(?err, "send media error failed");
+        }
+    }
+
+    /// What both sides support, `None` until the capabilities of the remote
+    /// side arrived.
+    pub fn profile(&self) -> Option<SessionProfile> {
+        self.profile
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn supports(&self, feature: SessionFeature) -> bool {
+        self.profile()
+            .is_some_and(|profile| profile.supports(feature))
+    }
+
+    /// The name the remote device shows to others, `None` until its
+    /// capabilities arrived.
+    pub fn remote_device_name(&self) -> Option<String> {
+        self.remote_device_name
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn store_capabilities(&self, capabilities: EndPointCapabilities) {
+        let local = local_capabilities();
+        let profile = if self.active {
+            agree_profile(&local, &capabilities)
+        } else {
+            agree_profile(&capabilities, &local)
+        };
+
+        let mut stored = self.profile.lock().unwrap_or_else(PoisonError::into_inner);
+        if stored.is_some() {
+            tracing::warn!("remote endpoint sent its capabilities again");
+            return;
+        }
+
+        tracing::info!(?profile, "agreed session profile");
+        self.frame_limit
+            .store(profile.max_frame_length as usize, Ordering::Relaxed);
+        *stored = Some(profile);
+
+        *self
+            .remote_device_name
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) =
+            sanitize_device_name(&capabilities.device_name);
+    }
+
+    /// Which side of the desktop session this device is, `None` before the
+    /// session is negotiated and for file manager connections. A swap flips
+    /// it.
+    pub fn role(&self) -> Option<SessionRole> {
+        *self.role.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn set_role(&self, role: SessionRole) {
+        *self.role.lock().unwrap_or_else(PoisonError::into_inner) = Some(role);
+    }
+
+    /// Whether the remote side may control this device while it shares its
+    /// screen. Only a swap asks for it, a visit is allowed by its password.
+    pub fn input_allowed(&self) -> bool {
+        self.input_allowed.load(Ordering::Relaxed)
+    }
+
+    /// Whether the remote side may negotiate the desktop of this device. A
+    /// visited device shares it from the start, the visiting one only once
+    /// its user agreed to swap.
+    fn may_share_desktop(&self) -> bool {
+        match self.role() {
+            Some(SessionRole::Host) => true,
+            Some(SessionRole::Viewer) => false,
+            None => !self.active,
+        }
+    }
+
+    /// Whether the message loop has channels to pass the remote frames to,
+    /// a side that never watched needs a decode pipeline first.
+    pub(crate) fn has_decode_sinks(&self) -> bool {
+        self.has_decode_sinks.load(Ordering::Relaxed)
+    }
+
+    /// Cancelled when the capture processes of this side have to stop, on
+    /// close or when the session swaps away from sharing.
+    pub(crate) fn media_token(&self) -> CancellationToken {
+        self.media_token
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub(crate) fn close_token(&self) -> CancellationToken {
+        self.close_token.clone()
+    }
+
+    /// Whether the remote side is still heard from, see
+    /// [`spawn_heartbeat`].
+    pub fn heartbeat_state(&self) -> HeartbeatState {
+        *self.heartbeat_state.borrow()
+    }
+
+    pub fn watch_heartbeat_state(&self) -> tokio::sync::watch::Receiver<HeartbeatState> {
+        self.heartbeat_state.subscribe()
+    }
+
+    pub(crate) fn set_heartbeat_state(&self, state: HeartbeatState) {
+        self.heartbeat_state.send_replace(state);
+    }
+
+    /// Rotates the session keys with a fresh X25519 exchange inside the
+    /// encrypted connection, returns once this side seals with the new keys.
+    /// Either side may start one.
+    pub async fn rekey(&self) -> CoreResult<()> {
+        let Some(ref key_rotation) = self.key_rotation else {
+            bail_core!("connection isn't encrypted");
+        };
+
+        let (request, done_rx) = key_rotation.start()?;
+        if let Err(err) = self.send(&EndPointMessage::RekeyRequest(request)).await {
+            key_rotation.cancel();
+            return Err(err);
+        }
+
+        let done = tokio::select! {
+            _ = self.close_token.cancelled() => {
+                return Err(CoreError::OutgoingMessageChannelDisconnect)
+            }
+            done = self.call_timeouts.wait(CallOperation::Rekey, done_rx) => done,
+        };
+
+        match done {
+            Ok(Ok(())) => {
+                tracing::info!(endpoint_id = ?self.endpoint_id, "session keys rotated");
+                self.stats.reset_link_stats();
+                Ok(())
+            }
+            Ok(Err(_)) => Err(core_error!("key rotation interrupted")),
+            Err(err) => {
+                // the remote side may have switched already, the connection
+                // can't be trusted to line up again
+                key_rotation.cancel();
+                self.close();
+                Err(err)
+            }
+        }
+    }
+
+    fn stop_media(&self) {
+        let mut media_token = self
+            .media_token
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        media_token.cancel();
+        *media_token = self.close_token.child_token();
+        self.media_pause.reset();
+        self.parameter_sets.clear();
+        self.encode_timings.clear();
+    }
+
+    fn swap_state(&self) -> MutexGuard<'_, SwapState> {
+        self.swap.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl EndPointClient {
+    /// Asks the remote side to swap and waits for its answer, whether it
+    /// accepted. On acceptance a viewer starts hosting before this returns.
+    pub(crate) async fn request_swap(&self, allow_input: bool) -> CoreResult<bool> {
+        if !self.supports(SessionFeature::Swap) {
+            bail_core!("remote endpoint can't swap");
+        }
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+
+        {
+            let mut swap = self.swap_state();
+            if swap.outgoing.is_some() || swap.incoming.is_some() {
+                bail_core!("swap already in progress");
+            }
+            swap.outgoing = Some((allow_input, reply_tx));
+        }
+
+        if let Err(err) = self.send(&EndPointMessage::SwapDirectionRequest).await {
+            self.swap_state().outgoing = None;
+            return Err(err);
+        }
+
+        tokio::select! {
+            _ = self.closed() => Err(CoreError::OutgoingMessageChannelDisconnect),
+            reply = tokio::time::timeout(SWAP_REPLY_TIMEOUT, reply_rx) => match reply {
+                Ok(reply) => reply.map_err(|_| CoreError::OutgoingMessageChannelDisconnect),
+                Err(_) => {
+                    self.swap_state().outgoing = None;
+                    Err(CoreError::Timeout)
+                }
+            },
+        }
+    }
+
+    /// Answers the pending swap request of the remote side. On acceptance a
+    /// viewer starts hosting before the reply goes out, the remote side asks
+    /// for the media right away.
+    pub(crate) async fn reply_swap(&self, accepted: bool, allow_input: bool) -> CoreResult<()> {
+        let requested_at = self.swap_state().incoming.take();
+        if !matches!(requested_at, Some(requested_at) if requested_at.elapsed() < SWAP_REPLY_TIMEOUT)
+        {
+            bail_core!("swap request expired");
+        }
+
+        if accepted && self.role() == Some(SessionRole::Viewer) {
+            self.become_host(allow_input);
+        }
+
+        self.send(&EndPointMessage::SwapDirectionReply { accepted })
+            .await
+    }
+
+    /// Stops watching, the remote side negotiates its media with this side
+    /// next like a new viewer does.
+    fn become_host(&self, allow_input: bool) {
+        self.input_allowed.store(allow_input, Ordering::Relaxed);
+        self.set_role(SessionRole::Host);
+    }
+
+    /// Stops sharing and negotiates the media of the remote side. The frames
+    /// go to `sinks`, or to the channels of the earlier viewer when `None`.
+    pub(crate) async fn become_viewer(
+        &self,
+        media_config: MediaConfig,
+        sinks: Option<ViewerSinks>,
+    ) -> CoreResult<()> {
+        self.stop_media();
+        self.set_role(SessionRole::Viewer);
+        let audio = media_config.audio.then(local_audio_params).flatten();
+        self.swap_state().viewer = Some((media_config.sanitize(), sinks));
+
+        let video_codecs = self
+            .profile()
+            .map(|profile| profile.video_codecs)
+            .unwrap_or_default();
+
+        // the swapped session is new to the remote side, nothing to rejoin
+        self.send(&EndPointMessage::NegotiateDesktopParamsRequest(
+            EndPointNegotiateDesktopParamsRequest {
+                video_codecs,
+                media_limits: local_media_limits(),
+                audio,
+                rejoin_token: None,
+            },
+        ))
+        .await
+    }
 }
 
 impl EndPointClient {
     pub fn try_send(&self, message: &EndPointMessage) -> CoreResult<()> {
-        let buffer = bincode_serialize(message)?;
+        self.record_activity(message);
+        let buffer = serialize_packet(message)?;
+        self.check_frame_length(&buffer)?;
+        let buffer_len = buffer.len();
         self.tx
             .try_send(buffer)
-            .map_err(|_| CoreError::OutgoingMessageChannelDisconnect)
+            .map(|_| self.stats.record_queued(buffer_len))
+            .map_err(|err| match err {
+                TrySendError::Full(_) => {
+                    self.stats.record_dropped_frame();
+                    CoreError::OutgoingMessageChannelFull
+                }
+                TrySendError::Closed(_) => CoreError::OutgoingMessageChannelDisconnect,
+            })
+    }
+
+    /// Queues a video or audio frame without waiting for room. A full send
+    /// queue returns `OutgoingMessageChannelFull` and the caller drops the
+    /// frame, a slow link mustn't stall the control messages behind it.
+    pub fn send_media_frame(&self, message: &EndPointMessage) -> CoreResult<()> {
+        self.try_send(message)
     }
 
+    /// Waits for room in the send queue, except for media frames which go
+    /// through [`send_media_frame`](Self::send_media_frame).
     pub fn blocking_send(&self, message: &EndPointMessage) -> CoreResult<()> {
-        let buffer = bincode_serialize(message)?;
+        if message.is_media_frame() {
+            return self.send_media_frame(message);
+        }
+
+        self.record_activity(message);
+        let buffer = serialize_packet(message)?;
+        self.check_frame_length(&buffer)?;
+        self.stats.record_queued(buffer.len());
         self.tx
             .blocking_send(buffer)
             .map_err(|_| CoreError::OutgoingMessageChannelDisconnect)
     }
 
+    /// Waits for room in the send queue, except for media frames which go
+    /// through [`send_media_frame`](Self::send_media_frame).
     pub async fn send(&self, message: &EndPointMessage) -> CoreResult<()> {
-        let buffer = bincode_serialize(message)?;
+        if message.is_media_frame() {
+            return self.send_media_frame(message);
+        }
+
+        self.record_activity(message);
+        let buffer = serialize_packet(message)?;
+        self.check_frame_length(&buffer)?;
+        self.stats.record_queued(buffer.len());
         self.tx
             .send(buffer)
             .await
             .map_err(|_| CoreError::OutgoingMessageChannelDisconnect)
     }
 
+    /// A frame longer than the remote side accepts would make it drop the
+    /// connection, the packet is refused here instead.
+    fn check_frame_length(&self, buffer: &[u8]) -> CoreResult<()> {
+        let len = if self.encrypted {
+            buffer.len() + ring::aead::MAX_TAG_LEN
+        } else {
+            buffer.len()
+        };
+
+        let max = self.frame_limit.load(Ordering::Relaxed);
+        if len > max {
+            self.stats.record_oversized_frame();
+            return Err(CoreError::FrameTooLarge { len, max });
+        }
+
+        Ok(())
+    }
+
+    fn record_activity(&self, message: &EndPointMessage) {
+        if message.is_activity() {
+            self.idle_tracker.touch();
+        }
+    }
+
+    /// Sends a request and waits for its reply at most the call timeout the
+    /// connection was opened with.
     pub async fn call<TReply>(&self, message: EndPointCallRequest) -> CoreResult<TReply>
     where
         TReply: DeserializeOwned,
     {
-        let call_id = self
-            .call_id
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst);
-
-        let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+        self.call_with_timeout(message, self.call_timeouts.timeout(CallOperation::Call))
+            .await
+    }
 
-        self.call_store.insert(call_id, tx);
-        defer! {
-            self.call_store.invalidate(&call_id);
-        }
+    /// Like [`call`](Self::call), for a request that takes more or less
+    /// time to answer than most.
+    pub async fn call_with_timeout<TReply>(
+        &self,
+        message: EndPointCallRequest,
+        timeout: Duration,
+    ) -> CoreResult<TReply>
+    where
+        TReply: DeserializeOwned,
+    {
+        // dropping the pending call removes it from the store on every path
+        let mut pending_call = self.call_store.register()?;
 
-        self.send(&EndPointMessage::CallRequest(call_id, message))
-            .await?;
+        self.send(&EndPointMessage::CallRequest(
+            pending_call.call_id(),
+            message,
+        ))
+        .await?;
 
-        let reply_bytes = rx.recv().await.ok_or(CoreError::Timeout)?;
+        let reply_bytes = pending_call.recv(timeout).await.map_err(|err| match err {
+            CoreError::Timeout => CoreError::CallTimeout {
+                operation: CallOperation::Call,
+            },
+            err => err,
+        })?;
 
         bincode_deserialize::<Result<TReply, String>>(&reply_bytes)?
             .map_err(|err_str| core_error!("{}", err_str))
@@ -251,13 +1152,58 @@ impl Display for EndPointClient {
     }
 }
 
+/// Waits for the capabilities of the passive side, the first packet it
+/// sends. Returns the agreed profile and the name of the passive device.
+async fn serve_active_capabilities(
+    rx: &mut tokio::sync::mpsc::Receiver<Bytes>,
+    call_timeouts: &CallTimeouts,
+) -> CoreResult<(SessionProfile, Option<String>)> {
+    let capabilities_buffer = call_timeouts
+        .wait(CallOperation::Handshake, rx.recv())
+        .await?
+        .ok_or(CoreError::OutgoingMessageChannelDisconnect)?;
+
+    let capabilities = match deserialize_packet(capabilities_buffer.deref())? {
+        EndPointMessage::Capabilities(capabilities) => capabilities,
+        EndPointMessage::Error { reason } => {
+            tracing::error!(?reason, "capabilities exchange failed with remote error");
+            return Err(reason.into());
+        }
+        EndPointMessage::VisitFailure(reason) => {
+            tracing::error!(?reason, "remote endpoint turned the visit down");
+            return Err(reason.into());
+        }
+        _ => {
+            tracing::error!("unexpected capabilities reply");
+            return Err(HandshakeFailure::UnexpectedReply.into());
+        }
+    };
+
+    let profile = agree_profile(&local_capabilities(), &capabilities);
+    tracing::info!(?profile, "agreed session profile");
+
+    if profile.video_codecs.is_empty() {
+        return Err(CoreError::MediaCodecUnsupported);
+    }
+
+    Ok((profile, sanitize_device_name(&capabilities.device_name)))
+}
+
 async fn serve_active_negotiate(
+    endpoint_id: EndPointID,
     tx: &Sender<Vec<u8>>,
     rx: &mut tokio::sync::mpsc::Receiver<Bytes>,
+    media_config: MediaConfig,
+    video_codecs: Vec<VideoCodec>,
+    call_timeouts: &CallTimeouts,
 ) -> CoreResult<EndPointNegotiateVisitDesktopParams> {
-    let negotiate_request_buffer = bincode_serialize(
+    // a session with this endpoint dropped recently, ask to rejoin it
+    let negotiate_request_buffer = serialize_packet(
         &EndPointMessage::NegotiateDesktopParamsRequest(EndPointNegotiateDesktopParamsRequest {
-            video_codecs: vec![VideoCodec::H264],
+            video_codecs,
+            media_limits: local_media_limits(),
+            audio: media_config.audio.then(local_audio_params).flatten(),
+            rejoin_token: session_tokens().take_received(&endpoint_id),
         }),
     )?;
 
@@ -265,24 +1211,31 @@ async fn serve_active_negotiate(
         .await
         .map_err(|_| CoreError::OutgoingMessageChannelDisconnect)?;
 
-    let negotiate_response_buffer = tokio::time::timeout(RECV_MESSAGE_TIMEOUT, rx.recv())
-        .await
-        .map_err(|_| CoreError::Timeout)?
+    let negotiate_response_buffer = call_timeouts
+        .wait(CallOperation::MediaStart, rx.recv())
+        .await?
         .ok_or(CoreError::OutgoingMessageChannelDisconnect)?;
 
-    let EndPointMessage::NegotiateDesktopParamsResponse(negotiate_response) =
-        bincode_deserialize(negotiate_response_buffer.deref())? else {
-            return Err(core_error!("unexpected negotiate reply"));
-        };
+    let negotiate_response = match deserialize_packet(negotiate_response_buffer.deref())? {
+        EndPointMessage::NegotiateDesktopParamsResponse(negotiate_response) => negotiate_response,
+        EndPointMessage::Error { reason } => {
+            tracing::error!(?reason, "negotiate failed with remote error");
+            return Err(reason.into());
+        }
+        _ => {
+            tracing::error!("unexpected negotiate reply");
+            return Err(HandshakeFailure::UnexpectedReply.into());
+        }
+    };
 
     let params = match negotiate_response {
         EndPointNegotiateDesktopParamsResponse::VideoError(err) => {
             tracing::error!(?err, "negotiate failed with video error");
-            return Err(core_error!("negotiate failed ({})", err));
+            return Err(CoreError::MediaCodecUnsupported);
         }
         EndPointNegotiateDesktopParamsResponse::MonitorError(err) => {
             tracing::error!(?err, "negotiate failed with display error");
-            return Err(core_error!("negotiate failed ({})", err));
+            return Err(CoreError::MediaNoDisplay);
         }
         EndPointNegotiateDesktopParamsResponse::Params(params) => {
             tracing::info!(?params, "negotiate success");
@@ -290,10 +1243,14 @@ async fn serve_active_negotiate(
         }
     };
 
-    let negotiate_request_buffer = bincode_serialize(&EndPointMessage::NegotiateFinishedRequest(
-        EndPointNegotiateFinishedRequest {
-            expected_frame_rate: 60,
-        },
+    if let Some(session_token) = params.session_token {
+        session_tokens().remember(endpoint_id, session_token);
+    }
+
+    let media_config =
+        fit_media_config(media_config, &params.primary_monitor, &params.media_limits);
+    let negotiate_request_buffer = serialize_packet(&EndPointMessage::NegotiateFinishedRequest(
+        EndPointNegotiateFinishedRequest { media_config },
     ))?;
 
     tx.send(negotiate_request_buffer)
@@ -303,66 +1260,364 @@ async fn serve_active_negotiate(
     Ok(params)
 }
 
+/// What the read and write loops of a connection share with its client.
+pub(crate) struct ConnectionContext {
+    pub call_timeouts: CallTimeouts,
+    pub stats: Arc<EndPointStats>,
+    pub close_token: CancellationToken,
+}
+
+/// Connections that aren't closed yet, so quitting can close them all. The
+/// clients are held weak, their owners decide how long they live.
+#[derive(Debug, Default)]
+pub(crate) struct OpenClients {
+    next_key: AtomicU64,
+    clients: DashMap<u64, Weak<EndPointClient>>,
+}
+
+impl OpenClients {
+    pub(crate) fn register(&self, client: &Arc<EndPointClient>) {
+        // connections come and go rarely, forget the closed ones meanwhile
+        self.clients
+            .retain(|_, client| client.upgrade().is_some_and(|client| !client.is_closed()));
+
+        let key = self.next_key.fetch_add(1, Ordering::Relaxed);
+        self.clients.insert(key, Arc::downgrade(client));
+    }
+
+    /// Closes every connection, the write loops send a `Disconnect` and
+    /// close their streams so the remote sides see the disconnect right
+    /// away. Waits at most `timeout` for them, returns how many were open.
+    pub(crate) async fn close_all(&self, timeout: Duration) -> usize {
+        let clients: Vec<Arc<EndPointClient>> = self
+            .clients
+            .iter()
+            .filter_map(|entry| entry.value().upgrade())
+            .filter(|client| !client.is_closed())
+            .collect();
+        self.clients.clear();
+
+        for client in clients.iter() {
+            client.close_with(DisconnectReason::AppQuit);
+        }
+
+        // a write loop drops its end of the channel once the stream is closed
+        let write_loops_exited =
+            futures::future::join_all(clients.iter().map(|client| client.tx.closed()));
+
+        if tokio::time::timeout(timeout, write_loops_exited)
+            .await
+            .is_err()
+        {
+            tracing::warn!("close endpoint clients timeout");
+        }
+
+        clients.len()
+    }
+
+    /// The encode latency of the connections sending video.
+    pub(crate) fn encode_latencies(&self) -> Vec<(EndPointID, EncodeLatency)> {
+        self.clients
+            .iter()
+            .filter_map(|entry| entry.value().upgrade())
+            .filter(|client| !client.is_closed())
+            .filter_map(|client| {
+                client
+                    .encode_timings
+                    .latency()
+                    .map(|latency| (client.endpoint_id, latency))
+            })
+            .collect()
+    }
+}
+
+/// Closes every connection of this process, for quitting the app. Waits at
+/// most `timeout` for the streams to close, returns how many were open.
+pub async fn close_all_clients(timeout: Duration) -> usize {
+    OPEN_CLIENTS.close_all(timeout).await
+}
+
+/// Encode latency of the recent frames of every connection this process
+/// sends video over, by the endpoint id of the connection.
+pub fn encode_latencies() -> Vec<(EndPointID, EncodeLatency)> {
+    OPEN_CLIENTS.encode_latencies()
+}
+
+/// Closes the connection once the write loop is gone, e.g. after the
+/// transport failed a send, instead of failing every later send on its own.
+/// Pending calls fail right away either way the connection closes, a
+/// cached client reconnects on its next use.
+pub(crate) fn watch_outgoing_channel(
+    endpoint_id: EndPointID,
+    tx: Sender<Vec<u8>>,
+    close_token: CancellationToken,
+    call_store: Arc<CallStore>,
+) {
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = close_token.cancelled() => {}
+            _ = tx.closed() => {
+                tracing::error!(?endpoint_id, "write loop exited, close connection");
+                close_token.cancel();
+            }
+        }
+
+        call_store.close();
+    });
+}
+
 fn handle_message(
     client: Arc<EndPointClient>,
     mut rx: tokio::sync::mpsc::Receiver<Bytes>,
-    video_frame_tx: Option<Sender<EndPointVideoFrame>>,
-    audio_frame_tx: Option<Sender<EndPointAudioFrame>>,
+    mut video_frame_tx: Option<Sender<VideoDecodeMessage>>,
+    mut audio_frame_tx: Option<Sender<EndPointAudioFrame>>,
+    session_permit: Option<IncomingSessionPermit>,
+    fec_level: u8,
 ) {
     tokio::spawn(async move {
+        // the incoming session slot is freed once the message loop exits
+        let _session_permit = session_permit;
+        let mut fec_decoder = FecDecoder::new(fec_level);
+        let mut slice_assembler = SliceAssembler::default();
+
+        // the decoder opens with the codec the viewer negotiated
+        if let (Some(tx), Some(video_codec)) = (&video_frame_tx, client.video_codec()) {
+            let _ = tx.send(VideoDecodeMessage::Codec(video_codec)).await;
+        }
+
         loop {
-            let buffer = match rx.recv().await {
-                Some(buffer) => buffer,
-                None => {
-                    tracing::info!("message handle channel is closed");
+            let buffer = tokio::select! {
+                _ = client.close_token.cancelled() => {
+                    tracing::info!("endpoint client is closed");
                     break;
                 }
+                buffer = rx.recv() => match buffer {
+                    Some(buffer) => buffer,
+                    None => {
+                        tracing::info!("message handle channel is closed");
+                        break;
+                    }
+                }
             };
 
-            let message = match bincode_deserialize(&buffer) {
-                Ok(message) => message,
+            let message = match deserialize_packet(&buffer) {
+                Ok(message) => {
+                    client.stats.record_success();
+                    message
+                }
+                Err(CoreError::ProtocolMismatch { version, codec }) => {
+                    // every later packet is in the same format
+                    tracing::error!(
+                        version,
+                        ?codec,
+                        "unsupported packet format, close connection"
+                    );
+                    break;
+                }
                 Err(err) => {
                     tracing::error!(?err, "deserialize endpoint message failed");
-                    continue;
+                    report_error_event(
+                        ErrorSource::MessageDeserialize,
+                        &client.endpoint_id,
+                        &err.to_string(),
+                    );
+                    if client.stats.record_deserialize_failure() {
+                        continue;
+                    }
+
+                    tracing::error!("too many broken messages, close connection");
+                    break;
                 }
             };
 
+            client.record_activity(&message);
+
             match message {
-                EndPointMessage::Error => {
-                    // handle_error(active_device_id, passive_device_id);
+                EndPointMessage::Error { reason } => {
+                    tracing::error!(?reason, "remote endpoint reported media error");
+                    if let Ok(mut media_error) = client.media_error.lock() {
+                        *media_error = Some(reason);
+                    }
                 }
                 EndPointMessage::NegotiateDesktopParamsRequest(req) => {
+                    if !client.may_share_desktop() {
+                        tracing::warn!("drop negotiate request without an agreed swap");
+                        continue;
+                    }
+
                     handle_negotiate_desktop_params_request(client.clone(), req).await
                 }
-                EndPointMessage::NegotiateDesktopParamsResponse(_) => {
-                    // this message should not received at handle_message loop because it already handled
-                    // at negotiate stage from active endpoint
+                EndPointMessage::NegotiateDesktopParamsResponse(resp) => {
+                    // the first negotiation is done before this loop starts,
+                    // only a swapped session gets its reply here
+                    let Some((media_config, sinks)) = client.swap_state().viewer.take() else {
+                        tracing::warn!("unexpected negotiate reply");
+                        continue;
+                    };
+
+                    let params = match resp {
+                        EndPointNegotiateDesktopParamsResponse::Params(params) => params,
+                        EndPointNegotiateDesktopParamsResponse::VideoError(err) => {
+                            tracing::error!(?err, "swap negotiate failed with video error");
+                            if let Ok(mut media_error) = client.media_error.lock() {
+                                *media_error = Some(MediaErrorReason::CodecUnsupported);
+                            }
+                            continue;
+                        }
+                        EndPointNegotiateDesktopParamsResponse::MonitorError(err) => {
+                            tracing::error!(?err, "swap negotiate failed with display error");
+                            if let Ok(mut media_error) = client.media_error.lock() {
+                                *media_error = Some(MediaErrorReason::NoDisplay);
+                            }
+                            continue;
+                        }
+                    };
+
+                    tracing::info!(?params, "swap negotiate success");
+
+                    let media_config = fit_media_config(
+                        media_config,
+                        &params.primary_monitor,
+                        &params.media_limits,
+                    );
+
+                    if let Some(session_token) = params.session_token {
+                        session_tokens().remember(client.endpoint_id, session_token);
+                    }
+
+                    client.set_monitor(params.primary_monitor).await;
+                    client.set_video_codec(params.video_codec.clone());
+
+                    if let Some(sinks) = sinks {
+                        video_frame_tx = Some(sinks.video_frame_tx);
+                        audio_frame_tx = Some(sinks.audio_frame_tx);
+                        client.has_decode_sinks.store(true, Ordering::Relaxed);
+                    }
+
+                    if let Some(ref tx) = video_frame_tx {
+                        let _ = tx.send(VideoDecodeMessage::Codec(params.video_codec)).await;
+                    }
+
+                    fec_decoder = FecDecoder::new(media_config.fec_level);
+                    slice_assembler = SliceAssembler::default();
+                    client
+                        .include_cursor
+                        .store(media_config.include_cursor, Ordering::Relaxed);
+                    client.store_content_hint(media_config.content_hint);
+                    spawn_idle_watcher(client.clone(), SessionRole::Viewer);
+                    spawn_rtt_prober(client.clone());
+                    spawn_network_reporter(client.clone());
+
+                    if let Err(err) = client
+                        .send(&EndPointMessage::NegotiateFinishedRequest(
+                            EndPointNegotiateFinishedRequest { media_config },
+                        ))
+                        .await
+                    {
+                        tracing::error!(?err, "send negotiate finished failed");
+                        break;
+                    }
                 }
-                EndPointMessage::NegotiateFinishedRequest(_) => {
-                    handle_negotiate_finished_request(client.clone());
+                EndPointMessage::NegotiateFinishedRequest(req) => {
+                    if !client.may_share_desktop() {
+                        tracing::warn!("drop negotiate finished without an agreed swap");
+                        continue;
+                    }
+
+                    // a visit is allowed by its password, a swap by the
+                    // consent given in become_host
+                    if client.role().is_none() {
+                        client.input_allowed.store(true, Ordering::Relaxed);
+                    }
+
+                    client
+                        .include_cursor
+                        .store(req.media_config.include_cursor, Ordering::Relaxed);
+                    client.store_content_hint(req.media_config.content_hint);
+                    // the desktop session is up, the host may chat and swap
+                    // from now on
+                    client.set_role(SessionRole::Host);
+                    chat().attach(client.clone());
+                    clipboard::attach(client.clone());
+                    swap::attach(client.clone());
+                    handle_negotiate_finished_request(client.clone(), req);
                 }
-                EndPointMessage::VideoFrame(video_frame) => {
+                EndPointMessage::VideoFrame(video_frame) => match video_frame_tx {
+                    Some(ref tx) if client.role() == Some(SessionRole::Viewer) => {
+                        client.stats.record_video_frame(video_frame.buffer.len());
+                        let events = fec_decoder.push_frame(video_frame);
+                        charge_reassembly(&client.endpoint_id, &fec_decoder);
+                        if let Err(err) = forward_fec_events(&client.stats, tx, events).await {
+                            tracing::error!(%err, "endpoint video frame message channel send failed");
+                            break;
+                        }
+                    }
+                    // still in flight from before a swap
+                    Some(_) => {}
+                    None => tracing::error!("as passive endpoint, shouldn't receive video frame"),
+                },
+                EndPointMessage::VideoFrameSlice(slice) => {
+                    if let (Some(ref tx), Some(SessionRole::Viewer)) =
+                        (&video_frame_tx, client.role())
+                    {
+                        let Some(video_frame) = slice_assembler.push(slice) else {
+                            continue;
+                        };
+
+                        client.stats.record_video_frame(video_frame.buffer.len());
+                        let events = fec_decoder.push_frame(video_frame);
+                        charge_reassembly(&client.endpoint_id, &fec_decoder);
+                        if let Err(err) = forward_fec_events(&client.stats, tx, events).await {
+                            tracing::error!(%err, "endpoint video frame message channel send failed");
+                            break;
+                        }
+                    }
+                }
+                EndPointMessage::VideoFrameParity(parity) => {
+                    if let (Some(ref tx), Some(SessionRole::Viewer)) =
+                        (&video_frame_tx, client.role())
+                    {
+                        client.stats.record_fec_parity(parity.buffer.len());
+                        let events = fec_decoder.push_parity(parity);
+                        charge_reassembly(&client.endpoint_id, &fec_decoder);
+                        if let Err(err) = forward_fec_events(&client.stats, tx, events).await {
+                            tracing::error!(%err, "endpoint video frame message channel send failed");
+                            break;
+                        }
+                    }
+                }
+                EndPointMessage::DisplayConfigChanged { width, height } => {
+                    if let Some(monitor) = client.monitor().await {
+                        let mut monitor = monitor.as_ref().clone();
+                        monitor.width = width.clamp(0, u16::MAX as i32) as u16;
+                        monitor.height = height.clamp(0, u16::MAX as i32) as u16;
+                        client.set_monitor(monitor).await;
+                    }
+
                     if let Some(ref tx) = video_frame_tx {
-                        if let Err(err) = tx.send(video_frame).await {
+                        let message = VideoDecodeMessage::DisplayConfigChanged { width, height };
+                        if let Err(err) = tx.send(message).await {
                             tracing::error!(%err, "endpoint video frame message channel send failed");
-                            return;
+                            break;
                         }
-                    } else {
-                        tracing::error!("as passive endpoint, shouldn't receive video frame");
                     }
                 }
-                EndPointMessage::AudioFrame(audio_frame) => {
-                    if let Some(ref tx) = audio_frame_tx {
+                EndPointMessage::AudioFrame(audio_frame) => match audio_frame_tx {
+                    Some(ref tx) if client.role() == Some(SessionRole::Viewer) => {
                         if let Err(err) = tx.send(audio_frame).await {
                             tracing::error!(%err, "endpoint audio frame message channel send failed");
-                            return;
+                            break;
                         }
-                    } else {
-                        tracing::error!("as passive endpoint, shouldn't receive audio frame");
                     }
-                }
+                    Some(_) => {}
+                    None => tracing::error!("as passive endpoint, shouldn't receive audio frame"),
+                },
                 EndPointMessage::InputCommand(input_event) => {
-                    handle_input(client.clone(), input_event).await
+                    // a device that swapped to sharing is only controlled
+                    // with the consent of its user
+                    if client.role() == Some(SessionRole::Host) && client.input_allowed() {
+                        handle_input(client.clone(), input_event).await
+                    }
                 }
                 EndPointMessage::CallRequest(call_id, message) => {
                     let client = client.clone();
@@ -372,11 +1627,14 @@ fn handle_message(
                                 call!(handle_visit_directory_request(req).await)
                             }
                             EndPointCallRequest::SendFileRequest(req) => {
-                                call!(handle_send_file_request(req).await)
+                                call!(handle_send_file_request(client.clone(), req).await)
                             }
                             EndPointCallRequest::DownloadFileRequest(req) => {
                                 call!(handle_download_file_request(client.clone(), req).await)
                             }
+                            EndPointCallRequest::ListDirectoryRequest(req) => {
+                                call!(handle_list_directory_request(req).await)
+                            }
                         };
 
                         match reply {
@@ -396,11 +1654,7 @@ fn handle_message(
                 }
                 EndPointMessage::CallReply(call_id, reply) => {
                     tracing::info!(?call_id, "receive call reply");
-                    if let Some(tx) = client.call_store.get(&call_id) {
-                        let _ = tx.send(reply).await;
-                    }
-
-                    client.call_store.invalidate(&call_id)
+                    client.call_store.reply(call_id, reply);
                 }
                 EndPointMessage::FileTransferBlock(block) => {
                     append_file_block(client.clone(), block).await
@@ -408,9 +1662,231 @@ fn handle_message(
                 EndPointMessage::FileTransferError(message) => {
                     delete_file_append_session(&message.id).await
                 }
+                EndPointMessage::FileTransferCancel(message) => {
+                    cancel_file_transfer(&message.id, message.reason, message.keep_partial).await;
+                }
+                EndPointMessage::FileTransferAck(ack) => ack_file_block(ack),
+                EndPointMessage::PauseMedia => {
+                    tracing::info!("remote endpoint paused media");
+                    client.media_pause.pause();
+                }
+                EndPointMessage::ResumeMedia => {
+                    tracing::info!("remote endpoint resumed media");
+                    client.media_pause.resume();
+                }
+                EndPointMessage::RequestKeyFrame => {
+                    tracing::info!("remote endpoint requested keyframe");
+                    client.media_pause.request_keyframe();
+                }
+                EndPointMessage::VideoParameterSets(parameter_sets) => {
+                    if let (Some(ref tx), Some(SessionRole::Viewer)) =
+                        (&video_frame_tx, client.role())
+                    {
+                        let message = VideoDecodeMessage::ParameterSets(parameter_sets);
+                        if let Err(err) = tx.send(message).await {
+                            tracing::error!(%err, "endpoint video frame message channel send failed");
+                            break;
+                        }
+                    }
+                }
+                EndPointMessage::KeepAlive => {}
+                EndPointMessage::VideoUnchanged => {
+                    if client.role() == Some(SessionRole::Viewer) {
+                        client.stats.record_video_unchanged();
+                    }
+                }
+                EndPointMessage::IncludeCursor(include_cursor) => {
+                    tracing::info!(?include_cursor, "remote endpoint toggled cursor");
+                    client
+                        .include_cursor
+                        .store(include_cursor, Ordering::Relaxed);
+                }
+                EndPointMessage::ContentHint(content_hint) => {
+                    tracing::info!(?content_hint, "remote endpoint changed content hint");
+                    client.store_content_hint(content_hint);
+                }
+                EndPointMessage::ContentModeChanged(content_mode) => {
+                    tracing::info!(?content_mode, "remote endpoint changed content mode");
+                    *client
+                        .content_mode
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner) = content_mode;
+                }
+                EndPointMessage::EncoderGraphicsCard(graphics_card) => {
+                    tracing::info!(?graphics_card, "remote endpoint changed encoder");
+                    client.set_encoder_graphics_card(graphics_card);
+                }
+                EndPointMessage::CaptureModeChanged(capture_mode) => {
+                    tracing::info!(?capture_mode, "remote endpoint changed capture mode");
+                    *client
+                        .capture_mode
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner) = Some(capture_mode);
+                }
+                EndPointMessage::NetworkReport(report) => {
+                    *client
+                        .network_report
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner) = Some(report);
+                }
+                EndPointMessage::Disconnect { reason } => {
+                    *client
+                        .remote_disconnect
+                        .lock()
+                        .unwrap_or_else(PoisonError::into_inner) = Some(reason);
+                    disconnect::report_remote_disconnect(client.endpoint_id, reason);
+                    break;
+                }
+                EndPointMessage::VisitFailure(reason) => {
+                    tracing::error!(?reason, "remote endpoint turned the visit down");
+                    break;
+                }
+                EndPointMessage::ChatMessage { text, timestamp } => {
+                    chat().receive(client.endpoint_id, text, timestamp)
+                }
+                EndPointMessage::ClipboardUpdate { content } => {
+                    clipboard::receive(client.endpoint_id, content)
+                }
+                EndPointMessage::RttProbe(timestamp) => {
+                    if let Err(err) = client.try_send(&EndPointMessage::RttProbeReply(timestamp)) {
+                        tracing::warn!(?err, "reply rtt probe failed");
+                    }
+                }
+                EndPointMessage::RttProbeReply(timestamp) => {
+                    client.stats.record_rtt(rtt_probe_elapsed(timestamp))
+                }
+                EndPointMessage::RekeyRequest(request) => {
+                    let Some(ref key_rotation) = client.key_rotation else {
+                        tracing::warn!("rekey request on a plain connection");
+                        continue;
+                    };
+
+                    let reply = match key_rotation.accept(&request) {
+                        Ok(Some(reply)) => reply,
+                        Ok(None) => {
+                            tracing::info!("rekey request crossed ours, ignore it");
+                            continue;
+                        }
+                        Err(err) => {
+                            tracing::error!(?err, "accept rekey request failed");
+                            break;
+                        }
+                    };
+
+                    if let Err(err) = client.send(&EndPointMessage::RekeyReply(reply)).await {
+                        tracing::error!(?err, "send rekey reply failed");
+                        break;
+                    }
+
+                    if let Err(err) = client.send(&EndPointMessage::RekeySwitch).await {
+                        tracing::error!(?err, "send rekey switch failed");
+                        break;
+                    }
+
+                    client.stats.reset_link_stats();
+                }
+                EndPointMessage::RekeyReply(reply) => {
+                    let Some(ref key_rotation) = client.key_rotation else {
+                        tracing::warn!("rekey reply on a plain connection");
+                        continue;
+                    };
+
+                    // the remote side switched right after the reply, the
+                    // connection is broken without the new keys
+                    let done_tx = match key_rotation.finish(&reply) {
+                        Ok(done_tx) => done_tx,
+                        Err(err) => {
+                            tracing::error!(?err, "finish rekey failed");
+                            break;
+                        }
+                    };
+
+                    if let Err(err) = client.send(&EndPointMessage::RekeySwitch).await {
+                        tracing::error!(?err, "send rekey switch failed");
+                        break;
+                    }
+
+                    let _ = done_tx.send(());
+                }
+                // the read loop swapped the keys already
+                EndPointMessage::RekeySwitch => {}
+                EndPointMessage::SwapDirectionRequest => match client.role() {
+                    Some(role) if client.swap_state().outgoing.is_none() => {
+                        client.swap_state().incoming = Some(Instant::now());
+                        report_swap_request(SwapRequestEvent {
+                            remote: remote_label(&client.endpoint_id),
+                            share_screen: role == SessionRole::Viewer,
+                        });
+                    }
+                    _ => {
+                        // no desktop session yet, or both sides asked at once
+                        tracing::warn!("decline swap request");
+                        let reply = EndPointMessage::SwapDirectionReply { accepted: false };
+                        if let Err(err) = client.try_send(&reply) {
+                            tracing::warn!(?err, "decline swap request failed");
+                        }
+                    }
+                },
+                EndPointMessage::Capabilities(capabilities) => {
+                    client.store_capabilities(capabilities)
+                }
+                EndPointMessage::SwapDirectionReply { accepted } => {
+                    let Some((allow_input, reply_tx)) = client.swap_state().outgoing.take() else {
+                        tracing::warn!("swap reply without request");
+                        continue;
+                    };
+
+                    // host before the request returns, the remote side asks
+                    // for the media right away
+                    if accepted && client.role() == Some(SessionRole::Viewer) {
+                        client.become_host(allow_input);
+                    }
+
+                    let _ = reply_tx.send(accepted);
+                }
             }
         }
 
+        // shut the connection down, the session can't work without this loop
+        client.close();
+        chat().detach(&client);
+        clipboard::detach(&client);
+        swap::detach(&client);
+
+        cancel_endpoint_file_transfers(client.endpoint_id).await;
+
         tracing::info!("message handle loop exit");
     });
 }
+
+/// Frames held for reordering count against the media budget of the
+/// session watching the endpoint.
+fn charge_reassembly(endpoint_id: &EndPointID, fec_decoder: &FecDecoder) {
+    if let Some(budget) = media_budget(endpoint_id) {
+        budget.set_reassembly_bytes(fec_decoder.held_bytes());
+    }
+}
+
+/// Passes the frames restored to order on to the decoder.
+async fn forward_fec_events(
+    stats: &EndPointStats,
+    tx: &Sender<VideoDecodeMessage>,
+    events: Vec<FecEvent>,
+) -> Result<(), tokio::sync::mpsc::error::SendError<VideoDecodeMessage>> {
+    for event in events {
+        match event {
+            FecEvent::Frame(video_frame) => tx.send(VideoDecodeMessage::Frame(video_frame)).await?,
+            FecEvent::Recovered(seq) => {
+                tracing::info!(?seq, "restore lost video frame from parity");
+                stats.record_fec_recovered();
+            }
+            FecEvent::Lost(frames) => {
+                tracing::warn!(?frames, "video frames lost, wait for next keyframe");
+                stats.record_fec_lost(frames);
+                tx.send(VideoDecodeMessage::FramesLost).await?;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -1,13 +1,20 @@
-use super::RECV_MESSAGE_TIMEOUT;
+use super::{ConnectionContext, RECV_MESSAGE_TIMEOUT};
 use crate::{
     api::endpoint::{
+        call_timeout::{CallOperation, CallTimeouts},
+        disconnect::{is_disconnect, take_disconnect, DISCONNECT_FLUSH_TIMEOUT},
+        handshake::{check_handshake_response, handshake_device_ids, HandshakeFailure},
+        handshake_token::handshake_tokens,
         id::EndPointID,
-        message::{EndPointHandshakeRequest, EndPointHandshakeResponse},
+        message::EndPointHandshakeRequest,
+        packet::frame_codec,
+        rekey::{is_rekey_switch, RotatingKey},
+        stats::{open_frame, EndPointStats, FrameVerdict},
     },
-    core_error,
     error::{CoreError, CoreResult},
     utility::{
-        bincode::{bincode_deserialize, bincode_serialize},
+        bincode::bincode_serialize,
+        error_event::{report_error_event, ErrorSource},
         nonce_value::NonceValue,
     },
 };
@@ -17,34 +24,58 @@ use futures::{
     SinkExt, StreamExt,
 };
 use ring::aead::{OpeningKey, SealingKey};
-use std::{net::SocketAddr, ops::Deref};
+use std::{net::SocketAddr, sync::Arc, time::Instant};
 use tokio::{net::UdpSocket, sync::mpsc::Sender};
-use tokio_util::{codec::LengthDelimitedCodec, udp::UdpFramed};
+use tokio_util::{codec::LengthDelimitedCodec, sync::CancellationToken, udp::UdpFramed};
 
 pub async fn serve_udp(
     socket: UdpSocket,
     endpoint_id: EndPointID,
-    sealing_key: Option<SealingKey<NonceValue>>,
-    opening_key: Option<OpeningKey<NonceValue>>,
+    sealing_key: Option<RotatingKey<SealingKey<NonceValue>>>,
+    opening_key: Option<RotatingKey<OpeningKey<NonceValue>>>,
     mut visit_credentials: Option<Vec<u8>>,
+    context: ConnectionContext,
 ) -> CoreResult<(Sender<Vec<u8>>, tokio::sync::mpsc::Receiver<Bytes>)> {
+    let ConnectionContext {
+        call_timeouts,
+        stats,
+        close_token,
+    } = context;
     let remote_addr = socket.peer_addr()?;
-    let mut framed = UdpFramed::new(
-        socket,
-        LengthDelimitedCodec::builder()
-            .little_endian()
-            .max_frame_length(32 * 1024 * 1024)
-            .new_codec(),
-    );
+    let mut framed = UdpFramed::new(socket, frame_codec());
 
     if let Some(visit_credentials) = visit_credentials.take() {
-        serve_udp_handshake(remote_addr, &mut framed, visit_credentials, endpoint_id).await?;
+        handshake_tokens()
+            .consume(&visit_credentials, Instant::now())
+            .into_result()?;
+        serve_udp_handshake(
+            remote_addr,
+            &mut framed,
+            visit_credentials,
+            endpoint_id,
+            &call_timeouts,
+        )
+        .await?;
     }
 
     let (tx, rx) = tokio::sync::mpsc::channel(32);
     let (sink, stream) = framed.split();
-    serve_udp_write(remote_addr, rx, sealing_key, sink);
-    let rx = serve_udp_read(remote_addr, opening_key, stream)?;
+    serve_udp_write(
+        remote_addr,
+        rx,
+        sealing_key,
+        sink,
+        stats.clone(),
+        close_token.clone(),
+    );
+    let rx = serve_udp_read(
+        endpoint_id,
+        remote_addr,
+        opening_key,
+        stream,
+        stats,
+        close_token,
+    )?;
     Ok((tx, rx))
 }
 
@@ -53,10 +84,9 @@ async fn serve_udp_handshake(
     stream: &mut UdpFramed<LengthDelimitedCodec>,
     visit_credentials: Vec<u8>,
     endpoint_id: EndPointID,
+    call_timeouts: &CallTimeouts,
 ) -> CoreResult<()> {
-    let EndPointID::DeviceID { local_device_id, remote_device_id } = endpoint_id else {
-        return Err(core_error!("lan connection needn't device id"));
-    };
+    let (local_device_id, remote_device_id) = handshake_device_ids(endpoint_id)?;
 
     let handshake_request_buffer = bincode_serialize(&EndPointHandshakeRequest {
         visit_credentials,
@@ -69,35 +99,44 @@ async fn serve_udp_handshake(
         .map_err(|_| CoreError::OutgoingMessageChannelDisconnect)?;
 
     // should we try receive 3 or more times because udp is connect less?
-    let (handshake_response_buffer, response_remote_addr) =
-        tokio::time::timeout(RECV_MESSAGE_TIMEOUT, stream.next())
-            .await
-            .map_err(|_| CoreError::Timeout)?
-            .ok_or(CoreError::OutgoingMessageChannelDisconnect)??;
+    let (handshake_response_buffer, response_remote_addr) = call_timeouts
+        .wait(CallOperation::Handshake, stream.next())
+        .await?
+        .ok_or(HandshakeFailure::Closed)??;
 
     if response_remote_addr != remote_addr {
-        return Err(core_error!("unexpected handshake reply addr"));
-    }
-
-    let resp: EndPointHandshakeResponse = bincode_deserialize(handshake_response_buffer.deref())?;
-
-    if resp.remote_device_id != remote_device_id {
-        return Err(core_error!("endpoints server build mismatch tunnel"));
+        tracing::error!(
+            ?remote_addr,
+            ?response_remote_addr,
+            "unexpected handshake reply addr"
+        );
+        return Err(HandshakeFailure::UnexpectedReplyAddr.into());
     }
 
-    Ok(())
+    check_handshake_response(&handshake_response_buffer, remote_device_id)
 }
 
 fn serve_udp_read(
+    endpoint_id: EndPointID,
     remote_addr: SocketAddr,
-    mut opening_key: Option<OpeningKey<NonceValue>>,
+    mut opening_key: Option<RotatingKey<OpeningKey<NonceValue>>>,
     mut stream: SplitStream<UdpFramed<LengthDelimitedCodec>>,
+    stats: Arc<EndPointStats>,
+    close_token: CancellationToken,
 ) -> CoreResult<tokio::sync::mpsc::Receiver<Bytes>> {
     let (tx, rx) = tokio::sync::mpsc::channel(1);
 
     tokio::spawn(async move {
+        // the stream ending after a disconnect is the remote side closing
+        let mut disconnected = false;
+
         loop {
-            let mut buffer = match stream.next().await {
+            let packet = tokio::select! {
+                _ = close_token.cancelled() => break,
+                packet = stream.next() => packet,
+            };
+
+            let mut buffer = match packet {
                 Some(packet) => match packet {
                     Ok((buffer, addr)) => {
                         if addr != remote_addr {
@@ -106,22 +145,50 @@ fn serve_udp_read(
 
                         buffer
                     }
+                    Err(err) if disconnected => {
+                        tracing::info!(?remote_addr, ?err, "read stream closed after disconnect");
+                        break;
+                    }
                     Err(err) => {
                         tracing::error!(?remote_addr, ?err, "read stream failed");
+                        report_error_event(ErrorSource::Socket, &endpoint_id, &err.to_string());
                         break;
                     }
                 },
+                None if disconnected => {
+                    tracing::info!(?remote_addr, "read stream closed after disconnect");
+                    break;
+                }
                 None => {
                     tracing::error!(?remote_addr, "read stream is closed");
                     break;
                 }
             };
 
+            let opening_key_mut = opening_key.as_mut().map(RotatingKey::key_mut);
+            let buffer_len = match open_frame(opening_key_mut, buffer.as_mut(), &stats) {
+                FrameVerdict::Accept(buffer_len) => buffer_len,
+                FrameVerdict::Skip => {
+                    report_error_event(ErrorSource::PacketOpen, &endpoint_id, "open frame failed");
+                    continue;
+                }
+                FrameVerdict::Close => {
+                    tracing::error!(?remote_addr, "too many broken frames, close connection");
+                    break;
+                }
+            };
+
+            buffer.truncate(buffer_len);
+            stats.record_received(buffer_len);
+            disconnected |= is_disconnect(&buffer);
+
+            // the frames after the switch are sealed with the new keys
             if let Some(ref mut opening_key) = opening_key {
-                if let Err(err) =
-                    opening_key.open_in_place(ring::aead::Aad::empty(), buffer.as_mut())
-                {
-                    tracing::error!(?err, "open endpoint message packet failed");
+                if is_rekey_switch(&buffer) && !opening_key.rotate(RECV_MESSAGE_TIMEOUT).await {
+                    tracing::error!(
+                        ?remote_addr,
+                        "rotated opening key missing, close connection"
+                    );
                     break;
                 }
             }
@@ -141,18 +208,63 @@ fn serve_udp_read(
 fn serve_udp_write(
     remote_addr: SocketAddr,
     mut rx: tokio::sync::mpsc::Receiver<Vec<u8>>,
-    mut sealing_key: Option<SealingKey<NonceValue>>,
+    mut sealing_key: Option<RotatingKey<SealingKey<NonceValue>>>,
     mut sink: SplitSink<UdpFramed<LengthDelimitedCodec>, (Bytes, SocketAddr)>,
+    stats: Arc<EndPointStats>,
+    close_token: CancellationToken,
 ) {
     tokio::spawn(async move {
         loop {
-            match rx.recv().await {
+            let buffer = tokio::select! {
+                biased;
+
+                _ = close_token.cancelled() => {
+                    // a disconnect queued right before closing goes out last
+                    if let Some(mut buffer) = take_disconnect(&mut rx) {
+                        let sealed = match sealing_key {
+                            Some(ref mut sealing_key) => sealing_key
+                                .key_mut()
+                                .seal_in_place_append_tag(ring::aead::Aad::empty(), &mut buffer)
+                                .is_ok(),
+                            None => true,
+                        };
+
+                        if sealed {
+                            let flush = sink.send((Bytes::from(buffer), remote_addr));
+                            if tokio::time::timeout(DISCONNECT_FLUSH_TIMEOUT, flush)
+                                .await
+                                .is_err()
+                            {
+                                tracing::warn!(?remote_addr, "flush disconnect timeout");
+                            }
+                        }
+                    }
+                    break;
+                }
+                buffer = rx.recv() => buffer,
+            };
+
+            match buffer {
                 Some(mut buffer) => {
+                    let buffer_len = buffer.len();
+                    let rekey_switch = is_rekey_switch(&buffer);
+                    let disconnect = is_disconnect(&buffer);
+
                     if let Some(ref mut sealing_key) = sealing_key {
                         if let Err(err) = sealing_key
+                            .key_mut()
                             .seal_in_place_append_tag(ring::aead::Aad::empty(), &mut buffer)
                         {
                             tracing::error!(?err, "seal endpoint message packet failed");
+                            close_token.cancel();
+                            break;
+                        }
+
+                        // the frames after the switch are sealed with the
+                        // new keys, handed over before it was queued
+                        if rekey_switch && !sealing_key.try_rotate() {
+                            tracing::error!(?remote_addr, "rotated sealing key missing");
+                            close_token.cancel();
                             break;
                         }
                     }
@@ -161,6 +273,14 @@ fn serve_udp_write(
                         tracing::error!(?remote_addr, "tcp write failed");
                         break;
                     }
+
+                    stats.record_sent(buffer_len);
+
+                    // nothing queued after a disconnect goes out
+                    if disconnect {
+                        let _ = sink.close().await;
+                        break;
+                    }
                 }
                 None => {
                     tracing::error!(?remote_addr, "input channel closed");
@@ -0,0 +1,284 @@
+use super::client::EndPointClient;
+use crate::{
+    core_error,
+    error::{CoreError, CoreResult},
+};
+use dashmap::DashMap;
+use moka::future::{Cache, CacheBuilder};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// How long a connection is reconnected before it's given up by default.
+pub const DEFAULT_RECONNECT_MAX_DURATION: Duration = Duration::from_secs(60);
+
+static RECONNECT_POLICY: Lazy<RwLock<ReconnectPolicy>> =
+    Lazy::new(|| RwLock::new(ReconnectPolicy::default()));
+
+static CONNECTION_LOST_TX: Lazy<tokio::sync::broadcast::Sender<ConnectionLost>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+pub type ReconnectFuture = Pin<Box<dyn Future<Output = CoreResult<Arc<EndPointClient>>> + Send>>;
+
+/// Connects to the remote device again, e.g. by visiting it once more.
+pub type Reconnect = Arc<dyn Fn() -> ReconnectFuture + Send + Sync>;
+
+/// How a dead connection is reconnected: attempts backing off from
+/// `initial_backoff` to `max_backoff`, until `max_duration` passed since
+/// the first one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub max_duration: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            max_duration: DEFAULT_RECONNECT_MAX_DURATION,
+        }
+    }
+}
+
+/// Applies to reconnects started afterwards.
+pub fn set_reconnect_policy(policy: ReconnectPolicy) {
+    if let Ok(mut reconnect_policy) = RECONNECT_POLICY.write() {
+        *reconnect_policy = policy;
+    }
+}
+
+pub fn reconnect_policy() -> ReconnectPolicy {
+    RECONNECT_POLICY
+        .read()
+        .map(|policy| *policy)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConnectionState {
+    Connected,
+    /// The connection died, it's reconnected when it's used next.
+    Reconnecting,
+    /// Reconnecting gave up, only [`EndPointClientCache::retry`] tries
+    /// again.
+    Closed,
+}
+
+/// A connection whose reconnect attempts ran out, or that needs the
+/// password of the visit again.
+#[derive(Debug, Clone, Serialize)]
+pub struct ConnectionLost {
+    pub key: String,
+    pub attempts: u32,
+    pub elapsed_ms: u64,
+    pub reason: String,
+}
+
+pub fn subscribe_connection_lost() -> tokio::sync::broadcast::Receiver<ConnectionLost> {
+    CONNECTION_LOST_TX.subscribe()
+}
+
+#[derive(Clone)]
+struct CachedClient {
+    client: Arc<EndPointClient>,
+    reconnect: Reconnect,
+    /// Reconnecting gave up.
+    lost: bool,
+}
+
+/// Connections kept by key, a connection that died meanwhile is replaced
+/// with a new one when it's asked for.
+pub struct EndPointClientCache {
+    clients: Cache<String, CachedClient>,
+    reconnecting: DashMap<String, Arc<tokio::sync::Mutex<()>>>,
+    /// Overrides the global [`reconnect_policy`].
+    policy: Option<ReconnectPolicy>,
+}
+
+impl EndPointClientCache {
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            clients: CacheBuilder::new(capacity).build(),
+            reconnecting: DashMap::new(),
+            policy: None,
+        }
+    }
+
+    pub fn with_policy(capacity: u64, policy: ReconnectPolicy) -> Self {
+        Self {
+            policy: Some(policy),
+            ..Self::new(capacity)
+        }
+    }
+
+    pub async fn insert(&self, key: String, client: Arc<EndPointClient>, reconnect: Reconnect) {
+        self.clients
+            .insert(
+                key,
+                CachedClient {
+                    client,
+                    reconnect,
+                    lost: false,
+                },
+            )
+            .await;
+    }
+
+    pub fn state(&self, key: &str) -> Option<ConnectionState> {
+        let cached = self.clients.get(key)?;
+
+        Some(if cached.lost {
+            ConnectionState::Closed
+        } else if cached.client.is_closed() {
+            ConnectionState::Reconnecting
+        } else {
+            ConnectionState::Connected
+        })
+    }
+
+    pub async fn invalidate(&self, key: &str) {
+        self.clients.invalidate(key).await;
+        self.reconnecting.remove(key);
+    }
+
+    /// Forgets every connection, later asks for them fail instead of
+    /// reconnecting.
+    pub fn clear(&self) {
+        self.clients.invalidate_all();
+        self.reconnecting.clear();
+    }
+
+    /// Returns the connection of the key, reconnecting first if it's closed.
+    /// Callers of the same key wait for a single reconnect. Fails right
+    /// away once reconnecting gave up.
+    pub async fn get_or_reconnect(&self, key: &str) -> CoreResult<Arc<EndPointClient>> {
+        self.connect(key, false).await
+    }
+
+    /// Reconnects a connection that was given up, with the full reconnect
+    /// budget again.
+    pub async fn retry(&self, key: &str) -> CoreResult<Arc<EndPointClient>> {
+        self.connect(key, true).await
+    }
+
+    async fn connect(&self, key: &str, retry: bool) -> CoreResult<Arc<EndPointClient>> {
+        if let Some(cached) = self.clients.get(key) {
+            if !cached.client.is_closed() {
+                return Ok(cached.client);
+            }
+
+            if cached.lost && !retry {
+                return Err(core_error!("connection lost, try again"));
+            }
+        }
+
+        let lock = self
+            .reconnecting
+            .entry(key.to_string())
+            .or_default()
+            .clone();
+        let _guard = lock.lock().await;
+
+        // another caller may have reconnected while this one waited
+        let cached = self
+            .clients
+            .get(key)
+            .ok_or_else(|| core_error!("remote file manager not exist"))?;
+
+        if !cached.client.is_closed() {
+            return Ok(cached.client);
+        }
+
+        if cached.lost && !retry {
+            return Err(core_error!("connection lost, try again"));
+        }
+
+        tracing::info!(key, "cached endpoint client is closed, reconnect");
+
+        let policy = self.policy.unwrap_or_else(reconnect_policy);
+        let started_at = Instant::now();
+        let mut backoff = policy.initial_backoff;
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            // an attempt hanging on doesn't stretch the budget either
+            let remaining = policy.max_duration.saturating_sub(started_at.elapsed());
+            let err = match tokio::time::timeout(remaining, (cached.reconnect)()).await {
+                Ok(Ok(client)) => {
+                    self.clients
+                        .insert(
+                            key.to_string(),
+                            CachedClient {
+                                client: client.clone(),
+                                reconnect: cached.reconnect,
+                                lost: false,
+                            },
+                        )
+                        .await;
+
+                    return Ok(client);
+                }
+                // only the user can bring the password, trying again won't
+                Ok(Err(CoreError::ReconnectNeedsPassword)) => {
+                    let err = CoreError::ReconnectNeedsPassword;
+                    self.give_up(key, cached, attempts, started_at.elapsed(), err.to_string())
+                        .await;
+                    return Err(err);
+                }
+                Ok(Err(err)) => err.to_string(),
+                Err(_) => String::from("reconnect timed out"),
+            };
+
+            let elapsed = started_at.elapsed();
+            if elapsed + backoff >= policy.max_duration {
+                self.give_up(key, cached, attempts, elapsed, err).await;
+                return Err(core_error!("connection lost, try again"));
+            }
+
+            tracing::warn!(key, attempts, ?backoff, %err, "reconnect failed, retry");
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(policy.max_backoff);
+        }
+    }
+
+    async fn give_up(
+        &self,
+        key: &str,
+        cached: CachedClient,
+        attempts: u32,
+        elapsed: Duration,
+        reason: String,
+    ) {
+        tracing::error!(key, attempts, ?elapsed, %reason, "reconnect gave up");
+
+        // frees what's left of the old connection
+        cached.client.close();
+        self.clients
+            .insert(
+                key.to_string(),
+                CachedClient {
+                    lost: true,
+                    ..cached
+                },
+            )
+            .await;
+
+        let _ = CONNECTION_LOST_TX.send(ConnectionLost {
+            key: key.to_string(),
+            attempts,
+            elapsed_ms: elapsed.as_millis() as u64,
+            reason,
+        });
+    }
+}
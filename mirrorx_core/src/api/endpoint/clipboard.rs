@@ -0,0 +1,237 @@
+use super::{
+    client::EndPointClient,
+    id::EndPointID,
+    message::{ClipboardContent, EndPointMessage, SessionFeature},
+};
+use crate::{bail_core, core_error, error::CoreResult};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::{
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+/// How often the local clipboard is checked for changes while a session
+/// syncs it.
+pub const CLIPBOARD_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// How long a change has to hold before it's sent, copying several times
+/// in a row only sends the last.
+pub const CLIPBOARD_DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Longest text synced, the clipboard isn't for moving files.
+pub const MAX_CLIPBOARD_TEXT_LEN: usize = 512 * 1024;
+
+static PEERS: Lazy<DashMap<EndPointID, ClipboardPeer>> = Lazy::new(DashMap::new);
+
+/// Content of the remote sides to put into the local clipboard, served by
+/// the thread owning the clipboard.
+static CLIPBOARD_TX: Lazy<Mutex<Sender<ClipboardContent>>> =
+    Lazy::new(|| Mutex::new(spawn_clipboard_thread()));
+
+#[derive(Debug)]
+struct ClipboardPeer {
+    client: Arc<EndPointClient>,
+    enabled: bool,
+}
+
+/// Tells the changes of the local clipboard to send apart from the content
+/// the remote side put there.
+#[derive(Debug, Default)]
+pub struct ClipboardTracker {
+    synced: Option<u64>,
+    pending: Option<(u64, Instant)>,
+}
+
+impl ClipboardTracker {
+    /// Takes the hash of the local clipboard, true once a change held for
+    /// the debounce and is to be sent.
+    pub fn observe(&mut self, hash: u64, now: Instant) -> bool {
+        if self.synced == Some(hash) {
+            self.pending = None;
+            return false;
+        }
+
+        match self.pending {
+            Some((pending, since)) if pending == hash => {
+                if now.duration_since(since) < CLIPBOARD_DEBOUNCE {
+                    return false;
+                }
+
+                self.synced = Some(hash);
+                self.pending = None;
+                true
+            }
+            _ => {
+                self.pending = Some((hash, now));
+                false
+            }
+        }
+    }
+
+    /// The content came from the remote side, it isn't sent back.
+    pub fn applied(&mut self, hash: u64) {
+        self.synced = Some(hash);
+        self.pending = None;
+    }
+}
+
+pub fn clipboard_content_hash(content: &ClipboardContent) -> u64 {
+    fxhash::hash64(content)
+}
+
+pub(crate) fn attach(client: Arc<EndPointClient>) {
+    // a new session starts without sync, the user turns it on
+    PEERS.insert(
+        client.endpoint_id(),
+        ClipboardPeer {
+            client,
+            enabled: false,
+        },
+    );
+}
+
+pub(crate) fn detach(client: &Arc<EndPointClient>) {
+    PEERS.remove_if(&client.endpoint_id(), |_, peer| {
+        Arc::ptr_eq(&peer.client, client)
+    });
+}
+
+/// Desktop sessions of either side that can sync the clipboard.
+pub fn clipboard_endpoint_ids() -> Vec<EndPointID> {
+    PEERS.iter().map(|entry| *entry.key()).collect()
+}
+
+/// Turns the clipboard sync of a session on or off. While on, the changes
+/// of the local clipboard go to the remote side and those of the remote
+/// side land in the local clipboard, the remote side syncs only if its
+/// user turned it on too.
+pub fn set_clipboard_sync(endpoint_id: &EndPointID, enabled: bool) -> CoreResult<()> {
+    let mut peer = PEERS
+        .get_mut(endpoint_id)
+        .ok_or_else(|| core_error!("desktop session not exist"))?;
+
+    if enabled && !peer.client.supports(SessionFeature::Clipboard) {
+        bail_core!("the remote device can't sync the clipboard");
+    }
+
+    peer.enabled = enabled;
+    drop(peer);
+
+    if enabled {
+        Lazy::force(&CLIPBOARD_TX);
+    }
+
+    tracing::info!(?endpoint_id, enabled, "set clipboard sync");
+    Ok(())
+}
+
+pub fn clipboard_sync(endpoint_id: &EndPointID) -> bool {
+    PEERS.get(endpoint_id).is_some_and(|peer| peer.enabled)
+}
+
+pub(crate) fn receive(endpoint_id: EndPointID, content: ClipboardContent) {
+    if !clipboard_sync(&endpoint_id) {
+        tracing::debug!(?endpoint_id, "clipboard sync is off, drop clipboard update");
+        return;
+    }
+
+    if let Err(err) = validate_clipboard_content(&content) {
+        tracing::warn!(?endpoint_id, ?err, "drop clipboard update");
+        return;
+    }
+
+    let tx = CLIPBOARD_TX.lock().unwrap_or_else(PoisonError::into_inner);
+    if tx.send(content).is_err() {
+        tracing::warn!("clipboard thread exited, drop clipboard update");
+    }
+}
+
+fn validate_clipboard_content(content: &ClipboardContent) -> CoreResult<()> {
+    match content {
+        ClipboardContent::Text(text) if text.is_empty() => {
+            Err(core_error!("clipboard text is empty"))
+        }
+        ClipboardContent::Text(text) if text.len() > MAX_CLIPBOARD_TEXT_LEN => Err(core_error!(
+            "clipboard text is too long (max {} bytes)",
+            MAX_CLIPBOARD_TEXT_LEN
+        )),
+        ClipboardContent::Text(_) => Ok(()),
+    }
+}
+
+/// The clipboard is owned by one thread for the life of the app, some
+/// platforms lose the content put there once its owner goes away.
+fn spawn_clipboard_thread() -> Sender<ClipboardContent> {
+    let (tx, rx) = std::sync::mpsc::channel();
+
+    if let Err(err) = std::thread::Builder::new()
+        .name(String::from("clipboard"))
+        .spawn(move || serve_clipboard(rx))
+    {
+        tracing::error!(?err, "spawn clipboard thread failed");
+    }
+
+    tx
+}
+
+fn serve_clipboard(rx: Receiver<ClipboardContent>) {
+    let mut clipboard = match arboard::Clipboard::new() {
+        Ok(clipboard) => clipboard,
+        Err(err) => {
+            tracing::error!(?err, "open clipboard failed");
+            return;
+        }
+    };
+
+    let mut tracker = ClipboardTracker::default();
+
+    loop {
+        match rx.recv_timeout(CLIPBOARD_POLL_INTERVAL) {
+            Ok(content) => {
+                tracker.applied(clipboard_content_hash(&content));
+
+                let ClipboardContent::Text(text) = content;
+                if let Err(err) = clipboard.set_text(text) {
+                    tracing::warn!(?err, "set clipboard failed");
+                }
+
+                continue;
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+
+        if !PEERS.iter().any(|peer| peer.enabled) {
+            continue;
+        }
+
+        // the clipboard holds something other than text, or nothing
+        let Ok(text) = clipboard.get_text() else {
+            continue;
+        };
+
+        let content = ClipboardContent::Text(text);
+        if tracker.observe(clipboard_content_hash(&content), Instant::now()) {
+            broadcast(content);
+        }
+    }
+}
+
+fn broadcast(content: ClipboardContent) {
+    if let Err(err) = validate_clipboard_content(&content) {
+        tracing::warn!(?err, "clipboard not synced");
+        return;
+    }
+
+    let message = EndPointMessage::ClipboardUpdate { content };
+
+    for peer in PEERS.iter().filter(|peer| peer.enabled) {
+        if let Err(err) = peer.client.try_send(&message) {
+            tracing::warn!(endpoint_id = ?peer.key(), ?err, "send clipboard update failed");
+        }
+    }
+}
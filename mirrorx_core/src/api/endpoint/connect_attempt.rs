@@ -0,0 +1,98 @@
+use crate::error::{CoreError, CoreResult};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::{
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio_util::sync::CancellationToken;
+
+static CONNECT_ATTEMPTS: Lazy<ConnectAttempts> = Lazy::new(ConnectAttempts::default);
+
+pub fn connect_attempts() -> &'static ConnectAttempts {
+    &CONNECT_ATTEMPTS
+}
+
+/// Connections being made, by the device they go to, so the user can abort
+/// one that hangs, e.g. on a peer behind a firewall.
+#[derive(Debug, Default)]
+pub struct ConnectAttempts {
+    next_id: AtomicU64,
+    attempts: DashMap<String, (u64, CancellationToken)>,
+}
+
+impl ConnectAttempts {
+    /// Starts an attempt to `target`, an attempt to it still running is
+    /// cancelled. The attempt is forgotten once dropped.
+    pub fn begin(&self, target: &str) -> ConnectAttempt<'_> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let token = CancellationToken::new();
+
+        if let Some((_, previous)) = self
+            .attempts
+            .insert(target.to_string(), (id, token.clone()))
+        {
+            previous.cancel();
+        }
+
+        ConnectAttempt {
+            attempts: self,
+            target: target.to_string(),
+            id,
+            token,
+        }
+    }
+
+    /// Aborts the attempt to `target`, false when there's none.
+    pub fn cancel(&self, target: &str) -> bool {
+        match self.attempts.remove(target) {
+            Some((_, (_, token))) => {
+                tracing::info!(?target, "cancel connect attempt");
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_pending(&self, target: &str) -> bool {
+        self.attempts.contains_key(target)
+    }
+}
+
+pub struct ConnectAttempt<'a> {
+    attempts: &'a ConnectAttempts,
+    target: String,
+    id: u64,
+    token: CancellationToken,
+}
+
+impl ConnectAttempt<'_> {
+    /// Runs a step of the attempt, it fails with [`CoreError::Cancelled`]
+    /// right away once the attempt is cancelled.
+    pub async fn run<T>(&self, step: impl Future<Output = CoreResult<T>>) -> CoreResult<T> {
+        cancellable(&self.token, step).await
+    }
+}
+
+impl Drop for ConnectAttempt<'_> {
+    fn drop(&mut self) {
+        // a newer attempt to the same target may have taken the slot
+        self.attempts
+            .attempts
+            .remove_if(&self.target, |_, (id, _)| *id == self.id);
+    }
+}
+
+/// Runs `future` until `token` is cancelled. The future is dropped then,
+/// the sockets and tasks it opened so far close with it.
+pub async fn cancellable<T>(
+    token: &CancellationToken,
+    future: impl Future<Output = CoreResult<T>>,
+) -> CoreResult<T> {
+    tokio::select! {
+        biased;
+        _ = token.cancelled() => Err(CoreError::Cancelled),
+        result = future => result,
+    }
+}
@@ -0,0 +1,172 @@
+use super::{
+    id::EndPointID,
+    socket_buffer::{apply_socket_buffers, SocketBuffers},
+};
+use crate::{
+    error::{CoreError, CoreResult},
+    utility::config_cell::ConfigCell,
+};
+use serde::{Deserialize, Serialize};
+use socket2::SockRef;
+use std::{net::SocketAddr, time::Duration};
+use tokio::net::{TcpSocket, TcpStream};
+
+/// How long a single connect attempt may take.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const MAX_CONNECT_ATTEMPTS: u32 = 10;
+
+static CONNECT_RETRY_CONFIG: ConfigCell<ConnectRetryConfig> =
+    ConfigCell::new(ConnectRetryConfig::default);
+
+/// Attempts of a connect and the backoff between them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    pub base_delay_ms: u64,
+    pub max_delay_ms: u64,
+    /// Attempts including the first.
+    pub attempts: u32,
+}
+
+impl RetryPolicy {
+    /// A LAN device answers at once or is gone, there's little to wait for.
+    pub const LAN: RetryPolicy = RetryPolicy {
+        base_delay_ms: 100,
+        max_delay_ms: 1000,
+        attempts: 3,
+    };
+
+    /// Rides out a WiFi that drops for a few seconds.
+    pub const INTERNET: RetryPolicy = RetryPolicy {
+        base_delay_ms: 500,
+        max_delay_ms: 8000,
+        attempts: 5,
+    };
+
+    pub fn sanitize(self) -> Self {
+        Self {
+            base_delay_ms: self.base_delay_ms,
+            max_delay_ms: self.max_delay_ms.max(self.base_delay_ms),
+            attempts: self.attempts.clamp(1, MAX_CONNECT_ATTEMPTS),
+        }
+    }
+
+    /// Delay before the `retry`th retry, counting from 1: the base delay
+    /// doubled per retry up to the max delay, less up to half of it by
+    /// `jitter` in `[0, 1)`. Devices that lost the same network don't come
+    /// back in lockstep then.
+    pub fn delay(&self, retry: u32, jitter: f64) -> Duration {
+        let doublings = retry.saturating_sub(1).min(32);
+        let delay_ms = self
+            .base_delay_ms
+            .saturating_mul(1u64 << doublings)
+            .min(self.max_delay_ms);
+
+        Duration::from_millis(delay_ms).mul_f64(1.0 - jitter.clamp(0.0, 1.0) / 2.0)
+    }
+}
+
+/// Retry policies of LAN connections and of connections through the
+/// endpoints server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectRetryConfig {
+    pub lan: RetryPolicy,
+    pub internet: RetryPolicy,
+}
+
+impl Default for ConnectRetryConfig {
+    fn default() -> Self {
+        Self {
+            lan: RetryPolicy::LAN,
+            internet: RetryPolicy::INTERNET,
+        }
+    }
+}
+
+impl ConnectRetryConfig {
+    pub fn sanitize(self) -> Self {
+        Self {
+            lan: self.lan.sanitize(),
+            internet: self.internet.sanitize(),
+        }
+    }
+
+    pub fn policy(&self, endpoint_id: &EndPointID) -> RetryPolicy {
+        match endpoint_id {
+            EndPointID::LANID { .. } => self.lan,
+            EndPointID::DeviceID { .. } => self.internet,
+        }
+    }
+}
+
+pub fn set_connect_retry_config(config: ConnectRetryConfig) {
+    CONNECT_RETRY_CONFIG.set(config.sanitize());
+}
+
+pub fn connect_retry_config() -> ConnectRetryConfig {
+    CONNECT_RETRY_CONFIG.get()
+}
+
+/// Opens the TCP connection to `addr`, retrying failed attempts with
+/// backoff. Nothing is sent before the connection is up, so the visit
+/// credentials and the keys of the session are as good for the next
+/// attempt as for the first. Fails with
+/// [`CoreError::ConnectAttemptsExhausted`] once the attempts are used up.
+pub async fn connect_with_retry(
+    addr: SocketAddr,
+    endpoint_id: &EndPointID,
+    policy: RetryPolicy,
+) -> CoreResult<(TcpStream, SocketBuffers)> {
+    let policy = policy.sanitize();
+    let mut reason = String::new();
+
+    for attempt in 1..=policy.attempts {
+        if attempt > 1 {
+            tokio::time::sleep(policy.delay(attempt - 1, rand::random())).await;
+        }
+
+        match connect(addr, endpoint_id).await {
+            Ok(connected) => {
+                tracing::info!(?endpoint_id, %addr, attempt, "endpoint connected");
+                return Ok(connected);
+            }
+            Err(err) => {
+                tracing::warn!(
+                    ?endpoint_id,
+                    %addr,
+                    attempt,
+                    attempts = policy.attempts,
+                    ?err,
+                    "connect endpoint failed"
+                );
+                reason = err.to_string();
+            }
+        }
+    }
+
+    Err(CoreError::ConnectAttemptsExhausted {
+        attempts: policy.attempts,
+        reason,
+    })
+}
+
+async fn connect(
+    addr: SocketAddr,
+    endpoint_id: &EndPointID,
+) -> CoreResult<(TcpStream, SocketBuffers)> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+
+    // the window scale is agreed on in the handshake, so the buffers are
+    // sized before
+    let socket_buffers = apply_socket_buffers(SockRef::from(&socket), endpoint_id);
+
+    let stream = tokio::time::timeout(CONNECT_TIMEOUT, socket.connect(addr))
+        .await
+        .map_err(|_| CoreError::Timeout)??;
+
+    Ok((stream, socket_buffers))
+}
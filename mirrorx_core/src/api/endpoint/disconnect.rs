@@ -0,0 +1,60 @@
+use super::{
+    id::EndPointID,
+    message::{DisconnectReason, EndPointMessage},
+    packet::deserialize_packet,
+};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::time::Duration;
+use tokio::sync::mpsc::Receiver;
+
+/// How long a closing connection waits for its `Disconnect` to go out
+/// before closing the stream anyway.
+pub const DISCONNECT_FLUSH_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Longer frames aren't a `Disconnect`, the loops don't deserialize video
+/// frames to find it.
+const MAX_DISCONNECT_PACKET_LEN: usize = 16;
+
+/// The remote side closed a connection on purpose.
+#[derive(Debug, Clone, Serialize)]
+pub struct RemoteDisconnectEvent {
+    pub endpoint_id: String,
+    pub reason: DisconnectReason,
+}
+
+static REMOTE_DISCONNECT_TX: Lazy<tokio::sync::broadcast::Sender<RemoteDisconnectEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+pub fn subscribe_remote_disconnect() -> tokio::sync::broadcast::Receiver<RemoteDisconnectEvent> {
+    REMOTE_DISCONNECT_TX.subscribe()
+}
+
+pub(crate) fn report_remote_disconnect(endpoint_id: EndPointID, reason: DisconnectReason) {
+    tracing::info!(?endpoint_id, ?reason, "remote endpoint disconnected");
+    let _ = REMOTE_DISCONNECT_TX.send(RemoteDisconnectEvent {
+        endpoint_id: endpoint_id.to_string(),
+        reason,
+    });
+}
+
+/// Whether the plaintext of a frame is a `Disconnect`.
+pub fn is_disconnect(buffer: &[u8]) -> bool {
+    buffer.len() <= MAX_DISCONNECT_PACKET_LEN
+        && matches!(
+            deserialize_packet(buffer),
+            Ok(EndPointMessage::Disconnect { .. })
+        )
+}
+
+/// The `Disconnect` queued before the connection was closed, the write
+/// loops send it last. The messages queued around it are dropped.
+pub(crate) fn take_disconnect(rx: &mut Receiver<Vec<u8>>) -> Option<Vec<u8>> {
+    while let Ok(buffer) = rx.try_recv() {
+        if is_disconnect(&buffer) {
+            return Some(buffer);
+        }
+    }
+
+    None
+}
@@ -30,6 +30,8 @@ pub fn serve_audio_decode(id: EndPointID, mut decode_rx: Receiver<EndPointAudioF
             match decode_rx.blocking_recv() {
                 Some(audio_frame) => {
                     match audio_decoder.decode(audio_frame) {
+                        // came after a later frame
+                        Ok(buffer) if buffer.is_empty() => continue,
                         Ok(buffer) => {
                             // because active endpoint always output 48000hz and 480 samples per channel after
                             // opus encode, so here we simply div (48000/480)=100 to get samples count after
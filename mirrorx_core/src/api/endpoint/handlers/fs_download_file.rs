@@ -6,8 +6,8 @@ use crate::{
             EndPointMessage,
         },
     },
-    component::fs::transfer::send_file_to_remote,
-    core_error,
+    component::fs::transfer::{file_content_hash, send_file_to_remote},
+    bail_core,
     error::CoreResult,
 };
 use std::{sync::Arc, time::Duration};
@@ -17,16 +17,27 @@ pub async fn handle_download_file_request(
     req: EndPointDownloadFileRequest,
 ) -> CoreResult<EndPointDownloadFileReply> {
     if !req.path.is_file() {
-        return Err(core_error!("file not exists"));
+        bail_core!("file not exists");
     }
 
     let id = req.id.clone();
     let meta = req.path.metadata()?;
     let size = meta.len();
+    let hash = file_content_hash(&req.path).await?;
+
+    // the file changed since the earlier transfer when the hash differs
+    let offset = match req.hash {
+        Some(ref partial_hash) if *partial_hash == hash && req.offset <= size => req.offset,
+        _ => 0,
+    };
+
+    if offset > 0 {
+        tracing::info!(path = ?req.path, offset, "resume sending file");
+    }
 
     tokio::spawn(async move {
         tokio::time::sleep(Duration::from_secs(1)).await;
-        if let Err(err) = send_file_to_remote(id.clone(), client.clone(), &req.path).await {
+        if let Err(err) = send_file_to_remote(id.clone(), client.clone(), &req.path, offset).await {
             tracing::error!(?err, "read file block failed");
             let _ = client
                 .send(&EndPointMessage::FileTransferError(
@@ -36,5 +47,5 @@ pub async fn handle_download_file_request(
         }
     });
 
-    Ok(EndPointDownloadFileReply { size })
+    Ok(EndPointDownloadFileReply { size, hash, offset })
 }
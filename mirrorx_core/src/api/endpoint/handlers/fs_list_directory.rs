@@ -0,0 +1,30 @@
+use crate::{
+    api::endpoint::message::{EndPointListDirectoryRequest, EndPointListDirectoryResponse},
+    component::fs::browse::{file_browse_policy, list_directory},
+    bail_core,
+    core_error,
+    error::CoreResult,
+};
+
+pub async fn handle_list_directory_request(
+    req: EndPointListDirectoryRequest,
+) -> CoreResult<EndPointListDirectoryResponse> {
+    let policy = file_browse_policy();
+    if !policy.allowed {
+        bail_core!("remote file browsing is not allowed");
+    }
+
+    let Some(root) = policy.root else {
+        bail_core!("no directory is exposed for browsing");
+    };
+
+    tracing::info!(path = ?req.path, offset = ?req.offset, limit = ?req.limit, "list directory");
+
+    let listing = tokio::task::spawn_blocking(move || {
+        list_directory(&root, &req.path, req.offset, req.limit)
+    })
+    .await
+    .map_err(|err| core_error!("list directory task failed ({})", err))??;
+
+    Ok(EndPointListDirectoryResponse { listing })
+}
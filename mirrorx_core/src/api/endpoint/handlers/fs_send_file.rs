@@ -1,20 +1,45 @@
 use crate::{
-    api::endpoint::message::{EndPointSendFileReply, EndPointSendFileRequest},
-    component::fs::transfer::create_file_append_session,
-    core_error,
-    error::CoreResult,
+    api::endpoint::{
+        client::EndPointClient,
+        message::{EndPointSendFileReply, EndPointSendFileRequest},
+    },
+    component::fs::{
+        safe_path::{confine_path, is_normalized_absolute_path},
+        transfer::{create_file_append_session, resume_offset},
+    },
+    bail_core,
+    error::{CoreError, CoreResult},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
 pub async fn handle_send_file_request(
+    client: Arc<EndPointClient>,
     req: EndPointSendFileRequest,
 ) -> CoreResult<EndPointSendFileReply> {
-    let path = req.path.join(req.filename);
+    if !is_normalized_absolute_path(&req.path) {
+        return Err(CoreError::UnsafePath(req.path));
+    }
+
+    // the filename comes from the sender, it may only name an entry of the
+    // target directory
+    let path = confine_path(&req.path, Path::new(&req.filename))?;
+    if path.parent() != Some(req.path.as_path()) {
+        return Err(CoreError::UnsafePath(PathBuf::from(req.filename)));
+    }
 
     if path.exists() {
-        return Err(core_error!("file already exists"));
+        bail_core!("file already exists");
+    }
+
+    let offset = resume_offset(&path, &req.hash, req.size).await;
+    if offset > 0 {
+        tracing::info!(?path, offset, "resume receiving file");
     }
 
-    create_file_append_session(req.id, &path).await?;
+    create_file_append_session(client, req.id, &path, &req.hash, offset, req.size).await?;
 
-    Ok(EndPointSendFileReply {})
+    Ok(EndPointSendFileReply { offset })
 }
@@ -3,7 +3,11 @@ use crate::{
         client::EndPointClient,
         message::{EndPointInput, InputEvent, KeyboardEvent, MouseEvent},
     },
-    component::{self, desktop::monitor::Monitor, input::key::MouseKey},
+    component::{
+        self,
+        desktop::{monitor::Monitor, window::CapturedWindow},
+        input::{encoding::InputEncoding, key::MouseKey},
+    },
 };
 use std::sync::Arc;
 
@@ -11,10 +15,23 @@ pub async fn handle_input(client: Arc<EndPointClient>, input_event: EndPointInpu
     for event in input_event.events {
         match event {
             InputEvent::Mouse(event) => {
-                if let Some(monitor) = client.monitor().await {
+                if let Some((monitor, event)) = map_to_window(client.captured_window(), &event) {
+                    handle_mouse(&event, &monitor);
+                } else if let Some(monitor) = client.monitor().await {
                     handle_mouse(&event, &monitor);
                 }
             }
+            InputEvent::Keyboard(KeyboardEvent::Text(text)) => {
+                // a viewer sending scancodes already typed the text
+                if client.profile().map(|profile| profile.input_encoding)
+                    != Some(InputEncoding::Unicode)
+                {
+                    tracing::warn!("drop text input, the unicode encoding wasn't agreed");
+                    continue;
+                }
+
+                let _ = component::input::keyboard_text(&text);
+            }
             InputEvent::Keyboard(event) => handle_keyboard(&event),
         }
     }
@@ -37,6 +54,34 @@ pub fn handle_mouse(event: &MouseEvent, monitor: &Monitor) {
     }
 }
 
+/// Positions of a mouse event are normalized to the frames, which show only
+/// the shared window when one is. Returns the event positioned on the display
+/// showing the window, `None` while the whole display is shared.
+pub fn map_to_window(
+    captured_window: &CapturedWindow,
+    event: &MouseEvent,
+) -> Option<(Arc<Monitor>, MouseEvent)> {
+    let mapped = match event {
+        MouseEvent::Up(key, x, y) => {
+            let (monitor, x, y) = captured_window.to_display(*x, *y)?;
+            (monitor, MouseEvent::Up(key.clone(), x, y))
+        }
+        MouseEvent::Down(key, x, y) => {
+            let (monitor, x, y) = captured_window.to_display(*x, *y)?;
+            (monitor, MouseEvent::Down(key.clone(), x, y))
+        }
+        MouseEvent::Move(key, x, y) => {
+            let (monitor, x, y) = captured_window.to_display(*x, *y)?;
+            (monitor, MouseEvent::Move(key.clone(), x, y))
+        }
+        MouseEvent::ScrollWheel(delta) => {
+            (captured_window.monitor()?, MouseEvent::ScrollWheel(*delta))
+        }
+    };
+
+    Some(mapped)
+}
+
 pub fn handle_mouse_double_click(key: &MouseKey, x: f32, y: f32, monitor: &Monitor) {
     let _ = component::input::mouse_double_click(monitor, key, x, y);
 }
@@ -49,5 +94,8 @@ pub fn handle_keyboard(event: &KeyboardEvent) {
         KeyboardEvent::KeyDown(key) => {
             let _ = component::input::keyboard_down(key);
         }
+        KeyboardEvent::Text(text) => {
+            let _ = component::input::keyboard_text(text);
+        }
     }
 }
@@ -1,6 +1,7 @@
 pub mod audio_frame;
 pub mod error;
 pub mod fs_download_file;
+pub mod fs_list_directory;
 pub mod fs_send_file;
 pub mod fs_visit_directory;
 pub mod input;
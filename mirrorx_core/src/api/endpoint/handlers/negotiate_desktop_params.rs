@@ -1,21 +1,73 @@
 use crate::{
     api::endpoint::{
+        capabilities::{agree_audio_params, agree_media_limits, local_media_limits},
         client::EndPointClient,
         message::{
             EndPointMessage, EndPointNegotiateDesktopParamsRequest,
             EndPointNegotiateDesktopParamsResponse, EndPointNegotiateVisitDesktopParams,
-            VideoCodec,
+            MediaErrorReason, VideoCodec,
         },
+        session_token::{session_tokens, SessionToken},
     },
-    component::desktop::monitor::get_primary_monitor_params,
+    component::{
+        desktop::virtual_display::primary_monitor_or_virtual,
+        video_encoder::adapter::{select_video_codec, usable_pinned_graphics_card},
+    },
+    core_error,
+    utility::permission::check_platform_permissions,
 };
-use std::sync::Arc;
+use std::{sync::Arc, time::Instant};
 
 pub async fn handle_negotiate_desktop_params_request(
     client: Arc<EndPointClient>,
     req: EndPointNegotiateDesktopParamsRequest,
 ) {
-    let resp = negotiate_media_params(&client, req).await;
+    let rejoined_params = req
+        .rejoin_token
+        .as_ref()
+        .and_then(|token| session_tokens().rejoin(token, client.endpoint_id(), Instant::now()));
+
+    let mut resp = match rejoined_params {
+        Some(params) => {
+            // the permissions were checked when the session was negotiated,
+            // the connection and the capture are new all the same
+            tracing::info!(endpoint_id = ?client.endpoint_id(), "rejoin desktop session");
+            client.set_monitor(params.primary_monitor.clone()).await;
+            EndPointNegotiateDesktopParamsResponse::Params(params)
+        }
+        None => {
+            // the request lists the codecs the profile of the session agreed
+            // on, preferred first, one this build can't encode falls back to
+            // the next
+            let Some(video_codec) = select_video_codec(&req.video_codecs) else {
+                tracing::error!(video_codecs = ?req.video_codecs, "no common video codec");
+                client.report_media_error(MediaErrorReason::CodecUnsupported);
+                return;
+            };
+
+            // refuse before negotiating, otherwise the visitor only finds out
+            // from a black screen once capture starts
+            let permissions = check_platform_permissions(false);
+            if let Err(err) = permissions.ensure_screen_capture() {
+                tracing::error!(?err, "refuse desktop session");
+                client.report_media_error(MediaErrorReason::PermissionDenied);
+                return;
+            }
+
+            if let Err(err) = permissions.ensure_accessibility() {
+                tracing::warn!(?err, "remote input will be ignored");
+            }
+
+            negotiate_media_params(&client, video_codec, &req).await
+        }
+    };
+
+    if let EndPointNegotiateDesktopParamsResponse::Params(ref mut params) = resp {
+        client.set_video_codec(params.video_codec.clone());
+        client.set_audio_params(params.audio);
+        client.set_encoder_graphics_card(params.encoder_graphics_card.clone());
+        params.session_token = issue_session_token(&client, params.clone());
+    }
 
     if let Err(err) = client
         .send(&EndPointMessage::NegotiateDesktopParamsResponse(resp))
@@ -30,11 +82,15 @@ pub async fn handle_negotiate_desktop_params_request(
 
 async fn negotiate_media_params(
     client: &EndPointClient,
-    _req: EndPointNegotiateDesktopParamsRequest,
+    video_codec: VideoCodec,
+    req: &EndPointNegotiateDesktopParamsRequest,
 ) -> EndPointNegotiateDesktopParamsResponse {
-    // todo: check support video and audio properties
-
-    let primary_monitor = match get_primary_monitor_params() {
+    // a headless machine may need a moment to bring up a virtual display,
+    // the viewer gets the missing display as a media error otherwise
+    let primary_monitor = match tokio::task::spawn_blocking(primary_monitor_or_virtual)
+        .await
+        .unwrap_or_else(|err| Err(core_error!("get primary monitor panicked ({})", err)))
+    {
         Ok(monitor) => monitor,
         Err(err) => {
             tracing::error!(?err, "get primary monitor params failed at negotiate stage");
@@ -44,12 +100,43 @@ async fn negotiate_media_params(
 
     client.set_monitor(primary_monitor.clone()).await;
 
+    let encoder_graphics_card =
+        usable_pinned_graphics_card(&video_codec).map(|graphics_card| graphics_card.name);
+
     let params = EndPointNegotiateVisitDesktopParams {
-        video_codec: VideoCodec::H264,
+        video_codec,
+        media_limits: agree_media_limits(&local_media_limits(), &req.media_limits),
+        audio: req.audio.as_ref().map(agree_audio_params),
         os_type: String::from(""),
         os_version: String::from(""),
         primary_monitor,
+        encoder_graphics_card,
+        session_token: None,
     };
 
     EndPointNegotiateDesktopParamsResponse::Params(params)
 }
+
+fn issue_session_token(
+    client: &Arc<EndPointClient>,
+    mut params: EndPointNegotiateVisitDesktopParams,
+) -> Option<SessionToken> {
+    params.session_token = None;
+
+    let token = match session_tokens().issue(client.endpoint_id(), params, Instant::now()) {
+        Ok(token) => token,
+        Err(err) => {
+            // the session works without, it just can't be rejoined
+            tracing::error!(?err, "issue session token failed");
+            return None;
+        }
+    };
+
+    let client = client.clone();
+    tokio::spawn(async move {
+        client.closed().await;
+        session_tokens().disconnect(&token, Instant::now());
+    });
+
+    Some(token)
+}
@@ -1,11 +1,35 @@
 use crate::{
-    api::endpoint::{client::EndPointClient, message::EndPointMessage},
+    api::endpoint::{
+        bandwidth::spawn_bandwidth_estimator,
+        client::EndPointClient,
+        idle::{spawn_idle_watcher, SessionRole},
+        media_pause::MEDIA_PAUSE_POLL_INTERVAL,
+        message::{
+            EndPointAudioParams, EndPointMessage, EndPointNegotiateFinishedRequest,
+            MediaErrorReason,
+        },
+    },
     component::{
         audio::{duplicator::new_record_stream_and_rx, encoder::AudioEncoder},
-        desktop::{monitor::get_active_monitors, Duplicator},
-        video_encoder::{config::*, video_encoder::VideoEncoder},
+        desktop::{
+            capture_error::capture_failure_reason,
+            display_config::DisplayChanges,
+            monitor::get_active_monitors,
+            window::{shared_window, CapturedWindow, WindowChange, WindowFollower},
+            Duplicator,
+        },
+        video_encoder::{
+            adapter::{report_encoder_fallback, select_encoder_config, software_encoder_config},
+            media_config::MediaConfig,
+            preprocess::CapturedDisplay,
+            video_encoder::VideoEncoder,
+        },
     },
     error::CoreError,
+    utility::{
+        error_event::{report_error_event, ErrorSource},
+        thread_priority::elevate_media_thread,
+    },
 };
 use cpal::traits::StreamTrait;
 use scopeguard::defer;
@@ -18,18 +42,41 @@ pub struct NegotiateFinishedRequest {
     pub texture_id: i64,
 }
 
-pub fn handle_negotiate_finished_request(client: Arc<EndPointClient>) {
-    spawn_desktop_capture_and_encode_process(client.clone());
-    spawn_audio_capture_and_encode_process(client);
+pub fn handle_negotiate_finished_request(
+    client: Arc<EndPointClient>,
+    req: EndPointNegotiateFinishedRequest,
+) {
+    let mut media_config = req.media_config;
+
+    // what leaves this machine is up to the host, the viewer doesn't know
+    // its windows
+    media_config.capture_window = shared_window();
+
+    tracing::info!(?media_config, "negotiate finished");
+    spawn_idle_watcher(client.clone(), SessionRole::Host);
+    spawn_bandwidth_estimator(client.clone(), media_config.bitrate_kbps as u64 * 1000);
+    let audio_params = client.audio_params().filter(|_| media_config.audio);
+    spawn_desktop_capture_and_encode_process(client.clone(), media_config);
+
+    // the viewer asked for video only, or has nothing to play audio on
+    if let Some(audio_params) = audio_params {
+        spawn_audio_capture_and_encode_process(client, audio_params);
+    }
 }
 
 #[cfg(target_os = "macos")]
-fn spawn_desktop_capture_and_encode_process(client: Arc<EndPointClient>) {
+fn spawn_desktop_capture_and_encode_process(
+    client: Arc<EndPointClient>,
+    media_config: MediaConfig,
+) {
     let (capture_frame_tx, mut capture_frame_rx) = tokio::sync::mpsc::channel(180);
+    let media_token = client.media_token();
 
     tokio::task::spawn_blocking(move || {
         tracing::info_span!("desktop_capture_and_encode_process", client = ?client);
 
+        let _priority = elevate_media_thread(client.endpoint_id().to_string(), "capture");
+
         defer! {
             tracing::info!("desktop capture process exit");
         }
@@ -38,31 +85,83 @@ fn spawn_desktop_capture_and_encode_process(client: Arc<EndPointClient>) {
             Ok(params) => params,
             Err(err) => {
                 tracing::error!(?err, "get_primary_monitor_params failed");
+                client.report_media_error(MediaErrorReason::NoDisplay);
                 return;
             }
         };
 
-        let mut encoder = match VideoEncoder::new(libx264::Libx264Config::new(), client.clone()) {
+        // the codec was settled when the viewer asked for the desktop
+        let video_codec = client.video_codec().unwrap_or_default();
+        let captured_display = CapturedDisplay::default();
+
+        let mut encoder = match VideoEncoder::new(
+            select_encoder_config(&video_codec),
+            media_config.clone(),
+            client.clone(),
+            captured_display.clone(),
+        ) {
             Ok(encoder) => encoder,
             Err(err) => {
                 tracing::error!(?err, "initialize encoder failed");
+                client.report_media_error(MediaErrorReason::CodecUnsupported);
                 return;
             }
         };
-
-        let primary_monitor = monitors.iter().find(|monitor| monitor.is_primary);
-
-        let (duplicator, monitor_id) = match Duplicator::new(
-            primary_monitor.map(|monitor| monitor.id.to_owned()),
-            capture_frame_tx,
-        ) {
-            Ok(duplicator) => duplicator,
-            Err(err) => {
-                tracing::error!(?err, "initialize encoder failed");
+        client.announce_encoder_graphics_card(
+            encoder
+                .graphics_card()
+                .map(|graphics_card| graphics_card.name.clone()),
+        );
+
+        let mut follower = match media_config.capture_window.map(WindowFollower::locate) {
+            Some(Ok(follower)) => Some(follower),
+            Some(Err(CoreError::WindowClosed)) => {
+                tracing::error!("shared window not found");
+                client.report_media_error(MediaErrorReason::WindowClosed);
+                return;
+            }
+            Some(Err(err)) => {
+                tracing::error!(?err, "locate shared window failed");
+                client.report_media_error(MediaErrorReason::NoDisplay);
                 return;
             }
+            None => None,
         };
 
+        let capture_monitor_id = match follower {
+            Some(ref follower) => Some(follower.display_id().to_owned()),
+            None => monitors
+                .iter()
+                .find(|monitor| monitor.is_primary)
+                .map(|monitor| monitor.id.to_owned()),
+        };
+
+        let mut include_cursor = client.include_cursor();
+
+        let start_duplicator = |monitor_id: &str, include_cursor: bool| {
+            Duplicator::new(
+                Some(monitor_id.to_owned()),
+                include_cursor,
+                capture_frame_tx.clone(),
+            )
+            .and_then(|(new_duplicator, _)| {
+                new_duplicator.start()?;
+                Ok(new_duplicator)
+            })
+        };
+
+        let mut display_changes = DisplayChanges::global().watch();
+
+        let (duplicator, mut monitor_id) =
+            match Duplicator::new(capture_monitor_id, include_cursor, capture_frame_tx.clone()) {
+                Ok(duplicator) => duplicator,
+                Err(err) => {
+                    tracing::error!(?err, "initialize desktop duplicator failed");
+                    client.report_media_error(capture_failure_reason(&err));
+                    return;
+                }
+            };
+
         let select_monitor = match monitors
             .into_iter()
             .find(|monitor| monitor.id == monitor_id)
@@ -70,30 +169,170 @@ fn spawn_desktop_capture_and_encode_process(client: Arc<EndPointClient>) {
             Some(monitor) => monitor,
             None => {
                 tracing::error!("can't find selected monitor");
+                client.report_media_error(MediaErrorReason::NoDisplay);
                 return;
             }
         };
 
         tracing::info!(?select_monitor.width,?select_monitor.height,"select monitor");
+        captured_display.set(&select_monitor);
+
+        // the input of the viewer lands on the shared window
+        client.captured_window().clear();
+        if let Some(ref follower) = follower {
+            publish_window(client.captured_window(), follower);
+        }
 
         // PASSIVE_ENDPOINTS_MONITORS.insert(client.id, select_monitor);
 
         if let Err(err) = duplicator.start() {
             tracing::error!(?err, "desktop capture process start failed");
+            client.report_media_error(capture_failure_reason(&err));
             return;
         }
 
-        defer! {
-            let _ = duplicator.stop();
+        if let Err(err) = client.report_capture_mode(duplicator.capture_mode()) {
+            tracing::warn!(?err, "report capture mode failed");
         }
 
+        let mut duplicator = scopeguard::guard(duplicator, |duplicator| {
+            let _ = duplicator.stop();
+        });
+
         loop {
+            // the session swapped, this side watches now
+            if media_token.is_cancelled() {
+                tracing::info!("desktop capture and encode process stopped");
+                return;
+            }
+
+            // the shared window moved to another display or went away
+            if let Some(ref mut follower) = follower {
+                match follower.poll() {
+                    WindowChange::Unchanged => {}
+                    WindowChange::Display(display_id) => {
+                        tracing::info!(?display_id, "shared window moved to another display");
+                        monitor_id = display_id;
+                        let _ = duplicator.stop();
+
+                        // frames of the previous display don't fit the window
+                        while capture_frame_rx.try_recv().is_ok() {}
+
+                        match start_duplicator(&monitor_id, include_cursor) {
+                            Ok(new_duplicator) => *duplicator = new_duplicator,
+                            Err(err) => {
+                                tracing::error!(?err, "switch desktop capture display failed");
+                                client.report_media_error(capture_failure_reason(&err));
+                                return;
+                            }
+                        }
+                    }
+                    WindowChange::Closed => {
+                        tracing::info!("shared window closed");
+                        client.report_media_error(MediaErrorReason::WindowClosed);
+                        return;
+                    }
+                }
+
+                publish_window(client.captured_window(), follower);
+            }
+
+            // the stream keeps the mode the display had when it was created
+            match display_changes.recreate(&mut *duplicator, |duplicator| {
+                let _ = duplicator.stop();
+
+                // frames of the previous mode don't fit any more
+                while capture_frame_rx.try_recv().is_ok() {}
+
+                start_duplicator(&monitor_id, include_cursor)
+            }) {
+                Ok(false) => {}
+                Ok(true) => {
+                    tracing::info!(?monitor_id, "display changed, desktop capture recreated");
+                    refresh_captured_display(&captured_display, &monitor_id);
+
+                    // the display of the window may have another size now
+                    if let Some(ref follower) = follower {
+                        client.captured_window().clear();
+                        publish_window(client.captured_window(), follower);
+                    }
+                }
+                Err(err) => {
+                    tracing::error!(?err, "recreate desktop capture after display change failed");
+                    client.report_media_error(capture_failure_reason(&err));
+                    return;
+                }
+            }
+
+            // the stream can't change its pointer setting, replace it instead
+            if include_cursor != client.include_cursor() {
+                include_cursor = client.include_cursor();
+                let _ = duplicator.stop();
+
+                match start_duplicator(&monitor_id, include_cursor) {
+                    Ok(new_duplicator) => *duplicator = new_duplicator,
+                    Err(err) => {
+                        tracing::error!(?err, "restart desktop capture process failed");
+                        client.report_media_error(capture_failure_reason(&err));
+                        return;
+                    }
+                }
+            }
+
+            // the viewer paused the video, stop capturing until it resumes
+            if client.media_paused() {
+                let _ = duplicator.stop();
+
+                while client.media_paused() && !media_token.is_cancelled() {
+                    std::thread::sleep(MEDIA_PAUSE_POLL_INTERVAL);
+                }
+
+                // frames captured before the pause are stale
+                while capture_frame_rx.try_recv().is_ok() {}
+
+                match start_duplicator(&monitor_id, include_cursor) {
+                    Ok(new_duplicator) => *duplicator = new_duplicator,
+                    Err(err) => {
+                        tracing::error!(?err, "resume desktop capture process failed");
+                        client.report_media_error(capture_failure_reason(&err));
+                        return;
+                    }
+                }
+
+                continue;
+            }
+
             match capture_frame_rx.blocking_recv() {
                 Some(capture_frame) => {
+                    let capture_frame = match follower {
+                        Some(ref follower) => match follower.crop(capture_frame) {
+                            Some(window_frame) => window_frame,
+                            None => continue,
+                        },
+                        None => capture_frame,
+                    };
+
                     if let Err(err) = encoder.encode(capture_frame) {
                         if let CoreError::OutgoingMessageChannelDisconnect = err {
                             tracing::info!("desktop capture and encode process exit");
                             return;
+                        } else if let Some(graphics_card) = encoder.graphics_card() {
+                            report_encoder_fallback(&graphics_card.id, &err.to_string());
+
+                            encoder = match VideoEncoder::new(
+                                software_encoder_config(&video_codec),
+                                media_config.clone(),
+                                client.clone(),
+                                captured_display.clone(),
+                            ) {
+                                Ok(encoder) => encoder,
+                                Err(err) => {
+                                    tracing::error!(?err, "initialize encoder failed");
+                                    client.report_media_error(MediaErrorReason::CodecUnsupported);
+                                    return;
+                                }
+                            };
+                            client.announce_encoder_graphics_card(None);
                         } else {
                             tracing::error!("video encode failed");
                             break;
@@ -110,29 +349,71 @@ fn spawn_desktop_capture_and_encode_process(client: Arc<EndPointClient>) {
 }
 
 #[cfg(target_os = "windows")]
-fn spawn_desktop_capture_and_encode_process(client: Arc<EndPointClient>) {
+fn spawn_desktop_capture_and_encode_process(
+    client: Arc<EndPointClient>,
+    media_config: MediaConfig,
+) {
+    use crate::component::desktop::capture_error::{
+        classify_capture_error, report_capture_escalation, CaptureErrorKind,
+        CaptureEscalationEvent, CaptureTolerance, CaptureVerdict,
+    };
+
     let monitors = match get_active_monitors(false) {
         Ok(params) => params,
         Err(err) => {
             tracing::error!(?err, "get_active_monitors failed");
+            client.report_media_error(MediaErrorReason::NoDisplay);
             return;
         }
     };
 
     let (capture_frame_tx, mut capture_frame_rx) = tokio::sync::mpsc::channel(180);
+    let media_token = client.media_token();
 
+    let captured_display = CapturedDisplay::default();
+
+    let capture_client = client.clone();
+    let capture_media_token = media_token.clone();
+    let capture_display = captured_display.clone();
+    let capture_window = media_config.capture_window;
     tokio::task::spawn_blocking(move || {
         defer! {
             tracing::info!( "desktop capture process exit");
         }
 
-        let primary_monitor = monitors.iter().find(|monitor| monitor.is_primary);
+        let _priority = elevate_media_thread(capture_client.endpoint_id().to_string(), "capture");
 
-        let (mut duplicator, monitor_id) =
-            match Duplicator::new(primary_monitor.map(|monitor| monitor.id.to_owned())) {
+        let mut follower = match capture_window.map(WindowFollower::locate) {
+            Some(Ok(follower)) => Some(follower),
+            Some(Err(CoreError::WindowClosed)) => {
+                tracing::error!("shared window not found");
+                capture_client.report_media_error(MediaErrorReason::WindowClosed);
+                return;
+            }
+            Some(Err(err)) => {
+                tracing::error!(?err, "locate shared window failed");
+                capture_client.report_media_error(MediaErrorReason::NoDisplay);
+                return;
+            }
+            None => None,
+        };
+
+        let capture_monitor_id = match follower {
+            Some(ref follower) => Some(follower.display_id().to_owned()),
+            None => monitors
+                .iter()
+                .find(|monitor| monitor.is_primary)
+                .map(|monitor| monitor.id.to_owned()),
+        };
+
+        let mut display_changes = DisplayChanges::global().watch();
+
+        let (mut duplicator, mut monitor_id) =
+            match Duplicator::new(capture_monitor_id, capture_client.include_cursor()) {
                 Ok(duplicator) => duplicator,
                 Err(err) => {
-                    tracing::error!(?err, "initialize encoder failed");
+                    tracing::error!(?err, "initialize desktop duplicator failed");
+                    capture_client.report_media_error(capture_failure_reason(&err));
                     return;
                 }
             };
@@ -144,49 +425,217 @@ fn spawn_desktop_capture_and_encode_process(client: Arc<EndPointClient>) {
             Some(monitor) => monitor,
             None => {
                 tracing::error!("can't find selected monitor");
+                capture_client.report_media_error(MediaErrorReason::NoDisplay);
                 return;
             }
         };
+        capture_display.set(&select_monitor);
+
+        // the input of the viewer lands on the shared window
+        capture_client.captured_window().clear();
+        if let Some(ref follower) = follower {
+            publish_window(capture_client.captured_window(), follower);
+        }
 
         // PASSIVE_ENDPOINTS_MONITORS.insert(client.id, select_monitor);
 
+        let mut tolerance = CaptureTolerance::default();
+
         loop {
-            match duplicator.capture() {
-                Ok(capture_frame) => {
-                    if let Err(_) = capture_frame_tx.blocking_send(capture_frame) {
+            // the session swapped, this side watches now
+            if capture_media_token.is_cancelled() {
+                return;
+            }
+
+            // the viewer paused the video, leave the screen alone
+            if capture_client.media_paused() {
+                std::thread::sleep(MEDIA_PAUSE_POLL_INTERVAL);
+                continue;
+            }
+
+            // the shared window moved to another display or went away
+            if let Some(ref mut follower) = follower {
+                match follower.poll() {
+                    WindowChange::Unchanged => {}
+                    WindowChange::Display(display_id) => {
+                        tracing::info!(?display_id, "shared window moved to another display");
+
+                        match Duplicator::new(Some(display_id), capture_client.include_cursor()) {
+                            Ok((new_duplicator, new_monitor_id)) => {
+                                duplicator = new_duplicator;
+                                monitor_id = new_monitor_id;
+                            }
+                            Err(err) => {
+                                tracing::error!(?err, "switch desktop duplicator display failed");
+                                capture_client.report_media_error(capture_failure_reason(&err));
+                                return;
+                            }
+                        }
+                    }
+                    WindowChange::Closed => {
+                        tracing::info!("shared window closed");
+                        capture_client.report_media_error(MediaErrorReason::WindowClosed);
                         return;
                     }
                 }
+
+                publish_window(capture_client.captured_window(), follower);
+            }
+
+            // the duplication keeps the mode the display had when it was
+            // created
+            match display_changes.recreate(&mut duplicator, |_| {
+                Duplicator::new(Some(monitor_id.clone()), capture_client.include_cursor())
+                    .map(|(new_duplicator, _)| new_duplicator)
+            }) {
+                Ok(false) => {}
+                Ok(true) => {
+                    tracing::info!(?monitor_id, "display changed, desktop duplicator recreated");
+                    refresh_captured_display(&capture_display, &monitor_id);
+
+                    // the display of the window may have another size now
+                    if let Some(ref follower) = follower {
+                        capture_client.captured_window().clear();
+                        publish_window(capture_client.captured_window(), follower);
+                    }
+                }
                 Err(err) => {
+                    tracing::error!(
+                        ?err,
+                        "recreate desktop duplicator after display change failed"
+                    );
+                    capture_client.report_media_error(capture_failure_reason(&err));
+                    return;
+                }
+            }
+
+            duplicator.set_include_cursor(capture_client.include_cursor());
+
+            let err = match duplicator.capture() {
+                // nothing changed, look at the session again
+                Ok(None) => continue,
+                Ok(Some(capture_frame)) => {
+                    tolerance.record_frame();
+
+                    if let Err(err) = capture_client.report_capture_mode(duplicator.capture_mode())
+                    {
+                        tracing::warn!(?err, "report capture mode failed");
+                    }
+
+                    let capture_frame = match follower {
+                        Some(ref follower) => match follower.crop(capture_frame) {
+                            Some(window_frame) => window_frame,
+                            None => continue,
+                        },
+                        None => capture_frame,
+                    };
+
+                    if let Err(_) = capture_frame_tx.blocking_send(capture_frame) {
+                        return;
+                    }
+                    continue;
+                }
+                Err(err) => err,
+            };
+
+            capture_client.raw_stats().record_capture_error();
+            report_error_event(
+                ErrorSource::Capture,
+                &capture_client.endpoint_id(),
+                &err.to_string(),
+            );
+            let mut verdict = tolerance.record_error(classify_capture_error(&err));
+
+            // a capture that can't be created again escalates further
+            while verdict == CaptureVerdict::Restart {
+                report_capture_escalation(CaptureEscalationEvent::Restart {
+                    endpoint_id: capture_client.endpoint_id().to_string(),
+                    reason: err.to_string(),
+                });
+
+                match Duplicator::new(Some(monitor_id.clone()), capture_client.include_cursor()) {
+                    Ok((new_duplicator, _)) => {
+                        duplicator = new_duplicator;
+                        break;
+                    }
+                    Err(err) => {
+                        tracing::error!(?err, "restart desktop duplicator failed");
+                        verdict = tolerance.record_error(CaptureErrorKind::Fatal);
+                    }
+                }
+            }
+
+            match verdict {
+                CaptureVerdict::Skip => {
+                    tracing::debug!(?err, "skip failed desktop capture");
+                }
+                CaptureVerdict::Restart => {}
+                CaptureVerdict::Fail => {
                     tracing::error!(?err, "desktop duplicator capture failed");
+                    report_capture_escalation(CaptureEscalationEvent::Fail {
+                        endpoint_id: capture_client.endpoint_id().to_string(),
+                        reason: err.to_string(),
+                    });
+                    capture_client.report_media_error(capture_failure_reason(&err));
                     break;
                 }
-            };
+            }
         }
     });
 
     tokio::task::spawn_blocking(move || {
+        let _priority = elevate_media_thread(client.endpoint_id().to_string(), "encode");
+        let mut hardware_failed = false;
+
+        // the codec was settled when the viewer asked for the desktop
+        let video_codec = client.video_codec().unwrap_or_default();
+
         loop {
             // defer! {
             //     tracing::info!(?active_device_id, ?passive_device_id, "video encode process exit");
             // }
 
-            let mut encoder = match VideoEncoder::new(libx264::Libx264Config::new(), client.clone())
-            {
+            let encoder_config = if hardware_failed {
+                software_encoder_config(&video_codec)
+            } else {
+                select_encoder_config(&video_codec)
+            };
+
+            let mut encoder = match VideoEncoder::new(
+                encoder_config,
+                media_config.clone(),
+                client.clone(),
+                captured_display.clone(),
+            ) {
                 Ok(encoder) => encoder,
                 Err(err) => {
                     tracing::error!(?err, "video encoder initialize failed");
+                    client.report_media_error(MediaErrorReason::CodecUnsupported);
                     return;
                 }
             };
+            client.announce_encoder_graphics_card(
+                encoder
+                    .graphics_card()
+                    .map(|graphics_card| graphics_card.name.clone()),
+            );
 
             loop {
+                if media_token.is_cancelled() {
+                    tracing::info!("video encode process stopped");
+                    return;
+                }
+
                 match capture_frame_rx.blocking_recv() {
                     Some(capture_frame) => {
                         if let Err(err) = encoder.encode(capture_frame) {
                             if let CoreError::OutgoingMessageChannelDisconnect = err {
                                 tracing::info!("desktop capture and encode process exit");
                                 return;
+                            } else if let Some(graphics_card) = encoder.graphics_card() {
+                                report_encoder_fallback(&graphics_card.id, &err.to_string());
+                                hardware_failed = true;
+                                break;
                             } else {
                                 tracing::error!(?err, "video encode failed");
                             }
@@ -202,62 +651,131 @@ fn spawn_desktop_capture_and_encode_process(client: Arc<EndPointClient>) {
     });
 }
 
-fn spawn_audio_capture_and_encode_process(client: Arc<EndPointClient>) {
+fn spawn_audio_capture_and_encode_process(
+    client: Arc<EndPointClient>,
+    audio_params: EndPointAudioParams,
+) {
     // let mut exit_rx = client.close_receiver();
+    let media_token = client.media_token();
 
-    tokio::task::spawn_blocking(move || loop {
-        // let Err(async_broadcast::TryRecvError::Empty) = exit_rx.try_recv() else {
-        //     tracing::info!("receive exit signal, exit");
-        //     return;
-        // };
-
-        let (stream, mut rx) = match new_record_stream_and_rx() {
-            Ok((stream, rx)) => (stream, rx),
+    tokio::task::spawn_blocking(move || {
+        // one encoder for the whole session, the viewer tells lost frames
+        // by the sequence numbers it keeps counting across capture restarts
+        let mut audio_encoder = match AudioEncoder::new(audio_params) {
+            Ok(audio_encoder) => audio_encoder,
             Err(err) => {
-                tracing::error!(?err, "initialize audio record stream failed");
-                continue;
+                tracing::error!(?err, "initialize audio encoder failed");
+                return;
             }
         };
 
-        if let Err(err) = stream.play() {
-            tracing::error!(?err, "play audio stream failed");
-            continue;
-        }
-
-        let mut audio_encoder = AudioEncoder::default();
-
         loop {
+            if media_token.is_cancelled() {
+                tracing::info!("audio encode process stopped");
+                return;
+            }
+
             // let Err(async_broadcast::TryRecvError::Empty) = exit_rx.try_recv() else {
             //     tracing::info!("receive exit signal, exit");
             //     return;
             // };
 
-            match rx.blocking_recv() {
-                Some(audio_frame) => match audio_encoder.encode(audio_frame) {
-                    Ok(frame) => {
-                        if let Err(err) = client.blocking_send(&EndPointMessage::AudioFrame(frame))
-                        {
-                            match err {
-                                CoreError::OutgoingMessageChannelDisconnect => {
-                                    tracing::info!("audio encode process exit");
-                                    return;
-                                }
-                                _ => {
-                                    tracing::error!(?err, "audio encode failed");
+            let (stream, mut rx) = match new_record_stream_and_rx() {
+                Ok((stream, rx)) => (stream, rx),
+                Err(err) => {
+                    tracing::error!(?err, "initialize audio record stream failed");
+                    continue;
+                }
+            };
+
+            if let Err(err) = stream.play() {
+                tracing::error!(?err, "play audio stream failed");
+                continue;
+            }
+
+            loop {
+                // let Err(async_broadcast::TryRecvError::Empty) = exit_rx.try_recv() else {
+                //     tracing::info!("receive exit signal, exit");
+                //     return;
+                // };
+
+                if media_token.is_cancelled() {
+                    tracing::info!("audio encode process stopped");
+                    return;
+                }
+
+                match rx.blocking_recv() {
+                    Some(audio_frame) => match audio_encoder.encode(audio_frame) {
+                        Ok(frames) => {
+                            for frame in frames {
+                                match client.send_media_frame(&EndPointMessage::AudioFrame(frame)) {
+                                    Ok(_) => {}
+                                    // the viewer fills the gap with silence
+                                    Err(CoreError::OutgoingMessageChannelFull) => {
+                                        tracing::debug!("send queue full, drop audio frame");
+                                    }
+                                    Err(CoreError::OutgoingMessageChannelDisconnect) => {
+                                        tracing::info!("audio encode process exit");
+                                        return;
+                                    }
+                                    Err(err) => {
+                                        tracing::error!(?err, "audio encode failed");
+                                    }
                                 }
                             }
                         }
-                    }
-                    Err(err) => {
-                        tracing::error!(?err, "audio encode failed");
+                        Err(err) => {
+                            tracing::error!(?err, "audio encode failed");
+                            break;
+                        }
+                    },
+                    None => {
+                        tracing::error!("audio duplicator tx closed");
                         break;
                     }
-                },
-                None => {
-                    tracing::error!("audio duplicator tx closed");
-                    break;
                 }
             }
         }
     });
 }
+
+/// The captured display may have another size or place after a display
+/// change, the privacy regions follow it.
+fn refresh_captured_display(captured_display: &CapturedDisplay, monitor_id: &str) {
+    match get_active_monitors(false) {
+        Ok(monitors) => {
+            if let Some(monitor) = monitors.iter().find(|monitor| monitor.id == monitor_id) {
+                captured_display.set(monitor);
+            }
+        }
+        Err(err) => tracing::warn!(?err, "refresh captured display failed"),
+    }
+}
+
+/// Tells the input handler where the shared window is now, the display
+/// showing it is looked up again once the window moved to another one.
+fn publish_window(captured_window: &CapturedWindow, follower: &WindowFollower) {
+    let monitor = match captured_window.monitor() {
+        Some(monitor) if monitor.id == follower.display_id() => monitor,
+        _ => match get_active_monitors(false) {
+            Ok(monitors) => {
+                match monitors
+                    .into_iter()
+                    .find(|monitor| monitor.id == follower.display_id())
+                {
+                    Some(monitor) => Arc::new(monitor),
+                    None => {
+                        tracing::warn!("display of the shared window not found");
+                        return;
+                    }
+                }
+            }
+            Err(err) => {
+                tracing::warn!(?err, "look up display of the shared window failed");
+                return;
+            }
+        },
+    };
+
+    captured_window.set(monitor, follower);
+}
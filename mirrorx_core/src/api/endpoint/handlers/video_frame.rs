@@ -1,28 +1,124 @@
 use crate::{
-    api::endpoint::{message::EndPointVideoFrame, EndPointID},
-    component::{frame::DesktopDecodeFrame, video_decoder::video_decoder::VideoDecoder},
+    api::endpoint::{
+        message::{EndPointVideoFrame, EndPointVideoParameterSets, VideoCodec},
+        EndPointID,
+    },
+    component::{
+        frame::DesktopDecodeFrame,
+        video_decoder::{
+            budget::{queued_bytes, register_media_budget, unregister_media_budget},
+            fallback::hardware_decode_supported,
+            frame_dump::{dump_frame, finish_frame_dump, FrameDumpEnd},
+            keyframe::is_keyframe,
+            rate_limit::{decode_fps_cap, DecodeStats},
+            thumbnail::Thumbnailer,
+            video_decoder::VideoDecoder,
+        },
+    },
+    utility::error_event::{report_error_event, ErrorSource},
 };
+use std::{collections::VecDeque, sync::Arc};
 use tokio::sync::mpsc::Sender;
 
+/// Input of the decode process, kept in the order the messages arrived so a
+/// reset lands between the frames of the old and the new size.
+#[derive(Debug)]
+pub enum VideoDecodeMessage {
+    Frame(EndPointVideoFrame),
+    DisplayConfigChanged {
+        width: i32,
+        height: i32,
+    },
+    /// Frames went missing, the ones after them reference what's lost.
+    FramesLost,
+    /// Parameter sets of the keyframes that follow, kept by the decoder for
+    /// when it starts over.
+    ParameterSets(EndPointVideoParameterSets),
+    /// The codec the session negotiated, the frames that follow are of it.
+    Codec(VideoCodec),
+}
+
 pub fn serve_video_decode(
     id: EndPointID,
     render_tx: Sender<DesktopDecodeFrame>,
-) -> Sender<EndPointVideoFrame> {
+    stats: Arc<DecodeStats>,
+) -> Sender<VideoDecodeMessage> {
     let (tx, mut rx) = tokio::sync::mpsc::channel(120);
 
     tokio::task::spawn_blocking(move || {
         tracing::info!(?id, "video decode process");
 
-        let mut decoder = VideoDecoder::new(render_tx);
+        let mut decoder = VideoDecoder::new(
+            id,
+            render_tx,
+            hardware_decode_supported(),
+            decode_fps_cap(),
+            stats.clone(),
+            Thumbnailer::new(id),
+        );
+        let mut pending = VecDeque::new();
+        let mut codec = VideoCodec::default();
+
+        let budget = stats.budget().clone();
+        register_media_budget(id, budget.clone());
+
+        'decode: while let Some(message) = rx.blocking_recv() {
+            // whatever queued up meanwhile is the backlog decoding fell behind
+            pending.push_back(message);
+            while let Ok(message) = rx.try_recv() {
+                pending.push_back(message);
+            }
+
+            // frames queued behind a codec change are of the new codec
+            if let Some(latest_codec) = latest_codec(&pending) {
+                codec = latest_codec;
+            }
+
+            let skipped = skip_to_latest_keyframe(&mut pending, &codec);
+            if skipped > 0 {
+                tracing::info!(?skipped, "decode falls behind, skip to latest keyframe");
+                stats.record_dropped(skipped);
+            }
+
+            let shed = budget.shed(&mut pending, &codec);
+            if shed > 0 {
+                tracing::warn!(?shed, "video exceeds memory budget, drop oldest frames");
+                decoder.wait_for_keyframe();
+            }
+
+            while let Some(message) = pending.pop_front() {
+                budget.set_queued_bytes(queued_bytes(&pending));
+
+                match message {
+                    VideoDecodeMessage::Frame(video_frame) => {
+                        dump_frame(&id, &video_frame);
 
-        while let Some(video_frame) = rx.blocking_recv() {
-            // let instant = std::time::Instant::now();
-            if let Err(err) = decoder.decode(video_frame) {
-                tracing::error!(?err, "decode video frame failed");
-                break;
+                        // let instant = std::time::Instant::now();
+                        if let Err(err) = decoder.decode(video_frame) {
+                            tracing::error!(?err, "decode video frame failed");
+                            report_error_event(ErrorSource::Decode, &id, &err.to_string());
+                            break 'decode;
+                        }
+                        // let elapsed = instant.elapsed();
+                        // tracing::info!(?elapsed, "instant");
+                    }
+                    VideoDecodeMessage::DisplayConfigChanged { width, height } => {
+                        tracing::info!(?width, ?height, "remote display config changed");
+                        decoder.reset();
+                    }
+                    VideoDecodeMessage::FramesLost => decoder.wait_for_keyframe(),
+                    VideoDecodeMessage::ParameterSets(parameter_sets) => {
+                        decoder.set_parameter_sets(parameter_sets)
+                    }
+                    VideoDecodeMessage::Codec(codec) => decoder.set_codec(codec),
+                }
             }
-            // let elapsed = instant.elapsed();
-            // tracing::info!(?elapsed, "instant");
+        }
+
+        unregister_media_budget(&id, &budget);
+
+        if let Err(err) = finish_frame_dump(&id, FrameDumpEnd::SessionEnded) {
+            tracing::error!(?err, "finish frame dump failed");
         }
 
         tracing::info!("video decode process exit");
@@ -30,3 +126,40 @@ pub fn serve_video_decode(
 
     tx
 }
+
+/// Drops the frames queued before the latest keyframe, nothing after it
+/// references them. Other messages stay in place. Returns how many frames
+/// were dropped.
+pub fn skip_to_latest_keyframe(
+    pending: &mut VecDeque<VideoDecodeMessage>,
+    codec: &VideoCodec,
+) -> u64 {
+    let latest_keyframe = pending.iter().rposition(|message| match message {
+        VideoDecodeMessage::Frame(video_frame) => is_keyframe(codec, &video_frame.buffer),
+        _ => false,
+    });
+
+    let Some(latest_keyframe) = latest_keyframe else {
+        return 0;
+    };
+
+    let mut index = 0;
+    let mut skipped = 0;
+    pending.retain(|message| {
+        let keep = index >= latest_keyframe || !matches!(message, VideoDecodeMessage::Frame(_));
+        index += 1;
+        if !keep {
+            skipped += 1;
+        }
+        keep
+    });
+
+    skipped
+}
+
+fn latest_codec(pending: &VecDeque<VideoDecodeMessage>) -> Option<VideoCodec> {
+    pending.iter().rev().find_map(|message| match message {
+        VideoDecodeMessage::Codec(codec) => Some(codec.clone()),
+        _ => None,
+    })
+}
@@ -0,0 +1,85 @@
+use super::{id::EndPointID, message::EndPointHandshakeResponse};
+use crate::{
+    error::{CoreError, CoreResult},
+    utility::bincode::bincode_deserialize,
+};
+use serde::Serialize;
+use std::fmt::Display;
+
+/// Why the handshake with the endpoints server failed, after the visit
+/// credentials were accepted locally.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HandshakeFailure {
+    /// The connection is a LAN one, there is no endpoints server to shake
+    /// hands with.
+    NotRelayed,
+    /// The server closed the connection instead of replying, it refuses
+    /// credentials another connection holds already.
+    Closed,
+    /// The reply isn't a handshake response.
+    MalformedReply,
+    /// The reply came from another address than the request went to.
+    UnexpectedReplyAddr,
+    /// The server joined the connection to another device than asked for.
+    MismatchedReply,
+    /// The remote device answered the capabilities or the negotiation with
+    /// another message.
+    UnexpectedReply,
+}
+
+impl Display for HandshakeFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let failure = match self {
+            HandshakeFailure::NotRelayed => "connection isn't relayed",
+            HandshakeFailure::Closed => "closed by the endpoints server",
+            HandshakeFailure::MalformedReply => "malformed reply",
+            HandshakeFailure::UnexpectedReplyAddr => "reply from an unexpected address",
+            HandshakeFailure::MismatchedReply => "reply for another device",
+            HandshakeFailure::UnexpectedReply => "unexpected reply of the remote device",
+        };
+
+        f.write_str(failure)
+    }
+}
+
+impl From<HandshakeFailure> for CoreError {
+    fn from(failure: HandshakeFailure) -> Self {
+        CoreError::HandshakeFailed(failure)
+    }
+}
+
+/// The local and the remote device id of a connection through the
+/// endpoints server.
+pub(crate) fn handshake_device_ids(endpoint_id: EndPointID) -> CoreResult<(i64, i64)> {
+    match endpoint_id {
+        EndPointID::DeviceID {
+            local_device_id,
+            remote_device_id,
+        } => Ok((local_device_id, remote_device_id)),
+        EndPointID::LANID { .. } => {
+            tracing::error!(?endpoint_id, "lan connection needn't handshake");
+            Err(HandshakeFailure::NotRelayed.into())
+        }
+    }
+}
+
+/// Checks the reply of the endpoints server joined the connection to the
+/// device it was asked for.
+pub fn check_handshake_response(buffer: &[u8], remote_device_id: i64) -> CoreResult<()> {
+    let resp: EndPointHandshakeResponse = bincode_deserialize(buffer).map_err(|err| {
+        tracing::error!(?err, "deserialize handshake response failed");
+        HandshakeFailure::MalformedReply
+    })?;
+
+    if resp.remote_device_id != remote_device_id {
+        tracing::error!(
+            expected = remote_device_id,
+            actual = resp.remote_device_id,
+            "endpoints server build mismatch tunnel"
+        );
+        return Err(HandshakeFailure::MismatchedReply.into());
+    }
+
+    Ok(())
+}
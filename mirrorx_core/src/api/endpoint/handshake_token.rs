@@ -0,0 +1,137 @@
+use crate::error::CoreError;
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::time::{Duration, Instant};
+
+/// Visit credentials are presented right after the visit is answered, a
+/// connection attempt that takes longer than this one is stale.
+pub const DEFAULT_HANDSHAKE_TOKEN_TTL: Duration = Duration::from_secs(30);
+
+static HANDSHAKE_TOKENS: Lazy<HandshakeTokens> =
+    Lazy::new(|| HandshakeTokens::new(DEFAULT_HANDSHAKE_TOKEN_TTL));
+
+pub fn handshake_tokens() -> &'static HandshakeTokens {
+    &HANDSHAKE_TOKENS
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HandshakeTokenStatus {
+    /// Issued, within its ttl and presented the first time.
+    Accepted,
+    /// Already presented for another connection.
+    Repeated,
+    /// Issued longer than the ttl ago.
+    Expired,
+    /// Never issued to this device, or pruned long ago.
+    Unknown,
+}
+
+impl HandshakeTokenStatus {
+    pub fn into_result(self) -> Result<(), CoreError> {
+        match self {
+            HandshakeTokenStatus::Accepted => Ok(()),
+            HandshakeTokenStatus::Repeated => Err(CoreError::HandshakeTokenRepeated),
+            HandshakeTokenStatus::Expired => Err(CoreError::HandshakeTokenExpired),
+            HandshakeTokenStatus::Unknown => Err(CoreError::HandshakeTokenUnknown),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct IssuedToken {
+    issued_at: Instant,
+    consumed: bool,
+}
+
+/// Lifecycle of the visit credentials the signaling server hands out with a
+/// visit, to both the visiting and the visited device. They are recorded
+/// when the visit is answered and consumed by the handshake with the
+/// endpoints server, so a set of credentials opens one connection, and only
+/// within the ttl. The endpoints server checks them on its side as well.
+#[derive(Debug)]
+pub struct HandshakeTokens {
+    ttl: Duration,
+    issued: DashMap<Vec<u8>, IssuedToken>,
+}
+
+impl HandshakeTokens {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            issued: DashMap::new(),
+        }
+    }
+
+    /// Records the credentials of a visit. Returns false when they were
+    /// recorded before, which means the visit was replayed.
+    pub fn issue(&self, token: &[u8], now: Instant) -> bool {
+        if self.issued.contains_key(token) {
+            tracing::warn!("visit credentials issued twice");
+            return false;
+        }
+
+        self.issued.insert(
+            token.to_vec(),
+            IssuedToken {
+                issued_at: now,
+                consumed: false,
+            },
+        );
+
+        true
+    }
+
+    /// Marks the credentials used by a handshake. Only `Accepted` lets the
+    /// handshake go on.
+    pub fn consume(&self, token: &[u8], now: Instant) -> HandshakeTokenStatus {
+        let Some(mut issued) = self.issued.get_mut(token) else {
+            return HandshakeTokenStatus::Unknown;
+        };
+
+        if issued.consumed {
+            return HandshakeTokenStatus::Repeated;
+        }
+
+        if now.saturating_duration_since(issued.issued_at) > self.ttl {
+            return HandshakeTokenStatus::Expired;
+        }
+
+        issued.consumed = true;
+        HandshakeTokenStatus::Accepted
+    }
+
+    /// Drops the credentials past their ttl. Consumed ones are kept until
+    /// then so that presenting them again reads as `Repeated`.
+    pub fn purge(&self, now: Instant) -> usize {
+        let before = self.issued.len();
+        self.issued
+            .retain(|_, issued| now.saturating_duration_since(issued.issued_at) <= self.ttl);
+        before - self.issued.len()
+    }
+
+    pub fn len(&self) -> usize {
+        self.issued.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.issued.is_empty()
+    }
+}
+
+/// Prunes the expired credentials once per ttl, for as long as the process
+/// runs.
+pub fn serve_handshake_token_cleanup() {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(handshake_tokens().ttl);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            interval.tick().await;
+
+            let purged = handshake_tokens().purge(Instant::now());
+            if purged > 0 {
+                tracing::info!(?purged, "purge expired visit credentials");
+            }
+        }
+    });
+}
@@ -0,0 +1,182 @@
+use super::{bandwidth::rtt_probe_timestamp, client::EndPointClient, message::EndPointMessage};
+use crate::utility::config_cell::ConfigCell;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, time::Duration};
+
+pub const MIN_HEARTBEAT_INTERVAL_MS: u64 = 500;
+
+pub const MAX_HEARTBEAT_INTERVAL_MS: u64 = 60 * 1000;
+
+pub const MAX_HEARTBEAT_FAILURES: u32 = 20;
+
+static HEARTBEAT_CONFIG: ConfigCell<HeartbeatConfig> = ConfigCell::new(HeartbeatConfig::default);
+
+static HEARTBEAT_STATE_TX: Lazy<tokio::sync::broadcast::Sender<HeartbeatStateEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+/// How often a connection probes the remote side, and how many intervals
+/// in a row may pass without hearing from it before it's torn down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeartbeatConfig {
+    pub interval_ms: u64,
+    pub failure_threshold: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        Self {
+            interval_ms: 3000,
+            failure_threshold: 3,
+        }
+    }
+}
+
+impl HeartbeatConfig {
+    pub fn sanitize(self) -> Self {
+        Self {
+            interval_ms: self
+                .interval_ms
+                .clamp(MIN_HEARTBEAT_INTERVAL_MS, MAX_HEARTBEAT_INTERVAL_MS),
+            failure_threshold: self.failure_threshold.clamp(1, MAX_HEARTBEAT_FAILURES),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_millis(self.interval_ms)
+    }
+}
+
+pub fn set_heartbeat_config(config: HeartbeatConfig) {
+    HEARTBEAT_CONFIG.set(config.sanitize());
+}
+
+pub fn heartbeat_config() -> HeartbeatConfig {
+    HEARTBEAT_CONFIG.get()
+}
+
+/// Whether the remote side of a connection is still heard from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeartbeatState {
+    Connected,
+    /// Nothing arrived for an interval or more, the connection may be
+    /// dead.
+    Degraded,
+    /// Nothing arrived for the failure threshold, the connection was
+    /// closed.
+    Disconnected,
+}
+
+/// Counts the heartbeat intervals in a row nothing arrived in.
+#[derive(Debug)]
+pub struct HeartbeatMonitor {
+    failure_threshold: u32,
+    missed: u32,
+}
+
+impl HeartbeatMonitor {
+    pub fn new(failure_threshold: u32) -> Self {
+        Self {
+            failure_threshold: failure_threshold.max(1),
+            missed: 0,
+        }
+    }
+
+    /// Takes whether anything arrived in the interval that just ended.
+    pub fn check(&mut self, heard: bool) -> HeartbeatState {
+        if heard {
+            self.missed = 0;
+        } else {
+            self.missed = self.missed.saturating_add(1);
+        }
+
+        match self.missed {
+            0 => HeartbeatState::Connected,
+            missed if missed < self.failure_threshold => HeartbeatState::Degraded,
+            _ => HeartbeatState::Disconnected,
+        }
+    }
+
+    pub fn missed(&self) -> u32 {
+        self.missed
+    }
+}
+
+/// A connection whose heartbeat state changed.
+#[derive(Debug, Clone, Serialize)]
+pub struct HeartbeatStateEvent {
+    pub endpoint_id: String,
+    pub state: HeartbeatState,
+    /// Intervals in a row nothing arrived in.
+    pub missed: u32,
+}
+
+pub fn subscribe_heartbeat_state() -> tokio::sync::broadcast::Receiver<HeartbeatStateEvent> {
+    HEARTBEAT_STATE_TX.subscribe()
+}
+
+/// Probes the remote side every interval and closes the connection once
+/// nothing arrived from it for the failure threshold. Any message counts,
+/// a busy link may hold back a probe reply behind the video. The task
+/// doesn't keep the client alive and exits with it.
+pub(crate) fn spawn_heartbeat(client: &Arc<EndPointClient>, config: HeartbeatConfig) {
+    let config = config.sanitize();
+    let close_token = client.close_token();
+    let client = Arc::downgrade(client);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(config.interval());
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        // the first tick completes right away
+        ticker.tick().await;
+
+        let mut monitor = HeartbeatMonitor::new(config.failure_threshold);
+        let mut received = None;
+
+        loop {
+            let Some(client) = client.upgrade() else {
+                return;
+            };
+
+            let now_received = client.raw_stats().received_messages();
+            if let Some(received) = received {
+                let state = monitor.check(now_received != received);
+
+                if client.heartbeat_state() != state {
+                    if state != HeartbeatState::Connected {
+                        tracing::warn!(
+                            endpoint_id = ?client.endpoint_id(),
+                            missed = monitor.missed(),
+                            ?state,
+                            "remote side is silent"
+                        );
+                    }
+
+                    client.set_heartbeat_state(state);
+                    let _ = HEARTBEAT_STATE_TX.send(HeartbeatStateEvent {
+                        endpoint_id: client.endpoint_id().to_string(),
+                        state,
+                        missed: monitor.missed(),
+                    });
+                }
+
+                if state == HeartbeatState::Disconnected {
+                    client.close();
+                    return;
+                }
+            }
+            received = Some(now_received);
+
+            // the reply, or the probe of the remote side, shows up by the
+            // next tick
+            let _ = client.try_send(&EndPointMessage::RttProbe(rtt_probe_timestamp()));
+            drop(client);
+
+            tokio::select! {
+                _ = close_token.cancelled() => return,
+                _ = ticker.tick() => {}
+            }
+        }
+    });
+}
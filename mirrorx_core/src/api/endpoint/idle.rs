@@ -0,0 +1,215 @@
+use super::{client::EndPointClient, message::DisconnectReason};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SessionRole {
+    /// This device shares its desktop.
+    Host,
+    /// This device watches and controls a remote desktop.
+    Viewer,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub struct IdleTimeoutPolicy {
+    /// Seconds without user activity before the session is closed, see
+    /// [`EndPointMessage::is_activity`](super::message::EndPointMessage::is_activity),
+    /// `None` keeps idle sessions open.
+    pub timeout_secs: Option<u64>,
+    /// Seconds before closing to warn the user.
+    pub warning_secs: u64,
+}
+
+impl Default for IdleTimeoutPolicy {
+    fn default() -> Self {
+        Self {
+            timeout_secs: None,
+            warning_secs: 60,
+        }
+    }
+}
+
+impl IdleTimeoutPolicy {
+    /// Returns the timeout and the idle time the warning is sent at, or
+    /// `None` when disabled.
+    pub fn thresholds(&self) -> Option<(Duration, Duration)> {
+        let timeout_secs = self.timeout_secs.filter(|secs| *secs > 0)?;
+        let warning_secs = self.warning_secs.min(timeout_secs);
+
+        Some((
+            Duration::from_secs(timeout_secs),
+            Duration::from_secs(timeout_secs - warning_secs),
+        ))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Default)]
+pub struct IdleTimeoutConfig {
+    pub host: IdleTimeoutPolicy,
+    pub viewer: IdleTimeoutPolicy,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum IdleTimeoutEvent {
+    Warning {
+        endpoint_id: String,
+        remaining_secs: u64,
+    },
+    Closed {
+        endpoint_id: String,
+    },
+}
+
+static IDLE_TIMEOUT_CONFIG: Lazy<RwLock<IdleTimeoutConfig>> =
+    Lazy::new(|| RwLock::new(IdleTimeoutConfig::default()));
+
+static IDLE_TIMEOUT_TX: Lazy<tokio::sync::broadcast::Sender<IdleTimeoutEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+pub fn subscribe_idle_timeout() -> tokio::sync::broadcast::Receiver<IdleTimeoutEvent> {
+    IDLE_TIMEOUT_TX.subscribe()
+}
+
+pub(crate) fn report_idle_timeout(event: IdleTimeoutEvent) {
+    tracing::info!(?event, "idle timeout");
+    let _ = IDLE_TIMEOUT_TX.send(event);
+}
+
+/// Applies to running sessions too, they read the policy on every check.
+pub fn set_idle_timeout_config(config: IdleTimeoutConfig) {
+    if let Ok(mut idle_timeout_config) = IDLE_TIMEOUT_CONFIG.write() {
+        *idle_timeout_config = config;
+    }
+}
+
+pub fn idle_timeout_config() -> IdleTimeoutConfig {
+    IDLE_TIMEOUT_CONFIG
+        .read()
+        .map(|config| *config)
+        .unwrap_or_default()
+}
+
+pub fn idle_timeout_policy(role: SessionRole) -> IdleTimeoutPolicy {
+    let config = idle_timeout_config();
+    match role {
+        SessionRole::Host => config.host,
+        SessionRole::Viewer => config.viewer,
+    }
+}
+
+/// Time of the last user activity of a session.
+#[derive(Debug)]
+pub struct IdleTracker {
+    created_at: Instant,
+    last_activity_millis: AtomicU64,
+}
+
+impl Default for IdleTracker {
+    fn default() -> Self {
+        Self {
+            created_at: Instant::now(),
+            last_activity_millis: AtomicU64::new(0),
+        }
+    }
+}
+
+impl IdleTracker {
+    pub fn touch(&self) {
+        let millis = self.created_at.elapsed().as_millis() as u64;
+        self.last_activity_millis
+            .fetch_max(millis, Ordering::Relaxed);
+    }
+
+    pub fn idle_for(&self) -> Duration {
+        let last_activity =
+            Duration::from_millis(self.last_activity_millis.load(Ordering::Relaxed));
+
+        self.created_at.elapsed().saturating_sub(last_activity)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum IdleAction {
+    Keep,
+    Warn { remaining: Duration },
+    Close,
+}
+
+/// Decides what to do with a session from its idle time, warning once per
+/// idle period.
+#[derive(Debug, Default)]
+pub struct IdleWatch {
+    warned: bool,
+}
+
+impl IdleWatch {
+    pub fn check(&mut self, policy: &IdleTimeoutPolicy, idle: Duration) -> IdleAction {
+        let Some((timeout, warning_at)) = policy.thresholds() else {
+            self.warned = false;
+            return IdleAction::Keep;
+        };
+
+        if idle >= timeout {
+            return IdleAction::Close;
+        }
+
+        if idle < warning_at {
+            self.warned = false;
+            return IdleAction::Keep;
+        }
+
+        if self.warned {
+            return IdleAction::Keep;
+        }
+
+        self.warned = true;
+        IdleAction::Warn {
+            remaining: timeout - idle,
+        }
+    }
+}
+
+/// Closes the session once it has been idle longer than the policy of its
+/// role allows. Exits with the session, or when it swaps to the other role.
+pub(crate) fn spawn_idle_watcher(client: Arc<EndPointClient>, role: SessionRole) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(IDLE_CHECK_INTERVAL);
+        let mut watch = IdleWatch::default();
+
+        loop {
+            tokio::select! {
+                _ = client.closed() => return,
+                _ = ticker.tick() => {}
+            }
+
+            if client.role() != Some(role) {
+                return;
+            }
+
+            let endpoint_id = client.endpoint_id().to_string();
+
+            match watch.check(&idle_timeout_policy(role), client.idle_for()) {
+                IdleAction::Keep => {}
+                IdleAction::Warn { remaining } => report_idle_timeout(IdleTimeoutEvent::Warning {
+                    endpoint_id,
+                    remaining_secs: remaining.as_secs(),
+                }),
+                IdleAction::Close => {
+                    report_idle_timeout(IdleTimeoutEvent::Closed { endpoint_id });
+                    client.close_with(DisconnectReason::IdleTimeout);
+                    return;
+                }
+            }
+        }
+    });
+}
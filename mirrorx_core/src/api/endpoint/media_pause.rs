@@ -0,0 +1,59 @@
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+/// How often a paused capture thread checks whether to resume.
+pub const MEDIA_PAUSE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Whether the video of a session is held back, e.g. while the viewer is
+/// minimized. Input and audio keep running. On the viewer it's what was
+/// asked for, on the host what was asked of it.
+#[derive(Debug, Default)]
+pub struct MediaPause {
+    paused: AtomicBool,
+    keyframe_pending: AtomicBool,
+}
+
+impl MediaPause {
+    /// Returns false when it was paused already.
+    pub fn pause(&self) -> bool {
+        !self.paused.swap(true, Ordering::SeqCst)
+    }
+
+    /// The next frame after a resume is a keyframe, the viewer dropped its
+    /// reference frames meanwhile. Returns false when it wasn't paused.
+    pub fn resume(&self) -> bool {
+        let resumed = self.paused.swap(false, Ordering::SeqCst);
+        if resumed {
+            self.keyframe_pending.store(true, Ordering::SeqCst);
+        }
+        resumed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// The next frame is encoded as a keyframe, the viewer asked for one
+    /// after its decoder started over.
+    pub fn request_keyframe(&self) {
+        self.keyframe_pending.store(true, Ordering::SeqCst);
+    }
+
+    /// Forgets the pause, a swapped session starts its media over.
+    pub fn reset(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+        self.keyframe_pending.store(false, Ordering::SeqCst);
+    }
+
+    /// Decides about a captured frame: `None` drops it while paused,
+    /// otherwise whether it must be encoded as a keyframe.
+    pub fn admit_frame(&self) -> Option<bool> {
+        if self.is_paused() {
+            return None;
+        }
+
+        Some(self.keyframe_pending.swap(false, Ordering::SeqCst))
+    }
+}
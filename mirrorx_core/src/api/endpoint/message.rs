@@ -1,4 +1,15 @@
-use crate::component::{desktop::monitor::Monitor, fs::Directory, input::key::MouseKey};
+use crate::api::endpoint::session_token::SessionToken;
+use crate::api::signaling::subscribe_message::VisitFailureReason;
+use crate::component::{
+    desktop::{capture_mode::CaptureMode, monitor::Monitor},
+    fs::{browse::DirectoryListing, Directory},
+    input::key::MouseKey,
+    video_encoder::{
+        content_hint::{ContentHint, ContentMode},
+        media_config::MediaConfig,
+    },
+};
+use crate::error::CoreError;
 use cpal::SampleFormat;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
@@ -17,7 +28,9 @@ pub struct EndPointHandshakeResponse {
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum EndPointMessage {
-    Error,
+    Error {
+        reason: MediaErrorReason,
+    },
     CallRequest(u16, EndPointCallRequest),
     CallReply(u16, #[serde(with = "serde_bytes")] Vec<u8>), // Vec -> Result<T, String>
     NegotiateDesktopParamsRequest(EndPointNegotiateDesktopParamsRequest),
@@ -28,6 +41,209 @@ pub enum EndPointMessage {
     InputCommand(EndPointInput),
     FileTransferBlock(EndPointFileTransferBlock),
     FileTransferError(EndPointFileTransferError),
+    FileTransferCancel(EndPointFileTransferCancel),
+    /// The user asked to keep the idle session open.
+    KeepAlive,
+    /// Asks the capturing side to draw the pointer into the frames or not.
+    IncludeCursor(bool),
+    /// The captured display changed its size, frames of the new size follow.
+    DisplayConfigChanged {
+        width: i32,
+        height: i32,
+    },
+    VideoFrameParity(EndPointVideoFrameParity),
+    /// A note typed by the user on the other side, `timestamp` is in unix
+    /// milliseconds of the sender.
+    ChatMessage {
+        text: String,
+        timestamp: i64,
+    },
+    /// Measures the round trip, the receiver echoes the value back in a
+    /// `RttProbeReply` right away. Sent periodically, it's the heartbeat of
+    /// the session and leaves the idle timer alone.
+    RttProbe(u64),
+    RttProbeReply(u64),
+    /// Asks to flip the desktop session on the same connection, the side
+    /// sharing its screen starts watching and the other way round.
+    SwapDirectionRequest,
+    /// Answers a `SwapDirectionRequest`. Once accepted the side that starts
+    /// watching negotiates the media like a new viewer does.
+    SwapDirectionReply {
+        accepted: bool,
+    },
+    /// Asks the capturing side to tune its encoder for this content.
+    ContentHint(ContentHint),
+    /// The capturing side tuned its encoder for other content, starting with
+    /// the next keyframe. `None` when it encodes with the media config as is.
+    ContentModeChanged(Option<ContentMode>),
+    /// Everything the sender supports, both sides send it once right after
+    /// the handshake and agree on what they both support.
+    Capabilities(EndPointCapabilities),
+    FileTransferAck(EndPointFileTransferAck),
+    /// Asks the host to stop capturing and sending video until
+    /// `ResumeMedia`, the session, input and audio keep running.
+    PauseMedia,
+    /// Starts the video again, beginning with a keyframe.
+    ResumeMedia,
+    /// The parameter sets the keyframes of this size decode with, sent by
+    /// the host ahead of the first frame that uses them.
+    VideoParameterSets(EndPointVideoParameterSets),
+    /// A part of an encoded frame, sent in place of `VideoFrame` when the
+    /// media config asks for more than one slice per frame.
+    VideoFrameSlice(EndPointVideoFrameSlice),
+    /// The screen of the host didn't change, sent about every second in
+    /// place of frames in change only mode. The viewer keeps showing the
+    /// last frame.
+    VideoUnchanged,
+    /// How the host captures the video, sent when its capture starts and
+    /// when it falls back to polling.
+    CaptureModeChanged(CaptureMode),
+    /// Asks the host to encode the next frame as a keyframe, the decoder of
+    /// the viewer started over.
+    RequestKeyFrame,
+    /// Starts a rotation of the session keys, sealed with the current ones.
+    RekeyRequest(EndPointRekey),
+    /// Answers a `RekeyRequest`, the sender switches right after it.
+    RekeyReply(EndPointRekey),
+    /// The last frame its sender sealed with the old keys, those after it
+    /// are sealed with the keys of the rotation.
+    RekeySwitch,
+    /// The clipboard of the sender changed, only sent while both sides
+    /// sync the clipboard of the session.
+    ClipboardUpdate {
+        content: ClipboardContent,
+    },
+    /// What the viewer received lately, sent about every second to a host
+    /// that supports it. The host adapts its encoder to it.
+    NetworkReport(EndPointNetworkReport),
+    /// The sender is closing the connection on purpose, the last message
+    /// before its end of the stream.
+    Disconnect {
+        reason: DisconnectReason,
+    },
+    /// The host encodes on another graphics card than it negotiated, `None`
+    /// once it fell back to the software encoder.
+    EncoderGraphicsCard(Option<String>),
+    /// The passive side turned the connection down before the session
+    /// started, the only message it sends then.
+    VisitFailure(VisitFailureReason),
+}
+
+impl EndPointMessage {
+    /// Whether the message comes from a user working with the session, only
+    /// these reset the idle timer. Media frames, rtt probes and the other
+    /// control messages flow on their own, counting them would keep an
+    /// abandoned session open forever.
+    pub fn is_activity(&self) -> bool {
+        matches!(
+            self,
+            EndPointMessage::InputCommand(_)
+                | EndPointMessage::KeepAlive
+                | EndPointMessage::ChatMessage { .. }
+        )
+    }
+
+    /// Whether the message carries video or audio, these are dropped when
+    /// the send queue is full. A later frame or a keyframe makes up for a
+    /// lost one, a lost control message breaks the session.
+    pub fn is_media_frame(&self) -> bool {
+        matches!(
+            self,
+            EndPointMessage::VideoFrame(_)
+                | EndPointMessage::VideoFrameSlice(_)
+                | EndPointMessage::VideoFrameParity(_)
+                | EndPointMessage::AudioFrame(_)
+        )
+    }
+}
+
+/// Why a side closed the connection, tells a graceful exit apart from a
+/// crash or a broken network.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum DisconnectReason {
+    /// The user closed the session.
+    SessionClosed,
+    /// The app is quitting.
+    AppQuit,
+    /// Nobody worked with the session for too long.
+    IdleTimeout,
+    /// Reasons added by newer versions deserialize to this variant, it must
+    /// stay the last one.
+    #[serde(other)]
+    Other,
+}
+
+/// Why the passive endpoint can't provide the media stream.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MediaErrorReason {
+    PermissionDenied,
+    NoDisplay,
+    CodecUnsupported,
+    /// The shared window was closed, the stream ended with it.
+    WindowClosed,
+    /// The screen capture couldn't be created although the permission was
+    /// granted, e.g. the graphics driver failed.
+    CaptureFailed,
+    /// Reasons added by newer versions deserialize to this variant, it must
+    /// stay the last one.
+    #[serde(other)]
+    Other,
+}
+
+impl From<MediaErrorReason> for CoreError {
+    fn from(reason: MediaErrorReason) -> Self {
+        match reason {
+            MediaErrorReason::PermissionDenied => CoreError::MediaPermissionDenied,
+            MediaErrorReason::NoDisplay => CoreError::MediaNoDisplay,
+            MediaErrorReason::CodecUnsupported => CoreError::MediaCodecUnsupported,
+            MediaErrorReason::WindowClosed => CoreError::MediaWindowClosed,
+            MediaErrorReason::CaptureFailed => CoreError::MediaCaptureFailed,
+            MediaErrorReason::Other => CoreError::MediaOtherError,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointCapabilities {
+    /// Names of the video codecs, preferred first. Names keep codecs added
+    /// by newer versions readable.
+    pub video_codecs: Vec<String>,
+    /// Names of the AEADs sealing the packets, preferred first.
+    pub aeads: Vec<String>,
+    pub features: Vec<SessionFeature>,
+    /// Longest frame the sender reads.
+    pub max_frame_length: u32,
+    /// The name the sender shows to others.
+    pub device_name: String,
+    /// Names of the keyboard input encodings, see
+    /// [`InputEncoding`](crate::component::input::encoding::InputEncoding),
+    /// preferred first.
+    pub input_encodings: Vec<String>,
+}
+
+/// Parts of a session either side may lack.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionFeature {
+    Audio,
+    Input,
+    FileTransfer,
+    Chat,
+    Swap,
+    Fec,
+    Clipboard,
+    NetworkReport,
+    /// Features added by newer versions deserialize to this variant, it must
+    /// stay the last one.
+    #[serde(other)]
+    Unknown,
+}
+
+/// What a clipboard holds, other kinds of content aren't synced.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Hash)]
+pub enum ClipboardContent {
+    Text(String),
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -35,19 +251,52 @@ pub enum EndPointCallRequest {
     VisitDirectoryRequest(EndPointVisitDirectoryRequest),
     SendFileRequest(EndPointSendFileRequest),
     DownloadFileRequest(EndPointDownloadFileRequest),
+    ListDirectoryRequest(EndPointListDirectoryRequest),
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointNegotiateDesktopParamsRequest {
     pub video_codecs: Vec<VideoCodec>,
+    /// What the viewer decodes at most.
+    pub media_limits: EndPointMediaLimits,
+    /// Format the viewer plays, `None` for a session without audio.
+    pub audio: Option<EndPointAudioParams>,
+    /// Token of an earlier session with the remote endpoint, its params are
+    /// taken over instead of negotiated again.
+    pub rejoin_token: Option<SessionToken>,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointNegotiateVisitDesktopParams {
     pub video_codec: VideoCodec,
+    /// What both sides handle, the media config of the viewer is held
+    /// within.
+    pub media_limits: EndPointMediaLimits,
+    /// Format of the audio frames, `None` when the session has no audio.
+    pub audio: Option<EndPointAudioParams>,
     pub os_type: String,
     pub os_version: String,
     pub primary_monitor: Monitor,
+    /// Name of the graphics card running the video encoder, `None` when the
+    /// software encoder is used.
+    pub encoder_graphics_card: Option<String>,
+    /// Token to rejoin this session with after the connection dropped.
+    pub session_token: Option<SessionToken>,
+}
+
+/// Frame rate and frame size a side of a desktop session handles.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EndPointMediaLimits {
+    pub max_frame_rate: u8,
+    pub max_width: u16,
+    pub max_height: u16,
+}
+
+/// Sample rate and channel count of an audio stream.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EndPointAudioParams {
+    pub sample_rate: u32,
+    pub channels: u8,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
@@ -108,14 +357,56 @@ impl Default for AudioSampleFormat {
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointNegotiateFinishedRequest {
     // pub selected_monitor_id: String,
-    pub expected_frame_rate: u8,
+    pub media_config: MediaConfig,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointVideoFrame {
+    /// Position of the frame in the stream, consecutive frames differ by one.
+    pub seq: u64,
+    pub width: i32,
+    pub height: i32,
+    pub pts: i64,
+
+    #[serde(with = "serde_bytes")]
+    pub buffer: Vec<u8>,
+}
+
+/// One of the slices a frame was cut into, the viewer decodes the frame
+/// once all `slice_count` of them arrived.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointVideoFrameSlice {
+    pub seq: u64,
+    pub slice_index: u8,
+    pub slice_count: u8,
+    pub width: i32,
+    pub height: i32,
+    pub pts: i64,
+
+    #[serde(with = "serde_bytes")]
+    pub buffer: Vec<u8>,
+}
+
+/// SPS and PPS of an H.264 stream in Annex B, each with its start code.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointVideoParameterSets {
+    pub width: i32,
+    pub height: i32,
+
+    #[serde(with = "serde_bytes")]
+    pub buffer: Vec<u8>,
+}
+
+/// XOR of every field of the frames `first_seq..first_seq + count`, restores
+/// any single frame of them that got lost.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointVideoFrameParity {
+    pub first_seq: u64,
+    pub count: u32,
     pub width: i32,
     pub height: i32,
     pub pts: i64,
+    pub length: u64,
 
     #[serde(with = "serde_bytes")]
     pub buffer: Vec<u8>,
@@ -126,10 +417,16 @@ pub struct EndPointAudioFrame {
     pub channels: u8,
     pub sample_format: AudioSampleFormat,
     pub sample_rate: u32,
+    /// Counts the frames of the stream, a gap is a lost frame.
+    pub seq: u64,
+    /// Microseconds of audio the stream carried before this frame.
+    pub pts: i64,
     #[serde(with = "serde_bytes")]
     pub buffer: Vec<u8>,
 }
 
+/// Positions are normalized to the captured frame, see
+/// [`ViewMapping`](crate::component::input::coordinate::ViewMapping).
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
 pub enum MouseEvent {
     Up(MouseKey, f32, f32),
@@ -142,6 +439,9 @@ pub enum MouseEvent {
 pub enum KeyboardEvent {
     KeyUp(tao::keyboard::KeyCode),
     KeyDown(tao::keyboard::KeyCode),
+    /// Characters typed on the viewer, only sent once both sides agreed on
+    /// the Unicode input encoding.
+    Text(String),
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Clone)]
@@ -165,36 +465,110 @@ pub struct EndPointVisitDirectoryResponse {
     pub dir: Directory,
 }
 
+/// Browses the directories the remote side exposes, `path` is relative to
+/// the exposed root.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointListDirectoryRequest {
+    pub path: PathBuf,
+    pub offset: u32,
+    pub limit: u32,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointListDirectoryResponse {
+    pub listing: DirectoryListing,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointSendFileRequest {
     pub id: String,
     pub filename: String,
     pub path: PathBuf,
     pub size: u64,
+    /// SHA-256 of the file, an earlier attempt resumes only while it holds.
+    #[serde(with = "serde_bytes")]
+    pub hash: Vec<u8>,
 }
 
+/// The receiver has the file up to `offset` from an earlier attempt, the
+/// sender goes on from there.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
-pub struct EndPointSendFileReply {}
+pub struct EndPointSendFileReply {
+    pub offset: u64,
+}
 
+/// `hash` and `offset` describe what an earlier attempt left on the
+/// receiving side, `None` when there is nothing to resume.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointDownloadFileRequest {
     pub id: String,
     pub path: PathBuf,
+    #[serde(with = "serde_bytes")]
+    pub hash: Option<Vec<u8>>,
+    pub offset: u64,
 }
 
+/// The file is sent from `offset`, zero when it changed since the earlier
+/// attempt.
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointDownloadFileReply {
     pub size: u64,
+    #[serde(with = "serde_bytes")]
+    pub hash: Vec<u8>,
+    pub offset: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointFileTransferBlock {
     pub id: String,
+    /// Position of the block in the file, the end of the file when `data`
+    /// is `None`.
+    pub offset: u64,
     #[serde(with = "serde_bytes")]
     pub data: Option<Vec<u8>>,
 }
 
+/// The receiver wrote the file up to `offset`, the sender may have that
+/// many more bytes in flight.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointFileTransferAck {
+    pub id: String,
+    pub offset: u64,
+}
+
+/// Covers the time since the report before.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct EndPointNetworkReport {
+    /// Bits per second the viewer received, all messages included.
+    pub received_bps: u64,
+    /// Video frames lost in thousandths of those sent.
+    pub loss_permille: u16,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
 pub struct EndPointFileTransferError {
     pub id: String,
 }
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub enum FileTransferCancelReason {
+    UserCancelled,
+    SessionClosed,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointFileTransferCancel {
+    pub id: String,
+    pub reason: FileTransferCancelReason,
+    pub keep_partial: bool,
+}
+
+/// One half of a key rotation, the X25519 public key of the sender and the
+/// nonce its keys are salted with.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct EndPointRekey {
+    #[serde(with = "serde_bytes")]
+    pub public_key: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub nonce: Vec<u8>,
+}
@@ -1,14 +1,36 @@
+pub mod bandwidth;
+pub mod call_store;
+pub mod call_timeout;
+pub mod capabilities;
+pub mod chat;
+pub mod clipboard;
 pub mod client;
+pub mod client_cache;
+pub mod connect_attempt;
+pub mod connect_retry;
+pub mod disconnect;
 pub mod handlers;
+pub mod handshake;
+pub mod handshake_token;
+pub mod heartbeat;
 pub mod id;
+pub mod idle;
+pub mod media_pause;
 pub mod message;
+pub mod network_info;
+pub mod packet;
+pub mod path_preference;
+pub mod rekey;
+pub mod session;
+pub mod session_limit;
+pub mod session_token;
+pub mod sessions;
+pub mod socket_buffer;
+pub mod stats;
+pub mod swap;
 
-use self::{
-    client::EndPointClient,
-    handlers::{audio_frame::serve_audio_decode, video_frame::serve_video_decode},
-    id::EndPointID,
-};
-use crate::{error::CoreResult, utility::nonce_value::NonceValue, DesktopDecodeFrame};
+use self::{client::EndPointClient, id::EndPointID, session_limit::IncomingSessionPermit};
+use crate::{error::CoreResult, utility::nonce_value::NonceValue};
 use ring::aead::{OpeningKey, SealingKey};
 use std::{net::SocketAddr, sync::Arc};
 use tokio::net::{TcpStream, UdpSocket};
@@ -23,34 +45,6 @@ pub enum EndPointStream {
     },
 }
 
-pub async fn create_desktop_active_endpoint_client(
-    endpoint_id: EndPointID,
-    key_pair: Option<(OpeningKey<NonceValue>, SealingKey<NonceValue>)>,
-    stream: EndPointStream,
-    visit_credentials: Option<Vec<u8>>,
-) -> CoreResult<(
-    Arc<EndPointClient>,
-    tokio::sync::mpsc::Receiver<DesktopDecodeFrame>,
-)> {
-    let (render_frame_tx, render_frame_rx) = tokio::sync::mpsc::channel(180);
-    let (audio_frame_tx, audio_frame_rx) = tokio::sync::mpsc::channel(180);
-
-    let video_frame_tx = serve_video_decode(endpoint_id, render_frame_tx);
-    serve_audio_decode(endpoint_id, audio_frame_rx);
-
-    let client = EndPointClient::new_desktop_active(
-        endpoint_id,
-        key_pair,
-        stream,
-        video_frame_tx,
-        audio_frame_tx,
-        visit_credentials,
-    )
-    .await?;
-
-    Ok((client, render_frame_rx))
-}
-
 pub async fn create_file_manager_active_endpoint_client(
     endpoint_id: EndPointID,
     key_pair: Option<(OpeningKey<NonceValue>, SealingKey<NonceValue>)>,
@@ -69,7 +63,15 @@ pub async fn create_passive_endpoint_client(
     key_pair: Option<(OpeningKey<NonceValue>, SealingKey<NonceValue>)>,
     stream: EndPointStream,
     visit_credentials: Option<Vec<u8>>,
+    session_permit: IncomingSessionPermit,
 ) -> CoreResult<()> {
-    EndPointClient::new_passive(endpoint_id, key_pair, stream, visit_credentials).await?;
+    EndPointClient::new_passive(
+        endpoint_id,
+        key_pair,
+        stream,
+        visit_credentials,
+        session_permit,
+    )
+    .await?;
     Ok(())
 }
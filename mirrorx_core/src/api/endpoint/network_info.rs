@@ -0,0 +1,61 @@
+use super::{id::EndPointID, message::VideoCodec, path_preference::PathReason};
+use crate::component::{
+    desktop::capture_mode::CaptureMode, video_encoder::encode_timing::EncodeLatency,
+};
+use serde::Serialize;
+
+/// Packets of sessions with a device id pass the endpoints server, LAN
+/// sessions connect to the remote ip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NetworkPath {
+    Direct,
+    Relayed,
+}
+
+impl From<&EndPointID> for NetworkPath {
+    fn from(endpoint_id: &EndPointID) -> Self {
+        match endpoint_id {
+            EndPointID::DeviceID { .. } => NetworkPath::Relayed,
+            EndPointID::LANID { .. } => NetworkPath::Direct,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Transport {
+    Tcp,
+    Udp,
+}
+
+/// How a session reaches its remote device, put together from what the
+/// connection already tracks so it's cheap to ask for repeatedly.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct NetworkInfo {
+    pub path: NetworkPath,
+    pub path_reason: PathReason,
+    pub transport: Transport,
+    /// AEAD algorithm sealing the packets, `None` for unencrypted LAN
+    /// connections.
+    pub aead: Option<&'static str>,
+    /// Latest round trip in milliseconds, `None` until a probe came back.
+    pub rtt_ms: Option<f64>,
+    /// Codec of the video either side sends, `None` until the desktop
+    /// session was negotiated.
+    pub video_codec: Option<VideoCodec>,
+    /// How the side sending the video captures it, `None` until its
+    /// capture started.
+    pub capture_mode: Option<CaptureMode>,
+    /// Available bandwidth towards the remote side, zero on the watching
+    /// side, only the side that sends video measures it.
+    pub estimated_bps: u64,
+    pub sent_bytes: u64,
+    /// Received video payload, parity frames excluded.
+    pub video_frame_bytes: u64,
+    /// Whether the video is paused, input and audio keep running.
+    pub media_paused: bool,
+    /// Encode latency of the recent frames, `None` on the side that doesn't
+    /// send video.
+    pub encode_latency: Option<EncodeLatency>,
+}
@@ -0,0 +1,118 @@
+use super::message::EndPointMessage;
+use crate::{
+    bail_core,
+    error::{CoreError, CoreResult},
+    utility::{
+        bincode::{bincode_deserialize, bincode_serialize_into},
+        config_cell::ConfigCell,
+    },
+};
+use tokio_util::codec::LengthDelimitedCodec;
+
+/// Format of the endpoint packets this app sends and reads, the first byte
+/// of every packet ahead of the bincode encoded message. Bump it whenever
+/// the wire format changes in a way older apps can't read.
+pub const PACKET_FORMAT_VERSION: u8 = 18;
+
+/// Longest frame this side accepts unless configured otherwise.
+pub const DEFAULT_MAX_FRAME_LENGTH: usize = 32 * 1024 * 1024;
+
+pub const MIN_FRAME_LENGTH_LIMIT: usize = 1024 * 1024;
+
+/// A keyframe of a large multi monitor capture may not fit the default,
+/// a frame is still held in memory as a whole.
+pub const MAX_FRAME_LENGTH_LIMIT: usize = 256 * 1024 * 1024;
+
+static MAX_FRAME_LENGTH: ConfigCell<usize> = ConfigCell::new(|| DEFAULT_MAX_FRAME_LENGTH);
+
+/// Byte orders and integer encoding of the packets. Both sides must agree
+/// on all of them, otherwise frames are cut at the wrong length or messages
+/// decode to garbage instead of failing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecConfig {
+    /// Byte order of the length prefix of every frame.
+    pub frame_little_endian: bool,
+    /// Byte order of fixed size integers in bincode.
+    pub bincode_little_endian: bool,
+    /// Whether bincode writes integers in its variable length encoding.
+    pub bincode_varint: bool,
+}
+
+/// The config this app speaks. `utility::bincode` and [`frame_codec`] are
+/// set up to match it.
+pub const CODEC_CONFIG: CodecConfig = CodecConfig {
+    frame_little_endian: true,
+    bincode_little_endian: true,
+    bincode_varint: true,
+};
+
+impl CodecConfig {
+    /// One bit per setting, the second byte of every packet.
+    pub const fn fingerprint(self) -> u8 {
+        (self.frame_little_endian as u8)
+            | (self.bincode_little_endian as u8) << 1
+            | (self.bincode_varint as u8) << 2
+    }
+}
+
+pub fn sanitize_max_frame_length(max_frame_length: usize) -> usize {
+    max_frame_length.clamp(MIN_FRAME_LENGTH_LIMIT, MAX_FRAME_LENGTH_LIMIT)
+}
+
+/// Sessions send frames up to the lower limit of both sides.
+pub fn set_max_frame_length(max_frame_length: usize) {
+    MAX_FRAME_LENGTH.set(sanitize_max_frame_length(max_frame_length));
+}
+
+pub fn max_frame_length() -> usize {
+    MAX_FRAME_LENGTH.get()
+}
+
+/// Frames endpoint packets with a length prefix of [`CODEC_CONFIG`], up to
+/// [`max_frame_length`] long.
+pub fn frame_codec() -> LengthDelimitedCodec {
+    let mut builder = LengthDelimitedCodec::builder();
+
+    if CODEC_CONFIG.frame_little_endian {
+        builder.little_endian();
+    } else {
+        builder.big_endian();
+    }
+
+    builder.max_frame_length(max_frame_length()).new_codec()
+}
+
+pub fn serialize_packet(message: &EndPointMessage) -> CoreResult<Vec<u8>> {
+    let mut buffer = vec![PACKET_FORMAT_VERSION, CODEC_CONFIG.fingerprint()];
+    bincode_serialize_into(&mut buffer, message)?;
+    Ok(buffer)
+}
+
+/// Fails with [`CoreError::ProtocolMismatch`] when the packet was written in
+/// another format version or with another codec config, so the first packet
+/// of a mismatched peer ends the connection.
+pub fn deserialize_packet(buffer: &[u8]) -> CoreResult<EndPointMessage> {
+    let Some((&version, buffer)) = buffer.split_first() else {
+        bail_core!("empty endpoint packet");
+    };
+
+    if version != PACKET_FORMAT_VERSION {
+        return Err(CoreError::ProtocolMismatch {
+            version,
+            codec: None,
+        });
+    }
+
+    let Some((&codec, payload)) = buffer.split_first() else {
+        bail_core!("endpoint packet without codec fingerprint");
+    };
+
+    if codec != CODEC_CONFIG.fingerprint() {
+        return Err(CoreError::ProtocolMismatch {
+            version,
+            codec: Some(codec),
+        });
+    }
+
+    bincode_deserialize(payload)
+}
@@ -0,0 +1,67 @@
+use super::network_info::NetworkPath;
+use crate::{core_error, error::CoreResult, utility::config_cell::ConfigCell};
+use serde::{Deserialize, Serialize};
+
+static PATH_PREFERENCE: ConfigCell<PathPreference> = ConfigCell::new(PathPreference::default);
+
+/// The path this device connects to remote devices along.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathPreference {
+    /// The direct path when the connection offers it, the relay otherwise.
+    #[default]
+    Auto,
+    /// Never through the relay, a connection without a direct path fails.
+    ForceDirect,
+    /// Only through the relay, the remote ip isn't connected to.
+    ForceRelay,
+}
+
+/// Why a session uses its path.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PathReason {
+    /// Picked among the paths the connection offers.
+    Auto,
+    /// The path preference of this device asked for it.
+    Forced,
+    /// The remote device opened the connection along it.
+    Incoming,
+}
+
+pub fn set_path_preference(preference: PathPreference) {
+    PATH_PREFERENCE.set(preference);
+}
+
+pub fn path_preference() -> PathPreference {
+    PATH_PREFERENCE.get()
+}
+
+/// Picks the path of a connection among the `available` ones. Fails when
+/// the preference forces a path that isn't available, rather than falling
+/// back to the other one.
+pub fn select_path(
+    preference: PathPreference,
+    available: &[NetworkPath],
+) -> CoreResult<(NetworkPath, PathReason)> {
+    let forced = match preference {
+        PathPreference::Auto => None,
+        PathPreference::ForceDirect => Some(NetworkPath::Direct),
+        PathPreference::ForceRelay => Some(NetworkPath::Relayed),
+    };
+
+    match forced {
+        Some(path) if available.contains(&path) => Ok((path, PathReason::Forced)),
+        Some(NetworkPath::Direct) => Err(core_error!(
+            "no direct path to the remote device and the relay is disabled"
+        )),
+        Some(NetworkPath::Relayed) => Err(core_error!(
+            "no relayed path to the remote device and direct connections are disabled"
+        )),
+        None => [NetworkPath::Direct, NetworkPath::Relayed]
+            .into_iter()
+            .find(|path| available.contains(path))
+            .map(|path| (path, PathReason::Auto))
+            .ok_or_else(|| core_error!("no path to the remote device")),
+    }
+}
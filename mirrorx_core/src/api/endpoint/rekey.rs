@@ -0,0 +1,379 @@
+use super::{
+    client::EndPointClient,
+    message::{EndPointMessage, EndPointRekey},
+    packet::serialize_packet,
+};
+use crate::{
+    api::signaling::key_exchange::{KeyDerivation, KeyDirection},
+    bail_core, core_error,
+    error::CoreResult,
+    utility::{config_cell::ConfigCell, nonce_value::NonceValue},
+};
+use once_cell::sync::Lazy;
+use ring::aead::{BoundKey, OpeningKey, SealingKey, UnboundKey, AES_256_GCM};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{UnboundedReceiver, UnboundedSender},
+    oneshot,
+};
+
+pub const MIN_REKEY_INTERVAL_SECS: u64 = 60;
+
+pub const MAX_REKEY_INTERVAL_SECS: u64 = 24 * 60 * 60;
+
+pub const MIN_REKEY_BYTES: u64 = 64 * 1024 * 1024;
+
+/// How often the rekey timer looks at the age and traffic of the keys.
+const REKEY_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+static REKEY_POLICY: ConfigCell<RekeyPolicy> = ConfigCell::new(RekeyPolicy::default);
+
+/// The serialized `RekeySwitch`, the loops compare the plaintext of every
+/// frame with it rather than deserializing video frames twice.
+static REKEY_SWITCH_PACKET: Lazy<Vec<u8>> =
+    Lazy::new(|| serialize_packet(&EndPointMessage::RekeySwitch).unwrap_or_default());
+
+/// When the side that opened an encrypted connection rotates its keys,
+/// whichever of the age or the traffic of the keys comes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RekeyPolicy {
+    pub interval_secs: u64,
+    /// Bytes sent and received under the same keys.
+    pub max_bytes: u64,
+}
+
+impl Default for RekeyPolicy {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60 * 60,
+            max_bytes: 64 * 1024 * 1024 * 1024,
+        }
+    }
+}
+
+impl RekeyPolicy {
+    pub fn sanitize(self) -> Self {
+        Self {
+            interval_secs: self
+                .interval_secs
+                .clamp(MIN_REKEY_INTERVAL_SECS, MAX_REKEY_INTERVAL_SECS),
+            max_bytes: self.max_bytes.max(MIN_REKEY_BYTES),
+        }
+    }
+
+    pub fn is_due(&self, age: Duration, bytes: u64) -> bool {
+        age >= Duration::from_secs(self.interval_secs) || bytes >= self.max_bytes
+    }
+}
+
+pub fn set_rekey_policy(policy: RekeyPolicy) {
+    REKEY_POLICY.set(policy.sanitize());
+}
+
+pub fn rekey_policy() -> RekeyPolicy {
+    REKEY_POLICY.get()
+}
+
+/// Whether a frame is the `RekeySwitch` marker, the last frame its sender
+/// sealed with the old keys.
+pub fn is_rekey_switch(buffer: &[u8]) -> bool {
+    buffer == REKEY_SWITCH_PACKET.as_slice()
+}
+
+/// The X25519 key and the nonce one side brings to a key rotation.
+pub struct RekeyOffer {
+    private_key: ring::agreement::EphemeralPrivateKey,
+    public_key: Vec<u8>,
+    nonce: [u8; ring::aead::NONCE_LEN],
+}
+
+impl RekeyOffer {
+    pub fn generate() -> CoreResult<Self> {
+        let private_key = ring::agreement::EphemeralPrivateKey::generate(
+            &ring::agreement::X25519,
+            &ring::rand::SystemRandom::new(),
+        )?;
+        let public_key = private_key.compute_public_key()?.as_ref().to_vec();
+
+        Ok(Self {
+            private_key,
+            public_key,
+            nonce: rand::random(),
+        })
+    }
+
+    pub fn message(&self) -> EndPointRekey {
+        EndPointRekey {
+            public_key: self.public_key.clone(),
+            nonce: self.nonce.to_vec(),
+        }
+    }
+
+    /// The keys after the rotation, derived like those of the visit key
+    /// exchange. `active` is whether this side opened the connection.
+    pub fn agree(
+        self,
+        remote: &EndPointRekey,
+        active: bool,
+    ) -> CoreResult<(OpeningKey<NonceValue>, SealingKey<NonceValue>)> {
+        let remote_nonce: [u8; ring::aead::NONCE_LEN] = remote
+            .nonce
+            .as_slice()
+            .try_into()
+            .map_err(|_| core_error!("invalid rekey nonce length"))?;
+
+        let (sealing_direction, opening_direction) = if active {
+            (KeyDirection::ActiveToPassive, KeyDirection::PassiveToActive)
+        } else {
+            (KeyDirection::PassiveToActive, KeyDirection::ActiveToPassive)
+        };
+
+        let remote_public_key =
+            ring::agreement::UnparsedPublicKey::new(&ring::agreement::X25519, &remote.public_key);
+
+        let local_nonce = self.nonce;
+        let (raw_sealing_key, raw_opening_key) = ring::agreement::agree_ephemeral(
+            self.private_key,
+            &remote_public_key,
+            ring::error::Unspecified,
+            |key_material| {
+                let sealing_key =
+                    KeyDerivation::LATEST.derive(key_material, &local_nonce, sealing_direction)?;
+                let opening_key =
+                    KeyDerivation::LATEST.derive(key_material, &remote_nonce, opening_direction)?;
+
+                Ok((sealing_key, opening_key))
+            },
+        )?;
+
+        let sealing_key = SealingKey::new(
+            UnboundKey::new(&AES_256_GCM, &raw_sealing_key)?,
+            NonceValue::new(remote_nonce),
+        );
+        let opening_key = OpeningKey::new(
+            UnboundKey::new(&AES_256_GCM, &raw_opening_key)?,
+            NonceValue::new(local_nonce),
+        );
+
+        Ok((opening_key, sealing_key))
+    }
+}
+
+/// A key of the read or write loop, and the keys of the rotations to come
+/// in the order they were agreed on.
+pub struct RotatingKey<K> {
+    key: K,
+    next: UnboundedReceiver<K>,
+}
+
+impl<K> RotatingKey<K> {
+    pub fn key_mut(&mut self) -> &mut K {
+        &mut self.key
+    }
+
+    /// Moves on to the key of the next rotation, false when it wasn't agreed
+    /// on yet.
+    pub fn try_rotate(&mut self) -> bool {
+        match self.next.try_recv() {
+            Ok(key) => {
+                self.key = key;
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    /// Like `try_rotate`, waits up to `timeout` for the key. The switch
+    /// marker of the remote side may be read before this side handled the
+    /// reply it follows.
+    pub async fn rotate(&mut self, timeout: Duration) -> bool {
+        match tokio::time::timeout(timeout, self.next.recv()).await {
+            Ok(Some(key)) => {
+                self.key = key;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+struct PendingRekey {
+    offer: RekeyOffer,
+    done_tx: oneshot::Sender<()>,
+}
+
+/// Key rotations of an encrypted connection. Each side seals a
+/// `RekeySwitch` as the last frame under the old keys and hands the new
+/// ones to its loops, which swap them in right at that frame. No frame is
+/// opened with keys other than those it was sealed with.
+pub struct KeyRotation {
+    active: bool,
+    next_opening_keys: UnboundedSender<OpeningKey<NonceValue>>,
+    next_sealing_keys: UnboundedSender<SealingKey<NonceValue>>,
+    pending: Mutex<Option<PendingRekey>>,
+}
+
+impl std::fmt::Debug for KeyRotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("KeyRotation")
+            .field("active", &self.active)
+            .finish_non_exhaustive()
+    }
+}
+
+impl KeyRotation {
+    #[allow(clippy::type_complexity)]
+    pub fn new(
+        active: bool,
+        opening_key: OpeningKey<NonceValue>,
+        sealing_key: SealingKey<NonceValue>,
+    ) -> (
+        KeyRotation,
+        RotatingKey<OpeningKey<NonceValue>>,
+        RotatingKey<SealingKey<NonceValue>>,
+    ) {
+        let (next_opening_keys, next_opening_rx) = tokio::sync::mpsc::unbounded_channel();
+        let (next_sealing_keys, next_sealing_rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let key_rotation = KeyRotation {
+            active,
+            next_opening_keys,
+            next_sealing_keys,
+            pending: Mutex::new(None),
+        };
+
+        (
+            key_rotation,
+            RotatingKey {
+                key: opening_key,
+                next: next_opening_rx,
+            },
+            RotatingKey {
+                key: sealing_key,
+                next: next_sealing_rx,
+            },
+        )
+    }
+
+    /// Starts a rotation, returns the request to send and a receiver that
+    /// resolves once the switch marker of this side is queued.
+    pub fn start(&self) -> CoreResult<(EndPointRekey, oneshot::Receiver<()>)> {
+        let mut pending = self.pending();
+        if pending.is_some() {
+            bail_core!("key rotation under way");
+        }
+
+        let offer = RekeyOffer::generate()?;
+        let request = offer.message();
+        let (done_tx, done_rx) = oneshot::channel();
+        *pending = Some(PendingRekey { offer, done_tx });
+
+        Ok((request, done_rx))
+    }
+
+    /// Answers a rotation of the remote side, the new keys are handed to the
+    /// loops before the reply is sent. When both sides started one at once
+    /// the side that opened the connection goes on with its own and ignores
+    /// the request, `None` then.
+    pub fn accept(&self, request: &EndPointRekey) -> CoreResult<Option<EndPointRekey>> {
+        let mut pending = self.pending();
+        if pending.is_some() {
+            if self.active {
+                return Ok(None);
+            }
+
+            // fails the rotation of this side, its request is ignored
+            pending.take();
+        }
+
+        let offer = RekeyOffer::generate()?;
+        let reply = offer.message();
+        let (opening_key, sealing_key) = offer.agree(request, self.active)?;
+        self.hand_over(opening_key, sealing_key)?;
+
+        Ok(Some(reply))
+    }
+
+    /// Takes the reply to the rotation of this side and hands the new keys
+    /// to the loops. The switch marker is to be sent before resolving the
+    /// returned sender.
+    pub fn finish(&self, reply: &EndPointRekey) -> CoreResult<oneshot::Sender<()>> {
+        let Some(PendingRekey { offer, done_tx }) = self.pending().take() else {
+            bail_core!("rekey reply without request");
+        };
+
+        let (opening_key, sealing_key) = offer.agree(reply, self.active)?;
+        self.hand_over(opening_key, sealing_key)?;
+
+        Ok(done_tx)
+    }
+
+    /// Gives up the rotation of this side, a reply arriving later fails.
+    pub fn cancel(&self) {
+        self.pending().take();
+    }
+
+    fn hand_over(
+        &self,
+        opening_key: OpeningKey<NonceValue>,
+        sealing_key: SealingKey<NonceValue>,
+    ) -> CoreResult<()> {
+        self.next_opening_keys
+            .send(opening_key)
+            .map_err(|_| core_error!("read loop exited"))?;
+        self.next_sealing_keys
+            .send(sealing_key)
+            .map_err(|_| core_error!("write loop exited"))?;
+
+        Ok(())
+    }
+
+    fn pending(&self) -> std::sync::MutexGuard<'_, Option<PendingRekey>> {
+        self.pending.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Rotates the keys of the connection once the policy says so. The task
+/// doesn't keep the client alive and exits with it.
+pub(crate) fn spawn_rekey_timer(client: &Arc<EndPointClient>, policy: RekeyPolicy) {
+    let policy = policy.sanitize();
+    let close_token = client.close_token();
+    let client = Arc::downgrade(client);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(REKEY_CHECK_INTERVAL);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let mut rotated_at = Instant::now();
+        let mut rotated_bytes = 0;
+
+        loop {
+            tokio::select! {
+                _ = close_token.cancelled() => return,
+                _ = ticker.tick() => {}
+            }
+
+            let Some(client) = client.upgrade() else {
+                return;
+            };
+
+            let stats = client.raw_stats();
+            let bytes = stats.sent_bytes() + stats.received_bytes();
+            if !policy.is_due(rotated_at.elapsed(), bytes.saturating_sub(rotated_bytes)) {
+                continue;
+            }
+
+            if let Err(err) = client.rekey().await {
+                tracing::warn!(endpoint_id = ?client.endpoint_id(), ?err, "rotate keys failed");
+            }
+
+            // a failed rotation is tried again after another interval
+            rotated_at = Instant::now();
+            rotated_bytes = bytes;
+        }
+    });
+}
@@ -0,0 +1,292 @@
+use super::{
+    client::EndPointClient,
+    handlers::{
+        audio_frame::serve_audio_decode,
+        video_frame::{serve_video_decode, VideoDecodeMessage},
+    },
+    id::EndPointID,
+    message::{DisconnectReason, EndPointAudioFrame, EndPointInput},
+    session_token::session_tokens,
+    sessions::desktop_sessions,
+    stats::EndPointStatsSnapshot,
+    swap::ViewerSinks,
+    EndPointStream,
+};
+use crate::{
+    component::{
+        desktop::monitor::Monitor,
+        input::encoding::InputEncoding,
+        video_decoder::rate_limit::{DecodeStats, DecodeStatsSnapshot},
+        video_encoder::{
+            content_hint::{ContentHint, ContentMode},
+            media_config::MediaConfig,
+        },
+    },
+    error::{CoreError, CoreResult},
+    utility::nonce_value::NonceValue,
+    DesktopDecodeFrame,
+};
+use ring::aead::{OpeningKey, SealingKey};
+use std::sync::Arc;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// A desktop session watched from this side, outgoing or swapped to, from
+/// start to close. Owns the endpoint client as its transport, the media
+/// config it negotiated and its decode pipeline, and is the one place the
+/// command layer and the desktop window talk to.
+#[derive(Debug)]
+pub struct Session {
+    client: Arc<EndPointClient>,
+    media_config: MediaConfig,
+    video_decode_tx: Sender<VideoDecodeMessage>,
+    decode_stats: Arc<DecodeStats>,
+}
+
+impl Session {
+    /// Connects, negotiates the media with the remote side and starts
+    /// decoding. The receiver yields the decoded frames to render.
+    pub async fn start(
+        endpoint_id: EndPointID,
+        key_pair: Option<(OpeningKey<NonceValue>, SealingKey<NonceValue>)>,
+        stream: EndPointStream,
+        visit_credentials: Option<Vec<u8>>,
+        media_config: MediaConfig,
+    ) -> CoreResult<(Arc<Session>, Receiver<DesktopDecodeFrame>)> {
+        desktop_sessions().ensure_capacity()?;
+
+        // the remote side encodes with the sanitized config, keep the same
+        let media_config = media_config.sanitize();
+
+        let pipeline = DecodePipeline::spawn(endpoint_id);
+
+        let client = EndPointClient::new_desktop_active(
+            endpoint_id,
+            key_pair,
+            stream,
+            pipeline.video_decode_tx.clone(),
+            pipeline.audio_frame_tx,
+            visit_credentials,
+            media_config.clone(),
+        )
+        .await?;
+
+        Session::register(
+            client,
+            media_config,
+            pipeline.video_decode_tx,
+            pipeline.decode_stats,
+            pipeline.render_frame_rx,
+        )
+    }
+
+    /// Starts watching on a connection that shared this desktop so far, after
+    /// both sides agreed to swap.
+    pub(crate) async fn attach(
+        client: Arc<EndPointClient>,
+        media_config: MediaConfig,
+    ) -> CoreResult<(Arc<Session>, Receiver<DesktopDecodeFrame>)> {
+        desktop_sessions().ensure_capacity()?;
+
+        let media_config = media_config.sanitize();
+        let pipeline = DecodePipeline::spawn(client.endpoint_id());
+
+        client
+            .become_viewer(
+                media_config.clone(),
+                Some(ViewerSinks {
+                    video_frame_tx: pipeline.video_decode_tx.clone(),
+                    audio_frame_tx: pipeline.audio_frame_tx,
+                }),
+            )
+            .await?;
+
+        Session::register(
+            client,
+            media_config,
+            pipeline.video_decode_tx,
+            pipeline.decode_stats,
+            pipeline.render_frame_rx,
+        )
+    }
+
+    fn register(
+        client: Arc<EndPointClient>,
+        media_config: MediaConfig,
+        video_decode_tx: Sender<VideoDecodeMessage>,
+        decode_stats: Arc<DecodeStats>,
+        render_frame_rx: Receiver<DesktopDecodeFrame>,
+    ) -> CoreResult<(Arc<Session>, Receiver<DesktopDecodeFrame>)> {
+        desktop_sessions().register(client.clone())?;
+
+        // the remote side or a broken connection may end the session too
+        let session_client = client.clone();
+        tokio::spawn(async move {
+            session_client.closed().await;
+            desktop_sessions().unregister(&session_client.endpoint_id());
+        });
+
+        // a decoder that started over, e.g. in software after the hardware
+        // one failed, can't wait for the next planned keyframe
+        let keyframe_client = client.clone();
+        let keyframe_stats = decode_stats.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = keyframe_client.closed() => break,
+                    _ = keyframe_stats.keyframe_requested() => {
+                        if let Err(err) = keyframe_client.request_keyframe() {
+                            tracing::warn!(?err, "request keyframe failed");
+                        }
+                    }
+                }
+            }
+        });
+
+        let session = Arc::new(Session {
+            client,
+            media_config,
+            video_decode_tx,
+            decode_stats,
+        });
+
+        Ok((session, render_frame_rx))
+    }
+
+    pub fn endpoint_id(&self) -> EndPointID {
+        self.client.endpoint_id()
+    }
+
+    /// The transport underneath, for what the session doesn't wrap.
+    pub fn client(&self) -> &Arc<EndPointClient> {
+        &self.client
+    }
+
+    /// The media config the session was negotiated with.
+    pub fn media_config(&self) -> &MediaConfig {
+        &self.media_config
+    }
+
+    pub async fn monitor(&self) -> Option<Arc<Monitor>> {
+        self.client.monitor().await
+    }
+
+    pub fn encoder_graphics_card(&self) -> Option<String> {
+        self.client.encoder_graphics_card()
+    }
+
+    /// The x264 preset and tune the remote side encodes with, `None` when it
+    /// encodes on a graphics card.
+    pub fn x264_settings(&self) -> Option<(&str, &str)> {
+        if self.encoder_graphics_card().is_some() {
+            return None;
+        }
+
+        Some((&self.media_config.x264_preset, &self.media_config.x264_tune))
+    }
+
+    pub fn stats(&self) -> EndPointStatsSnapshot {
+        self.client.stats()
+    }
+
+    /// Frames the decoder left out, to keep up or to stay under the fps cap.
+    pub fn decode_stats(&self) -> DecodeStatsSnapshot {
+        self.decode_stats.snapshot()
+    }
+
+    /// Messages waiting for the decoder, grows when decoding falls behind.
+    pub fn decode_backlog(&self) -> usize {
+        self.video_decode_tx.max_capacity() - self.video_decode_tx.capacity()
+    }
+
+    /// Whether the remote side refused to capture its screen for lack of the
+    /// permission.
+    pub fn permission_denied(&self) -> bool {
+        matches!(
+            self.client.media_error(),
+            Some(CoreError::MediaPermissionDenied)
+        )
+    }
+
+    pub fn include_cursor(&self) -> bool {
+        self.client.include_cursor()
+    }
+
+    pub fn set_include_cursor(&self, include_cursor: bool) -> CoreResult<()> {
+        self.client.set_include_cursor(include_cursor)
+    }
+
+    pub fn content_hint(&self) -> ContentHint {
+        self.client.content_hint()
+    }
+
+    pub fn set_content_hint(&self, content_hint: ContentHint) -> CoreResult<()> {
+        self.client.set_content_hint(content_hint)
+    }
+
+    /// The content the remote encoder is tuned for, it replaces the x264 tune
+    /// of [`x264_settings`](Self::x264_settings) while set.
+    pub fn content_mode(&self) -> Option<ContentMode> {
+        self.client.content_mode()
+    }
+
+    /// How keystrokes go to the remote desktop, scancodes until the
+    /// capabilities of the remote side arrived.
+    pub fn input_encoding(&self) -> InputEncoding {
+        self.client
+            .profile()
+            .map(|profile| profile.input_encoding)
+            .unwrap_or_default()
+    }
+
+    /// Sends input to the remote desktop. Returns false and drops the input
+    /// when another session has the focus.
+    pub fn send_input(&self, input: EndPointInput) -> CoreResult<bool> {
+        desktop_sessions().send_input(self.endpoint_id(), input)
+    }
+
+    pub fn focus(&self) -> CoreResult<()> {
+        desktop_sessions().focus(self.endpoint_id())
+    }
+
+    pub fn is_focused(&self) -> bool {
+        desktop_sessions().focused() == Some(self.endpoint_id())
+    }
+
+    /// Ends the session for good, it can't be rejoined afterwards.
+    pub fn close(&self) {
+        session_tokens().forget(&self.endpoint_id());
+        desktop_sessions().unregister(&self.endpoint_id());
+        self.client.close_with(DisconnectReason::SessionClosed);
+    }
+
+    pub async fn closed(&self) {
+        self.client.closed().await
+    }
+}
+
+/// The decoders of a session, fed through the channels its client gets.
+struct DecodePipeline {
+    video_decode_tx: Sender<VideoDecodeMessage>,
+    audio_frame_tx: Sender<EndPointAudioFrame>,
+    decode_stats: Arc<DecodeStats>,
+    render_frame_rx: Receiver<DesktopDecodeFrame>,
+}
+
+impl DecodePipeline {
+    fn spawn(endpoint_id: EndPointID) -> Self {
+        let (render_frame_tx, render_frame_rx) = tokio::sync::mpsc::channel(180);
+        let (audio_frame_tx, audio_frame_rx) = tokio::sync::mpsc::channel(180);
+
+        let decode_stats = Arc::new(DecodeStats::default());
+        let video_decode_tx =
+            serve_video_decode(endpoint_id, render_frame_tx, decode_stats.clone());
+        serve_audio_decode(endpoint_id, audio_frame_rx);
+
+        Self {
+            video_decode_tx,
+            audio_frame_tx,
+            decode_stats,
+            render_frame_rx,
+        }
+    }
+}
@@ -0,0 +1,108 @@
+use once_cell::sync::Lazy;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+static INCOMING_SESSIONS: Lazy<SessionLimiter> =
+    Lazy::new(|| SessionLimiter::new(default_max_incoming_sessions()));
+
+#[derive(Debug)]
+struct SessionCounts {
+    max: AtomicUsize,
+    active: AtomicUsize,
+}
+
+/// Counts the incoming sessions against a limit. Clones share the counts,
+/// the app uses [`SessionLimiter::global`].
+#[derive(Debug, Clone)]
+pub struct SessionLimiter {
+    counts: Arc<SessionCounts>,
+}
+
+impl SessionLimiter {
+    pub fn new(limit: usize) -> Self {
+        Self {
+            counts: Arc::new(SessionCounts {
+                max: AtomicUsize::new(limit.max(1)),
+                active: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// The limiter of the sessions signaling and the LAN server accept.
+    pub fn global() -> Self {
+        INCOMING_SESSIONS.clone()
+    }
+
+    pub fn max(&self) -> usize {
+        self.counts.max.load(Ordering::SeqCst)
+    }
+
+    /// Sessions already running are kept when the limit is lowered.
+    pub fn set_max(&self, limit: usize) {
+        self.counts.max.store(limit.max(1), Ordering::SeqCst);
+    }
+
+    pub fn active(&self) -> usize {
+        self.counts.active.load(Ordering::SeqCst)
+    }
+
+    pub fn try_acquire(&self) -> Option<IncomingSessionPermit> {
+        let limit = self.max();
+
+        match self
+            .counts
+            .active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                (active < limit).then_some(active + 1)
+            }) {
+            Ok(_) => Some(IncomingSessionPermit {
+                counts: self.counts.clone(),
+            }),
+            Err(active) => {
+                tracing::warn!(?active, ?limit, "incoming session limit reached");
+                None
+            }
+        }
+    }
+}
+
+/// Occupies one incoming session slot of its limiter until dropped.
+#[derive(Debug)]
+pub struct IncomingSessionPermit {
+    counts: Arc<SessionCounts>,
+}
+
+impl Drop for IncomingSessionPermit {
+    fn drop(&mut self) {
+        self.counts.active.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Every session runs its own capture and encode process, so allow one
+/// session per two cores.
+pub fn default_max_incoming_sessions() -> usize {
+    let cores = std::thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(1);
+
+    (cores / 2).clamp(1, 8)
+}
+
+pub fn max_incoming_sessions() -> usize {
+    INCOMING_SESSIONS.max()
+}
+
+/// Sessions already running are kept when the limit is lowered.
+pub fn set_max_incoming_sessions(limit: usize) {
+    INCOMING_SESSIONS.set_max(limit);
+}
+
+pub fn active_incoming_sessions() -> usize {
+    INCOMING_SESSIONS.active()
+}
+
+pub fn try_acquire_incoming_session() -> Option<IncomingSessionPermit> {
+    INCOMING_SESSIONS.try_acquire()
+}
@@ -0,0 +1,179 @@
+use super::{id::EndPointID, message::EndPointNegotiateVisitDesktopParams};
+use crate::{core_error, error::CoreResult};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt::Debug,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+/// Long enough to ride out a switch of network or a short sleep, short
+/// enough that a token is worthless soon after its session ended.
+pub const DEFAULT_SESSION_REJOIN_WINDOW: Duration = Duration::from_secs(60);
+
+static SESSION_TOKENS: Lazy<SessionTokens> =
+    Lazy::new(|| SessionTokens::new(DEFAULT_SESSION_REJOIN_WINDOW));
+
+pub fn session_tokens() -> &'static SessionTokens {
+    &SESSION_TOKENS
+}
+
+/// Identifies a negotiated desktop session across connections.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SessionToken([u8; 32]);
+
+// anyone holding the token may take over the session, keep it out of logs
+impl Debug for SessionToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SessionToken(..)")
+    }
+}
+
+impl SessionToken {
+    pub fn generate() -> CoreResult<Self> {
+        let mut token = [0u8; 32];
+        SystemRandom::new()
+            .fill(&mut token)
+            .map_err(|_| core_error!("generate session token failed"))?;
+
+        Ok(SessionToken(token))
+    }
+}
+
+#[derive(Debug)]
+struct IssuedSession {
+    endpoint_id: EndPointID,
+    params: EndPointNegotiateVisitDesktopParams,
+    /// When the connection of the session ended, `None` while it is up.
+    disconnected_at: Option<Instant>,
+}
+
+/// Lets a viewer whose connection dropped rejoin its session instead of
+/// negotiating it again. The host issues a token along with the negotiated
+/// params and the viewer presents it when it connects to the same endpoint
+/// again. Within the window after the old connection ended the host answers
+/// with the params it has, so the session keeps its display, codec and
+/// limits without checking the permissions again, otherwise negotiation
+/// starts afresh.
+///
+/// Only the negotiation is skipped. The new connection runs its own key
+/// exchange, a relayed connection can only be set up by a visit, and the host
+/// starts capturing anew for it.
+///
+/// Tokens are single use, every rejoin is answered with a new one.
+#[derive(Debug)]
+pub struct SessionTokens {
+    window: RwLock<Duration>,
+    issued: DashMap<SessionToken, IssuedSession>,
+    /// Tokens this device got as a viewer, by the endpoint that issued them.
+    received: DashMap<EndPointID, SessionToken>,
+}
+
+impl SessionTokens {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window: RwLock::new(window),
+            issued: DashMap::new(),
+            received: DashMap::new(),
+        }
+    }
+
+    /// Zero turns rejoining off, every connection starts a new session.
+    pub fn set_window(&self, window: Duration) {
+        if let Ok(mut current_window) = self.window.write() {
+            *current_window = window;
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+            .read()
+            .map(|window| *window)
+            .unwrap_or(DEFAULT_SESSION_REJOIN_WINDOW)
+    }
+
+    /// Issues the token of a session the host just negotiated.
+    pub fn issue(
+        &self,
+        endpoint_id: EndPointID,
+        params: EndPointNegotiateVisitDesktopParams,
+        now: Instant,
+    ) -> CoreResult<SessionToken> {
+        self.purge(now);
+
+        let token = SessionToken::generate()?;
+        self.issued.insert(
+            token,
+            IssuedSession {
+                endpoint_id,
+                params,
+                disconnected_at: None,
+            },
+        );
+
+        Ok(token)
+    }
+
+    /// Starts the rejoin window of a session whose connection ended.
+    pub fn disconnect(&self, token: &SessionToken, now: Instant) {
+        if let Some(mut session) = self.issued.get_mut(token) {
+            session.disconnected_at.get_or_insert(now);
+        }
+    }
+
+    /// Redeems a token, returns the params its session was negotiated with.
+    /// `None` when the token is unknown, was issued to another endpoint or
+    /// has expired, the caller negotiates a fresh session then.
+    pub fn rejoin(
+        &self,
+        token: &SessionToken,
+        endpoint_id: EndPointID,
+        now: Instant,
+    ) -> Option<EndPointNegotiateVisitDesktopParams> {
+        let window = self.window();
+        let (_, session) = self.issued.remove(token)?;
+
+        if session.endpoint_id != endpoint_id {
+            tracing::warn!(?endpoint_id, "session token presented by another endpoint");
+            return None;
+        }
+
+        // the old connection may not have timed out yet, that's a rejoin too
+        let in_window = session
+            .disconnected_at
+            .is_none_or(|disconnected_at| now.saturating_duration_since(disconnected_at) <= window);
+
+        if !in_window || window.is_zero() {
+            return None;
+        }
+
+        Some(session.params)
+    }
+
+    fn purge(&self, now: Instant) {
+        let window = self.window();
+        self.issued.retain(|_, session| {
+            session.disconnected_at.is_none_or(|disconnected_at| {
+                now.saturating_duration_since(disconnected_at) <= window
+            })
+        });
+    }
+
+    /// Keeps the token the remote endpoint issued, to present on reconnect.
+    pub fn remember(&self, endpoint_id: EndPointID, token: SessionToken) {
+        self.received.insert(endpoint_id, token);
+    }
+
+    /// Takes the token to present when connecting to an endpoint.
+    pub fn take_received(&self, endpoint_id: &EndPointID) -> Option<SessionToken> {
+        self.received.remove(endpoint_id).map(|(_, token)| token)
+    }
+
+    /// Drops the received token of a session the user closed on purpose.
+    pub fn forget(&self, endpoint_id: &EndPointID) {
+        self.received.remove(endpoint_id);
+    }
+}
@@ -0,0 +1,190 @@
+use super::{
+    client::EndPointClient,
+    id::EndPointID,
+    message::{EndPointInput, EndPointMessage},
+    network_info::NetworkInfo,
+    stats::EndPointStatsSnapshot,
+};
+use crate::{
+    component::video_encoder::content_hint::ContentHint,
+    core_error,
+    error::{CoreError, CoreResult},
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::sync::{Arc, RwLock};
+
+/// Upper bound of desktop sessions this device visits at once. Every session
+/// holds its own connection, decode thread, render window and up to 180
+/// decoded frames in flight, and software decoding of one 1080p stream keeps
+/// a core busy, so more sessions than this mostly stutter.
+pub const MAX_DESKTOP_SESSIONS: usize = 8;
+
+static DESKTOP_SESSIONS: Lazy<DesktopSessions> =
+    Lazy::new(|| DesktopSessions::new(MAX_DESKTOP_SESSIONS));
+
+pub fn desktop_sessions() -> &'static DesktopSessions {
+    &DESKTOP_SESSIONS
+}
+
+/// Outgoing desktop sessions keyed by endpoint. All of them keep rendering,
+/// but only the focused one receives input.
+#[derive(Debug)]
+pub struct DesktopSessions {
+    sessions: DashMap<EndPointID, Arc<EndPointClient>>,
+    focused: RwLock<Option<EndPointID>>,
+    capacity: usize,
+}
+
+impl DesktopSessions {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            sessions: DashMap::new(),
+            focused: RwLock::new(None),
+            capacity,
+        }
+    }
+
+    /// Fails when another session would exceed the capacity. Checked before
+    /// connecting so a refused session costs nothing.
+    pub fn ensure_capacity(&self) -> CoreResult<()> {
+        if self.sessions.len() >= self.capacity {
+            return Err(core_error!(
+                "too many desktop sessions (max {})",
+                self.capacity
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Adds a session and focuses it, the user just opened it to work with.
+    pub fn register(&self, client: Arc<EndPointClient>) -> CoreResult<()> {
+        let endpoint_id = client.endpoint_id();
+
+        if !self.sessions.contains_key(&endpoint_id) {
+            self.ensure_capacity()?;
+        }
+
+        self.sessions.insert(endpoint_id, client);
+
+        if let Ok(mut focused) = self.focused.write() {
+            *focused = Some(endpoint_id);
+        }
+
+        Ok(())
+    }
+
+    pub fn unregister(&self, endpoint_id: &EndPointID) {
+        self.sessions.remove(endpoint_id);
+
+        if let Ok(mut focused) = self.focused.write() {
+            if focused.as_ref() == Some(endpoint_id) {
+                *focused = None;
+            }
+        }
+    }
+
+    pub fn focus(&self, endpoint_id: EndPointID) -> CoreResult<()> {
+        if !self.sessions.contains_key(&endpoint_id) {
+            return Err(core_error!("desktop session not exist"));
+        }
+
+        if let Ok(mut focused) = self.focused.write() {
+            *focused = Some(endpoint_id);
+        }
+
+        Ok(())
+    }
+
+    pub fn focused(&self) -> Option<EndPointID> {
+        self.focused.read().ok().and_then(|focused| *focused)
+    }
+
+    pub fn endpoint_ids(&self) -> Vec<EndPointID> {
+        self.sessions.iter().map(|entry| *entry.key()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    /// Sends input produced for a session. Returns false and drops the input
+    /// when the session isn't focused.
+    pub fn send_input(&self, endpoint_id: EndPointID, input: EndPointInput) -> CoreResult<bool> {
+        if self.focused() != Some(endpoint_id) {
+            return Ok(false);
+        }
+
+        let client = match self.sessions.get(&endpoint_id) {
+            Some(entry) => entry.value().clone(),
+            None => return Err(CoreError::OutgoingMessageChannelDisconnect),
+        };
+
+        client.try_send(&EndPointMessage::InputCommand(input))?;
+        Ok(true)
+    }
+
+    /// Switches the encoder tuning of a session, focused or not.
+    pub fn set_content_hint(
+        &self,
+        endpoint_id: EndPointID,
+        content_hint: ContentHint,
+    ) -> CoreResult<()> {
+        let client = match self.sessions.get(&endpoint_id) {
+            Some(entry) => entry.value().clone(),
+            None => return Err(core_error!("desktop session not exist")),
+        };
+
+        client.set_content_hint(content_hint)
+    }
+
+    /// Pauses or resumes the video of a session, e.g. while its window is
+    /// minimized. Input and audio keep running.
+    pub fn set_media_paused(&self, endpoint_id: EndPointID, paused: bool) -> CoreResult<()> {
+        let client = match self.sessions.get(&endpoint_id) {
+            Some(entry) => entry.value().clone(),
+            None => return Err(core_error!("desktop session not exist")),
+        };
+
+        if paused {
+            client.pause_media()
+        } else {
+            client.resume_media()
+        }
+    }
+
+    pub fn network_info(&self, endpoint_id: EndPointID) -> CoreResult<NetworkInfo> {
+        let client = match self.sessions.get(&endpoint_id) {
+            Some(entry) => entry.value().clone(),
+            None => return Err(core_error!("desktop session not exist")),
+        };
+
+        Ok(client.network_info())
+    }
+
+    /// Round trip, bitrates and frame drops of a session, measured since it
+    /// connected or last rotated its keys.
+    pub fn stats(&self, endpoint_id: EndPointID) -> CoreResult<EndPointStatsSnapshot> {
+        let client = match self.sessions.get(&endpoint_id) {
+            Some(entry) => entry.value().clone(),
+            None => return Err(core_error!("desktop session not exist")),
+        };
+
+        Ok(client.stats())
+    }
+
+    /// The name the remote device of a session shows to others.
+    pub fn remote_device_name(&self, endpoint_id: EndPointID) -> CoreResult<Option<String>> {
+        let client = match self.sessions.get(&endpoint_id) {
+            Some(entry) => entry.value().clone(),
+            None => return Err(core_error!("desktop session not exist")),
+        };
+
+        Ok(client.remote_device_name())
+    }
+}
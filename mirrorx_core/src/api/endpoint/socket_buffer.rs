@@ -0,0 +1,142 @@
+use super::id::EndPointID;
+use crate::utility::config_cell::ConfigCell;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use socket2::SockRef;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, PoisonError},
+    time::Duration,
+};
+
+/// Smallest socket buffer asked for, about what systems start with anyway.
+pub const MIN_SOCKET_BUFFER_BYTES: usize = 64 * 1024;
+
+/// Largest socket buffer asked for, a 400 Mbps link with 300 ms round
+/// trips fills it. Systems cap it further: Linux at `net.core.rmem_max` and
+/// `net.core.wmem_max`, macOS at `kern.ipc.maxsockbuf`. Linux also reports
+/// twice the size asked for, its bookkeeping lives in the buffer.
+pub const MAX_SOCKET_BUFFER_BYTES: usize = 16 * 1024 * 1024;
+
+static SOCKET_BUFFER_CONFIG: ConfigCell<SocketBufferConfig> =
+    ConfigCell::new(SocketBufferConfig::default);
+
+static MEASURED_BDP: Lazy<Mutex<HashMap<EndPointID, u64>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Sizes of the kernel buffers of the endpoint sockets.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SocketBufferConfig {
+    /// `SO_RCVBUF` in bytes. `None` sizes it from the bandwidth-delay
+    /// product an earlier session with the remote side measured, or leaves
+    /// the system default when there's none.
+    pub recv_buffer_bytes: Option<usize>,
+    /// `SO_SNDBUF` in bytes, sized like the receive buffer when `None`.
+    pub send_buffer_bytes: Option<usize>,
+}
+
+impl SocketBufferConfig {
+    pub fn sanitize(self) -> Self {
+        let clamp = |bytes: usize| bytes.clamp(MIN_SOCKET_BUFFER_BYTES, MAX_SOCKET_BUFFER_BYTES);
+
+        Self {
+            recv_buffer_bytes: self.recv_buffer_bytes.map(clamp),
+            send_buffer_bytes: self.send_buffer_bytes.map(clamp),
+        }
+    }
+
+    /// The receive and send buffer sizes to ask for, `None` keeps the
+    /// system default.
+    pub fn resolve(&self, measured_bdp: Option<u64>) -> (Option<usize>, Option<usize>) {
+        let tuned = measured_bdp.map(buffer_for_bdp);
+
+        (
+            self.recv_buffer_bytes.or(tuned),
+            self.send_buffer_bytes.or(tuned),
+        )
+    }
+}
+
+/// The buffer sizes a socket ended up with as the system reports them,
+/// `None` where it doesn't tell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SocketBuffers {
+    pub recv_buffer_bytes: Option<usize>,
+    pub send_buffer_bytes: Option<usize>,
+}
+
+pub fn set_socket_buffer_config(config: SocketBufferConfig) {
+    SOCKET_BUFFER_CONFIG.set(config.sanitize());
+}
+
+pub fn socket_buffer_config() -> SocketBufferConfig {
+    SOCKET_BUFFER_CONFIG.get()
+}
+
+/// A buffer holding twice the bandwidth-delay product, so the window
+/// doesn't close while acks are on their way.
+pub fn buffer_for_bdp(bdp_bytes: u64) -> usize {
+    (bdp_bytes.saturating_mul(2) as usize).clamp(MIN_SOCKET_BUFFER_BYTES, MAX_SOCKET_BUFFER_BYTES)
+}
+
+/// Keeps the bandwidth-delay product a session measured, the next
+/// connection to the same remote side sizes its buffers from it.
+pub fn record_bdp(endpoint_id: EndPointID, bps: u64, rtt: Duration) {
+    let bdp = (bps as f64 / 8.0 * rtt.as_secs_f64()) as u64;
+
+    MEASURED_BDP
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .insert(endpoint_id, bdp);
+}
+
+pub fn measured_bdp(endpoint_id: &EndPointID) -> Option<u64> {
+    MEASURED_BDP
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(endpoint_id)
+        .copied()
+}
+
+/// Sizes the buffers of a socket for a connection to `endpoint_id`. A size
+/// the system refuses is left as it was, the connection works either way.
+pub fn apply_socket_buffers(socket: SockRef<'_>, endpoint_id: &EndPointID) -> SocketBuffers {
+    let (recv_buffer_bytes, send_buffer_bytes) =
+        socket_buffer_config().resolve(measured_bdp(endpoint_id));
+
+    set_socket_buffers(socket, recv_buffer_bytes, send_buffer_bytes)
+}
+
+/// Asks for the given buffer sizes, `None` leaves a buffer alone. Returns
+/// what the system applied.
+pub fn set_socket_buffers(
+    socket: SockRef<'_>,
+    recv_buffer_bytes: Option<usize>,
+    send_buffer_bytes: Option<usize>,
+) -> SocketBuffers {
+    if let Some(bytes) = recv_buffer_bytes {
+        if let Err(err) = socket.set_recv_buffer_size(bytes) {
+            tracing::warn!(?err, ?bytes, "set socket receive buffer failed");
+        }
+    }
+
+    if let Some(bytes) = send_buffer_bytes {
+        if let Err(err) = socket.set_send_buffer_size(bytes) {
+            tracing::warn!(?err, ?bytes, "set socket send buffer failed");
+        }
+    }
+
+    let buffers = SocketBuffers {
+        recv_buffer_bytes: socket.recv_buffer_size().ok(),
+        send_buffer_bytes: socket.send_buffer_size().ok(),
+    };
+
+    tracing::info!(
+        ?recv_buffer_bytes,
+        ?send_buffer_bytes,
+        ?buffers,
+        "socket buffers applied"
+    );
+
+    buffers
+}
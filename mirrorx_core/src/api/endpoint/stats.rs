@@ -0,0 +1,385 @@
+use crate::utility::nonce_value::NonceValue;
+use once_cell::sync::Lazy;
+use ring::aead::OpeningKey;
+use serde::Serialize;
+use std::{
+    sync::atomic::{AtomicU32, AtomicU64, Ordering},
+    time::{Duration, Instant},
+};
+
+/// Broken frames in a row tolerated before the connection is torn down. A
+/// single bad frame is most likely corruption, a run of them is not.
+pub const MAX_CONSECUTIVE_FRAME_FAILURES: u32 = 8;
+
+/// Span the bitrate of a connection is measured over before it's folded
+/// into the moving average.
+pub const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Rate meters count time from here, so a point in time fits an atomic.
+static RATE_ORIGIN: Lazy<Instant> = Lazy::new(Instant::now);
+
+/// Bitrate of one direction of a connection, a moving average over windows
+/// of [`RATE_WINDOW`]. Lock-free, the read and write loops record into it.
+#[derive(Debug, Default)]
+pub struct RateMeter {
+    /// Start of the current window in micros after [`RATE_ORIGIN`] plus
+    /// one, zero before the first record.
+    window_start: AtomicU64,
+    window_bytes: AtomicU64,
+    bps: AtomicU64,
+}
+
+impl RateMeter {
+    pub fn record(&self, bytes: usize, now: Instant) {
+        self.window_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+
+        let now = rate_micros(now);
+        let start = self.window_start.load(Ordering::Relaxed);
+        if start == 0 {
+            let _ =
+                self.window_start
+                    .compare_exchange(0, now, Ordering::Relaxed, Ordering::Relaxed);
+            return;
+        }
+
+        let elapsed = now.saturating_sub(start);
+        if elapsed < RATE_WINDOW.as_micros() as u64 {
+            return;
+        }
+
+        // only the caller that moved the window on folds it in
+        if self
+            .window_start
+            .compare_exchange(start, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+        {
+            return;
+        }
+
+        let bits = self
+            .window_bytes
+            .swap(0, Ordering::Relaxed)
+            .saturating_mul(8);
+        let bps = (bits as u128 * 1_000_000 / elapsed as u128) as u64;
+        let _ = self
+            .bps
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |average| {
+                Some(match average {
+                    0 => bps,
+                    average => average - average / 4 + bps / 4,
+                })
+            });
+    }
+
+    /// Moving average in bits per second, zero once nothing was recorded
+    /// for two windows.
+    pub fn bps(&self, now: Instant) -> u64 {
+        let start = self.window_start.load(Ordering::Relaxed);
+        let idle = rate_micros(now).saturating_sub(start);
+        if start == 0 || idle > 2 * RATE_WINDOW.as_micros() as u64 {
+            return 0;
+        }
+
+        self.bps.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.window_start.store(0, Ordering::Relaxed);
+        self.window_bytes.store(0, Ordering::Relaxed);
+        self.bps.store(0, Ordering::Relaxed);
+    }
+}
+
+fn rate_micros(now: Instant) -> u64 {
+    now.saturating_duration_since(*RATE_ORIGIN).as_micros() as u64 + 1
+}
+
+#[derive(Debug, Default)]
+pub struct EndPointStats {
+    aead_open_failures: AtomicU64,
+    deserialize_failures: AtomicU64,
+    consecutive_failures: AtomicU32,
+    video_frame_bytes: AtomicU64,
+    video_frames: AtomicU64,
+    fec_parity_bytes: AtomicU64,
+    fec_recovered_frames: AtomicU64,
+    fec_lost_frames: AtomicU64,
+    video_unchanged_markers: AtomicU64,
+    queued_bytes: AtomicU64,
+    sent_bytes: AtomicU64,
+    received_bytes: AtomicU64,
+    rtt_micros: AtomicU64,
+    last_rtt_micros: AtomicU64,
+    smoothed_rtt_micros: AtomicU64,
+    estimated_bps: AtomicU64,
+    capture_errors: AtomicU64,
+    received_messages: AtomicU64,
+    inbound: RateMeter,
+    outbound: RateMeter,
+    dropped_frames: AtomicU64,
+    oversized_frames: AtomicU64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EndPointStatsSnapshot {
+    pub aead_open_failures: u64,
+    pub deserialize_failures: u64,
+    /// Received video payload, parity frames excluded.
+    pub video_frame_bytes: u64,
+    /// Received parity payload, the bandwidth FEC costs on top of the video.
+    pub fec_parity_bytes: u64,
+    /// Lost video frames restored from parity frames.
+    pub fec_recovered_frames: u64,
+    /// Lost video frames that couldn't be restored.
+    pub fec_lost_frames: u64,
+    /// Times the host said its screen didn't change, in place of frames.
+    pub video_unchanged_markers: u64,
+    /// Smoothed available bandwidth towards the remote side, zero until the
+    /// sending side has measured it.
+    pub estimated_bps: u64,
+    /// Frames the screen capture of this side failed to produce, skipped or
+    /// not.
+    pub capture_errors: u64,
+    /// Moving average of the round trip in milliseconds, `None` until a
+    /// probe came back.
+    pub smoothed_rtt_ms: Option<f64>,
+    /// Received bits per second, a moving average.
+    pub inbound_bps: u64,
+    /// Sent bits per second, a moving average.
+    pub outbound_bps: u64,
+    /// Frames given up, the send queue was full or the frame was broken.
+    pub dropped_frames: u64,
+    /// Frames refused before sending, longer than the remote side accepts.
+    pub oversized_frames: u64,
+}
+
+pub enum FrameVerdict {
+    /// The frame is valid, holds the plaintext length.
+    Accept(usize),
+    Skip,
+    Close,
+}
+
+impl EndPointStats {
+    pub fn snapshot(&self) -> EndPointStatsSnapshot {
+        let now = Instant::now();
+
+        EndPointStatsSnapshot {
+            aead_open_failures: self.aead_open_failures.load(Ordering::Relaxed),
+            deserialize_failures: self.deserialize_failures.load(Ordering::Relaxed),
+            video_frame_bytes: self.video_frame_bytes.load(Ordering::Relaxed),
+            fec_parity_bytes: self.fec_parity_bytes.load(Ordering::Relaxed),
+            fec_recovered_frames: self.fec_recovered_frames.load(Ordering::Relaxed),
+            fec_lost_frames: self.fec_lost_frames.load(Ordering::Relaxed),
+            video_unchanged_markers: self.video_unchanged_markers.load(Ordering::Relaxed),
+            estimated_bps: self.estimated_bps.load(Ordering::Relaxed),
+            capture_errors: self.capture_errors.load(Ordering::Relaxed),
+            smoothed_rtt_ms: self.smoothed_rtt().map(|rtt| rtt.as_secs_f64() * 1000.0),
+            inbound_bps: self.inbound.bps(now),
+            outbound_bps: self.outbound.bps(now),
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            oversized_frames: self.oversized_frames.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Starts the link measurements over, the round trip, the bitrates and
+    /// the frame drops. Byte totals and failure counts stay.
+    pub fn reset_link_stats(&self) {
+        self.smoothed_rtt_micros.store(0, Ordering::Relaxed);
+        self.inbound.reset();
+        self.outbound.reset();
+        self.dropped_frames.store(0, Ordering::Relaxed);
+        self.oversized_frames.store(0, Ordering::Relaxed);
+    }
+
+    /// Returns false once too many consecutive frames failed.
+    pub fn record_aead_open_failure(&self) -> bool {
+        self.aead_open_failures.fetch_add(1, Ordering::Relaxed);
+        self.record_failure()
+    }
+
+    /// Returns false once too many consecutive frames failed.
+    pub fn record_deserialize_failure(&self) -> bool {
+        self.deserialize_failures.fetch_add(1, Ordering::Relaxed);
+        self.record_failure()
+    }
+
+    /// A frame opened, the failures before it weren't consecutive.
+    pub fn record_frame_opened(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    /// A message arrived intact.
+    pub fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        self.received_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn received_messages(&self) -> u64 {
+        self.received_messages.load(Ordering::Relaxed)
+    }
+
+    pub fn record_video_frame(&self, bytes: usize) {
+        self.video_frame_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.video_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Video frames received, parity frames excluded.
+    pub fn video_frames(&self) -> u64 {
+        self.video_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn record_fec_parity(&self, bytes: usize) {
+        self.fec_parity_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn record_fec_recovered(&self) {
+        self.fec_recovered_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_fec_lost(&self, frames: u64) {
+        self.fec_lost_frames.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    pub fn fec_lost_frames(&self) -> u64 {
+        self.fec_lost_frames.load(Ordering::Relaxed)
+    }
+
+    pub fn record_video_unchanged(&self) {
+        self.video_unchanged_markers.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// A message entered the send queue.
+    pub fn record_queued(&self, bytes: usize) {
+        self.queued_bytes.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// A message left the send queue and was written to the connection.
+    pub fn record_sent(&self, bytes: usize) {
+        let bytes = bytes as u64;
+        let _ = self
+            .queued_bytes
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| {
+                Some(queued.saturating_sub(bytes))
+            });
+        self.sent_bytes.fetch_add(bytes, Ordering::Relaxed);
+        self.outbound.record(bytes as usize, Instant::now());
+    }
+
+    pub fn queued_bytes(&self) -> u64 {
+        self.queued_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn sent_bytes(&self) -> u64 {
+        self.sent_bytes.load(Ordering::Relaxed)
+    }
+
+    /// A frame was read from the connection and opened.
+    pub fn record_received(&self, bytes: usize) {
+        self.received_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+        self.inbound.record(bytes, Instant::now());
+    }
+
+    pub fn received_bytes(&self) -> u64 {
+        self.received_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn record_rtt(&self, rtt: Duration) {
+        let micros = (rtt.as_micros() as u64).max(1);
+        self.rtt_micros.store(micros, Ordering::Relaxed);
+        self.last_rtt_micros.store(micros, Ordering::Relaxed);
+        let _ = self.smoothed_rtt_micros.fetch_update(
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+            |smoothed| {
+                Some(match smoothed {
+                    0 => micros,
+                    smoothed => (smoothed - smoothed / 8 + micros / 8).max(1),
+                })
+            },
+        );
+    }
+
+    /// Moving average of the round trips measured, weighing the latest by
+    /// an eighth.
+    pub fn smoothed_rtt(&self) -> Option<Duration> {
+        match self.smoothed_rtt_micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    /// The latest round trip measured, unlike `take_rtt` it stays.
+    pub fn last_rtt(&self) -> Option<Duration> {
+        match self.last_rtt_micros.load(Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    /// The round trip measured since the last call, if any.
+    pub fn take_rtt(&self) -> Option<Duration> {
+        match self.rtt_micros.swap(0, Ordering::Relaxed) {
+            0 => None,
+            micros => Some(Duration::from_micros(micros)),
+        }
+    }
+
+    pub fn estimated_bps(&self) -> u64 {
+        self.estimated_bps.load(Ordering::Relaxed)
+    }
+
+    pub fn set_estimated_bps(&self, estimated_bps: u64) {
+        self.estimated_bps.store(estimated_bps, Ordering::Relaxed);
+    }
+
+    pub fn record_capture_error(&self) {
+        self.capture_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped_frame(&self) {
+        self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_oversized_frame(&self) {
+        self.oversized_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The broken frame is dropped.
+    fn record_failure(&self) -> bool {
+        self.dropped_frames.fetch_add(1, Ordering::Relaxed);
+        self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1
+            < MAX_CONSECUTIVE_FRAME_FAILURES
+    }
+}
+
+/// Decrypts a frame in place. The nonce sequence advances even when opening
+/// fails, so both sides stay in step after a skipped frame.
+pub fn open_frame(
+    opening_key: Option<&mut OpeningKey<NonceValue>>,
+    buffer: &mut [u8],
+    stats: &EndPointStats,
+) -> FrameVerdict {
+    let Some(opening_key) = opening_key else {
+        stats.record_frame_opened();
+        return FrameVerdict::Accept(buffer.len());
+    };
+
+    match opening_key.open_in_place(ring::aead::Aad::empty(), buffer) {
+        Ok(output) => {
+            stats.record_frame_opened();
+            FrameVerdict::Accept(output.len())
+        }
+        Err(err) => {
+            tracing::error!(?err, "open endpoint message packet failed");
+            if stats.record_aead_open_failure() {
+                FrameVerdict::Skip
+            } else {
+                FrameVerdict::Close
+            }
+        }
+    }
+}
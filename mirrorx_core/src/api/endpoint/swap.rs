@@ -0,0 +1,174 @@
+use super::{
+    client::EndPointClient, handlers::video_frame::VideoDecodeMessage, id::EndPointID,
+    idle::SessionRole, message::EndPointAudioFrame, session::Session, sessions::desktop_sessions,
+};
+use crate::{
+    component::video_encoder::media_config::MediaConfig, core_error, error::CoreResult,
+    DesktopDecodeFrame,
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{
+    mpsc::{Receiver, Sender},
+    oneshot,
+};
+
+/// How long a swap request waits for the user on the other side to answer.
+pub const SWAP_REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+static PEERS: Lazy<DashMap<EndPointID, Arc<EndPointClient>>> = Lazy::new(DashMap::new);
+
+static SWAP_REQUEST_TX: Lazy<tokio::sync::broadcast::Sender<SwapRequestEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+/// The remote side asks to swap, answer with `respond_swap`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SwapRequestEvent {
+    /// Device id of the remote side, or its ip for LAN sessions.
+    pub remote: String,
+    /// Whether accepting makes this device share its screen.
+    pub share_screen: bool,
+}
+
+/// Yields the swap requests of the remote sides.
+pub fn subscribe_swap_request() -> tokio::sync::broadcast::Receiver<SwapRequestEvent> {
+    SWAP_REQUEST_TX.subscribe()
+}
+
+pub(crate) fn report_swap_request(event: SwapRequestEvent) {
+    tracing::info!(?event, "remote endpoint asks to swap direction");
+    let _ = SWAP_REQUEST_TX.send(event);
+}
+
+/// Where the frames go while this side watches.
+#[derive(Debug)]
+pub struct ViewerSinks {
+    pub video_frame_tx: Sender<VideoDecodeMessage>,
+    pub audio_frame_tx: Sender<EndPointAudioFrame>,
+}
+
+/// Swap progress of a connection, kept by its client.
+#[derive(Debug, Default)]
+pub(crate) struct SwapState {
+    /// The request this side sent, with whether the remote side may control
+    /// this device if it starts sharing.
+    pub outgoing: Option<(bool, oneshot::Sender<bool>)>,
+    /// When the request of the remote side arrived.
+    pub incoming: Option<Instant>,
+    /// Media config to watch with and the channels for a new decode
+    /// pipeline, waiting for the negotiation reply.
+    pub viewer: Option<(MediaConfig, Option<ViewerSinks>)>,
+}
+
+pub(crate) fn attach(client: Arc<EndPointClient>) {
+    PEERS.insert(client.endpoint_id(), client);
+}
+
+pub(crate) fn detach(client: &Arc<EndPointClient>) {
+    PEERS.remove_if(&client.endpoint_id(), |_, peer| Arc::ptr_eq(peer, client));
+}
+
+/// Desktop sessions of either side that can swap.
+pub fn swap_endpoint_ids() -> Vec<EndPointID> {
+    PEERS.iter().map(|entry| *entry.key()).collect()
+}
+
+pub enum SwapOutcome {
+    Declined,
+    /// This device shares its screen now.
+    Hosting,
+    /// This device watches the remote screen now. Holds the new session
+    /// with its frames to render when the session had no viewer yet on this
+    /// side, otherwise the existing viewer gets the frames again.
+    Viewing(Option<(Arc<Session>, Receiver<DesktopDecodeFrame>)>),
+}
+
+/// Asks the remote side to swap and waits until its user answers. The
+/// remote side may control this device afterwards only with `allow_input`.
+pub async fn request_swap(
+    endpoint_id: EndPointID,
+    allow_input: bool,
+    media_config: MediaConfig,
+) -> CoreResult<SwapOutcome> {
+    let client = find_peer(&endpoint_id)?;
+    let role = client
+        .role()
+        .ok_or_else(|| core_error!("desktop session not negotiated"))?;
+
+    ensure_can_view(&client, role)?;
+
+    if !client.request_swap(allow_input).await? {
+        return Ok(SwapOutcome::Declined);
+    }
+
+    // the message loop started hosting when the reply arrived
+    match role {
+        SessionRole::Viewer => Ok(SwapOutcome::Hosting),
+        SessionRole::Host => become_viewer(client, media_config).await,
+    }
+}
+
+/// Answers the swap request of the remote side, `allow_input` as in
+/// `request_swap`.
+pub async fn respond_swap(
+    endpoint_id: EndPointID,
+    accepted: bool,
+    allow_input: bool,
+    media_config: MediaConfig,
+) -> CoreResult<SwapOutcome> {
+    let client = find_peer(&endpoint_id)?;
+    let role = client
+        .role()
+        .ok_or_else(|| core_error!("desktop session not negotiated"))?;
+
+    if accepted {
+        ensure_can_view(&client, role)?;
+    }
+
+    client.reply_swap(accepted, allow_input).await?;
+
+    if !accepted {
+        return Ok(SwapOutcome::Declined);
+    }
+
+    // a viewer started hosting before the reply went out
+    match role {
+        SessionRole::Viewer => Ok(SwapOutcome::Hosting),
+        SessionRole::Host => become_viewer(client, media_config).await,
+    }
+}
+
+async fn become_viewer(
+    client: Arc<EndPointClient>,
+    media_config: MediaConfig,
+) -> CoreResult<SwapOutcome> {
+    if client.has_decode_sinks() {
+        client.become_viewer(media_config, None).await?;
+        return Ok(SwapOutcome::Viewing(None));
+    }
+
+    let (session, render_frame_rx) = Session::attach(client, media_config).await?;
+    Ok(SwapOutcome::Viewing(Some((session, render_frame_rx))))
+}
+
+/// A host without a viewer window needs room for another desktop session,
+/// checked before the swap is agreed on.
+fn ensure_can_view(client: &EndPointClient, role: SessionRole) -> CoreResult<()> {
+    if role == SessionRole::Host && !client.has_decode_sinks() {
+        desktop_sessions().ensure_capacity()?;
+    }
+
+    Ok(())
+}
+
+fn find_peer(endpoint_id: &EndPointID) -> CoreResult<Arc<EndPointClient>> {
+    PEERS
+        .get(endpoint_id)
+        .map(|entry| entry.value().clone())
+        .ok_or_else(|| core_error!("desktop session not exist"))
+}
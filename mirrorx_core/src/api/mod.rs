@@ -1,3 +1,4 @@
 pub mod config;
+pub mod diagnose;
 pub mod endpoint;
 pub mod signaling;
@@ -0,0 +1,197 @@
+use super::subscribe_message::VisitFailureReason;
+use crate::utility::config_cell::ConfigCell;
+use once_cell::sync::Lazy;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// A one-time code is meant to be read out to the remote side right away,
+/// one left unused longer than this is stale.
+pub const DEFAULT_ONE_TIME_CODE_TTL: Duration = Duration::from_secs(10 * 60);
+
+pub const ONE_TIME_CODE_LEN: usize = 8;
+
+/// Upper case letters and digits without the ones read alike, the code is
+/// typed over from a screen or a phone call.
+const ONE_TIME_CODE_ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+
+static ACCESS_MODE: ConfigCell<AccessMode> = ConfigCell::new(AccessMode::default);
+
+static ONE_TIME_CODES: Lazy<OneTimeCodes> =
+    Lazy::new(|| OneTimeCodes::new(DEFAULT_ONE_TIME_CODE_TTL));
+
+pub fn one_time_codes() -> &'static OneTimeCodes {
+    &ONE_TIME_CODES
+}
+
+/// Which credentials open a visit to this device. The remote side enters
+/// either into the same password field, it can't tell them apart.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AccessMode {
+    /// The password of the device, for trusted repeat access.
+    DevicePassword,
+    /// A code generated on this device for one visit, for ad-hoc support.
+    OneTimeCode,
+    /// Either of them.
+    #[default]
+    Both,
+}
+
+impl AccessMode {
+    pub fn accepts_device_password(self) -> bool {
+        matches!(self, AccessMode::DevicePassword | AccessMode::Both)
+    }
+
+    pub fn accepts_one_time_code(self) -> bool {
+        matches!(self, AccessMode::OneTimeCode | AccessMode::Both)
+    }
+}
+
+pub fn set_access_mode(mode: AccessMode) {
+    ACCESS_MODE.set(mode);
+}
+
+pub fn access_mode() -> AccessMode {
+    ACCESS_MODE.get()
+}
+
+/// The code shown to the user, with the seconds it stays valid.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OneTimeCode {
+    pub code: String,
+    pub expires_in_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IssuedCode {
+    code: String,
+    issued_at: Instant,
+}
+
+/// The one-time code of this device. There's at most one, generating a new
+/// code replaces the old one, and a visit it opened consumes it.
+#[derive(Debug)]
+pub struct OneTimeCodes {
+    ttl: Duration,
+    current: Mutex<Option<IssuedCode>>,
+}
+
+impl OneTimeCodes {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            current: Mutex::new(None),
+        }
+    }
+
+    /// Generates a fresh code, the previous one stops working.
+    pub fn rotate(&self, now: Instant) -> OneTimeCode {
+        let code: String = {
+            let mut rng = rand::thread_rng();
+            (0..ONE_TIME_CODE_LEN)
+                .map(|_| {
+                    ONE_TIME_CODE_ALPHABET[rng.gen_range(0..ONE_TIME_CODE_ALPHABET.len())] as char
+                })
+                .collect()
+        };
+
+        if let Ok(mut current) = self.current.lock() {
+            *current = Some(IssuedCode {
+                code: code.clone(),
+                issued_at: now,
+            });
+        }
+
+        OneTimeCode {
+            code,
+            expires_in_secs: self.ttl.as_secs(),
+        }
+    }
+
+    /// The code to show, `None` once it expired or was used.
+    pub fn current(&self, now: Instant) -> Option<OneTimeCode> {
+        let issued = self.issued(now)?;
+
+        let remaining = self
+            .ttl
+            .saturating_sub(now.saturating_duration_since(issued.issued_at));
+
+        Some(OneTimeCode {
+            code: issued.code,
+            expires_in_secs: remaining.as_secs(),
+        })
+    }
+
+    fn issued(&self, now: Instant) -> Option<IssuedCode> {
+        let current = self.current.lock().ok()?;
+        let issued = current.as_ref()?;
+
+        if now.saturating_duration_since(issued.issued_at) > self.ttl {
+            return None;
+        }
+
+        Some(issued.clone())
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut current) = self.current.lock() {
+            *current = None;
+        }
+    }
+
+    /// Takes the code if it's still the one `issued`, neither rotated nor
+    /// used meanwhile. Two visits racing with the same code let only the
+    /// first one in.
+    fn consume(&self, issued: &IssuedCode) -> bool {
+        let Ok(mut current) = self.current.lock() else {
+            return false;
+        };
+
+        if current.as_ref() != Some(issued) {
+            return false;
+        }
+
+        *current = None;
+        true
+    }
+
+    /// Tries the credentials the mode accepts with `agree`, which opens the
+    /// visit with one of them and fails with `InvalidPassword` when it was
+    /// sealed with another. The device password goes first, a one-time code
+    /// is consumed when the visit opened with it.
+    pub fn verify<T>(
+        &self,
+        mode: AccessMode,
+        device_password: &str,
+        now: Instant,
+        mut agree: impl FnMut(&str) -> Result<T, VisitFailureReason>,
+    ) -> Result<T, VisitFailureReason> {
+        if mode.accepts_device_password() {
+            match agree(device_password) {
+                Err(VisitFailureReason::InvalidPassword) => {}
+                result => return result,
+            }
+        }
+
+        if !mode.accepts_one_time_code() {
+            return Err(VisitFailureReason::InvalidPassword);
+        }
+
+        let Some(issued) = self.issued(now) else {
+            return Err(VisitFailureReason::InvalidPassword);
+        };
+
+        let agreed = agree(&issued.code)?;
+
+        if !self.consume(&issued) {
+            return Err(VisitFailureReason::InvalidPassword);
+        }
+
+        tracing::info!("one-time code used by a visit");
+        Ok(agreed)
+    }
+}
@@ -0,0 +1,200 @@
+use crate::error::{CoreError, CoreResult};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+
+/// Failed calls in a row that open the breaker.
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// How long an open breaker fails calls before it lets one through again.
+pub const DEFAULT_COOLDOWN_SECS: u64 = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    pub failure_threshold: u32,
+    pub cooldown_secs: u64,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: DEFAULT_FAILURE_THRESHOLD,
+            cooldown_secs: DEFAULT_COOLDOWN_SECS,
+        }
+    }
+}
+
+impl CircuitBreakerConfig {
+    /// A breaker opening after no failure or closing right away does
+    /// nothing useful.
+    pub fn sanitize(self) -> Self {
+        Self {
+            failure_threshold: self.failure_threshold.clamp(1, 100),
+            cooldown_secs: self.cooldown_secs.clamp(1, 60 * 60),
+        }
+    }
+
+    fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.cooldown_secs)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    /// Calls go through.
+    Closed,
+    /// Calls fail right away with [`CoreError::SignalingUnavailable`].
+    Open,
+    /// The cooldown passed, one call probes whether the server is back and
+    /// the others fail until it did.
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CircuitBreakerStats {
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    /// Seconds until an open breaker lets a call through again.
+    pub retry_in_secs: Option<u64>,
+    /// Calls failed without reaching the server.
+    pub rejected: u64,
+}
+
+/// Guards the calls to the signaling server. A server failing repeatedly
+/// gets no calls for a cooldown, instead of every visit and its retries
+/// waiting for the same timeout.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    consecutive_failures: u32,
+    /// When the breaker opened or the last probe went out.
+    opened_at: Option<Instant>,
+    probing: bool,
+    rejected: u64,
+}
+
+impl Default for CircuitBreaker {
+    fn default() -> Self {
+        Self::new(CircuitBreakerConfig::default())
+    }
+}
+
+impl CircuitBreaker {
+    pub fn new(config: CircuitBreakerConfig) -> Self {
+        Self {
+            config: config.sanitize(),
+            consecutive_failures: 0,
+            opened_at: None,
+            probing: false,
+            rejected: 0,
+        }
+    }
+
+    /// Takes effect with the next call, an open breaker keeps its state.
+    pub fn set_config(&mut self, config: CircuitBreakerConfig) {
+        self.config = config.sanitize();
+    }
+
+    pub fn config(&self) -> CircuitBreakerConfig {
+        self.config
+    }
+
+    /// Lets a call through or fails it. Once the cooldown passed, one call
+    /// probes the server, and another one only after a further cooldown in
+    /// case the probe never reports back.
+    pub fn acquire(&mut self, now: Instant) -> CoreResult<()> {
+        let Some(opened_at) = self.opened_at else {
+            return Ok(());
+        };
+
+        if now.saturating_duration_since(opened_at) < self.config.cooldown() {
+            self.rejected += 1;
+            return Err(CoreError::SignalingUnavailable);
+        }
+
+        tracing::info!("signaling circuit breaker half open, probe the server");
+        self.opened_at = Some(now);
+        self.probing = true;
+        Ok(())
+    }
+
+    pub fn record_success(&mut self) {
+        if self.opened_at.is_some() {
+            tracing::info!("signaling circuit breaker closed");
+        }
+
+        self.consecutive_failures = 0;
+        self.opened_at = None;
+        self.probing = false;
+    }
+
+    /// A failed probe opens the breaker again for a whole cooldown.
+    pub fn record_failure(&mut self, now: Instant) {
+        self.consecutive_failures = self.consecutive_failures.saturating_add(1);
+
+        if self.probing || self.consecutive_failures >= self.config.failure_threshold {
+            if !self.probing {
+                tracing::warn!(
+                    consecutive_failures = ?self.consecutive_failures,
+                    "signaling circuit breaker opened"
+                );
+            }
+
+            self.opened_at = Some(now);
+            self.probing = false;
+        }
+    }
+
+    /// Records the result of a call that went through. Failures the server
+    /// answered, like a wrong password, show it's working.
+    pub fn record<T>(&mut self, result: &CoreResult<T>, now: Instant) {
+        match result {
+            Err(err) if counts_as_server_failure(err) => self.record_failure(now),
+            _ => self.record_success(),
+        }
+    }
+
+    pub fn state(&self, now: Instant) -> CircuitState {
+        match self.opened_at {
+            None => CircuitState::Closed,
+            Some(_) if self.probing => CircuitState::HalfOpen,
+            Some(opened_at)
+                if now.saturating_duration_since(opened_at) >= self.config.cooldown() =>
+            {
+                CircuitState::HalfOpen
+            }
+            Some(_) => CircuitState::Open,
+        }
+    }
+
+    pub fn stats(&self, now: Instant) -> CircuitBreakerStats {
+        let retry_in_secs = match (self.state(now), self.opened_at) {
+            (CircuitState::Open, Some(opened_at)) => Some(
+                self.config
+                    .cooldown()
+                    .saturating_sub(now.saturating_duration_since(opened_at))
+                    .as_secs(),
+            ),
+            _ => None,
+        };
+
+        CircuitBreakerStats {
+            state: self.state(now),
+            consecutive_failures: self.consecutive_failures,
+            retry_in_secs,
+            rejected: self.rejected,
+        }
+    }
+}
+
+/// Whether an error says the signaling server isn't working, rather than
+/// answering a call it didn't like.
+pub fn counts_as_server_failure(err: &CoreError) -> bool {
+    matches!(
+        err,
+        CoreError::Timeout
+            | CoreError::VisitTimeout
+            | CoreError::VisitServerBusy
+            | CoreError::ReqwestError(_)
+    )
+}
@@ -0,0 +1,138 @@
+use super::key_exchange::KeyAgreementError;
+use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// How long the answered exchanges of a device are remembered. The
+/// exchange carries no time of its own, a replay later than this isn't
+/// recognized.
+pub const EXCHANGE_REPLAY_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// Exchanges remembered per device, the oldest is forgotten first.
+pub const MAX_EXCHANGES_PER_DEVICE: usize = 64;
+
+static EXCHANGE_REPLAY_GUARD: Lazy<ExchangeReplayGuard> =
+    Lazy::new(|| ExchangeReplayGuard::new(EXCHANGE_REPLAY_TTL, MAX_EXCHANGES_PER_DEVICE));
+
+pub fn exchange_replay_guard() -> &'static ExchangeReplayGuard {
+    &EXCHANGE_REPLAY_GUARD
+}
+
+/// The digests of the exchanges of one active device, oldest first.
+type DeviceExchanges = VecDeque<(Instant, [u8; 32])>;
+
+/// The key exchanges the passive side answered recently, by the active
+/// device that sent them. The salt, nonce and sealed secret are fresh for
+/// every visit, the same ones again are a captured visit sent anew.
+pub struct ExchangeReplayGuard {
+    ttl: Duration,
+    capacity: usize,
+    exchanges: Mutex<HashMap<i64, DeviceExchanges>>,
+}
+
+impl ExchangeReplayGuard {
+    pub fn new(ttl: Duration, capacity: usize) -> Self {
+        Self {
+            ttl,
+            capacity,
+            exchanges: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Answers an exchange with `agree` and remembers it once answered. An
+    /// exchange answered before fails with [`KeyAgreementError::Replayed`],
+    /// one that fails to open isn't remembered, so made up exchanges don't
+    /// push out real ones.
+    pub fn answer_once<T>(
+        &self,
+        active_device_id: i64,
+        password_salt: &[u8],
+        secret: &[u8],
+        secret_nonce: &[u8],
+        now: Instant,
+        agree: impl FnOnce() -> Result<T, KeyAgreementError>,
+    ) -> Result<T, KeyAgreementError> {
+        let digest = exchange_digest(password_salt, secret, secret_nonce);
+
+        if self.seen(active_device_id, &digest, now) {
+            return Err(KeyAgreementError::Replayed);
+        }
+
+        let agreed = agree()?;
+
+        // a replay racing the original is answered by both, only the first
+        // gets to keep it
+        if !self.remember(active_device_id, digest, now) {
+            return Err(KeyAgreementError::Replayed);
+        }
+
+        Ok(agreed)
+    }
+
+    /// Drops the exchanges older than the ttl, returns how many.
+    pub fn purge(&self, now: Instant) -> usize {
+        let mut exchanges = self.exchanges();
+        let mut purged = 0;
+
+        exchanges.retain(|_, device_exchanges| {
+            let len = device_exchanges.len();
+            device_exchanges
+                .retain(|(answered_at, _)| now.saturating_duration_since(*answered_at) < self.ttl);
+            purged += len - device_exchanges.len();
+            !device_exchanges.is_empty()
+        });
+
+        purged
+    }
+
+    pub fn len(&self) -> usize {
+        self.exchanges().values().map(VecDeque::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn seen(&self, active_device_id: i64, digest: &[u8; 32], now: Instant) -> bool {
+        self.purge(now);
+        self.exchanges()
+            .get(&active_device_id)
+            .is_some_and(|device_exchanges| device_exchanges.iter().any(|(_, seen)| seen == digest))
+    }
+
+    fn remember(&self, active_device_id: i64, digest: [u8; 32], now: Instant) -> bool {
+        let mut exchanges = self.exchanges();
+        let device_exchanges = exchanges.entry(active_device_id).or_default();
+
+        if device_exchanges.iter().any(|(_, seen)| *seen == digest) {
+            return false;
+        }
+
+        if device_exchanges.len() >= self.capacity {
+            device_exchanges.pop_front();
+        }
+
+        device_exchanges.push_back((now, digest));
+        true
+    }
+
+    fn exchanges(&self) -> MutexGuard<'_, HashMap<i64, DeviceExchanges>> {
+        self.exchanges
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+fn exchange_digest(password_salt: &[u8], secret: &[u8], secret_nonce: &[u8]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in [password_salt, secret_nonce, secret] {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+
+    hasher.finalize().into()
+}
@@ -1,4 +1,5 @@
 use super::subscribe_message::VisitFailureReason;
+use crate::error::CoreError;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize)]
@@ -10,6 +11,18 @@ pub enum HttpError {
     RemoteOffline,
 }
 
+impl From<HttpError> for CoreError {
+    fn from(err: HttpError) -> Self {
+        match err {
+            HttpError::Internal => CoreError::VisitInternalError,
+            HttpError::Timeout => CoreError::VisitTimeout,
+            HttpError::InvalidArgs => CoreError::VisitInvalidArgs,
+            HttpError::ResourceExhausted => CoreError::VisitServerBusy,
+            HttpError::RemoteOffline => CoreError::VisitRemoteOffline,
+        }
+    }
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(untagged)]
 pub enum Response<T> {
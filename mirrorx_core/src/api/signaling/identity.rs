@@ -0,0 +1,210 @@
+use crate::{
+    api::config::entity::kv::KVRepository,
+    core_error,
+    error::{CoreError, CoreResult},
+};
+use once_cell::sync::Lazy;
+use ring::signature::{Ed25519KeyPair, KeyPair, UnparsedPublicKey, ED25519};
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+static DEVICE_IDENTITY: Lazy<RwLock<Option<Arc<DeviceIdentity>>>> = Lazy::new(|| RwLock::new(None));
+
+static MUTUAL_AUTH_CONFIG: Lazy<RwLock<MutualAuthConfig>> =
+    Lazy::new(|| RwLock::new(MutualAuthConfig::default()));
+
+const ACTIVE_TRANSCRIPT_LABEL: &[u8] = b"mirrorx visit active identity v1";
+const PASSIVE_TRANSCRIPT_LABEL: &[u8] = b"mirrorx visit passive identity v1";
+
+/// The long-term Ed25519 key pair of this device. It signs the ephemeral
+/// keys of a visit, so a device trusting its public key knows the keys came
+/// from this device and not from someone in between who knows the password.
+pub struct DeviceIdentity {
+    key_pair: Ed25519KeyPair,
+}
+
+impl std::fmt::Debug for DeviceIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceIdentity")
+            .field("public_key", &base64::encode(self.public_key()))
+            .finish()
+    }
+}
+
+impl DeviceIdentity {
+    /// A new key pair in PKCS#8, the form it's stored in.
+    pub fn generate() -> CoreResult<Vec<u8>> {
+        let pkcs8 = Ed25519KeyPair::generate_pkcs8(&ring::rand::SystemRandom::new())
+            .map_err(|_| core_error!("generate device identity key failed"))?;
+        Ok(pkcs8.as_ref().to_vec())
+    }
+
+    pub fn from_pkcs8(pkcs8: &[u8]) -> CoreResult<Self> {
+        let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8)
+            .map_err(|err| core_error!("parse device identity key failed ({})", err))?;
+        Ok(Self { key_pair })
+    }
+
+    /// The stored identity of this device, generated on first use.
+    pub fn load_or_generate(kv: &KVRepository) -> CoreResult<Self> {
+        if let Some(pkcs8) = kv.get_device_identity_key()? {
+            return Self::from_pkcs8(&pkcs8);
+        }
+
+        let pkcs8 = Self::generate()?;
+        kv.set_device_identity_key(&pkcs8)?;
+        tracing::info!("device identity key generated");
+
+        Self::from_pkcs8(&pkcs8)
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        self.key_pair.public_key().as_ref()
+    }
+
+    pub fn prove(&self, transcript: &[u8]) -> DeviceIdentityProof {
+        DeviceIdentityProof {
+            public_key: self.public_key().to_vec(),
+            signature: self.key_pair.sign(transcript).as_ref().to_vec(),
+        }
+    }
+}
+
+/// Who signed a key exchange secret and the signature over its transcript.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceIdentityProof {
+    #[serde(with = "serde_bytes")]
+    pub public_key: Vec<u8>,
+    #[serde(with = "serde_bytes")]
+    pub signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedDevice {
+    pub name: String,
+    /// Base64 of the Ed25519 public key the device shows with
+    /// `config_device_public_key_get`.
+    pub public_key: String,
+}
+
+/// Mutual authentication of visits. While enabled, this device signs its
+/// half of every key exchange, and only completes exchanges whose other half
+/// is signed by a trusted device, both when visiting and when visited.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MutualAuthConfig {
+    pub enabled: bool,
+    pub trusted_devices: Vec<TrustedDevice>,
+}
+
+impl MutualAuthConfig {
+    /// Drops the entries that aren't a base64 Ed25519 public key.
+    pub fn sanitize(self) -> Self {
+        let trusted_devices = self
+            .trusted_devices
+            .into_iter()
+            .filter(|device| {
+                let valid = base64::decode(device.public_key.trim())
+                    .is_ok_and(|public_key| public_key.len() == 32);
+                if !valid {
+                    tracing::warn!(name = ?device.name, "drop trusted device with invalid public key");
+                }
+                valid
+            })
+            .map(|device| TrustedDevice {
+                name: device.name,
+                public_key: device.public_key.trim().to_string(),
+            })
+            .collect();
+
+        Self {
+            enabled: self.enabled,
+            trusted_devices,
+        }
+    }
+
+    pub fn trusts(&self, public_key: &[u8]) -> bool {
+        self.trusted_devices.iter().any(|device| {
+            base64::decode(&device.public_key).is_ok_and(|trusted| trusted == public_key)
+        })
+    }
+
+    /// Checks the signature over `transcript` and that the signer is
+    /// trusted.
+    pub fn verify(&self, proof: &DeviceIdentityProof, transcript: &[u8]) -> CoreResult<()> {
+        UnparsedPublicKey::new(&ED25519, &proof.public_key)
+            .verify(transcript, &proof.signature)
+            .map_err(|_| CoreError::VisitSignatureInvalid)?;
+
+        if !self.trusts(&proof.public_key) {
+            return Err(CoreError::VisitDeviceUntrusted);
+        }
+
+        Ok(())
+    }
+}
+
+pub fn set_device_identity(identity: Option<Arc<DeviceIdentity>>) {
+    if let Ok(mut device_identity) = DEVICE_IDENTITY.write() {
+        *device_identity = identity;
+    }
+}
+
+/// `None` until the storage was loaded.
+pub fn device_identity() -> Option<Arc<DeviceIdentity>> {
+    DEVICE_IDENTITY
+        .read()
+        .map(|identity| identity.clone())
+        .unwrap_or_default()
+}
+
+/// Applies to visits started afterwards.
+pub fn set_mutual_auth_config(config: MutualAuthConfig) {
+    if let Ok(mut mutual_auth_config) = MUTUAL_AUTH_CONFIG.write() {
+        *mutual_auth_config = config.sanitize();
+    }
+}
+
+pub fn mutual_auth_config() -> MutualAuthConfig {
+    MUTUAL_AUTH_CONFIG
+        .read()
+        .map(|config| config.clone())
+        .unwrap_or_default()
+}
+
+/// What the active device signs, its ephemeral key and nonce bound to its
+/// device id and the key the reply is encrypted to.
+pub fn active_transcript(
+    active_device_id: i64,
+    reply_public_key_n: &[u8],
+    active_exchange_public_key: &[u8],
+    active_exchange_nonce: &[u8],
+) -> Vec<u8> {
+    [
+        ACTIVE_TRANSCRIPT_LABEL,
+        &active_device_id.to_le_bytes(),
+        reply_public_key_n,
+        active_exchange_public_key,
+        active_exchange_nonce,
+    ]
+    .concat()
+}
+
+/// What the passive device signs, both ephemeral keys and nonces, so its
+/// signature only fits the exchange with this active device.
+pub fn passive_transcript(
+    active_device_id: i64,
+    active_exchange_public_key: &[u8],
+    active_exchange_nonce: &[u8],
+    passive_exchange_public_key: &[u8],
+    passive_exchange_nonce: &[u8],
+) -> Vec<u8> {
+    [
+        PASSIVE_TRANSCRIPT_LABEL,
+        &active_device_id.to_le_bytes(),
+        active_exchange_public_key,
+        active_exchange_nonce,
+        passive_exchange_public_key,
+        passive_exchange_nonce,
+    ]
+    .concat()
+}
@@ -0,0 +1,755 @@
+use super::{
+    identity::{active_transcript, passive_transcript, DeviceIdentity, MutualAuthConfig},
+    key_exchange_failed, key_generation_failed,
+    subscribe_message::{
+        ActiveEndpointKeyExchangeSecret, PassiveEndpointKeyExchangeSecret, SignedKeyExchangeSecret,
+        VersionedKeyExchangeSecret, VisitFailureReason,
+    },
+};
+use crate::{
+    error::{CoreError, CoreResult},
+    utility::{
+        bincode::{bincode_deserialize, bincode_serialize},
+        nonce_value::NonceValue,
+    },
+};
+use hmac::Hmac;
+use rand::{CryptoRng, RngCore};
+use ring::aead::{BoundKey, OpeningKey, SealingKey, UnboundKey};
+use rsa::{BigUint, PublicKey, PublicKeyParts, RsaPrivateKey};
+use sha2::Sha256;
+
+/// Size of the key pair the passive device encrypts its reply to.
+pub const REPLY_KEY_BITS: usize = 4096;
+
+/// PBKDF2 rounds deriving the key that seals the visit secret from the
+/// password.
+pub const PASSWORD_ROUNDS: u32 = 10000;
+
+/// How the session keys are derived from the X25519 shared secret. The
+/// active device offers the latest one it knows, the passive device answers
+/// with the latest one both know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyDerivation {
+    /// HKDF without info, the directions only differ by their salt. Devices
+    /// that don't negotiate the key derivation use it.
+    Unlabeled,
+    /// HKDF with a versioned label per direction.
+    Labeled,
+}
+
+impl KeyDerivation {
+    pub const LATEST: KeyDerivation = KeyDerivation::Labeled;
+
+    pub fn version(self) -> u8 {
+        match self {
+            KeyDerivation::Unlabeled => 0,
+            KeyDerivation::Labeled => 1,
+        }
+    }
+
+    /// The derivation to use with a device offering `version`, the latest
+    /// one this device knows for a newer version.
+    pub fn negotiate(version: u8) -> Self {
+        match version {
+            0 => KeyDerivation::Unlabeled,
+            _ => KeyDerivation::Labeled,
+        }
+    }
+
+    /// The session key of `direction`, salted with the exchange nonce of the
+    /// sending side.
+    pub fn derive(
+        self,
+        key_material: &[u8],
+        nonce: &[u8],
+        direction: KeyDirection,
+    ) -> Result<Vec<u8>, ring::error::Unspecified> {
+        match self {
+            KeyDerivation::Unlabeled => derive_session_key(key_material, nonce),
+            KeyDerivation::Labeled => hkdf_session_key(key_material, nonce, direction.label()),
+        }
+    }
+}
+
+/// Which way the traffic a session key protects goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyDirection {
+    ActiveToPassive,
+    PassiveToActive,
+}
+
+impl KeyDirection {
+    /// HKDF info of the keys of this direction. Keys of another channel, a
+    /// separate one for files say, would get labels of their own.
+    pub fn label(self) -> &'static [u8] {
+        match self {
+            KeyDirection::ActiveToPassive => b"mirrorx endpoint active to passive v1",
+            KeyDirection::PassiveToActive => b"mirrorx endpoint passive to active v1",
+        }
+    }
+}
+
+/// The active half of a visit key exchange. All randomness but the X25519
+/// key comes from the rng it's started with, `OsRng` in the app and a seeded
+/// one in tests. ring only generates ephemeral keys from its system random,
+/// the derivation of the session keys is [`derive_session_key`] so it can be
+/// checked with fixed key material.
+pub struct ActiveKeyExchange {
+    reply_private_key: RsaPrivateKey,
+    exchange_private_key: ring::agreement::EphemeralPrivateKey,
+    exchange_public_key: Vec<u8>,
+    exchange_nonce: [u8; ring::aead::NONCE_LEN],
+    key_derivation: KeyDerivation,
+    local_device_id: i64,
+    /// Set when the reply has to be signed by a trusted device.
+    mutual_auth: Option<MutualAuthConfig>,
+    pub password_salt: [u8; 16],
+    /// The exchange secret sealed with the password, sent to the passive
+    /// device with the salt and the nonce.
+    pub secret: Vec<u8>,
+    pub secret_nonce: [u8; ring::aead::NONCE_LEN],
+}
+
+impl ActiveKeyExchange {
+    /// Seals the active half of the exchange with the password. The passive
+    /// device encrypts its reply to `reply_private_key`, usually a fresh key
+    /// of [`REPLY_KEY_BITS`].
+    pub fn new<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        reply_private_key: RsaPrivateKey,
+        local_device_id: i64,
+        password: &str,
+    ) -> CoreResult<Self> {
+        Self::with_key_derivation(
+            rng,
+            reply_private_key,
+            local_device_id,
+            password,
+            KeyDerivation::LATEST,
+        )
+    }
+
+    /// Like [`ActiveKeyExchange::new`], offering `key_derivation`. Offering
+    /// [`KeyDerivation::Unlabeled`] sends the plain secret older passive
+    /// devices understand.
+    pub fn with_key_derivation<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        reply_private_key: RsaPrivateKey,
+        local_device_id: i64,
+        password: &str,
+        key_derivation: KeyDerivation,
+    ) -> CoreResult<Self> {
+        Self::seal(
+            rng,
+            reply_private_key,
+            local_device_id,
+            password,
+            key_derivation,
+            None,
+        )
+    }
+
+    /// Like [`ActiveKeyExchange::new`], and while mutual authentication is
+    /// enabled signs the exchange with `identity` and only opens a reply
+    /// signed by a device `config` trusts.
+    pub fn with_identity<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        reply_private_key: RsaPrivateKey,
+        local_device_id: i64,
+        password: &str,
+        identity: Option<&DeviceIdentity>,
+        config: &MutualAuthConfig,
+    ) -> CoreResult<Self> {
+        if !config.enabled {
+            return Self::new(rng, reply_private_key, local_device_id, password);
+        }
+
+        let Some(identity) = identity else {
+            return Err(key_exchange_failed("device identity isn't loaded"));
+        };
+
+        Self::seal(
+            rng,
+            reply_private_key,
+            local_device_id,
+            password,
+            KeyDerivation::LATEST,
+            Some((identity, config)),
+        )
+    }
+
+    fn seal<R: RngCore + CryptoRng>(
+        rng: &mut R,
+        reply_private_key: RsaPrivateKey,
+        local_device_id: i64,
+        password: &str,
+        key_derivation: KeyDerivation,
+        identity: Option<(&DeviceIdentity, &MutualAuthConfig)>,
+    ) -> CoreResult<Self> {
+        let reply_public_key = reply_private_key.to_public_key();
+
+        // generate exchange key pair and nonce
+        let exchange_private_key = ring::agreement::EphemeralPrivateKey::generate(
+            &ring::agreement::X25519,
+            &ring::rand::SystemRandom::new(),
+        )
+        .map_err(key_generation_failed)?;
+        let exchange_public_key = exchange_private_key
+            .compute_public_key()
+            .map_err(key_generation_failed)?;
+
+        let mut exchange_nonce = [0u8; ring::aead::NONCE_LEN];
+        rng.fill_bytes(&mut exchange_nonce);
+
+        // generate and sealing active device key exchange secret
+        let active_device_secret = ActiveEndpointKeyExchangeSecret {
+            exchange_reply_public_key_n: &reply_public_key.n().to_bytes_le(),
+            exchange_reply_public_key_e: &reply_public_key.e().to_bytes_le(),
+            active_exchange_public_key: exchange_public_key.as_ref(),
+            active_exchange_nonce: &exchange_nonce,
+        };
+
+        // generate secret sealing key with salt
+        let mut password_salt = [0u8; 16];
+        rng.fill_bytes(&mut password_salt);
+
+        let mut secret_sealing_key = [0u8; 32];
+        pbkdf2::pbkdf2::<Hmac<Sha256>>(
+            password.as_bytes(),
+            &password_salt,
+            PASSWORD_ROUNDS,
+            &mut secret_sealing_key,
+        );
+
+        let mut secret = match (key_derivation, identity) {
+            (KeyDerivation::Unlabeled, _) => bincode_serialize(&active_device_secret),
+            (KeyDerivation::Labeled, None) => bincode_serialize(&VersionedKeyExchangeSecret {
+                secret: active_device_secret,
+                key_derivation_version: key_derivation.version(),
+            }),
+            (KeyDerivation::Labeled, Some((identity, _))) => {
+                let proof = identity.prove(&active_transcript(
+                    local_device_id,
+                    active_device_secret.exchange_reply_public_key_n,
+                    active_device_secret.active_exchange_public_key,
+                    active_device_secret.active_exchange_nonce,
+                ));
+
+                bincode_serialize(&SignedKeyExchangeSecret {
+                    secret: VersionedKeyExchangeSecret {
+                        secret: active_device_secret,
+                        key_derivation_version: key_derivation.version(),
+                    },
+                    proof,
+                })
+            }
+        }
+        .map_err(key_exchange_failed)?;
+
+        let secret_sealing_unbound_key =
+            ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, &secret_sealing_key)
+                .map_err(key_exchange_failed)?;
+
+        let mut secret_nonce = [0u8; ring::aead::NONCE_LEN];
+        rng.fill_bytes(&mut secret_nonce);
+
+        let mut secret_sealing_key =
+            ring::aead::SealingKey::new(secret_sealing_unbound_key, NonceValue::new(secret_nonce));
+
+        secret_sealing_key
+            .seal_in_place_append_tag(
+                ring::aead::Aad::from(local_device_id.to_le_bytes()),
+                &mut secret,
+            )
+            .map_err(key_exchange_failed)?;
+
+        Ok(Self {
+            reply_private_key,
+            exchange_private_key,
+            exchange_public_key: exchange_public_key.as_ref().to_vec(),
+            exchange_nonce,
+            key_derivation,
+            local_device_id,
+            mutual_auth: identity.map(|(_, config)| config.clone()),
+            password_salt,
+            secret,
+            secret_nonce,
+        })
+    }
+
+    /// Decrypts the secret the passive device replied with and derives the
+    /// keys of the endpoint connection from it. With mutual authentication
+    /// a reply not signed by a trusted device fails with
+    /// [`CoreError::VisitSignatureInvalid`] or
+    /// [`CoreError::VisitDeviceUntrusted`].
+    pub fn open_reply(
+        self,
+        secret: &[u8],
+    ) -> CoreResult<(OpeningKey<NonceValue>, SealingKey<NonceValue>)> {
+        let passive_device_secret_buffer = self
+            .reply_private_key
+            .decrypt(rsa::PaddingScheme::PKCS1v15Encrypt, secret)?;
+
+        // a passive device answers an offer in kind
+        let (passive_device_secret, key_derivation) = match (self.key_derivation, &self.mutual_auth)
+        {
+            (KeyDerivation::Labeled, Some(config)) => {
+                let signed: SignedKeyExchangeSecret<PassiveEndpointKeyExchangeSecret> =
+                    match bincode_deserialize(&passive_device_secret_buffer) {
+                        Ok(signed) => signed,
+                        Err(_) => {
+                            bincode_deserialize::<
+                                VersionedKeyExchangeSecret<PassiveEndpointKeyExchangeSecret>,
+                            >(&passive_device_secret_buffer)?;
+
+                            tracing::warn!("passive device replied without a device identity");
+                            return Err(CoreError::VisitDeviceUntrusted);
+                        }
+                    };
+
+                let versioned = signed.secret;
+                if versioned.key_derivation_version > self.key_derivation.version() {
+                    return Err(key_exchange_failed(
+                        "passive device chose a key derivation not offered",
+                    ));
+                }
+
+                config.verify(
+                    &signed.proof,
+                    &passive_transcript(
+                        self.local_device_id,
+                        &self.exchange_public_key,
+                        &self.exchange_nonce,
+                        versioned.secret.passive_exchange_public_key,
+                        versioned.secret.passive_exchange_nonce,
+                    ),
+                )?;
+
+                (
+                    versioned.secret,
+                    KeyDerivation::negotiate(versioned.key_derivation_version),
+                )
+            }
+            (KeyDerivation::Unlabeled, _) => (
+                bincode_deserialize::<PassiveEndpointKeyExchangeSecret>(
+                    &passive_device_secret_buffer,
+                )?,
+                KeyDerivation::Unlabeled,
+            ),
+            (KeyDerivation::Labeled, None) => {
+                let versioned: VersionedKeyExchangeSecret<PassiveEndpointKeyExchangeSecret> =
+                    bincode_deserialize(&passive_device_secret_buffer)?;
+
+                if versioned.key_derivation_version > self.key_derivation.version() {
+                    return Err(key_exchange_failed(
+                        "passive device chose a key derivation not offered",
+                    ));
+                }
+
+                (
+                    versioned.secret,
+                    KeyDerivation::negotiate(versioned.key_derivation_version),
+                )
+            }
+        };
+
+        if passive_device_secret.passive_exchange_nonce.len() != ring::aead::NONCE_LEN {
+            return Err(key_exchange_failed("invalid passive exchange nonce length"));
+        }
+
+        let passive_exchange_public_key = ring::agreement::UnparsedPublicKey::new(
+            &ring::agreement::X25519,
+            passive_device_secret.passive_exchange_public_key,
+        );
+
+        let exchange_nonce = self.exchange_nonce;
+        let (raw_sealing_key, raw_opening_key) = ring::agreement::agree_ephemeral(
+            self.exchange_private_key,
+            &passive_exchange_public_key,
+            ring::error::Unspecified,
+            |key_material| {
+                let sealing_key = key_derivation.derive(
+                    key_material,
+                    &exchange_nonce,
+                    KeyDirection::ActiveToPassive,
+                )?;
+                let opening_key = key_derivation.derive(
+                    key_material,
+                    passive_device_secret.passive_exchange_nonce,
+                    KeyDirection::PassiveToActive,
+                )?;
+
+                Ok((sealing_key, opening_key))
+            },
+        )?;
+
+        let unbound_sealing_key =
+            ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, &raw_sealing_key)?;
+
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(passive_device_secret.passive_exchange_nonce);
+        let sealing_key = ring::aead::SealingKey::new(unbound_sealing_key, NonceValue::new(nonce));
+
+        let unbound_opening_key =
+            ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, &raw_opening_key)?;
+
+        let opening_key =
+            ring::aead::OpeningKey::new(unbound_opening_key, NonceValue::new(exchange_nonce));
+
+        Ok((opening_key, sealing_key))
+    }
+}
+
+/// AES-256-GCM key of one direction of the endpoint connection, HKDF-SHA512
+/// of the X25519 shared secret salted with the exchange nonce of the sending
+/// side. The derivation of [`KeyDerivation::Unlabeled`].
+pub fn derive_session_key(
+    key_material: &[u8],
+    nonce: &[u8],
+) -> Result<Vec<u8>, ring::error::Unspecified> {
+    hkdf_session_key(key_material, nonce, b"")
+}
+
+fn hkdf_session_key(
+    key_material: &[u8],
+    nonce: &[u8],
+    info: &[u8],
+) -> Result<Vec<u8>, ring::error::Unspecified> {
+    ring::hkdf::Salt::new(ring::hkdf::HKDF_SHA512, nonce)
+        .extract(key_material)
+        .expand(&[info], &ring::aead::AES_256_GCM)
+        .and_then(|orm| {
+            let mut key = vec![0u8; ring::aead::AES_256_GCM.key_len()];
+            orm.fill(&mut key)?;
+            Ok(key)
+        })
+}
+
+/// Why the passive side refused a key exchange.
+#[derive(Debug)]
+pub enum KeyAgreementError {
+    /// Relayed to the active device as is.
+    Failure(VisitFailureReason),
+    /// The active device didn't prove a trusted identity, it's told the
+    /// visit was rejected.
+    Identity(CoreError),
+    /// The exchange was answered before, someone captured and sent it
+    /// again.
+    Replayed,
+}
+
+impl KeyAgreementError {
+    pub fn reason(self) -> VisitFailureReason {
+        match self {
+            KeyAgreementError::Failure(reason) => reason,
+            KeyAgreementError::Identity(err) => {
+                tracing::warn!(?err, "reject visit of an unauthenticated device");
+                VisitFailureReason::RemoteReject
+            }
+            KeyAgreementError::Replayed => {
+                tracing::warn!("reject replayed key exchange");
+                VisitFailureReason::Replayed
+            }
+        }
+    }
+}
+
+impl From<VisitFailureReason> for KeyAgreementError {
+    fn from(reason: VisitFailureReason) -> Self {
+        KeyAgreementError::Failure(reason)
+    }
+}
+
+/// The reply to the active device and the session keys of the passive side.
+pub type AgreedKeys = (Vec<u8>, SealingKey<NonceValue>, OpeningKey<NonceValue>);
+
+/// The passive side of the exchange: opens the secret the active device
+/// sealed with the password and replies with the passive half of the key
+/// agreement, encrypted to the reply key of the active device.
+pub fn key_agreement<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    domain_password: &str,
+    active_device_id: i64,
+    password_salt: &[u8],
+    secret: Vec<u8>,
+    secret_nonce: &[u8],
+) -> Result<AgreedKeys, VisitFailureReason> {
+    key_agreement_with_identity(
+        rng,
+        domain_password,
+        active_device_id,
+        password_salt,
+        secret,
+        secret_nonce,
+        None,
+        &MutualAuthConfig::default(),
+    )
+    .map_err(KeyAgreementError::reason)
+}
+
+/// Like [`key_agreement`]. While `config` is enabled only an active device
+/// signing the exchange as a trusted device is answered, and the reply to
+/// an active device that signed is signed with `identity`.
+#[allow(clippy::too_many_arguments)]
+pub fn key_agreement_with_identity<R: RngCore + CryptoRng>(
+    rng: &mut R,
+    domain_password: &str,
+    active_device_id: i64,
+    password_salt: &[u8],
+    mut secret: Vec<u8>,
+    secret_nonce: &[u8],
+    identity: Option<&DeviceIdentity>,
+    config: &MutualAuthConfig,
+) -> Result<AgreedKeys, KeyAgreementError> {
+    if secret_nonce.len() != ring::aead::NONCE_LEN {
+        return Err(VisitFailureReason::InternalError.into());
+    }
+
+    // generate secret opening key with salt
+    let mut active_device_secret_opening_key = [0u8; 32];
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(
+        domain_password.as_bytes(),
+        password_salt,
+        PASSWORD_ROUNDS,
+        &mut active_device_secret_opening_key,
+    );
+
+    let unbound_key = match ring::aead::UnboundKey::new(
+        &ring::aead::AES_256_GCM,
+        &active_device_secret_opening_key,
+    ) {
+        Ok(unbound_key) => unbound_key,
+        Err(err) => {
+            tracing::error!(?err, "create unbound key failed");
+            return Err(VisitFailureReason::InternalError.into());
+        }
+    };
+
+    let mut active_device_secret_opening_nonce = [0u8; ring::aead::NONCE_LEN];
+    active_device_secret_opening_nonce[..ring::aead::NONCE_LEN]
+        .copy_from_slice(&secret_nonce[..ring::aead::NONCE_LEN]);
+
+    let mut active_device_secret_opening_key = ring::aead::OpeningKey::new(
+        unbound_key,
+        NonceValue::new(active_device_secret_opening_nonce),
+    );
+
+    let active_device_secret_buffer = match active_device_secret_opening_key.open_in_place(
+        ring::aead::Aad::from(active_device_id.to_le_bytes()),
+        &mut secret,
+    ) {
+        Ok(buffer) => buffer,
+        Err(_) => return Err(VisitFailureReason::InvalidPassword.into()),
+    };
+
+    // an active device with mutual authentication sends the signed secret,
+    // one offering a key derivation the versioned secret, and one that
+    // doesn't the plain one
+    let (active_device_secret, offered_version, proof) = match bincode_deserialize::<
+        SignedKeyExchangeSecret<ActiveEndpointKeyExchangeSecret>,
+    >(&*active_device_secret_buffer)
+    {
+        Ok(signed) => (
+            signed.secret.secret,
+            Some(signed.secret.key_derivation_version),
+            Some(signed.proof),
+        ),
+        Err(_) => match bincode_deserialize::<
+            VersionedKeyExchangeSecret<ActiveEndpointKeyExchangeSecret>,
+        >(&*active_device_secret_buffer)
+        {
+            Ok(versioned) => (
+                versioned.secret,
+                Some(versioned.key_derivation_version),
+                None,
+            ),
+            Err(_) => match bincode_deserialize::<ActiveEndpointKeyExchangeSecret>(
+                &*active_device_secret_buffer,
+            ) {
+                Ok(secret) => (secret, None, None),
+                Err(_) => {
+                    return Err(VisitFailureReason::InvalidArgs.into());
+                }
+            },
+        },
+    };
+
+    if config.enabled {
+        let Some(ref proof) = proof else {
+            return Err(KeyAgreementError::Identity(CoreError::VisitDeviceUntrusted));
+        };
+
+        config
+            .verify(
+                proof,
+                &active_transcript(
+                    active_device_id,
+                    active_device_secret.exchange_reply_public_key_n,
+                    active_device_secret.active_exchange_public_key,
+                    active_device_secret.active_exchange_nonce,
+                ),
+            )
+            .map_err(KeyAgreementError::Identity)?;
+    }
+
+    let key_derivation = offered_version
+        .map(KeyDerivation::negotiate)
+        .unwrap_or(KeyDerivation::Unlabeled);
+
+    if active_device_secret.active_exchange_nonce.len() != ring::aead::NONCE_LEN {
+        return Err(VisitFailureReason::InvalidArgs.into());
+    }
+
+    // generate passive device key exchange pair and nonce, ring only takes
+    // its own random source for the ephemeral key
+
+    let system_random_rng = ring::rand::SystemRandom::new();
+
+    let passive_exchange_private_key = match ring::agreement::EphemeralPrivateKey::generate(
+        &ring::agreement::X25519,
+        &system_random_rng,
+    ) {
+        Ok(private_key) => private_key,
+        Err(_) => return Err(VisitFailureReason::InternalError.into()),
+    };
+
+    let passive_exchange_public_key = match passive_exchange_private_key.compute_public_key() {
+        Ok(public_key) => public_key,
+        Err(err) => {
+            tracing::error!(
+                ?err,
+                "compute public key from passive exchange private key failed"
+            );
+            return Err(VisitFailureReason::InternalError.into());
+        }
+    };
+
+    let mut passive_exchange_nonce = [0u8; ring::aead::NONCE_LEN];
+    rng.fill_bytes(&mut passive_exchange_nonce);
+
+    // key agreement
+
+    let mut active_exchange_nonce = [0u8; ring::aead::NONCE_LEN];
+    active_exchange_nonce[..ring::aead::NONCE_LEN]
+        .copy_from_slice(&active_device_secret.active_exchange_nonce[..ring::aead::NONCE_LEN]);
+
+    let active_exchange_public_key = ring::agreement::UnparsedPublicKey::new(
+        &ring::agreement::X25519,
+        active_device_secret.active_exchange_public_key,
+    );
+
+    let agree_result = ring::agreement::agree_ephemeral(
+        passive_exchange_private_key,
+        &active_exchange_public_key,
+        ring::error::Unspecified,
+        |key_material| {
+            let sealing_key = key_derivation.derive(
+                key_material,
+                &passive_exchange_nonce,
+                KeyDirection::PassiveToActive,
+            )?;
+            let opening_key = key_derivation.derive(
+                key_material,
+                active_device_secret.active_exchange_nonce,
+                KeyDirection::ActiveToPassive,
+            )?;
+
+            Ok((sealing_key, opening_key))
+        },
+    );
+
+    let (raw_sealing_key, raw_opening_key) = match agree_result {
+        Ok(v) => v,
+        Err(err) => {
+            tracing::error!(?err, "agree ephemeral failed");
+            return Err(VisitFailureReason::InternalError.into());
+        }
+    };
+
+    // derive opening and sealing key
+
+    let unbound_sealing_key = match UnboundKey::new(&ring::aead::AES_256_GCM, &raw_sealing_key) {
+        Ok(unbound_sealing_key) => unbound_sealing_key,
+        Err(err) => {
+            tracing::error!(?err, "create unbound sealing key failed");
+            return Err(VisitFailureReason::InternalError.into());
+        }
+    };
+
+    let sealing_key = SealingKey::new(unbound_sealing_key, NonceValue::new(active_exchange_nonce));
+
+    let unbound_opening_key = match UnboundKey::new(&ring::aead::AES_256_GCM, &raw_opening_key) {
+        Ok(unbound_opening_key) => unbound_opening_key,
+        Err(err) => {
+            tracing::error!(?err, "create unbound opening failed");
+            return Err(VisitFailureReason::InternalError.into());
+        }
+    };
+
+    let opening_key =
+        ring::aead::OpeningKey::new(unbound_opening_key, NonceValue::new(passive_exchange_nonce));
+
+    // build key exchange response
+
+    let passive_device_secret = PassiveEndpointKeyExchangeSecret {
+        passive_exchange_public_key: passive_exchange_public_key.as_ref(),
+        passive_exchange_nonce: &passive_exchange_nonce,
+    };
+
+    // an active device that signed wants a signed reply
+    let passive_device_secret_buffer = match (offered_version, proof, identity) {
+        (Some(_), Some(_), Some(identity)) => {
+            let proof = identity.prove(&passive_transcript(
+                active_device_id,
+                active_device_secret.active_exchange_public_key,
+                active_device_secret.active_exchange_nonce,
+                passive_device_secret.passive_exchange_public_key,
+                passive_device_secret.passive_exchange_nonce,
+            ));
+
+            bincode_serialize(&SignedKeyExchangeSecret {
+                secret: VersionedKeyExchangeSecret {
+                    secret: passive_device_secret,
+                    key_derivation_version: key_derivation.version(),
+                },
+                proof,
+            })
+        }
+        (Some(_), _, _) => bincode_serialize(&VersionedKeyExchangeSecret {
+            secret: passive_device_secret,
+            key_derivation_version: key_derivation.version(),
+        }),
+        (None, _, _) => bincode_serialize(&passive_device_secret),
+    };
+
+    let passive_device_secret_buffer = match passive_device_secret_buffer {
+        Ok(buffer) => buffer,
+        Err(_) => return Err(VisitFailureReason::InternalError.into()),
+    };
+
+    let active_exchange_reply_public_key = match rsa::RsaPublicKey::new(
+        BigUint::from_bytes_le(active_device_secret.exchange_reply_public_key_n),
+        BigUint::from_bytes_le(active_device_secret.exchange_reply_public_key_e),
+    ) {
+        Ok(public_key) => public_key,
+        Err(err) => {
+            tracing::error!(?err, "recover exchange reply public key failed");
+            return Err(VisitFailureReason::InternalError.into());
+        }
+    };
+
+    let secret_buffer = match active_exchange_reply_public_key.encrypt(
+        rng,
+        rsa::PaddingScheme::PKCS1v15Encrypt,
+        &passive_device_secret_buffer,
+    ) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            tracing::error!(?err, "encrypt exchange reply data failed");
+            return Err(VisitFailureReason::InternalError.into());
+        }
+    };
+
+    Ok((secret_buffer, sealing_key, opening_key))
+}
@@ -0,0 +1,208 @@
+use super::{key_exchange::REPLY_KEY_BITS, key_generation_failed};
+use crate::error::CoreResult;
+use once_cell::sync::Lazy;
+use rsa::{rand_core::OsRng, RsaPrivateKey};
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+use tokio_util::sync::CancellationToken;
+
+/// Keys kept ready, for a visit and a retry right after it.
+pub const KEY_POOL_CAPACITY: usize = 2;
+
+/// How long an unused private key may sit in memory before it's replaced.
+pub const POOLED_KEY_TTL: Duration = Duration::from_secs(10 * 60);
+
+static KEY_POOL: Lazy<KeyPool> =
+    Lazy::new(|| KeyPool::new(REPLY_KEY_BITS, KEY_POOL_CAPACITY, POOLED_KEY_TTL));
+
+static KEY_POOL_TOKEN: Lazy<Mutex<Option<CancellationToken>>> = Lazy::new(|| Mutex::new(None));
+
+/// The pool of the reply keys of visits.
+pub fn key_pool() -> &'static KeyPool {
+    &KEY_POOL
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct KeyPoolStats {
+    pub key_bits: usize,
+    pub capacity: usize,
+    pub ready: usize,
+    /// Visits served from the pool and visits that generated their key.
+    pub hits: u64,
+    pub misses: u64,
+    pub oldest_key_age_secs: Option<u64>,
+}
+
+/// RSA key pairs generated ahead of the visits that need them, a key takes
+/// seconds to generate and a visit would wait for it.
+pub struct KeyPool {
+    bits: usize,
+    capacity: usize,
+    ttl: Duration,
+    keys: Mutex<VecDeque<(Instant, RsaPrivateKey)>>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    refill: Notify,
+}
+
+impl KeyPool {
+    pub fn new(bits: usize, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            bits,
+            capacity,
+            ttl,
+            keys: Mutex::new(VecDeque::with_capacity(capacity)),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            refill: Notify::new(),
+        }
+    }
+
+    /// The oldest key generated within the ttl, the expired ones are
+    /// dropped.
+    pub fn take(&self, now: Instant) -> Option<RsaPrivateKey> {
+        self.purge(now);
+        let key = self.keys().pop_front().map(|(_, key)| key);
+        self.refill.notify_one();
+        key
+    }
+
+    /// Adds a key generated at `generated_at`, false when the pool is full.
+    pub fn put(&self, generated_at: Instant, key: RsaPrivateKey) -> bool {
+        let mut keys = self.keys();
+        if keys.len() >= self.capacity {
+            return false;
+        }
+
+        keys.push_back((generated_at, key));
+        true
+    }
+
+    /// Drops the keys older than the ttl, returns how many.
+    pub fn purge(&self, now: Instant) -> usize {
+        let mut keys = self.keys();
+        let len = keys.len();
+        keys.retain(|(generated_at, _)| now.saturating_duration_since(*generated_at) < self.ttl);
+        len - keys.len()
+    }
+
+    pub fn clear(&self) {
+        self.keys().clear();
+    }
+
+    pub fn missing(&self) -> usize {
+        self.capacity.saturating_sub(self.keys().len())
+    }
+
+    pub fn stats(&self, now: Instant) -> KeyPoolStats {
+        let keys = self.keys();
+
+        KeyPoolStats {
+            key_bits: self.bits,
+            capacity: self.capacity,
+            ready: keys.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            oldest_key_age_secs: keys
+                .front()
+                .map(|(generated_at, _)| now.saturating_duration_since(*generated_at).as_secs()),
+        }
+    }
+
+    /// Hands out a pooled key right away, or generates one off the runtime
+    /// when the pool is empty. The pool refills afterwards.
+    pub async fn take_or_generate(&self) -> CoreResult<RsaPrivateKey> {
+        if let Some(key) = self.take(Instant::now()) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(key);
+        }
+
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        generate_key(self.bits).await
+    }
+
+    /// Generates keys one at a time until the pool is full, returns how
+    /// many. A key being generated when cancelled is thrown away.
+    pub async fn fill(&self, token: &CancellationToken) -> CoreResult<usize> {
+        let mut generated = 0;
+
+        while self.missing() > 0 && !token.is_cancelled() {
+            let key = generate_key(self.bits).await?;
+            if token.is_cancelled() || !self.put(Instant::now(), key) {
+                break;
+            }
+
+            generated += 1;
+        }
+
+        Ok(generated)
+    }
+
+    fn keys(&self) -> MutexGuard<'_, VecDeque<(Instant, RsaPrivateKey)>> {
+        self.keys.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Keeps the pool of [`key_pool`] full until [`stop_key_pool`], replacing
+/// the keys as they expire.
+pub fn serve_key_pool() {
+    let token = CancellationToken::new();
+    if let Some(previous) = KEY_POOL_TOKEN
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .replace(token.clone())
+    {
+        previous.cancel();
+    }
+
+    tokio::spawn(async move {
+        let pool = key_pool();
+
+        loop {
+            pool.purge(Instant::now());
+
+            match pool.fill(&token).await {
+                Ok(0) => {}
+                Ok(generated) => tracing::info!(?generated, "visit key pool refilled"),
+                Err(err) => tracing::warn!(?err, "refill visit key pool failed"),
+            }
+
+            tokio::select! {
+                _ = token.cancelled() => break,
+                _ = pool.refill.notified() => {}
+                _ = tokio::time::sleep(pool.ttl / 2) => {}
+            }
+        }
+
+        pool.clear();
+    });
+}
+
+/// Stops refilling the pool and drops the keys in it.
+pub fn stop_key_pool() {
+    if let Some(token) = KEY_POOL_TOKEN
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take()
+    {
+        token.cancel();
+    }
+
+    key_pool().clear();
+}
+
+async fn generate_key(bits: usize) -> CoreResult<RsaPrivateKey> {
+    tokio::task::spawn_blocking(move || {
+        RsaPrivateKey::new(&mut OsRng, bits).map_err(key_generation_failed)
+    })
+    .await
+    .map_err(key_generation_failed)?
+}
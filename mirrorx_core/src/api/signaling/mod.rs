@@ -1,22 +1,44 @@
+pub mod access_code;
+pub mod circuit_breaker;
+pub mod exchange_replay;
 pub mod http_message;
+pub mod identity;
+pub mod key_exchange;
+pub mod key_pool;
+pub mod outbound_queue;
 pub mod subscribe_message;
+pub mod transport;
+pub mod visit_failure;
 
 use self::{
+    access_code::{access_mode, one_time_codes},
+    circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitBreakerStats},
+    exchange_replay::exchange_replay_guard,
     http_message::{
         IdentityResponse, RegisterRequest, RegisterResponse, Response, VisitRequest, VisitResponse,
     },
-    subscribe_message::{
-        ActiveEndpointKeyExchangeSecret, ClientMessage, PassiveEndpointKeyExchangeSecret,
-        ServerMessage, Subscription, VisitFailureReason,
+    identity::{device_identity, mutual_auth_config},
+    key_exchange::{
+        key_agreement_with_identity, ActiveKeyExchange, KeyAgreementError, KeyDerivation,
     },
+    key_pool::key_pool,
+    outbound_queue::{Outbound, OutboundQueueStats},
+    subscribe_message::{ClientMessage, ServerMessage, Subscription, VisitFailureReason},
+    transport::{
+        connect_tcp, connect_websocket, websocket_url, SignalingTransport, SubscribeSink,
+        SubscribeStream,
+    },
+    visit_failure::visit_retry_delay,
 };
 use super::{
-    config::LocalStorage,
-    endpoint::{create_passive_endpoint_client, id::EndPointID},
+    config::Storage,
+    endpoint::{
+        create_passive_endpoint_client, handshake_token::handshake_tokens, id::EndPointID,
+        session_limit::try_acquire_incoming_session,
+    },
 };
 use crate::{
-    core_error,
-    error::CoreResult,
+    error::{CoreError, CoreResult},
     utility::{
         bincode::{bincode_deserialize, bincode_serialize},
         nonce_value::NonceValue,
@@ -24,25 +46,34 @@ use crate::{
     },
 };
 use bytes::Bytes;
-use futures::{
-    stream::{SplitSink, SplitStream},
-    SinkExt, StreamExt,
-};
-use hmac::Hmac;
-use rand::RngCore;
+use futures::{Future, SinkExt, StreamExt};
 use reqwest::IntoUrl;
-use ring::aead::{BoundKey, OpeningKey, SealingKey, UnboundKey};
-use rsa::{rand_core::OsRng, BigUint, PublicKey, PublicKeyParts};
-use sha2::Sha256;
-use std::{net::SocketAddr, time::Duration};
-use tokio::net::TcpStream;
-use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use ring::aead::{OpeningKey, SealingKey};
+use rsa::rand_core::OsRng;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
 use url::Url;
 
 pub struct SignalingClient {
     url: Url,
     http_client: reqwest::Client,
-    subscribe_tx: Option<tokio::sync::mpsc::Sender<Bytes>>,
+    outbound: Arc<Outbound>,
+    circuit_breaker: Mutex<CircuitBreaker>,
+    transport: SignalingTransport,
+    /// Transport of the subscribe connection, `None` before it's up.
+    active_transport: Option<SignalingTransport>,
+    connection_task: Option<tokio::task::JoinHandle<()>>,
+}
+
+/// Everything needed to open the endpoint connection of an accepted visit.
+pub struct VisitReply {
+    pub endpoint_addr: String,
+    pub visit_credentials: Vec<u8>,
+    pub opening_key: OpeningKey<NonceValue>,
+    pub sealing_key: SealingKey<NonceValue>,
 }
 
 impl SignalingClient {
@@ -57,19 +88,64 @@ impl SignalingClient {
         Ok(Self {
             url,
             http_client,
-            subscribe_tx: None,
+            outbound: Arc::new(Outbound::default()),
+            circuit_breaker: Mutex::new(CircuitBreaker::default()),
+            transport: SignalingTransport::default(),
+            active_transport: None,
+            connection_task: None,
         })
     }
 
+    /// Picks the transport of the next [`SignalingClient::subscribe`], the
+    /// running subscribe connection keeps its own.
+    pub fn set_transport(&mut self, transport: SignalingTransport) {
+        self.transport = transport;
+    }
+
+    pub fn active_transport(&self) -> Option<SignalingTransport> {
+        self.active_transport
+    }
+
+    /// Applies to the next calls to the signaling server.
+    pub fn set_circuit_breaker_config(&self, config: CircuitBreakerConfig) {
+        self.circuit_breaker().set_config(config);
+    }
+
+    pub fn circuit_breaker_stats(&self) -> CircuitBreakerStats {
+        self.circuit_breaker().stats(Instant::now())
+    }
+
+    fn circuit_breaker(&self) -> MutexGuard<'_, CircuitBreaker> {
+        self.circuit_breaker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Runs a call to the signaling server through the circuit breaker, it
+    /// fails with [`CoreError::SignalingUnavailable`] while the breaker is
+    /// open.
+    async fn call<T>(&self, call: impl Future<Output = CoreResult<T>>) -> CoreResult<T> {
+        self.circuit_breaker().acquire(Instant::now())?;
+
+        let result = call.await;
+        self.circuit_breaker().record(&result, Instant::now());
+
+        result
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn identity(&self) -> CoreResult<Response<IdentityResponse>> {
         let url = self.url.join("/api/identity")?;
         let resp = self
-            .http_client
-            .get(url)
-            .send()
-            .await?
-            .json::<Response<IdentityResponse>>()
+            .call(async {
+                Ok(self
+                    .http_client
+                    .get(url)
+                    .send()
+                    .await?
+                    .json::<Response<IdentityResponse>>()
+                    .await?)
+            })
             .await?;
 
         Ok(resp)
@@ -83,21 +159,24 @@ impl SignalingClient {
     ) -> CoreResult<Response<RegisterResponse>> {
         let url = self.url.join("/api/domain/register")?;
         let resp = self
-            .http_client
-            .post(url)
-            .json(&RegisterRequest {
-                device_id,
-                device_finger_print: device_finger_print.to_string(),
+            .call(async {
+                Ok(self
+                    .http_client
+                    .post(url)
+                    .json(&RegisterRequest {
+                        device_id,
+                        device_finger_print: device_finger_print.to_string(),
+                    })
+                    .send()
+                    .await?
+                    .json::<Response<RegisterResponse>>()
+                    .await?)
             })
-            .send()
-            .await?
-            .json::<Response<RegisterResponse>>()
             .await?;
 
         Ok(resp)
     }
 
-    #[allow(clippy::type_complexity)]
     #[tracing::instrument(skip(self))]
     pub async fn visit(
         &self,
@@ -105,229 +184,249 @@ impl SignalingClient {
         remote_device_id: i64,
         password: String,
         visit_desktop: bool,
-    ) -> CoreResult<
-        Response<
-            Result<
-                (
-                    String,
-                    Vec<u8>,
-                    OpeningKey<NonceValue>,
-                    SealingKey<NonceValue>,
-                ),
-                VisitFailureReason,
-            >,
-        >,
-    > {
-        let url = self.url.join("/api/visit")?;
-
-        let secure_random = ring::rand::SystemRandom::new();
-
-        // generate key pair for passive device key exchange reply
-        let reply_private_key = rsa::RsaPrivateKey::new(&mut OsRng, 4096)?;
-        let reply_public_key = reply_private_key.to_public_key();
-
-        // generate exchange key pair and nonce
-        let active_exchange_private_key = ring::agreement::EphemeralPrivateKey::generate(
-            &ring::agreement::X25519,
-            &secure_random,
-        )?;
-        let active_exchange_public_key = active_exchange_private_key.compute_public_key()?;
-
-        let mut active_exchange_nonce = [0u8; ring::aead::NONCE_LEN];
-        OsRng.fill_bytes(&mut active_exchange_nonce);
+    ) -> CoreResult<VisitReply> {
+        self.visit_with_key_derivation(
+            local_device_id,
+            remote_device_id,
+            password,
+            visit_desktop,
+            KeyDerivation::LATEST,
+        )
+        .await
+    }
 
-        let mut visit_credentials_buffer = [0u8; 16];
-        OsRng.fill_bytes(&mut visit_credentials_buffer);
+    async fn visit_with_key_derivation(
+        &self,
+        local_device_id: i64,
+        remote_device_id: i64,
+        password: String,
+        visit_desktop: bool,
+        key_derivation: KeyDerivation,
+    ) -> CoreResult<VisitReply> {
+        let url = self.url.join("/api/visit")?;
 
-        // generate and sealing active device key exchange secret
-        let active_device_secret = ActiveEndpointKeyExchangeSecret {
-            exchange_reply_public_key_n: &reply_public_key.n().to_bytes_le(),
-            exchange_reply_public_key_e: &reply_public_key.e().to_bytes_le(),
-            active_exchange_public_key: active_exchange_public_key.as_ref(),
-            active_exchange_nonce: &active_exchange_nonce,
+        // key pair for passive device key exchange reply, generated ahead
+        // by the pool, or off the runtime so a cancelled visit doesn't wait
+        // for it
+        let reply_private_key = key_pool().take_or_generate().await?;
+
+        // mutual authentication needs the latest exchange, a visit
+        // falling back to the plain one goes without
+        let key_exchange = if key_derivation == KeyDerivation::LATEST {
+            ActiveKeyExchange::with_identity(
+                &mut OsRng,
+                reply_private_key,
+                local_device_id,
+                &password,
+                device_identity().as_deref(),
+                &mutual_auth_config(),
+            )?
+        } else {
+            ActiveKeyExchange::with_key_derivation(
+                &mut OsRng,
+                reply_private_key,
+                local_device_id,
+                &password,
+                key_derivation,
+            )?
         };
 
-        // generate secret sealing key with salt
-        let mut active_device_secret_salt = [0u8; 16];
-        OsRng.fill_bytes(&mut active_device_secret_salt);
+        // a busy server counts against the breaker, so the error it answered
+        // with is taken apart inside the call
+        let resp = self
+            .call(async {
+                let resp = self
+                    .http_client
+                    .post(url)
+                    .json(&VisitRequest {
+                        active_device_id: local_device_id,
+                        passive_device_id: remote_device_id,
+                        visit_desktop,
+                        password_salt: base64::encode(key_exchange.password_salt),
+                        secret: base64::encode(&key_exchange.secret),
+                        secret_nonce: base64::encode(key_exchange.secret_nonce),
+                    })
+                    .timeout(Duration::from_secs(60))
+                    .send()
+                    .await
+                    .map_err(visit_request_failed)?
+                    .json::<Response<VisitResponse>>()
+                    .await
+                    .map_err(visit_request_failed)?;
+
+                match resp {
+                    Response::Message(resp) => Ok(resp),
+                    Response::Error(err) => Err(CoreError::from(err)),
+                }
+            })
+            .await?;
 
-        let mut active_device_secret_sealing_key = [0u8; 32];
-        pbkdf2::pbkdf2::<Hmac<Sha256>>(
-            password.as_bytes(),
-            &active_device_secret_salt,
-            10000,
-            &mut active_device_secret_sealing_key,
-        );
+        let secret = match resp.result {
+            Ok(secret) => base64::decode(secret).map_err(key_exchange_failed)?,
+            Err(reason) => return Err(reason.into()),
+        };
 
-        let mut active_device_secret_buffer = bincode_serialize(&active_device_secret)?;
+        let visit_credentials =
+            base64::decode(resp.visit_credentials).map_err(key_exchange_failed)?;
 
-        let active_device_secret_sealing_unbound_key = ring::aead::UnboundKey::new(
-            &ring::aead::AES_256_GCM,
-            &active_device_secret_sealing_key,
-        )?;
+        if !handshake_tokens().issue(&visit_credentials, Instant::now()) {
+            return Err(CoreError::HandshakeTokenRepeated);
+        }
 
-        let mut active_device_secret_sealing_nonce = [0u8; ring::aead::NONCE_LEN];
-        OsRng.fill_bytes(&mut active_device_secret_sealing_nonce);
+        let (opening_key, sealing_key) =
+            key_exchange.open_reply(&secret).map_err(|err| match err {
+                CoreError::VisitSignatureInvalid | CoreError::VisitDeviceUntrusted => err,
+                err => key_exchange_failed(err),
+            })?;
 
-        let mut active_device_secret_sealing_key = ring::aead::SealingKey::new(
-            active_device_secret_sealing_unbound_key,
-            NonceValue::new(active_device_secret_sealing_nonce),
-        );
+        Ok(VisitReply {
+            endpoint_addr: resp.endpoint_addr,
+            visit_credentials,
+            opening_key,
+            sealing_key,
+        })
+    }
 
-        active_device_secret_sealing_key.seal_in_place_append_tag(
-            ring::aead::Aad::from(local_device_id.to_le_bytes()),
-            &mut active_device_secret_buffer,
-        )?;
+    /// Visits like [`SignalingClient::visit`], and tries again after a short
+    /// delay while the signaling server times out or is busy. A passive
+    /// device refusing the versioned key exchange as invalid may predate it,
+    /// it's visited once more with the plain one, unless mutual
+    /// authentication is enabled.
+    #[tracing::instrument(skip(self, password))]
+    pub async fn visit_with_retry(
+        &self,
+        local_device_id: i64,
+        remote_device_id: i64,
+        password: String,
+        visit_desktop: bool,
+    ) -> CoreResult<VisitReply> {
+        let mut attempt = 1;
+        let mut key_derivation = KeyDerivation::LATEST;
+
+        loop {
+            let err = match self
+                .visit_with_key_derivation(
+                    local_device_id,
+                    remote_device_id,
+                    password.clone(),
+                    visit_desktop,
+                    key_derivation,
+                )
+                .await
+            {
+                Ok(reply) => return Ok(reply),
+                Err(err) => err,
+            };
 
-        let resp = self
-            .http_client
-            .post(url)
-            .json(&VisitRequest {
-                active_device_id: local_device_id,
-                passive_device_id: remote_device_id,
-                visit_desktop,
-                password_salt: base64::encode(active_device_secret_salt),
-                secret: base64::encode(active_device_secret_buffer),
-                secret_nonce: base64::encode(active_device_secret_sealing_nonce),
-            })
-            .timeout(Duration::from_secs(60))
-            .send()
-            .await?
-            .json::<Response<VisitResponse>>()
-            .await?;
+            if matches!(err, CoreError::VisitInvalidArgs)
+                && key_derivation != KeyDerivation::Unlabeled
+                && !mutual_auth_config().enabled
+            {
+                tracing::warn!("visit refused as invalid, try again with unlabeled key derivation");
+                key_derivation = KeyDerivation::Unlabeled;
+                continue;
+            }
 
-        match resp {
-            Response::Message(resp) => {
-                let secret = match resp.result {
-                    Ok(secret) => base64::decode(secret)?,
-                    Err(reason) => return Ok(Response::Message(Err(reason))),
-                };
+            let Some(delay) = visit_retry_delay(&err, attempt) else {
+                return Err(err);
+            };
 
-                let visit_credentials = base64::decode(resp.visit_credentials)?;
-
-                let passive_device_secret_buffer =
-                    reply_private_key.decrypt(rsa::PaddingScheme::PKCS1v15Encrypt, &secret)?;
-
-                let passive_device_secret: PassiveEndpointKeyExchangeSecret =
-                    bincode_deserialize(&passive_device_secret_buffer)?;
-
-                let passive_exchange_public_key = ring::agreement::UnparsedPublicKey::new(
-                    &ring::agreement::X25519,
-                    passive_device_secret.passive_exchange_public_key,
-                );
-
-                let (raw_sealing_key, raw_opening_key) = ring::agreement::agree_ephemeral(
-                    active_exchange_private_key,
-                    &passive_exchange_public_key,
-                    ring::error::Unspecified,
-                    |key_material| {
-                        let sealing_key =
-                            ring::hkdf::Salt::new(ring::hkdf::HKDF_SHA512, &active_exchange_nonce)
-                                .extract(key_material)
-                                .expand(&["".as_bytes()], &ring::aead::AES_256_GCM)
-                                .and_then(|orm| {
-                                    let mut key = Vec::<u8>::new();
-                                    key.resize(ring::aead::AES_256_GCM.key_len(), 0);
-                                    orm.fill(&mut key)?;
-                                    Ok(key)
-                                })?;
-
-                        let opening_key = ring::hkdf::Salt::new(
-                            ring::hkdf::HKDF_SHA512,
-                            passive_device_secret.passive_exchange_nonce,
-                        )
-                        .extract(key_material)
-                        .expand(&["".as_bytes()], &ring::aead::AES_256_GCM)
-                        .and_then(|orm| {
-                            let mut key = Vec::<u8>::new();
-                            key.resize(ring::aead::AES_256_GCM.key_len(), 0);
-                            orm.fill(&mut key)?;
-                            Ok(key)
-                        })?;
-
-                        Ok((sealing_key, opening_key))
-                    },
-                )?;
-
-                let unbound_sealing_key =
-                    ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, &raw_sealing_key)?;
-
-                let mut nonce = [0u8; 12];
-                nonce.copy_from_slice(passive_device_secret.passive_exchange_nonce);
-                let sealing_key =
-                    ring::aead::SealingKey::new(unbound_sealing_key, NonceValue::new(nonce));
-
-                let unbound_opening_key =
-                    ring::aead::UnboundKey::new(&ring::aead::AES_256_GCM, &raw_opening_key)?;
-
-                let mut nonce = [0u8; 12];
-                nonce.copy_from_slice(&active_exchange_nonce);
-                let opening_key =
-                    ring::aead::OpeningKey::new(unbound_opening_key, NonceValue::new(nonce));
-
-                Ok(Response::Message(Ok((
-                    resp.endpoint_addr,
-                    visit_credentials,
-                    opening_key,
-                    sealing_key,
-                ))))
-            }
-            Response::Error(err) => Ok(Response::Error(err)),
+            tracing::warn!(?err, ?attempt, ?delay, "visit failed, try again");
+            tokio::time::sleep(delay).await;
+            attempt += 1;
         }
     }
 
-    // see https://github.com/rust-lang/rust-clippy/pull/9496, which was merged but not release
-    #[allow(clippy::never_loop)]
-    pub async fn subscribe(
+    /// Opens the subscribe connection over the selected transport. The
+    /// addresses are those of the subscribe port, the WebSocket transport
+    /// goes through the domain address instead.
+    pub async fn subscribe<S: Storage>(
         &mut self,
         addrs: Vec<SocketAddr>,
         device_id: i64,
         device_finger_print: &str,
-        storage: LocalStorage,
+        storage: S,
     ) -> CoreResult<()> {
         let subscription_bytes = Bytes::from(bincode_serialize(&Subscription {
             device_id,
             device_finger_print: device_finger_print.to_string(),
         })?);
 
-        for addr in addrs {
-            let Ok(Ok(stream)) = tokio::time::timeout(
-                Duration::from_secs(10),
-                tokio::net::TcpStream::connect(addr),
-            )
-            .await else {
-                continue;
-            };
+        // replaced by the new connection, or gone when it can't be opened
+        self.active_transport = None;
 
-            let mut framed_stream = Framed::new(
-                stream,
-                LengthDelimitedCodec::builder()
-                    .length_field_length(2)
-                    .little_endian()
-                    .new_codec(),
-            );
+        let (mut sink, stream) = match self.transport {
+            SignalingTransport::Tcp => connect_tcp(&addrs).await?,
+            SignalingTransport::WebSocket => connect_websocket(&websocket_url(&self.url)?).await?,
+        };
+
+        tracing::info!(transport = ?self.transport, "signaling subscribe connected");
+
+        sink.send(subscription_bytes).await?;
+
+        let (tx, rx) = tokio::sync::mpsc::channel(1);
 
-            framed_stream.send(subscription_bytes.clone()).await?;
+        let connection_task = tokio::spawn(serve_connection(
+            rx,
+            sink,
+            stream,
+            storage,
+            self.outbound.clone(),
+        ));
 
-            let (sink, stream) = framed_stream.split();
-            let (tx, rx) = tokio::sync::mpsc::channel(1);
+        self.outbound.attach(tx).await;
+        self.active_transport = Some(self.transport);
+        self.connection_task = Some(connection_task);
 
-            tokio::spawn(serve_connection(rx, sink, stream, storage.clone()));
+        Ok(())
+    }
 
-            self.subscribe_tx = Some(tx);
+    /// Closes the subscribe connection, the server drops the subscription
+    /// of this device and stops routing visits to it.
+    pub async fn close(&mut self) {
+        self.outbound.detach();
+        self.active_transport = None;
 
-            return Ok(());
+        if let Some(connection_task) = self.connection_task.take() {
+            let _ = connection_task.await;
         }
+    }
+
+    /// Sends a message over the subscribe connection, or queues it until the
+    /// next [`SignalingClient::subscribe`] when the connection is down.
+    pub async fn send(&self, message: Bytes) -> CoreResult<()> {
+        self.outbound.send(message).await
+    }
+
+    pub async fn outbound_queue_stats(&self) -> OutboundQueueStats {
+        self.outbound.stats().await
+    }
+}
+
+fn key_generation_failed<E: std::fmt::Debug>(err: E) -> CoreError {
+    tracing::error!(?err, "generate visit key failed");
+    CoreError::VisitKeyGenerationFailed
+}
+
+fn key_exchange_failed<E: std::fmt::Debug>(err: E) -> CoreError {
+    tracing::error!(?err, "visit key exchange failed");
+    CoreError::VisitKeyExchangeFailed
+}
 
-        Err(core_error!("non addr usable"))
+fn visit_request_failed(err: reqwest::Error) -> CoreError {
+    if err.is_timeout() {
+        CoreError::VisitTimeout
+    } else {
+        CoreError::ReqwestError(err)
     }
 }
 
-async fn serve_connection(
+async fn serve_connection<S: Storage>(
     mut rx: tokio::sync::mpsc::Receiver<Bytes>,
-    mut sink: SplitSink<Framed<TcpStream, LengthDelimitedCodec>, Bytes>,
-    mut stream: SplitStream<Framed<TcpStream, LengthDelimitedCodec>>,
-    storage: LocalStorage,
+    mut sink: SubscribeSink,
+    mut stream: SubscribeStream,
+    storage: S,
+    outbound: Arc<Outbound>,
 ) {
     let mut ticker = tokio::time::interval(Duration::from_secs(60));
     let mut last_ping = None;
@@ -356,6 +455,7 @@ async fn serve_connection(
                     let _ = sink.send(buffer).await;
                     continue;
                 } else {
+                    let _ = sink.close().await;
                     return;
                 }
             },
@@ -394,8 +494,10 @@ async fn serve_connection(
                 secret_nonce,
                 passive_visit_credentials,
             } => {
+                // the reply goes out through the outbound queue, it waits
+                // for the next connection when this one breaks meanwhile
                 let storage = storage.clone();
-                let (tx, rx) = tokio::sync::oneshot::channel();
+                let outbound = outbound.clone();
                 tokio::spawn(async move {
                     let result = serve_visit_request(
                         storage,
@@ -424,30 +526,52 @@ async fn serve_connection(
                         }
                     };
 
-                    let _ = tx.send(buffer);
-                });
-
-                match rx.await {
-                    Ok(buffer) => {
-                        if let Err(err) = sink.send(Bytes::from(buffer)).await {
-                            tracing::error!(?err, "reply visit failed");
-                        }
+                    if let Err(err) = outbound.send(Bytes::from(buffer)).await {
+                        tracing::error!(?err, "reply visit failed");
                     }
-                    Err(err) => {
-                        tracing::error!(
-                            ?err,
-                            "receive key exchange result failed, this shouldn't happen!"
-                        );
-                    }
-                }
+                });
             }
         }
     }
 }
 
+/// While allow-list mode is enabled only the devices on the allow list may
+/// visit. It fails closed, the list can't be read means no visit.
+pub(crate) fn check_allow_list<S: Storage>(
+    storage: &S,
+    active_device_id: i64,
+) -> Result<(), VisitFailureReason> {
+    let enabled = match storage.kv().get_allow_list_enabled() {
+        Ok(enabled) => enabled.unwrap_or(false),
+        Err(err) => {
+            tracing::error!(?err, "read allow list mode failed");
+            return Err(VisitFailureReason::InternalError);
+        }
+    };
+
+    if !enabled {
+        return Ok(());
+    }
+
+    match storage.allow_list().contains(active_device_id) {
+        Ok(true) => Ok(()),
+        Ok(false) => {
+            tracing::warn!(
+                active_device_id,
+                "reject visit of a device not on the allow list"
+            );
+            Err(VisitFailureReason::NotAllowed)
+        }
+        Err(err) => {
+            tracing::error!(?err, "read allow list failed");
+            Err(VisitFailureReason::InternalError)
+        }
+    }
+}
+
 #[allow(clippy::too_many_arguments)]
-async fn serve_visit_request(
-    storage: LocalStorage,
+async fn serve_visit_request<S: Storage>(
+    storage: S,
     active_device_id: i64,
     passive_device_id: i64,
     endpoint_addr: String,
@@ -456,6 +580,14 @@ async fn serve_visit_request(
     secret_nonce: Vec<u8>,
     passive_visit_credentials: Vec<u8>,
 ) -> Result<Vec<u8>, VisitFailureReason> {
+    // refused before the key agreement spends any work on the device
+    check_allow_list(&storage, active_device_id)?;
+
+    // a replayed visit request carries credentials seen before
+    if !handshake_tokens().issue(&passive_visit_credentials, Instant::now()) {
+        return Err(VisitFailureReason::Replayed);
+    }
+
     let Ok(domain) = storage.domain().get_primary_domain() else {
         return Err(VisitFailureReason::InternalError);
     };
@@ -464,19 +596,40 @@ async fn serve_visit_request(
         return Err(VisitFailureReason::InternalError);
     };
 
-    let (secret, sealing_key, opening_key) = match key_agreement(
+    let identity = device_identity();
+    let mutual_auth_config = mutual_auth_config();
+
+    let (secret, sealing_key, opening_key) = one_time_codes().verify(
+        access_mode(),
         &domain.password,
-        active_device_id,
-        password_salt,
-        secret,
-        secret_nonce,
-    )
-    .await
-    {
-        Ok(v) => v,
-        Err(err) => {
-            return Err(err);
-        }
+        Instant::now(),
+        |password| {
+            exchange_replay_guard()
+                .answer_once(
+                    active_device_id,
+                    &password_salt,
+                    &secret,
+                    &secret_nonce,
+                    Instant::now(),
+                    || {
+                        key_agreement_with_identity(
+                            &mut OsRng,
+                            password,
+                            active_device_id,
+                            &password_salt,
+                            secret.clone(),
+                            &secret_nonce,
+                            identity.as_deref(),
+                            &mutual_auth_config,
+                        )
+                    },
+                )
+                .map_err(KeyAgreementError::reason)
+        },
+    )?;
+
+    let Some(session_permit) = try_acquire_incoming_session() else {
+        return Err(VisitFailureReason::ServerBusy);
     };
 
     tokio::spawn(async move {
@@ -488,6 +641,7 @@ async fn serve_visit_request(
             Some((opening_key, sealing_key)),
             crate::api::endpoint::EndPointStream::ActiveTCP(endpoint_addr),
             Some(passive_visit_credentials),
+            session_permit,
         )
         .await
         {
@@ -497,203 +651,3 @@ async fn serve_visit_request(
 
     Ok(secret)
 }
-
-async fn key_agreement(
-    domain_password: &str,
-    active_device_id: i64,
-    password_salt: Vec<u8>,
-    mut secret: Vec<u8>,
-    secret_nonce: Vec<u8>,
-) -> Result<(Vec<u8>, SealingKey<NonceValue>, OpeningKey<NonceValue>), VisitFailureReason> {
-    if secret_nonce.len() != ring::aead::NONCE_LEN {
-        return Err(VisitFailureReason::InternalError);
-    }
-
-    // generate secret opening key with salt
-    let mut active_device_secret_opening_key = [0u8; 32];
-    pbkdf2::pbkdf2::<Hmac<Sha256>>(
-        domain_password.as_bytes(),
-        &password_salt,
-        10000,
-        &mut active_device_secret_opening_key,
-    );
-
-    let unbound_key = match ring::aead::UnboundKey::new(
-        &ring::aead::AES_256_GCM,
-        &active_device_secret_opening_key,
-    ) {
-        Ok(unbound_key) => unbound_key,
-        Err(err) => {
-            tracing::error!(?err, "create unbound key failed");
-            return Err(VisitFailureReason::InternalError);
-        }
-    };
-
-    let mut active_device_secret_opening_nonce = [0u8; ring::aead::NONCE_LEN];
-    active_device_secret_opening_nonce[..ring::aead::NONCE_LEN]
-        .copy_from_slice(&secret_nonce[..ring::aead::NONCE_LEN]);
-
-    let mut active_device_secret_opening_key = ring::aead::OpeningKey::new(
-        unbound_key,
-        NonceValue::new(active_device_secret_opening_nonce),
-    );
-
-    let active_device_secret_buffer = match active_device_secret_opening_key.open_in_place(
-        ring::aead::Aad::from(active_device_id.to_le_bytes()),
-        &mut secret,
-    ) {
-        Ok(buffer) => buffer,
-        Err(_) => return Err(VisitFailureReason::InvalidPassword),
-    };
-
-    let active_device_secret =
-        match bincode_deserialize::<ActiveEndpointKeyExchangeSecret>(&*active_device_secret_buffer)
-        {
-            Ok(secret) => secret,
-            Err(_) => {
-                return Err(VisitFailureReason::InvalidArgs);
-            }
-        };
-
-    if active_device_secret.active_exchange_nonce.len() != ring::aead::NONCE_LEN {
-        return Err(VisitFailureReason::InvalidArgs);
-    }
-
-    // generate passive device key exchange pair and nonce
-
-    let system_random_rng = ring::rand::SystemRandom::new();
-
-    let passive_exchange_private_key = match ring::agreement::EphemeralPrivateKey::generate(
-        &ring::agreement::X25519,
-        &system_random_rng,
-    ) {
-        Ok(private_key) => private_key,
-        Err(_) => return Err(VisitFailureReason::InternalError),
-    };
-
-    let passive_exchange_public_key = match passive_exchange_private_key.compute_public_key() {
-        Ok(public_key) => public_key,
-        Err(err) => {
-            tracing::error!(
-                ?err,
-                "compute public key from passive exchange private key failed"
-            );
-            return Err(VisitFailureReason::InternalError);
-        }
-    };
-
-    let mut passive_exchange_nonce = [0u8; ring::aead::NONCE_LEN];
-    OsRng.fill_bytes(&mut passive_exchange_nonce);
-
-    // key agreement
-
-    let mut active_exchange_nonce = [0u8; ring::aead::NONCE_LEN];
-    active_exchange_nonce[..ring::aead::NONCE_LEN]
-        .copy_from_slice(&active_device_secret.active_exchange_nonce[..ring::aead::NONCE_LEN]);
-
-    let active_exchange_public_key = ring::agreement::UnparsedPublicKey::new(
-        &ring::agreement::X25519,
-        active_device_secret.active_exchange_public_key,
-    );
-
-    let agree_result = ring::agreement::agree_ephemeral(
-        passive_exchange_private_key,
-        &active_exchange_public_key,
-        ring::error::Unspecified,
-        |key_material| {
-            let sealing_key =
-                ring::hkdf::Salt::new(ring::hkdf::HKDF_SHA512, &passive_exchange_nonce)
-                    .extract(key_material)
-                    .expand(&["".as_bytes()], &ring::aead::AES_256_GCM)
-                    .and_then(|orm| {
-                        let mut key = Vec::<u8>::new();
-                        key.resize(ring::aead::AES_256_GCM.key_len(), 0);
-                        orm.fill(&mut key)?;
-                        Ok(key)
-                    })?;
-
-            let opening_key = ring::hkdf::Salt::new(
-                ring::hkdf::HKDF_SHA512,
-                active_device_secret.active_exchange_nonce,
-            )
-            .extract(key_material)
-            .expand(&["".as_bytes()], &ring::aead::AES_256_GCM)
-            .and_then(|orm| {
-                let mut key = Vec::<u8>::new();
-                key.resize(ring::aead::AES_256_GCM.key_len(), 0);
-                orm.fill(&mut key)?;
-                Ok(key)
-            })?;
-
-            Ok((sealing_key, opening_key))
-        },
-    );
-
-    let (raw_sealing_key, raw_opening_key) = match agree_result {
-        Ok(v) => v,
-        Err(err) => {
-            tracing::error!(?err, "agree ephemeral failed");
-            return Err(VisitFailureReason::InternalError);
-        }
-    };
-
-    // derive opening and sealing key
-
-    let unbound_sealing_key = match UnboundKey::new(&ring::aead::AES_256_GCM, &raw_sealing_key) {
-        Ok(unbound_sealing_key) => unbound_sealing_key,
-        Err(err) => {
-            tracing::error!(?err, "create unbound sealing key failed");
-            return Err(VisitFailureReason::InternalError);
-        }
-    };
-
-    let sealing_key = SealingKey::new(unbound_sealing_key, NonceValue::new(active_exchange_nonce));
-
-    let unbound_opening_key = match UnboundKey::new(&ring::aead::AES_256_GCM, &raw_opening_key) {
-        Ok(unbound_opening_key) => unbound_opening_key,
-        Err(err) => {
-            tracing::error!(?err, "create unbound opening failed");
-            return Err(VisitFailureReason::InternalError);
-        }
-    };
-
-    let opening_key =
-        ring::aead::OpeningKey::new(unbound_opening_key, NonceValue::new(passive_exchange_nonce));
-
-    // build key exchange response
-
-    let passive_device_secret = PassiveEndpointKeyExchangeSecret {
-        passive_exchange_public_key: passive_exchange_public_key.as_ref(),
-        passive_exchange_nonce: &passive_exchange_nonce,
-    };
-
-    let passive_device_secret_buffer = match bincode_serialize(&passive_device_secret) {
-        Ok(buffer) => buffer,
-        Err(_) => return Err(VisitFailureReason::InternalError),
-    };
-
-    let active_exchange_reply_public_key = match rsa::RsaPublicKey::new(
-        BigUint::from_bytes_le(active_device_secret.exchange_reply_public_key_n),
-        BigUint::from_bytes_le(active_device_secret.exchange_reply_public_key_e),
-    ) {
-        Ok(public_key) => public_key,
-        Err(err) => {
-            tracing::error!(?err, "recover exchange reply public key failed");
-            return Err(VisitFailureReason::InternalError);
-        }
-    };
-
-    let secret_buffer = match active_exchange_reply_public_key.encrypt(
-        &mut OsRng,
-        rsa::PaddingScheme::PKCS1v15Encrypt,
-        &passive_device_secret_buffer,
-    ) {
-        Ok(buffer) => buffer,
-        Err(err) => {
-            tracing::error!(?err, "encrypt exchange reply data failed");
-            return Err(VisitFailureReason::InternalError);
-        }
-    };
-
-    Ok((secret_buffer, sealing_key, opening_key))
-}
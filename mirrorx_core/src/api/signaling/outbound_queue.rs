@@ -0,0 +1,153 @@
+use crate::error::{CoreError, CoreResult};
+use bytes::Bytes;
+use serde::Serialize;
+use std::{
+    collections::VecDeque,
+    sync::PoisonError,
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::Sender;
+
+pub const OUTBOUND_QUEUE_CAPACITY: usize = 32;
+
+/// A connect request replayed later than this would surprise the user more
+/// than a failed one.
+pub const OUTBOUND_MESSAGE_TTL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Serialize)]
+pub struct OutboundQueueStats {
+    pub depth: usize,
+    pub expired: u64,
+}
+
+/// Holds messages sent while the subscribe connection is down until they can
+/// be flushed to the next connection.
+#[derive(Debug)]
+pub struct OutboundQueue {
+    messages: VecDeque<(Instant, Bytes)>,
+    capacity: usize,
+    ttl: Duration,
+    expired: u64,
+}
+
+impl Default for OutboundQueue {
+    fn default() -> Self {
+        Self::new(OUTBOUND_QUEUE_CAPACITY, OUTBOUND_MESSAGE_TTL)
+    }
+}
+
+impl OutboundQueue {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            messages: VecDeque::with_capacity(capacity),
+            capacity,
+            ttl,
+            expired: 0,
+        }
+    }
+
+    pub fn push(&mut self, message: Bytes) -> CoreResult<()> {
+        self.evict_expired();
+
+        if self.messages.len() >= self.capacity {
+            tracing::warn!(capacity = ?self.capacity, "signaling outbound queue is full");
+            return Err(CoreError::OutgoingMessageChannelFull);
+        }
+
+        self.messages.push_back((Instant::now(), message));
+        Ok(())
+    }
+
+    /// Takes every message that hasn't expired yet, oldest first.
+    pub fn drain(&mut self) -> Vec<Bytes> {
+        self.evict_expired();
+        self.messages
+            .drain(..)
+            .map(|(_, message)| message)
+            .collect()
+    }
+
+    pub fn stats(&self) -> OutboundQueueStats {
+        let depth = self
+            .messages
+            .iter()
+            .filter(|(enqueue_time, _)| enqueue_time.elapsed() < self.ttl)
+            .count();
+
+        let expired = self.expired + (self.messages.len() - depth) as u64;
+
+        OutboundQueueStats { depth, expired }
+    }
+
+    fn evict_expired(&mut self) {
+        while let Some((enqueue_time, _)) = self.messages.front() {
+            if enqueue_time.elapsed() < self.ttl {
+                break;
+            }
+
+            self.messages.pop_front();
+            self.expired += 1;
+        }
+    }
+}
+
+/// The way out of the subscribe connection, shared by the client and the
+/// tasks replying to the server. Messages sent while the connection is down
+/// wait in the queue for the next one.
+#[derive(Debug, Default)]
+pub(crate) struct Outbound {
+    tx: std::sync::Mutex<Option<Sender<Bytes>>>,
+    queue: tokio::sync::Mutex<OutboundQueue>,
+}
+
+impl Outbound {
+    pub(crate) async fn send(&self, message: Bytes) -> CoreResult<()> {
+        // the queue lock keeps a message from slipping in behind a flush
+        let mut queue = self.queue.lock().await;
+
+        let message = match self.tx() {
+            Some(tx) => match tx.send(message).await {
+                Ok(_) => return Ok(()),
+                Err(err) => err.0,
+            },
+            None => message,
+        };
+
+        queue.push(message)
+    }
+
+    /// Sends the queued messages over a new connection before anything
+    /// else goes out over it.
+    pub(crate) async fn attach(&self, tx: Sender<Bytes>) {
+        let mut queue = self.queue.lock().await;
+
+        let messages = queue.drain();
+        if !messages.is_empty() {
+            tracing::info!(count = ?messages.len(), "flush signaling outbound queue");
+        }
+
+        for message in messages {
+            if tx.send(message).await.is_err() {
+                tracing::warn!("subscribe connection closed while flushing outbound queue");
+                break;
+            }
+        }
+
+        *self.tx.lock().unwrap_or_else(PoisonError::into_inner) = Some(tx);
+    }
+
+    pub(crate) fn detach(&self) {
+        *self.tx.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    pub(crate) async fn stats(&self) -> OutboundQueueStats {
+        self.queue.lock().await.stats()
+    }
+
+    fn tx(&self) -> Option<Sender<Bytes>> {
+        self.tx
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
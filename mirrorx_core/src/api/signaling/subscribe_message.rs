@@ -1,3 +1,5 @@
+use super::identity::DeviceIdentityProof;
+use crate::error::CoreError;
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -6,12 +8,32 @@ pub struct Subscription {
     pub device_finger_print: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
 pub enum VisitFailureReason {
     RemoteReject,
     InvalidPassword,
     InternalError,
     InvalidArgs,
+    ServerBusy,
+    NotAllowed,
+    /// The passive device answered the same visit before. Told apart from
+    /// `InvalidArgs`, a device predating labeled key derivation, so the
+    /// visit isn't tried again with the weaker derivation.
+    Replayed,
+}
+
+impl From<VisitFailureReason> for CoreError {
+    fn from(reason: VisitFailureReason) -> Self {
+        match reason {
+            VisitFailureReason::RemoteReject => CoreError::VisitRemoteRejected,
+            VisitFailureReason::InvalidPassword => CoreError::VisitInvalidPassword,
+            VisitFailureReason::InternalError => CoreError::VisitInternalError,
+            VisitFailureReason::InvalidArgs => CoreError::VisitInvalidArgs,
+            VisitFailureReason::ServerBusy => CoreError::VisitServerBusy,
+            VisitFailureReason::NotAllowed => CoreError::VisitNotAllowed,
+            VisitFailureReason::Replayed => CoreError::VisitReplayed,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,3 +80,20 @@ pub struct PassiveEndpointKeyExchangeSecret<'a> {
     pub passive_exchange_public_key: &'a [u8],
     pub passive_exchange_nonce: &'a [u8],
 }
+
+/// An exchange secret with the key derivation version of its sender. It's
+/// encoded as the plain secret followed by the version, devices that don't
+/// negotiate the key derivation send the plain secret.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VersionedKeyExchangeSecret<T> {
+    pub secret: T,
+    pub key_derivation_version: u8,
+}
+
+/// A versioned exchange secret signed with the identity of its sender, sent
+/// while mutual authentication is enabled.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SignedKeyExchangeSecret<T> {
+    pub secret: VersionedKeyExchangeSecret<T>,
+    pub proof: DeviceIdentityProof,
+}
@@ -0,0 +1,103 @@
+use crate::{
+    bail_core, core_error,
+    error::{CoreError, CoreResult},
+    utility::happy_eyeballs::connect_first,
+};
+use bytes::{Bytes, BytesMut};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{net::SocketAddr, pin::Pin, time::Duration};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use url::Url;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How the subscribe connection reaches the signaling server. Both carry the
+/// same bincode messages, one per frame.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SignalingTransport {
+    /// Length delimited frames on the subscribe port of the domain.
+    #[default]
+    Tcp,
+    /// Binary messages on the `/subscribe` path of the domain address, for
+    /// networks that only let HTTP(S) out.
+    WebSocket,
+}
+
+pub(crate) type SubscribeSink = Pin<Box<dyn Sink<Bytes, Error = CoreError> + Send>>;
+pub(crate) type SubscribeStream = Pin<Box<dyn Stream<Item = CoreResult<Bytes>> + Send>>;
+
+/// Connects to the first address that answers, racing the IPv6 and IPv4
+/// addresses of a dual stack domain.
+pub(crate) async fn connect_tcp(
+    addrs: &[SocketAddr],
+) -> CoreResult<(SubscribeSink, SubscribeStream)> {
+    let stream = connect_first(addrs, CONNECT_TIMEOUT, |addr| async move {
+        Ok(tokio::net::TcpStream::connect(addr).await?)
+    })
+    .await?;
+
+    let framed_stream = Framed::new(
+        stream,
+        LengthDelimitedCodec::builder()
+            .length_field_length(2)
+            .little_endian()
+            .new_codec(),
+    );
+
+    let (sink, stream) = framed_stream.split();
+    let sink = sink.sink_map_err(CoreError::from);
+    let stream = stream.map(|buffer| buffer.map(BytesMut::freeze).map_err(CoreError::from));
+
+    Ok((Box::pin(sink), Box::pin(stream)))
+}
+
+pub(crate) async fn connect_websocket(url: &Url) -> CoreResult<(SubscribeSink, SubscribeStream)> {
+    let (stream, _) = tokio::time::timeout(
+        CONNECT_TIMEOUT,
+        tokio_tungstenite::connect_async(url.as_str()),
+    )
+    .await
+    .map_err(|_| CoreError::Timeout)??;
+
+    let (sink, stream) = stream.split();
+
+    let sink = sink.with(|buffer: Bytes| {
+        futures::future::ready(Ok::<_, CoreError>(Message::Binary(buffer.to_vec())))
+    });
+
+    // control frames are answered by tungstenite itself, a close ends the
+    // connection like a closed tcp stream does
+    let stream = stream
+        .take_while(|message| futures::future::ready(!matches!(message, Ok(Message::Close(_)))))
+        .filter_map(|message| {
+            futures::future::ready(match message {
+                Ok(Message::Binary(buffer)) => Some(Ok(Bytes::from(buffer))),
+                Ok(_) => None,
+                Err(err) => Some(Err(CoreError::from(err))),
+            })
+        });
+
+    Ok((Box::pin(sink), Box::pin(stream)))
+}
+
+/// The WebSocket endpoint of a signaling domain, `ws` for `http` domains and
+/// `wss` for `https` ones.
+pub fn websocket_url(domain: &Url) -> CoreResult<Url> {
+    let scheme = match domain.scheme() {
+        "http" => "ws",
+        "https" => "wss",
+        scheme => bail_core!("unsupported signaling scheme ({})", scheme),
+    };
+
+    let mut url = domain.clone();
+    url.set_scheme(scheme)
+        .map_err(|_| core_error!("set websocket scheme failed"))?;
+    url.set_path("subscribe");
+    url.set_query(None);
+    url.set_fragment(None);
+
+    Ok(url)
+}
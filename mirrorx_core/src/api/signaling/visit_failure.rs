@@ -0,0 +1,145 @@
+use crate::{api::endpoint::handshake::HandshakeFailure, error::CoreError};
+use serde::Serialize;
+use std::time::Duration;
+
+/// Attempts of a visit that fails for a passing reason, including the first.
+pub const VISIT_ATTEMPTS: u32 = 3;
+
+/// What the user can do about a failed visit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VisitRecovery {
+    /// Asking again later may work out, the remote device or the server
+    /// isn't ready now.
+    RetryLater,
+    /// The password was wrong, ask the user for it again.
+    ReenterPassword,
+    /// Asking again gets the same answer.
+    None,
+}
+
+/// A failed visit as shown to the user, the error of the visit commands.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VisitFailure {
+    pub message: String,
+    pub recovery: VisitRecovery,
+}
+
+impl From<CoreError> for VisitFailure {
+    fn from(err: CoreError) -> Self {
+        let (message, recovery) = match err {
+            CoreError::VisitRemoteOffline => (
+                "The remote device is offline, make sure MirrorX is running on it.",
+                VisitRecovery::RetryLater,
+            ),
+            CoreError::VisitRemoteRejected => (
+                "The remote device rejected the visit.",
+                VisitRecovery::None,
+            ),
+            CoreError::VisitInvalidPassword => (
+                "The password is incorrect.",
+                VisitRecovery::ReenterPassword,
+            ),
+            CoreError::VisitTimeout | CoreError::Timeout => (
+                "The signaling server didn't answer in time.",
+                VisitRecovery::RetryLater,
+            ),
+            CoreError::VisitServerBusy => (
+                "The signaling server is busy.",
+                VisitRecovery::RetryLater,
+            ),
+            CoreError::SignalingUnavailable => (
+                "The signaling server failed repeatedly, MirrorX waits a moment before trying it again.",
+                VisitRecovery::RetryLater,
+            ),
+            CoreError::VisitInvalidArgs => (
+                "The visit request was refused as invalid, the remote device may run an incompatible version.",
+                VisitRecovery::None,
+            ),
+            CoreError::VisitInternalError => (
+                "The signaling server or the remote device failed to handle the visit.",
+                VisitRecovery::RetryLater,
+            ),
+            CoreError::VisitKeyGenerationFailed | CoreError::VisitKeyExchangeFailed => (
+                "A secure connection to the remote device couldn't be set up.",
+                VisitRecovery::None,
+            ),
+            CoreError::VisitSignatureInvalid => (
+                "The remote device failed to prove its identity, the connection may be intercepted.",
+                VisitRecovery::None,
+            ),
+            CoreError::VisitDeviceUntrusted => (
+                "The remote device isn't one of the trusted devices.",
+                VisitRecovery::None,
+            ),
+            CoreError::VisitNotAllowed => (
+                "The remote device only accepts devices on its allow list.",
+                VisitRecovery::None,
+            ),
+            CoreError::VisitReplayed => (
+                "The remote device saw this visit before, the connection may be intercepted.",
+                VisitRecovery::None,
+            ),
+            CoreError::HandshakeTokenRepeated
+            | CoreError::HandshakeFailed(HandshakeFailure::Closed) => (
+                "The visit is already in use by another connection.",
+                VisitRecovery::RetryLater,
+            ),
+            CoreError::HandshakeTokenExpired | CoreError::HandshakeTokenUnknown => (
+                "The visit expired before the connection was made.",
+                VisitRecovery::RetryLater,
+            ),
+            CoreError::HandshakeFailed(_) => (
+                "The connection to the remote device couldn't be set up, it may run an incompatible version of MirrorX.",
+                VisitRecovery::None,
+            ),
+            CoreError::ConnectAttemptsExhausted { .. } => (
+                "The remote device couldn't be reached, the network may be down.",
+                VisitRecovery::RetryLater,
+            ),
+            CoreError::ReconnectNeedsPassword => (
+                "The connection broke, enter the password to connect again.",
+                VisitRecovery::ReenterPassword,
+            ),
+            CoreError::Cancelled => ("The connection was cancelled.", VisitRecovery::None),
+            CoreError::ProtocolMismatch { .. } => (
+                "The remote device runs an incompatible version of MirrorX.",
+                VisitRecovery::None,
+            ),
+            err => {
+                let recovery = if err.is_retryable() {
+                    VisitRecovery::RetryLater
+                } else {
+                    VisitRecovery::None
+                };
+
+                return VisitFailure {
+                    message: err.to_string(),
+                    recovery,
+                };
+            }
+        };
+
+        VisitFailure {
+            message: message.to_string(),
+            recovery,
+        }
+    }
+}
+
+/// How long to wait before visiting again without asking the user, `None`
+/// when the failure won't pass that quickly or the attempts are used up.
+/// `attempt` counts from 1.
+pub fn visit_retry_delay(err: &CoreError, attempt: u32) -> Option<Duration> {
+    if attempt >= VISIT_ATTEMPTS {
+        return None;
+    }
+
+    // an offline device or a broken server takes longer than a few seconds
+    match err {
+        CoreError::Timeout | CoreError::VisitTimeout | CoreError::VisitServerBusy => {
+            Some(Duration::from_secs(1) * 2u32.pow(attempt - 1))
+        }
+        _ => None,
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{api::endpoint::message::EndPointAudioParams, core_error, error::CoreResult};
+use mirrorx_native::opus::{decoder::*, encoder::*};
+
+/// Sample rates Opus takes, ascending.
+pub const OPUS_SAMPLE_RATES: [u32; 5] = [8000, 12000, 16000, 24000, 48000];
+
+/// Frames per second of an audio stream, every frame carries 10 ms.
+pub const AUDIO_FRAMES_PER_SECOND: u32 = 100;
+
+/// Longest frame Opus decodes, 120 ms at 48 kHz.
+const MAX_DECODE_SAMPLES: usize = 5760;
+
+/// Encodes and decodes frames of interleaved f32 samples in the format the
+/// codec was created for.
+pub trait AudioCodec: Send {
+    fn encode(&mut self, samples: &[f32]) -> CoreResult<Vec<u8>>;
+    fn decode(&mut self, packet: &[u8]) -> CoreResult<Vec<f32>>;
+}
+
+/// The codec audio frames are encoded with.
+pub fn new_audio_codec(params: &EndPointAudioParams) -> CoreResult<Box<dyn AudioCodec>> {
+    Ok(Box::new(OpusCodec::new(params)?))
+}
+
+/// Samples of all channels in a frame of `params`.
+pub fn frame_samples(params: &EndPointAudioParams) -> usize {
+    (params.sample_rate / AUDIO_FRAMES_PER_SECOND) as usize * params.channels as usize
+}
+
+pub struct OpusCodec {
+    opus_encoder: *mut OpusEncoder,
+    opus_decoder: *mut OpusDecoder,
+    channels: usize,
+    encode_buffer: Vec<u8>,
+    decode_buffer: Vec<f32>,
+}
+
+unsafe impl Send for OpusCodec {}
+
+impl OpusCodec {
+    pub fn new(params: &EndPointAudioParams) -> CoreResult<Self> {
+        if !OPUS_SAMPLE_RATES.contains(&params.sample_rate) || !(1..=2).contains(&params.channels) {
+            return Err(core_error!("unsupported opus params ({:?})", params));
+        }
+
+        let mut codec = OpusCodec {
+            opus_encoder: std::ptr::null_mut(),
+            opus_decoder: std::ptr::null_mut(),
+            channels: params.channels as usize,
+            encode_buffer: vec![0u8; 4000],
+            decode_buffer: vec![0f32; MAX_DECODE_SAMPLES * params.channels as usize],
+        };
+
+        unsafe {
+            let mut ret = 0;
+            codec.opus_encoder = opus_encoder_create(
+                params.sample_rate as _,
+                params.channels as _,
+                OPUS_APPLICATION_RESTRICTED_LOWDELAY,
+                &mut ret,
+            );
+
+            if ret < 0 || codec.opus_encoder.is_null() {
+                return Err(core_error!("opus_encoder_create returns error ({})", ret));
+            }
+
+            codec.opus_decoder =
+                opus_decoder_create(params.sample_rate as _, params.channels as _, &mut ret);
+
+            if ret < 0 || codec.opus_decoder.is_null() {
+                return Err(core_error!("opus_decoder_create returns error ({})", ret));
+            }
+        }
+
+        Ok(codec)
+    }
+}
+
+impl AudioCodec for OpusCodec {
+    fn encode(&mut self, samples: &[f32]) -> CoreResult<Vec<u8>> {
+        let ret = unsafe {
+            opus_encode_float(
+                self.opus_encoder,
+                samples.as_ptr(),
+                (samples.len() / self.channels) as _,
+                self.encode_buffer.as_mut_ptr(),
+                self.encode_buffer.len() as _,
+            )
+        };
+
+        if ret < 0 {
+            return Err(core_error!("opus encode failed ({})", ret));
+        }
+
+        Ok(self.encode_buffer[..ret as usize].to_vec())
+    }
+
+    fn decode(&mut self, packet: &[u8]) -> CoreResult<Vec<f32>> {
+        let ret = unsafe {
+            opus_decode_float(
+                self.opus_decoder,
+                packet.as_ptr(),
+                packet.len() as _,
+                self.decode_buffer.as_mut_ptr(),
+                MAX_DECODE_SAMPLES as _,
+                0,
+            )
+        };
+
+        if ret < 0 {
+            return Err(core_error!("opus decode failed ({})", ret));
+        }
+
+        Ok(self.decode_buffer[..ret as usize * self.channels].to_vec())
+    }
+}
+
+impl Drop for OpusCodec {
+    fn drop(&mut self) {
+        unsafe {
+            if !self.opus_encoder.is_null() {
+                opus_encoder_destroy(self.opus_encoder);
+            }
+
+            if !self.opus_decoder.is_null() {
+                opus_decoder_destroy(self.opus_decoder);
+            }
+        }
+    }
+}
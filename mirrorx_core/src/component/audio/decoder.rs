@@ -1,21 +1,45 @@
+use super::{
+    codec::{frame_samples, new_audio_codec, AudioCodec},
+    resampler::Resampler,
+};
 use crate::{
-    api::endpoint::message::EndPointAudioFrame, component::audio::resampler::Resampler, core_error,
+    api::endpoint::message::{EndPointAudioFrame, EndPointAudioParams},
     error::CoreResult,
 };
 use cpal::{SampleFormat, SampleRate};
-use mirrorx_native::{
-    ffmpeg::avutil::{AVSampleFormat, AV_SAMPLE_FMT_FLT, AV_SAMPLE_FMT_S16},
-    opus::decoder::{
-        opus_decode, opus_decode_float, opus_decoder_create, opus_decoder_destroy, OpusDecoder,
-    },
-};
+use mirrorx_native::ffmpeg::avutil::{AVSampleFormat, AV_SAMPLE_FMT_FLT, AV_SAMPLE_FMT_S16};
+
+/// Lost frames filled with silence at most, after a longer gap the stream
+/// just goes on.
+pub const MAX_CONCEALED_FRAMES: u64 = 10;
+
+/// Tracks the sequence numbers of the received audio frames.
+#[derive(Debug, Default)]
+pub struct AudioSequence {
+    next_seq: Option<u64>,
+}
+
+impl AudioSequence {
+    /// Frames lost right before `seq`, `None` for a frame arriving after a
+    /// later one, which is too late to play. A frame far behind the ones
+    /// before starts over, the host restarted the stream after a swap.
+    pub fn receive(&mut self, seq: u64) -> Option<u64> {
+        let lost = match self.next_seq {
+            Some(next_seq) if seq.saturating_add(MAX_CONCEALED_FRAMES) < next_seq => 0,
+            Some(next_seq) if seq < next_seq => return None,
+            Some(next_seq) => seq - next_seq,
+            None => 0,
+        };
+
+        self.next_seq = Some(seq.saturating_add(1));
+        Some(lost)
+    }
+}
 
 pub struct AudioDecoder {
-    opus_decoder: *mut OpusDecoder,
+    codec: Option<(EndPointAudioParams, Box<dyn AudioCodec>)>,
     resampler: Option<Resampler>,
-    channels: u8,
-    sample_rate: u32,
-    sample_format: SampleFormat,
+    sequence: AudioSequence,
     out_channels: u8,
     out_sample_format: SampleFormat,
     out_sample_rate: SampleRate,
@@ -28,124 +52,85 @@ impl AudioDecoder {
         out_sample_rate: SampleRate,
     ) -> AudioDecoder {
         Self {
-            opus_decoder: std::ptr::null_mut(),
+            codec: None,
             resampler: None,
-            channels: 0,
-            sample_rate: 0,
-            sample_format: SampleFormat::F32,
+            sequence: AudioSequence::default(),
             out_channels,
             out_sample_format,
             out_sample_rate,
         }
     }
+
+    /// Samples of `audio_frame` in the output format, led by silence for
+    /// the frames lost before it. Empty for a frame that came too late.
     pub fn decode(&mut self, audio_frame: EndPointAudioFrame) -> CoreResult<Vec<u8>> {
-        unsafe {
-            let audio_frame_sample_format = audio_frame.sample_format.into();
-
-            if self.opus_decoder.is_null()
-                || self.channels != audio_frame.channels
-                || self.sample_rate != audio_frame.sample_rate
-                || self.sample_format != audio_frame_sample_format
-            {
-                if !self.opus_decoder.is_null() {
-                    opus_decoder_destroy(self.opus_decoder);
-                }
-
-                let mut ret = 0;
-                let opus_decoder = opus_decoder_create(
-                    audio_frame.sample_rate as i32,
-                    audio_frame.channels as isize,
-                    &mut ret,
-                );
-
-                if ret < 0 {
-                    return Err(core_error!("opus_decoder_create returns error ({})", ret));
-                }
-
-                self.opus_decoder = opus_decoder;
-                self.channels = audio_frame.channels;
-                self.sample_format = audio_frame_sample_format;
-                self.sample_rate = audio_frame.sample_rate;
-
-                if self.channels != self.out_channels
-                    || self.sample_rate != self.out_sample_rate.0
-                    || self.sample_format != self.out_sample_format
-                {
-                    let input_av_sample_format =
-                        cpal_sample_format_to_av_sample_format(self.sample_format);
-                    let output_av_sample_format =
-                        cpal_sample_format_to_av_sample_format(self.out_sample_format);
-
-                    self.resampler = Some(Resampler::new(
-                        480,
-                        self.channels as _,
-                        self.sample_rate as _,
-                        input_av_sample_format as _,
-                        self.out_channels as _,
-                        self.out_sample_rate.0 as _,
-                        output_av_sample_format as _,
-                    )?);
-
-                    tracing::info!(
-                        "use audio resampler with 
-                    input_channels:{}, 
-                    input_sample_rate:{}, 
-                    input_sample_format:{:?}, 
-                    output_channels:{}, 
-                    output_sample_rate:{}, 
-                    output_sample_format:{:?}",
-                        self.channels,
-                        self.sample_rate,
-                        self.sample_format,
-                        self.out_channels,
-                        self.out_sample_rate.0,
-                        self.out_sample_format
-                    );
-                }
+        let Some(lost) = self.sequence.receive(audio_frame.seq) else {
+            return Ok(Vec::new());
+        };
+
+        let params = EndPointAudioParams {
+            sample_rate: audio_frame.sample_rate,
+            channels: audio_frame.channels,
+        };
+
+        let mut codec = match self.codec.take() {
+            Some((codec_params, codec)) if codec_params == params => codec,
+            _ => {
+                self.resampler = self.new_resampler(&params)?;
+                new_audio_codec(&params)?
             }
-
-            let mut buffer = Vec::<u8>::with_capacity(960 * self.sample_format.sample_size());
-            let frame_size = buffer.capacity()
-                / self.sample_format.sample_size()
-                / (audio_frame.channels as usize);
-
-            let ret = match self.sample_format {
-                SampleFormat::I16 | SampleFormat::U16 => opus_decode(
-                    self.opus_decoder,
-                    audio_frame.buffer.as_ptr(),
-                    audio_frame.buffer.len() as _,
-                    std::mem::transmute(buffer.as_mut_ptr()),
-                    frame_size as _,
-                    0,
-                ),
-                SampleFormat::F32 => opus_decode_float(
-                    self.opus_decoder,
-                    audio_frame.buffer.as_ptr(),
-                    audio_frame.buffer.len() as _,
-                    std::mem::transmute(buffer.as_mut_ptr()),
-                    frame_size as _,
-                    0,
-                ),
-            };
-
-            buffer.set_len(
-                (ret as usize) * self.sample_format.sample_size() * (self.channels as usize),
+        };
+
+        let decoded = codec.decode(&audio_frame.buffer);
+        self.codec = Some((params, codec));
+        let decoded = decoded?;
+
+        let mut samples = Vec::new();
+        if lost > 0 {
+            tracing::warn!(lost, "audio frames lost, play silence");
+            samples.resize(
+                lost.min(MAX_CONCEALED_FRAMES) as usize * frame_samples(&params),
+                0.0,
             );
+        }
+        samples.extend_from_slice(&decoded);
 
-            if let Some(ref mut resampler) = self.resampler {
-                buffer = resampler.convert(buffer.as_slice())?;
-            }
+        let buffer: Vec<u8> = samples.iter().flat_map(|v| v.to_ne_bytes()).collect();
 
-            Ok(buffer)
+        match self.resampler {
+            Some(ref mut resampler) => resampler.convert(&buffer),
+            None => Ok(buffer),
         }
     }
-}
 
-impl Drop for AudioDecoder {
-    fn drop(&mut self) {
-        if !self.opus_decoder.is_null() {
-            unsafe { opus_decoder_destroy(self.opus_decoder) }
+    /// The decoded samples go to the output as they are when their format
+    /// matches.
+    fn new_resampler(&self, params: &EndPointAudioParams) -> CoreResult<Option<Resampler>> {
+        if params.channels == self.out_channels
+            && params.sample_rate == self.out_sample_rate.0
+            && self.out_sample_format == SampleFormat::F32
+        {
+            return Ok(None);
         }
+
+        tracing::info!(
+            input_channels = params.channels,
+            input_sample_rate = params.sample_rate,
+            output_channels = self.out_channels,
+            output_sample_rate = self.out_sample_rate.0,
+            output_sample_format = ?self.out_sample_format,
+            "use audio resampler"
+        );
+
+        Ok(Some(Resampler::new(
+            (frame_samples(params) / params.channels.max(1) as usize) as _,
+            params.channels as _,
+            params.sample_rate as _,
+            AV_SAMPLE_FMT_FLT,
+            self.out_channels as _,
+            self.out_sample_rate.0 as _,
+            cpal_sample_format_to_av_sample_format(self.out_sample_format),
+        )?))
     }
 }
 
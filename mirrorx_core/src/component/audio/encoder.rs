@@ -1,111 +1,132 @@
+use super::{
+    codec::{frame_samples, new_audio_codec, AudioCodec, AUDIO_FRAMES_PER_SECOND},
+    resampler::Resampler,
+};
 use crate::{
-    api::endpoint::message::EndPointAudioFrame, component::frame::AudioEncodeFrame, core_error,
+    api::endpoint::message::{AudioSampleFormat, EndPointAudioFrame, EndPointAudioParams},
+    component::frame::AudioEncodeFrame,
     error::CoreResult,
 };
-use cpal::SampleFormat;
-use mirrorx_native::opus::encoder::*;
+use cpal::{Sample, SampleFormat};
+use mirrorx_native::ffmpeg::avutil::AV_SAMPLE_FMT_FLT;
 
+/// Turns captured samples into audio frames of the negotiated format.
 pub struct AudioEncoder {
-    opus_encoder: *mut OpusEncoder,
-    channels: u16,
-    sample_rate: u32,
-    sample_format: SampleFormat,
-    encode_buffer: [u8; 64000],
+    params: EndPointAudioParams,
+    codec: Box<dyn AudioCodec>,
+    /// Converts captured samples of another rate or channel count, with the
+    /// rate and channel count it was created for.
+    resampler: Option<(u32, u16, Resampler)>,
+    pending: Vec<f32>,
+    seq: u64,
+    encoded_samples: u64,
 }
 
 impl AudioEncoder {
-    pub fn encode(&mut self, capture_frame: AudioEncodeFrame) -> CoreResult<EndPointAudioFrame> {
-        unsafe {
-            if self.opus_encoder.is_null()
-                || self.channels != capture_frame.channels
-                || self.sample_rate != capture_frame.sample_rate
-                || self.sample_format != capture_frame.sample_format
-            {
-                if !self.opus_encoder.is_null() {
-                    opus_encoder_destroy(self.opus_encoder);
-                }
+    pub fn new(params: EndPointAudioParams) -> CoreResult<Self> {
+        Ok(Self {
+            params,
+            codec: new_audio_codec(&params)?,
+            resampler: None,
+            pending: Vec::new(),
+            seq: 0,
+            encoded_samples: 0,
+        })
+    }
 
-                let fs = if capture_frame.sample_rate <= 8000 {
-                    8000
-                } else if capture_frame.sample_rate <= 12000 {
-                    12000
-                } else if capture_frame.sample_rate <= 16000 {
-                    16000
-                } else if capture_frame.sample_rate <= 24000 {
-                    24000
-                } else {
-                    48000
-                };
+    /// Frames completed by `capture_frame`, none while the next one is still
+    /// filling up.
+    pub fn encode(
+        &mut self,
+        capture_frame: AudioEncodeFrame,
+    ) -> CoreResult<Vec<EndPointAudioFrame>> {
+        let samples = self.convert(capture_frame)?;
+        self.pending.extend_from_slice(&samples);
 
-                let mut ret = 0;
-                let opus_encoder = opus_encoder_create(
-                    fs,
-                    capture_frame.channels as _,
-                    OPUS_APPLICATION_RESTRICTED_LOWDELAY,
-                    &mut ret,
-                );
+        let frame_samples = frame_samples(&self.params);
+        let mut frames = Vec::new();
 
-                if ret < 0 {
-                    return Err(core_error!("opus_encoder_create returns error ({})", ret));
-                }
+        while self.pending.len() >= frame_samples {
+            let buffer = self.codec.encode(&self.pending[..frame_samples])?;
+            self.pending.drain(..frame_samples);
 
-                self.opus_encoder = opus_encoder;
-                self.channels = capture_frame.channels;
-                self.sample_format = capture_frame.sample_format;
-                self.sample_rate = fs as _;
-            }
+            frames.push(EndPointAudioFrame {
+                channels: self.params.channels,
+                sample_format: AudioSampleFormat::F32,
+                sample_rate: self.params.sample_rate,
+                seq: self.seq,
+                pts: (self.encoded_samples * 1_000_000 / self.params.sample_rate as u64) as i64,
+                buffer,
+            });
 
-            let frame_size = capture_frame.buffer.len()
-                / capture_frame.sample_format.sample_size()
-                / (capture_frame.channels as usize);
+            self.seq += 1;
+            self.encoded_samples += (frame_samples / self.params.channels as usize) as u64;
+        }
+
+        Ok(frames)
+    }
 
-            let ret = match capture_frame.sample_format {
-                SampleFormat::I16 | SampleFormat::U16 => opus_encode(
-                    self.opus_encoder,
-                    std::mem::transmute(capture_frame.buffer.as_ptr()),
-                    frame_size as _,
-                    self.encode_buffer.as_mut_ptr(),
-                    self.encode_buffer.len() as _,
-                ),
-                SampleFormat::F32 => opus_encode_float(
-                    self.opus_encoder,
-                    std::mem::transmute(capture_frame.buffer.as_ptr()),
-                    frame_size as _,
-                    self.encode_buffer.as_mut_ptr(),
-                    self.encode_buffer.len() as _,
-                ),
-            };
+    /// Captured samples in the negotiated format. Channels past the first
+    /// two are left out.
+    fn convert(&mut self, capture_frame: AudioEncodeFrame) -> CoreResult<Vec<f32>> {
+        let channels = capture_frame.channels.clamp(1, 2);
+        let samples: Vec<f32> = samples_to_f32(&capture_frame.buffer, capture_frame.sample_format)
+            .chunks_exact(capture_frame.channels.max(1) as usize)
+            .flat_map(|frame| frame[..channels as usize].iter().copied())
+            .collect();
 
-            if ret > 0 {
-                Ok(EndPointAudioFrame {
-                    channels: self.channels as _,
-                    sample_format: self.sample_format.into(),
-                    sample_rate: self.sample_rate as _,
-                    buffer: self.encode_buffer[..ret as usize].to_vec(),
-                })
-            } else {
-                Err(core_error!("opus encode failed ({})", ret))
-            }
+        if capture_frame.sample_rate == self.params.sample_rate
+            && channels == self.params.channels as u16
+        {
+            return Ok(samples);
         }
+
+        let mut resampler = match self.resampler.take() {
+            Some(resampler)
+                if resampler.0 == capture_frame.sample_rate && resampler.1 == channels =>
+            {
+                resampler
+            }
+            _ => (
+                capture_frame.sample_rate,
+                channels,
+                Resampler::new(
+                    (capture_frame.sample_rate / AUDIO_FRAMES_PER_SECOND) as _,
+                    channels,
+                    capture_frame.sample_rate as _,
+                    AV_SAMPLE_FMT_FLT,
+                    self.params.channels as _,
+                    self.params.sample_rate as _,
+                    AV_SAMPLE_FMT_FLT,
+                )?,
+            ),
+        };
+
+        let input: Vec<u8> = samples.iter().flat_map(|v| v.to_ne_bytes()).collect();
+        let converted = resampler.2.convert(&input);
+        self.resampler = Some(resampler);
+
+        Ok(bytes_to_f32(&converted?))
     }
 }
 
-impl Default for AudioEncoder {
-    fn default() -> Self {
-        Self {
-            opus_encoder: std::ptr::null_mut(),
-            channels: 0,
-            sample_rate: 0,
-            sample_format: SampleFormat::I16,
-            encode_buffer: [0u8; 64000],
-        }
+fn samples_to_f32(buffer: &[u8], sample_format: SampleFormat) -> Vec<f32> {
+    match sample_format {
+        SampleFormat::I16 => buffer
+            .chunks_exact(2)
+            .map(|v| i16::from_ne_bytes([v[0], v[1]]).to_f32())
+            .collect(),
+        SampleFormat::U16 => buffer
+            .chunks_exact(2)
+            .map(|v| u16::from_ne_bytes([v[0], v[1]]).to_f32())
+            .collect(),
+        SampleFormat::F32 => bytes_to_f32(buffer),
     }
 }
 
-impl Drop for AudioEncoder {
-    fn drop(&mut self) {
-        if !self.opus_encoder.is_null() {
-            unsafe { opus_encoder_destroy(self.opus_encoder) }
-        }
-    }
+pub(crate) fn bytes_to_f32(buffer: &[u8]) -> Vec<f32> {
+    buffer
+        .chunks_exact(4)
+        .map(|v| f32::from_ne_bytes([v[0], v[1], v[2], v[3]]))
+        .collect()
 }
@@ -1,3 +1,4 @@
+pub mod codec;
 pub mod decoder;
 pub mod duplicator;
 pub mod encoder;
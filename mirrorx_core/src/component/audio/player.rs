@@ -72,17 +72,26 @@ pub fn new_play_stream_and_tx(
     Ok((stream, tx))
 }
 
+/// Plays silence when no samples are ready, a lost or late frame mustn't
+/// hold up the audio thread.
 fn play_samples<T>(data: &mut [T], rx: &mut Receiver<Vec<u8>>)
 where
     T: Sample,
 {
-    if let Some(samples) = rx.blocking_recv() {
+    let mut played = 0;
+
+    if let Ok(samples) = rx.try_recv() {
+        played = (samples.len() / T::FORMAT.sample_size()).min(data.len());
         unsafe {
             std::ptr::copy_nonoverlapping(
                 std::mem::transmute(samples.as_ptr()),
                 data.as_mut_ptr(),
-                (samples.len() / T::FORMAT.sample_size()).min(data.len()),
+                played,
             )
         }
     };
+
+    for sample in &mut data[played..] {
+        *sample = T::from(&0.0f32);
+    }
 }
@@ -16,7 +16,8 @@ pub struct Resampler {
     // src_data: *mut *mut u8,
     // src_linesize: i32,
     src_rate: i32,
-    src_nb_samples: i32,
+    /// Bytes of a sample of every channel of the input.
+    src_sample_size: i32,
     // src_nb_channels: i32,
     // src_sample_fmt: i32,
     dst_data: *mut *mut u8,
@@ -178,7 +179,8 @@ impl Resampler {
                 // src_data,
                 // src_linesize,
                 src_rate: input_sample_rate,
-                src_nb_samples: nb_samples,
+                src_sample_size: av_get_bytes_per_sample(input_sample_format)
+                    * src_channel_layout.nb_channels,
                 // src_nb_channels,
                 // src_sample_fmt: input_sample_format,
                 dst_data,
@@ -192,10 +194,13 @@ impl Resampler {
         }
     }
 
+    /// Converts the samples of `input_data`, however many it holds.
     pub fn convert(&mut self, input_data: &[u8]) -> CoreResult<Vec<u8>> {
+        let src_nb_samples = input_data.len() as i32 / self.src_sample_size.max(1);
+
         unsafe {
             self.dst_nb_samples = av_rescale_rnd(
-                swr_get_delay(self.swr_context, self.src_rate as _) + (self.src_nb_samples as i64),
+                swr_get_delay(self.swr_context, self.src_rate as _) + (src_nb_samples as i64),
                 self.dst_rate.into(),
                 self.src_rate.into(),
                 AV_ROUND_UP,
@@ -225,7 +230,7 @@ impl Resampler {
                 self.dst_data,
                 self.dst_nb_samples,
                 &input_data.as_ptr(),
-                self.src_nb_samples,
+                src_nb_samples,
             );
 
             if ret < 0 {
@@ -0,0 +1,127 @@
+use crate::{
+    api::endpoint::message::MediaErrorReason, error::CoreError,
+    utility::permission::check_platform_permissions,
+};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+/// Transient capture failures in a row skipped before the capture restarts.
+/// A frame is due every few milliseconds, so this is well under a second.
+pub const MAX_TRANSIENT_CAPTURE_FAILURES: u32 = 30;
+
+/// Capture restarts without a frame in between before the session gives up
+/// on the screen.
+pub const MAX_CAPTURE_RESTARTS: u32 = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CaptureErrorKind {
+    /// The next frame will likely work, e.g. a driver glitch.
+    Transient,
+    /// The capture can't produce frames any more until it's created again,
+    /// e.g. the desktop switched or the graphics device was reset.
+    Fatal,
+}
+
+pub fn classify_capture_error(err: &CoreError) -> CaptureErrorKind {
+    #[cfg(target_os = "windows")]
+    if let CoreError::HResultError { error, .. } = err {
+        use windows::Win32::Graphics::Dxgi::{
+            DXGI_ERROR_ACCESS_LOST, DXGI_ERROR_DEVICE_REMOVED, DXGI_ERROR_DEVICE_RESET,
+            DXGI_ERROR_SESSION_DISCONNECTED, DXGI_ERROR_UNSUPPORTED,
+        };
+
+        let code = error.code();
+        if code == DXGI_ERROR_ACCESS_LOST
+            || code == DXGI_ERROR_DEVICE_REMOVED
+            || code == DXGI_ERROR_DEVICE_RESET
+            || code == DXGI_ERROR_SESSION_DISCONNECTED
+            || code == DXGI_ERROR_UNSUPPORTED
+        {
+            return CaptureErrorKind::Fatal;
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    let _ = err;
+
+    CaptureErrorKind::Transient
+}
+
+/// What the viewer is told when the capture can't be created or started.
+/// Without the screen capture permission the platform often fails with an
+/// unrelated error, so the permission is looked at again.
+pub fn capture_failure_reason(err: &CoreError) -> MediaErrorReason {
+    match err {
+        CoreError::ScreenCapturePermissionDenied => MediaErrorReason::PermissionDenied,
+        CoreError::NoDisplayAvailable => MediaErrorReason::NoDisplay,
+        CoreError::WindowClosed => MediaErrorReason::WindowClosed,
+        _ if !check_platform_permissions(false).screen_capture.is_usable() => {
+            MediaErrorReason::PermissionDenied
+        }
+        _ => MediaErrorReason::CaptureFailed,
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CaptureVerdict {
+    /// Leave the frame out and capture the next one.
+    Skip,
+    /// Create the capture again.
+    Restart,
+    /// Give up, the session can't get frames of this screen.
+    Fail,
+}
+
+/// Decides what a capture failure escalates to, so a flaky driver costs a
+/// few frames instead of the session.
+#[derive(Debug, Default)]
+pub struct CaptureTolerance {
+    consecutive_failures: u32,
+    restarts: u32,
+}
+
+impl CaptureTolerance {
+    pub fn record_frame(&mut self) {
+        self.consecutive_failures = 0;
+        self.restarts = 0;
+    }
+
+    pub fn record_error(&mut self, kind: CaptureErrorKind) -> CaptureVerdict {
+        if kind == CaptureErrorKind::Transient {
+            self.consecutive_failures += 1;
+            if self.consecutive_failures < MAX_TRANSIENT_CAPTURE_FAILURES {
+                return CaptureVerdict::Skip;
+            }
+        }
+
+        self.consecutive_failures = 0;
+        self.restarts += 1;
+
+        if self.restarts > MAX_CAPTURE_RESTARTS {
+            CaptureVerdict::Fail
+        } else {
+            CaptureVerdict::Restart
+        }
+    }
+}
+
+/// A capture failure that restarted the capture or ended the session,
+/// skipped frames aren't reported.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum CaptureEscalationEvent {
+    Restart { endpoint_id: String, reason: String },
+    Fail { endpoint_id: String, reason: String },
+}
+
+static CAPTURE_ESCALATION_TX: Lazy<tokio::sync::broadcast::Sender<CaptureEscalationEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+pub fn subscribe_capture_escalation() -> tokio::sync::broadcast::Receiver<CaptureEscalationEvent> {
+    CAPTURE_ESCALATION_TX.subscribe()
+}
+
+pub fn report_capture_escalation(event: CaptureEscalationEvent) {
+    tracing::warn!(?event, "capture failure escalated");
+    let _ = CAPTURE_ESCALATION_TX.send(event);
+}
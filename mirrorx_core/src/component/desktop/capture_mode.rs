@@ -0,0 +1,119 @@
+use super::window::FrameRect;
+use serde::{Deserialize, Serialize};
+use std::{ops::Range, time::Duration};
+
+/// How long an event driven capture waits for the screen to change before
+/// it looks at the session again, a static screen produces no frames.
+pub const CAPTURE_WAIT_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// Interval of the captures taken when the system doesn't tell what
+/// changed.
+pub const POLLING_CAPTURE_INTERVAL: Duration = Duration::from_millis(33);
+
+/// How the host finds out that its screen changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CaptureMode {
+    /// The system hands over a frame only when something changed, along
+    /// with the rectangles that did. Only these are compared and encoded.
+    EventDriven,
+    /// Frames are taken at a fixed rate, the change detector compares them
+    /// whole.
+    Polling,
+}
+
+/// A changed rectangle of a frame given by its edges, clipped to the frame
+/// and grown to even edges to cover whole NV12 chroma blocks. `None` when
+/// nothing of it is on the frame.
+pub fn clip_dirty_rect(
+    left: i32,
+    top: i32,
+    right: i32,
+    bottom: i32,
+    frame_width: i32,
+    frame_height: i32,
+) -> Option<FrameRect> {
+    let left = left.max(0) & !1;
+    let top = top.max(0) & !1;
+    let right = ((right.min(frame_width) + 1) & !1).min(frame_width);
+    let bottom = ((bottom.min(frame_height) + 1) & !1).min(frame_height);
+
+    if right <= left || bottom <= top {
+        return None;
+    }
+
+    Some(FrameRect {
+        left,
+        top,
+        width: right - left,
+        height: bottom - top,
+    })
+}
+
+/// The changed rectangles of a frame cut down to `crop`, in the pixels of
+/// the cropped frame.
+pub fn crop_dirty_rects(rects: &[FrameRect], crop: &FrameRect) -> Vec<FrameRect> {
+    rects
+        .iter()
+        .filter_map(|rect| {
+            clip_dirty_rect(
+                rect.left - crop.left,
+                rect.top - crop.top,
+                rect.left + rect.width - crop.left,
+                rect.top + rect.height - crop.top,
+                crop.width,
+                crop.height,
+            )
+        })
+        .collect()
+}
+
+/// The changed rectangles of a frame scaled along with it. They grow to
+/// whole pixels of the scaled frame, so nothing changed is left out.
+pub fn scale_dirty_rects(
+    rects: &[FrameRect],
+    src_width: i32,
+    src_height: i32,
+    dst_width: i32,
+    dst_height: i32,
+) -> Vec<FrameRect> {
+    if src_width <= 0 || src_height <= 0 {
+        return Vec::new();
+    }
+
+    let scale = |value: i32, src: i32, dst: i32, round_up: bool| {
+        let scaled = value as i64 * dst as i64;
+        let scaled = if round_up {
+            (scaled + src as i64 - 1) / src as i64
+        } else {
+            scaled / src as i64
+        };
+        scaled as i32
+    };
+
+    rects
+        .iter()
+        .filter_map(|rect| {
+            clip_dirty_rect(
+                scale(rect.left, src_width, dst_width, false),
+                scale(rect.top, src_height, dst_height, false),
+                scale(rect.left + rect.width, src_width, dst_width, true),
+                scale(rect.top + rect.height, src_height, dst_height, true),
+                dst_width,
+                dst_height,
+            )
+        })
+        .collect()
+}
+
+/// Whether a changed rectangle overlaps the pixels of the given columns and
+/// rows.
+pub fn is_dirty(rects: &[FrameRect], x: &Range<usize>, y: &Range<usize>) -> bool {
+    rects.iter().any(|rect| {
+        let (left, top) = (rect.left.max(0) as usize, rect.top.max(0) as usize);
+        let right = (rect.left + rect.width).max(0) as usize;
+        let bottom = (rect.top + rect.height).max(0) as usize;
+
+        left < x.end && x.start < right && top < y.end && y.start < bottom
+    })
+}
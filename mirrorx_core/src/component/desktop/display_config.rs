@@ -0,0 +1,208 @@
+use crate::error::CoreResult;
+use once_cell::sync::Lazy;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Notices when the captured display changes its size, because of a new
+/// resolution or a rotated screen.
+#[derive(Debug, Default)]
+pub struct DisplayConfigWatch {
+    size: Option<(i32, i32)>,
+    announce_first: bool,
+}
+
+impl DisplayConfigWatch {
+    /// Counts the first frame as a change as well, for captures the viewer
+    /// wasn't told the size of when the session was negotiated.
+    pub fn announcing_first_frame() -> Self {
+        Self {
+            size: None,
+            announce_first: true,
+        }
+    }
+
+    /// Records the size of a captured frame. Returns true when it differs
+    /// from the previous frame, the first frame counts only when announced.
+    pub fn observe(&mut self, width: i32, height: i32) -> bool {
+        match self.size.replace((width, height)) {
+            Some(size) => size != (width, height),
+            None => self.announce_first,
+        }
+    }
+}
+
+static DISPLAY_CHANGES: Lazy<DisplayChanges> = Lazy::new(|| {
+    let display_changes = DisplayChanges::default();
+    listen_display_changes();
+    display_changes
+});
+
+/// Counts the display changes the OS reported, a new resolution, a rotated
+/// screen, a display plugged in or out. Clones share the count.
+#[derive(Debug, Default, Clone)]
+pub struct DisplayChanges {
+    generation: Arc<AtomicU64>,
+}
+
+impl DisplayChanges {
+    /// The changes the OS reports, it's listened to from the first call on.
+    pub fn global() -> Self {
+        DISPLAY_CHANGES.clone()
+    }
+
+    pub fn notify(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Watches the changes from now on.
+    pub fn watch(&self) -> DisplayChangeWatch {
+        DisplayChangeWatch {
+            generation: self.generation.load(Ordering::SeqCst),
+            display_changes: self.clone(),
+        }
+    }
+}
+
+/// Tells a capture when to create itself again. A capture keeps the mode of
+/// the display it was created for, so after a change its frames are stale or
+/// it fails altogether.
+#[derive(Debug)]
+pub struct DisplayChangeWatch {
+    display_changes: DisplayChanges,
+    generation: u64,
+}
+
+impl DisplayChangeWatch {
+    /// Whether the display changed since the previous call.
+    pub fn changed(&mut self) -> bool {
+        let generation = self.display_changes.generation.load(Ordering::SeqCst);
+        std::mem::replace(&mut self.generation, generation) != generation
+    }
+
+    /// Replaces `capture` by the one `create` makes from it when the display
+    /// changed. Returns whether it was replaced, the previous capture stays
+    /// when creating the new one fails.
+    pub fn recreate<T>(
+        &mut self,
+        capture: &mut T,
+        create: impl FnOnce(&mut T) -> CoreResult<T>,
+    ) -> CoreResult<bool> {
+        if !self.changed() {
+            return Ok(false);
+        }
+
+        *capture = create(capture)?;
+        Ok(true)
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn listen_display_changes() {
+    use core_graphics::display::CGDirectDisplayID;
+    use mirrorx_native::os::macos::core_graphics::{
+        kCGDisplayBeginConfigurationFlag, CGDisplayChangeSummaryFlags,
+        CGDisplayRegisterReconfigurationCallback,
+    };
+    use std::os::raw::c_void;
+
+    // called once before and once after a display is reconfigured, through
+    // the main run loop of the app
+    extern "C" fn display_reconfigured(
+        display: CGDirectDisplayID,
+        flags: CGDisplayChangeSummaryFlags,
+        _user_info: *mut c_void,
+    ) {
+        if flags & kCGDisplayBeginConfigurationFlag != 0 {
+            return;
+        }
+
+        tracing::info!(?display, ?flags, "display reconfigured");
+        DISPLAY_CHANGES.notify();
+    }
+
+    let err = unsafe {
+        CGDisplayRegisterReconfigurationCallback(display_reconfigured, std::ptr::null_mut())
+    };
+
+    if err != 0 {
+        tracing::error!(?err, "register display reconfiguration callback failed");
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn listen_display_changes() {
+    use windows::{
+        w,
+        Win32::{
+            Foundation::{HINSTANCE, HWND, LPARAM, LRESULT, WPARAM},
+            UI::WindowsAndMessaging::*,
+        },
+    };
+
+    unsafe extern "system" fn window_proc(
+        hwnd: HWND,
+        msg: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        if msg == WM_DISPLAYCHANGE {
+            tracing::info!("display changed");
+            DISPLAY_CHANGES.notify();
+        }
+
+        DefWindowProcW(hwnd, msg, wparam, lparam)
+    }
+
+    let listener = std::thread::Builder::new()
+        .name("display_change_listener".into())
+        .spawn(|| unsafe {
+            let class_name = w!("MirrorXDisplayChangeListener");
+
+            let window_class = WNDCLASSW {
+                lpfnWndProc: Some(window_proc),
+                lpszClassName: class_name,
+                ..Default::default()
+            };
+
+            if RegisterClassW(&window_class) == 0 {
+                tracing::error!("register display change window class failed");
+                return;
+            }
+
+            // WM_DISPLAYCHANGE is broadcast to top-level windows only, a
+            // message-only window doesn't get it
+            let hwnd = CreateWindowExW(
+                WINDOW_EX_STYLE::default(),
+                class_name,
+                w!(""),
+                WS_OVERLAPPED,
+                0,
+                0,
+                0,
+                0,
+                HWND::default(),
+                HMENU::default(),
+                HINSTANCE::default(),
+                None,
+            );
+
+            if hwnd.0 == 0 {
+                tracing::error!("create display change window failed");
+                return;
+            }
+
+            let mut msg = MSG::default();
+            while GetMessageW(&mut msg, HWND::default(), 0, 0).as_bool() {
+                DispatchMessageW(&msg);
+            }
+        });
+
+    if let Err(err) = listener {
+        tracing::error!(?err, "spawn display change listener failed");
+    }
+}
+
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+fn listen_display_changes() {}
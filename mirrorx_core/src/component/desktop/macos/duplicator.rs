@@ -1,9 +1,19 @@
 use crate::{
-    component::{desktop::monitor::NSScreen, frame::DesktopEncodeFrame},
+    component::{
+        desktop::{
+            capture_mode::{clip_dirty_rect, CaptureMode},
+            monitor::NSScreen,
+            window::FrameRect,
+        },
+        frame::DesktopEncodeFrame,
+    },
     core_error,
     error::CoreResult,
 };
 use block::ConcreteBlock;
+use core_foundation::{
+    base::TCFType, boolean::CFBoolean, dictionary::CFDictionary, string::CFString,
+};
 use dispatch::ffi::{dispatch_queue_create, dispatch_release, DISPATCH_QUEUE_SERIAL};
 use mirrorx_native::os::macos::{core_graphics::*, core_video::*, io_surface::*};
 use once_cell::unsync::OnceCell;
@@ -21,6 +31,7 @@ unsafe impl Sync for Duplicator {}
 impl Duplicator {
     pub fn new(
         monitor_id: Option<String>,
+        include_cursor: bool,
         capture_frame_tx: Sender<DesktopEncodeFrame>,
     ) -> CoreResult<(Self, String)> {
         unsafe {
@@ -79,12 +90,19 @@ impl Duplicator {
 
             let block = block.copy();
 
+            // the stream composites the pointer itself, it can't be toggled
+            // once the stream is created
+            let properties = CFDictionary::from_CFType_pairs(&[(
+                CFString::wrap_under_get_rule(kCGDisplayStreamShowCursor).as_CFType(),
+                CFBoolean::from(include_cursor).as_CFType(),
+            )]);
+
             let display_stream = CGDisplayStreamCreateWithDispatchQueue(
                 screen.screenNumber(),
                 screen_size.width as usize,
                 screen_size.height as usize,
                 kCVPixelFormatType_420YpCbCr8BiPlanarFullRange as i32,
-                std::ptr::null_mut(),
+                properties.as_concrete_TypeRef(),
                 dispatch_queue,
                 block.deref(),
             );
@@ -110,6 +128,11 @@ impl Duplicator {
         }
     }
 
+    /// The display stream hands over frames only when the screen changed.
+    pub fn capture_mode(&self) -> CaptureMode {
+        CaptureMode::EventDriven
+    }
+
     pub fn stop(&self) -> CoreResult<()> {
         unsafe {
             let error_code = CGDisplayStreamStop(self.display_stream);
@@ -142,6 +165,11 @@ unsafe fn frame_available_handler(
         return;
     }
 
+    // idle and blank updates carry no new image
+    if status != kCGDisplayStreamFrameStatusFrameComplete {
+        return;
+    }
+
     let mut pixel_buffer = std::ptr::null_mut();
     let ret = CVPixelBufferCreateWithIOSurface(
         std::ptr::null(),
@@ -190,6 +218,7 @@ unsafe fn frame_available_handler(
         luminance_stride: luminance_stride as i32,
         chrominance_bytes,
         chrominance_stride: chrominance_stride as i32,
+        dirty_rects: update_dirty_rects(update_ref, width as i32, height as i32),
     };
 
     if (*capture_frame_tx).blocking_send(capture_frame).is_err() {
@@ -201,3 +230,41 @@ unsafe fn frame_available_handler(
         tracing::warn!(count = dropped_frames, "drop frames");
     }
 }
+
+/// The rectangles the update reports changed, `None` when it has none.
+unsafe fn update_dirty_rects(
+    update_ref: CGDisplayStreamUpdateRef,
+    width: i32,
+    height: i32,
+) -> Option<Vec<FrameRect>> {
+    if update_ref.is_null() {
+        return None;
+    }
+
+    let mut rect_count = 0;
+    let rects = CGDisplayStreamUpdateGetRects(
+        update_ref,
+        kCGDisplayStreamUpdateDirtyRects,
+        &mut rect_count,
+    );
+
+    if rects.is_null() {
+        return None;
+    }
+
+    let dirty_rects = std::slice::from_raw_parts(rects, rect_count)
+        .iter()
+        .filter_map(|rect| {
+            clip_dirty_rect(
+                rect.origin.x.floor() as i32,
+                rect.origin.y.floor() as i32,
+                (rect.origin.x + rect.size.width).ceil() as i32,
+                (rect.origin.y + rect.size.height).ceil() as i32,
+                width,
+                height,
+            )
+        })
+        .collect();
+
+    Some(dirty_rects)
+}
@@ -1,4 +1,9 @@
+pub mod capture_error;
+pub mod capture_mode;
+pub mod display_config;
 pub mod monitor;
+pub mod virtual_display;
+pub mod window;
 
 #[cfg(target_os = "macos")]
 mod macos;
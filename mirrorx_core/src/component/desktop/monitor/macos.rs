@@ -1,4 +1,4 @@
-use super::Monitor;
+use super::{select_primary_monitor, Monitor};
 use crate::{core_error, error::CoreResult};
 use core_graphics::display::{CGDirectDisplayID, CGRect, *};
 use mirrorx_native::os::macos::core_graphics::*;
@@ -15,14 +15,7 @@ use std::{
 };
 
 pub fn get_primary_monitor_params() -> CoreResult<Monitor> {
-    let monitors = get_active_monitors(false)?;
-    for monitor in monitors.into_iter() {
-        if monitor.is_primary {
-            return Ok(monitor);
-        }
-    }
-
-    Err(core_error!("no primary display"))
+    select_primary_monitor(get_active_monitors(false)?)
 }
 
 pub fn get_active_monitors(take_screen_shot: bool) -> CoreResult<Vec<Monitor>> {
@@ -37,6 +30,7 @@ pub fn get_active_monitors(take_screen_shot: bool) -> CoreResult<Vec<Monitor>> {
 
             let monitor_width = CGDisplayPixelsWide(display_id);
             let monitor_height = CGDisplayPixelsHigh(display_id);
+            let monitor_bounds = CGDisplayBounds(display_id);
 
             let screen_shot_buffer = if take_screen_shot {
                 take_screen_shot_as_png(display_id)
@@ -52,8 +46,8 @@ pub fn get_active_monitors(take_screen_shot: bool) -> CoreResult<Vec<Monitor>> {
                 height: monitor_height as u16,
                 is_primary: display_id == main_display_id,
                 screen_shot: screen_shot_buffer,
-                left: 0,
-                top: 0,
+                left: monitor_bounds.origin.x as i32,
+                top: monitor_bounds.origin.y as i32,
             });
         }
 
@@ -1,3 +1,7 @@
+use crate::{
+    core_error,
+    error::{CoreError, CoreResult},
+};
 use serde::{Deserialize, Serialize};
 
 #[cfg(target_os = "windows")]
@@ -21,6 +25,21 @@ pub struct Monitor {
     pub height: u16,
     pub is_primary: bool,
     pub screen_shot: Option<Vec<u8>>,
-    pub left: u16,
-    pub top: u16,
+    /// Position on the desktop spanning every monitor, negative for monitors
+    /// left of or above the primary one.
+    pub left: i32,
+    pub top: i32,
+}
+
+/// The primary one of the monitors, [`CoreError::NoDisplayAvailable`] when
+/// there are none at all, like on a headless server.
+pub fn select_primary_monitor(monitors: Vec<Monitor>) -> CoreResult<Monitor> {
+    if monitors.is_empty() {
+        return Err(CoreError::NoDisplayAvailable);
+    }
+
+    monitors
+        .into_iter()
+        .find(|monitor| monitor.is_primary)
+        .ok_or_else(|| core_error!("no primary display"))
 }
@@ -1,5 +1,5 @@
 use crate::{
-    component::desktop::monitor::Monitor,
+    component::desktop::monitor::{select_primary_monitor, Monitor},
     core_error,
     error::{CoreError, CoreResult},
     HRESULT,
@@ -18,14 +18,7 @@ use windows::{
 };
 
 pub fn get_primary_monitor_params() -> CoreResult<Monitor> {
-    let monitors = get_active_monitors(false)?;
-    for monitor in monitors.into_iter() {
-        if monitor.is_primary {
-            return Ok(monitor);
-        }
-    }
-
-    Err(core_error!("no primary display"))
+    select_primary_monitor(get_active_monitors(false)?)
 }
 
 pub fn get_active_monitors(take_screen_shot: bool) -> CoreResult<Vec<Monitor>> {
@@ -149,8 +142,8 @@ unsafe fn enum_dxgi_outputs(
                     height: (monitor_info.rcMonitor.bottom - monitor_info.rcMonitor.top) as u16,
                     is_primary: monitor_is_primary,
                     screen_shot: screent_shot_buffer,
-                    left: monitor_info.rcMonitor.left as u16,
-                    top: monitor_info.rcMonitor.top as u16,
+                    left: monitor_info.rcMonitor.left,
+                    top: monitor_info.rcMonitor.top,
                 });
             }
         }
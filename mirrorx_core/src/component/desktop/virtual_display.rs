@@ -0,0 +1,119 @@
+use super::monitor::{get_active_monitors, select_primary_monitor, Monitor};
+use crate::error::{CoreError, CoreResult};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::PathBuf,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+/// How long the display of a virtual display driver may take to show up
+/// after its command ran.
+pub const VIRTUAL_DISPLAY_WAIT: Duration = Duration::from_secs(5);
+
+const VIRTUAL_DISPLAY_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+static VIRTUAL_DISPLAY_COMMAND: Lazy<RwLock<Option<VirtualDisplayCommand>>> =
+    Lazy::new(|| RwLock::new(None));
+
+/// Adds a display of a virtual display driver, e.g. the tool shipped with an
+/// indirect display driver. It runs without a shell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VirtualDisplayCommand {
+    pub program: PathBuf,
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// The command run when a headless machine is asked to share its screen.
+/// Only used on Windows, other platforms report the missing display.
+pub fn set_virtual_display_command(command: Option<VirtualDisplayCommand>) {
+    *VIRTUAL_DISPLAY_COMMAND
+        .write()
+        .unwrap_or_else(std::sync::PoisonError::into_inner) = command;
+}
+
+pub fn virtual_display_command() -> Option<VirtualDisplayCommand> {
+    VIRTUAL_DISPLAY_COMMAND
+        .read()
+        .unwrap_or_else(std::sync::PoisonError::into_inner)
+        .clone()
+}
+
+/// The primary monitor to share. Without any display the configured virtual
+/// display is added first, otherwise it fails with
+/// [`CoreError::NoDisplayAvailable`]. Blocks while the virtual display comes
+/// up.
+pub fn primary_monitor_or_virtual() -> CoreResult<Monitor> {
+    resolve_primary_monitor(
+        || get_active_monitors(false),
+        add_virtual_display,
+        VIRTUAL_DISPLAY_WAIT,
+    )
+}
+
+/// [`primary_monitor_or_virtual`] with the monitors enumerated by
+/// `enumerate`. `add_virtual_display` returns whether it added a display,
+/// the monitors are enumerated again until it shows up or `wait` passed.
+pub fn resolve_primary_monitor<E, A>(
+    enumerate: E,
+    add_virtual_display: A,
+    wait: Duration,
+) -> CoreResult<Monitor>
+where
+    E: Fn() -> CoreResult<Vec<Monitor>>,
+    A: FnOnce() -> CoreResult<bool>,
+{
+    match select_primary_monitor(enumerate()?) {
+        Err(CoreError::NoDisplayAvailable) => {}
+        result => return result,
+    }
+
+    match add_virtual_display() {
+        Ok(true) => tracing::info!("no display attached, added virtual display"),
+        Ok(false) => return Err(CoreError::NoDisplayAvailable),
+        Err(err) => {
+            tracing::error!(?err, "add virtual display failed");
+            return Err(CoreError::NoDisplayAvailable);
+        }
+    }
+
+    let deadline = Instant::now() + wait;
+    loop {
+        match select_primary_monitor(enumerate()?) {
+            Err(CoreError::NoDisplayAvailable) if Instant::now() < deadline => {
+                std::thread::sleep(VIRTUAL_DISPLAY_POLL_INTERVAL)
+            }
+            result => return result,
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn add_virtual_display() -> CoreResult<bool> {
+    use crate::core_error;
+
+    let Some(command) = virtual_display_command() else {
+        return Ok(false);
+    };
+
+    let status = std::process::Command::new(&command.program)
+        .args(&command.args)
+        .status()?;
+
+    if !status.success() {
+        return Err(core_error!(
+            "virtual display command {:?} exited with {}",
+            command.program,
+            status
+        ));
+    }
+
+    Ok(true)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn add_virtual_display() -> CoreResult<bool> {
+    Ok(false)
+}
@@ -0,0 +1,116 @@
+use super::WindowInfo;
+use crate::{component::input::coordinate::CaptureArea, core_error, error::CoreResult};
+use core_foundation::{
+    base::{CFType, TCFType},
+    boolean::CFBoolean,
+    dictionary::{CFDictionary, CFDictionaryRef},
+    number::CFNumber,
+    string::{CFString, CFStringRef},
+};
+use core_graphics::{
+    display::CGDisplay,
+    geometry::CGRect,
+    window::{
+        copy_window_info, kCGNullWindowID, kCGWindowBounds, kCGWindowIsOnscreen, kCGWindowLayer,
+        kCGWindowListExcludeDesktopElements, kCGWindowListOptionIncludingWindow,
+        kCGWindowListOptionOnScreenOnly, kCGWindowName, kCGWindowNumber, kCGWindowOwnerName,
+        kCGWindowOwnerPID, CGWindowID,
+    },
+};
+
+/// The windows on screen of the normal window layer, front to back. Titles
+/// are left out by the system unless screen recording is allowed, the name
+/// of the owner stands in then.
+pub fn list_windows() -> CoreResult<Vec<WindowInfo>> {
+    let window_list = copy_window_info(
+        kCGWindowListOptionOnScreenOnly | kCGWindowListExcludeDesktopElements,
+        kCGNullWindowID,
+    )
+    .ok_or_else(|| core_error!("CGWindowListCopyWindowInfo returns null"))?;
+
+    let windows = window_list
+        .iter()
+        .filter_map(|item| {
+            let description: CFDictionary<CFString, CFType> =
+                unsafe { TCFType::wrap_under_get_rule(*item as CFDictionaryRef) };
+            parse_window_description(&description)
+        })
+        .collect();
+
+    Ok(windows)
+}
+
+/// `None` once the window is closed.
+pub fn window_info(window_id: u64) -> Option<WindowInfo> {
+    let window_list =
+        copy_window_info(kCGWindowListOptionIncludingWindow, window_id as CGWindowID)?;
+
+    let item = window_list.iter().next()?;
+    let description: CFDictionary<CFString, CFType> =
+        unsafe { TCFType::wrap_under_get_rule(*item as CFDictionaryRef) };
+
+    parse_window_description(&description).filter(|window| window.id == window_id)
+}
+
+/// Bounds of the displays in points, the space window bounds are given in.
+pub fn display_areas() -> CoreResult<Vec<(String, CaptureArea)>> {
+    let display_ids = CGDisplay::active_displays()
+        .map_err(|err| core_error!("CGGetActiveDisplayList failed ({})", err))?;
+
+    Ok(display_ids
+        .into_iter()
+        .map(|display_id| {
+            let bounds = CGDisplay::new(display_id).bounds();
+            (
+                display_id.to_string(),
+                CaptureArea {
+                    left: bounds.origin.x,
+                    top: bounds.origin.y,
+                    width: bounds.size.width,
+                    height: bounds.size.height,
+                },
+            )
+        })
+        .collect())
+}
+
+fn parse_window_description(description: &CFDictionary<CFString, CFType>) -> Option<WindowInfo> {
+    let find = |key: CFStringRef| description.find(unsafe { CFString::wrap_under_get_rule(key) });
+
+    let number = |key: CFStringRef| find(key)?.downcast::<CFNumber>()?.to_i64();
+    let string = |key: CFStringRef| {
+        find(key)
+            .and_then(|value| value.downcast::<CFString>())
+            .map(|value| value.to_string())
+            .unwrap_or_default()
+    };
+
+    // menu bar, dock and overlays live on other layers
+    if number(unsafe { kCGWindowLayer })? != 0 {
+        return None;
+    }
+
+    let bounds = find(unsafe { kCGWindowBounds })?.downcast::<CFDictionary>()?;
+    let bounds = CGRect::from_dict_representation(&bounds)?;
+
+    let owner_process = string(unsafe { kCGWindowOwnerName });
+    let title = match string(unsafe { kCGWindowName }) {
+        title if title.is_empty() => owner_process.clone(),
+        title => title,
+    };
+
+    Some(WindowInfo {
+        id: number(unsafe { kCGWindowNumber })? as u64,
+        title,
+        owner_process,
+        owner_pid: number(unsafe { kCGWindowOwnerPID })? as u32,
+        left: bounds.origin.x.round() as i32,
+        top: bounds.origin.y.round() as i32,
+        width: bounds.size.width.round() as i32,
+        height: bounds.size.height.round() as i32,
+        // left out for windows off screen
+        visible: find(unsafe { kCGWindowIsOnscreen })
+            .and_then(|value| value.downcast::<CFBoolean>())
+            .is_some_and(bool::from),
+    })
+}
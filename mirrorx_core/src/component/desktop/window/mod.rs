@@ -0,0 +1,384 @@
+use crate::component::{
+    desktop::{capture_mode::crop_dirty_rects, monitor::Monitor},
+    frame::DesktopEncodeFrame,
+    input::coordinate::CaptureArea,
+    video_encoder::preprocess::{apply_privacy_region, privacy_regions},
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Ordering,
+    sync::{Arc, Mutex, PoisonError, RwLock},
+    time::{Duration, Instant},
+};
+
+#[cfg(target_os = "windows")]
+mod windows;
+
+#[cfg(target_os = "windows")]
+pub use self::windows::{display_areas, list_windows, window_info};
+
+#[cfg(target_os = "macos")]
+mod macos;
+
+#[cfg(target_os = "macos")]
+pub use macos::{display_areas, list_windows, window_info};
+
+/// How often the capture looks up where the shared window went.
+pub const WINDOW_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+static SHARED_WINDOW: Lazy<RwLock<Option<u64>>> = Lazy::new(|| RwLock::new(None));
+
+/// A top level window of this machine, which can be shared in place of a
+/// whole monitor.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct WindowInfo {
+    /// The window handle on Windows, the window number on macOS. Valid as
+    /// long as the window exists.
+    pub id: u64,
+    pub title: String,
+    /// Name of the process owning the window.
+    pub owner_process: String,
+    pub owner_pid: u32,
+    /// Bounds in the space of [`display_areas`], which spans every monitor.
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+    /// False while minimized or hidden, nothing of it is shared then.
+    pub visible: bool,
+}
+
+impl WindowInfo {
+    pub fn bounds(&self) -> CaptureArea {
+        CaptureArea {
+            left: self.left as f64,
+            top: self.top as f64,
+            width: self.width as f64,
+            height: self.height as f64,
+        }
+    }
+}
+
+/// Shares a window instead of the monitor, `None` goes back to the monitor.
+/// Applies to captures started afterwards.
+pub fn set_shared_window(window_id: Option<u64>) {
+    if let Ok(mut shared_window) = SHARED_WINDOW.write() {
+        *shared_window = window_id;
+    }
+}
+
+pub fn shared_window() -> Option<u64> {
+    SHARED_WINDOW
+        .read()
+        .map(|window| *window)
+        .unwrap_or_default()
+}
+
+/// A rectangle in the pixels of a captured frame. Position and size are
+/// even to match NV12 chroma blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameRect {
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// Where a window shows in a frame of `display`. Window and display are
+/// given in the same units, points on macOS, the frame in pixels. `None`
+/// when too little of the window is on the display.
+pub fn window_frame_rect(
+    window: &CaptureArea,
+    display: &CaptureArea,
+    frame_width: i32,
+    frame_height: i32,
+) -> Option<FrameRect> {
+    if display.width <= 0.0 || display.height <= 0.0 {
+        return None;
+    }
+
+    let scale_x = frame_width as f64 / display.width;
+    let scale_y = frame_height as f64 / display.height;
+
+    let left = ((window.left - display.left) * scale_x).max(0.0).floor() as i32 & !1;
+    let top = ((window.top - display.top) * scale_y).max(0.0).floor() as i32 & !1;
+    let right = ((window.left + window.width - display.left) * scale_x)
+        .min(frame_width as f64)
+        .ceil() as i32;
+    let bottom = ((window.top + window.height - display.top) * scale_y)
+        .min(frame_height as f64)
+        .ceil() as i32;
+
+    let width = (right - left) & !1;
+    let height = (bottom - top) & !1;
+
+    if width < 2 || height < 2 {
+        return None;
+    }
+
+    Some(FrameRect {
+        left,
+        top,
+        width,
+        height,
+    })
+}
+
+fn overlap(a: &CaptureArea, b: &CaptureArea) -> f64 {
+    let area = intersection(a, b);
+    area.width * area.height
+}
+
+fn intersection(a: &CaptureArea, b: &CaptureArea) -> CaptureArea {
+    let left = a.left.max(b.left);
+    let top = a.top.max(b.top);
+
+    CaptureArea {
+        left,
+        top,
+        width: ((a.left + a.width).min(b.left + b.width) - left).max(0.0),
+        height: ((a.top + a.height).min(b.top + b.height) - top).max(0.0),
+    }
+}
+
+/// The display showing the most of a window, `None` when it's on none of
+/// them, like a minimized window on Windows.
+pub fn display_of_window<'a>(
+    window: &CaptureArea,
+    displays: &'a [(String, CaptureArea)],
+) -> Option<&'a (String, CaptureArea)> {
+    displays
+        .iter()
+        .map(|display| (display, overlap(window, &display.1)))
+        .filter(|(_, area)| *area > 0.0)
+        .max_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+        .map(|(display, _)| display)
+}
+
+/// Copies a rectangle out of a NV12 frame, the rectangle must lie inside.
+pub fn crop_frame(frame: &DesktopEncodeFrame, rect: &FrameRect) -> DesktopEncodeFrame {
+    let (left, top) = (rect.left as usize, rect.top as usize);
+    let (width, height) = (rect.width as usize, rect.height as usize);
+    let luminance_stride = frame.luminance_stride as usize;
+    let chrominance_stride = frame.chrominance_stride as usize;
+
+    let mut luminance_bytes = Vec::with_capacity(width * height);
+    for y in top..top + height {
+        let row = y * luminance_stride;
+        luminance_bytes.extend_from_slice(&frame.luminance_bytes[row + left..row + left + width]);
+    }
+
+    // interleaved UV pairs, one per 2x2 luma block, so a row is as wide in
+    // bytes as the luma row
+    let mut chrominance_bytes = Vec::with_capacity(width * height / 2);
+    for y in top / 2..(top + height) / 2 {
+        let row = y * chrominance_stride;
+        chrominance_bytes
+            .extend_from_slice(&frame.chrominance_bytes[row + left..row + left + width]);
+    }
+
+    DesktopEncodeFrame {
+        capture_time: frame.capture_time,
+        width: rect.width,
+        height: rect.height,
+        luminance_bytes,
+        luminance_stride: rect.width,
+        chrominance_bytes,
+        chrominance_stride: rect.width,
+        dirty_rects: frame
+            .dirty_rects
+            .as_ref()
+            .map(|rects| crop_dirty_rects(rects, rect)),
+    }
+}
+
+/// What became of the shared window since the last look.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WindowChange {
+    /// Still on the captured display, perhaps moved or resized.
+    Unchanged,
+    /// Moved over to another display, the capture has to switch to it.
+    Display(String),
+    /// Closed, the capture ends.
+    Closed,
+}
+
+/// Follows the shared window as it moves and resizes, and cuts it out of
+/// the frames captured from its display.
+#[derive(Debug)]
+pub struct WindowFollower {
+    window_id: u64,
+    bounds: CaptureArea,
+    visible: bool,
+    display_id: String,
+    display: CaptureArea,
+    polled_at: Instant,
+}
+
+impl WindowFollower {
+    /// `None` when the window isn't on any of the displays.
+    pub fn new(
+        window: &WindowInfo,
+        displays: &[(String, CaptureArea)],
+        now: Instant,
+    ) -> Option<Self> {
+        let bounds = window.bounds();
+        let (display_id, display) = display_of_window(&bounds, displays)?;
+
+        Some(Self {
+            window_id: window.id,
+            bounds,
+            visible: window.visible,
+            display_id: display_id.clone(),
+            display: *display,
+            polled_at: now,
+        })
+    }
+
+    pub fn window_id(&self) -> u64 {
+        self.window_id
+    }
+
+    /// The display to capture.
+    pub fn display_id(&self) -> &str {
+        &self.display_id
+    }
+
+    pub fn due(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.polled_at) >= WINDOW_POLL_INTERVAL
+    }
+
+    /// Takes where the window is now, `None` once it's closed. The displays
+    /// are listed only when the window left the captured one, which is
+    /// switched to the display showing the most of it.
+    pub fn update(
+        &mut self,
+        window: Option<&WindowInfo>,
+        displays: impl FnOnce() -> Vec<(String, CaptureArea)>,
+        now: Instant,
+    ) -> WindowChange {
+        self.polled_at = now;
+
+        let Some(window) = window else {
+            return WindowChange::Closed;
+        };
+
+        self.bounds = window.bounds();
+        self.visible = window.visible;
+
+        let center_x = self.bounds.left + self.bounds.width / 2.0;
+        let center_y = self.bounds.top + self.bounds.height / 2.0;
+        let on_display = (self.display.left..self.display.left + self.display.width)
+            .contains(&center_x)
+            && (self.display.top..self.display.top + self.display.height).contains(&center_y);
+
+        if on_display {
+            return WindowChange::Unchanged;
+        }
+
+        let displays = displays();
+        match display_of_window(&self.bounds, &displays) {
+            Some((display_id, display)) if *display_id != self.display_id => {
+                self.display_id = display_id.clone();
+                self.display = *display;
+                WindowChange::Display(display_id.clone())
+            }
+            _ => WindowChange::Unchanged,
+        }
+    }
+
+    /// The part of the window on its display, which the cut out frames show.
+    pub fn visible_area(&self) -> CaptureArea {
+        intersection(&self.bounds, &self.display)
+    }
+
+    /// Cuts the window out of a frame of its display. Privacy regions are
+    /// given in display coordinates, so they're masked before. `None` when
+    /// the window isn't on the frame, e.g. while it's minimized.
+    pub fn crop(&self, mut frame: DesktopEncodeFrame) -> Option<DesktopEncodeFrame> {
+        if !self.visible {
+            return None;
+        }
+
+        let rect = window_frame_rect(&self.bounds, &self.display, frame.width, frame.height)?;
+
+        for region in privacy_regions().iter() {
+            apply_privacy_region(&mut frame, region);
+        }
+
+        Some(crop_frame(&frame, &rect))
+    }
+}
+
+#[derive(Debug)]
+struct WindowPlacement {
+    monitor: Arc<Monitor>,
+    display: CaptureArea,
+    window: CaptureArea,
+}
+
+/// Where the capture last found the shared window, so the input of the
+/// viewer lands on the window rather than spreading over its whole display.
+/// Clones share the placement.
+#[derive(Debug, Default, Clone)]
+pub struct CapturedWindow(Arc<Mutex<Option<WindowPlacement>>>);
+
+impl CapturedWindow {
+    /// `monitor` is the display the follower captures.
+    pub fn set(&self, monitor: Arc<Monitor>, follower: &WindowFollower) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(WindowPlacement {
+            monitor,
+            display: follower.display,
+            window: follower.visible_area(),
+        });
+    }
+
+    /// The whole display is shared.
+    pub fn clear(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    /// Maps a position normalized to the window frames onto the display
+    /// showing the window, `None` while no window is shared.
+    pub fn to_display(&self, x: f32, y: f32) -> Option<(Arc<Monitor>, f32, f32)> {
+        let placement = self.0.lock().unwrap_or_else(PoisonError::into_inner);
+        let placement = placement.as_ref()?;
+
+        let (absolute_x, absolute_y) = placement.window.absolute_position(x, y);
+        let (x, y) = placement.display.relative_position(absolute_x, absolute_y);
+
+        Some((placement.monitor.clone(), x, y))
+    }
+
+    pub fn monitor(&self) -> Option<Arc<Monitor>> {
+        self.0
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .map(|placement| placement.monitor.clone())
+    }
+}
+
+#[cfg(any(target_os = "windows", target_os = "macos"))]
+impl WindowFollower {
+    /// Finds the window on the displays, `WindowClosed` when it doesn't
+    /// exist anymore.
+    pub fn locate(window_id: u64) -> crate::error::CoreResult<Self> {
+        let window = window_info(window_id).ok_or(crate::error::CoreError::WindowClosed)?;
+
+        Self::new(&window, &display_areas()?, Instant::now())
+            .ok_or_else(|| crate::core_error!("shared window is on no display"))
+    }
+
+    /// Looks the window up again once [`WINDOW_POLL_INTERVAL`] passed.
+    pub fn poll(&mut self) -> WindowChange {
+        let now = Instant::now();
+        if !self.due(now) {
+            return WindowChange::Unchanged;
+        }
+
+        let window = window_info(self.window_id);
+        self.update(window.as_ref(), || display_areas().unwrap_or_default(), now)
+    }
+}
@@ -0,0 +1,119 @@
+use super::WindowInfo;
+use crate::{
+    component::{desktop::monitor::get_active_monitors, input::coordinate::CaptureArea},
+    error::CoreResult,
+};
+use std::path::Path;
+use windows::{
+    core::PWSTR,
+    Win32::{
+        Foundation::{CloseHandle, BOOL, HWND, LPARAM, RECT},
+        System::Threading::{
+            OpenProcess, QueryFullProcessImageNameW, PROCESS_NAME_WIN32,
+            PROCESS_QUERY_LIMITED_INFORMATION,
+        },
+        UI::WindowsAndMessaging::{
+            EnumWindows, GetWindowLongW, GetWindowRect, GetWindowTextLengthW, GetWindowTextW,
+            GetWindowThreadProcessId, IsIconic, IsWindow, IsWindowVisible, GWL_EXSTYLE,
+            WS_EX_TOOLWINDOW,
+        },
+    },
+};
+
+/// The visible windows with a title, front to back.
+pub fn list_windows() -> CoreResult<Vec<WindowInfo>> {
+    unsafe extern "system" fn enum_window(hwnd: HWND, lparam: LPARAM) -> BOOL {
+        let windows = &mut *(lparam.0 as *mut Vec<WindowInfo>);
+
+        let tool_window = GetWindowLongW(hwnd, GWL_EXSTYLE) as u32 & WS_EX_TOOLWINDOW.0 != 0;
+
+        if IsWindowVisible(hwnd).as_bool() && !tool_window {
+            if let Some(window) = query_window(hwnd) {
+                if !window.title.is_empty() {
+                    windows.push(window);
+                }
+            }
+        }
+
+        true.into()
+    }
+
+    let mut windows = Vec::new();
+
+    unsafe {
+        EnumWindows(
+            Some(enum_window),
+            LPARAM(&mut windows as *mut Vec<WindowInfo> as isize),
+        );
+    }
+
+    Ok(windows)
+}
+
+/// `None` once the window is closed.
+pub fn window_info(window_id: u64) -> Option<WindowInfo> {
+    let hwnd = HWND(window_id as isize);
+
+    unsafe {
+        if !IsWindow(hwnd).as_bool() {
+            return None;
+        }
+
+        query_window(hwnd)
+    }
+}
+
+pub fn display_areas() -> CoreResult<Vec<(String, CaptureArea)>> {
+    Ok(get_active_monitors(false)?
+        .iter()
+        .map(|monitor| (monitor.id.clone(), CaptureArea::from(monitor)))
+        .collect())
+}
+
+unsafe fn query_window(hwnd: HWND) -> Option<WindowInfo> {
+    let mut rect = RECT::default();
+    if !GetWindowRect(hwnd, &mut rect).as_bool() {
+        return None;
+    }
+
+    let mut title = vec![0u16; GetWindowTextLengthW(hwnd).max(0) as usize + 1];
+    let title_len = GetWindowTextW(hwnd, &mut title).max(0) as usize;
+
+    let mut owner_pid = 0u32;
+    GetWindowThreadProcessId(hwnd, Some(&mut owner_pid));
+
+    Some(WindowInfo {
+        id: hwnd.0 as u64,
+        title: String::from_utf16_lossy(&title[..title_len]),
+        owner_process: process_name(owner_pid).unwrap_or_default(),
+        owner_pid,
+        left: rect.left,
+        top: rect.top,
+        width: rect.right - rect.left,
+        height: rect.bottom - rect.top,
+        visible: IsWindowVisible(hwnd).as_bool() && !IsIconic(hwnd).as_bool(),
+    })
+}
+
+unsafe fn process_name(pid: u32) -> Option<String> {
+    let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, false, pid).ok()?;
+
+    let mut path = vec![0u16; 1024];
+    let mut path_len = path.len() as u32;
+    let queried = QueryFullProcessImageNameW(
+        process,
+        PROCESS_NAME_WIN32,
+        PWSTR(path.as_mut_ptr()),
+        &mut path_len,
+    );
+    CloseHandle(process);
+
+    if !queried.as_bool() {
+        return None;
+    }
+
+    let path = String::from_utf16_lossy(&path[..path_len as usize]);
+    Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
@@ -5,7 +5,13 @@ use super::{
 };
 use crate::{
     component::{
-        desktop::windows::dx_math::{BPP, VERTEX},
+        desktop::{
+            capture_mode::{
+                clip_dirty_rect, CaptureMode, CAPTURE_WAIT_TIMEOUT, POLLING_CAPTURE_INTERVAL,
+            },
+            window::FrameRect,
+            windows::dx_math::{BPP, VERTEX},
+        },
         frame::DesktopEncodeFrame,
     },
     core_error,
@@ -13,18 +19,18 @@ use crate::{
     HRESULT,
 };
 use scopeguard::defer;
-use std::os::raw::c_void;
+use std::{os::raw::c_void, time::Instant};
 use tracing::info;
 use windows::{
     core::{Interface, PCSTR, PCWSTR},
     Win32::{
+        Foundation::RECT,
         Graphics::{
             Direct3D::*,
             Direct3D11::*,
             Dxgi::{Common::*, *},
             Gdi::*,
         },
-        System::WindowsProgramming::INFINITE,
         UI::WindowsAndMessaging::*,
     },
 };
@@ -65,14 +71,32 @@ pub struct Duplicator {
     mouse_visible: bool,
     mouse_shape_buffer: Vec<u8>,
     mouse_shape_info: DXGI_OUTDUPL_POINTER_SHAPE_INFO,
+    include_cursor: bool,
+    /// Where the pointer was drawn onto the last frame.
+    cursor_rect: Option<FrameRect>,
+
+    capture_mode: CaptureMode,
+    /// Whether the backend texture holds a desktop image yet.
+    captured: bool,
 
     epoch: once_cell::unsync::OnceCell<std::time::Instant>,
 }
 
+/// What came of waiting for the desktop to change.
+enum Acquired {
+    Unchanged,
+    /// The backend texture holds a new image, with the rectangles that
+    /// changed when DXGI told.
+    Changed(Option<Vec<FrameRect>>),
+}
+
 unsafe impl Send for Duplicator {}
 
 impl Duplicator {
-    pub fn new(monitor_id: Option<String>) -> CoreResult<(Duplicator, String)> {
+    pub fn new(
+        monitor_id: Option<String>,
+        include_cursor: bool,
+    ) -> CoreResult<(Duplicator, String)> {
         unsafe {
             prepare_desktop()?;
 
@@ -142,6 +166,10 @@ impl Duplicator {
                     mouse_visible: false,
                     mouse_shape_buffer: Vec::new(),
                     mouse_shape_info: std::mem::zeroed(),
+                    include_cursor,
+                    cursor_rect: None,
+                    capture_mode: CaptureMode::EventDriven,
+                    captured: false,
                     epoch: once_cell::unsync::OnceCell::new(),
                 },
                 monitor_id,
@@ -149,38 +177,81 @@ impl Duplicator {
         }
     }
 
-    pub fn capture(&mut self) -> CoreResult<DesktopEncodeFrame> {
+    /// DXGI hands the pointer over separately from the desktop image, so it's
+    /// only in the frame when it's drawn onto the backend texture.
+    pub fn set_include_cursor(&mut self, include_cursor: bool) {
+        self.include_cursor = include_cursor;
+    }
+
+    /// DXGI reports the rectangles that changed along with every frame,
+    /// unless its metadata can't be read, then frames are polled.
+    pub fn capture_mode(&self) -> CaptureMode {
+        self.capture_mode
+    }
+
+    /// The next frame, `None` when the desktop didn't change for a while.
+    pub fn capture(&mut self) -> CoreResult<Option<DesktopEncodeFrame>> {
         unsafe {
-            if let Err(err) = self.acquire_frame() {
-                if let CoreError::HResultError {
-                    ref error,
-                    file: _,
-                    line: _,
-                } = err
-                {
-                    if error.code() == DXGI_ERROR_ACCESS_LOST {
-                        // todo: re-init dxig
-                        tracing::warn!("DXGI ACCESS LOST");
+            let dirty_rects = match self.acquire_frame() {
+                Ok(Acquired::Changed(dirty_rects)) => dirty_rects,
+                Ok(Acquired::Unchanged) => return Ok(None),
+                Err(err) => {
+                    if let CoreError::HResultError {
+                        ref error,
+                        file: _,
+                        line: _,
+                    } = err
+                    {
+                        if error.code() == DXGI_ERROR_ACCESS_LOST {
+                            // the desktop switched or the display mode
+                            // changed, the capture loop creates the
+                            // duplicator again
+                            tracing::warn!("DXGI ACCESS LOST");
+                        }
                     }
+                    return Err(err);
                 }
-                return Err(err);
-            }
+            };
 
             self.draw_lumina_and_chrominance_texture()?;
-            self.create_capture_frame()
+            let mut capture_frame = self.create_capture_frame()?;
+            capture_frame.dirty_rects = dirty_rects;
+            Ok(Some(capture_frame))
         }
     }
 
-    unsafe fn acquire_frame(&mut self) -> CoreResult<()> {
+    unsafe fn acquire_frame(&mut self) -> CoreResult<Acquired> {
         let mut dxgi_resource = None;
         let mut dxgi_outdupl_frame_info = std::mem::zeroed();
 
+        let deadline = Instant::now()
+            + match self.capture_mode {
+                CaptureMode::EventDriven => CAPTURE_WAIT_TIMEOUT,
+                CaptureMode::Polling => POLLING_CAPTURE_INTERVAL,
+            };
+
         loop {
-            HRESULT!(self.duplication.AcquireNextFrame(
-                INFINITE,
+            let timeout = deadline.saturating_duration_since(Instant::now());
+
+            if let Err(err) = self.duplication.AcquireNextFrame(
+                timeout.as_millis() as u32,
                 &mut dxgi_outdupl_frame_info,
                 &mut dxgi_resource,
-            ));
+            ) {
+                if err.code() != DXGI_ERROR_WAIT_TIMEOUT {
+                    return Err(CoreError::HResultError {
+                        error: err,
+                        file: file!().to_string(),
+                        line: line!().to_string(),
+                    });
+                }
+
+                return Ok(match self.capture_mode {
+                    // the backend texture still holds the last image
+                    CaptureMode::Polling if self.captured => Acquired::Changed(None),
+                    _ => Acquired::Unchanged,
+                });
+            }
 
             self.update_mouse(&dxgi_outdupl_frame_info)?;
 
@@ -192,19 +263,96 @@ impl Duplicator {
             break;
         }
 
+        let mut dirty_rects = match self.capture_mode {
+            CaptureMode::EventDriven => match self.frame_dirty_rects(&dxgi_outdupl_frame_info) {
+                Ok(dirty_rects) => Some(dirty_rects),
+                Err(err) => {
+                    tracing::warn!(?err, "read frame dirty rects failed, poll the desktop");
+                    self.capture_mode = CaptureMode::Polling;
+                    None
+                }
+            },
+            CaptureMode::Polling => None,
+        };
+
+        // the pointer is gone from where it was drawn, and drawn anew
+        let previous_cursor_rect = self.cursor_rect.take();
+
         if let Some(resource) = dxgi_resource {
             let desktop_texture: ID3D11Texture2D = HRESULT!(resource.cast());
 
             self.device_context
                 .CopyResource(&self.backend_texture, &desktop_texture);
 
-            // if self.mouse_visible {
-            //     self.draw_mouse()?;
-            // }
+            if self.include_cursor && self.mouse_visible && !self.mouse_shape_buffer.is_empty() {
+                if let Err(err) = self.draw_mouse() {
+                    tracing::warn!(?err, "draw mouse failed");
+                }
+            }
         }
 
         HRESULT!(self.duplication.ReleaseFrame());
-        Ok(())
+        self.captured = true;
+
+        if let Some(ref mut dirty_rects) = dirty_rects {
+            dirty_rects.extend(previous_cursor_rect);
+            dirty_rects.extend(self.cursor_rect);
+        }
+
+        Ok(Acquired::Changed(dirty_rects))
+    }
+
+    /// The rectangles of the acquired frame that changed, moved ones by
+    /// where they went. A frame without metadata changed as a whole.
+    unsafe fn frame_dirty_rects(
+        &self,
+        frame_info: &DXGI_OUTDUPL_FRAME_INFO,
+    ) -> CoreResult<Vec<FrameRect>> {
+        let width = self.dxgi_outdupl_desc.ModeDesc.Width as i32;
+        let height = self.dxgi_outdupl_desc.ModeDesc.Height as i32;
+        let buffer_size = frame_info.TotalMetadataBufferSize as usize;
+
+        if buffer_size == 0 {
+            return Ok(vec![FrameRect {
+                left: 0,
+                top: 0,
+                width,
+                height,
+            }]);
+        }
+
+        let mut move_rects: Vec<DXGI_OUTDUPL_MOVE_RECT> = Vec::with_capacity(
+            (buffer_size + std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>() - 1)
+                / std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>(),
+        );
+        let mut move_rects_size = 0;
+        HRESULT!(self.duplication.GetFrameMoveRects(
+            (move_rects.capacity() * std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>()) as u32,
+            move_rects.as_mut_ptr(),
+            &mut move_rects_size,
+        ));
+        move_rects
+            .set_len(move_rects_size as usize / std::mem::size_of::<DXGI_OUTDUPL_MOVE_RECT>());
+
+        let mut rects: Vec<RECT> = Vec::with_capacity(
+            (buffer_size + std::mem::size_of::<RECT>() - 1) / std::mem::size_of::<RECT>(),
+        );
+        let mut rects_size = 0;
+        HRESULT!(self.duplication.GetFrameDirtyRects(
+            (rects.capacity() * std::mem::size_of::<RECT>()) as u32,
+            rects.as_mut_ptr(),
+            &mut rects_size,
+        ));
+        rects.set_len(rects_size as usize / std::mem::size_of::<RECT>());
+
+        Ok(move_rects
+            .iter()
+            .map(|move_rect| move_rect.DestinationRect)
+            .chain(rects)
+            .filter_map(|rect| {
+                clip_dirty_rect(rect.left, rect.top, rect.right, rect.bottom, width, height)
+            })
+            .collect())
     }
 
     unsafe fn draw_lumina_and_chrominance_texture(&self) -> CoreResult<()> {
@@ -502,6 +650,15 @@ impl Duplicator {
         vertices[5].pos.x = (pointer_left + pointer_width - center_x) as f32 / center_x as f32;
         vertices[5].pos.y = -1f32 * (pointer_top - center_y) as f32 / center_y as f32;
 
+        self.cursor_rect = clip_dirty_rect(
+            pointer_left,
+            pointer_top,
+            pointer_left + pointer_width,
+            pointer_top + pointer_height,
+            desktop_width as i32,
+            desktop_height as i32,
+        );
+
         pointer_texture_desc.Width = pointer_width as u32;
         if pointer_texture_desc.Width == 0 {
             tracing::error!("pointer width == 0, {:?}", pointer_width);
@@ -589,6 +746,13 @@ impl Duplicator {
 
         self.device_context.Draw(VERTICES.len() as u32, 0);
 
+        // the NV12 conversion passes must not blend with the previous content
+        self.device_context.OMSetBlendState(
+            None::<&ID3D11BlendState>,
+            Some(blend_factor.as_ptr()),
+            0xFFFFFFFF,
+        );
+
         // self.device_context.Flush();
 
         Ok(())
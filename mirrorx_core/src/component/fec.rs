@@ -0,0 +1,253 @@
+use crate::api::endpoint::message::{EndPointVideoFrame, EndPointVideoFrameParity};
+use std::collections::BTreeMap;
+
+pub const MAX_FEC_LEVEL: u8 = 3;
+
+/// Returns how many frames one parity frame covers at a `fec_level`, or
+/// `None` when FEC is off. One parity frame is about as large as the largest
+/// frame of its group, so the bandwidth overhead is roughly `1 / group size`.
+///
+/// | level | group size | overhead | restores        |
+/// |-------|------------|----------|-----------------|
+/// | 0     | -          | 0        | nothing         |
+/// | 1     | 8          | ~13%     | 1 of 8 frames   |
+/// | 2     | 4          | ~25%     | 1 of 4 frames   |
+/// | 3     | 2          | ~50%     | 1 of 2 frames   |
+pub fn fec_group_size(fec_level: u8) -> Option<u32> {
+    match fec_level {
+        0 => None,
+        1 => Some(8),
+        2 => Some(4),
+        _ => Some(2),
+    }
+}
+
+/// Builds the parity frame of every group of outgoing frames.
+#[derive(Debug)]
+pub struct FecEncoder {
+    group_size: u32,
+    parity: Option<EndPointVideoFrameParity>,
+}
+
+impl FecEncoder {
+    pub fn new(fec_level: u8) -> Option<Self> {
+        fec_group_size(fec_level).map(|group_size| FecEncoder {
+            group_size,
+            parity: None,
+        })
+    }
+
+    /// Adds a frame to the current group, returns the parity frame once the
+    /// group is complete. Frames must be pushed in `seq` order.
+    pub fn push(&mut self, frame: &EndPointVideoFrame) -> Option<EndPointVideoFrameParity> {
+        let parity = self.parity.get_or_insert_with(|| EndPointVideoFrameParity {
+            first_seq: frame.seq,
+            count: 0,
+            width: 0,
+            height: 0,
+            pts: 0,
+            length: 0,
+            buffer: Vec::new(),
+        });
+
+        xor_frame(parity, frame);
+        parity.count += 1;
+
+        if parity.count >= self.group_size {
+            self.parity.take()
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum FecEvent {
+    Frame(EndPointVideoFrame),
+    /// The frame of this `seq` was lost and rebuilt from the parity of its
+    /// group, it is delivered as `Frame` in its turn.
+    Recovered(u64),
+    /// Frames were lost for good, decoding has to resume from a keyframe.
+    Lost(u64),
+}
+
+/// Puts incoming frames back in order and restores lost ones from parity
+/// frames. Frames after a gap are held until the parity of the gap arrives,
+/// so a stream without loss passes through without delay.
+#[derive(Debug)]
+pub struct FecDecoder {
+    group_size: Option<u32>,
+    next_seq: Option<u64>,
+    /// Held frames, and with FEC on the recently delivered ones the parity
+    /// of a later gap is computed with.
+    frames: BTreeMap<u64, EndPointVideoFrame>,
+}
+
+impl FecDecoder {
+    pub fn new(fec_level: u8) -> Self {
+        FecDecoder {
+            group_size: fec_group_size(fec_level),
+            next_seq: None,
+            frames: BTreeMap::new(),
+        }
+    }
+
+    pub fn push_frame(&mut self, frame: EndPointVideoFrame) -> Vec<FecEvent> {
+        let mut events = Vec::new();
+        let next_seq = *self.next_seq.get_or_insert(frame.seq);
+
+        if frame.seq < next_seq || self.frames.contains_key(&frame.seq) {
+            return events;
+        }
+
+        self.frames.insert(frame.seq, frame);
+        self.drain(&mut events);
+
+        // without parity on the way, or once it is overdue, the gap is lost
+        let max_held = self
+            .group_size
+            .map_or(0, |group_size| group_size as usize * 2);
+        if self.held_count() > max_held {
+            self.skip_gap(&mut events);
+        }
+
+        events
+    }
+
+    pub fn push_parity(&mut self, parity: EndPointVideoFrameParity) -> Vec<FecEvent> {
+        let mut events = Vec::new();
+        let Some(next_seq) = self.next_seq else {
+            return events;
+        };
+
+        let end_seq = parity.first_seq + parity.count as u64;
+        if end_seq <= next_seq {
+            return events;
+        }
+
+        let missing: Vec<u64> = (parity.first_seq..end_seq)
+            .filter(|seq| !self.frames.contains_key(seq))
+            .collect();
+
+        match missing.as_slice() {
+            [] => {}
+            [seq] if *seq >= next_seq => {
+                let frame = self.restore(parity, *seq);
+                self.frames.insert(*seq, frame);
+                events.push(FecEvent::Recovered(*seq));
+            }
+            _ => {
+                let lost = (next_seq..end_seq)
+                    .filter(|seq| !self.frames.contains_key(seq))
+                    .count() as u64;
+
+                if lost > 0 {
+                    events.push(FecEvent::Lost(lost));
+
+                    // deliver what survived of the group, a keyframe among
+                    // them lets decoding resume right away
+                    for seq in next_seq..end_seq {
+                        if let Some(frame) = self.take_frame(seq) {
+                            events.push(FecEvent::Frame(frame));
+                        }
+                    }
+
+                    self.next_seq = Some(end_seq);
+                }
+            }
+        }
+
+        self.drain(&mut events);
+        events
+    }
+
+    fn restore(&self, parity: EndPointVideoFrameParity, seq: u64) -> EndPointVideoFrame {
+        let end_seq = parity.first_seq + parity.count as u64;
+        let mut parity = parity;
+
+        for frame in self
+            .frames
+            .range(parity.first_seq..end_seq)
+            .map(|(_, frame)| frame)
+        {
+            xor_frame(&mut parity, frame);
+        }
+
+        parity.buffer.truncate(parity.length as usize);
+
+        EndPointVideoFrame {
+            seq,
+            width: parity.width,
+            height: parity.height,
+            pts: parity.pts,
+            buffer: parity.buffer,
+        }
+    }
+
+    /// Payload of the held frames, including the ones kept for parity.
+    pub fn held_bytes(&self) -> u64 {
+        self.frames
+            .values()
+            .map(|frame| frame.buffer.len() as u64)
+            .sum()
+    }
+
+    fn held_count(&self) -> usize {
+        self.next_seq
+            .map_or(0, |next_seq| self.frames.range(next_seq..).count())
+    }
+
+    fn skip_gap(&mut self, events: &mut Vec<FecEvent>) {
+        let Some(next_seq) = self.next_seq else {
+            return;
+        };
+
+        if let Some(first_held) = self.frames.range(next_seq..).next().map(|(seq, _)| *seq) {
+            events.push(FecEvent::Lost(first_held - next_seq));
+            self.next_seq = Some(first_held);
+            self.drain(events);
+        }
+    }
+
+    fn drain(&mut self, events: &mut Vec<FecEvent>) {
+        let Some(mut next_seq) = self.next_seq else {
+            return;
+        };
+
+        while let Some(frame) = self.take_frame(next_seq) {
+            events.push(FecEvent::Frame(frame));
+            next_seq += 1;
+        }
+
+        self.next_seq = Some(next_seq);
+
+        // a parity references at most a group of frames before the gap
+        let window = self.group_size.unwrap_or(0) as u64;
+        self.frames = self.frames.split_off(&next_seq.saturating_sub(window));
+    }
+
+    fn take_frame(&mut self, seq: u64) -> Option<EndPointVideoFrame> {
+        if self.group_size.is_some() {
+            self.frames.get(&seq).cloned()
+        } else {
+            self.frames.remove(&seq)
+        }
+    }
+}
+
+fn xor_frame(parity: &mut EndPointVideoFrameParity, frame: &EndPointVideoFrame) {
+    parity.width ^= frame.width;
+    parity.height ^= frame.height;
+    parity.pts ^= frame.pts;
+    parity.length ^= frame.buffer.len() as u64;
+
+    if parity.buffer.len() < frame.buffer.len() {
+        parity.buffer.resize(frame.buffer.len(), 0);
+    }
+
+    parity
+        .buffer
+        .iter_mut()
+        .zip(frame.buffer.iter())
+        .for_each(|(parity, byte)| *parity ^= byte);
+}
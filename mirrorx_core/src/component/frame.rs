@@ -1,5 +1,6 @@
+use super::{desktop::window::FrameRect, video_decoder::budget::RenderCharge};
 use cpal::SampleFormat;
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
 
 pub struct DesktopEncodeFrame {
     pub capture_time: Duration,
@@ -9,6 +10,9 @@ pub struct DesktopEncodeFrame {
     pub luminance_stride: i32,
     pub chrominance_bytes: Vec<u8>,
     pub chrominance_stride: i32,
+    /// What changed since the previous frame as the system reported it,
+    /// `None` when it didn't tell and the whole frame may have changed.
+    pub dirty_rects: Option<Vec<FrameRect>>,
 }
 
 unsafe impl Send for DesktopEncodeFrame {}
@@ -27,6 +31,8 @@ pub struct DesktopDecodeFrame {
     pub plane_data: Vec<Vec<u8>>,
     pub line_sizes: Vec<i32>,
     pub format: DesktopDecodeFrameFormat,
+    /// Holds the frame in the media budget of its session until dropped.
+    pub budget_charge: Option<Arc<RenderCharge>>,
 }
 
 pub struct AudioEncodeFrame {
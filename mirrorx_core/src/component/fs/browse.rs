@@ -0,0 +1,142 @@
+use super::safe_path::confine_path;
+use crate::{
+    core_error,
+    error::{CoreError, CoreResult},
+};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    io::ErrorKind,
+    path::{Component, Path, PathBuf},
+    sync::RwLock,
+};
+
+/// Upper bound of entries returned by one page, however many are requested.
+pub const MAX_PAGE_SIZE: u32 = 500;
+
+/// Whether remote devices may browse this device and which directory they
+/// see as their root. Browsing is refused until explicitly allowed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct FileBrowsePolicy {
+    pub allowed: bool,
+    pub root: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct DirectoryListingEntry {
+    pub name: String,
+    pub size: u64,
+    pub is_dir: bool,
+    pub is_symlink: bool,
+    pub modified_time: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
+pub struct DirectoryListing {
+    /// Path relative to the exposed root.
+    pub path: PathBuf,
+    pub entries: Vec<DirectoryListingEntry>,
+    /// Count of entries in the whole directory, not only this page.
+    pub total: u64,
+}
+
+static FILE_BROWSE_POLICY: Lazy<RwLock<FileBrowsePolicy>> =
+    Lazy::new(|| RwLock::new(FileBrowsePolicy::default()));
+
+pub fn set_file_browse_policy(policy: FileBrowsePolicy) {
+    if let Ok(mut file_browse_policy) = FILE_BROWSE_POLICY.write() {
+        *file_browse_policy = policy;
+    }
+}
+
+pub fn file_browse_policy() -> FileBrowsePolicy {
+    FILE_BROWSE_POLICY
+        .read()
+        .map(|policy| policy.clone())
+        .unwrap_or_default()
+}
+
+/// Lists one page of a directory under `root`. Entries are sorted with
+/// directories first so pages stay stable between requests.
+pub fn list_directory(
+    root: &Path,
+    path: &Path,
+    offset: u32,
+    limit: u32,
+) -> CoreResult<DirectoryListing> {
+    let dir_path = resolve_browse_path(root, path)?;
+
+    let dir = std::fs::read_dir(&dir_path).map_err(|err| match err.kind() {
+        ErrorKind::PermissionDenied => core_error!("permission denied"),
+        _ => err.into(),
+    })?;
+
+    let mut entries = Vec::new();
+    for entry in dir {
+        let Ok(entry) = entry else {
+            continue;
+        };
+
+        let Ok(symlink_meta) = entry.metadata() else {
+            continue;
+        };
+
+        let is_symlink = symlink_meta.file_type().is_symlink();
+
+        // describe the symlink target, or the link itself when it's broken
+        let meta = if is_symlink {
+            std::fs::metadata(entry.path()).unwrap_or(symlink_meta)
+        } else {
+            symlink_meta
+        };
+
+        let modified_time = meta
+            .modified()
+            .map(|time| chrono::DateTime::<chrono::Local>::from(time).timestamp())
+            .unwrap_or_default();
+
+        entries.push(DirectoryListingEntry {
+            name: entry.file_name().to_string_lossy().to_string(),
+            size: meta.len(),
+            is_dir: meta.is_dir(),
+            is_symlink,
+            modified_time,
+        });
+    }
+
+    entries.sort_by(|a, b| b.is_dir.cmp(&a.is_dir).then_with(|| a.name.cmp(&b.name)));
+
+    let total = entries.len() as u64;
+    let entries = entries
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit.min(MAX_PAGE_SIZE) as usize)
+        .collect();
+
+    Ok(DirectoryListing {
+        path: path.to_path_buf(),
+        entries,
+        total,
+    })
+}
+
+/// Joins a path relative to the root, refusing anything that ends up outside
+/// of it, including through symlinks.
+fn resolve_browse_path(root: &Path, path: &Path) -> CoreResult<PathBuf> {
+    let root = root.canonicalize()?;
+
+    let joined = if path
+        .components()
+        .all(|component| component == Component::CurDir)
+    {
+        root.clone()
+    } else {
+        confine_path(&root, path)?
+    };
+
+    if !joined.canonicalize()?.starts_with(&root) {
+        return Err(CoreError::UnsafePath(path.to_path_buf()));
+    }
+
+    Ok(joined)
+}
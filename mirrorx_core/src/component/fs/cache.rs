@@ -0,0 +1,258 @@
+use crate::{core_error, error::CoreResult};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::RwLock,
+    time::SystemTime,
+};
+
+pub const DEFAULT_CACHE_MAX_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Smallest budget, below it a single frame dump wouldn't fit.
+pub const MIN_CACHE_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+static CACHE_CONFIG: Lazy<RwLock<CacheConfig>> = Lazy::new(|| RwLock::new(CacheConfig::default()));
+
+static DEFAULT_CACHE_ROOT: Lazy<RwLock<Option<PathBuf>>> = Lazy::new(|| RwLock::new(None));
+
+/// Files written right now, keyed by path with the count of their leases.
+static CACHE_LEASES: Lazy<DashMap<PathBuf, usize>> = Lazy::new(DashMap::new);
+
+/// Where the files features write on their own go, like frame dumps, and how
+/// much they may take. The settings database lives elsewhere and is never
+/// counted nor evicted.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CacheConfig {
+    /// `None` uses the cache directory of the app.
+    pub root: Option<PathBuf>,
+    /// Once the files take more, the least recently used ones are removed.
+    pub max_bytes: u64,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            root: None,
+            max_bytes: DEFAULT_CACHE_MAX_BYTES,
+        }
+    }
+}
+
+impl CacheConfig {
+    pub fn sanitize(self) -> Self {
+        Self {
+            root: self.root.filter(|root| !root.as_os_str().is_empty()),
+            max_bytes: self.max_bytes.max(MIN_CACHE_MAX_BYTES),
+        }
+    }
+}
+
+/// Applies to files written afterwards, the files under an earlier root stay
+/// where they are.
+pub fn set_cache_config(config: CacheConfig) {
+    if let Ok(mut cache_config) = CACHE_CONFIG.write() {
+        *cache_config = config.sanitize();
+    }
+}
+
+pub fn cache_config() -> CacheConfig {
+    CACHE_CONFIG
+        .read()
+        .map(|config| config.clone())
+        .unwrap_or_default()
+}
+
+/// The cache directory of the app, used while no root is configured.
+pub fn set_default_cache_root(root: PathBuf) {
+    if let Ok(mut default_cache_root) = DEFAULT_CACHE_ROOT.write() {
+        *default_cache_root = Some(root);
+    }
+}
+
+/// `None` before the app told its cache directory and none is configured.
+pub fn cache_root() -> Option<PathBuf> {
+    cache_config().root.or_else(|| {
+        DEFAULT_CACHE_ROOT
+            .read()
+            .map(|root| root.clone())
+            .unwrap_or_default()
+    })
+}
+
+/// Keeps a file from being evicted while it's written, until dropped.
+#[derive(Debug)]
+pub struct CacheLease {
+    path: PathBuf,
+}
+
+impl CacheLease {
+    pub fn new(path: PathBuf) -> Self {
+        *CACHE_LEASES.entry(path.clone()).or_insert(0) += 1;
+        Self { path }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CacheLease {
+    fn drop(&mut self) {
+        CACHE_LEASES.remove_if_mut(&self.path, |_, count| {
+            *count -= 1;
+            *count == 0
+        });
+    }
+}
+
+pub fn is_leased(path: &Path) -> bool {
+    CACHE_LEASES.contains_key(path)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CacheUsage {
+    pub root: Option<PathBuf>,
+    pub bytes: u64,
+    pub files: u64,
+    pub max_bytes: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CacheEviction {
+    pub removed_files: u64,
+    pub freed_bytes: u64,
+    /// Bytes of files in use, kept even over the budget.
+    pub leased_bytes: u64,
+}
+
+#[derive(Debug)]
+struct CacheFile {
+    path: PathBuf,
+    size: u64,
+    used_at: SystemTime,
+}
+
+/// Every file under `root`, a missing root holds none.
+fn list_cache_files(root: &Path) -> CoreResult<Vec<CacheFile>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+
+    while let Some(dir) = dirs.pop() {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            // symlinks aren't followed, they may point out of the cache
+            let metadata = entry.metadata()?;
+
+            if metadata.is_dir() {
+                dirs.push(entry.path());
+            } else if metadata.is_file() {
+                // access times are often off or coarse, a write counts too
+                let used_at = [metadata.accessed(), metadata.modified()]
+                    .into_iter()
+                    .flatten()
+                    .max()
+                    .unwrap_or(SystemTime::UNIX_EPOCH);
+
+                files.push(CacheFile {
+                    path: entry.path(),
+                    size: metadata.len(),
+                    used_at,
+                });
+            }
+        }
+    }
+
+    Ok(files)
+}
+
+pub fn cache_usage_of(root: &Path, max_bytes: u64) -> CoreResult<CacheUsage> {
+    let files = list_cache_files(root)?;
+
+    Ok(CacheUsage {
+        root: Some(root.to_path_buf()),
+        bytes: files.iter().map(|file| file.size).sum(),
+        files: files.len() as u64,
+        max_bytes,
+    })
+}
+
+/// Removes the least recently used files under `root` until the rest fits
+/// in `max_bytes`, files with a lease are kept.
+pub fn evict_cache_of(root: &Path, max_bytes: u64) -> CoreResult<CacheEviction> {
+    let mut files = list_cache_files(root)?;
+    let mut total: u64 = files.iter().map(|file| file.size).sum();
+    let mut eviction = CacheEviction::default();
+
+    files.sort_by_key(|file| file.used_at);
+
+    for file in files {
+        if total <= max_bytes {
+            break;
+        }
+
+        if is_leased(&file.path) {
+            eviction.leased_bytes += file.size;
+            continue;
+        }
+
+        match std::fs::remove_file(&file.path) {
+            Ok(_) => {
+                total -= file.size;
+                eviction.removed_files += 1;
+                eviction.freed_bytes += file.size;
+            }
+            Err(err) => tracing::warn!(?err, path = ?file.path, "evict cache file failed"),
+        }
+    }
+
+    if eviction.removed_files > 0 {
+        tracing::info!(?eviction, "cache evicted");
+    }
+
+    Ok(eviction)
+}
+
+pub fn cache_usage() -> CoreResult<CacheUsage> {
+    let max_bytes = cache_config().max_bytes;
+    match cache_root() {
+        Some(root) => cache_usage_of(&root, max_bytes),
+        None => Ok(CacheUsage {
+            root: None,
+            bytes: 0,
+            files: 0,
+            max_bytes,
+        }),
+    }
+}
+
+/// Brings the cache back into its budget, call it after writing to it.
+pub fn enforce_cache_budget() -> CoreResult<CacheEviction> {
+    match cache_root() {
+        Some(root) => evict_cache_of(&root, cache_config().max_bytes),
+        None => Ok(CacheEviction::default()),
+    }
+}
+
+/// Removes every file of the cache not in use.
+pub fn clear_cache() -> CoreResult<CacheEviction> {
+    match cache_root() {
+        Some(root) => evict_cache_of(&root, 0),
+        None => Ok(CacheEviction::default()),
+    }
+}
+
+/// A directory of the cache for one kind of files, created when missing.
+pub fn cache_dir(name: &str) -> CoreResult<PathBuf> {
+    let root = cache_root().ok_or_else(|| core_error!("cache directory unknown"))?;
+    let dir = root.join(name);
+    std::fs::create_dir_all(&dir)?;
+    Ok(dir)
+}
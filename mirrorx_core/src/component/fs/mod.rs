@@ -4,7 +4,11 @@ mod macos;
 #[cfg(target_os = "windows")]
 mod windows;
 
+pub mod browse;
+pub mod cache;
+pub mod safe_path;
 pub mod transfer;
+pub mod window;
 
 use crate::error::CoreResult;
 use rayon::prelude::*;
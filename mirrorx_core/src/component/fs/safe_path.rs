@@ -0,0 +1,47 @@
+use crate::error::{CoreError, CoreResult};
+use std::path::{Component, Path, PathBuf};
+
+/// Joins a path received from the remote side onto a local root. The remote
+/// path must be relative and free of `..`, and may not carry characters that
+/// turn into drive letters or separators on Windows whatever platform this
+/// is, so the result never leaves the root.
+pub fn confine_path(root: &Path, relative: &Path) -> CoreResult<PathBuf> {
+    let mut confined = root.to_path_buf();
+    let mut depth = 0;
+
+    for component in relative.components() {
+        match component {
+            Component::Normal(name) => {
+                let Some(name) = name.to_str() else {
+                    return Err(CoreError::UnsafePath(relative.to_path_buf()));
+                };
+
+                if name.contains(&['\\', '/', ':'][..]) {
+                    return Err(CoreError::UnsafePath(relative.to_path_buf()));
+                }
+
+                confined.push(name);
+                depth += 1;
+            }
+            Component::CurDir => {}
+            Component::ParentDir | Component::RootDir | Component::Prefix(_) => {
+                return Err(CoreError::UnsafePath(relative.to_path_buf()));
+            }
+        }
+    }
+
+    if depth == 0 {
+        return Err(CoreError::UnsafePath(relative.to_path_buf()));
+    }
+
+    Ok(confined)
+}
+
+/// Whether a path is absolute and has no `..` component, so what it names
+/// doesn't depend on how it's normalized.
+pub fn is_normalized_absolute_path(path: &Path) -> bool {
+    path.is_absolute()
+        && path
+            .components()
+            .all(|component| !matches!(component, Component::ParentDir))
+}
@@ -1,21 +1,109 @@
+use super::window::{
+    file_chunk_size, window_for, ReorderBuffer, SendWindow, FILE_ACK_TIMEOUT, INITIAL_WINDOW_CHUNKS,
+};
 use crate::{
     api::endpoint::{
         client::EndPointClient,
-        message::{EndPointFileTransferBlock, EndPointFileTransferError, EndPointMessage},
+        id::EndPointID,
+        message::{
+            EndPointFileTransferAck, EndPointFileTransferBlock, EndPointFileTransferError,
+            EndPointMessage, FileTransferCancelReason,
+        },
     },
-    error::CoreResult,
+    bail_core, core_error,
+    error::{CoreError, CoreResult},
 };
 use moka::future::{Cache, CacheBuilder};
 use once_cell::sync::Lazy;
-use std::{path::Path, sync::Arc, time::Duration};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    future::Future,
+    io::SeekFrom,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt, BufReader, BufWriter},
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    io::{AsyncRead, AsyncReadExt, AsyncSeekExt, AsyncWriteExt, BufReader, BufWriter},
+    sync::{
+        mpsc::{UnboundedReceiver, UnboundedSender},
+        watch,
+    },
 };
+use tokio_util::sync::CancellationToken;
+
+#[derive(Clone)]
+pub struct FileAppendSession {
+    endpoint_id: EndPointID,
+    tx: UnboundedSender<FileAppendCommand>,
+}
+
+#[derive(Clone)]
+pub struct FileSendSession {
+    endpoint_id: EndPointID,
+    cancel_token: CancellationToken,
+    acked_tx: Arc<watch::Sender<u64>>,
+    progress: Arc<ProgressReporter>,
+}
+
+enum FileAppendCommand {
+    Block { offset: u64, data: Vec<u8> },
+    Finish { offset: u64 },
+    Cancel { keep_partial: bool },
+    Interrupt,
+}
+
+/// What becomes of the partial file once the transfer stops.
+enum PartialFile {
+    Complete,
+    Keep,
+    Remove,
+    Resume,
+}
 
-pub static APPEND_FILES: Lazy<Cache<String, UnboundedSender<Option<Vec<u8>>>>> = Lazy::new(|| {
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTransferCancelledEvent {
+    pub id: String,
+    pub reason: FileTransferCancelReason,
+}
+
+/// A received file is on disk under its final name.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTransferCompleteEvent {
+    pub id: String,
+    pub path: PathBuf,
+}
+
+/// Bytes of a transfer on the receiving side out of the file size.
+#[derive(Debug, Clone, Serialize)]
+pub struct FileTransferProgressEvent {
+    pub id: String,
+    pub transferred: u64,
+    pub total: u64,
+}
+
+/// Suffix of the file a transfer writes to until it completes.
+pub const PARTIAL_FILE_SUFFIX: &str = ".mirrorx-part";
+
+/// Suffix of the file keeping the hash of the content a partial file
+/// belongs to, a later transfer resumes the partial file only for the same
+/// content.
+pub const PARTIAL_HASH_SUFFIX: &str = ".mirrorx-part-hash";
+
+/// The progress of a transfer is published at most this often.
+pub const FILE_TRANSFER_PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+// every block and ack looks the session up, only stalled transfers expire
+pub static APPEND_FILES: Lazy<Cache<String, FileAppendSession>> = Lazy::new(|| {
     CacheBuilder::new(64)
-        .time_to_live(Duration::from_secs(3 * 60))
+        .time_to_idle(Duration::from_secs(3 * 60))
+        .build()
+});
+
+pub static SEND_FILES: Lazy<Cache<String, FileSendSession>> = Lazy::new(|| {
+    CacheBuilder::new(64)
+        .time_to_idle(Duration::from_secs(3 * 60))
         .build()
 });
 
@@ -25,12 +113,141 @@ pub static BYTES_TRANSFERRED_CACHE: Lazy<Cache<String, u64>> = Lazy::new(|| {
         .build()
 });
 
-pub async fn create_file_append_session(id: String, path: &Path) -> CoreResult<()> {
+static FILE_TRANSFER_CANCELLED_TX: Lazy<
+    tokio::sync::broadcast::Sender<FileTransferCancelledEvent>,
+> = Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+static FILE_TRANSFER_COMPLETE_TX: Lazy<tokio::sync::broadcast::Sender<FileTransferCompleteEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+static FILE_TRANSFER_PROGRESS_TX: Lazy<tokio::sync::broadcast::Sender<FileTransferProgressEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(64).0);
+
+/// Publishes the progress of a transfer, at most every
+/// [`FILE_TRANSFER_PROGRESS_INTERVAL`] but always once it's done.
+#[derive(Debug)]
+pub struct ProgressReporter {
+    id: String,
+    total: u64,
+    reported_at: Mutex<Option<Instant>>,
+}
+
+impl ProgressReporter {
+    pub fn new(id: String, total: u64) -> Self {
+        Self {
+            id,
+            total,
+            reported_at: Mutex::new(None),
+        }
+    }
+
+    /// Publishes `transferred` if it's due, returns whether it was.
+    pub fn report(&self, transferred: u64, now: Instant) -> bool {
+        let mut reported_at = self
+            .reported_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+
+        let due = transferred >= self.total
+            || reported_at.is_none_or(|reported_at| {
+                now.saturating_duration_since(reported_at) >= FILE_TRANSFER_PROGRESS_INTERVAL
+            });
+
+        if !due {
+            return false;
+        }
+
+        *reported_at = Some(now);
+        drop(reported_at);
+
+        let _ = FILE_TRANSFER_PROGRESS_TX.send(FileTransferProgressEvent {
+            id: self.id.clone(),
+            transferred,
+            total: self.total,
+        });
+
+        true
+    }
+}
+
+pub fn subscribe_file_transfer_cancelled(
+) -> tokio::sync::broadcast::Receiver<FileTransferCancelledEvent> {
+    FILE_TRANSFER_CANCELLED_TX.subscribe()
+}
+
+pub fn subscribe_file_transfer_complete(
+) -> tokio::sync::broadcast::Receiver<FileTransferCompleteEvent> {
+    FILE_TRANSFER_COMPLETE_TX.subscribe()
+}
+
+pub fn subscribe_file_transfer_progress(
+) -> tokio::sync::broadcast::Receiver<FileTransferProgressEvent> {
+    FILE_TRANSFER_PROGRESS_TX.subscribe()
+}
+
+/// SHA-256 of the content of the file, read off the async runtime.
+pub async fn file_content_hash(path: &Path) -> CoreResult<Vec<u8>> {
+    let path = path.to_path_buf();
+
+    tokio::task::spawn_blocking(move || -> CoreResult<Vec<u8>> {
+        let mut file = std::fs::File::open(path)?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        Ok(hasher.finalize().to_vec())
+    })
+    .await
+    .unwrap_or_else(|err| Err(core_error!("hash file panicked ({})", err)))
+}
+
+/// Hash and length of what an earlier transfer to `path` left, `None` when
+/// there is nothing to resume.
+pub async fn partial_file_state(path: &Path) -> Option<(Vec<u8>, u64)> {
+    let hash = tokio::fs::read(partial_hash_path(path)).await.ok()?;
+    let len = tokio::fs::metadata(partial_file_path(path))
+        .await
+        .ok()?
+        .len();
+
+    Some((hash, len))
+}
+
+/// Offset a transfer of `size` bytes with content `hash` to `path` resumes
+/// at, zero when the earlier transfer was for other content.
+pub async fn resume_offset(path: &Path, hash: &[u8], size: u64) -> u64 {
+    match partial_file_state(path).await {
+        Some((partial_hash, len)) if partial_hash == hash && len <= size => len,
+        Some(_) => {
+            tracing::info!(?path, "file changed since the earlier transfer, start over");
+            0
+        }
+        None => 0,
+    }
+}
+
+/// Receives the file into its partial file, keeping the first `offset`
+/// bytes an earlier transfer of the same content wrote.
+pub async fn create_file_append_session(
+    client: Arc<EndPointClient>,
+    id: String,
+    path: &Path,
+    hash: &[u8],
+    offset: u64,
+    total: u64,
+) -> CoreResult<()> {
     let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
-    APPEND_FILES.insert(id.clone(), tx).await;
+    APPEND_FILES
+        .insert(
+            id.clone(),
+            FileAppendSession {
+                endpoint_id: client.endpoint_id(),
+                tx,
+            },
+        )
+        .await;
 
-    if let Err(err) = save_file_from_remote(id.clone(), path, rx).await {
+    if let Err(err) = save_file_from_remote(client, id.clone(), path, hash, offset, total, rx).await
+    {
         APPEND_FILES.invalidate(&id).await;
         return Err(err);
     }
@@ -43,8 +260,18 @@ pub async fn delete_file_append_session(id: &str) {
 }
 
 pub async fn append_file_block(client: Arc<EndPointClient>, block: EndPointFileTransferBlock) {
-    if let Some(tx) = APPEND_FILES.get(&block.id) {
-        match tx.send(block.data) {
+    if let Some(session) = APPEND_FILES.get(&block.id) {
+        let command = match block.data {
+            Some(data) => FileAppendCommand::Block {
+                offset: block.offset,
+                data,
+            },
+            None => FileAppendCommand::Finish {
+                offset: block.offset,
+            },
+        };
+
+        match session.tx.send(command) {
             Ok(_) => return,
             Err(_) => {
                 tracing::error!(id = block.id, "append file block channel failed");
@@ -61,37 +288,233 @@ pub async fn append_file_block(client: Arc<EndPointClient>, block: EndPointFileT
         .await;
 }
 
+/// Hands the ack of the receiver to the sending transfer, if it still runs.
+pub fn ack_file_block(ack: EndPointFileTransferAck) {
+    if let Some(session) = SEND_FILES.get(&ack.id) {
+        if advance_acked_offset(&session.acked_tx, ack.offset) {
+            session.progress.report(ack.offset, Instant::now());
+        }
+    }
+}
+
+/// Acks may overtake each other, only a later offset moves the transfer on
+/// and returns true.
+pub(crate) fn advance_acked_offset(acked_tx: &watch::Sender<u64>, offset: u64) -> bool {
+    acked_tx.send_if_modified(|acked_offset| {
+        if offset > *acked_offset {
+            *acked_offset = offset;
+            true
+        } else {
+            false
+        }
+    })
+}
+
+/// Cancel the local part of a transfer, whichever direction it is. Calling it
+/// again for the same id is a no-op and returns false. A received file
+/// interrupted by the session closing keeps its partial file to resume.
+pub async fn cancel_file_transfer(
+    id: &str,
+    reason: FileTransferCancelReason,
+    keep_partial: bool,
+) -> bool {
+    let mut cancelled = false;
+
+    if let Some(session) = SEND_FILES.get(id) {
+        SEND_FILES.invalidate(id).await;
+        session.cancel_token.cancel();
+        cancelled = true;
+    }
+
+    if let Some(session) = APPEND_FILES.get(id) {
+        APPEND_FILES.invalidate(id).await;
+        let command = if reason == FileTransferCancelReason::SessionClosed {
+            FileAppendCommand::Interrupt
+        } else {
+            FileAppendCommand::Cancel { keep_partial }
+        };
+        let _ = session.tx.send(command);
+        cancelled = true;
+    }
+
+    if cancelled {
+        tracing::info!(?id, ?reason, "file transfer cancelled");
+        let _ = FILE_TRANSFER_CANCELLED_TX.send(FileTransferCancelledEvent {
+            id: id.to_string(),
+            reason,
+        });
+    }
+
+    cancelled
+}
+
+pub async fn cancel_endpoint_file_transfers(endpoint_id: EndPointID) {
+    let mut ids: Vec<String> = SEND_FILES
+        .iter()
+        .filter(|(_, session)| session.endpoint_id == endpoint_id)
+        .map(|(id, _)| id.to_string())
+        .collect();
+
+    ids.extend(
+        APPEND_FILES
+            .iter()
+            .filter(|(_, session)| session.endpoint_id == endpoint_id)
+            .map(|(id, _)| id.to_string()),
+    );
+
+    for id in ids {
+        cancel_file_transfer(&id, FileTransferCancelReason::SessionClosed, false).await;
+    }
+}
+
 async fn save_file_from_remote(
+    client: Arc<EndPointClient>,
     id: String,
     path: &Path,
-    mut rx: UnboundedReceiver<Option<Vec<u8>>>,
+    hash: &[u8],
+    offset: u64,
+    total: u64,
+    mut rx: UnboundedReceiver<FileAppendCommand>,
 ) -> CoreResult<()> {
-    let file = tokio::fs::File::create(path).await?;
+    let partial_path = partial_file_path(path);
+    let hash_path = partial_hash_path(path);
+
+    let mut file = if offset > 0 {
+        let file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(&partial_path)
+            .await?;
+
+        if file.metadata().await?.len() < offset {
+            bail_core!("partial file is shorter than the resume offset");
+        }
+
+        // the earlier transfer may have written past the last ack
+        file.set_len(offset).await?;
+        file
+    } else {
+        tokio::fs::File::create(&partial_path).await?
+    };
+
+    file.seek(SeekFrom::Start(offset)).await?;
+    tokio::fs::write(&hash_path, hash).await?;
+
     let mut writer = BufWriter::new(file);
+    let path = path.to_path_buf();
+
+    BYTES_TRANSFERRED_CACHE.insert(id.clone(), offset).await;
 
     tokio::spawn(async move {
-        loop {
-            let Some(buffer) = rx.recv().await else {
+        let progress = ProgressReporter::new(id.clone(), total);
+        let mut reorder_buffer = ReorderBuffer::starting_at(offset);
+        let mut finish_offset = None;
+
+        // a transfer stopping without a cancel resumes later
+        let partial_file = loop {
+            if finish_offset == Some(reorder_buffer.next_offset()) {
+                break PartialFile::Complete;
+            }
+
+            let Some(command) = rx.recv().await else {
                 tracing::info!("exit write file");
-                break;
+                break PartialFile::Resume;
             };
 
-            match buffer {
-                Some(buffer) => {
-                    if let Err(err) = writer.write_all(&buffer).await {
+            match command {
+                FileAppendCommand::Block { offset, data } => {
+                    let blocks = match reorder_buffer.push(offset, data) {
+                        Ok(blocks) => blocks,
+                        Err(err) => {
+                            tracing::error!(?err, "reorder file block failed");
+                            let _ = client
+                                .send(&EndPointMessage::FileTransferError(
+                                    EndPointFileTransferError { id: id.clone() },
+                                ))
+                                .await;
+                            break PartialFile::Remove;
+                        }
+                    };
+
+                    if blocks.is_empty() {
+                        continue;
+                    }
+
+                    if let Err(err) = write_blocks(&mut writer, &id, blocks).await {
                         tracing::error!(?err, "write file has error occurred");
-                        break;
+                        break PartialFile::Resume;
                     }
 
-                    update_transferred_bytes_count(&id, buffer.len() as _).await;
+                    progress.report(reorder_buffer.next_offset(), Instant::now());
+
+                    let _ = client
+                        .send(&EndPointMessage::FileTransferAck(EndPointFileTransferAck {
+                            id: id.clone(),
+                            offset: reorder_buffer.next_offset(),
+                        }))
+                        .await;
+                }
+                FileAppendCommand::Finish { offset } => {
+                    // blocks overtaken by the end of the file may still come
+                    finish_offset = Some(offset);
                 }
-                None => {
-                    break;
+                FileAppendCommand::Cancel { keep_partial } => {
+                    break if keep_partial {
+                        PartialFile::Keep
+                    } else {
+                        PartialFile::Remove
+                    };
                 }
+                FileAppendCommand::Interrupt => break PartialFile::Resume,
             }
-        }
+        };
 
-        let _ = writer.flush().await;
+        match partial_file {
+            PartialFile::Complete => {
+                match finish_received_file(writer, &partial_path, &path).await {
+                    Ok(_) => {
+                        tracing::info!(?id, ?path, "file received");
+                        remove_partial_file(&hash_path).await;
+                        let _ = FILE_TRANSFER_COMPLETE_TX.send(FileTransferCompleteEvent {
+                            id: id.clone(),
+                            path,
+                        });
+                    }
+                    Err(err) => {
+                        tracing::error!(?id, ?err, "finish received file failed");
+                        let _ = client
+                            .send(&EndPointMessage::FileTransferError(
+                                EndPointFileTransferError { id: id.clone() },
+                            ))
+                            .await;
+                        remove_partial_file(&partial_path).await;
+                        remove_partial_file(&hash_path).await;
+                    }
+                }
+            }
+            PartialFile::Keep => {
+                let _ = writer.flush().await;
+                drop(writer);
+
+                // a partial file kept on cancel goes by the final name
+                if let Err(err) = tokio::fs::rename(&partial_path, &path).await {
+                    tracing::error!(?err, ?path, "rename partial file failed");
+                }
+                remove_partial_file(&hash_path).await;
+            }
+            PartialFile::Remove => {
+                drop(writer);
+                remove_partial_file(&partial_path).await;
+                remove_partial_file(&hash_path).await;
+            }
+            PartialFile::Resume => {
+                let _ = writer.flush().await;
+                tracing::info!(
+                    ?id,
+                    offset = reorder_buffer.next_offset(),
+                    "file transfer interrupted, keep partial file to resume"
+                );
+            }
+        }
 
         APPEND_FILES.invalidate(&id).await;
     });
@@ -99,61 +522,195 @@ async fn save_file_from_remote(
     Ok(())
 }
 
+/// The file a transfer to `path` writes to until it completes.
+pub fn partial_file_path(path: &Path) -> PathBuf {
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(PARTIAL_FILE_SUFFIX);
+    PathBuf::from(partial_path)
+}
+
+/// The file keeping the hash of the content the partial file of `path`
+/// belongs to.
+pub fn partial_hash_path(path: &Path) -> PathBuf {
+    let mut hash_path = path.as_os_str().to_owned();
+    hash_path.push(PARTIAL_HASH_SUFFIX);
+    PathBuf::from(hash_path)
+}
+
+/// Flushes a received file out of the buffers of the app and the OS, and
+/// only then gives it its final name, so a crash leaves either a partial
+/// file or the whole file. The partial file stays when flushing fails.
+pub async fn finish_received_file(
+    mut writer: BufWriter<tokio::fs::File>,
+    partial_path: &Path,
+    path: &Path,
+) -> CoreResult<()> {
+    writer.flush().await.map_err(CoreError::FileFlushFailed)?;
+
+    let file = writer.into_inner();
+    file.sync_all().await.map_err(CoreError::FileFlushFailed)?;
+    drop(file);
+
+    tokio::fs::rename(partial_path, path).await?;
+    Ok(())
+}
+
+async fn write_blocks<W>(writer: &mut W, id: &str, blocks: Vec<Vec<u8>>) -> CoreResult<()>
+where
+    W: AsyncWriteExt + Unpin,
+{
+    for block in blocks {
+        writer.write_all(&block).await?;
+        update_transferred_bytes_count(id, block.len() as _).await;
+    }
+
+    Ok(())
+}
+
+async fn remove_partial_file(path: &Path) {
+    if let Err(err) = tokio::fs::remove_file(path).await {
+        tracing::error!(?err, ?path, "remove partial file failed");
+    }
+}
+
+/// Sends the file from `offset`, the receiver has the bytes before it from
+/// an earlier transfer.
 pub async fn send_file_to_remote(
     id: String,
     client: Arc<EndPointClient>,
     path: &Path,
+    offset: u64,
 ) -> CoreResult<()> {
-    let file = tokio::fs::File::open(path).await?;
+    let mut file = tokio::fs::File::open(path).await?;
+    let total = file.metadata().await?.len();
+    if offset > total {
+        bail_core!("resume offset is beyond the end of the file");
+    }
+
+    file.seek(SeekFrom::Start(offset)).await?;
     let mut reader = BufReader::new(file);
 
-    tokio::spawn(async move {
-        let mut buffer = [0u8; 1024 * 64];
+    let cancel_token = CancellationToken::new();
+    let (acked_tx, mut acked_rx) = watch::channel(offset);
+    SEND_FILES
+        .insert(
+            id.clone(),
+            FileSendSession {
+                endpoint_id: client.endpoint_id(),
+                cancel_token: cancel_token.clone(),
+                acked_tx: Arc::new(acked_tx),
+                progress: Arc::new(ProgressReporter::new(id.clone(), total)),
+            },
+        )
+        .await;
 
-        loop {
-            let (message, n) = match reader.read(&mut buffer).await {
-                Ok(n) => {
-                    let content = if n > 0 {
-                        Some(buffer.as_slice()[0..n].to_vec())
-                    } else {
-                        None
-                    };
+    BYTES_TRANSFERRED_CACHE.insert(id.clone(), offset).await;
 
-                    (
-                        EndPointMessage::FileTransferBlock(EndPointFileTransferBlock {
-                            id: id.clone(),
-                            data: content,
-                        }),
-                        n,
-                    )
-                }
-                Err(err) => {
-                    tracing::error!(?err, "read file failed");
-                    (
-                        EndPointMessage::FileTransferError(EndPointFileTransferError {
-                            id: id.clone(),
-                        }),
-                        0,
-                    )
+    // start from the bandwidth-delay product when the session measured it
+    let chunk_size = file_chunk_size();
+    let initial_bytes = match (client.network_info().rtt_ms, client.estimated_bps()) {
+        (Some(rtt_ms), estimated_bps) if estimated_bps > 0 => window_for(
+            estimated_bps,
+            Duration::from_secs_f64(rtt_ms / 1000.0),
+            chunk_size,
+        ),
+        _ => chunk_size as u64 * INITIAL_WINDOW_CHUNKS,
+    };
+    let mut window = SendWindow::adaptive(chunk_size, initial_bytes).starting_at(offset);
+
+    tokio::spawn(async move {
+        let result = send_file_blocks(
+            &id,
+            &mut reader,
+            &mut window,
+            &mut acked_rx,
+            &cancel_token,
+            |block| {
+                let client = client.clone();
+                async move {
+                    let len = block.data.as_ref().map_or(0, |data| data.len());
+                    let id = block.id.clone();
+                    client
+                        .send(&EndPointMessage::FileTransferBlock(block))
+                        .await?;
+                    update_transferred_bytes_count(&id, len as _).await;
+                    Ok(())
                 }
-            };
+            },
+        )
+        .await;
 
-            if let Err(err) = client.send(&message).await {
-                tracing::error!(?err, "send file message failed");
-                break;
-            }
+        if cancel_token.is_cancelled() {
+            tracing::info!(?id, "send file cancelled");
+        } else if let Err(err) = result {
+            tracing::error!(?id, ?err, "send file failed");
+            let _ = client
+                .send(&EndPointMessage::FileTransferError(
+                    EndPointFileTransferError { id: id.clone() },
+                ))
+                .await;
+        }
 
-            update_transferred_bytes_count(&id, n as _).await;
+        SEND_FILES.invalidate(&id).await;
+    });
 
-            match message {
-                EndPointMessage::FileTransferBlock(message) if message.data.is_none() => break,
-                EndPointMessage::FileTransferError(_) => break,
-                _ => {}
+    Ok(())
+}
+
+/// Sends the file in blocks of the window's chunk size, as many in flight
+/// as the window allows, and the end of the file last. Returns early once
+/// cancelled.
+pub(crate) async fn send_file_blocks<R, F, Fut>(
+    id: &str,
+    reader: &mut R,
+    window: &mut SendWindow,
+    acked_rx: &mut watch::Receiver<u64>,
+    cancel_token: &CancellationToken,
+    mut send: F,
+) -> CoreResult<()>
+where
+    R: AsyncRead + Unpin,
+    F: FnMut(EndPointFileTransferBlock) -> Fut,
+    Fut: Future<Output = CoreResult<()>>,
+{
+    let mut buffer = vec![0u8; window.chunk_size()];
+
+    loop {
+        let acked_offset = *acked_rx.borrow_and_update();
+        window.on_ack(acked_offset, Instant::now());
+
+        if window.is_full() {
+            tokio::select! {
+                _ = cancel_token.cancelled() => return Ok(()),
+                changed = tokio::time::timeout(FILE_ACK_TIMEOUT, acked_rx.changed()) => {
+                    match changed {
+                        Ok(Ok(_)) => continue,
+                        Ok(Err(_)) => return Err(core_error!("file transfer ack channel closed")),
+                        Err(_) => return Err(CoreError::Timeout),
+                    }
+                }
             }
         }
-    });
 
-    Ok(())
+        if cancel_token.is_cancelled() {
+            return Ok(());
+        }
+
+        let n = reader.read(&mut buffer).await?;
+
+        send(EndPointFileTransferBlock {
+            id: id.to_string(),
+            offset: window.sent_offset(),
+            data: (n > 0).then(|| buffer[..n].to_vec()),
+        })
+        .await?;
+
+        if n == 0 {
+            return Ok(());
+        }
+
+        window.on_sent(n, Instant::now());
+    }
 }
 
 pub fn query_transferred_bytes_count(id: &str) -> u64 {
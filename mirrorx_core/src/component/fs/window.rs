@@ -0,0 +1,236 @@
+use crate::{core_error, error::CoreResult};
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+pub const DEFAULT_FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+pub const MIN_FILE_CHUNK_SIZE: usize = 4 * 1024;
+
+pub const MAX_FILE_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// Most bytes of a transfer sent but not acked yet. The receiver holds as
+/// much for reordering, so this bounds the memory on both sides.
+pub const MAX_IN_FLIGHT_BYTES: u64 = 16 * 1024 * 1024;
+
+/// Chunks in flight before the first acks measured the link, when the
+/// session has no bandwidth estimate to start from.
+pub const INITIAL_WINDOW_CHUNKS: u64 = 8;
+
+/// The sender gives up when the receiver acks nothing for this long.
+pub const FILE_ACK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Round trips this much longer than the shortest one mean the window
+/// fills a queue on the way instead of the link.
+const QUEUE_RTT_FACTOR: f64 = 1.5;
+
+static FILE_CHUNK_SIZE: AtomicUsize = AtomicUsize::new(DEFAULT_FILE_CHUNK_SIZE);
+
+/// Bytes read from the file per block, clamped to
+/// [`MIN_FILE_CHUNK_SIZE`]..=[`MAX_FILE_CHUNK_SIZE`]. Applies to transfers
+/// started afterwards.
+pub fn set_file_chunk_size(bytes: usize) {
+    FILE_CHUNK_SIZE.store(
+        bytes.clamp(MIN_FILE_CHUNK_SIZE, MAX_FILE_CHUNK_SIZE),
+        Ordering::Relaxed,
+    );
+}
+
+pub fn file_chunk_size() -> usize {
+    FILE_CHUNK_SIZE.load(Ordering::Relaxed)
+}
+
+/// Bytes in flight that keep a link of this bandwidth and round trip busy,
+/// twice the bandwidth-delay product so acks arriving in bursts don't
+/// starve it.
+pub fn window_for(bps: u64, rtt: Duration, chunk_size: usize) -> u64 {
+    let bdp = (bps as f64 / 8.0 * rtt.as_secs_f64()) as u64;
+    bdp.saturating_mul(2)
+        .clamp(chunk_size as u64, MAX_IN_FLIGHT_BYTES)
+}
+
+/// Tracks the blocks of a transfer the receiver hasn't acked and how many
+/// more may go out. Acks carry the offset the receiver wrote up to.
+#[derive(Debug)]
+pub struct SendWindow {
+    chunk_size: usize,
+    limit_bytes: u64,
+    adaptive: bool,
+    sent_offset: u64,
+    acked_offset: u64,
+    sent_at: VecDeque<(u64, Instant)>,
+    min_rtt: Option<Duration>,
+}
+
+impl SendWindow {
+    /// A window tuned by the round trips of the acks, starting at
+    /// `initial_bytes`.
+    pub fn adaptive(chunk_size: usize, initial_bytes: u64) -> Self {
+        Self::new(chunk_size, initial_bytes, true)
+    }
+
+    /// A window that never changes, one chunk makes it stop-and-wait.
+    pub fn fixed(chunk_size: usize, limit_bytes: u64) -> Self {
+        Self::new(chunk_size, limit_bytes, false)
+    }
+
+    fn new(chunk_size: usize, limit_bytes: u64, adaptive: bool) -> Self {
+        Self {
+            chunk_size,
+            limit_bytes: limit_bytes.clamp(chunk_size as u64, MAX_IN_FLIGHT_BYTES),
+            adaptive,
+            sent_offset: 0,
+            acked_offset: 0,
+            sent_at: VecDeque::new(),
+            min_rtt: None,
+        }
+    }
+
+    /// The window of a transfer resumed at `offset`, the bytes before it are
+    /// acked already.
+    pub fn starting_at(mut self, offset: u64) -> Self {
+        self.sent_offset = offset;
+        self.acked_offset = offset;
+        self
+    }
+
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+
+    pub fn limit_bytes(&self) -> u64 {
+        self.limit_bytes
+    }
+
+    /// Offset of the next block.
+    pub fn sent_offset(&self) -> u64 {
+        self.sent_offset
+    }
+
+    pub fn acked_offset(&self) -> u64 {
+        self.acked_offset
+    }
+
+    pub fn in_flight_bytes(&self) -> u64 {
+        self.sent_offset - self.acked_offset
+    }
+
+    /// Whether another chunk would exceed the window.
+    pub fn is_full(&self) -> bool {
+        self.in_flight_bytes() + self.chunk_size as u64 > self.limit_bytes
+    }
+
+    pub fn on_sent(&mut self, len: usize, now: Instant) {
+        self.sent_offset += len as u64;
+        self.sent_at.push_back((self.sent_offset, now));
+    }
+
+    /// Takes in the offset the receiver acked. Acks are cumulative, a stale
+    /// one overtaken by a later ack changes nothing and returns false.
+    pub fn on_ack(&mut self, offset: u64, now: Instant) -> bool {
+        if offset <= self.acked_offset || offset > self.sent_offset {
+            return false;
+        }
+
+        let acked_bytes = offset - self.acked_offset;
+        self.acked_offset = offset;
+
+        let mut rtt = None;
+        while let Some(&(end_offset, sent_at)) = self.sent_at.front() {
+            if end_offset > offset {
+                break;
+            }
+
+            rtt = Some(now.saturating_duration_since(sent_at));
+            self.sent_at.pop_front();
+        }
+
+        if let (true, Some(rtt)) = (self.adaptive, rtt) {
+            self.tune(acked_bytes, rtt);
+        }
+
+        true
+    }
+
+    /// Grows the window by the acked bytes, doubling it every round trip,
+    /// while the round trip stays short. Once the blocks queue up on the
+    /// way it shrinks the window by how much the round trip grew.
+    fn tune(&mut self, acked_bytes: u64, rtt: Duration) {
+        let min_rtt = self.min_rtt.map_or(rtt, |min_rtt| min_rtt.min(rtt));
+        self.min_rtt = Some(min_rtt);
+
+        let limit_bytes = if rtt <= min_rtt.mul_f64(QUEUE_RTT_FACTOR) {
+            self.limit_bytes.saturating_add(acked_bytes)
+        } else {
+            let ratio = QUEUE_RTT_FACTOR * min_rtt.as_secs_f64() / rtt.as_secs_f64();
+            (self.limit_bytes as f64 * ratio) as u64
+        };
+
+        self.limit_bytes = limit_bytes.clamp(self.chunk_size as u64, MAX_IN_FLIGHT_BYTES);
+    }
+}
+
+/// Puts the blocks of a transfer back in file order on the receiving side.
+#[derive(Debug, Default)]
+pub struct ReorderBuffer {
+    next_offset: u64,
+    pending: BTreeMap<u64, Vec<u8>>,
+    pending_bytes: u64,
+}
+
+impl ReorderBuffer {
+    /// The buffer of a transfer resumed at `offset`, the file is written up
+    /// to there.
+    pub fn starting_at(offset: u64) -> Self {
+        Self {
+            next_offset: offset,
+            ..Default::default()
+        }
+    }
+
+    /// Offset the file is written up to, the one to ack.
+    pub fn next_offset(&self) -> u64 {
+        self.next_offset
+    }
+
+    pub fn pending_bytes(&self) -> u64 {
+        self.pending_bytes
+    }
+
+    /// Returns the blocks that continue the file in order, this one and
+    /// those held back for it. Duplicates are dropped, blocks beyond
+    /// [`MAX_IN_FLIGHT_BYTES`] are refused.
+    pub fn push(&mut self, offset: u64, data: Vec<u8>) -> CoreResult<Vec<Vec<u8>>> {
+        if offset < self.next_offset || self.pending.contains_key(&offset) {
+            tracing::warn!(offset, "drop duplicate file block");
+            return Ok(Vec::new());
+        }
+
+        let end_offset = offset + data.len() as u64;
+        if end_offset - self.next_offset > MAX_IN_FLIGHT_BYTES {
+            return Err(core_error!(
+                "file block at {} is beyond the transfer window",
+                offset
+            ));
+        }
+
+        if offset > self.next_offset {
+            self.pending_bytes += data.len() as u64;
+            self.pending.insert(offset, data);
+            return Ok(Vec::new());
+        }
+
+        self.next_offset = end_offset;
+        let mut ready = vec![data];
+
+        while let Some(data) = self.pending.remove(&self.next_offset) {
+            self.pending_bytes -= data.len() as u64;
+            self.next_offset += data.len() as u64;
+            ready.push(data);
+        }
+
+        Ok(ready)
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{
+    api::endpoint::message::{EndPointInput, InputEvent, MouseEvent},
+    utility::config_cell::ConfigCell,
+};
+use std::time::{Duration, Instant};
+
+/// A 1000Hz mouse reports a move every millisecond, one message per 8ms
+/// still tracks the pointer at more than the frame rate of the stream.
+pub const DEFAULT_INPUT_COALESCE_WINDOW: Duration = Duration::from_millis(8);
+
+static INPUT_COALESCE_WINDOW: ConfigCell<Duration> =
+    ConfigCell::new(|| DEFAULT_INPUT_COALESCE_WINDOW);
+
+/// Zero sends every event at once.
+pub fn set_input_coalesce_window(window: Duration) {
+    INPUT_COALESCE_WINDOW.set(window);
+}
+
+pub fn input_coalesce_window() -> Duration {
+    INPUT_COALESCE_WINDOW.get()
+}
+
+/// Batches input events into as few messages as possible. Mouse moves in a
+/// row collapse into the latest position and wait up to the window for more,
+/// every other event flushes the batch at once, so clicks and keys are never
+/// delayed nor reordered against the moves around them.
+#[derive(Debug)]
+pub struct InputCoalescer {
+    window: Duration,
+    pending: Vec<InputEvent>,
+    pending_since: Option<Instant>,
+}
+
+impl InputCoalescer {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window,
+            pending: Vec::new(),
+            pending_since: None,
+        }
+    }
+
+    /// Queues the events, returns the batch to send once it is due. Call it
+    /// regularly, even without events, so held moves go out in time.
+    pub fn push(
+        &mut self,
+        events: impl IntoIterator<Item = InputEvent>,
+        now: Instant,
+    ) -> Option<EndPointInput> {
+        let mut urgent = false;
+
+        for event in events {
+            let merge = match (&event, self.pending.last()) {
+                (
+                    InputEvent::Mouse(MouseEvent::Move(key, ..)),
+                    Some(InputEvent::Mouse(MouseEvent::Move(last_key, ..))),
+                ) => key == last_key,
+                _ => false,
+            };
+
+            if merge {
+                self.pending.pop();
+            }
+
+            if !matches!(event, InputEvent::Mouse(MouseEvent::Move(..))) {
+                urgent = true;
+            }
+
+            self.pending.push(event);
+        }
+
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        let pending_since = *self.pending_since.get_or_insert(now);
+
+        if urgent || now.saturating_duration_since(pending_since) >= self.window {
+            self.flush()
+        } else {
+            None
+        }
+    }
+
+    /// Returns everything queued regardless of the window.
+    pub fn flush(&mut self) -> Option<EndPointInput> {
+        self.pending_since = None;
+
+        if self.pending.is_empty() {
+            return None;
+        }
+
+        Some(EndPointInput {
+            events: std::mem::take(&mut self.pending),
+        })
+    }
+}
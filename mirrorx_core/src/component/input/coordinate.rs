@@ -0,0 +1,157 @@
+use crate::component::desktop::monitor::Monitor;
+
+/// How the viewer lays out the frame in its window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ViewMode {
+    /// Scaled to fit the window, centered with bars around it.
+    ScaleToFit,
+    /// One frame pixel per window pixel, scrolled when the window is smaller.
+    Native,
+}
+
+/// Maps window positions of the viewer onto the frame it shows. A frame
+/// pixel `p` is drawn at `origin + p * scale`.
+///
+/// Pointer positions travel normalized to the captured frame, `0.0..=1.0` on
+/// both axes, whatever size the viewer renders the frame at or the host
+/// encodes it at. The host maps them back with [`CaptureArea`], so neither
+/// side depends on the scaling of the other.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ViewMapping {
+    mode: ViewMode,
+    origin_x: f32,
+    origin_y: f32,
+    scale: f32,
+}
+
+impl ViewMapping {
+    pub fn scale_to_fit(
+        view_width: f32,
+        view_height: f32,
+        frame_width: f32,
+        frame_height: f32,
+    ) -> Self {
+        let scale = (view_width / frame_width).min(view_height / frame_height);
+
+        Self {
+            mode: ViewMode::ScaleToFit,
+            origin_x: (view_width - frame_width * scale) / 2.0,
+            origin_y: (view_height - frame_height * scale) / 2.0,
+            scale,
+        }
+    }
+
+    /// `scroll_x` and `scroll_y` are the frame position at the top left
+    /// corner of the view.
+    pub fn native(scroll_x: f32, scroll_y: f32) -> Self {
+        Self {
+            mode: ViewMode::Native,
+            origin_x: -scroll_x,
+            origin_y: -scroll_y,
+            scale: 1.0,
+        }
+    }
+
+    pub fn mode(&self) -> ViewMode {
+        self.mode
+    }
+
+    /// Returns the normalized frame position under a window position, `None`
+    /// when it is outside of the frame.
+    pub fn normalize(
+        &self,
+        x: f32,
+        y: f32,
+        frame_width: f32,
+        frame_height: f32,
+    ) -> Option<(f32, f32)> {
+        if self.scale <= 0.0 || frame_width <= 0.0 || frame_height <= 0.0 {
+            return None;
+        }
+
+        let frame_x = (x - self.origin_x) / self.scale;
+        let frame_y = (y - self.origin_y) / self.scale;
+
+        if !(0.0..=frame_width).contains(&frame_x) || !(0.0..=frame_height).contains(&frame_y) {
+            return None;
+        }
+
+        Some((frame_x / frame_width, frame_y / frame_height))
+    }
+}
+
+/// The area the host captures, in the coordinate space of its input API,
+/// which spans every monitor and may start at negative positions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CaptureArea {
+    pub left: f64,
+    pub top: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+impl CaptureArea {
+    /// A region of this area, given relative to it and clamped into it.
+    pub fn crop(&self, left: f64, top: f64, width: f64, height: f64) -> CaptureArea {
+        let left = left.clamp(0.0, self.width);
+        let top = top.clamp(0.0, self.height);
+
+        CaptureArea {
+            left: self.left + left,
+            top: self.top + top,
+            width: width.clamp(0.0, self.width - left),
+            height: height.clamp(0.0, self.height - top),
+        }
+    }
+
+    /// Maps a normalized position onto the area. The far edges land on the
+    /// last pixel of the area rather than the first one of its neighbour.
+    pub fn absolute_position(&self, x: f32, y: f32) -> (f64, f64) {
+        let map = |normalized: f32, origin: f64, size: f64| {
+            let offset = normalized.clamp(0.0, 1.0) as f64 * size;
+            origin + offset.min((size - 1.0).max(0.0))
+        };
+
+        (map(x, self.left, self.width), map(y, self.top, self.height))
+    }
+
+    /// Normalizes a position to the area, the inverse of
+    /// [`absolute_position`](Self::absolute_position).
+    pub fn relative_position(&self, x: f64, y: f64) -> (f32, f32) {
+        let map = |value: f64, origin: f64, size: f64| {
+            if size <= 0.0 {
+                return 0.0;
+            }
+
+            ((value - origin) / size).clamp(0.0, 1.0) as f32
+        };
+
+        (map(x, self.left, self.width), map(y, self.top, self.height))
+    }
+}
+
+impl From<&Monitor> for CaptureArea {
+    fn from(monitor: &Monitor) -> Self {
+        CaptureArea {
+            left: monitor.left as f64,
+            top: monitor.top as f64,
+            width: monitor.width as f64,
+            height: monitor.height as f64,
+        }
+    }
+}
+
+/// Maps an absolute position onto the `0..=65535` range Windows absolute
+/// input takes for the whole virtual desktop.
+pub fn to_virtual_desktop(x: f64, y: f64, virtual_screen: &CaptureArea) -> (i32, i32) {
+    let map = |value: f64, origin: f64, size: f64| {
+        ((value - origin) * 65535.0 / (size - 1.0).max(1.0))
+            .round()
+            .clamp(0.0, 65535.0) as i32
+    };
+
+    (
+        map(x, virtual_screen.left, virtual_screen.width),
+        map(y, virtual_screen.top, virtual_screen.height),
+    )
+}
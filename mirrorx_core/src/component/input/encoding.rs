@@ -0,0 +1,197 @@
+//! How keystrokes travel to the remote desktop.
+//!
+//! Scancodes name the physical key, the host turns them into characters
+//! with its own keyboard layout. Shortcuts, modifiers and function keys work
+//! the same everywhere, but text comes out wrong when the layouts of both
+//! sides differ, a `Z` typed on a German layout arrives as `Y` on a US host.
+//!
+//! Unicode sends the characters the layout of the viewer produced, so text
+//! arrives as typed whatever the host layout. Keys producing no text still go
+//! as scancodes, as do character keys pressed with Ctrl or Command, which
+//! don't produce text either. The price: characters typed with AltGr reach
+//! the host with Ctrl and Alt held, which a few applications take for a
+//! shortcut, and the first press of a dead key goes out as a scancode.
+//!
+//! Platform keysyms, as X11 uses them, aren't supported by any host yet.
+
+use crate::api::endpoint::message::KeyboardEvent;
+use serde::{Deserialize, Serialize};
+use tao::keyboard::KeyCode;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InputEncoding {
+    /// Physical keys only, what every version understands.
+    #[default]
+    Scancode,
+    /// Text as characters, other keys as scancodes.
+    Unicode,
+}
+
+impl InputEncoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InputEncoding::Scancode => "scancode",
+            InputEncoding::Unicode => "unicode",
+        }
+    }
+
+    /// `None` for encodings of newer versions, like `keysym`.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "scancode" => Some(InputEncoding::Scancode),
+            "unicode" => Some(InputEncoding::Unicode),
+            _ => None,
+        }
+    }
+}
+
+/// Whether a key may produce text, the others always go as scancodes.
+pub fn is_character_key(key: &KeyCode) -> bool {
+    matches!(
+        key,
+        KeyCode::Backquote
+            | KeyCode::Backslash
+            | KeyCode::BracketLeft
+            | KeyCode::BracketRight
+            | KeyCode::Comma
+            | KeyCode::Digit0
+            | KeyCode::Digit1
+            | KeyCode::Digit2
+            | KeyCode::Digit3
+            | KeyCode::Digit4
+            | KeyCode::Digit5
+            | KeyCode::Digit6
+            | KeyCode::Digit7
+            | KeyCode::Digit8
+            | KeyCode::Digit9
+            | KeyCode::Equal
+            | KeyCode::IntlBackslash
+            | KeyCode::IntlRo
+            | KeyCode::IntlYen
+            | KeyCode::KeyA
+            | KeyCode::KeyB
+            | KeyCode::KeyC
+            | KeyCode::KeyD
+            | KeyCode::KeyE
+            | KeyCode::KeyF
+            | KeyCode::KeyG
+            | KeyCode::KeyH
+            | KeyCode::KeyI
+            | KeyCode::KeyJ
+            | KeyCode::KeyK
+            | KeyCode::KeyL
+            | KeyCode::KeyM
+            | KeyCode::KeyN
+            | KeyCode::KeyO
+            | KeyCode::KeyP
+            | KeyCode::KeyQ
+            | KeyCode::KeyR
+            | KeyCode::KeyS
+            | KeyCode::KeyT
+            | KeyCode::KeyU
+            | KeyCode::KeyV
+            | KeyCode::KeyW
+            | KeyCode::KeyX
+            | KeyCode::KeyY
+            | KeyCode::KeyZ
+            | KeyCode::Minus
+            | KeyCode::Period
+            | KeyCode::Quote
+            | KeyCode::Semicolon
+            | KeyCode::Slash
+            | KeyCode::Space
+            | KeyCode::Numpad0
+            | KeyCode::Numpad1
+            | KeyCode::Numpad2
+            | KeyCode::Numpad3
+            | KeyCode::Numpad4
+            | KeyCode::Numpad5
+            | KeyCode::Numpad6
+            | KeyCode::Numpad7
+            | KeyCode::Numpad8
+            | KeyCode::Numpad9
+            | KeyCode::NumpadAdd
+            | KeyCode::NumpadDecimal
+            | KeyCode::NumpadDivide
+            | KeyCode::NumpadMultiply
+            | KeyCode::NumpadSubtract
+    )
+}
+
+/// Turns the keys and text of the viewer window into keyboard events in the
+/// agreed encoding. With Unicode a character key waits for the text it
+/// produces, the text goes out in place of the key, the key goes out when
+/// none follows. Feed the events in the order the window got them and
+/// [`flush`](Self::flush) before anything else and at the end of a frame.
+#[derive(Debug, Default)]
+pub struct KeyEncoder {
+    encoding: InputEncoding,
+    pending: Option<KeyCode>,
+    /// Held keys whose press went out as text, their release is dropped.
+    typed: Vec<KeyCode>,
+}
+
+impl KeyEncoder {
+    pub fn new(encoding: InputEncoding) -> Self {
+        Self {
+            encoding,
+            ..Default::default()
+        }
+    }
+
+    pub fn encoding(&self) -> InputEncoding {
+        self.encoding
+    }
+
+    /// The profile arrives after the window opened, keys pressed until then
+    /// go as scancodes.
+    pub fn set_encoding(&mut self, encoding: InputEncoding) -> Vec<KeyboardEvent> {
+        let events = self.flush();
+        self.encoding = encoding;
+        events
+    }
+
+    pub fn key(&mut self, key: KeyCode, pressed: bool) -> Vec<KeyboardEvent> {
+        let mut events = self.flush();
+
+        if !pressed {
+            match self.typed.iter().position(|typed| *typed == key) {
+                Some(index) => {
+                    self.typed.remove(index);
+                }
+                None => events.push(KeyboardEvent::KeyUp(key)),
+            }
+        } else if self.encoding == InputEncoding::Unicode && is_character_key(&key) {
+            self.pending = Some(key);
+        } else {
+            events.push(KeyboardEvent::KeyDown(key));
+        }
+
+        events
+    }
+
+    pub fn text(&mut self, text: &str) -> Vec<KeyboardEvent> {
+        // the key already went out and the host types with its own layout
+        if self.encoding != InputEncoding::Unicode || text.is_empty() {
+            return self.flush();
+        }
+
+        if let Some(key) = self.pending.take() {
+            if !self.typed.contains(&key) {
+                self.typed.push(key);
+            }
+        }
+
+        vec![KeyboardEvent::Text(text.to_string())]
+    }
+
+    /// Sends a waiting key, no text came for it.
+    pub fn flush(&mut self) -> Vec<KeyboardEvent> {
+        self.pending
+            .take()
+            .map(KeyboardEvent::KeyDown)
+            .into_iter()
+            .collect()
+    }
+}
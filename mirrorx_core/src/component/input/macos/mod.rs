@@ -1,10 +1,10 @@
 mod key_code;
 
 use self::key_code::*;
-use super::key::MouseKey;
+use super::{coordinate::CaptureArea, key::MouseKey};
 use crate::{component::desktop::monitor::Monitor, core_error, error::CoreResult};
 use core_graphics::{
-    display::{CGDirectDisplayID, CGDisplayMoveCursorToPoint, CGPoint},
+    display::{CGDirectDisplayID, CGDisplay, CGDisplayMoveCursorToPoint, CGPoint},
     event::{
         CGEvent, CGEventTapLocation, CGEventType, CGKeyCode, CGMouseButton, EventField,
         ScrollEventUnit,
@@ -197,23 +197,61 @@ pub fn keyboard_down(key: &tao::keyboard::KeyCode) -> CoreResult<()> {
     post_keyboard_event(key, true)
 }
 
+/// Types the characters whatever the keyboard layout.
+pub fn keyboard_text(text: &str) -> CoreResult<()> {
+    let chars: Vec<char> = text.chars().collect();
+
+    // an event carries up to 20 UTF-16 units
+    for chunk in chars.chunks(10) {
+        let chunk: String = chunk.iter().collect();
+
+        for press in [true, false] {
+            let source = CGEventSource::new(CGEventSourceStateID::HIDSystemState)
+                .map_err(|_| core_error!("create CGEventSource failed"))?;
+            let event = CGEvent::new_keyboard_event(source, 0, press)
+                .map_err(|_| core_error!("create keyboard CGEvent failed"))?;
+
+            event.set_string(&chunk);
+            event.post(CGEventTapLocation::HID);
+        }
+    }
+
+    Ok(())
+}
+
+/// `x` and `y` are normalized to the display, see [`CaptureArea`].
 unsafe fn post_mouse_event(
     display_id: CGDirectDisplayID,
     x: f32,
     y: f32,
     event_create_fn: impl Fn(CGEventSource, CGPoint) -> CoreResult<Vec<CGEvent>> + 'static + Send,
 ) -> CoreResult<()> {
+    // events take global coordinates in points, which differ from pixels on
+    // HiDPI displays and span every display
+    let bounds = CGDisplay::new(display_id).bounds();
+    let display_area = CaptureArea {
+        left: bounds.origin.x,
+        top: bounds.origin.y,
+        width: bounds.size.width,
+        height: bounds.size.height,
+    };
+
+    let (global_x, global_y) = display_area.absolute_position(x, y);
+
     // todo: use self created serial queue
     dispatch::Queue::global(dispatch::QueuePriority::High).barrier_async(move || {
         if let Ok(event_source) = CGEventSource::new(CGEventSourceStateID::HIDSystemState) {
-            let point = CGPoint::new(x as f64, y as f64);
+            let point = CGPoint::new(global_x, global_y);
 
             if let Ok(events) = event_create_fn(event_source, point) {
                 for event in events.iter() {
                     event.post(CGEventTapLocation::HID);
                 }
 
-                let _ = CGDisplayMoveCursorToPoint(display_id, point);
+                // relative to the display this time
+                let display_point =
+                    CGPoint::new(global_x - display_area.left, global_y - display_area.top);
+                let _ = CGDisplayMoveCursorToPoint(display_id, display_point);
             }
         }
     });
@@ -1,3 +1,6 @@
+pub mod coalesce;
+pub mod coordinate;
+pub mod encoding;
 pub mod key;
 
 #[cfg(target_os = "macos")]
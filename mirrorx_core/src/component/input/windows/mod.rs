@@ -1,4 +1,7 @@
-use super::key::{KeyboardKey, MouseKey};
+use super::{
+    coordinate::{to_virtual_desktop, CaptureArea},
+    key::{KeyboardKey, MouseKey},
+};
 use crate::{
     component::desktop::monitor::Monitor,
     core_error,
@@ -27,7 +30,7 @@ pub fn mouse_up(monitor: &Monitor, key: &MouseKey, x: f32, y: f32) -> CoreResult
         0
     };
 
-    unsafe { send_input(&[(mouse_data, dw_flags)], monitor.left, monitor.top, x, y) }
+    unsafe { send_input(&[(mouse_data, dw_flags)], monitor, x, y) }
 }
 
 pub fn mouse_down(monitor: &Monitor, key: &MouseKey, x: f32, y: f32) -> CoreResult<()> {
@@ -48,7 +51,7 @@ pub fn mouse_down(monitor: &Monitor, key: &MouseKey, x: f32, y: f32) -> CoreResu
         0
     };
 
-    unsafe { send_input(&[(mouse_data, dw_flags)], monitor.left, monitor.top, x, y) }
+    unsafe { send_input(&[(mouse_data, dw_flags)], monitor, x, y) }
 }
 
 pub fn mouse_double_click(monitor: &Monitor, key: &MouseKey, x: f32, y: f32) -> CoreResult<()> {
@@ -99,7 +102,7 @@ pub fn mouse_double_click(monitor: &Monitor, key: &MouseKey, x: f32, y: f32) ->
         args.push((mouse_data, up_flags));
     }
 
-    unsafe { send_input(&args, monitor.left, monitor.top, x, y) }
+    unsafe { send_input(&args, monitor, x, y) }
 }
 
 pub fn mouse_move(monitor: &Monitor, key: &MouseKey, x: f32, y: f32) -> CoreResult<()> {
@@ -136,15 +139,14 @@ pub fn mouse_move(monitor: &Monitor, key: &MouseKey, x: f32, y: f32) -> CoreResu
         0
     };
 
-    unsafe { send_input(&[(mouse_data, dw_flags)], monitor.left, monitor.top, x, y) }
+    unsafe { send_input(&[(mouse_data, dw_flags)], monitor, x, y) }
 }
 
 pub fn mouse_scroll_wheel(monitor: &Monitor, delta: f32) -> CoreResult<()> {
     unsafe {
         send_input(
             &[(delta.round() as i32, MOUSEEVENTF_WHEEL)],
-            monitor.left,
-            monitor.top,
+            monitor,
             0f32,
             0f32,
         )
@@ -159,20 +161,28 @@ pub fn keyboard_down(key: &tao::keyboard::KeyCode) -> CoreResult<()> {
     unsafe { post_keyboard_event(key, true) }
 }
 
+/// Types the characters whatever the keyboard layout.
+pub fn keyboard_text(text: &str) -> CoreResult<()> {
+    unsafe { post_text_event(text) }
+}
+
+/// `x` and `y` are normalized to the monitor, see [`CaptureArea`].
 unsafe fn send_input(
     args: &[(i32, MOUSE_EVENT_FLAGS)],
-    left: u16,
-    top: u16,
-    screen_coordinate_x: f32,
-    screen_coordinate_y: f32,
+    monitor: &Monitor,
+    x: f32,
+    y: f32,
 ) -> CoreResult<()> {
-    let dx = ((left as f32 + screen_coordinate_x) * 65535f32
-        / GetSystemMetrics(SM_CXVIRTUALSCREEN) as f32)
-        .round() as i32;
+    let (x, y) = CaptureArea::from(monitor).absolute_position(x, y);
+
+    let virtual_screen = CaptureArea {
+        left: GetSystemMetrics(SM_XVIRTUALSCREEN) as f64,
+        top: GetSystemMetrics(SM_YVIRTUALSCREEN) as f64,
+        width: GetSystemMetrics(SM_CXVIRTUALSCREEN) as f64,
+        height: GetSystemMetrics(SM_CYVIRTUALSCREEN) as f64,
+    };
 
-    let dy = ((top as f32 + screen_coordinate_y) * 65535f32
-        / GetSystemMetrics(SM_CYVIRTUALSCREEN) as f32)
-        .round() as i32;
+    let (dx, dy) = to_virtual_desktop(x, y, &virtual_screen);
 
     let mut inputs = Vec::with_capacity(args.len());
 
@@ -239,6 +249,36 @@ unsafe fn post_keyboard_event(key: &tao::keyboard::KeyCode, press: bool) -> Core
     }
 }
 
+unsafe fn post_text_event(text: &str) -> CoreResult<()> {
+    let mut inputs = Vec::new();
+
+    // a press and a release per UTF-16 unit, surrogate pairs included
+    for unit in text.encode_utf16() {
+        for flags in [KEYEVENTF_UNICODE, KEYEVENTF_UNICODE | KEYEVENTF_KEYUP] {
+            inputs.push(INPUT {
+                r#type: INPUT_KEYBOARD,
+                Anonymous: INPUT_0 {
+                    ki: KEYBDINPUT {
+                        wVk: VIRTUAL_KEY(0),
+                        wScan: unit,
+                        dwFlags: flags,
+                        ..Default::default()
+                    },
+                },
+            });
+        }
+    }
+
+    if SendInput(&inputs, std::mem::size_of::<INPUT>() as i32) as usize == inputs.len() {
+        Ok(())
+    } else {
+        Err(core_error!(
+            "SendInput failed ({:?})",
+            GetLastError().to_hresult()
+        ))
+    }
+}
+
 const fn is_extend_key(key: VIRTUAL_KEY) -> bool {
     matches!(
         key,
@@ -1,20 +1,94 @@
-use crate::error::CoreResult;
+use super::discover_event::{report_discover_event, NodeTracker, NODE_SWEEP_INTERVAL, NODE_TTL};
+use crate::{
+    api::config::{
+        device_name::{convert_host_name_to_string, device_name, sanitize_device_name},
+        device_tag::{device_tag, sanitize_device_tag},
+    },
+    error::CoreResult,
+};
 use hostname;
-use moka::future::Cache;
 use serde::{Deserialize, Serialize};
 use std::{
-    ffi::OsStr,
+    collections::HashMap,
     net::{IpAddr, Ipv4Addr},
-    sync::{atomic::AtomicBool, Arc},
-    time::Duration,
+    sync::{atomic::AtomicBool, Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
 };
 
+/// UDP port devices broadcast their presence on.
+pub const LAN_DISCOVER_PORT: u16 = 48000;
+
 #[derive(Debug, Clone, Serialize)]
 pub struct Node {
     pub host_name: String,
+    /// The name the device shows to others, `None` for devices that don't
+    /// broadcast one.
+    pub device_name: Option<String>,
     pub addr: IpAddr,
     pub os: String,
     pub os_version: String,
+    /// Given by the user to a device connected to before.
+    pub nickname: Option<String>,
+    /// A label the device groups itself under, like a floor or a team.
+    pub tag: Option<String>,
+    /// Unix timestamp in milliseconds of its last broadcast.
+    pub last_seen: i64,
+}
+
+/// Narrows the discovered devices, an unset field matches every device.
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct NodeFilter {
+    /// Matches the start of the device name, the nickname or the host name.
+    pub name_prefix: Option<String>,
+    pub tag: Option<String>,
+}
+
+impl NodeFilter {
+    /// Both compare ignoring case.
+    pub fn matches(&self, node: &Node) -> bool {
+        if let Some(tag) = self.tag.as_deref().map(str::trim) {
+            if !tag.is_empty()
+                && !node
+                    .tag
+                    .as_deref()
+                    .is_some_and(|node_tag| node_tag.eq_ignore_ascii_case(tag))
+            {
+                return false;
+            }
+        }
+
+        if let Some(name_prefix) = self.name_prefix.as_deref().map(str::trim) {
+            if !name_prefix.is_empty() {
+                let name_prefix = name_prefix.to_lowercase();
+
+                return [
+                    node.device_name.as_deref(),
+                    node.nickname.as_deref(),
+                    Some(node.host_name.as_str()),
+                ]
+                .into_iter()
+                .flatten()
+                .any(|name| name.to_lowercase().starts_with(&name_prefix));
+            }
+        }
+
+        true
+    }
+}
+
+pub fn filter_nodes(nodes: Vec<Node>, filter: &NodeFilter) -> Vec<Node> {
+    nodes
+        .into_iter()
+        .filter(|node| filter.matches(node))
+        .collect()
+}
+
+/// Shows the nicknames of the devices connected to before, the connection
+/// history keys LAN devices by ip.
+pub fn merge_nicknames(nodes: &mut [Node], nicknames: &HashMap<String, String>) {
+    for node in nodes.iter_mut() {
+        node.nickname = nicknames.get(&node.addr.to_string()).cloned();
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -23,18 +97,81 @@ pub enum BroadcastPacket {
     TargetDead,
 }
 
-#[derive(Debug, Default, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct TargetLivePacket {
+    pub host_name: String,
+    pub os: String,
+    pub os_version: String,
+    /// Devices that don't know it ignore it.
+    pub device_name: String,
+    /// Last, empty when the device has none.
+    pub tag: String,
+}
+
+/// What devices broadcast before they had a tag.
+#[derive(Deserialize)]
+enum UntaggedBroadcastPacket {
+    TargetLive(UntaggedTargetLivePacket),
+}
+
+#[derive(Deserialize)]
+struct UntaggedTargetLivePacket {
+    host_name: String,
+    os: String,
+    os_version: String,
+    device_name: String,
+}
+
+/// What devices broadcast before they had a device name.
+#[derive(Deserialize)]
+enum LegacyBroadcastPacket {
+    TargetLive(LegacyTargetLivePacket),
+}
+
+#[derive(Deserialize)]
+struct LegacyTargetLivePacket {
     host_name: String,
     os: String,
     os_version: String,
 }
 
+/// Reads the packets of this version and the live packets of devices
+/// before the tag or the device name.
+pub fn deserialize_broadcast_packet(buffer: &[u8]) -> bincode::Result<BroadcastPacket> {
+    bincode::deserialize::<BroadcastPacket>(buffer).or_else(|err| {
+        if let Ok(UntaggedBroadcastPacket::TargetLive(live_packet)) =
+            bincode::deserialize::<UntaggedBroadcastPacket>(buffer)
+        {
+            return Ok(BroadcastPacket::TargetLive(TargetLivePacket {
+                host_name: live_packet.host_name,
+                os: live_packet.os,
+                os_version: live_packet.os_version,
+                device_name: live_packet.device_name,
+                tag: String::new(),
+            }));
+        }
+
+        match bincode::deserialize::<LegacyBroadcastPacket>(buffer) {
+            Ok(LegacyBroadcastPacket::TargetLive(live_packet)) => {
+                Ok(BroadcastPacket::TargetLive(TargetLivePacket {
+                    host_name: live_packet.host_name,
+                    os: live_packet.os,
+                    os_version: live_packet.os_version,
+                    device_name: String::new(),
+                    tag: String::new(),
+                }))
+            }
+            Err(_) => Err(err),
+        }
+    })
+}
+
 pub struct Discover {
-    cache: Cache<IpAddr, Node>,
+    tracker: Arc<Mutex<NodeTracker>>,
     discoverable: Arc<AtomicBool>,
     write_exit_tx: Option<tokio::sync::oneshot::Sender<()>>,
     read_exit_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    write_task: Option<tokio::task::JoinHandle<()>>,
 }
 
 impl Discover {
@@ -46,7 +183,7 @@ impl Discover {
             local_lan_ip
         };
 
-        let stream = tokio::net::UdpSocket::bind((listen_ip, 48000)).await?;
+        let stream = tokio::net::UdpSocket::bind((listen_ip, LAN_DISCOVER_PORT)).await?;
         stream.set_broadcast(true)?;
 
         tracing::info!("lan discover listen on {}", stream.local_addr()?);
@@ -54,11 +191,8 @@ impl Discover {
         let live_packet = gen_target_live_packet()?;
         let local_host_name = live_packet.host_name.clone();
         let dead_packet = bincode::serialize(&BroadcastPacket::TargetDead)?;
-        let live_packet = bincode::serialize(&BroadcastPacket::TargetLive(live_packet))?;
 
-        let cache = Cache::builder()
-            .time_to_live(Duration::from_secs(17))
-            .build();
+        let tracker = Arc::new(Mutex::new(NodeTracker::new(NODE_TTL)));
 
         let writer = Arc::new(stream);
         let reader = writer.clone();
@@ -66,18 +200,36 @@ impl Discover {
         let (write_exit_tx, mut write_exit_rx) = tokio::sync::oneshot::channel();
         let (read_exit_tx, mut read_exit_rx) = tokio::sync::oneshot::channel();
         let discoverable = Arc::new(AtomicBool::new(true));
-        let cache_copy = cache.clone();
+        let tracker_copy = tracker.clone();
 
         tokio::spawn(async move {
-            let mut buffer = [0u8; 256];
+            let mut buffer = [0u8; 1024];
+            let mut sweep_ticker = tokio::time::interval(NODE_SWEEP_INTERVAL);
 
             loop {
-                let Err(tokio::sync::oneshot::error::TryRecvError::Empty) = read_exit_rx.try_recv() else {
-                    tracing::info!("lan discover broadcast recv loop exit");
-                    return;
+                let recv_result = tokio::select! {
+                    _ = &mut read_exit_rx => {
+                        tracing::info!("lan discover broadcast recv loop exit");
+                        return;
+                    }
+                    _ = sweep_ticker.tick() => {
+                        let events = tracker_copy
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .expire(Instant::now());
+
+                        for event in events {
+                            tracing::info!(?event, "lan discover target expired");
+                            report_discover_event(event);
+                        }
+                        continue;
+                    }
+                    recv_result = reader.recv_from(&mut buffer) => recv_result,
                 };
 
-                let (buffer_len, target_addr) = match reader.recv_from(&mut buffer).await {
+                // a failed or malformed packet is skipped, the other devices
+                // are still discovered
+                let (buffer_len, target_addr) = match recv_result {
                     Ok(v) => v,
                     Err(err) => {
                         tracing::error!(?err, "lan discover broadcast packet recv failed");
@@ -85,7 +237,7 @@ impl Discover {
                     }
                 };
 
-                let packet = match bincode::deserialize::<BroadcastPacket>(&buffer[..buffer_len]) {
+                let packet = match deserialize_broadcast_packet(&buffer[..buffer_len]) {
                     Ok(v) => v,
                     Err(err) => {
                         tracing::error!(
@@ -105,35 +257,58 @@ impl Discover {
 
                         tracing::info!(?target_addr, "lan discover target live");
 
-                        cache_copy
-                            .insert(
-                                target_addr.ip(),
-                                Node {
-                                    host_name: live_packet.host_name.to_string(),
-                                    addr: target_addr.ip(),
-                                    os: live_packet.os.to_string(),
-                                    os_version: live_packet.os_version.to_string(),
-                                },
-                            )
-                            .await;
+                        let node = Node {
+                            host_name: live_packet.host_name.to_string(),
+                            device_name: sanitize_device_name(&live_packet.device_name),
+                            addr: target_addr.ip(),
+                            os: live_packet.os.to_string(),
+                            os_version: live_packet.os_version.to_string(),
+                            nickname: None,
+                            tag: sanitize_device_tag(&live_packet.tag),
+                            last_seen: chrono::Utc::now().timestamp_millis(),
+                        };
+
+                        let event = tracker_copy
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .observe(node, Instant::now());
+
+                        if let Some(event) = event {
+                            report_discover_event(event);
+                        }
                     }
                     BroadcastPacket::TargetDead => {
                         tracing::info!(?target_addr, "lan discover target dead");
-                        cache_copy.invalidate(&target_addr.ip()).await;
+
+                        let event = tracker_copy
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .remove(target_addr.ip());
+
+                        if let Some(event) = event {
+                            report_discover_event(event);
+                        }
                     }
                 }
             }
         });
 
         let discoverable_copy = discoverable.clone();
-        tokio::spawn(async move {
+        let write_task = tokio::spawn(async move {
             let mut ticker = tokio::time::interval(Duration::from_secs(11));
 
             loop {
                 tokio::select! {
                     _ = ticker.tick() => (),
                     _ = &mut write_exit_rx => {
-                        let _ = writer.send(&dead_packet).await;
+                        // the other devices drop this one right away instead
+                        // of waiting for its entry to expire
+                        if let Err(err) = writer
+                            .send_to(&dead_packet, (Ipv4Addr::BROADCAST, LAN_DISCOVER_PORT))
+                            .await
+                        {
+                            tracing::warn!(?err, "lan discover broadcast dead packet failed");
+                        }
                         tracing::info!("lan discover broadcast loop exit");
                         return;
                     }
@@ -143,8 +318,23 @@ impl Discover {
                     continue;
                 }
 
+                // the user may rename or retag the device meanwhile
+                let live_packet = BroadcastPacket::TargetLive(TargetLivePacket {
+                    device_name: device_name(),
+                    tag: device_tag().unwrap_or_default(),
+                    ..live_packet.clone()
+                });
+
+                let live_packet = match bincode::serialize(&live_packet) {
+                    Ok(buffer) => buffer,
+                    Err(err) => {
+                        tracing::warn!(?err, "serialize lan discover live packet failed");
+                        continue;
+                    }
+                };
+
                 if let Err(err) = writer
-                    .send_to(&live_packet, (Ipv4Addr::BROADCAST, 48000))
+                    .send_to(&live_packet, (Ipv4Addr::BROADCAST, LAN_DISCOVER_PORT))
                     .await
                 {
                     tracing::warn!(?err, "lan discover broadcast failed");
@@ -153,15 +343,50 @@ impl Discover {
         });
 
         Ok(Self {
-            cache,
+            tracker,
             discoverable,
             write_exit_tx: Some(write_exit_tx),
             read_exit_tx: Some(read_exit_tx),
+            write_task: Some(write_task),
         })
     }
 
+    /// Stops discovering and waits until the other devices were told this
+    /// one is gone. Dropping it stops as well but doesn't wait.
+    pub async fn shutdown(mut self) {
+        self.stop();
+
+        if let Some(write_task) = self.write_task.take() {
+            let _ = write_task.await;
+        }
+    }
+
+    fn stop(&mut self) {
+        if let Some(tx) = self.write_exit_tx.take() {
+            let _ = tx.send(());
+        }
+
+        if let Some(tx) = self.read_exit_tx.take() {
+            let _ = tx.send(());
+        }
+
+        // a new discover starts without devices
+        let events = self
+            .tracker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+
+        for event in events {
+            report_discover_event(event);
+        }
+    }
+
     pub fn nodes_snapshot(&self) -> Vec<Node> {
-        self.cache.iter().map(|(_, node)| node).collect()
+        self.tracker
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .snapshot(Instant::now())
     }
 
     pub fn discoverable(&self) -> bool {
@@ -176,13 +401,7 @@ impl Discover {
 
 impl Drop for Discover {
     fn drop(&mut self) {
-        if let Some(tx) = self.write_exit_tx.take() {
-            let _ = tx.send(());
-        }
-
-        if let Some(tx) = self.read_exit_tx.take() {
-            let _ = tx.send(());
-        }
+        self.stop();
     }
 }
 
@@ -239,20 +458,7 @@ fn gen_target_live_packet() -> CoreResult<TargetLivePacket> {
         host_name,
         os,
         os_version,
+        device_name: device_name(),
+        tag: device_tag().unwrap_or_default(),
     })
 }
-
-fn convert_host_name_to_string(v: &OsStr) -> CoreResult<String> {
-    #[cfg(target_os = "windows")]
-    {
-        use crate::core_error;
-        use std::os::windows::ffi::OsStrExt;
-
-        let result: Vec<u16> = v.encode_wide().collect();
-        String::from_utf16(&result)
-            .map_err(|err| core_error!("convert host name to string failed ({:?})", err))
-    }
-
-    #[cfg(not(target_os = "windows"))]
-    Ok(v.to_string_lossy().to_string())
-}
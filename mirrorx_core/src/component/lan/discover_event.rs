@@ -0,0 +1,119 @@
+use super::discover::Node;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+/// How long a device stays listed without broadcasting, it broadcasts
+/// every 11 seconds.
+pub const NODE_TTL: Duration = Duration::from_secs(17);
+
+/// How often devices past [`NODE_TTL`] are looked for.
+pub const NODE_SWEEP_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A change of the discovered devices. The node carries the time it was
+/// seen last, the nickname is left to the caller.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DiscoverEvent {
+    Added {
+        node: Node,
+    },
+    /// The device broadcasts another name, tag or os version.
+    Updated {
+        node: Node,
+    },
+    /// The device went away or stopped broadcasting for [`NODE_TTL`].
+    Removed {
+        node: Node,
+    },
+}
+
+static DISCOVER_EVENT_TX: Lazy<tokio::sync::broadcast::Sender<DiscoverEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(64).0);
+
+pub fn subscribe_discover_events() -> tokio::sync::broadcast::Receiver<DiscoverEvent> {
+    DISCOVER_EVENT_TX.subscribe()
+}
+
+pub(crate) fn report_discover_event(event: DiscoverEvent) {
+    let _ = DISCOVER_EVENT_TX.send(event);
+}
+
+/// The devices currently on the LAN, turns their broadcasts into
+/// [`DiscoverEvent`]s.
+pub struct NodeTracker {
+    ttl: Duration,
+    nodes: HashMap<IpAddr, (Node, Instant)>,
+}
+
+impl NodeTracker {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            nodes: HashMap::new(),
+        }
+    }
+
+    /// A live packet of `node` arrived. Rebroadcasting the same device
+    /// only keeps it listed.
+    pub fn observe(&mut self, node: Node, now: Instant) -> Option<DiscoverEvent> {
+        match self.nodes.insert(node.addr, (node.clone(), now)) {
+            None => Some(DiscoverEvent::Added { node }),
+            Some((previous, _)) if !advertises_same(&previous, &node) => {
+                Some(DiscoverEvent::Updated { node })
+            }
+            Some(_) => None,
+        }
+    }
+
+    /// The device at `addr` said it's going away.
+    pub fn remove(&mut self, addr: IpAddr) -> Option<DiscoverEvent> {
+        self.nodes
+            .remove(&addr)
+            .map(|(node, _)| DiscoverEvent::Removed { node })
+    }
+
+    /// Drops the devices not seen for the ttl.
+    pub fn expire(&mut self, now: Instant) -> Vec<DiscoverEvent> {
+        let expired: Vec<IpAddr> = self
+            .nodes
+            .iter()
+            .filter(|(_, (_, seen_at))| now.saturating_duration_since(*seen_at) >= self.ttl)
+            .map(|(addr, _)| *addr)
+            .collect();
+
+        expired
+            .into_iter()
+            .filter_map(|addr| self.remove(addr))
+            .collect()
+    }
+
+    /// Drops every device, when discovery stops.
+    pub fn clear(&mut self) -> Vec<DiscoverEvent> {
+        self.nodes
+            .drain()
+            .map(|(_, (node, _))| DiscoverEvent::Removed { node })
+            .collect()
+    }
+
+    /// The devices seen within the ttl, whether or not they were swept yet.
+    pub fn snapshot(&self, now: Instant) -> Vec<Node> {
+        self.nodes
+            .values()
+            .filter(|(_, seen_at)| now.saturating_duration_since(*seen_at) < self.ttl)
+            .map(|(node, _)| node.clone())
+            .collect()
+    }
+}
+
+fn advertises_same(previous: &Node, node: &Node) -> bool {
+    previous.host_name == node.host_name
+        && previous.device_name == node.device_name
+        && previous.os == node.os
+        && previous.os_version == node.os_version
+        && previous.tag == node.tag
+}
@@ -1,2 +1,3 @@
 pub mod discover;
+pub mod discover_event;
 pub mod server;
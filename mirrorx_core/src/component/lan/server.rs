@@ -1,21 +1,54 @@
 use crate::{
-    api::endpoint::{create_passive_endpoint_client, EndPointStream},
+    api::{
+        endpoint::{
+            create_passive_endpoint_client,
+            message::EndPointMessage,
+            packet::{frame_codec, serialize_packet},
+            session_limit::SessionLimiter,
+            EndPointStream,
+        },
+        signaling::subscribe_message::VisitFailureReason,
+    },
     error::CoreResult,
 };
-use std::net::IpAddr;
+use futures::SinkExt;
+use std::{
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+
+/// TCP port devices accept LAN connections on.
+pub const LAN_SERVER_PORT: u16 = 48001;
+
+/// How long a turned down device gets to take the busy reply.
+const BUSY_REPLY_TIMEOUT: Duration = Duration::from_secs(3);
 
 pub struct Server {
+    local_addr: SocketAddr,
     exit_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    accept_task: Option<tokio::task::JoinHandle<()>>,
 }
 
 impl Server {
     pub async fn new(local_lan_ip: IpAddr) -> CoreResult<Self> {
-        let listener = tokio::net::TcpListener::bind((local_lan_ip, 48001)).await?;
+        Self::bind(
+            SocketAddr::new(local_lan_ip, LAN_SERVER_PORT),
+            SessionLimiter::global(),
+        )
+        .await
+    }
+
+    /// Listens on `addr` and counts the accepted sessions against `limiter`.
+    pub async fn bind(addr: SocketAddr, limiter: SessionLimiter) -> CoreResult<Self> {
+        let local_lan_ip = addr.ip();
+        let listener = tokio::net::TcpListener::bind(addr).await?;
         let local_addr = listener.local_addr()?;
         let (exit_tx, mut exit_rx) = tokio::sync::oneshot::channel();
         tracing::info!(?local_addr, "local lan server listen");
 
-        tokio::spawn(async move {
+        let accept_task = tokio::spawn(async move {
             loop {
                 let (stream, addr) = tokio::select! {
                     _ = &mut exit_rx => {
@@ -31,6 +64,15 @@ impl Server {
                     }
                 };
 
+                let Some(session_permit) = limiter.try_acquire() else {
+                    tracing::warn!(
+                        ?addr,
+                        "reject lan stream because incoming session limit reached"
+                    );
+                    tokio::spawn(reply_busy(stream));
+                    continue;
+                };
+
                 if let Err(err) = create_passive_endpoint_client(
                     crate::api::endpoint::id::EndPointID::LANID {
                         local_ip: local_lan_ip,
@@ -39,6 +81,7 @@ impl Server {
                     None,
                     EndPointStream::PassiveTCP(stream),
                     None,
+                    session_permit,
                 )
                 .await
                 {
@@ -50,9 +93,47 @@ impl Server {
         });
 
         Ok(Self {
+            local_addr,
             exit_tx: Some(exit_tx),
+            accept_task: Some(accept_task),
         })
     }
+
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Stops accepting and waits until the listener is closed.
+    pub async fn shutdown(mut self) {
+        if let Some(exit_tx) = self.exit_tx.take() {
+            let _ = exit_tx.send(());
+        }
+
+        if let Some(accept_task) = self.accept_task.take() {
+            let _ = accept_task.await;
+        }
+    }
+}
+
+/// Tells the device that the session limit is reached before the stream is
+/// closed, so it reports a busy device rather than a broken connection.
+async fn reply_busy(stream: TcpStream) {
+    let buffer = match serialize_packet(&EndPointMessage::VisitFailure(
+        VisitFailureReason::ServerBusy,
+    )) {
+        Ok(buffer) => buffer,
+        Err(err) => {
+            tracing::error!(?err, "serialize busy reply failed");
+            return;
+        }
+    };
+
+    let mut framed = Framed::new(stream, frame_codec());
+    match tokio::time::timeout(BUSY_REPLY_TIMEOUT, framed.send(buffer.into())).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => tracing::warn!(?err, "send busy reply failed"),
+        Err(_) => tracing::warn!("send busy reply timeout"),
+    }
 }
 
 impl Drop for Server {
@@ -2,9 +2,12 @@
 
 pub mod audio;
 pub mod desktop;
+pub mod fec;
 pub mod frame;
 pub mod fs;
 pub mod input;
 pub mod lan;
+pub mod parameter_sets;
+pub mod slice;
 pub mod video_decoder;
 pub mod video_encoder;
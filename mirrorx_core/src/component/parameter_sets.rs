@@ -0,0 +1,144 @@
+use crate::api::endpoint::message::{EndPointVideoFrame, EndPointVideoParameterSets};
+use std::{
+    ops::Range,
+    sync::{PoisonError, RwLock, RwLockReadGuard},
+};
+
+const NAL_UNIT_TYPE_IDR: u8 = 5;
+const NAL_UNIT_TYPE_SPS: u8 = 7;
+const NAL_UNIT_TYPE_PPS: u8 = 8;
+
+/// The NAL units of an Annex B H.264 packet, their types and where they
+/// are in the packet, start codes included.
+pub(crate) fn h264_nal_units(buffer: &[u8]) -> Vec<(u8, Range<usize>)> {
+    let mut starts = Vec::new();
+    let mut offset = 0;
+
+    while offset + 3 < buffer.len() {
+        // both 3 and 4 bytes start codes end with 00 00 01
+        if buffer[offset] == 0 && buffer[offset + 1] == 0 && buffer[offset + 2] == 1 {
+            let start = if offset > 0 && buffer[offset - 1] == 0 {
+                offset - 1
+            } else {
+                offset
+            };
+
+            starts.push((start, offset + 3));
+            offset += 3;
+        } else {
+            offset += 1;
+        }
+    }
+
+    starts
+        .iter()
+        .enumerate()
+        .map(|(index, &(start, header))| {
+            let end = starts
+                .get(index + 1)
+                .map_or(buffer.len(), |&(next_start, _)| next_start);
+            (buffer[header] & 0x1F, start..end)
+        })
+        .collect()
+}
+
+/// The SPS and PPS of a packet back to back with their start codes, `None`
+/// when the packet carries no SPS.
+pub fn extract_h264_parameter_sets(buffer: &[u8]) -> Option<Vec<u8>> {
+    let nal_units = h264_nal_units(buffer);
+
+    if !nal_units
+        .iter()
+        .any(|(nal_unit_type, _)| *nal_unit_type == NAL_UNIT_TYPE_SPS)
+    {
+        return None;
+    }
+
+    let parameter_sets = nal_units
+        .into_iter()
+        .filter(|(nal_unit_type, _)| {
+            *nal_unit_type == NAL_UNIT_TYPE_SPS || *nal_unit_type == NAL_UNIT_TYPE_PPS
+        })
+        .flat_map(|(_, range)| buffer[range].iter().copied())
+        .collect();
+
+    Some(parameter_sets)
+}
+
+/// Puts the parameter sets in front of an IDR slice that comes without
+/// them, so a fresh decoder opens with it. Only parameter sets of the frame
+/// size apply. Returns whether the frame was changed.
+pub fn prepend_parameter_sets(
+    parameter_sets: &EndPointVideoParameterSets,
+    frame: &mut EndPointVideoFrame,
+) -> bool {
+    if (parameter_sets.width, parameter_sets.height) != (frame.width, frame.height) {
+        return false;
+    }
+
+    let nal_unit_types: Vec<u8> = h264_nal_units(&frame.buffer)
+        .into_iter()
+        .map(|(nal_unit_type, _)| nal_unit_type)
+        .collect();
+
+    if !nal_unit_types.contains(&NAL_UNIT_TYPE_IDR) || nal_unit_types.contains(&NAL_UNIT_TYPE_SPS) {
+        return false;
+    }
+
+    let mut buffer = Vec::with_capacity(parameter_sets.buffer.len() + frame.buffer.len());
+    buffer.extend_from_slice(&parameter_sets.buffer);
+    buffer.extend_from_slice(&frame.buffer);
+    frame.buffer = buffer;
+
+    true
+}
+
+/// The parameter sets the encoder of a host currently produces. Encoders
+/// may leave them out of later keyframes once they sent them, the cache
+/// puts them back, and the viewer gets them ahead of the frames so a
+/// decoder it recreates opens with the first IDR slice.
+#[derive(Debug, Default)]
+pub struct ParameterSetCache {
+    current: RwLock<Option<EndPointVideoParameterSets>>,
+}
+
+impl ParameterSetCache {
+    /// Takes the parameter sets of an encoded frame, or puts the cached ones
+    /// in front of an IDR slice that lacks them. Returns the parameter sets
+    /// when they changed, a new size or tuning changes them.
+    pub fn observe(&self, frame: &mut EndPointVideoFrame) -> Option<EndPointVideoParameterSets> {
+        let Some(buffer) = extract_h264_parameter_sets(&frame.buffer) else {
+            if let Some(ref parameter_sets) = *self.read() {
+                prepend_parameter_sets(parameter_sets, frame);
+            }
+            return None;
+        };
+
+        let parameter_sets = EndPointVideoParameterSets {
+            width: frame.width,
+            height: frame.height,
+            buffer,
+        };
+
+        let mut current = self.current.write().unwrap_or_else(PoisonError::into_inner);
+        if current.as_ref() == Some(&parameter_sets) {
+            return None;
+        }
+
+        *current = Some(parameter_sets.clone());
+        Some(parameter_sets)
+    }
+
+    pub fn current(&self) -> Option<EndPointVideoParameterSets> {
+        self.read().clone()
+    }
+
+    /// Forgets the parameter sets, the next encode context sends its own.
+    pub fn clear(&self) {
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Option<EndPointVideoParameterSets>> {
+        self.current.read().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,213 @@
+use crate::{
+    api::endpoint::message::{EndPointVideoFrame, EndPointVideoFrameSlice},
+    component::parameter_sets::h264_nal_units,
+};
+use std::ops::Range;
+
+/// Upper bound of `slices_per_frame`, more slices cost compression and gain
+/// little on a desktop.
+pub const MAX_SLICES_PER_FRAME: u8 = 8;
+
+const NAL_UNIT_TYPE_NON_IDR: u8 = 1;
+const NAL_UNIT_TYPE_IDR: u8 = 5;
+
+/// Cuts an encoded H.264 frame at its slices. Parameter sets and other NAL
+/// units go with the slice following them, trailing ones with the last
+/// slice. A frame of a single slice comes back as the whole frame.
+pub fn split_h264_slices(frame: &EndPointVideoFrame) -> Vec<EndPointVideoFrameSlice> {
+    let mut boundaries = Vec::new();
+    let mut group_start = 0;
+    let mut group_has_slice = false;
+
+    for (nal_unit_type, range) in h264_nal_units(&frame.buffer) {
+        if nal_unit_type != NAL_UNIT_TYPE_NON_IDR && nal_unit_type != NAL_UNIT_TYPE_IDR {
+            continue;
+        }
+
+        if group_has_slice {
+            boundaries.push(group_start..range.start);
+            group_start = range.start;
+        }
+
+        group_has_slice = true;
+    }
+    boundaries.push(group_start..frame.buffer.len());
+
+    let slice_count = boundaries.len() as u8;
+
+    boundaries
+        .into_iter()
+        .enumerate()
+        .map(|(slice_index, range)| EndPointVideoFrameSlice {
+            seq: frame.seq,
+            slice_index: slice_index as u8,
+            slice_count,
+            width: frame.width,
+            height: frame.height,
+            pts: frame.pts,
+            buffer: frame.buffer[range].to_vec(),
+        })
+        .collect()
+}
+
+/// Keeps only the slices of a delta frame covering the rows of pixels
+/// `rows`, for an update of a small part of a static screen. The decoder
+/// shows the previous frame where slices are left out. Returns false and
+/// leaves the frame as it is for a keyframe, a frame of a single slice or a
+/// slice header it can't read.
+pub fn retain_h264_slices(frame: &mut EndPointVideoFrame, rows: Range<i32>) -> bool {
+    let nal_units = h264_nal_units(&frame.buffer);
+    if nal_units
+        .iter()
+        .any(|(nal_unit_type, _)| *nal_unit_type == NAL_UNIT_TYPE_IDR)
+    {
+        return false;
+    }
+
+    let slices = split_h264_slices(frame);
+    if slices.len() < 2 {
+        return false;
+    }
+
+    let mut first_macroblocks = Vec::with_capacity(slices.len());
+    for slice in slices.iter() {
+        let first_macroblock = h264_nal_units(&slice.buffer)
+            .into_iter()
+            .find(|(nal_unit_type, _)| *nal_unit_type == NAL_UNIT_TYPE_NON_IDR)
+            .and_then(|(_, range)| first_mb_in_slice(&slice.buffer[range]));
+
+        let Some(first_macroblock) = first_macroblock else {
+            return false;
+        };
+        first_macroblocks.push(first_macroblock);
+    }
+
+    // slices cover whole rows of 16x16 macroblocks in a desktop stream
+    let macroblocks_per_row = (frame.width as u32).div_ceil(16).max(1);
+    let first_row = rows.start.max(0) as u32 / 16;
+    let end_row = (rows.end.max(0) as u32).div_ceil(16);
+
+    let mut buffer = Vec::with_capacity(frame.buffer.len());
+    for (index, slice) in slices.iter().enumerate() {
+        let slice_first_row = first_macroblocks[index] / macroblocks_per_row;
+        let slice_end_row = first_macroblocks
+            .get(index + 1)
+            .map_or(u32::MAX, |next| next.div_ceil(macroblocks_per_row));
+
+        if slice_first_row < end_row && first_row < slice_end_row {
+            buffer.extend_from_slice(&slice.buffer);
+        }
+    }
+
+    if buffer.is_empty() {
+        return false;
+    }
+
+    frame.buffer = buffer;
+    true
+}
+
+/// `first_mb_in_slice` of a slice NAL unit, start code included, the first
+/// Exp-Golomb code after the NAL unit header.
+fn first_mb_in_slice(nal_unit: &[u8]) -> Option<u32> {
+    let header = nal_unit.windows(3).position(|window| window == [0, 0, 1])? + 3;
+
+    // the code is in the first bytes, drop emulation prevention bytes there
+    let mut bytes = Vec::with_capacity(8);
+    let mut zeros = 0;
+    for &byte in nal_unit.get(header + 1..)?.iter().take(12) {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        bytes.push(byte);
+    }
+
+    let bit = |index: usize| -> Option<u32> {
+        let byte = bytes.get(index / 8)?;
+        Some(((byte >> (7 - index % 8)) & 1) as u32)
+    };
+
+    let mut leading_zeros = 0;
+    while bit(leading_zeros)? == 0 {
+        leading_zeros += 1;
+        if leading_zeros > 31 {
+            return None;
+        }
+    }
+
+    let mut value = 0u32;
+    for index in 0..leading_zeros {
+        value = (value << 1) | bit(leading_zeros + 1 + index)?;
+    }
+
+    Some((1 << leading_zeros) - 1 + value)
+}
+
+/// Puts the slices of a frame back together on the viewer. Slices of one
+/// frame are collected until all of them arrived, a slice of a later frame
+/// drops an incomplete one, forward error correction may restore it.
+#[derive(Debug, Default)]
+pub struct SliceAssembler {
+    seq: Option<u64>,
+    slices: Vec<Option<Vec<u8>>>,
+    dropped: u64,
+}
+
+impl SliceAssembler {
+    /// Takes a slice, returns the frame once its last slice arrived.
+    pub fn push(&mut self, slice: EndPointVideoFrameSlice) -> Option<EndPointVideoFrame> {
+        if slice.slice_count == 0 || slice.slice_index >= slice.slice_count {
+            tracing::warn!(
+                slice_index = ?slice.slice_index,
+                slice_count = ?slice.slice_count,
+                "drop malformed video frame slice"
+            );
+            return None;
+        }
+
+        match self.seq {
+            // the frame was completed or given up already
+            Some(seq) if slice.seq < seq => return None,
+            Some(seq) if slice.seq == seq && self.slices.len() == slice.slice_count as usize => {}
+            _ => {
+                if self.slices.iter().any(Option::is_some) {
+                    self.dropped += 1;
+                    tracing::warn!(seq = ?self.seq, "drop incomplete sliced video frame");
+                }
+
+                self.seq = Some(slice.seq);
+                self.slices = vec![None; slice.slice_count as usize];
+            }
+        }
+
+        self.slices[slice.slice_index as usize] = Some(slice.buffer);
+
+        if self.slices.iter().any(Option::is_none) {
+            return None;
+        }
+
+        let buffer = std::mem::take(&mut self.slices)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect();
+
+        // later slices of this frame are duplicates
+        self.seq = Some(slice.seq + 1);
+
+        Some(EndPointVideoFrame {
+            seq: slice.seq,
+            width: slice.width,
+            height: slice.height,
+            pts: slice.pts,
+            buffer,
+        })
+    }
+
+    /// Frames given up because a slice of them never arrived.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+}
@@ -0,0 +1,226 @@
+use super::keyframe::is_keyframe;
+use crate::{
+    api::endpoint::{
+        handlers::video_frame::VideoDecodeMessage, id::EndPointID, message::VideoCodec,
+    },
+    utility::config_cell::ConfigCell,
+};
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+};
+
+/// Decoded frames of the current size the automatic budget holds. Encoded
+/// frames are a fraction of that, so this leaves room for them as well.
+pub const AUTO_BUDGET_FRAMES: u64 = 24;
+
+/// Smallest automatic budget, small screens still get room for keyframes.
+pub const MIN_AUTO_BUDGET_BYTES: u64 = 64 * 1024 * 1024;
+
+static MEDIA_BUDGET_BYTES: ConfigCell<u64> = ConfigCell::new(|| 0);
+
+static MEDIA_BUDGETS: Lazy<DashMap<EndPointID, Arc<MediaBudget>>> = Lazy::new(DashMap::new);
+
+/// Caps the memory the video of one session holds on the way from the
+/// connection to the screen. Zero sizes the budget from the resolution of
+/// the session.
+pub fn set_media_budget_bytes(bytes: u64) {
+    MEDIA_BUDGET_BYTES.set(bytes);
+}
+
+pub fn media_budget_bytes() -> u64 {
+    MEDIA_BUDGET_BYTES.get()
+}
+
+/// Budget of a session with frames of this size, [`AUTO_BUDGET_FRAMES`]
+/// decoded NV12 frames, the largest format the decoder renders.
+pub fn auto_budget_bytes(width: i32, height: i32) -> u64 {
+    let frame_bytes = width.max(0) as u64 * height.max(0) as u64 * 3 / 2;
+    (frame_bytes * AUTO_BUDGET_FRAMES).max(MIN_AUTO_BUDGET_BYTES)
+}
+
+/// Memory held by the video of a session, summed over its three stages:
+/// frames held for reordering and FEC, encoded frames waiting for the
+/// decoder and decoded frames waiting for the renderer.
+#[derive(Debug)]
+pub struct MediaBudget {
+    configured_bytes: u64,
+    limit_bytes: AtomicU64,
+    reassembly_bytes: AtomicU64,
+    queued_bytes: AtomicU64,
+    render_bytes: AtomicU64,
+    dropped_frames: AtomicU64,
+    dropped_renders: AtomicU64,
+}
+
+impl Default for MediaBudget {
+    fn default() -> Self {
+        MediaBudget::new(media_budget_bytes())
+    }
+}
+
+impl MediaBudget {
+    /// Zero sizes the budget by the frames, see [`auto_budget_bytes`].
+    pub fn new(configured_bytes: u64) -> Self {
+        Self {
+            configured_bytes,
+            limit_bytes: AtomicU64::new(configured_bytes),
+            reassembly_bytes: AtomicU64::new(0),
+            queued_bytes: AtomicU64::new(0),
+            render_bytes: AtomicU64::new(0),
+            dropped_frames: AtomicU64::new(0),
+            dropped_renders: AtomicU64::new(0),
+        }
+    }
+
+    /// The cap, zero until an automatic budget saw the first frame.
+    pub fn limit_bytes(&self) -> u64 {
+        self.limit_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn buffered_bytes(&self) -> u64 {
+        self.reassembly_bytes.load(Ordering::Relaxed)
+            + self.queued_bytes.load(Ordering::Relaxed)
+            + self.render_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Encoded frames left out to stay in the budget.
+    pub fn dropped_frames(&self) -> u64 {
+        self.dropped_frames.load(Ordering::Relaxed)
+    }
+
+    /// Decoded frames not handed to the renderer to stay in the budget.
+    pub fn dropped_renders(&self) -> u64 {
+        self.dropped_renders.load(Ordering::Relaxed)
+    }
+
+    /// Sizes an automatic budget for frames of this size.
+    pub fn set_frame_size(&self, width: i32, height: i32) {
+        if self.configured_bytes == 0 {
+            self.limit_bytes
+                .store(auto_budget_bytes(width, height), Ordering::Relaxed);
+        }
+    }
+
+    pub fn set_reassembly_bytes(&self, bytes: u64) {
+        self.reassembly_bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    pub fn set_queued_bytes(&self, bytes: u64) {
+        self.queued_bytes.store(bytes, Ordering::Relaxed);
+    }
+
+    fn exceeded_by(&self, extra_bytes: u64) -> bool {
+        let limit_bytes = self.limit_bytes();
+        limit_bytes > 0 && self.buffered_bytes() + extra_bytes > limit_bytes
+    }
+
+    /// Charges a decoded frame until the renderer drops the returned charge,
+    /// or returns `None` when the frame doesn't fit. Leaving a decoded frame
+    /// out breaks nothing, the renderer shows the next one.
+    pub fn admit_render(self: &Arc<Self>, bytes: u64) -> Option<Arc<RenderCharge>> {
+        if self.exceeded_by(bytes) {
+            self.dropped_renders.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        self.render_bytes.fetch_add(bytes, Ordering::Relaxed);
+        Some(Arc::new(RenderCharge {
+            budget: self.clone(),
+            bytes,
+        }))
+    }
+
+    /// Drops the oldest frames waiting for the decoder until the budget
+    /// holds, keyframes last since decoding resumes from them. Frames after
+    /// a dropped one can't be decoded until the next keyframe anyway. Returns
+    /// how many frames were dropped.
+    pub fn shed(&self, pending: &mut VecDeque<VideoDecodeMessage>, codec: &VideoCodec) -> u64 {
+        let mut queued = queued_bytes(pending);
+        let mut dropped = 0;
+
+        for keyframes in [false, true] {
+            let mut index = 0;
+            while index < pending.len() && self.exceeded_with(queued) {
+                let droppable = match &pending[index] {
+                    VideoDecodeMessage::Frame(video_frame) => {
+                        keyframes || !is_keyframe(codec, &video_frame.buffer)
+                    }
+                    _ => false,
+                };
+
+                if !droppable {
+                    index += 1;
+                    continue;
+                }
+
+                if let Some(VideoDecodeMessage::Frame(video_frame)) = pending.remove(index) {
+                    queued -= video_frame.buffer.len() as u64;
+                    dropped += 1;
+                }
+            }
+        }
+
+        self.set_queued_bytes(queued);
+        self.dropped_frames.fetch_add(dropped, Ordering::Relaxed);
+        dropped
+    }
+
+    /// Whether the budget is exceeded with this many bytes waiting for the
+    /// decoder.
+    fn exceeded_with(&self, queued_bytes: u64) -> bool {
+        let limit_bytes = self.limit_bytes();
+        let buffered_bytes = self.reassembly_bytes.load(Ordering::Relaxed)
+            + queued_bytes
+            + self.render_bytes.load(Ordering::Relaxed);
+
+        limit_bytes > 0 && buffered_bytes > limit_bytes
+    }
+}
+
+/// Bytes of the encoded frames in a decode queue.
+pub fn queued_bytes(pending: &VecDeque<VideoDecodeMessage>) -> u64 {
+    pending
+        .iter()
+        .map(|message| match message {
+            VideoDecodeMessage::Frame(video_frame) => video_frame.buffer.len() as u64,
+            _ => 0,
+        })
+        .sum()
+}
+
+/// A decoded frame on its way to the screen, released with the last clone
+/// of the frame.
+#[derive(Debug)]
+pub struct RenderCharge {
+    budget: Arc<MediaBudget>,
+    bytes: u64,
+}
+
+impl Drop for RenderCharge {
+    fn drop(&mut self) {
+        self.budget
+            .render_bytes
+            .fetch_sub(self.bytes, Ordering::Relaxed);
+    }
+}
+
+pub(crate) fn register_media_budget(endpoint_id: EndPointID, budget: Arc<MediaBudget>) {
+    MEDIA_BUDGETS.insert(endpoint_id, budget);
+}
+
+pub(crate) fn unregister_media_budget(endpoint_id: &EndPointID, budget: &Arc<MediaBudget>) {
+    MEDIA_BUDGETS.remove_if(endpoint_id, |_, registered| Arc::ptr_eq(registered, budget));
+}
+
+/// The budget of the session watching the remote endpoint, if any.
+pub(crate) fn media_budget(endpoint_id: &EndPointID) -> Option<Arc<MediaBudget>> {
+    MEDIA_BUDGETS
+        .get(endpoint_id)
+        .map(|entry| entry.value().clone())
+}
@@ -0,0 +1,95 @@
+use crate::api::endpoint::id::EndPointID;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+/// Hardware decoder failures after which a session sticks with the software
+/// decoder, a bitstream or driver that broke it this often will again.
+pub const MAX_HARDWARE_DECODE_FAILURES: u32 = 3;
+
+static DECODER_FALLBACK_TX: Lazy<tokio::sync::broadcast::Sender<DecoderFallback>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecoderKind {
+    Hardware,
+    Software,
+}
+
+/// Whether this platform has a hardware decoder ffmpeg can use,
+/// VideoToolbox on macOS and D3D11VA on Windows.
+pub fn hardware_decode_supported() -> bool {
+    cfg!(any(target_os = "macos", target_os = "windows"))
+}
+
+/// Picks the decoder of each decode context of a session. A failing
+/// hardware decoder is replaced by the software one until the stream
+/// starts over, after [`MAX_HARDWARE_DECODE_FAILURES`] for the rest of the
+/// session.
+#[derive(Debug)]
+pub struct DecoderSelection {
+    prefer_hardware: bool,
+    hardware_failures: u32,
+    fallen_back: bool,
+}
+
+impl DecoderSelection {
+    pub fn new(prefer_hardware: bool) -> Self {
+        Self {
+            prefer_hardware,
+            hardware_failures: 0,
+            fallen_back: false,
+        }
+    }
+
+    /// The decoder the next decode context opens with.
+    pub fn next(&self) -> DecoderKind {
+        if self.prefer_hardware
+            && !self.fallen_back
+            && self.hardware_failures < MAX_HARDWARE_DECODE_FAILURES
+        {
+            DecoderKind::Hardware
+        } else {
+            DecoderKind::Software
+        }
+    }
+
+    /// Records a failure of the hardware decoder, the stream continues in
+    /// software. Returns whether the session gave up on hardware for good.
+    pub fn hardware_failed(&mut self) -> bool {
+        self.hardware_failures += 1;
+        self.fallen_back = true;
+        self.hardware_failures >= MAX_HARDWARE_DECODE_FAILURES
+    }
+
+    /// The stream starts over, e.g. with a new size, and tries the hardware
+    /// decoder again unless it failed too often.
+    pub fn reset(&mut self) {
+        self.fallen_back = false;
+    }
+
+    pub fn hardware_failures(&self) -> u32 {
+        self.hardware_failures
+    }
+}
+
+/// A session that switched to the software decoder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DecoderFallback {
+    pub endpoint_id: String,
+    pub reason: String,
+    /// The session stays with the software decoder from now on.
+    pub permanent: bool,
+}
+
+pub fn subscribe_decoder_fallback() -> tokio::sync::broadcast::Receiver<DecoderFallback> {
+    DECODER_FALLBACK_TX.subscribe()
+}
+
+pub(crate) fn announce_decoder_fallback(endpoint_id: &EndPointID, reason: String, permanent: bool) {
+    let _ = DECODER_FALLBACK_TX.send(DecoderFallback {
+        endpoint_id: endpoint_id.to_string(),
+        reason,
+        permanent,
+    });
+}
@@ -0,0 +1,258 @@
+use super::keyframe::is_keyframe;
+use crate::{
+    api::endpoint::{
+        id::EndPointID,
+        message::{EndPointVideoFrame, VideoCodec},
+    },
+    bail_core,
+    component::fs::cache::CacheLease,
+    error::CoreResult,
+};
+use dashmap::{mapref::entry::Entry, DashMap};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+pub const MAX_FRAME_DUMP_BYTES: u64 = 2 * 1024 * 1024 * 1024;
+
+pub const MAX_FRAME_DUMP_SECS: u64 = 60 * 60;
+
+static FRAME_DUMPS: Lazy<DashMap<EndPointID, FrameDump>> = Lazy::new(DashMap::new);
+
+static FRAME_DUMP_FINISHED_TX: Lazy<tokio::sync::broadcast::Sender<FrameDumpSummary>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+/// Caps of a dump, whichever is hit first ends it.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub struct FrameDumpLimits {
+    pub max_bytes: u64,
+    pub max_secs: u64,
+}
+
+impl Default for FrameDumpLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: 256 * 1024 * 1024,
+            max_secs: 120,
+        }
+    }
+}
+
+impl FrameDumpLimits {
+    pub fn sanitize(self) -> Self {
+        Self {
+            max_bytes: self.max_bytes.clamp(1, MAX_FRAME_DUMP_BYTES),
+            max_secs: self.max_secs.clamp(1, MAX_FRAME_DUMP_SECS),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameDumpEnd {
+    Stopped,
+    SizeLimit,
+    TimeLimit,
+    SessionEnded,
+    WriteFailed,
+}
+
+/// What a dump recorded, written next to the stream as json.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct FrameDumpSummary {
+    pub endpoint_id: String,
+    /// The received payloads as is, an Annex B elementary stream that
+    /// `ffplay -f h264` or `ffmpeg -f h264 -i` reads, `-f hevc` for HEVC.
+    pub stream_path: PathBuf,
+    pub codec: VideoCodec,
+    /// Frame sizes in the order the remote display changed, the first one
+    /// the stream starts with.
+    pub resolutions: Vec<(i32, i32)>,
+    pub frames: u64,
+    pub bytes: u64,
+    pub end: Option<FrameDumpEnd>,
+}
+
+/// Records the encoded frames of a session, starting with a keyframe so
+/// the stream replays on its own.
+pub struct FrameDump {
+    writer: BufWriter<File>,
+    /// Keeps the stream out of cache eviction while it's written.
+    _lease: CacheLease,
+    summary: FrameDumpSummary,
+    limits: FrameDumpLimits,
+    started_at: Instant,
+    waiting_keyframe: bool,
+}
+
+impl FrameDump {
+    /// Creates `<name>.h264` or `<name>.hevc` in `dir`. Other codecs don't
+    /// make an elementary stream without a container.
+    pub fn create(
+        dir: &Path,
+        name: &str,
+        endpoint_id: &EndPointID,
+        codec: VideoCodec,
+        limits: FrameDumpLimits,
+    ) -> CoreResult<Self> {
+        let extension = match codec {
+            VideoCodec::H264 => "h264",
+            VideoCodec::Hevc => "hevc",
+            _ => bail_core!("frame dump supports h264 and hevc streams only"),
+        };
+
+        std::fs::create_dir_all(dir)?;
+        let stream_path = dir.join(name).with_extension(extension);
+        let writer = BufWriter::new(File::create(&stream_path)?);
+
+        Ok(Self {
+            writer,
+            _lease: CacheLease::new(stream_path.clone()),
+            waiting_keyframe: true,
+            summary: FrameDumpSummary {
+                endpoint_id: endpoint_id.to_string(),
+                stream_path,
+                codec,
+                resolutions: Vec::new(),
+                frames: 0,
+                bytes: 0,
+                end: None,
+            },
+            limits: limits.sanitize(),
+            started_at: Instant::now(),
+        })
+    }
+
+    pub fn write(&mut self, frame: &EndPointVideoFrame) -> Option<FrameDumpEnd> {
+        self.write_at(frame, Instant::now())
+    }
+
+    /// Appends the payload, returns why the dump ends once a cap is hit. The
+    /// frame that would exceed the size cap isn't written.
+    pub fn write_at(&mut self, frame: &EndPointVideoFrame, now: Instant) -> Option<FrameDumpEnd> {
+        if now.saturating_duration_since(self.started_at)
+            >= Duration::from_secs(self.limits.max_secs)
+        {
+            return Some(FrameDumpEnd::TimeLimit);
+        }
+
+        if self.waiting_keyframe {
+            if !is_keyframe(&self.summary.codec, &frame.buffer) {
+                return None;
+            }
+            self.waiting_keyframe = false;
+        }
+
+        if self.summary.bytes + frame.buffer.len() as u64 > self.limits.max_bytes {
+            return Some(FrameDumpEnd::SizeLimit);
+        }
+
+        if let Err(err) = self.writer.write_all(&frame.buffer) {
+            tracing::error!(?err, "write frame dump failed");
+            return Some(FrameDumpEnd::WriteFailed);
+        }
+
+        if self.summary.resolutions.last() != Some(&(frame.width, frame.height)) {
+            self.summary.resolutions.push((frame.width, frame.height));
+        }
+
+        self.summary.frames += 1;
+        self.summary.bytes += frame.buffer.len() as u64;
+
+        None
+    }
+
+    /// Flushes the stream and writes the summary next to it.
+    pub fn finish(mut self, end: FrameDumpEnd) -> CoreResult<FrameDumpSummary> {
+        self.writer.flush()?;
+        self.summary.end = Some(end);
+
+        let summary_path = self.summary.stream_path.with_extension("json");
+        std::fs::write(summary_path, serde_json::to_vec_pretty(&self.summary)?)?;
+
+        Ok(self.summary)
+    }
+}
+
+/// Starts recording the frames a session receives into `dir`, returns the
+/// path of the stream. The dump holds the remote screen content unredacted,
+/// callers must have the user's consent.
+pub fn start_frame_dump(
+    endpoint_id: EndPointID,
+    codec: VideoCodec,
+    dir: &Path,
+    limits: FrameDumpLimits,
+) -> CoreResult<PathBuf> {
+    let Entry::Vacant(entry) = FRAME_DUMPS.entry(endpoint_id) else {
+        bail_core!("frame dump of this session is running already");
+    };
+
+    let name = format!(
+        "frames-{}-{}",
+        dump_label(&endpoint_id),
+        chrono::Local::now().format("%Y%m%d%H%M%S")
+    );
+
+    let dump = FrameDump::create(dir, &name, &endpoint_id, codec, limits)?;
+    let stream_path = dump.summary.stream_path.clone();
+    entry.insert(dump);
+
+    tracing::warn!(?endpoint_id, ?stream_path, "frame dump started");
+    Ok(stream_path)
+}
+
+/// Ends the dump of a session, `None` when none runs.
+pub fn stop_frame_dump(endpoint_id: &EndPointID) -> CoreResult<Option<FrameDumpSummary>> {
+    finish_frame_dump(endpoint_id, FrameDumpEnd::Stopped)
+}
+
+pub fn subscribe_frame_dump_finished() -> tokio::sync::broadcast::Receiver<FrameDumpSummary> {
+    FRAME_DUMP_FINISHED_TX.subscribe()
+}
+
+/// Hands a received frame to the dump of its session, if one runs.
+pub(crate) fn dump_frame(endpoint_id: &EndPointID, frame: &EndPointVideoFrame) {
+    if FRAME_DUMPS.is_empty() {
+        return;
+    }
+
+    let end = match FRAME_DUMPS.get_mut(endpoint_id) {
+        Some(mut dump) => dump.write(frame),
+        None => return,
+    };
+
+    if let Some(end) = end {
+        if let Err(err) = finish_frame_dump(endpoint_id, end) {
+            tracing::error!(?err, "finish frame dump failed");
+        }
+    }
+}
+
+pub(crate) fn finish_frame_dump(
+    endpoint_id: &EndPointID,
+    end: FrameDumpEnd,
+) -> CoreResult<Option<FrameDumpSummary>> {
+    let Some((_, dump)) = FRAME_DUMPS.remove(endpoint_id) else {
+        return Ok(None);
+    };
+
+    let summary = dump.finish(end)?;
+    tracing::info!(?summary, "frame dump finished");
+    let _ = FRAME_DUMP_FINISHED_TX.send(summary.clone());
+
+    Ok(Some(summary))
+}
+
+fn dump_label(endpoint_id: &EndPointID) -> String {
+    match endpoint_id {
+        EndPointID::DeviceID {
+            remote_device_id, ..
+        } => remote_device_id.to_string(),
+        EndPointID::LANID { remote_ip, .. } => remote_ip.to_string().replace([':', '.'], "_"),
+    }
+}
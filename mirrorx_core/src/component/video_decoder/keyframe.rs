@@ -0,0 +1,110 @@
+use crate::api::endpoint::message::VideoCodec;
+
+const NAL_UNIT_TYPE_IDR: u8 = 5;
+const NAL_UNIT_TYPE_SPS: u8 = 7;
+
+// HEVC IRAP pictures, BLA to CRA, and the VPS sent ahead of them
+const HEVC_NAL_UNIT_TYPES_IRAP: std::ops::RangeInclusive<u8> = 16..=21;
+const HEVC_NAL_UNIT_TYPE_VPS: u8 = 32;
+
+/// Whether a packet of the codec can start a decode.
+pub fn is_keyframe(codec: &VideoCodec, buffer: &[u8]) -> bool {
+    match codec {
+        VideoCodec::Hevc => is_hevc_keyframe(buffer),
+        _ => is_h264_keyframe(buffer),
+    }
+}
+
+/// Whether an Annex B H.264 packet can start a decode, that is it carries an
+/// IDR slice or the sequence parameters sent along with one.
+pub fn is_h264_keyframe(buffer: &[u8]) -> bool {
+    let mut offset = 0;
+
+    while offset + 3 < buffer.len() {
+        // both 3 and 4 bytes start codes end with 00 00 01
+        if buffer[offset] == 0 && buffer[offset + 1] == 0 && buffer[offset + 2] == 1 {
+            let nal_unit_type = buffer[offset + 3] & 0x1F;
+            if nal_unit_type == NAL_UNIT_TYPE_IDR || nal_unit_type == NAL_UNIT_TYPE_SPS {
+                return true;
+            }
+
+            offset += 3;
+        } else {
+            offset += 1;
+        }
+    }
+
+    false
+}
+
+/// Whether an Annex B HEVC packet can start a decode, that is it carries an
+/// IRAP picture or the video parameters sent along with one.
+pub fn is_hevc_keyframe(buffer: &[u8]) -> bool {
+    let mut offset = 0;
+
+    while offset + 3 < buffer.len() {
+        if buffer[offset] == 0 && buffer[offset + 1] == 0 && buffer[offset + 2] == 1 {
+            let nal_unit_type = (buffer[offset + 3] >> 1) & 0x3F;
+            if HEVC_NAL_UNIT_TYPES_IRAP.contains(&nal_unit_type)
+                || nal_unit_type == HEVC_NAL_UNIT_TYPE_VPS
+            {
+                return true;
+            }
+
+            offset += 3;
+        } else {
+            offset += 1;
+        }
+    }
+
+    false
+}
+
+/// Drops packets until a keyframe arrives, so a fresh decoder never renders
+/// the garbage predicted from reference frames it hasn't seen.
+#[derive(Debug)]
+pub struct KeyFrameGate {
+    codec: VideoCodec,
+    waiting: bool,
+    dropped: u64,
+}
+
+impl Default for KeyFrameGate {
+    fn default() -> Self {
+        Self::new(VideoCodec::default())
+    }
+}
+
+impl KeyFrameGate {
+    pub fn new(codec: VideoCodec) -> Self {
+        Self {
+            codec,
+            waiting: true,
+            dropped: 0,
+        }
+    }
+
+    pub fn admit(&mut self, buffer: &[u8]) -> bool {
+        if self.waiting {
+            if !is_keyframe(&self.codec, buffer) {
+                self.dropped += 1;
+                return false;
+            }
+
+            if self.dropped > 0 {
+                tracing::info!(dropped = ?self.dropped, "drop packets before keyframe");
+            }
+
+            self.waiting = false;
+            self.dropped = 0;
+        }
+
+        true
+    }
+
+    /// Waits for the next keyframe again, needed whenever the decoder is
+    /// recreated.
+    pub fn reset(&mut self) {
+        self.waiting = true;
+    }
+}
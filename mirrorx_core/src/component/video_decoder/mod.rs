@@ -1 +1,7 @@
+pub mod budget;
+pub mod fallback;
+pub mod frame_dump;
+pub mod keyframe;
+pub mod rate_limit;
+pub mod thumbnail;
 pub mod video_decoder;
@@ -0,0 +1,237 @@
+use super::{budget::MediaBudget, fallback::DecoderKind, keyframe::is_keyframe};
+use crate::api::endpoint::message::VideoCodec;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc, RwLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+static DECODE_FPS_CAP: Lazy<RwLock<u32>> = Lazy::new(|| RwLock::new(0));
+
+/// Caps the frames this device renders per second, whatever the remote side
+/// captures at. Zero renders every frame. Applies to sessions started
+/// afterwards.
+pub fn set_decode_fps_cap(fps_cap: u32) {
+    if let Ok(mut decode_fps_cap) = DECODE_FPS_CAP.write() {
+        *decode_fps_cap = fps_cap;
+    }
+}
+
+pub fn decode_fps_cap() -> u32 {
+    DECODE_FPS_CAP.read().map(|fps_cap| *fps_cap).unwrap_or(0)
+}
+
+/// Whether no later frame is predicted from an Annex B H.264 packet, that is
+/// every slice it carries has a `nal_ref_idc` of zero. Only such frames can
+/// be left out without breaking the frames after them.
+pub fn is_h264_disposable(buffer: &[u8]) -> bool {
+    let mut offset = 0;
+    let mut has_slice = false;
+
+    while offset + 3 < buffer.len() {
+        if buffer[offset] == 0 && buffer[offset + 1] == 0 && buffer[offset + 2] == 1 {
+            let nal_header = buffer[offset + 3];
+            let nal_unit_type = nal_header & 0x1F;
+
+            // coded slices, 5 is IDR
+            if (1..=5).contains(&nal_unit_type) {
+                if nal_header & 0x60 != 0 {
+                    return false;
+                }
+
+                has_slice = true;
+            }
+
+            offset += 3;
+        } else {
+            offset += 1;
+        }
+    }
+
+    has_slice
+}
+
+/// Whether no later frame is predicted from an Annex B HEVC packet, that is
+/// every slice it carries is a sub-layer non-reference picture, the even
+/// VCL NAL unit types below 16.
+pub fn is_hevc_disposable(buffer: &[u8]) -> bool {
+    let mut offset = 0;
+    let mut has_slice = false;
+
+    while offset + 3 < buffer.len() {
+        if buffer[offset] == 0 && buffer[offset + 1] == 0 && buffer[offset + 2] == 1 {
+            let nal_unit_type = (buffer[offset + 3] >> 1) & 0x3F;
+
+            // coded slices, IRAP pictures from 16 on
+            if nal_unit_type < 32 {
+                if nal_unit_type >= 16 || nal_unit_type % 2 == 1 {
+                    return false;
+                }
+
+                has_slice = true;
+            }
+
+            offset += 3;
+        } else {
+            offset += 1;
+        }
+    }
+
+    has_slice
+}
+
+/// Whether no later frame is predicted from a packet of the codec.
+pub fn is_disposable(codec: &VideoCodec, buffer: &[u8]) -> bool {
+    match codec {
+        VideoCodec::Hevc => is_hevc_disposable(buffer),
+        _ => is_h264_disposable(buffer),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeDecision {
+    /// Decode and render the frame.
+    Present,
+    /// Decode the frame for the ones predicted from it, but don't render it.
+    DecodeOnly,
+    /// Leave the frame out, nothing depends on it.
+    Drop,
+}
+
+/// Spaces the rendered frames out to the fps cap. Frames due before their
+/// time are dropped when nothing references them, and only decoded when
+/// something does, which still saves the conversion and the upload of them.
+/// Keyframes are always rendered.
+#[derive(Debug)]
+pub struct DecodeRateLimiter {
+    codec: VideoCodec,
+    interval: Option<Duration>,
+    next_due: Option<Instant>,
+}
+
+impl DecodeRateLimiter {
+    pub fn new(fps_cap: u32, codec: VideoCodec) -> Self {
+        Self {
+            codec,
+            interval: (fps_cap > 0).then(|| Duration::from_secs(1) / fps_cap),
+            next_due: None,
+        }
+    }
+
+    pub fn set_codec(&mut self, codec: VideoCodec) {
+        self.codec = codec;
+    }
+
+    pub fn admit(&mut self, buffer: &[u8], now: Instant) -> DecodeDecision {
+        let Some(interval) = self.interval else {
+            return DecodeDecision::Present;
+        };
+
+        let due = self.next_due.is_none_or(|next_due| now >= next_due);
+
+        if due || is_keyframe(&self.codec, buffer) {
+            self.next_due = Some(now + interval);
+            DecodeDecision::Present
+        } else if is_disposable(&self.codec, buffer) {
+            DecodeDecision::Drop
+        } else {
+            DecodeDecision::DecodeOnly
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DecodeStats {
+    dropped_frames: AtomicU64,
+    unpresented_frames: AtomicU64,
+    decoder: AtomicU8,
+    hardware_fallbacks: AtomicU64,
+    keyframe_request: Notify,
+    budget: Arc<MediaBudget>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodeStatsSnapshot {
+    /// Frames left out before decoding, to keep up or to stay under the cap.
+    pub dropped_frames: u64,
+    /// Frames decoded only as references, never rendered.
+    pub unpresented_frames: u64,
+    /// Video held between the connection and the screen.
+    pub buffered_bytes: u64,
+    /// Cap of `buffered_bytes`, zero until the first frame sized it.
+    pub budget_bytes: u64,
+    /// Frames left out before decoding to stay in the budget, on top of
+    /// `dropped_frames`.
+    pub budget_dropped_frames: u64,
+    /// Frames decoded but not rendered to stay in the budget, the renderer
+    /// doesn't keep up.
+    pub budget_dropped_renders: u64,
+    /// The decoder of the current stream, `None` until the first frame.
+    pub decoder: Option<DecoderKind>,
+    /// Times the hardware decoder failed and the software one took over.
+    pub hardware_fallbacks: u64,
+}
+
+impl DecodeStats {
+    pub fn snapshot(&self) -> DecodeStatsSnapshot {
+        DecodeStatsSnapshot {
+            dropped_frames: self.dropped_frames.load(Ordering::Relaxed),
+            unpresented_frames: self.unpresented_frames.load(Ordering::Relaxed),
+            buffered_bytes: self.budget.buffered_bytes(),
+            budget_bytes: self.budget.limit_bytes(),
+            budget_dropped_frames: self.budget.dropped_frames(),
+            budget_dropped_renders: self.budget.dropped_renders(),
+            decoder: self.decoder(),
+            hardware_fallbacks: self.hardware_fallbacks.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn budget(&self) -> &Arc<MediaBudget> {
+        &self.budget
+    }
+
+    pub fn record_dropped(&self, frames: u64) {
+        self.dropped_frames.fetch_add(frames, Ordering::Relaxed);
+    }
+
+    pub fn record_unpresented(&self) {
+        self.unpresented_frames.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn decoder(&self) -> Option<DecoderKind> {
+        match self.decoder.load(Ordering::Relaxed) {
+            1 => Some(DecoderKind::Hardware),
+            2 => Some(DecoderKind::Software),
+            _ => None,
+        }
+    }
+
+    pub fn set_decoder(&self, decoder: DecoderKind) {
+        let value = match decoder {
+            DecoderKind::Hardware => 1,
+            DecoderKind::Software => 2,
+        };
+        self.decoder.store(value, Ordering::Relaxed);
+    }
+
+    pub fn record_hardware_fallback(&self) {
+        self.hardware_fallbacks.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Asks the remote side for a keyframe, the decoder starts over and
+    /// can't wait for the next one the encoder plans.
+    pub fn request_keyframe(&self) {
+        self.keyframe_request.notify_one();
+    }
+
+    /// Resolves once the decoder asked for a keyframe, requests made
+    /// meanwhile are folded into one.
+    pub async fn keyframe_requested(&self) {
+        self.keyframe_request.notified().await
+    }
+}
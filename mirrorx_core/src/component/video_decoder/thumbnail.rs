@@ -0,0 +1,218 @@
+use crate::{
+    api::endpoint::id::EndPointID,
+    component::frame::{DesktopDecodeFrame, DesktopDecodeFrameFormat},
+    error::CoreResult,
+};
+use image::{codecs::jpeg::JpegEncoder, ColorType, RgbImage};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+static THUMBNAIL_CONFIG: Lazy<RwLock<ThumbnailConfig>> =
+    Lazy::new(|| RwLock::new(ThumbnailConfig::default()));
+
+static THUMBNAIL_TX: Lazy<tokio::sync::broadcast::Sender<SessionThumbnail>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+/// Thumbnails of the remote screens for the session list, taken from frames
+/// decoded for rendering anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ThumbnailConfig {
+    pub enabled: bool,
+    /// Bounds of a thumbnail, the aspect ratio of the screen is kept.
+    pub max_width: u32,
+    pub max_height: u32,
+    pub interval_secs: u32,
+    /// JPEG quality, 1 to 100.
+    pub quality: u8,
+}
+
+impl Default for ThumbnailConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_width: 320,
+            max_height: 180,
+            interval_secs: 3,
+            quality: 70,
+        }
+    }
+}
+
+impl ThumbnailConfig {
+    pub fn sanitize(self) -> Self {
+        Self {
+            enabled: self.enabled,
+            max_width: self.max_width.clamp(16, 1280),
+            max_height: self.max_height.clamp(16, 720),
+            interval_secs: self.interval_secs.max(1),
+            quality: self.quality.clamp(1, 100),
+        }
+    }
+}
+
+/// Applies to the next thumbnail of every session.
+pub fn set_thumbnail_config(config: ThumbnailConfig) {
+    if let Ok(mut thumbnail_config) = THUMBNAIL_CONFIG.write() {
+        *thumbnail_config = config.sanitize();
+    }
+}
+
+pub fn thumbnail_config() -> ThumbnailConfig {
+    THUMBNAIL_CONFIG
+        .read()
+        .map(|config| *config)
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionThumbnail {
+    pub endpoint_id: String,
+    pub width: u32,
+    pub height: u32,
+    /// Base64 of the JPEG.
+    pub jpeg: String,
+}
+
+pub fn subscribe_session_thumbnail() -> tokio::sync::broadcast::Receiver<SessionThumbnail> {
+    THUMBNAIL_TX.subscribe()
+}
+
+/// Takes a thumbnail of the session every [`ThumbnailConfig::interval_secs`],
+/// a few thousand pixels sampled from a frame and a small JPEG.
+pub struct Thumbnailer {
+    endpoint_id: EndPointID,
+    last_taken: Option<Instant>,
+}
+
+impl Thumbnailer {
+    pub fn new(endpoint_id: EndPointID) -> Self {
+        Self {
+            endpoint_id,
+            last_taken: None,
+        }
+    }
+
+    /// Called with every decoded frame.
+    pub fn offer(&mut self, frame: &DesktopDecodeFrame) {
+        if let Some(thumbnail) = self.offer_at(frame, Instant::now()) {
+            let _ = THUMBNAIL_TX.send(thumbnail);
+        }
+    }
+
+    /// The thumbnail of the frame if one is due at `now`.
+    pub fn offer_at(
+        &mut self,
+        frame: &DesktopDecodeFrame,
+        now: Instant,
+    ) -> Option<SessionThumbnail> {
+        let config = thumbnail_config();
+        if !config.enabled {
+            return None;
+        }
+
+        let interval = Duration::from_secs(config.interval_secs as u64);
+        if let Some(last_taken) = self.last_taken {
+            if now.duration_since(last_taken) < interval {
+                return None;
+            }
+        }
+
+        self.last_taken = Some(now);
+
+        let image = downsample_frame(frame, config.max_width, config.max_height)?;
+        let jpeg = match encode_jpeg(&image, config.quality) {
+            Ok(jpeg) => jpeg,
+            Err(err) => {
+                tracing::warn!(?err, "encode session thumbnail failed");
+                return None;
+            }
+        };
+
+        Some(SessionThumbnail {
+            endpoint_id: self.endpoint_id.to_string(),
+            width: image.width(),
+            height: image.height(),
+            jpeg: base64::encode(jpeg),
+        })
+    }
+}
+
+/// Samples the frame down to fit the bounds, nearest neighbour on the planes
+/// as decoded. `None` when the planes are smaller than the frame claims.
+pub fn downsample_frame(
+    frame: &DesktopDecodeFrame,
+    max_width: u32,
+    max_height: u32,
+) -> Option<RgbImage> {
+    if frame.width <= 0 || frame.height <= 0 || max_width == 0 || max_height == 0 {
+        return None;
+    }
+
+    let (width, height) = (frame.width as u32, frame.height as u32);
+    let scale = (max_width as f64 / width as f64)
+        .min(max_height as f64 / height as f64)
+        .min(1.0);
+    let thumbnail_width = ((width as f64 * scale).round() as u32).max(1);
+    let thumbnail_height = ((height as f64 * scale).round() as u32).max(1);
+
+    let luma_plane = frame.plane_data.first()?;
+    let luma_stride = *frame.line_sizes.first()? as usize;
+    let chroma_stride = *frame.line_sizes.get(1)? as usize;
+
+    let mut image = RgbImage::new(thumbnail_width, thumbnail_height);
+    for y in 0..thumbnail_height {
+        let source_y = (y as u64 * height as u64 / thumbnail_height as u64) as usize;
+        for x in 0..thumbnail_width {
+            let source_x = (x as u64 * width as u64 / thumbnail_width as u64) as usize;
+
+            let luma = *luma_plane.get(source_y * luma_stride + source_x)?;
+            let chroma_offset = (source_y / 2) * chroma_stride;
+            let (cb, cr) = match frame.format {
+                DesktopDecodeFrameFormat::NV12 => {
+                    let offset = chroma_offset + (source_x / 2) * 2;
+                    let plane = frame.plane_data.get(1)?;
+                    (*plane.get(offset)?, *plane.get(offset + 1)?)
+                }
+                DesktopDecodeFrameFormat::YUV420P => {
+                    let offset = chroma_offset + source_x / 2;
+                    (
+                        *frame.plane_data.get(1)?.get(offset)?,
+                        *frame.plane_data.get(2)?.get(offset)?,
+                    )
+                }
+            };
+
+            image.put_pixel(x, y, image::Rgb(yuv_to_rgb(luma, cb, cr)));
+        }
+    }
+
+    Some(image)
+}
+
+/// BT.601 limited range, what the H.264 decoder puts out.
+fn yuv_to_rgb(y: u8, cb: u8, cr: u8) -> [u8; 3] {
+    let y = 1.164 * (y as f32 - 16.0);
+    let cb = cb as f32 - 128.0;
+    let cr = cr as f32 - 128.0;
+
+    [
+        (y + 1.596 * cr).round().clamp(0.0, 255.0) as u8,
+        (y - 0.392 * cb - 0.813 * cr).round().clamp(0.0, 255.0) as u8,
+        (y + 2.017 * cb).round().clamp(0.0, 255.0) as u8,
+    ]
+}
+
+pub fn encode_jpeg(image: &RgbImage, quality: u8) -> CoreResult<Vec<u8>> {
+    let mut buffer = Vec::new();
+    JpegEncoder::new_with_quality(&mut buffer, quality).encode(
+        image.as_raw(),
+        image.width(),
+        image.height(),
+        ColorType::Rgb8,
+    )?;
+    Ok(buffer)
+}
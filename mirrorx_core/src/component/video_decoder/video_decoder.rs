@@ -1,33 +1,168 @@
+use super::{
+    fallback::{announce_decoder_fallback, DecoderKind, DecoderSelection},
+    keyframe::KeyFrameGate,
+    rate_limit::{DecodeDecision, DecodeRateLimiter, DecodeStats},
+    thumbnail::Thumbnailer,
+};
 use crate::{
-    api::endpoint::message::EndPointVideoFrame,
-    component::frame::{DesktopDecodeFrame, DesktopDecodeFrameFormat},
+    api::endpoint::{
+        id::EndPointID,
+        message::{EndPointVideoFrame, EndPointVideoParameterSets, VideoCodec},
+    },
+    component::{
+        frame::{DesktopDecodeFrame, DesktopDecodeFrameFormat},
+        parameter_sets::prepend_parameter_sets,
+    },
     core_error,
-    error::CoreResult,
+    error::{CoreError, CoreResult},
 };
 use mirrorx_native::ffmpeg::{avcodec::*, avutil::*};
+use std::{sync::Arc, time::Instant};
 use tokio::sync::mpsc::Sender;
 
 pub struct VideoDecoder {
+    endpoint_id: EndPointID,
+    codec: VideoCodec,
     decode_context: Option<DecodeContext>,
+    decoder_selection: DecoderSelection,
+    keyframe_gate: KeyFrameGate,
+    parameter_sets: Option<EndPointVideoParameterSets>,
+    rate_limiter: DecodeRateLimiter,
+    stats: Arc<DecodeStats>,
+    thumbnailer: Thumbnailer,
     render_frame_tx: Sender<DesktopDecodeFrame>,
     _last_pts: i64,
 }
 
 impl VideoDecoder {
-    pub fn new(render_frame_tx: Sender<DesktopDecodeFrame>) -> VideoDecoder {
+    pub fn new(
+        endpoint_id: EndPointID,
+        render_frame_tx: Sender<DesktopDecodeFrame>,
+        prefer_hardware: bool,
+        fps_cap: u32,
+        stats: Arc<DecodeStats>,
+        thumbnailer: Thumbnailer,
+    ) -> VideoDecoder {
         // unsafe {
         //     av_log_set_level(AV_LOG_TRACE);
         //     av_log_set_flags(AV_LOG_SKIP_REPEATED);
         // }
 
         VideoDecoder {
+            endpoint_id,
+            codec: VideoCodec::default(),
             decode_context: None,
+            decoder_selection: DecoderSelection::new(prefer_hardware),
+            keyframe_gate: KeyFrameGate::default(),
+            parameter_sets: None,
+            rate_limiter: DecodeRateLimiter::new(fps_cap, VideoCodec::default()),
+            stats,
+            thumbnailer,
             render_frame_tx,
             _last_pts: 0,
         }
     }
 
-    pub fn decode(&mut self, mut video_frame: EndPointVideoFrame) -> CoreResult<()> {
+    /// Drops the decode context, the next frame creates one of its own size
+    /// and decoding resumes from its keyframe.
+    pub fn reset(&mut self) {
+        self.decode_context = None;
+        self.decoder_selection.reset();
+        self.keyframe_gate.reset();
+    }
+
+    /// Switches to the codec the session negotiated, the next frame opens a
+    /// decode context of it.
+    pub fn set_codec(&mut self, codec: VideoCodec) {
+        if self.codec == codec {
+            return;
+        }
+
+        tracing::info!(?codec, "video codec changed");
+        self.keyframe_gate = KeyFrameGate::new(codec.clone());
+        self.rate_limiter.set_codec(codec.clone());
+        // parameter sets of another codec don't apply
+        self.parameter_sets = None;
+        self.codec = codec;
+        self.reset();
+    }
+
+    /// Skips frames until the next keyframe, frames predicted from lost ones
+    /// would only render garbage.
+    pub fn wait_for_keyframe(&mut self) {
+        self.keyframe_gate.reset();
+    }
+
+    /// Keeps the parameter sets the host sent, a decode context created
+    /// later opens with them on the first IDR slice of their size.
+    pub fn set_parameter_sets(&mut self, parameter_sets: EndPointVideoParameterSets) {
+        self.parameter_sets = Some(parameter_sets);
+    }
+
+    /// Decodes a frame and renders it. A failing hardware decoder is
+    /// replaced by the software one, which starts with the next keyframe.
+    pub fn decode(&mut self, video_frame: EndPointVideoFrame) -> CoreResult<()> {
+        match self.decode_frame(video_frame) {
+            // without a renderer nothing decodes anymore
+            Err(err) if self.decoding_in_hardware() && !self.render_frame_tx.is_closed() => {
+                self.fall_back_to_software(&err);
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn decoding_in_hardware(&self) -> bool {
+        self.decode_context
+            .as_ref()
+            .is_some_and(|decode_context| {
+                decode_context.decoder == DecoderKind::Hardware
+            })
+    }
+
+    fn fall_back_to_software(&mut self, err: &CoreError) {
+        let permanent = self.decoder_selection.hardware_failed();
+        tracing::warn!(
+            ?err,
+            ?permanent,
+            failures = self.decoder_selection.hardware_failures(),
+            "hardware decoder failed, fall back to software decoder"
+        );
+
+        // the software decoder opens with the next frame and waits for a
+        // keyframe, the host is asked for one right away
+        self.decode_context = None;
+        self.keyframe_gate.reset();
+        self.stats.record_hardware_fallback();
+        self.stats.set_decoder(DecoderKind::Software);
+        self.stats.request_keyframe();
+
+        announce_decoder_fallback(&self.endpoint_id, err.to_string(), permanent);
+    }
+
+    fn open_decode_context(&mut self, width: i32, height: i32) -> CoreResult<DecodeContext> {
+        let decoder = self.decoder_selection.next();
+
+        let decode_context = match DecodeContext::new(&self.codec, width, height, decoder) {
+            Err(err) if decoder == DecoderKind::Hardware => {
+                self.fall_back_to_software(&err);
+                DecodeContext::new(&self.codec, width, height, DecoderKind::Software)?
+            }
+            result => result?,
+        };
+
+        tracing::info!(
+            codec = ?self.codec,
+            ?width,
+            ?height,
+            decoder = ?decode_context.decoder,
+            "decode context opened"
+        );
+        self.stats.set_decoder(decode_context.decoder);
+        Ok(decode_context)
+    }
+
+    fn decode_frame(&mut self, mut video_frame: EndPointVideoFrame) -> CoreResult<()> {
         unsafe {
             if let Some(decode_context) = self.decode_context.as_ref() {
                 if (*decode_context.codec_ctx).width != video_frame.width
@@ -39,9 +174,30 @@ impl VideoDecoder {
 
             if self.decode_context.is_none() {
                 self.decode_context =
-                    Some(DecodeContext::new(video_frame.width, video_frame.height)?);
+                    Some(self.open_decode_context(video_frame.width, video_frame.height)?);
+                self.keyframe_gate.reset();
+                self.stats
+                    .budget()
+                    .set_frame_size(video_frame.width, video_frame.height);
+            }
+
+            if let Some(ref parameter_sets) = self.parameter_sets {
+                prepend_parameter_sets(parameter_sets, &mut video_frame);
+            }
+
+            if !self.keyframe_gate.admit(&video_frame.buffer) {
+                return Ok(());
             }
 
+            let present = match self.rate_limiter.admit(&video_frame.buffer, Instant::now()) {
+                DecodeDecision::Present => true,
+                DecodeDecision::DecodeOnly => false,
+                DecodeDecision::Drop => {
+                    self.stats.record_dropped(1);
+                    return Ok(());
+                }
+            };
+
             let Some(ref decode_context)= self.decode_context else{
                 return Err(core_error!("decode context is empty"));
             };
@@ -79,6 +235,12 @@ impl VideoDecoder {
                     ));
                 }
 
+                if !present {
+                    self.stats.record_unpresented();
+                    av_frame_unref(decode_context.decode_frame);
+                    continue;
+                }
+
                 let tmp_frame = if (*decode_context.codec_ctx).hw_device_ctx.is_null() {
                     decode_context.decode_frame
                 } else {
@@ -151,14 +313,25 @@ impl VideoDecoder {
                     }
                 };
 
+                // a stalled renderer gets the frames that fit, later ones
+                // replace them anyway
+                let bytes = plane_data.iter().map(|plane| plane.len() as u64).sum();
+                let Some(budget_charge) = self.stats.budget().admit_render(bytes) else {
+                    av_frame_unref(tmp_frame);
+                    continue;
+                };
+
                 let desktop_decode_frame = DesktopDecodeFrame {
                     width: (*tmp_frame).width,
                     height: (*tmp_frame).height,
                     plane_data,
                     line_sizes,
                     format,
+                    budget_charge: Some(budget_charge),
                 };
 
+                self.thumbnailer.offer(&desktop_decode_frame);
+
                 if self
                     .render_frame_tx
                     .blocking_send(desktop_decode_frame)
@@ -173,7 +346,23 @@ impl VideoDecoder {
     }
 }
 
+/// The ffmpeg codec id of a video codec.
+pub fn av_codec_id(codec: &VideoCodec) -> AVCodecID {
+    match codec {
+        VideoCodec::H264 => AV_CODEC_ID_H264,
+        VideoCodec::Hevc => AV_CODEC_ID_HEVC,
+        VideoCodec::VP8 => AV_CODEC_ID_VP8,
+        VideoCodec::VP9 => AV_CODEC_ID_VP9,
+    }
+}
+
+/// Whether the ffmpeg of this build decodes the codec.
+pub fn decoder_available(codec: &VideoCodec) -> bool {
+    unsafe { !avcodec_find_decoder(av_codec_id(codec)).is_null() }
+}
+
 struct DecodeContext {
+    decoder: DecoderKind,
     codec_ctx: *mut AVCodecContext,
     packet: *mut AVPacket,
     decode_frame: *mut AVFrame,
@@ -181,11 +370,19 @@ struct DecodeContext {
 }
 
 impl DecodeContext {
-    fn new(width: i32, height: i32) -> CoreResult<DecodeContext> {
+    fn new(
+        video_codec: &VideoCodec,
+        width: i32,
+        height: i32,
+        decoder: DecoderKind,
+    ) -> CoreResult<DecodeContext> {
         unsafe {
-            let mut decode_ctx = DecodeContext::default();
+            let mut decode_ctx = DecodeContext {
+                decoder,
+                ..Default::default()
+            };
 
-            let codec = avcodec_find_decoder(AV_CODEC_ID_H264);
+            let codec = avcodec_find_decoder(av_codec_id(video_codec));
 
             if codec.is_null() {
                 return Err(core_error!("avcodec_find_decoder returns null"));
@@ -206,56 +403,33 @@ impl DecodeContext {
             // (*decode_ctx.codec_ctx).colorspace = AVCOL_SPC_BT709;
             // (*decode_ctx.codec_ctx).flags2 |= AV_CODEC_FLAG2_LOCAL_HEADER;
 
-            // let mut hw_device_type = av_hwdevice_find_type_by_name(
-            //     CString::new(if cfg!(target_os = "windows") {
-            //         "d3d11va"
-            //     } else {
-            //         "videotoolbox"
-            //     })?
-            //     .as_ptr(),
-            // );
-
-            // if hw_device_type == AV_HWDEVICE_TYPE_NONE {
-            //     tracing::error!("current environment does't support hardware decode");
-
-            //     let mut devices = Vec::new();
-            //     loop {
-            //         hw_device_type = av_hwdevice_iterate_types(hw_device_type);
-            //         if hw_device_type == AV_HWDEVICE_TYPE_NONE {
-            //             break;
-            //         }
-
-            //         let device_name = av_hwdevice_get_type_name(hw_device_type);
-
-            //         devices.push(
-            //             CStr::from_ptr(device_name)
-            //                 .to_str()
-            //                 .map_or("unknown", |v| v),
-            //         );
-            //     }
-
-            //     tracing::info!(?devices, "current environment support hw device");
-            //     tracing::info!("use software decoder");
-            // } else {
-            //     let mut hwdevice_ctx = std::ptr::null_mut();
-
-            //     let ret = av_hwdevice_ctx_create(
-            //         &mut hwdevice_ctx,
-            //         hw_device_type,
-            //         std::ptr::null(),
-            //         std::ptr::null_mut(),
-            //         0,
-            //     );
-
-            //     if ret < 0 {
-            //         return Err(core_error!(
-            //             "av_hwdevice_ctx_create returns error code: {}",
-            //             ret,
-            //         ));
-            //     }
-
-            //     (*decode_ctx.codec_ctx).hw_device_ctx = av_buffer_ref(hwdevice_ctx);
-            // }
+            if decoder == DecoderKind::Hardware {
+                let hw_device_type = if cfg!(target_os = "windows") {
+                    AV_HWDEVICE_TYPE_D3D11VA
+                } else {
+                    AV_HWDEVICE_TYPE_VIDEOTOOLBOX
+                };
+
+                let mut hw_device_ctx = std::ptr::null_mut();
+                let ret = av_hwdevice_ctx_create(
+                    &mut hw_device_ctx,
+                    hw_device_type,
+                    std::ptr::null(),
+                    std::ptr::null_mut(),
+                    0,
+                );
+
+                if ret < 0 {
+                    return Err(core_error!(
+                        "av_hwdevice_ctx_create returns error code: {}",
+                        ret
+                    ));
+                }
+
+                // the codec context owns the device from now on and frees it
+                // along with itself
+                (*decode_ctx.codec_ctx).hw_device_ctx = hw_device_ctx;
+            }
 
             decode_ctx.packet = av_packet_alloc();
             if decode_ctx.packet.is_null() {
@@ -285,6 +459,7 @@ impl DecodeContext {
 impl Default for DecodeContext {
     fn default() -> Self {
         Self {
+            decoder: DecoderKind::Software,
             codec_ctx: std::ptr::null_mut(),
             packet: std::ptr::null_mut(),
             decode_frame: std::ptr::null_mut(),
@@ -312,10 +487,8 @@ impl Drop for DecodeContext {
                 av_packet_free(&mut self.packet);
             }
 
+            // frees the hardware device as well
             if !self.codec_ctx.is_null() {
-                if !(*self.codec_ctx).hw_device_ctx.is_null() {
-                    av_buffer_ref((*self.codec_ctx).hw_device_ctx);
-                }
                 avcodec_free_context(&mut self.codec_ctx);
             }
         }
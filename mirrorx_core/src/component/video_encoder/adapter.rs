@@ -0,0 +1,119 @@
+use super::{
+    config::{
+        hardware::HardwareEncoderConfig, libx264::Libx264Config, libx265::Libx265Config,
+        EncoderConfig,
+    },
+    video_encoder::encoder_available,
+};
+use crate::{
+    api::endpoint::message::VideoCodec,
+    utility::os::{enum_graphics_cards, GraphicsCards},
+};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::sync::RwLock;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct EncoderFallbackEvent {
+    pub graphics_card_id: String,
+    pub reason: String,
+}
+
+static PINNED_GRAPHICS_CARD: Lazy<RwLock<Option<String>>> = Lazy::new(|| RwLock::new(None));
+
+static ENCODER_FALLBACK_TX: Lazy<tokio::sync::broadcast::Sender<EncoderFallbackEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+pub fn subscribe_encoder_fallback() -> tokio::sync::broadcast::Receiver<EncoderFallbackEvent> {
+    ENCODER_FALLBACK_TX.subscribe()
+}
+
+/// Pin the video encoder of later sessions to the graphics card with the
+/// given id, `None` restores the default software encoder.
+pub fn pin_graphics_card(graphics_card_id: Option<String>) {
+    if let Ok(mut pinned) = PINNED_GRAPHICS_CARD.write() {
+        *pinned = graphics_card_id;
+    }
+}
+
+pub fn pinned_graphics_card() -> Option<String> {
+    PINNED_GRAPHICS_CARD
+        .read()
+        .ok()
+        .and_then(|pinned| pinned.clone())
+}
+
+/// Looks up the pinned graphics card among the currently present cards.
+pub fn resolve_pinned_graphics_card() -> Option<GraphicsCards> {
+    let graphics_card_id = pinned_graphics_card()?;
+
+    match enum_graphics_cards() {
+        Ok(graphics_cards) => graphics_cards
+            .into_iter()
+            .find(|graphics_card| graphics_card.id == graphics_card_id),
+        Err(err) => {
+            tracing::error!(?err, "enum graphics cards failed");
+            None
+        }
+    }
+}
+
+/// The pinned graphics card if it's present and has a hardware encoder of
+/// the codec, the one the session is negotiated with.
+pub fn usable_pinned_graphics_card(codec: &VideoCodec) -> Option<GraphicsCards> {
+    resolve_pinned_graphics_card().filter(|graphics_card| {
+        HardwareEncoderConfig::new(graphics_card.clone(), codec.clone()).is_ok()
+    })
+}
+
+/// Returns the hardware encoder of the codec on the pinned graphics card, or
+/// the software one when nothing is pinned or the pinned card can't be used
+/// any more.
+pub fn select_encoder_config(codec: &VideoCodec) -> Box<dyn EncoderConfig> {
+    let Some(graphics_card_id) = pinned_graphics_card() else {
+        return software_encoder_config(codec);
+    };
+
+    let Some(graphics_card) = resolve_pinned_graphics_card() else {
+        report_encoder_fallback(&graphics_card_id, "graphics card is not present");
+        return software_encoder_config(codec);
+    };
+
+    match HardwareEncoderConfig::new(graphics_card, codec.clone()) {
+        Ok(config) => Box::new(config),
+        Err(err) => {
+            report_encoder_fallback(&graphics_card_id, &err.to_string());
+            software_encoder_config(codec)
+        }
+    }
+}
+
+/// libx265 for HEVC, libx264 for everything else.
+pub fn software_encoder_config(codec: &VideoCodec) -> Box<dyn EncoderConfig> {
+    match codec {
+        VideoCodec::Hevc => Box::new(Libx265Config::new()),
+        _ => Box::new(Libx264Config::new()),
+    }
+}
+
+/// The first of the offered codecs this build encodes, H264 always and HEVC
+/// when its ffmpeg has libx265, which hardware encoders fall back to.
+pub fn select_video_codec(offered: &[VideoCodec]) -> Option<VideoCodec> {
+    offered
+        .iter()
+        .find(|codec| match codec {
+            VideoCodec::H264 => true,
+            VideoCodec::Hevc => encoder_available(&*software_encoder_config(codec)),
+            _ => false,
+        })
+        .cloned()
+}
+
+pub fn report_encoder_fallback(graphics_card_id: &str, reason: &str) {
+    tracing::warn!(?graphics_card_id, ?reason, "fall back to software encoder");
+
+    let _ = ENCODER_FALLBACK_TX.send(EncoderFallbackEvent {
+        graphics_card_id: graphics_card_id.to_string(),
+        reason: reason.to_string(),
+    });
+}
@@ -0,0 +1,118 @@
+use super::bitrate::MIN_BITRATE_KBPS;
+use crate::api::endpoint::message::EndPointNetworkReport;
+
+/// Output resolution in percent of the one the media config asks for, the
+/// controller steps down a tier on sustained loss.
+pub const RESOLUTION_TIERS: [u8; 3] = [100, 75, 50];
+
+/// Loss from this many thousandths on backs the bitrate off.
+pub const HIGH_LOSS_PERMILLE: u16 = 50;
+
+/// Loss below this many thousandths lets the bitrate ramp up.
+pub const LOW_LOSS_PERMILLE: u16 = 10;
+
+/// Lossy reports in a row before the resolution steps down a tier.
+pub const SUSTAINED_LOSS_REPORTS: u32 = 3;
+
+/// Clean reports in a row before the resolution steps up a tier again.
+pub const SUSTAINED_CLEAN_REPORTS: u32 = 10;
+
+/// Percent of the received bitrate kept on loss.
+const BACKOFF_PERCENT: u64 = 80;
+
+/// Percent the bitrate grows by with every clean report.
+const RAMP_PERCENT: u32 = 8;
+
+/// The bitrate only ramps while the viewer receives at least this percent
+/// of it, an idle sender proves nothing about the link.
+const RAMP_RECEIVED_PERCENT: u64 = 80;
+
+/// Lowest scale a media config takes.
+const MIN_SCALE_PERCENT: u8 = 25;
+
+/// What the encoder should produce at most.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EncoderTarget {
+    pub bitrate_kbps: u32,
+    pub scale_percent: u8,
+}
+
+/// Follows the reports of the viewer with the bitrate and the resolution
+/// of the encoder, never above what the media config asks for. Loss backs
+/// the bitrate off below what arrived, a clean link ramps it up a few
+/// percent a report.
+#[derive(Debug)]
+pub struct AdaptiveBitrate {
+    ceiling: EncoderTarget,
+    bitrate_kbps: u32,
+    tier: usize,
+    lossy_reports: u32,
+    clean_reports: u32,
+}
+
+impl AdaptiveBitrate {
+    pub fn new(bitrate_kbps: u32, scale_percent: u8) -> Self {
+        let bitrate_kbps = bitrate_kbps.max(MIN_BITRATE_KBPS);
+
+        Self {
+            ceiling: EncoderTarget {
+                bitrate_kbps,
+                scale_percent,
+            },
+            bitrate_kbps,
+            tier: 0,
+            lossy_reports: 0,
+            clean_reports: 0,
+        }
+    }
+
+    pub fn target(&self) -> EncoderTarget {
+        let scale_percent =
+            (self.ceiling.scale_percent as u32 * RESOLUTION_TIERS[self.tier] as u32 / 100) as u8;
+
+        EncoderTarget {
+            bitrate_kbps: self.bitrate_kbps,
+            scale_percent: scale_percent.max(MIN_SCALE_PERCENT),
+        }
+    }
+
+    pub fn on_report(&mut self, report: &EndPointNetworkReport) -> EncoderTarget {
+        let received_kbps = report.received_bps / 1000;
+
+        if report.loss_permille >= HIGH_LOSS_PERMILLE {
+            self.clean_reports = 0;
+            self.lossy_reports = self.lossy_reports.saturating_add(1);
+
+            let backoff_kbps = (received_kbps * BACKOFF_PERCENT / 100).min(u32::MAX as u64) as u32;
+            self.bitrate_kbps = self.bitrate_kbps.min(backoff_kbps).max(MIN_BITRATE_KBPS);
+
+            if self.lossy_reports >= SUSTAINED_LOSS_REPORTS
+                && self.tier + 1 < RESOLUTION_TIERS.len()
+            {
+                self.tier += 1;
+                self.lossy_reports = 0;
+                tracing::info!(tier = ?self.tier, "sustained loss, lower resolution");
+            }
+        } else if report.loss_permille < LOW_LOSS_PERMILLE {
+            self.lossy_reports = 0;
+            self.clean_reports = self.clean_reports.saturating_add(1);
+
+            if received_kbps * 100 >= self.bitrate_kbps as u64 * RAMP_RECEIVED_PERCENT {
+                let ramp_kbps = (self.bitrate_kbps * RAMP_PERCENT / 100).max(1);
+                self.bitrate_kbps = (self.bitrate_kbps + ramp_kbps).min(self.ceiling.bitrate_kbps);
+            }
+
+            if self.clean_reports >= SUSTAINED_CLEAN_REPORTS && self.tier > 0 {
+                self.tier -= 1;
+                self.clean_reports = 0;
+                tracing::info!(tier = ?self.tier, "link recovered, raise resolution");
+            }
+        } else {
+            // some loss, hold until it clears up or gets worse
+            self.lossy_reports = 0;
+            self.clean_reports = 0;
+        }
+
+        self.target()
+    }
+}
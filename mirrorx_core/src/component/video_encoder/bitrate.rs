@@ -0,0 +1,75 @@
+/// Lowest bitrate the controller goes down to, the same floor a media config
+/// has.
+pub const MIN_BITRATE_KBPS: u32 = 100;
+
+/// Share of the estimated bandwidth given to video, the rest is left to
+/// audio, input and file transfers.
+const VIDEO_SHARE: f64 = 0.85;
+
+/// Changes smaller than this share of the current bitrate keep the encoder
+/// as it is.
+const HYSTERESIS: f64 = 0.1;
+
+/// Follows the bandwidth estimate of the session with the encoder bitrate,
+/// never above the bitrate the media config asks for.
+#[derive(Debug)]
+pub struct BitrateController {
+    config_kbps: u32,
+    ceiling_kbps: u32,
+    current_kbps: u32,
+}
+
+impl BitrateController {
+    pub fn new(ceiling_kbps: u32) -> Self {
+        let ceiling_kbps = ceiling_kbps.max(MIN_BITRATE_KBPS);
+
+        Self {
+            config_kbps: ceiling_kbps,
+            ceiling_kbps,
+            current_kbps: ceiling_kbps,
+        }
+    }
+
+    /// Caps the bitrate below the one the media config asks for. Returns the
+    /// bitrate to switch the encoder to when the current one is above the
+    /// cap.
+    pub fn set_ceiling(&mut self, ceiling_kbps: u32) -> Option<u32> {
+        self.ceiling_kbps = ceiling_kbps.clamp(MIN_BITRATE_KBPS, self.config_kbps);
+
+        if self.current_kbps <= self.ceiling_kbps {
+            return None;
+        }
+
+        self.current_kbps = self.ceiling_kbps;
+        Some(self.current_kbps)
+    }
+
+    pub fn current_kbps(&self) -> u32 {
+        self.current_kbps
+    }
+
+    /// Returns the bitrate to switch the encoder to, or `None` to keep the
+    /// current one. An estimate of zero means there is none yet.
+    pub fn update(&mut self, estimated_bps: u64) -> Option<u32> {
+        if estimated_bps == 0 {
+            return None;
+        }
+
+        let target_kbps = ((estimated_bps as f64 * VIDEO_SHARE / 1000.0) as u32)
+            .clamp(MIN_BITRATE_KBPS, self.ceiling_kbps);
+
+        let change = (target_kbps as f64 - self.current_kbps as f64).abs();
+
+        // reaching the ceiling or the floor is always worth it
+        let at_bound = target_kbps == self.ceiling_kbps || target_kbps == MIN_BITRATE_KBPS;
+
+        if target_kbps == self.current_kbps
+            || (change < self.current_kbps as f64 * HYSTERESIS && !at_bound)
+        {
+            return None;
+        }
+
+        self.current_kbps = target_kbps;
+        Some(target_kbps)
+    }
+}
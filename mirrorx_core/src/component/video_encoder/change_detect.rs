@@ -0,0 +1,259 @@
+use crate::component::{desktop::capture_mode::is_dirty, frame::DesktopEncodeFrame};
+use std::{collections::hash_map::DefaultHasher, hash::Hasher, ops::Range, time::Duration};
+
+/// Side of the square regions a frame is compared in, in pixels.
+pub const CHANGE_REGION_SIZE: usize = 64;
+
+/// How often the host tells the viewer its screen didn't change, while it
+/// sends no frames.
+pub const UNCHANGED_MARKER_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Share of the frame height changed rows may span and still go out as a
+/// partial update.
+pub const PARTIAL_UPDATE_MAX_SHARE: f64 = 0.25;
+
+/// What changed in a frame since the last one encoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrameChange {
+    /// Nothing beyond the threshold, the frame isn't encoded.
+    Unchanged,
+    /// Only these rows of pixels changed, few enough to send just the
+    /// slices covering them.
+    Rows(Range<i32>),
+    Full,
+}
+
+/// Tells frames showing something new from a static screen. A frame is cut
+/// in regions of [`CHANGE_REGION_SIZE`], a region whose hash stays the same
+/// is unchanged without looking at its pixels. The others are compared with
+/// the last content sent, and count as changed when a byte of luma or
+/// chroma differs by more than the threshold, smaller differences are
+/// capture noise. Regions outside the dirty rectangles of a frame aren't
+/// looked at.
+#[derive(Debug)]
+pub struct ChangeDetector {
+    threshold: u8,
+    width: i32,
+    height: i32,
+    /// The content the viewer has, packed without stride.
+    luminance: Vec<u8>,
+    chrominance: Vec<u8>,
+    hashes: Vec<u64>,
+    /// Capture time of the last frame or marker sent.
+    sent_at: Option<Duration>,
+}
+
+impl ChangeDetector {
+    pub fn new(threshold: u8) -> Self {
+        Self {
+            threshold,
+            width: 0,
+            height: 0,
+            luminance: Vec::new(),
+            chrominance: Vec::new(),
+            hashes: Vec::new(),
+            sent_at: None,
+        }
+    }
+
+    /// Compares a frame with the content the viewer has, and takes the
+    /// changed regions as the new content. A frame of another size is a
+    /// full change.
+    pub fn observe(&mut self, frame: &DesktopEncodeFrame) -> FrameChange {
+        if (frame.width, frame.height) != (self.width, self.height) || self.hashes.is_empty() {
+            self.reset(frame);
+            return FrameChange::Full;
+        }
+
+        let (columns, rows) = self.regions();
+        let mut changed_rows: Option<Range<i32>> = None;
+
+        for region_y in 0..rows {
+            for region_x in 0..columns {
+                let index = region_y * columns + region_x;
+                let (x, y) = region_bounds(frame.width, frame.height, region_x, region_y);
+
+                if let Some(ref dirty_rects) = frame.dirty_rects {
+                    if !is_dirty(dirty_rects, &x, &y) {
+                        continue;
+                    }
+                }
+
+                let hash = region_hash(frame, region_x, region_y);
+
+                if hash == self.hashes[index] || !self.differs(frame, region_x, region_y) {
+                    continue;
+                }
+
+                self.hashes[index] = hash;
+                self.store_region(frame, region_x, region_y);
+
+                let y = y.start as i32..y.end as i32;
+                changed_rows = Some(match changed_rows {
+                    Some(rows) => rows.start.min(y.start)..rows.end.max(y.end),
+                    None => y,
+                });
+            }
+        }
+
+        match changed_rows {
+            None => FrameChange::Unchanged,
+            Some(rows)
+                if (rows.end - rows.start) as f64
+                    <= frame.height as f64 * PARTIAL_UPDATE_MAX_SHARE =>
+            {
+                FrameChange::Rows(rows)
+            }
+            Some(_) => FrameChange::Full,
+        }
+    }
+
+    /// Whether to tell the viewer the screen is unchanged, at most every
+    /// [`UNCHANGED_MARKER_INTERVAL`] since the last frame or marker.
+    pub fn marker_due(&mut self, capture_time: Duration) -> bool {
+        let due = self.sent_at.is_none_or(|sent_at| {
+            capture_time.saturating_sub(sent_at) >= UNCHANGED_MARKER_INTERVAL
+        });
+
+        if due {
+            self.sent_at = Some(capture_time);
+        }
+
+        due
+    }
+
+    /// A frame went out, the next marker is due an interval later.
+    pub fn frame_sent(&mut self, capture_time: Duration) {
+        self.sent_at = Some(capture_time);
+    }
+
+    fn regions(&self) -> (usize, usize) {
+        (
+            (self.width as usize).div_ceil(CHANGE_REGION_SIZE),
+            (self.height as usize).div_ceil(CHANGE_REGION_SIZE),
+        )
+    }
+
+    fn reset(&mut self, frame: &DesktopEncodeFrame) {
+        self.width = frame.width;
+        self.height = frame.height;
+
+        let (width, height) = (frame.width as usize, frame.height as usize);
+        self.luminance = vec![0; width * height];
+        self.chrominance = vec![0; width * height / 2];
+
+        let (columns, rows) = self.regions();
+        self.hashes = Vec::with_capacity(columns * rows);
+        for region_y in 0..rows {
+            for region_x in 0..columns {
+                self.hashes.push(region_hash(frame, region_x, region_y));
+                self.store_region(frame, region_x, region_y);
+            }
+        }
+    }
+
+    fn differs(&self, frame: &DesktopEncodeFrame, region_x: usize, region_y: usize) -> bool {
+        let width = frame.width as usize;
+        let mut differs = false;
+
+        for_each_row(frame, region_x, region_y, |plane, y, row| {
+            let stored = match plane {
+                Plane::Luminance => &self.luminance[y * width..],
+                Plane::Chrominance => &self.chrominance[y * width..],
+            };
+            let stored = &stored[row.left..row.left + row.bytes.len()];
+
+            differs |= row
+                .bytes
+                .iter()
+                .zip(stored)
+                .any(|(byte, stored)| byte.abs_diff(*stored) > self.threshold);
+        });
+
+        differs
+    }
+
+    fn store_region(&mut self, frame: &DesktopEncodeFrame, region_x: usize, region_y: usize) {
+        let width = frame.width as usize;
+        let (luminance, chrominance) = (&mut self.luminance, &mut self.chrominance);
+
+        for_each_row(frame, region_x, region_y, |plane, y, row| {
+            let stored = match plane {
+                Plane::Luminance => &mut luminance[y * width..],
+                Plane::Chrominance => &mut chrominance[y * width..],
+            };
+            stored[row.left..row.left + row.bytes.len()].copy_from_slice(row.bytes);
+        });
+    }
+}
+
+enum Plane {
+    Luminance,
+    Chrominance,
+}
+
+struct Row<'a> {
+    /// Offset of the first byte in the row.
+    left: usize,
+    bytes: &'a [u8],
+}
+
+/// The pixels of a region, clipped to the frame.
+fn region_bounds(
+    width: i32,
+    height: i32,
+    region_x: usize,
+    region_y: usize,
+) -> (Range<usize>, Range<usize>) {
+    let left = region_x * CHANGE_REGION_SIZE;
+    let top = region_y * CHANGE_REGION_SIZE;
+    (
+        left..(left + CHANGE_REGION_SIZE).min(width as usize),
+        top..(top + CHANGE_REGION_SIZE).min(height as usize),
+    )
+}
+
+/// Visits the luma rows of a region and the chroma rows covering them. A
+/// chroma row holds interleaved UV pairs, as wide in bytes as a luma row.
+fn for_each_row<'a>(
+    frame: &'a DesktopEncodeFrame,
+    region_x: usize,
+    region_y: usize,
+    mut visit: impl FnMut(Plane, usize, Row<'a>),
+) {
+    let (x, y) = region_bounds(frame.width, frame.height, region_x, region_y);
+    let luminance_stride = frame.luminance_stride as usize;
+    let chrominance_stride = frame.chrominance_stride as usize;
+
+    for row in y.clone() {
+        let offset = row * luminance_stride;
+        visit(
+            Plane::Luminance,
+            row,
+            Row {
+                left: x.start,
+                bytes: &frame.luminance_bytes[offset + x.start..offset + x.end],
+            },
+        );
+    }
+
+    for row in y.start / 2..y.end / 2 {
+        let offset = row * chrominance_stride;
+        visit(
+            Plane::Chrominance,
+            row,
+            Row {
+                left: x.start,
+                bytes: &frame.chrominance_bytes[offset + x.start..offset + x.end],
+            },
+        );
+    }
+}
+
+fn region_hash(frame: &DesktopEncodeFrame, region_x: usize, region_y: usize) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for_each_row(frame, region_x, region_y, |_, _, row| {
+        hasher.write(row.bytes)
+    });
+    hasher.finish()
+}
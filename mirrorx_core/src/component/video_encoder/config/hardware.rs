@@ -0,0 +1,104 @@
+use super::{set_codec_ctx_option, EncoderConfig};
+use crate::{
+    api::endpoint::message::VideoCodec,
+    component::video_encoder::media_config::MediaConfig,
+    core_error,
+    error::CoreResult,
+    utility::os::{GraphicsCardVendor, GraphicsCards},
+};
+use mirrorx_native::ffmpeg::avcodec::*;
+use std::ffi::CString;
+
+/// H264 or HEVC hardware encoder bound to a specific graphics card.
+///
+/// Only nvenc accepts a device index, AMF and QSV always run on the card
+/// driving the primary display, and VideoToolbox picks the GPU by itself.
+pub struct HardwareEncoderConfig {
+    graphics_card: GraphicsCards,
+    codec: VideoCodec,
+    ffmpeg_encoder_name: CString,
+}
+
+impl HardwareEncoderConfig {
+    pub fn new(graphics_card: GraphicsCards, codec: VideoCodec) -> CoreResult<Self> {
+        let codec_name = match codec {
+            VideoCodec::H264 => "h264",
+            VideoCodec::Hevc => "hevc",
+            _ => return Err(core_error!("no hardware encoder for {:?}", codec)),
+        };
+
+        let backend = if cfg!(target_os = "macos") {
+            "videotoolbox"
+        } else {
+            match graphics_card.vendor {
+                GraphicsCardVendor::Nvidia => "nvenc",
+                GraphicsCardVendor::Amd => "amf",
+                GraphicsCardVendor::Intel => "qsv",
+                _ => {
+                    return Err(core_error!(
+                        "graphics card '{}' has no supported hardware encoder",
+                        graphics_card.name
+                    ))
+                }
+            }
+        };
+
+        Ok(HardwareEncoderConfig {
+            graphics_card,
+            codec,
+            ffmpeg_encoder_name: CString::new(format!("{}_{}", codec_name, backend))?,
+        })
+    }
+}
+
+impl EncoderConfig for HardwareEncoderConfig {
+    fn apply_option(
+        &self,
+        codec_ctx: *mut AVCodecContext,
+        _media_config: &MediaConfig,
+    ) -> CoreResult<()> {
+        if cfg!(target_os = "macos") {
+            let profile = match self.codec {
+                VideoCodec::Hevc => "main",
+                _ => "high",
+            };
+            set_codec_ctx_option(codec_ctx, "profile", profile, 0)?;
+            set_codec_ctx_option(codec_ctx, "realtime", "true", 0)?;
+            return Ok(());
+        }
+
+        match self.graphics_card.vendor {
+            GraphicsCardVendor::Nvidia => {
+                let gpu = self.graphics_card.vendor_index.to_string();
+                set_codec_ctx_option(codec_ctx, "gpu", &gpu, 0)?;
+                set_codec_ctx_option(codec_ctx, "preset", "p1", 0)?;
+                set_codec_ctx_option(codec_ctx, "tune", "ull", 0)?;
+                set_codec_ctx_option(codec_ctx, "zerolatency", "1", 0)?;
+            }
+            GraphicsCardVendor::Amd => {
+                set_codec_ctx_option(codec_ctx, "usage", "ultralowlatency", 0)?;
+            }
+            GraphicsCardVendor::Intel => {
+                set_codec_ctx_option(codec_ctx, "preset", "veryfast", 0)?;
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    fn ffmpeg_encoder_name(&self) -> *const i8 {
+        self.ffmpeg_encoder_name.as_ptr()
+    }
+
+    fn av_codec_id(&self) -> AVCodecID {
+        match self.codec {
+            VideoCodec::Hevc => AV_CODEC_ID_HEVC,
+            _ => AV_CODEC_ID_H264,
+        }
+    }
+
+    fn graphics_card(&self) -> Option<&GraphicsCards> {
+        Some(&self.graphics_card)
+    }
+}
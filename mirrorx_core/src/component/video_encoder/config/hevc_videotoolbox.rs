@@ -1,5 +1,5 @@
 use super::{set_codec_ctx_option, EncoderConfig};
-use crate::error::CoreResult;
+use crate::{component::video_encoder::media_config::MediaConfig, error::CoreResult};
 use mirrorx_native::ffmpeg::avcodec::*;
 use std::ffi::CString;
 
@@ -16,7 +16,11 @@ impl HEVCVideoToolboxConfig {
 }
 
 impl EncoderConfig for HEVCVideoToolboxConfig {
-    fn apply_option(&self, codec_ctx: *mut AVCodecContext) -> CoreResult<()> {
+    fn apply_option(
+        &self,
+        codec_ctx: *mut AVCodecContext,
+        _media_config: &MediaConfig,
+    ) -> CoreResult<()> {
         set_codec_ctx_option(codec_ctx, "profile", "high", 0)?;
         set_codec_ctx_option(codec_ctx, "realtime", "true", 0)?;
         // set_codec_ctx_option(codec_ctx, "prio_speed", "true", 0)?;
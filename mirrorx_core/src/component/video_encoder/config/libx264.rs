@@ -1,5 +1,8 @@
 use super::{set_codec_ctx_option, EncoderConfig};
-use crate::error::CoreResult;
+use crate::{
+    component::video_encoder::media_config::{MediaConfig, X264_TUNE_NONE},
+    error::CoreResult,
+};
 use mirrorx_native::ffmpeg::avcodec::*;
 use std::ffi::CString;
 
@@ -16,11 +19,18 @@ impl Libx264Config {
 }
 
 impl EncoderConfig for Libx264Config {
-    fn apply_option(&self, codec_ctx: *mut AVCodecContext) -> CoreResult<()> {
+    fn apply_option(
+        &self,
+        codec_ctx: *mut AVCodecContext,
+        media_config: &MediaConfig,
+    ) -> CoreResult<()> {
         set_codec_ctx_option(codec_ctx, "profile", "baseline", 0)?;
         set_codec_ctx_option(codec_ctx, "level", "5.0", 0)?;
-        set_codec_ctx_option(codec_ctx, "preset", "ultrafast", 0)?;
-        set_codec_ctx_option(codec_ctx, "tune", "zerolatency", 0)?;
+        set_codec_ctx_option(codec_ctx, "preset", &media_config.x264_preset, 0)?;
+
+        if media_config.x264_tune != X264_TUNE_NONE {
+            set_codec_ctx_option(codec_ctx, "tune", &media_config.x264_tune, 0)?;
+        }
 
         Ok(())
     }
@@ -0,0 +1,46 @@
+use super::{set_codec_ctx_option, EncoderConfig};
+use crate::{component::video_encoder::media_config::MediaConfig, error::CoreResult};
+use mirrorx_native::ffmpeg::avcodec::*;
+use std::ffi::CString;
+
+pub struct Libx265Config {
+    ffmpeg_encoder_name: CString,
+}
+
+impl Libx265Config {
+    pub fn new() -> Self {
+        Libx265Config {
+            ffmpeg_encoder_name: CString::new("libx265").unwrap(),
+        }
+    }
+}
+
+impl Default for Libx265Config {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EncoderConfig for Libx265Config {
+    fn apply_option(
+        &self,
+        codec_ctx: *mut AVCodecContext,
+        media_config: &MediaConfig,
+    ) -> CoreResult<()> {
+        // x265 shares the presets of x264 but takes a single tuning, and only
+        // zerolatency keeps frames from waiting for the look-ahead
+        set_codec_ctx_option(codec_ctx, "preset", &media_config.x264_preset, 0)?;
+        set_codec_ctx_option(codec_ctx, "tune", "zerolatency", 0)?;
+        set_codec_ctx_option(codec_ctx, "x265-params", "log-level=error", 0)?;
+
+        Ok(())
+    }
+
+    fn ffmpeg_encoder_name(&self) -> *const i8 {
+        self.ffmpeg_encoder_name.as_ptr()
+    }
+
+    fn av_codec_id(&self) -> AVCodecID {
+        AV_CODEC_ID_HEVC
+    }
+}
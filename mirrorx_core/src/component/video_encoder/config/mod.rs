@@ -1,15 +1,49 @@
 pub mod h264_videotoolbox;
+pub mod hardware;
 pub mod hevc_videotoolbox;
 pub mod libx264;
+pub mod libx265;
 
-use crate::{core_error, error::CoreResult};
+use super::media_config::MediaConfig;
+use crate::{core_error, error::CoreResult, utility::os::GraphicsCards};
 use mirrorx_native::ffmpeg::{avcodec::*, avutil::*};
 use std::ffi::CString;
 
 pub trait EncoderConfig {
-    fn apply_option(&self, codec_ctx: *mut AVCodecContext) -> CoreResult<()>;
+    fn apply_option(
+        &self,
+        codec_ctx: *mut AVCodecContext,
+        media_config: &MediaConfig,
+    ) -> CoreResult<()>;
     fn ffmpeg_encoder_name(&self) -> *const i8;
     fn av_codec_id(&self) -> AVCodecID;
+
+    /// The graphics card this encoder runs on, `None` for software encoders.
+    fn graphics_card(&self) -> Option<&GraphicsCards> {
+        None
+    }
+}
+
+impl EncoderConfig for Box<dyn EncoderConfig> {
+    fn apply_option(
+        &self,
+        codec_ctx: *mut AVCodecContext,
+        media_config: &MediaConfig,
+    ) -> CoreResult<()> {
+        (**self).apply_option(codec_ctx, media_config)
+    }
+
+    fn ffmpeg_encoder_name(&self) -> *const i8 {
+        (**self).ffmpeg_encoder_name()
+    }
+
+    fn av_codec_id(&self) -> AVCodecID {
+        (**self).av_codec_id()
+    }
+
+    fn graphics_card(&self) -> Option<&GraphicsCards> {
+        (**self).graphics_card()
+    }
 }
 
 fn set_codec_ctx_option(
@@ -0,0 +1,182 @@
+use super::media_config::MediaConfig;
+use crate::component::frame::DesktopEncodeFrame;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Keyframe interval of [`ContentMode::Text`] in frames, so a lost frame
+/// smears text only for a moment.
+pub const TEXT_KEYFRAME_INTERVAL: i32 = 60;
+
+/// Shortest time between two switches of the automatic detection. Every
+/// switch starts a new encode context with a keyframe, so it must not
+/// flip-flop on every scroll.
+pub const CONTENT_MODE_SWITCH_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Share of sampled pixels changing from frame to frame above which the
+/// screen counts as motion, and below which it counts as text again.
+const MOTION_ENTER_SHARE: f64 = 0.2;
+const MOTION_LEAVE_SHARE: f64 = 0.05;
+
+/// Weight of the latest frame in the smoothed share of changed pixels.
+const MOTION_SMOOTHING: f64 = 0.1;
+
+/// Distance between the sampled pixels in both directions.
+const SAMPLE_STEP: usize = 8;
+
+/// Luma difference a sampled pixel needs to count as changed, below it is
+/// capture noise.
+const CHANGE_THRESHOLD: u8 = 8;
+
+/// What the user says the shared screen shows, the encoder is tuned for it.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentHint {
+    /// Encodes with the media config as it is.
+    #[default]
+    Off,
+    /// Picks the mode from the motion between the captured frames.
+    Auto,
+    Text,
+    Motion,
+}
+
+/// The content the encoder is tuned for.
+///
+/// | mode     | x264 tune          | keyframe interval   |
+/// |----------|--------------------|---------------------|
+/// | `Text`   | `zerolatency`      | at most 60 frames   |
+/// | `Motion` | `film,zerolatency` | as configured       |
+///
+/// The capture pipeline only produces NV12 frames, so both modes stay on
+/// 4:2:0 chroma subsampling. Hardware encoders ignore the tune and only
+/// take the keyframe interval.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum ContentMode {
+    Text,
+    Motion,
+}
+
+impl ContentMode {
+    /// Returns the media config to encode this content with.
+    pub fn tune(self, media_config: &MediaConfig) -> MediaConfig {
+        let mut media_config = media_config.clone();
+
+        match self {
+            ContentMode::Text => {
+                media_config.keyframe_interval =
+                    media_config.keyframe_interval.min(TEXT_KEYFRAME_INTERVAL);
+                media_config.x264_tune = String::from("zerolatency");
+            }
+            ContentMode::Motion => {
+                // the psy tuning of film, without the look-ahead delay
+                media_config.x264_tune = String::from("film,zerolatency");
+            }
+        }
+
+        media_config
+    }
+}
+
+/// Smoothed share of the pixels that change from frame to frame, measured
+/// on a sparse grid of the luma plane.
+#[derive(Debug, Default)]
+pub struct MotionDetector {
+    previous_samples: Vec<u8>,
+    motion_share: f64,
+}
+
+impl MotionDetector {
+    pub fn observe(&mut self, frame: &DesktopEncodeFrame) -> f64 {
+        let stride = frame.luminance_stride as usize;
+
+        let samples: Vec<u8> = (0..frame.height as usize)
+            .step_by(SAMPLE_STEP)
+            .flat_map(|y| {
+                (0..frame.width as usize)
+                    .step_by(SAMPLE_STEP)
+                    .filter_map(move |x| frame.luminance_bytes.get(y * stride + x).copied())
+            })
+            .collect();
+
+        // a frame of another size has nothing to compare with
+        if !samples.is_empty() && samples.len() == self.previous_samples.len() {
+            let changed = samples
+                .iter()
+                .zip(self.previous_samples.iter())
+                .filter(|(sample, previous)| sample.abs_diff(**previous) > CHANGE_THRESHOLD)
+                .count();
+
+            let share = changed as f64 / samples.len() as f64;
+            self.motion_share += (share - self.motion_share) * MOTION_SMOOTHING;
+        }
+
+        self.previous_samples = samples;
+        self.motion_share
+    }
+}
+
+/// Turns the content hint into the mode the encoder is tuned for, detecting
+/// it from the frames when the hint is [`ContentHint::Auto`].
+#[derive(Debug, Default)]
+pub struct ContentTuning {
+    detector: MotionDetector,
+    mode: Option<ContentMode>,
+    /// Capture time of the frame the detection last switched at.
+    switched_at: Option<Duration>,
+}
+
+impl ContentTuning {
+    /// The mode of the current encode context, `None` while the hint is off.
+    pub fn mode(&self) -> Option<ContentMode> {
+        self.mode
+    }
+
+    /// Returns the media config to create the next encode context with.
+    pub fn tune(&self, media_config: &MediaConfig) -> MediaConfig {
+        match self.mode {
+            Some(mode) => mode.tune(media_config),
+            None => media_config.clone(),
+        }
+    }
+
+    /// Looks at a frame about to be encoded. Returns true when the mode
+    /// changed, the encoder then starts over with a keyframe.
+    pub fn update(&mut self, hint: ContentHint, frame: &DesktopEncodeFrame) -> bool {
+        let mode = match hint {
+            ContentHint::Off => None,
+            ContentHint::Text => Some(ContentMode::Text),
+            ContentHint::Motion => Some(ContentMode::Motion),
+            ContentHint::Auto => Some(self.detect(frame)),
+        };
+
+        if mode == self.mode {
+            return false;
+        }
+
+        self.mode = mode;
+        self.switched_at = Some(frame.capture_time);
+        true
+    }
+
+    fn detect(&mut self, frame: &DesktopEncodeFrame) -> ContentMode {
+        let motion_share = self.detector.observe(frame);
+
+        let Some(mode) = self.mode else {
+            return ContentMode::Text;
+        };
+
+        let settled = self.switched_at.is_none_or(|switched_at| {
+            frame.capture_time.saturating_sub(switched_at) >= CONTENT_MODE_SWITCH_INTERVAL
+        });
+        if !settled {
+            return mode;
+        }
+
+        match mode {
+            ContentMode::Text if motion_share > MOTION_ENTER_SHARE => ContentMode::Motion,
+            ContentMode::Motion if motion_share < MOTION_LEAVE_SHARE => ContentMode::Text,
+            mode => mode,
+        }
+    }
+}
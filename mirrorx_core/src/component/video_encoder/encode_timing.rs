@@ -0,0 +1,223 @@
+use mirrorx_native::ffmpeg::avutil::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex, MutexGuard, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+
+/// Frames the encode latency percentiles are taken over, ten seconds at 30
+/// fps.
+pub const ENCODE_TIMING_WINDOW: usize = 300;
+
+/// Frames an encoder may hold back before their packets come out, those of
+/// frames it dropped are forgotten beyond.
+pub const MAX_PENDING_FRAMES: usize = 64;
+
+/// Target of the per frame trace events, a tracing filter like
+/// `mirrorx_core::encode_trace=info` picks them out.
+pub const ENCODE_TRACE_TARGET: &str = "mirrorx_core::encode_trace";
+
+static ENCODE_TRACE: AtomicBool = AtomicBool::new(false);
+
+/// Turns the per frame trace events of all encoders on or off.
+pub fn set_encode_trace(enabled: bool) {
+    ENCODE_TRACE.store(enabled, Ordering::Relaxed);
+}
+
+pub fn encode_trace_enabled() -> bool {
+    ENCODE_TRACE.load(Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrameType {
+    I,
+    P,
+    B,
+    /// The encoder didn't tell, or a type H.264 doesn't use.
+    Unknown,
+}
+
+impl FrameType {
+    pub fn from_picture_type(picture_type: AVPictureType) -> Self {
+        match picture_type {
+            AV_PICTURE_TYPE_I => FrameType::I,
+            AV_PICTURE_TYPE_P => FrameType::P,
+            AV_PICTURE_TYPE_B => FrameType::B,
+            _ => FrameType::Unknown,
+        }
+    }
+}
+
+/// Reads the `AV_PKT_DATA_QUALITY_STATS` side data of a packet: the
+/// quantizer as a lambda in its first four bytes, the picture type in the
+/// fifth. libx264 and the hardware encoders of ffmpeg attach it,
+/// VideoToolbox doesn't.
+pub fn parse_quality_stats(side_data: &[u8]) -> Option<(u8, FrameType)> {
+    if side_data.len() < 5 {
+        return None;
+    }
+
+    let lambda = u32::from_le_bytes([side_data[0], side_data[1], side_data[2], side_data[3]]);
+    let qp = (lambda + FF_QP2LAMBDA as u32 / 2) / FF_QP2LAMBDA as u32;
+
+    Some((
+        qp.min(u8::MAX as u32) as u8,
+        FrameType::from_picture_type(side_data[4] as AVPictureType),
+    ))
+}
+
+/// Where the time of one encoded frame went.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EncodeTiming {
+    pub seq: u64,
+    /// On the clock of the capture, which starts with its first frame.
+    pub capture_time: Duration,
+    /// From the encoder taking the frame to handing it to ffmpeg: scaling,
+    /// preprocessing and change detection.
+    pub prepare: Duration,
+    /// From handing the frame to ffmpeg to the packet coming out.
+    pub encode: Duration,
+    pub bytes: usize,
+    pub frame_type: FrameType,
+    pub qp: Option<u8>,
+}
+
+impl EncodeTiming {
+    /// Emits the timing as a trace event, see [`set_encode_trace`].
+    pub fn trace(&self) {
+        tracing::info!(
+            target: ENCODE_TRACE_TARGET,
+            seq = self.seq,
+            capture_ms = self.capture_time.as_millis() as u64,
+            prepare_us = self.prepare.as_micros() as u64,
+            encode_us = self.encode.as_micros() as u64,
+            bytes = self.bytes,
+            frame_type = ?self.frame_type,
+            qp = ?self.qp,
+            "frame encoded"
+        );
+    }
+}
+
+/// A frame handed to the encoder whose packet didn't come out yet.
+#[derive(Debug, Clone, Copy)]
+pub struct PendingFrame {
+    pub pts: i64,
+    pub capture_time: Duration,
+    pub prepare: Duration,
+    pub sent_at: Instant,
+}
+
+/// The frames in the encoder, matched to their packets by pts.
+#[derive(Debug, Default)]
+pub struct PendingFrames {
+    frames: VecDeque<PendingFrame>,
+}
+
+impl PendingFrames {
+    pub fn push(&mut self, frame: PendingFrame) {
+        if self.frames.len() >= MAX_PENDING_FRAMES {
+            self.frames.pop_front();
+        }
+
+        self.frames.push_back(frame);
+    }
+
+    /// The frame a packet of `pts` was encoded from.
+    pub fn take(&mut self, pts: i64) -> Option<PendingFrame> {
+        let index = self.frames.iter().position(|frame| frame.pts == pts)?;
+        self.frames.remove(index)
+    }
+
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+}
+
+/// Encode latency percentiles of the recent frames, in milliseconds.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EncodeLatency {
+    pub frames: usize,
+    pub p50_ms: f64,
+    pub p95_ms: f64,
+    pub p99_ms: f64,
+    /// Keyframes among the frames, they take the longest to encode.
+    pub keyframes: usize,
+}
+
+/// The encode timings of the last [`ENCODE_TIMING_WINDOW`] frames of a
+/// session.
+#[derive(Debug)]
+pub struct EncodeTimings {
+    window: usize,
+    timings: Mutex<VecDeque<(Duration, FrameType)>>,
+}
+
+impl Default for EncodeTimings {
+    fn default() -> Self {
+        Self::new(ENCODE_TIMING_WINDOW)
+    }
+}
+
+impl EncodeTimings {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            timings: Mutex::new(VecDeque::with_capacity(window.max(1))),
+        }
+    }
+
+    pub fn record(&self, timing: &EncodeTiming) {
+        let mut timings = self.timings();
+        if timings.len() >= self.window {
+            timings.pop_front();
+        }
+
+        timings.push_back((timing.encode, timing.frame_type));
+    }
+
+    pub fn clear(&self) {
+        self.timings().clear();
+    }
+
+    /// `None` until a frame was encoded.
+    pub fn latency(&self) -> Option<EncodeLatency> {
+        let timings = self.timings();
+        if timings.is_empty() {
+            return None;
+        }
+
+        let mut encode: Vec<Duration> = timings.iter().map(|(encode, _)| *encode).collect();
+        encode.sort_unstable();
+
+        // nearest rank
+        let percentile = |p: usize| {
+            let rank = (encode.len() * p).div_ceil(100);
+            encode[rank.max(1) - 1].as_secs_f64() * 1000.0
+        };
+
+        Some(EncodeLatency {
+            frames: encode.len(),
+            p50_ms: percentile(50),
+            p95_ms: percentile(95),
+            p99_ms: percentile(99),
+            keyframes: timings
+                .iter()
+                .filter(|(_, frame_type)| *frame_type == FrameType::I)
+                .count(),
+        })
+    }
+
+    fn timings(&self) -> MutexGuard<'_, VecDeque<(Duration, FrameType)>> {
+        self.timings.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
@@ -0,0 +1,263 @@
+use super::content_hint::ContentHint;
+use crate::component::{
+    desktop::capture_mode::scale_dirty_rects, fec::MAX_FEC_LEVEL, frame::DesktopEncodeFrame,
+    slice::MAX_SLICES_PER_FRAME,
+};
+use serde::{Deserialize, Serialize};
+
+/// Named encoder settings for users who don't want to tune the raw knobs.
+///
+/// | preset       | frame rate | scale | bitrate   | keyframe interval |
+/// |--------------|------------|-------|-----------|-------------------|
+/// | `Low`        | 20         | 50%   | 1000 kbps | 240 frames        |
+/// | `Balanced`   | 30         | 75%   | 4000 kbps | 300 frames        |
+/// | `High`       | 60         | 100%  | 8000 kbps | 600 frames        |
+/// | `SharpText`  | 30         | 100%  | 6000 kbps | 60 frames         |
+///
+/// The capture pipeline only produces NV12 frames, so every preset stays on
+/// 4:2:0 chroma subsampling. `SharpText` keeps native resolution and a short
+/// keyframe interval instead, which is what makes small glyphs readable.
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum QualityPreset {
+    Low,
+    #[default]
+    Balanced,
+    High,
+    SharpText,
+    Custom,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct MediaConfig {
+    pub preset: QualityPreset,
+    pub frame_rate: u8,
+    /// Output resolution in percent of the captured monitor resolution.
+    pub scale_percent: u8,
+    pub bitrate_kbps: u32,
+    /// Keyframe interval in frames.
+    pub keyframe_interval: i32,
+    /// Whether the pointer is drawn into the captured frames. Leaving it out
+    /// saves the frames encoded only because the pointer moved.
+    #[serde(default = "default_include_cursor")]
+    pub include_cursor: bool,
+    /// Redundancy of forward error correction, see
+    /// [`fec_group_size`](crate::component::fec::fec_group_size). 0 sends no
+    /// parity frames.
+    #[serde(default)]
+    pub fec_level: u8,
+    /// Speed preset of the software encoder, one of [`X264_PRESETS`]. Hardware
+    /// encoders ignore it.
+    #[serde(default = "default_x264_preset")]
+    pub x264_preset: String,
+    /// Tuning of the software encoder, one of [`X264_TUNES`]. Hardware encoders
+    /// ignore it.
+    #[serde(default = "default_x264_tune")]
+    pub x264_tune: String,
+    /// What the shared screen shows, tunes the encoder in place of
+    /// `x264_tune` and the keyframe interval unless it's off. Switchable
+    /// while the session is running.
+    #[serde(default)]
+    pub content_hint: ContentHint,
+    /// The window shared in place of the monitor, one of
+    /// [`list_windows`](crate::component::desktop::window::list_windows).
+    /// The host sets it from its own selection, whatever the viewer sent.
+    #[serde(default)]
+    pub capture_window: Option<u64>,
+    /// Slices every frame is encoded in, up to [`MAX_SLICES_PER_FRAME`].
+    /// They're sent one by one, 1 sends whole frames.
+    #[serde(default = "default_slices_per_frame")]
+    pub slices_per_frame: u8,
+    /// Encodes a frame only when the screen changed, for monitoring static
+    /// screens. A change of a few rows goes out as the slices covering them
+    /// when there is more than one slice per frame.
+    #[serde(default)]
+    pub change_only: bool,
+    /// Difference of a luma or chroma byte to the content last sent above
+    /// which it counts as changed in `change_only` mode, smaller ones are
+    /// capture noise.
+    #[serde(default = "default_change_threshold")]
+    pub change_threshold: u8,
+    /// Whether the host sends its audio along, off for video only sessions.
+    #[serde(default = "default_audio")]
+    pub audio: bool,
+}
+
+/// Upper bound of `change_threshold`, beyond it real changes of the screen
+/// pass as noise.
+pub const MAX_CHANGE_THRESHOLD: u8 = 64;
+
+/// x264 presets from the fastest to the slowest. Slower presets compress
+/// better at the same bitrate and cost more CPU time per frame.
+pub const X264_PRESETS: [&str; 9] = [
+    "ultrafast",
+    "superfast",
+    "veryfast",
+    "faster",
+    "fast",
+    "medium",
+    "slow",
+    "slower",
+    "veryslow",
+];
+
+/// Leaves the tuning of x264 unset.
+pub const X264_TUNE_NONE: &str = "none";
+
+/// x264 tunings. `zerolatency` turns B-frames, the rate control look-ahead
+/// and frame threading off, so every frame leaves the encoder right after it
+/// went in. Any other tuning makes x264 buffer frames for the look-ahead,
+/// which delays them, fine for recording but noticeable when controlling a
+/// remote desktop.
+pub const X264_TUNES: [&str; 9] = [
+    X264_TUNE_NONE,
+    "zerolatency",
+    "film",
+    "animation",
+    "grain",
+    "stillimage",
+    "psnr",
+    "ssim",
+    "fastdecode",
+];
+
+fn default_include_cursor() -> bool {
+    true
+}
+
+fn default_audio() -> bool {
+    true
+}
+
+fn default_slices_per_frame() -> u8 {
+    1
+}
+
+fn default_change_threshold() -> u8 {
+    8
+}
+
+fn default_x264_preset() -> String {
+    String::from("ultrafast")
+}
+
+fn default_x264_tune() -> String {
+    String::from("zerolatency")
+}
+
+impl MediaConfig {
+    /// Returns the concrete settings of a preset. `Custom` has no settings of
+    /// its own and falls back to `Balanced`.
+    pub fn from_preset(preset: QualityPreset) -> Self {
+        let (frame_rate, scale_percent, bitrate_kbps, keyframe_interval) = match preset {
+            QualityPreset::Low => (20, 50, 1000, 240),
+            QualityPreset::Balanced | QualityPreset::Custom => (30, 75, 4000, 300),
+            QualityPreset::High => (60, 100, 8000, 600),
+            QualityPreset::SharpText => (30, 100, 6000, 60),
+        };
+
+        MediaConfig {
+            preset,
+            frame_rate,
+            scale_percent,
+            bitrate_kbps,
+            keyframe_interval,
+            include_cursor: default_include_cursor(),
+            fec_level: 0,
+            x264_preset: default_x264_preset(),
+            x264_tune: default_x264_tune(),
+            content_hint: ContentHint::default(),
+            capture_window: None,
+            slices_per_frame: default_slices_per_frame(),
+            change_only: false,
+            change_threshold: default_change_threshold(),
+            audio: default_audio(),
+        }
+    }
+
+    /// Clamps every field into a range the encoder accepts, so a config coming
+    /// from the remote side or an edited database can't break the encoder.
+    pub fn sanitize(mut self) -> Self {
+        self.frame_rate = self.frame_rate.clamp(1, 60);
+        self.scale_percent = self.scale_percent.clamp(25, 100);
+        self.bitrate_kbps = self.bitrate_kbps.clamp(100, 50 * 1000);
+        self.keyframe_interval = self.keyframe_interval.clamp(1, 4000);
+        self.fec_level = self.fec_level.min(MAX_FEC_LEVEL);
+        self.slices_per_frame = self.slices_per_frame.clamp(1, MAX_SLICES_PER_FRAME);
+        self.change_threshold = self.change_threshold.min(MAX_CHANGE_THRESHOLD);
+
+        if !X264_PRESETS.contains(&self.x264_preset.as_str()) {
+            tracing::warn!(x264_preset = ?self.x264_preset, "unsupported x264 preset, use default");
+            self.x264_preset = default_x264_preset();
+        }
+
+        if !X264_TUNES.contains(&self.x264_tune.as_str()) {
+            tracing::warn!(x264_tune = ?self.x264_tune, "unsupported x264 tune, use default");
+            self.x264_tune = default_x264_tune();
+        }
+
+        self
+    }
+
+    /// Returns the encoded size of a captured frame. Both sides are kept even
+    /// because NV12 chroma planes are subsampled by two.
+    pub fn scaled_size(&self, width: i32, height: i32) -> (i32, i32) {
+        let scale = |v: i32| ((v as i64 * self.scale_percent as i64 / 100) as i32 & !1).max(2);
+        (scale(width), scale(height))
+    }
+}
+
+impl Default for MediaConfig {
+    fn default() -> Self {
+        MediaConfig::from_preset(QualityPreset::default())
+    }
+}
+
+/// Nearest neighbour downscale of a NV12 frame. Returns the frame untouched
+/// when the target size equals the source size.
+pub fn scale_frame(frame: DesktopEncodeFrame, width: i32, height: i32) -> DesktopEncodeFrame {
+    if frame.width == width && frame.height == height {
+        return frame;
+    }
+
+    let (dst_width, dst_height) = (width as usize, height as usize);
+    let (src_width, src_height) = (frame.width as usize, frame.height as usize);
+    let luminance_stride = frame.luminance_stride as usize;
+    let chrominance_stride = frame.chrominance_stride as usize;
+
+    let mut luminance_bytes = Vec::with_capacity(dst_width * dst_height);
+    for y in 0..dst_height {
+        let src_row = (y * src_height / dst_height) * luminance_stride;
+        for x in 0..dst_width {
+            luminance_bytes.push(frame.luminance_bytes[src_row + x * src_width / dst_width]);
+        }
+    }
+
+    // every chroma sample is an interleaved UV pair covering a 2x2 luma block
+    let (dst_chroma_width, dst_chroma_height) = (dst_width / 2, dst_height / 2);
+    let (src_chroma_width, src_chroma_height) = (src_width / 2, src_height / 2);
+
+    let mut chrominance_bytes = Vec::with_capacity(dst_width * dst_chroma_height);
+    for y in 0..dst_chroma_height {
+        let src_row = (y * src_chroma_height / dst_chroma_height) * chrominance_stride;
+        for x in 0..dst_chroma_width {
+            let src_offset = src_row + (x * src_chroma_width / dst_chroma_width) * 2;
+            chrominance_bytes
+                .extend_from_slice(&frame.chrominance_bytes[src_offset..src_offset + 2]);
+        }
+    }
+
+    DesktopEncodeFrame {
+        capture_time: frame.capture_time,
+        width,
+        height,
+        luminance_bytes,
+        luminance_stride: width,
+        chrominance_bytes,
+        chrominance_stride: width,
+        dirty_rects: frame
+            .dirty_rects
+            .as_ref()
+            .map(|rects| scale_dirty_rects(rects, frame.width, frame.height, width, height)),
+    }
+}
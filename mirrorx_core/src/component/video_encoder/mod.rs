@@ -1,2 +1,11 @@
+pub mod adapter;
+pub mod adaptive;
+pub mod bitrate;
+pub mod change_detect;
 pub mod config;
+pub mod content_hint;
+pub mod encode_timing;
+pub mod media_config;
+pub mod preprocess;
+pub mod validate;
 pub mod video_encoder;
@@ -0,0 +1,254 @@
+use crate::component::{desktop::monitor::Monitor, frame::DesktopEncodeFrame};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+/// A stage that edits every captured frame before it is scaled and encoded.
+pub trait FramePreprocessor {
+    fn process(&self, frame: &mut DesktopEncodeFrame);
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub enum PrivacyRegionKind {
+    Opaque,
+    Blur,
+}
+
+/// A rectangle in desktop coordinates, the ones monitors are placed in, that
+/// never leaves this machine unmasked.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct PrivacyRegion {
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+    pub kind: PrivacyRegionKind,
+}
+
+/// Size of the pixelation blocks, large enough that text inside can't be
+/// recovered.
+const BLUR_BLOCK_SIZE: usize = 16;
+
+static PRIVACY_REGIONS: Lazy<RwLock<Vec<PrivacyRegion>>> = Lazy::new(|| RwLock::new(Vec::new()));
+
+pub fn set_privacy_regions(regions: Vec<PrivacyRegion>) {
+    if let Ok(mut privacy_regions) = PRIVACY_REGIONS.write() {
+        *privacy_regions = regions;
+    }
+}
+
+pub fn privacy_regions() -> Vec<PrivacyRegion> {
+    PRIVACY_REGIONS
+        .read()
+        .map(|regions| regions.clone())
+        .unwrap_or_default()
+}
+
+/// The area of the captured display on the desktop, in the coordinates the
+/// privacy regions are given in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct DisplayPlacement {
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl From<&Monitor> for DisplayPlacement {
+    fn from(monitor: &Monitor) -> Self {
+        Self {
+            left: monitor.left,
+            top: monitor.top,
+            width: monitor.width as i32,
+            height: monitor.height as i32,
+        }
+    }
+}
+
+impl DisplayPlacement {
+    /// The part of `region` on this display in pixels of a frame of
+    /// `frame_width` x `frame_height`. The frame of a scaled display has more
+    /// pixels than the display has points, edges are rounded outwards so the
+    /// mask never leaves a pixel of the region uncovered.
+    pub fn map_region(
+        &self,
+        region: &PrivacyRegion,
+        frame_width: i32,
+        frame_height: i32,
+    ) -> PrivacyRegion {
+        let map = |v: i32, origin: i32, frame: i32, display: i32, round_up: bool| {
+            let pixels = (v as i64 - origin as i64) * frame as i64;
+            let display = display.max(1) as i64;
+
+            let v = if round_up {
+                -(-pixels).div_euclid(display)
+            } else {
+                pixels.div_euclid(display)
+            };
+
+            v.clamp(i32::MIN as i64, i32::MAX as i64) as i32
+        };
+
+        let left = map(region.left, self.left, frame_width, self.width, false);
+        let top = map(region.top, self.top, frame_height, self.height, false);
+        let right = map(
+            region.left.saturating_add(region.width),
+            self.left,
+            frame_width,
+            self.width,
+            true,
+        );
+        let bottom = map(
+            region.top.saturating_add(region.height),
+            self.top,
+            frame_height,
+            self.height,
+            true,
+        );
+
+        PrivacyRegion {
+            left,
+            top,
+            width: right.saturating_sub(left),
+            height: bottom.saturating_sub(top),
+            kind: region.kind,
+        }
+    }
+}
+
+/// The display the frames come from, set by the capture once it picked one
+/// and read by the encoder masking them. Clones share the display.
+#[derive(Debug, Default, Clone)]
+pub struct CapturedDisplay(Arc<Mutex<Option<DisplayPlacement>>>);
+
+impl CapturedDisplay {
+    pub fn set(&self, monitor: &Monitor) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(monitor.into());
+    }
+
+    pub fn placement(&self) -> Option<DisplayPlacement> {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// Masks the configured privacy regions. It runs before scaling so the mask
+/// covers the same display area whatever the output resolution is.
+#[derive(Default)]
+pub struct PrivacyMask {
+    display: CapturedDisplay,
+}
+
+impl PrivacyMask {
+    pub fn new(display: CapturedDisplay) -> Self {
+        Self { display }
+    }
+}
+
+impl FramePreprocessor for PrivacyMask {
+    fn process(&self, frame: &mut DesktopEncodeFrame) {
+        let Ok(regions) = PRIVACY_REGIONS.read() else {
+            return;
+        };
+
+        // without a display the regions are taken as frame pixels
+        let placement = self.display.placement();
+
+        for region in regions.iter() {
+            match placement {
+                Some(placement) => apply_privacy_region(
+                    frame,
+                    &placement.map_region(region, frame.width, frame.height),
+                ),
+                None => apply_privacy_region(frame, region),
+            }
+        }
+    }
+}
+
+pub fn apply_privacy_region(frame: &mut DesktopEncodeFrame, region: &PrivacyRegion) {
+    // clamp to the frame and align to even pixels to match NV12 chroma blocks
+    let (width, height) = (frame.width & !1, frame.height & !1);
+    let align = |v: i32, max: i32| ((v.clamp(0, max) + 1) & !1).min(max) as usize;
+
+    let left = (region.left.clamp(0, width) & !1) as usize;
+    let top = (region.top.clamp(0, height) & !1) as usize;
+    let right = align(region.left.saturating_add(region.width), width);
+    let bottom = align(region.top.saturating_add(region.height), height);
+
+    if left >= right || top >= bottom {
+        return;
+    }
+
+    let luminance_stride = frame.luminance_stride as usize;
+    let chrominance_stride = frame.chrominance_stride as usize;
+
+    match region.kind {
+        PrivacyRegionKind::Opaque => {
+            for y in top..bottom {
+                let row = y * luminance_stride;
+                frame.luminance_bytes[row + left..row + right].fill(0);
+            }
+
+            for y in top / 2..bottom / 2 {
+                let row = y * chrominance_stride;
+                frame.chrominance_bytes[row + left..row + right].fill(128);
+            }
+        }
+        PrivacyRegionKind::Blur => {
+            pixelate(
+                &mut frame.luminance_bytes,
+                luminance_stride,
+                (left, top, right, bottom),
+                BLUR_BLOCK_SIZE,
+                1,
+            );
+
+            pixelate(
+                &mut frame.chrominance_bytes,
+                chrominance_stride,
+                (left / 2, top / 2, right / 2, bottom / 2),
+                BLUR_BLOCK_SIZE / 2,
+                2,
+            );
+        }
+    }
+}
+
+/// Replaces every block of the area with its average. `channels` is the count
+/// of interleaved samples per pixel, which are averaged separately.
+fn pixelate(
+    plane: &mut [u8],
+    stride: usize,
+    (left, top, right, bottom): (usize, usize, usize, usize),
+    block_size: usize,
+    channels: usize,
+) {
+    for block_top in (top..bottom).step_by(block_size) {
+        let block_bottom = (block_top + block_size).min(bottom);
+
+        for block_left in (left..right).step_by(block_size) {
+            let block_right = (block_left + block_size).min(right);
+
+            for channel in 0..channels {
+                let mut sum = 0usize;
+                let mut count = 0usize;
+
+                for y in block_top..block_bottom {
+                    for x in block_left..block_right {
+                        sum += plane[y * stride + x * channels + channel] as usize;
+                        count += 1;
+                    }
+                }
+
+                let average = (sum / count.max(1)) as u8;
+
+                for y in block_top..block_bottom {
+                    for x in block_left..block_right {
+                        plane[y * stride + x * channels + channel] = average;
+                    }
+                }
+            }
+        }
+    }
+}
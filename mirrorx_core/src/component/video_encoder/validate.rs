@@ -0,0 +1,326 @@
+use super::{
+    adapter::software_encoder_config,
+    config::EncoderConfig,
+    media_config::MediaConfig,
+    video_encoder::{encoder_available, encoder_name, probe_encoder},
+};
+use crate::api::endpoint::{
+    capabilities::{local_capabilities, video_codec_name},
+    message::VideoCodec,
+};
+use mirrorx_native::ffmpeg::avcodec::*;
+use serde::Serialize;
+
+/// Largest frame of H.264 level 5.2 in 16x16 macroblocks, 4096x2304. The
+/// hardware decoders of viewers don't go beyond.
+pub const MAX_FRAME_MACROBLOCKS: u32 = 36_864;
+
+/// Longest side of a frame hardware encoders take.
+pub const MAX_FRAME_SIDE: i32 = 4096;
+
+/// Macroblocks per second of H.264 level 5.2, 4096x2304 at 56 frames.
+pub const MAX_MACROBLOCK_RATE: u32 = 2_073_600;
+
+/// Bits per pixel and frame below which the picture falls apart into
+/// blocks, and above which the bitrate buys nothing visible.
+pub const MIN_BITS_PER_PIXEL: f64 = 0.01;
+pub const MAX_BITS_PER_PIXEL: f64 = 1.0;
+
+/// Something about a media config that won't work out as asked.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MediaConfigProblem {
+    /// The encoder would take `applied` in place of `value`.
+    OutOfRange {
+        field: String,
+        value: String,
+        applied: String,
+    },
+    /// This build has no encoder of the name.
+    CodecUnavailable {
+        encoder: String,
+    },
+    /// The encoder produces a codec this app doesn't negotiate with viewers.
+    CodecNotNegotiable {
+        encoder: String,
+    },
+    FrameTooLarge {
+        width: i32,
+        height: i32,
+    },
+    FrameRateTooHigh {
+        frame_rate: u8,
+        max_frame_rate: u32,
+    },
+    BitrateTooLow {
+        bitrate_kbps: u32,
+        min_kbps: u32,
+    },
+    BitrateTooHigh {
+        bitrate_kbps: u32,
+        max_kbps: u32,
+    },
+    /// The pinned graphics card failed to open its encoder, sessions fall
+    /// back to the software encoder.
+    HardwareEncoderFailed {
+        encoder: String,
+        reason: String,
+    },
+    EncoderFailed {
+        encoder: String,
+        reason: String,
+    },
+}
+
+impl MediaConfigProblem {
+    /// Whether a session fails with it, rather than running with less than
+    /// asked.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            MediaConfigProblem::CodecUnavailable { .. }
+                | MediaConfigProblem::CodecNotNegotiable { .. }
+                | MediaConfigProblem::FrameTooLarge { .. }
+                | MediaConfigProblem::EncoderFailed { .. }
+        )
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            MediaConfigProblem::OutOfRange {
+                field,
+                value,
+                applied,
+            } => format!("{field} {value} is out of range, {applied} is used instead."),
+            MediaConfigProblem::CodecUnavailable { encoder } => {
+                format!("The encoder {encoder} isn't available on this device.")
+            }
+            MediaConfigProblem::CodecNotNegotiable { encoder } => {
+                format!("Viewers can't decode the codec of the encoder {encoder}.")
+            }
+            MediaConfigProblem::FrameTooLarge { width, height } => format!(
+                "{width}x{height} exceeds what encoders and decoders take, lower the scale."
+            ),
+            MediaConfigProblem::FrameRateTooHigh {
+                frame_rate,
+                max_frame_rate,
+            } => format!(
+                "{frame_rate} fps exceeds the {max_frame_rate} fps decoders take at this size."
+            ),
+            MediaConfigProblem::BitrateTooLow {
+                bitrate_kbps,
+                min_kbps,
+            } => format!(
+                "{bitrate_kbps} kbps is too little for this size and frame rate, at least {min_kbps} kbps keep the picture readable."
+            ),
+            MediaConfigProblem::BitrateTooHigh {
+                bitrate_kbps,
+                max_kbps,
+            } => format!(
+                "{bitrate_kbps} kbps is more than this size and frame rate use, {max_kbps} kbps look the same."
+            ),
+            MediaConfigProblem::HardwareEncoderFailed { encoder, reason } => format!(
+                "The hardware encoder {encoder} failed ({reason}), the software encoder is used instead."
+            ),
+            MediaConfigProblem::EncoderFailed { encoder, reason } => {
+                format!("The encoder {encoder} failed to open ({reason}).")
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MediaConfigIssue {
+    pub fatal: bool,
+    pub message: String,
+    #[serde(flatten)]
+    pub problem: MediaConfigProblem,
+}
+
+impl From<MediaConfigProblem> for MediaConfigIssue {
+    fn from(problem: MediaConfigProblem) -> Self {
+        MediaConfigIssue {
+            fatal: problem.is_fatal(),
+            message: problem.message(),
+            problem,
+        }
+    }
+}
+
+/// The outcome of [`validate_media_config`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MediaConfigReport {
+    /// False when a session with the config would fail.
+    pub ok: bool,
+    /// The encoder sessions would use.
+    pub encoder: String,
+    /// Size of the encoded frames.
+    pub width: i32,
+    pub height: i32,
+    pub issues: Vec<MediaConfigIssue>,
+}
+
+/// The problems of a media config for a monitor of the given size that
+/// show without an encoder, the ranges of the fields and the limits of
+/// H.264.
+pub fn check_media_config(
+    media_config: &MediaConfig,
+    capture_width: i32,
+    capture_height: i32,
+) -> Vec<MediaConfigProblem> {
+    let sanitized = media_config.clone().sanitize();
+    let mut problems = Vec::new();
+
+    let mut out_of_range = |field: &str, value: String, applied: String| {
+        if value != applied {
+            problems.push(MediaConfigProblem::OutOfRange {
+                field: field.to_string(),
+                value,
+                applied,
+            });
+        }
+    };
+
+    macro_rules! check_range {
+        ($($field:ident),*) => {
+            $(out_of_range(
+                stringify!($field),
+                media_config.$field.to_string(),
+                sanitized.$field.to_string(),
+            );)*
+        };
+    }
+
+    check_range!(
+        frame_rate,
+        scale_percent,
+        bitrate_kbps,
+        keyframe_interval,
+        fec_level,
+        slices_per_frame,
+        change_threshold,
+        x264_preset,
+        x264_tune
+    );
+
+    let (width, height) = sanitized.scaled_size(capture_width, capture_height);
+    let macroblocks = (width as u32).div_ceil(16) * (height as u32).div_ceil(16);
+
+    if width > MAX_FRAME_SIDE || height > MAX_FRAME_SIDE || macroblocks > MAX_FRAME_MACROBLOCKS {
+        problems.push(MediaConfigProblem::FrameTooLarge { width, height });
+    } else if macroblocks * sanitized.frame_rate as u32 > MAX_MACROBLOCK_RATE {
+        problems.push(MediaConfigProblem::FrameRateTooHigh {
+            frame_rate: sanitized.frame_rate,
+            max_frame_rate: MAX_MACROBLOCK_RATE / macroblocks,
+        });
+    }
+
+    let pixel_rate = width as f64 * height as f64 * sanitized.frame_rate as f64;
+    let bits_per_pixel = sanitized.bitrate_kbps as f64 * 1000.0 / pixel_rate;
+
+    if bits_per_pixel < MIN_BITS_PER_PIXEL {
+        problems.push(MediaConfigProblem::BitrateTooLow {
+            bitrate_kbps: sanitized.bitrate_kbps,
+            min_kbps: (pixel_rate * MIN_BITS_PER_PIXEL / 1000.0).ceil() as u32,
+        });
+    } else if bits_per_pixel > MAX_BITS_PER_PIXEL {
+        problems.push(MediaConfigProblem::BitrateTooHigh {
+            bitrate_kbps: sanitized.bitrate_kbps,
+            max_kbps: (pixel_rate * MAX_BITS_PER_PIXEL / 1000.0).floor() as u32,
+        });
+    }
+
+    problems
+}
+
+/// Checks a media config for a monitor of the given size against the
+/// encoder sessions would use, without capturing anything. The encoder is
+/// opened for the size and closed again, a failing hardware encoder is
+/// followed by the software one like in a session.
+pub fn validate_media_config(
+    media_config: &MediaConfig,
+    capture_width: i32,
+    capture_height: i32,
+    encoder_config: &dyn EncoderConfig,
+) -> MediaConfigReport {
+    let sanitized = media_config.clone().sanitize();
+    let (width, height) = sanitized.scaled_size(capture_width, capture_height);
+
+    let mut problems = check_media_config(media_config, capture_width, capture_height);
+    let mut encoder = encoder_name(encoder_config);
+
+    let negotiable = video_codec(encoder_config.av_codec_id()).is_some_and(|codec| {
+        local_capabilities()
+            .video_codecs
+            .iter()
+            .any(|name| name == video_codec_name(&codec))
+    });
+
+    if !negotiable {
+        problems.push(MediaConfigProblem::CodecNotNegotiable {
+            encoder: encoder.clone(),
+        });
+    } else if !problems.iter().any(MediaConfigProblem::is_fatal) {
+        if let Err(problem) = probe(encoder_config, width, height, &sanitized) {
+            match problem {
+                MediaConfigProblem::EncoderFailed {
+                    encoder: hardware,
+                    reason,
+                } if encoder_config.graphics_card().is_some() => {
+                    problems.push(MediaConfigProblem::HardwareEncoderFailed {
+                        encoder: hardware,
+                        reason,
+                    });
+
+                    let codec = video_codec(encoder_config.av_codec_id()).unwrap_or_default();
+                    let software = software_encoder_config(&codec);
+                    encoder = encoder_name(&*software);
+                    if let Err(problem) = probe(&*software, width, height, &sanitized) {
+                        problems.push(problem);
+                    }
+                }
+                problem => problems.push(problem),
+            }
+        }
+    }
+
+    let issues: Vec<MediaConfigIssue> = problems.into_iter().map(MediaConfigIssue::from).collect();
+
+    MediaConfigReport {
+        ok: !issues.iter().any(|issue| issue.fatal),
+        encoder,
+        width,
+        height,
+        issues,
+    }
+}
+
+fn video_codec(av_codec_id: AVCodecID) -> Option<VideoCodec> {
+    match av_codec_id {
+        AV_CODEC_ID_H264 => Some(VideoCodec::H264),
+        AV_CODEC_ID_HEVC => Some(VideoCodec::Hevc),
+        AV_CODEC_ID_VP8 => Some(VideoCodec::VP8),
+        AV_CODEC_ID_VP9 => Some(VideoCodec::VP9),
+        _ => None,
+    }
+}
+
+fn probe(
+    encoder_config: &dyn EncoderConfig,
+    width: i32,
+    height: i32,
+    media_config: &MediaConfig,
+) -> Result<(), MediaConfigProblem> {
+    let encoder = encoder_name(encoder_config);
+
+    if !encoder_available(encoder_config) {
+        return Err(MediaConfigProblem::CodecUnavailable { encoder });
+    }
+
+    probe_encoder(encoder_config, width, height, media_config).map_err(|err| {
+        MediaConfigProblem::EncoderFailed {
+            encoder,
+            reason: err.to_string(),
+        }
+    })
+}
@@ -1,43 +1,217 @@
-use super::config::EncoderConfig;
+use super::{
+    adaptive::AdaptiveBitrate,
+    bitrate::BitrateController,
+    change_detect::{ChangeDetector, FrameChange},
+    config::EncoderConfig,
+    content_hint::ContentTuning,
+    encode_timing::{
+        encode_trace_enabled, parse_quality_stats, EncodeTiming, FrameType, PendingFrame,
+        PendingFrames,
+    },
+    media_config::{scale_frame, MediaConfig},
+    preprocess::{CapturedDisplay, FramePreprocessor, PrivacyMask},
+};
 use crate::{
     api::endpoint::{
         client::EndPointClient,
         message::{EndPointMessage, EndPointVideoFrame},
     },
-    component::frame::DesktopEncodeFrame,
+    component::{
+        desktop::display_config::DisplayConfigWatch, fec::FecEncoder, frame::DesktopEncodeFrame,
+        slice::{retain_h264_slices, split_h264_slices},
+    },
     core_error,
-    error::CoreResult,
+    error::{CoreError, CoreResult},
+    utility::os::GraphicsCards,
 };
 use mirrorx_native::ffmpeg::{avcodec::*, avutil::*};
-use std::sync::Arc;
+use std::{
+    ffi::CStr,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 pub struct VideoEncoder<T>
 where
     T: EncoderConfig,
 {
     encoder_config: T,
+    media_config: MediaConfig,
+    preprocessors: Vec<Box<dyn FramePreprocessor>>,
     encode_context: Option<EncodeContext>,
     client: Arc<EndPointClient>,
+    last_encode_time: Option<Duration>,
+    display_config_watch: DisplayConfigWatch,
+    fec_encoder: Option<FecEncoder>,
+    bitrate_controller: BitrateController,
+    adaptive_bitrate: AdaptiveBitrate,
+    content_tuning: ContentTuning,
+    change_detector: Option<ChangeDetector>,
+    pending_frames: PendingFrames,
 }
 
 impl<T> VideoEncoder<T>
 where
     T: EncoderConfig,
 {
-    pub fn new(encoder_config: T, client: Arc<EndPointClient>) -> CoreResult<VideoEncoder<T>> {
+    pub fn new(
+        encoder_config: T,
+        media_config: MediaConfig,
+        client: Arc<EndPointClient>,
+        captured_display: CapturedDisplay,
+    ) -> CoreResult<VideoEncoder<T>> {
         unsafe {
             av_log_set_level(AV_LOG_INFO);
             av_log_set_flags(AV_LOG_SKIP_REPEATED);
         }
 
+        let media_config = media_config.sanitize();
+        let fec_encoder = FecEncoder::new(media_config.fec_level);
+        let bitrate_controller = BitrateController::new(media_config.bitrate_kbps);
+        let adaptive_bitrate =
+            AdaptiveBitrate::new(media_config.bitrate_kbps, media_config.scale_percent);
+        let change_detector = media_config
+            .change_only
+            .then(|| ChangeDetector::new(media_config.change_threshold));
+
+        // frames of a window come masked, the privacy regions are placed on
+        // the display it was cut out of, and the viewer only knows the size
+        // of the monitor
+        let (preprocessors, display_config_watch): (Vec<Box<dyn FramePreprocessor>>, _) =
+            if media_config.capture_window.is_some() {
+                (Vec::new(), DisplayConfigWatch::announcing_first_frame())
+            } else {
+                (
+                    vec![Box::new(PrivacyMask::new(captured_display))],
+                    DisplayConfigWatch::default(),
+                )
+            };
+
         Ok(VideoEncoder {
             encoder_config,
+            media_config,
+            preprocessors,
             encode_context: None,
             client,
+            last_encode_time: None,
+            display_config_watch,
+            fec_encoder,
+            bitrate_controller,
+            adaptive_bitrate,
+            content_tuning: ContentTuning::default(),
+            change_detector,
+            pending_frames: PendingFrames::default(),
         })
     }
 
-    pub fn encode(&mut self, capture_frame: DesktopEncodeFrame) -> CoreResult<()> {
+    pub fn graphics_card(&self) -> Option<&GraphicsCards> {
+        self.encoder_config.graphics_card()
+    }
+
+    pub fn add_preprocessor(&mut self, preprocessor: Box<dyn FramePreprocessor>) {
+        self.preprocessors.push(preprocessor);
+    }
+
+    pub fn encode(&mut self, mut capture_frame: DesktopEncodeFrame) -> CoreResult<()> {
+        // the viewer doesn't show the video while paused
+        let Some(keyframe) = self.client.media_pause().admit_frame() else {
+            return Ok(());
+        };
+
+        if keyframe {
+            // a new encode context starts with a keyframe
+            self.encode_context = None;
+        }
+
+        if self
+            .display_config_watch
+            .observe(capture_frame.width, capture_frame.height)
+        {
+            tracing::info!(
+                width = ?capture_frame.width,
+                height = ?capture_frame.height,
+                "display config changed"
+            );
+
+            self.client
+                .blocking_send(&EndPointMessage::DisplayConfigChanged {
+                    width: capture_frame.width,
+                    height: capture_frame.height,
+                })?;
+
+            // a new encode context starts with a keyframe of the new size
+            self.encode_context = None;
+        }
+
+        // drop frames captured faster than the configured frame rate
+        let frame_interval = Duration::from_secs(1) / self.media_config.frame_rate as u32;
+        if let Some(last_encode_time) = self.last_encode_time {
+            if capture_frame.capture_time.saturating_sub(last_encode_time) < frame_interval {
+                return Ok(());
+            }
+        }
+        self.last_encode_time = Some(capture_frame.capture_time);
+        let prepare_started_at = Instant::now();
+
+        // a frame of another scale starts a new encode context
+        if let Some(report) = self.client.take_network_report() {
+            let target = self.adaptive_bitrate.on_report(&report);
+            self.media_config.scale_percent = target.scale_percent;
+
+            if let Some(bitrate_kbps) = self.bitrate_controller.set_ceiling(target.bitrate_kbps) {
+                tracing::info!(?bitrate_kbps, ?report, "follow network report");
+                self.switch_bitrate(bitrate_kbps);
+            }
+        }
+
+        if let Some(bitrate_kbps) = self.bitrate_controller.update(self.client.estimated_bps()) {
+            tracing::info!(?bitrate_kbps, "follow bandwidth estimate");
+            self.switch_bitrate(bitrate_kbps);
+        }
+
+        for preprocessor in self.preprocessors.iter() {
+            preprocessor.process(&mut capture_frame);
+        }
+
+        if self
+            .content_tuning
+            .update(self.client.content_hint(), &capture_frame)
+        {
+            let content_mode = self.content_tuning.mode();
+            tracing::info!(?content_mode, "content mode changed");
+
+            self.client
+                .blocking_send(&EndPointMessage::ContentModeChanged(content_mode))?;
+
+            // the new tuning starts with the keyframe of a new encode context
+            self.encode_context = None;
+        }
+
+        let (width, height) = self
+            .media_config
+            .scaled_size(capture_frame.width, capture_frame.height);
+        let capture_frame = scale_frame(capture_frame, width, height);
+
+        // a static screen costs a marker a second, a new encode context
+        // still starts with a whole frame
+        let change = match self.change_detector {
+            Some(ref mut change_detector) => {
+                let change = change_detector.observe(&capture_frame);
+
+                if change == FrameChange::Unchanged && self.encode_context.is_some() {
+                    if change_detector.marker_due(capture_frame.capture_time) {
+                        self.client
+                            .blocking_send(&EndPointMessage::VideoUnchanged)?;
+                    }
+                    return Ok(());
+                }
+
+                change_detector.frame_sent(capture_frame.capture_time);
+                change
+            }
+            None => FrameChange::Full,
+        };
+
         unsafe {
             let mut ret: i32;
 
@@ -54,6 +228,7 @@ where
                     capture_frame.width,
                     capture_frame.height,
                     &self.encoder_config,
+                    &self.content_tuning.tune(&self.media_config),
                 )?);
             }
 
@@ -77,6 +252,14 @@ where
                 * ((*(encode_context).codec_ctx).time_base.den as f64))
                 as i64;
 
+            let sent_at = Instant::now();
+            self.pending_frames.push(PendingFrame {
+                pts: (*(encode_context).frame).pts,
+                capture_time: capture_frame.capture_time,
+                prepare: sent_at.saturating_duration_since(prepare_started_at),
+                sent_at,
+            });
+
             ret = avcodec_send_frame((encode_context).codec_ctx, (encode_context).frame);
 
             if ret != 0 {
@@ -103,24 +286,150 @@ where
                     ));
                 }
 
-                let frame = EndPointVideoFrame {
-                    width: (*(encode_context).codec_ctx).width,
-                    height: (*(encode_context).codec_ctx).height,
-                    pts: (*(encode_context).packet).pts,
-                    buffer: std::slice::from_raw_parts(
+                let mut frame = self.video_frame(
+                    (*(encode_context).codec_ctx).width,
+                    (*(encode_context).codec_ctx).height,
+                    (*(encode_context).packet).pts,
+                    std::slice::from_raw_parts(
                         (*(encode_context).packet).data,
                         (*(encode_context).packet).size as usize,
                     )
                     .to_vec(),
-                };
+                );
 
-                self.client
-                    .blocking_send(&EndPointMessage::VideoFrame(frame))?;
+                if let Some(pending_frame) = self.pending_frames.take(frame.pts) {
+                    let (qp, frame_type) = packet_quality((encode_context).packet);
+                    let timing = EncodeTiming {
+                        seq: frame.seq,
+                        capture_time: pending_frame.capture_time,
+                        prepare: pending_frame.prepare,
+                        encode: pending_frame.sent_at.elapsed(),
+                        bytes: frame.buffer.len(),
+                        frame_type,
+                        qp,
+                    };
+
+                    self.client.encode_timings().record(&timing);
+                    if encode_trace_enabled() {
+                        timing.trace();
+                    }
+                }
+
+                // the viewer keeps them for when its decoder starts over
+                if self.encoder_config.av_codec_id() == AV_CODEC_ID_H264 {
+                    if let Some(parameter_sets) = self.client.parameter_sets().observe(&mut frame) {
+                        self.client
+                            .blocking_send(&EndPointMessage::VideoParameterSets(parameter_sets))?;
+                    }
+                }
+
+                // a change of a few rows only needs the slices covering them,
+                // the viewer keeps the rest of the previous frame
+                if let FrameChange::Rows(ref rows) = change {
+                    if self.media_config.slices_per_frame > 1
+                        && self.encoder_config.av_codec_id() == AV_CODEC_ID_H264
+                    {
+                        retain_h264_slices(&mut frame, rows.clone());
+                    }
+                }
+
+                let parity = self
+                    .fec_encoder
+                    .as_mut()
+                    .and_then(|fec_encoder| fec_encoder.push(&frame));
+
+                // parity covers the whole frame, the viewer puts the slices
+                // back together before it restores lost frames
+                if self.media_config.slices_per_frame > 1
+                    && self.encoder_config.av_codec_id() == AV_CODEC_ID_H264
+                {
+                    let slices = split_h264_slices(&frame);
+                    if slices.len() > 1 {
+                        for slice in slices {
+                            self.send_frame(&EndPointMessage::VideoFrameSlice(slice))?;
+                        }
+                    } else {
+                        self.send_frame(&EndPointMessage::VideoFrame(frame))?;
+                    }
+                } else {
+                    self.send_frame(&EndPointMessage::VideoFrame(frame))?;
+                }
+
+                // parity may restore a frame dropped above
+                if let Some(parity) = parity {
+                    self.send_frame(&EndPointMessage::VideoFrameParity(parity))?;
+                }
 
                 av_packet_unref((encode_context).packet);
             }
         }
     }
+
+    /// Numbers an encoded frame. The sequence is kept by the client, an
+    /// encoder recreated mid-stream carries on where the last one stopped.
+    pub(crate) fn video_frame(
+        &self,
+        width: i32,
+        height: i32,
+        pts: i64,
+        buffer: Vec<u8>,
+    ) -> EndPointVideoFrame {
+        EndPointVideoFrame {
+            seq: self.client.next_video_frame_seq(),
+            width,
+            height,
+            pts,
+            buffer,
+        }
+    }
+
+    /// A full send queue drops the frame, the next one is encoded as a
+    /// keyframe so the viewer can pick up again.
+    fn send_frame(&self, message: &EndPointMessage) -> CoreResult<()> {
+        match self.client.send_media_frame(message) {
+            Err(CoreError::OutgoingMessageChannelFull) => {
+                tracing::debug!("send queue full, drop video frame");
+                if !matches!(message, EndPointMessage::VideoFrameParity(_)) {
+                    self.client.media_pause().request_keyframe();
+                }
+                Ok(())
+            }
+            result => result,
+        }
+    }
+
+    fn switch_bitrate(&mut self, bitrate_kbps: u32) {
+        self.media_config.bitrate_kbps = bitrate_kbps;
+
+        // libx264 reconfigures itself on the next frame, other encoders
+        // take the bitrate when their context is created again
+        if let Some(ref encode_context) = self.encode_context {
+            unsafe { apply_bitrate(encode_context.codec_ctx, bitrate_kbps) };
+        }
+    }
+}
+
+/// The ffmpeg name of the encoder, like `libx264`.
+pub fn encoder_name(encoder_config: &dyn EncoderConfig) -> String {
+    unsafe { CStr::from_ptr(encoder_config.ffmpeg_encoder_name()) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Whether the ffmpeg of this build has the encoder.
+pub fn encoder_available(encoder_config: &dyn EncoderConfig) -> bool {
+    unsafe { !avcodec_find_encoder_by_name(encoder_config.ffmpeg_encoder_name()).is_null() }
+}
+
+/// Opens the encoder for frames of the size and closes it again, without
+/// encoding anything.
+pub fn probe_encoder(
+    encoder_config: &dyn EncoderConfig,
+    width: i32,
+    height: i32,
+    media_config: &MediaConfig,
+) -> CoreResult<()> {
+    EncodeContext::new(width, height, encoder_config, media_config).map(|_| ())
 }
 
 struct EncodeContext {
@@ -134,11 +443,14 @@ impl EncodeContext {
         width: i32,
         height: i32,
         encoder_config: &dyn EncoderConfig,
+        media_config: &MediaConfig,
     ) -> CoreResult<EncodeContext> {
         unsafe {
-            let codec = avcodec_find_encoder(encoder_config.av_codec_id());
+            let codec = avcodec_find_encoder_by_name(encoder_config.ffmpeg_encoder_name());
             if codec.is_null() {
-                return Err(core_error!("avcodec_find_encoder returns null pointer"));
+                return Err(core_error!(
+                    "avcodec_find_encoder_by_name returns null pointer"
+                ));
             }
 
             let encoder_context = EncodeContext {
@@ -156,13 +468,17 @@ impl EncodeContext {
 
             (*encoder_context.codec_ctx).width = width;
             (*encoder_context.codec_ctx).height = height;
-            (*encoder_context.codec_ctx).framerate = AVRational { num: 60, den: 1 };
-            (*encoder_context.codec_ctx).time_base = AVRational { num: 1, den: 60 };
-            (*encoder_context.codec_ctx).gop_size = 4000;
-            (*encoder_context.codec_ctx).bit_rate = 4000 * 1000;
-            (*encoder_context.codec_ctx).rc_max_rate = 4000 * 1000;
-            (*encoder_context.codec_ctx).rc_min_rate = 4000 * 1000;
-            (*encoder_context.codec_ctx).rc_buffer_size = 4000 * 1000 * 2;
+            (*encoder_context.codec_ctx).framerate = AVRational {
+                num: media_config.frame_rate as i32,
+                den: 1,
+            };
+            (*encoder_context.codec_ctx).time_base = AVRational {
+                num: 1,
+                den: media_config.frame_rate as i32,
+            };
+            (*encoder_context.codec_ctx).gop_size = media_config.keyframe_interval;
+            (*encoder_context.codec_ctx).slices = media_config.slices_per_frame as i32;
+            apply_bitrate(encoder_context.codec_ctx, media_config.bitrate_kbps);
             (*encoder_context.codec_ctx).has_b_frames = 0;
             (*encoder_context.codec_ctx).max_b_frames = 0;
             (*encoder_context.codec_ctx).pix_fmt = AV_PIX_FMT_NV12;
@@ -176,7 +492,7 @@ impl EncodeContext {
             (*encoder_context.frame).width = width;
             (*encoder_context.frame).height = height;
 
-            encoder_config.apply_option(encoder_context.codec_ctx)?;
+            encoder_config.apply_option(encoder_context.codec_ctx, media_config)?;
 
             let mut ret = av_frame_get_buffer(encoder_context.frame, 0);
             if ret < 0 {
@@ -204,6 +520,33 @@ impl EncodeContext {
     }
 }
 
+/// The quantizer and picture type the encoder attached to a packet, a
+/// keyframe tells its type by the flags of the packet otherwise.
+unsafe fn packet_quality(packet: *const AVPacket) -> (Option<u8>, FrameType) {
+    let mut size = 0;
+    let data = av_packet_get_side_data(packet, AV_PKT_DATA_QUALITY_STATS, &mut size);
+
+    if !data.is_null() {
+        if let Some((qp, frame_type)) = parse_quality_stats(std::slice::from_raw_parts(data, size))
+        {
+            return (Some(qp), frame_type);
+        }
+    }
+
+    if (*packet).flags & AV_PKT_FLAG_KEY != 0 {
+        (None, FrameType::I)
+    } else {
+        (None, FrameType::Unknown)
+    }
+}
+
+unsafe fn apply_bitrate(codec_ctx: *mut AVCodecContext, bitrate_kbps: u32) {
+    (*codec_ctx).bit_rate = bitrate_kbps as i64 * 1000;
+    (*codec_ctx).rc_max_rate = bitrate_kbps as i64 * 1000;
+    (*codec_ctx).rc_min_rate = bitrate_kbps as i64 * 1000;
+    (*codec_ctx).rc_buffer_size = bitrate_kbps as i32 * 1000 * 2;
+}
+
 impl Drop for EncodeContext {
     fn drop(&mut self) {
         unsafe {
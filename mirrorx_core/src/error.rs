@@ -1,5 +1,6 @@
 use std::{
     io,
+    path::PathBuf,
     string::{FromUtf16Error, FromUtf8Error},
 };
 use thiserror::Error;
@@ -36,6 +37,128 @@ pub enum CoreError {
     #[error("operation timeout")]
     Timeout,
 
+    /// The remote side didn't answer `operation` within its timeout, see
+    /// [`CallTimeouts`](crate::api::endpoint::call_timeout::CallTimeouts).
+    #[error("remote device didn't answer the {operation} in time")]
+    CallTimeout {
+        operation: crate::api::endpoint::call_timeout::CallOperation,
+    },
+
+    #[error("operation cancelled")]
+    Cancelled,
+
+    #[error("unsafe path ({0:?})")]
+    UnsafePath(PathBuf),
+
+    #[error("received file couldn't be written to disk durably ({0:?})")]
+    FileFlushFailed(io::Error),
+
+    #[error("remote device denied the permission to capture screen")]
+    MediaPermissionDenied,
+
+    #[error("remote device has no display to capture, it needs a monitor, a dummy display plug or a virtual display driver")]
+    MediaNoDisplay,
+
+    #[error("remote device doesn't support the requested codec")]
+    MediaCodecUnsupported,
+
+    #[error("remote device stopped sharing, the shared window was closed")]
+    MediaWindowClosed,
+
+    #[error("remote device failed to capture its screen")]
+    MediaCaptureFailed,
+
+    #[error("remote device failed to provide media for an unknown reason")]
+    MediaOtherError,
+
+    #[error("screen recording permission is not granted to this app")]
+    ScreenCapturePermissionDenied,
+
+    #[error("accessibility permission is not granted to this app")]
+    AccessibilityPermissionDenied,
+
+    #[error("no display is attached, attach a monitor or a dummy display plug, or configure a virtual display driver")]
+    NoDisplayAvailable,
+
+    #[error("the shared window was closed")]
+    WindowClosed,
+
+    #[error("signaling server failed repeatedly, calls are paused for a while")]
+    SignalingUnavailable,
+
+    #[error("remote device is offline")]
+    VisitRemoteOffline,
+
+    #[error("remote device rejected the visit")]
+    VisitRemoteRejected,
+
+    #[error("visit password is incorrect")]
+    VisitInvalidPassword,
+
+    #[error("visit request timeout")]
+    VisitTimeout,
+
+    #[error("signaling server is busy")]
+    VisitServerBusy,
+
+    #[error("visit request arguments are invalid")]
+    VisitInvalidArgs,
+
+    #[error("signaling server or remote device failed to handle the visit")]
+    VisitInternalError,
+
+    #[error("generate visit key failed")]
+    VisitKeyGenerationFailed,
+
+    #[error("visit key exchange failed")]
+    VisitKeyExchangeFailed,
+
+    #[error("device identity signature of the visit key exchange is invalid")]
+    VisitSignatureInvalid,
+
+    #[error("remote device isn't a trusted device")]
+    VisitDeviceUntrusted,
+
+    #[error("remote device only accepts visits of devices on its allow list")]
+    VisitNotAllowed,
+
+    #[error("remote device refused the visit as a replay of an earlier one")]
+    VisitReplayed,
+
+    #[error("handshake with the endpoints server failed ({0})")]
+    HandshakeFailed(crate::api::endpoint::handshake::HandshakeFailure),
+
+    #[error("visit credentials were already used by another connection")]
+    HandshakeTokenRepeated,
+
+    #[error("visit credentials expired before the connection was made")]
+    HandshakeTokenExpired,
+
+    #[error("visit credentials were not issued to this device")]
+    HandshakeTokenUnknown,
+
+    #[error("connect to the remote device failed {attempts} times ({reason})")]
+    ConnectAttemptsExhausted { attempts: u32, reason: String },
+
+    /// The password of a visit isn't kept, the user has to enter it again.
+    #[error("reconnecting to the remote device needs its password")]
+    ReconnectNeedsPassword,
+
+    /// `codec` is the framing and encoding fingerprint of the remote device,
+    /// known when the format version matched.
+    #[error("remote device speaks unsupported packet format {version} (codec {codec:?})")]
+    ProtocolMismatch { version: u8, codec: Option<u8> },
+
+    /// `max` is the longest frame the remote side accepts.
+    #[error("packet of {len} bytes is longer than the frame length limit of {max} bytes")]
+    FrameTooLarge { len: usize, max: usize },
+
+    #[error("settings bundle passphrase is incorrect or the bundle is damaged")]
+    SettingsBundleInvalidPassphrase,
+
+    #[error("settings bundle format {0} is newer than this app supports")]
+    SettingsBundleUnsupportedVersion(u32),
+
     #[error("tokio oneshot channel receive error ({0:?})")]
     OneshotReceiveError(#[from] tokio::sync::oneshot::error::RecvError),
 
@@ -51,6 +174,9 @@ pub enum CoreError {
     #[error("reqwest error ({0:?})")]
     ReqwestError(#[from] reqwest::Error),
 
+    #[error("websocket error ({0:?})")]
+    WebSocketError(Box<tokio_tungstenite::tungstenite::Error>),
+
     #[error("url parse error ({0:?})")]
     UrlError(#[from] url::ParseError),
 
@@ -96,6 +222,31 @@ pub enum CoreError {
     ImageError(#[from] image::ImageError),
 }
 
+impl CoreError {
+    /// Whether trying the same operation again later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            CoreError::Timeout
+                | CoreError::CallTimeout { .. }
+                | CoreError::VisitRemoteOffline
+                | CoreError::VisitTimeout
+                | CoreError::VisitServerBusy
+                | CoreError::VisitInternalError
+                | CoreError::ConnectAttemptsExhausted { .. }
+                | CoreError::ReqwestError(_)
+        )
+    }
+}
+
+// boxed, the tungstenite error alone would more than double the size of
+// every result
+impl From<tokio_tungstenite::tungstenite::Error> for CoreError {
+    fn from(err: tokio_tungstenite::tungstenite::Error) -> Self {
+        CoreError::WebSocketError(Box::new(err))
+    }
+}
+
 impl serde::Serialize for CoreError {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -0,0 +1,186 @@
+use crate::api::signaling::{
+    access_code::{AccessMode, OneTimeCodes, ONE_TIME_CODE_LEN},
+    key_exchange::{key_agreement, ActiveKeyExchange},
+    subscribe_message::VisitFailureReason,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::time::{Duration, Instant};
+
+const DEVICE_PASSWORD: &str = "device-password";
+
+/// Stands in for the key agreement, opens only what was sealed with
+/// `sealed_with`.
+fn agree_with(sealed_with: &str) -> impl FnMut(&str) -> Result<String, VisitFailureReason> + '_ {
+    move |password| {
+        if password == sealed_with {
+            Ok(password.to_string())
+        } else {
+            Err(VisitFailureReason::InvalidPassword)
+        }
+    }
+}
+
+#[test]
+fn test_one_time_code_consumed_on_success() {
+    let now = Instant::now();
+    let one_time_codes = OneTimeCodes::new(Duration::from_secs(600));
+
+    let one_time_code = one_time_codes.rotate(now);
+    assert_eq!(one_time_code.code.len(), ONE_TIME_CODE_LEN);
+    assert_eq!(one_time_codes.current(now), Some(one_time_code.clone()));
+
+    // a wrong guess leaves the code in place
+    assert!(matches!(
+        one_time_codes.verify(AccessMode::Both, DEVICE_PASSWORD, now, agree_with("guess")),
+        Err(VisitFailureReason::InvalidPassword)
+    ));
+
+    let opened = one_time_codes
+        .verify(
+            AccessMode::Both,
+            DEVICE_PASSWORD,
+            now,
+            agree_with(&one_time_code.code),
+        )
+        .unwrap();
+    assert_eq!(opened, one_time_code.code);
+
+    assert_eq!(one_time_codes.current(now), None);
+    assert!(one_time_codes
+        .verify(
+            AccessMode::Both,
+            DEVICE_PASSWORD,
+            now,
+            agree_with(&one_time_code.code),
+        )
+        .is_err());
+
+    // the device password isn't used up
+    for _ in 0..2 {
+        assert!(one_time_codes
+            .verify(
+                AccessMode::Both,
+                DEVICE_PASSWORD,
+                now,
+                agree_with(DEVICE_PASSWORD),
+            )
+            .is_ok());
+    }
+}
+
+#[test]
+fn test_one_time_code_rotated_during_agreement() {
+    let now = Instant::now();
+    let one_time_codes = OneTimeCodes::new(Duration::from_secs(600));
+    one_time_codes.rotate(now);
+
+    // the user generated a new code while the visit was being opened
+    let rotated = std::cell::RefCell::new(None);
+    let result = one_time_codes.verify(AccessMode::OneTimeCode, DEVICE_PASSWORD, now, |code| {
+        *rotated.borrow_mut() = Some(one_time_codes.rotate(now));
+        Ok(code.to_string())
+    });
+    assert!(matches!(result, Err(VisitFailureReason::InvalidPassword)));
+    assert_eq!(one_time_codes.current(now), rotated.into_inner());
+}
+
+#[test]
+fn test_one_time_code_expires_and_rotates() {
+    let now = Instant::now();
+    let one_time_codes = OneTimeCodes::new(Duration::from_secs(600));
+
+    let first = one_time_codes.rotate(now);
+    let second = one_time_codes.rotate(now);
+    assert_ne!(first.code, second.code);
+    assert!(one_time_codes
+        .verify(
+            AccessMode::OneTimeCode,
+            DEVICE_PASSWORD,
+            now,
+            agree_with(&first.code),
+        )
+        .is_err());
+
+    let later = now + Duration::from_secs(300);
+    assert_eq!(
+        one_time_codes
+            .current(later)
+            .map(|code| code.expires_in_secs),
+        Some(300)
+    );
+
+    let expired = now + Duration::from_secs(601);
+    assert_eq!(one_time_codes.current(expired), None);
+    assert!(one_time_codes
+        .verify(
+            AccessMode::OneTimeCode,
+            DEVICE_PASSWORD,
+            expired,
+            agree_with(&second.code),
+        )
+        .is_err());
+}
+
+#[test]
+fn test_access_mode_limits_credentials() {
+    let now = Instant::now();
+    let one_time_codes = OneTimeCodes::new(Duration::from_secs(600));
+    let one_time_code = one_time_codes.rotate(now);
+
+    assert!(one_time_codes
+        .verify(
+            AccessMode::OneTimeCode,
+            DEVICE_PASSWORD,
+            now,
+            agree_with(DEVICE_PASSWORD),
+        )
+        .is_err());
+
+    assert!(one_time_codes
+        .verify(
+            AccessMode::DevicePassword,
+            DEVICE_PASSWORD,
+            now,
+            agree_with(&one_time_code.code),
+        )
+        .is_err());
+    assert!(one_time_codes.current(now).is_some());
+
+    // failures other than a wrong password end the visit as they are
+    assert!(matches!(
+        one_time_codes.verify(AccessMode::Both, DEVICE_PASSWORD, now, |_| {
+            Err::<(), _>(VisitFailureReason::InvalidArgs)
+        }),
+        Err(VisitFailureReason::InvalidArgs)
+    ));
+    assert!(one_time_codes.current(now).is_some());
+}
+
+#[test]
+fn test_one_time_code_opens_key_agreement() {
+    let now = Instant::now();
+    let mut rng = StdRng::seed_from_u64(155);
+    let reply_private_key = rsa::RsaPrivateKey::new(&mut rng, 1024).unwrap();
+
+    let one_time_codes = OneTimeCodes::new(Duration::from_secs(600));
+    let one_time_code = one_time_codes.rotate(now);
+
+    // the visiting side enters the code where it would enter the password
+    let active =
+        ActiveKeyExchange::new(&mut rng, reply_private_key, 1, &one_time_code.code).unwrap();
+
+    let result = one_time_codes.verify(AccessMode::Both, DEVICE_PASSWORD, now, |password| {
+        key_agreement(
+            &mut rng,
+            password,
+            1,
+            &active.password_salt,
+            active.secret.clone(),
+            &active.secret_nonce,
+        )
+    });
+
+    let (reply, _, _) = result.unwrap();
+    assert!(active.open_reply(&reply).is_ok());
+    assert_eq!(one_time_codes.current(now), None);
+}
@@ -0,0 +1,109 @@
+use crate::{
+    api::endpoint::message::EndPointNetworkReport,
+    component::video_encoder::{
+        adaptive::{
+            AdaptiveBitrate, EncoderTarget, HIGH_LOSS_PERMILLE, SUSTAINED_CLEAN_REPORTS,
+            SUSTAINED_LOSS_REPORTS,
+        },
+        bitrate::MIN_BITRATE_KBPS,
+    },
+};
+
+fn report(received_kbps: u64, loss_permille: u16) -> EndPointNetworkReport {
+    EndPointNetworkReport {
+        received_bps: received_kbps * 1000,
+        loss_permille,
+    }
+}
+
+#[test]
+fn test_adaptive_bitrate_ramps_down_on_loss() {
+    let mut controller = AdaptiveBitrate::new(4000, 100);
+
+    // backs off below what arrived
+    let target = controller.on_report(&report(2000, 100));
+    assert_eq!(
+        target,
+        EncoderTarget {
+            bitrate_kbps: 1600,
+            scale_percent: 100
+        }
+    );
+
+    // sustained loss lowers the resolution a tier
+    for _ in 1..SUSTAINED_LOSS_REPORTS {
+        controller.on_report(&report(2000, HIGH_LOSS_PERMILLE));
+    }
+    assert_eq!(
+        controller.target(),
+        EncoderTarget {
+            bitrate_kbps: 1600,
+            scale_percent: 75
+        }
+    );
+
+    // never below the floor nor the lowest tier
+    for _ in 0..SUSTAINED_LOSS_REPORTS * 4 {
+        controller.on_report(&report(0, 1000));
+    }
+    assert_eq!(
+        controller.target(),
+        EncoderTarget {
+            bitrate_kbps: MIN_BITRATE_KBPS,
+            scale_percent: 50
+        }
+    );
+}
+
+#[test]
+fn test_adaptive_bitrate_ramps_up_on_clean_link() {
+    let mut controller = AdaptiveBitrate::new(4000, 80);
+    for _ in 0..SUSTAINED_LOSS_REPORTS {
+        controller.on_report(&report(1000, 200));
+    }
+    assert_eq!(
+        controller.target(),
+        EncoderTarget {
+            bitrate_kbps: 800,
+            scale_percent: 60
+        }
+    );
+
+    // grows a few percent a report while the viewer receives it all
+    let target = controller.on_report(&report(800, 0));
+    assert_eq!(target.bitrate_kbps, 864);
+
+    for _ in 1..SUSTAINED_CLEAN_REPORTS {
+        let bitrate_kbps = controller.target().bitrate_kbps;
+        controller.on_report(&report(bitrate_kbps as u64, 0));
+    }
+    assert_eq!(controller.target().scale_percent, 80);
+
+    // never above the media config
+    for _ in 0..50 {
+        let bitrate_kbps = controller.target().bitrate_kbps;
+        controller.on_report(&report(bitrate_kbps as u64, 0));
+    }
+    assert_eq!(
+        controller.target(),
+        EncoderTarget {
+            bitrate_kbps: 4000,
+            scale_percent: 80
+        }
+    );
+}
+
+#[test]
+fn test_adaptive_bitrate_holds_without_proof() {
+    let mut controller = AdaptiveBitrate::new(4000, 100);
+    controller.on_report(&report(1000, 100));
+    assert_eq!(controller.target().bitrate_kbps, 800);
+
+    // an idle sender proves nothing about the link
+    controller.on_report(&report(10, 0));
+    assert_eq!(controller.target().bitrate_kbps, 800);
+
+    // some loss neither ramps nor backs off
+    controller.on_report(&report(800, 20));
+    assert_eq!(controller.target().bitrate_kbps, 800);
+}
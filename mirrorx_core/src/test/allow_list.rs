@@ -0,0 +1,49 @@
+use crate::api::{
+    config::{LocalStorage, Storage},
+    signaling::{check_allow_list, subscribe_message::VisitFailureReason},
+};
+
+#[test]
+fn test_allow_list_repository() -> anyhow::Result<()> {
+    let storage = LocalStorage::memory()?;
+
+    storage.allow_list().add(1234567890)?;
+    storage.allow_list().add(1234567890)?;
+    storage.allow_list().add(2345678901)?;
+
+    assert!(storage.allow_list().contains(1234567890)?);
+    assert!(!storage.allow_list().contains(3456789012)?);
+    assert_eq!(storage.allow_list().query()?.len(), 2);
+
+    storage.allow_list().remove(1234567890)?;
+    assert!(!storage.allow_list().contains(1234567890)?);
+    assert_eq!(storage.allow_list().query()?.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_check_allow_list() -> anyhow::Result<()> {
+    let storage = LocalStorage::memory()?;
+
+    // without allow-list mode every device may visit
+    assert!(check_allow_list(&storage, 1234567890).is_ok());
+
+    storage.kv().set_allow_list_enabled(true)?;
+    assert!(matches!(
+        check_allow_list(&storage, 1234567890),
+        Err(VisitFailureReason::NotAllowed)
+    ));
+
+    storage.allow_list().add(1234567890)?;
+    assert!(check_allow_list(&storage, 1234567890).is_ok());
+    assert!(matches!(
+        check_allow_list(&storage, 2345678901),
+        Err(VisitFailureReason::NotAllowed)
+    ));
+
+    storage.kv().set_allow_list_enabled(false)?;
+    assert!(check_allow_list(&storage, 2345678901).is_ok());
+
+    Ok(())
+}
@@ -0,0 +1,138 @@
+use crate::{
+    api::endpoint::{
+        capabilities::agree_audio_params,
+        message::{EndPointAudioFrame, EndPointAudioParams},
+    },
+    component::{
+        audio::{
+            decoder::{AudioDecoder, AudioSequence, MAX_CONCEALED_FRAMES},
+            encoder::AudioEncoder,
+        },
+        frame::AudioEncodeFrame,
+    },
+};
+use cpal::{SampleFormat, SampleRate};
+
+const STEREO_48K: EndPointAudioParams = EndPointAudioParams {
+    sample_rate: 48000,
+    channels: 2,
+};
+
+/// `ms` of a stereo 48 kHz tone.
+fn capture_frame(ms: usize) -> AudioEncodeFrame {
+    let buffer = (0..48 * ms)
+        .flat_map(|i| {
+            let v = (i as f32 * 440.0 * std::f32::consts::TAU / 48000.0).sin() * 0.5;
+            [v, v]
+        })
+        .flat_map(|v| v.to_ne_bytes())
+        .collect();
+
+    AudioEncodeFrame {
+        channels: 2,
+        sample_format: SampleFormat::F32,
+        sample_rate: 48000,
+        buffer,
+    }
+}
+
+#[test]
+fn test_agree_audio_params() {
+    let params = agree_audio_params(&EndPointAudioParams {
+        sample_rate: 44100,
+        channels: 6,
+    });
+    assert_eq!(params, STEREO_48K);
+
+    let params = agree_audio_params(&EndPointAudioParams {
+        sample_rate: 16000,
+        channels: 1,
+    });
+    assert_eq!(params.sample_rate, 16000);
+    assert_eq!(params.channels, 1);
+
+    let params = agree_audio_params(&EndPointAudioParams {
+        sample_rate: 96000,
+        channels: 0,
+    });
+    assert_eq!(params.sample_rate, 48000);
+    assert_eq!(params.channels, 1);
+}
+
+#[test]
+fn test_audio_sequence() {
+    let mut sequence = AudioSequence::default();
+
+    assert_eq!(sequence.receive(5), Some(0));
+    assert_eq!(sequence.receive(6), Some(0));
+    assert_eq!(sequence.receive(9), Some(2));
+    // came after a later one
+    assert_eq!(sequence.receive(8), None);
+    assert_eq!(sequence.receive(9), None);
+    assert_eq!(sequence.receive(10), Some(0));
+
+    // a restarted stream
+    assert_eq!(sequence.receive(0), Some(0));
+    assert_eq!(sequence.receive(2), Some(1));
+}
+
+#[test]
+fn test_audio_encoder_frames() -> anyhow::Result<()> {
+    let mut encoder = AudioEncoder::new(STEREO_48K)?;
+
+    let frames = encoder.encode(capture_frame(25))?;
+    assert_eq!(frames.len(), 2);
+    assert_eq!((frames[0].seq, frames[0].pts), (0, 0));
+    assert_eq!((frames[1].seq, frames[1].pts), (1, 10_000));
+
+    // the 5 ms left over fill the next frame
+    let frames = encoder.encode(capture_frame(5))?;
+    assert_eq!(frames.len(), 1);
+    assert_eq!((frames[0].seq, frames[0].pts), (2, 20_000));
+
+    assert!(encoder.encode(capture_frame(5))?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_audio_decoder_plays_silence_for_lost_frames() -> anyhow::Result<()> {
+    let mut encoder = AudioEncoder::new(STEREO_48K)?;
+    let mut frames: Vec<EndPointAudioFrame> = encoder.encode(capture_frame(40))?;
+    assert_eq!(frames.len(), 4);
+
+    let mut decoder = AudioDecoder::new(2, SampleFormat::F32, SampleRate(48000));
+    let frame_bytes = 480 * 2 * std::mem::size_of::<f32>();
+
+    let late = frames.remove(2);
+    let lost = frames.remove(1);
+
+    assert_eq!(decoder.decode(frames.remove(0))?.len(), frame_bytes);
+
+    let buffer = decoder.decode(frames.remove(0))?;
+    assert_eq!(buffer.len(), 3 * frame_bytes);
+    assert!(buffer[..2 * frame_bytes].iter().all(|v| *v == 0));
+
+    assert!(decoder.decode(late)?.is_empty());
+    assert!(decoder.decode(lost)?.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn test_audio_decoder_caps_concealment() -> anyhow::Result<()> {
+    let mut encoder = AudioEncoder::new(STEREO_48K)?;
+    let mut frame = encoder.encode(capture_frame(10))?.remove(0);
+
+    let mut decoder = AudioDecoder::new(2, SampleFormat::F32, SampleRate(48000));
+    let frame_bytes = 480 * 2 * std::mem::size_of::<f32>();
+    assert_eq!(decoder.decode(frame.clone())?.len(), frame_bytes);
+
+    frame.seq = 1000;
+    assert_eq!(
+        decoder.decode(frame)?.len(),
+        (MAX_CONCEALED_FRAMES as usize + 1) * frame_bytes
+    );
+
+    Ok(())
+}
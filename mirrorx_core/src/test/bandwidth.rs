@@ -0,0 +1,160 @@
+use crate::{
+    api::endpoint::bandwidth::{
+        BandwidthAggressiveness, BandwidthEstimator, NetworkReporter, BANDWIDTH_SAMPLE_INTERVAL,
+    },
+    component::video_encoder::bitrate::{BitrateController, MIN_BITRATE_KBPS},
+};
+use std::time::{Duration, Instant};
+
+const BASE_RTT: Duration = Duration::from_millis(40);
+
+/// A link of fixed capacity behind a send queue. The sender writes at the
+/// estimate, the link carries what its capacity allows per sample and the
+/// rest waits, delaying the round trip probes.
+struct VirtualLink {
+    estimator: BandwidthEstimator,
+    queued_bytes: f64,
+    now: Instant,
+    samples: u32,
+}
+
+impl VirtualLink {
+    fn new(aggressiveness: BandwidthAggressiveness, initial_bps: u64) -> Self {
+        let now = Instant::now();
+        let mut estimator = BandwidthEstimator::new(aggressiveness, initial_bps);
+        estimator.on_sample(0, 0, now);
+
+        Self {
+            estimator,
+            queued_bytes: 0.0,
+            now,
+            samples: 0,
+        }
+    }
+
+    /// Runs the link for `duration` and returns the estimate after every
+    /// sample.
+    fn run(&mut self, capacity_bps: f64, duration: Duration) -> Vec<f64> {
+        let interval = BANDWIDTH_SAMPLE_INTERVAL.as_secs_f64();
+        let mut estimates = Vec::new();
+
+        for _ in 0..(duration.as_secs_f64() / interval) as usize {
+            self.now += BANDWIDTH_SAMPLE_INTERVAL;
+            self.samples += 1;
+
+            self.queued_bytes += self.estimator.estimated_bps() as f64 / 8.0 * interval;
+            let drained_bytes = self.queued_bytes.min(capacity_bps / 8.0 * interval);
+            self.queued_bytes -= drained_bytes;
+
+            if self.samples.is_multiple_of(4) {
+                let queue_delay = self.queued_bytes * 8.0 / capacity_bps;
+                self.estimator
+                    .on_rtt(BASE_RTT + Duration::from_secs_f64(queue_delay));
+            }
+
+            let estimate =
+                self.estimator
+                    .on_sample(drained_bytes as u64, self.queued_bytes as u64, self.now);
+            estimates.push(estimate as f64);
+        }
+
+        estimates
+    }
+}
+
+#[test]
+fn test_bandwidth_estimate_converges_near_capacity() {
+    let capacity_bps = 8_000_000.0;
+    let mut link = VirtualLink::new(BandwidthAggressiveness::Balanced, 1_000_000);
+
+    let estimates = link.run(capacity_bps, Duration::from_secs(120));
+
+    // the last 20 seconds hover around the capacity
+    for estimate in &estimates[estimates.len() - 80..] {
+        let ratio = estimate / capacity_bps;
+        assert!((0.75..1.15).contains(&ratio), "estimate ratio {}", ratio);
+    }
+}
+
+#[test]
+fn test_bandwidth_estimate_backs_off_quickly() {
+    let mut link = VirtualLink::new(BandwidthAggressiveness::Balanced, 1_000_000);
+    link.run(8_000_000.0, Duration::from_secs(120));
+
+    let capacity_bps = 2_000_000.0;
+    let estimates = link.run(capacity_bps, Duration::from_secs(10));
+
+    // within 4 seconds of the drop the estimate is near the new capacity
+    assert!(estimates[15] < capacity_bps * 1.25);
+
+    // and it doesn't collapse far below it
+    assert!(*estimates.last().unwrap() > capacity_bps * 0.6);
+}
+
+#[test]
+fn test_bandwidth_aggressiveness() {
+    let capacity_bps = 50_000_000.0;
+
+    let mut conservative = VirtualLink::new(BandwidthAggressiveness::Conservative, 1_000_000);
+    let mut aggressive = VirtualLink::new(BandwidthAggressiveness::Aggressive, 1_000_000);
+
+    let conservative = conservative.run(capacity_bps, Duration::from_secs(10));
+    let aggressive = aggressive.run(capacity_bps, Duration::from_secs(10));
+
+    let conservative = *conservative.last().unwrap();
+    let aggressive = *aggressive.last().unwrap();
+
+    // both ramp up cautiously, a few percent a second
+    assert!(conservative < 2_000_000.0);
+    assert!(aggressive < 5_000_000.0);
+    assert!(aggressive > conservative);
+}
+
+#[test]
+fn test_bitrate_controller_follows_estimate() {
+    let mut controller = BitrateController::new(4000);
+
+    // no estimate yet
+    assert_eq!(controller.update(0), None);
+
+    assert_eq!(controller.update(2_000_000), Some(1700));
+    assert_eq!(controller.update(2_050_000), None);
+    assert_eq!(controller.current_kbps(), 1700);
+
+    // never above the configured bitrate, nor below the floor
+    assert_eq!(controller.update(100_000_000), Some(4000));
+    assert_eq!(controller.update(10_000), Some(MIN_BITRATE_KBPS));
+}
+
+#[test]
+fn test_bitrate_controller_ceiling() {
+    let mut controller = BitrateController::new(4000);
+
+    assert_eq!(controller.set_ceiling(2000), Some(2000));
+    assert_eq!(controller.update(100_000_000), None);
+
+    // lifting the cap lets the estimate through again
+    assert_eq!(controller.set_ceiling(10_000), None);
+    assert_eq!(controller.update(100_000_000), Some(4000));
+}
+
+#[test]
+fn test_network_reporter_covers_interval() {
+    let mut reporter = NetworkReporter::default();
+    let now = Instant::now();
+
+    assert_eq!(reporter.sample(1000, 10, 0, now), None);
+
+    let report = reporter
+        .sample(251_000, 105, 5, now + Duration::from_secs(2))
+        .expect("second sample reports");
+    assert_eq!(report.received_bps, 1_000_000);
+    assert_eq!(report.loss_permille, 50);
+
+    // nothing sent, nothing lost
+    let report = reporter
+        .sample(251_000, 105, 5, now + Duration::from_secs(3))
+        .expect("third sample reports");
+    assert_eq!(report.received_bps, 0);
+    assert_eq!(report.loss_permille, 0);
+}
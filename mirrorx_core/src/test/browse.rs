@@ -0,0 +1,62 @@
+use crate::component::fs::browse::list_directory;
+use std::path::Path;
+
+fn new_browse_root(name: &str) -> anyhow::Result<std::path::PathBuf> {
+    let root = std::env::temp_dir().join(format!("mirrorx_browse_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&root);
+    std::fs::create_dir_all(root.join("docs"))?;
+    for i in 0..5 {
+        std::fs::write(root.join(format!("file_{i}.txt")), vec![0u8; i])?;
+    }
+    std::fs::write(root.join("docs").join("readme.md"), b"readme")?;
+    Ok(root)
+}
+
+#[test]
+fn test_list_directory_paginates() -> anyhow::Result<()> {
+    let root = new_browse_root("paginate")?;
+
+    let first = list_directory(&root, Path::new(""), 0, 3)?;
+    assert_eq!(first.total, 6);
+    assert_eq!(first.entries.len(), 3);
+    assert_eq!(first.entries[0].name, "docs");
+    assert!(first.entries[0].is_dir);
+
+    let second = list_directory(&root, Path::new(""), 3, 3)?;
+    assert_eq!(second.entries.len(), 3);
+    assert_eq!(second.entries[2].name, "file_4.txt");
+    assert_eq!(second.entries[2].size, 4);
+
+    let docs = list_directory(&root, Path::new("docs"), 0, 10)?;
+    assert_eq!(docs.entries.len(), 1);
+    assert_eq!(docs.entries[0].name, "readme.md");
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn test_list_directory_stays_in_root() -> anyhow::Result<()> {
+    let root = new_browse_root("confine")?;
+
+    assert!(list_directory(&root, Path::new(".."), 0, 10).is_err());
+    assert!(list_directory(&root, Path::new("docs/../.."), 0, 10).is_err());
+    assert!(list_directory(&root, &std::env::temp_dir(), 0, 10).is_err());
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(std::env::temp_dir(), root.join("escape"))?;
+        assert!(list_directory(&root, Path::new("escape"), 0, 10).is_err());
+
+        let listing = list_directory(&root, Path::new(""), 0, 10)?;
+        let escape = listing
+            .entries
+            .iter()
+            .find(|entry| entry.name == "escape")
+            .unwrap();
+        assert!(escape.is_symlink);
+    }
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
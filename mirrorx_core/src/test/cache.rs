@@ -0,0 +1,103 @@
+use crate::component::fs::cache::{
+    cache_usage_of, evict_cache_of, is_leased, CacheConfig, CacheEviction, CacheLease,
+    MIN_CACHE_MAX_BYTES,
+};
+use std::{path::PathBuf, time::Duration};
+
+fn cache_root(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("mirrorx_cache_{}_{}", name, std::process::id()))
+}
+
+fn write_file(path: &PathBuf, len: usize) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, vec![0xAB; len]).unwrap();
+    // apart far enough for the file times to order them
+    std::thread::sleep(Duration::from_millis(20));
+}
+
+#[test]
+fn test_evict_least_recently_used() -> anyhow::Result<()> {
+    let root = cache_root("evict");
+    let _ = std::fs::remove_dir_all(&root);
+
+    let a = root.join("frame_dumps").join("a.h264");
+    let b = root.join("frame_dumps").join("b.h264");
+    let c = root.join("thumbnails").join("c.jpg");
+    write_file(&a, 100);
+    write_file(&b, 100);
+    write_file(&c, 100);
+    // written again, used most recently now
+    write_file(&a, 100);
+
+    let usage = cache_usage_of(&root, 200)?;
+    assert_eq!((usage.bytes, usage.files), (300, 3));
+
+    // the oldest file is in use, the next oldest goes
+    let lease = CacheLease::new(b.clone());
+    let eviction = evict_cache_of(&root, 200)?;
+    assert_eq!(
+        eviction,
+        CacheEviction {
+            removed_files: 1,
+            freed_bytes: 100,
+            leased_bytes: 100,
+        }
+    );
+    assert!(a.exists());
+    assert!(b.exists());
+    assert!(!c.exists());
+
+    // within the budget nothing goes
+    assert_eq!(evict_cache_of(&root, 200)?, CacheEviction::default());
+
+    // clearing keeps the file in use
+    evict_cache_of(&root, 0)?;
+    assert!(!a.exists());
+    assert!(b.exists());
+
+    drop(lease);
+    evict_cache_of(&root, 0)?;
+    assert!(!b.exists());
+    assert_eq!(cache_usage_of(&root, 200)?.bytes, 0);
+
+    std::fs::remove_dir_all(&root)?;
+
+    // a cache never written to is empty
+    assert_eq!(cache_usage_of(&root, 200)?.files, 0);
+    assert_eq!(evict_cache_of(&root, 0)?, CacheEviction::default());
+
+    Ok(())
+}
+
+#[test]
+fn test_cache_lease_counts() {
+    let path = cache_root("lease").join("stream.h264");
+
+    let first = CacheLease::new(path.clone());
+    let second = CacheLease::new(path.clone());
+    assert_eq!(first.path(), path.as_path());
+
+    drop(first);
+    assert!(is_leased(&path));
+
+    drop(second);
+    assert!(!is_leased(&path));
+}
+
+#[test]
+fn test_cache_config_sanitize() {
+    let config = CacheConfig {
+        root: Some(PathBuf::new()),
+        max_bytes: 0,
+    }
+    .sanitize();
+
+    assert_eq!(config.root, None);
+    assert_eq!(config.max_bytes, MIN_CACHE_MAX_BYTES);
+
+    let config = CacheConfig {
+        root: Some(PathBuf::from("/var/cache/mirrorx")),
+        ..CacheConfig::default()
+    };
+    assert_eq!(config.clone().sanitize(), config);
+}
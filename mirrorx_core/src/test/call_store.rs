@@ -0,0 +1,147 @@
+use crate::{
+    api::endpoint::{call_store::CallStore, client::watch_outgoing_channel, id::EndPointID},
+    error::CoreError,
+};
+use std::{sync::Arc, time::Duration};
+use tokio_util::sync::CancellationToken;
+
+#[tokio::test]
+async fn test_call_store_timeout_removes_entry() {
+    let store = CallStore::default();
+
+    let mut pending_call = store.register().unwrap();
+    assert_eq!(store.pending_count(), 1);
+
+    let result = pending_call.recv(Duration::from_millis(10)).await;
+    assert!(matches!(result, Err(CoreError::Timeout)));
+
+    drop(pending_call);
+    assert_eq!(store.pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_call_store_reply() {
+    let store = CallStore::default();
+
+    let mut pending_call = store.register().unwrap();
+    store.reply(pending_call.call_id(), vec![1, 2, 3]);
+    assert_eq!(store.pending_count(), 0);
+
+    let reply = pending_call.recv(Duration::from_secs(1)).await.unwrap();
+    assert_eq!(reply, vec![1, 2, 3]);
+
+    // a late duplicate reply is dropped
+    store.reply(pending_call.call_id(), vec![4]);
+    drop(pending_call);
+    assert_eq!(store.pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_call_store_hammer_with_timeouts() {
+    let store = Arc::new(CallStore::default());
+    let mut tasks = Vec::new();
+
+    for i in 0..2000u32 {
+        let store = store.clone();
+        tasks.push(tokio::spawn(async move {
+            let mut pending_call = store.register().unwrap();
+            let call_id = pending_call.call_id();
+
+            match i % 4 {
+                // reply before waiting
+                0 => store.reply(call_id, vec![0]),
+                // reply while waiting
+                1 => {
+                    let store = store.clone();
+                    tokio::spawn(async move { store.reply(call_id, vec![1]) });
+                }
+                // never replied
+                2 => {}
+                // abandoned before waiting, like a failed send
+                _ => return,
+            }
+
+            let _ = pending_call.recv(Duration::from_millis(5)).await;
+            drop(pending_call);
+
+            // reply arriving after the call timed out
+            store.reply(call_id, vec![2]);
+        }));
+    }
+
+    for task in tasks {
+        task.await.unwrap();
+    }
+
+    assert_eq!(store.pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_call_store_close_fails_pending_call() {
+    let store = Arc::new(CallStore::default());
+
+    let waiting = {
+        let store = store.clone();
+        tokio::spawn(async move {
+            let mut pending_call = store.register().unwrap();
+            pending_call.recv(Duration::from_secs(60)).await
+        })
+    };
+
+    while store.pending_count() == 0 {
+        tokio::task::yield_now().await;
+    }
+
+    store.close();
+
+    let result = tokio::time::timeout(Duration::from_secs(1), waiting)
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(matches!(
+        result,
+        Err(CoreError::OutgoingMessageChannelDisconnect)
+    ));
+
+    assert!(matches!(
+        store.register(),
+        Err(CoreError::OutgoingMessageChannelDisconnect)
+    ));
+    assert_eq!(store.pending_count(), 0);
+}
+
+#[tokio::test]
+async fn test_write_loop_exit_closes_connection() {
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse().unwrap(),
+        remote_ip: "10.0.7.1".parse().unwrap(),
+    };
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<Vec<u8>>(1);
+    let close_token = CancellationToken::new();
+    let store = Arc::new(CallStore::default());
+
+    watch_outgoing_channel(endpoint_id, tx.clone(), close_token.clone(), store.clone());
+
+    let mut pending_call = store.register().unwrap();
+
+    // the write loop owns the receiving end, it's dropped when the loop exits
+    drop(rx);
+
+    tokio::time::timeout(Duration::from_secs(1), close_token.cancelled())
+        .await
+        .unwrap();
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(1),
+        pending_call.recv(Duration::from_secs(60)),
+    )
+    .await
+    .unwrap();
+    assert!(matches!(
+        result,
+        Err(CoreError::OutgoingMessageChannelDisconnect)
+    ));
+
+    assert!(tx.send(vec![0]).await.is_err());
+}
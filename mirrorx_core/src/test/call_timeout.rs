@@ -0,0 +1,129 @@
+use crate::{
+    api::endpoint::{
+        call_timeout::{
+            wait_for, CallOperation, CallTimeouts, MAX_CALL_TIMEOUT_MS, MIN_CALL_TIMEOUT_MS,
+        },
+        client::EndPointClient,
+        id::EndPointID,
+        message::{
+            EndPointCallRequest, EndPointVisitDirectoryRequest, EndPointVisitDirectoryResponse,
+        },
+        EndPointStream,
+    },
+    error::CoreError,
+};
+use std::{net::IpAddr, time::Duration};
+use tokio::net::TcpListener;
+
+#[test]
+fn test_call_timeouts_sanitize() {
+    let timeouts = CallTimeouts {
+        handshake_ms: 0,
+        media_start_ms: u64::MAX,
+        call_ms: 5000,
+        rekey_ms: 1,
+    }
+    .sanitize();
+
+    assert_eq!(timeouts.handshake_ms, MIN_CALL_TIMEOUT_MS);
+    assert_eq!(timeouts.media_start_ms, MAX_CALL_TIMEOUT_MS);
+    assert_eq!(timeouts.call_ms, 5000);
+    assert_eq!(timeouts.rekey_ms, MIN_CALL_TIMEOUT_MS);
+
+    assert_eq!(CallTimeouts::default().sanitize(), CallTimeouts::default());
+}
+
+#[test]
+fn test_call_timeouts_per_operation() {
+    let timeouts = CallTimeouts {
+        handshake_ms: 1000,
+        media_start_ms: 2000,
+        call_ms: 3000,
+        rekey_ms: 4000,
+    };
+
+    assert_eq!(
+        timeouts.timeout(CallOperation::Handshake),
+        Duration::from_secs(1)
+    );
+    assert_eq!(
+        timeouts.timeout(CallOperation::MediaStart),
+        Duration::from_secs(2)
+    );
+    assert_eq!(
+        timeouts.timeout(CallOperation::Call),
+        Duration::from_secs(3)
+    );
+    assert_eq!(
+        timeouts.timeout(CallOperation::Rekey),
+        Duration::from_secs(4)
+    );
+
+    // the host may have to start capturing before it answers
+    let timeouts = CallTimeouts::default();
+    assert!(
+        timeouts.timeout(CallOperation::MediaStart) > timeouts.timeout(CallOperation::Handshake)
+    );
+}
+
+#[tokio::test]
+async fn test_wait_for_names_the_operation() {
+    let result = wait_for(
+        CallOperation::MediaStart,
+        Duration::from_millis(10),
+        std::future::pending::<()>(),
+    )
+    .await;
+
+    let err = result.unwrap_err();
+    assert!(matches!(
+        err,
+        CoreError::CallTimeout {
+            operation: CallOperation::MediaStart
+        }
+    ));
+    assert!(err.to_string().contains("media start"));
+    assert!(err.is_retryable());
+
+    let value = wait_for(CallOperation::Call, Duration::from_secs(1), async { 7 }).await;
+    assert_eq!(value.unwrap(), 7);
+}
+
+#[tokio::test]
+async fn test_call_with_timeout_fails_with_call_timeout() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.12.1".parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    // the remote side never answers
+    let (_stream, _) = listener.accept().await?;
+
+    let result = client
+        .call_with_timeout::<EndPointVisitDirectoryResponse>(
+            EndPointCallRequest::VisitDirectoryRequest(EndPointVisitDirectoryRequest {
+                path: None,
+            }),
+            Duration::from_millis(50),
+        )
+        .await;
+
+    assert!(matches!(
+        result,
+        Err(CoreError::CallTimeout {
+            operation: CallOperation::Call
+        })
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,376 @@
+use crate::{
+    api::endpoint::{
+        capabilities::{
+            agree_media_limits, agree_profile, fit_media_config, local_capabilities,
+            local_media_limits, preferred_video_codec, AES_256_GCM,
+        },
+        client::EndPointClient,
+        id::EndPointID,
+        message::{
+            EndPointCapabilities, EndPointMediaLimits, EndPointMessage,
+            EndPointNegotiateDesktopParamsRequest, MediaErrorReason, SessionFeature, VideoCodec,
+        },
+        packet::{deserialize_packet, frame_codec, serialize_packet},
+        session_limit::SessionLimiter,
+        EndPointStream,
+    },
+    component::{
+        desktop::monitor::Monitor,
+        input::encoding::InputEncoding,
+        lan::server::Server,
+        video_encoder::{adapter::select_video_codec, media_config::MediaConfig},
+    },
+    utility::bincode::{bincode_deserialize, bincode_serialize},
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use serde::Serialize;
+use std::{net::IpAddr, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::Framed;
+
+fn capabilities(
+    video_codecs: &[&str],
+    aeads: &[&str],
+    features: &[SessionFeature],
+    max_frame_length: u32,
+) -> EndPointCapabilities {
+    EndPointCapabilities {
+        video_codecs: video_codecs.iter().map(|name| name.to_string()).collect(),
+        aeads: aeads.iter().map(|name| name.to_string()).collect(),
+        features: features.to_vec(),
+        max_frame_length,
+        device_name: String::from("Remote PC\n"),
+        input_encodings: vec![String::from("scancode")],
+    }
+}
+
+#[test]
+fn test_agree_profile_intersects_capabilities() {
+    let active = capabilities(
+        &["av1", "vp9", "h264"],
+        &["chacha20-poly1305", AES_256_GCM],
+        &[
+            SessionFeature::Audio,
+            SessionFeature::Unknown,
+            SessionFeature::Chat,
+            SessionFeature::Swap,
+        ],
+        1024,
+    );
+    let passive = capabilities(
+        &["h264", "vp9", "vp8"],
+        &[AES_256_GCM],
+        &[
+            SessionFeature::Swap,
+            SessionFeature::Unknown,
+            SessionFeature::Input,
+            SessionFeature::Audio,
+        ],
+        4096,
+    );
+
+    let profile = agree_profile(&active, &passive);
+
+    // in the order the active side prefers, the unknown codec left out
+    assert_eq!(
+        profile.video_codecs,
+        vec![VideoCodec::VP9, VideoCodec::H264]
+    );
+    assert_eq!(profile.aead.as_deref(), Some(AES_256_GCM));
+    assert_eq!(
+        profile.features,
+        vec![SessionFeature::Audio, SessionFeature::Swap]
+    );
+    assert!(profile.supports(SessionFeature::Swap));
+    assert!(!profile.supports(SessionFeature::Chat));
+    assert!(!profile.supports(SessionFeature::Input));
+    assert!(!profile.supports(SessionFeature::Unknown));
+    assert_eq!(profile.max_frame_length, 1024);
+}
+
+#[test]
+fn test_agree_profile_without_common_ground() {
+    let active = capabilities(
+        &["vp8"],
+        &["chacha20-poly1305"],
+        &[SessionFeature::Fec],
+        4096,
+    );
+    let passive = capabilities(&["h264"], &[AES_256_GCM], &[SessionFeature::Chat], 2048);
+
+    let profile = agree_profile(&active, &passive);
+
+    assert!(profile.video_codecs.is_empty());
+    assert_eq!(profile.aead, None);
+    assert!(profile.features.is_empty());
+    assert_eq!(profile.max_frame_length, 2048);
+}
+
+#[test]
+fn test_agree_profile_falls_back_to_h264() {
+    let hevc = capabilities(&["hevc", "h264"], &[AES_256_GCM], &[], 4096);
+    let h264_only = capabilities(&["h264"], &[AES_256_GCM], &[], 4096);
+
+    assert_eq!(
+        agree_profile(&hevc, &hevc).video_codecs,
+        vec![VideoCodec::Hevc, VideoCodec::H264]
+    );
+    assert_eq!(
+        agree_profile(&hevc, &h264_only).video_codecs,
+        vec![VideoCodec::H264]
+    );
+    assert_eq!(
+        agree_profile(&h264_only, &hevc).video_codecs,
+        vec![VideoCodec::H264]
+    );
+}
+
+#[test]
+fn test_select_video_codec() {
+    assert_eq!(
+        select_video_codec(&[VideoCodec::VP9, VideoCodec::H264]),
+        Some(VideoCodec::H264)
+    );
+    assert_eq!(select_video_codec(&[VideoCodec::VP8]), None);
+    assert_eq!(select_video_codec(&[]), None);
+}
+
+#[test]
+fn test_agree_media_limits() {
+    let remote = EndPointMediaLimits {
+        max_frame_rate: 30,
+        max_width: 8192,
+        max_height: 1080,
+    };
+
+    let limits = agree_media_limits(&local_media_limits(), &remote);
+    assert_eq!(limits.max_frame_rate, 30);
+    assert_eq!(limits.max_width, local_media_limits().max_width);
+    assert_eq!(limits.max_height, 1080);
+
+    let remote = EndPointMediaLimits {
+        max_frame_rate: 0,
+        ..remote
+    };
+    assert_eq!(
+        agree_media_limits(&local_media_limits(), &remote).max_frame_rate,
+        1
+    );
+}
+
+#[test]
+fn test_fit_media_config() {
+    let monitor = Monitor {
+        id: String::from("1"),
+        name: String::from("primary"),
+        refresh_rate: 60,
+        width: 3840,
+        height: 2160,
+        is_primary: true,
+        screen_shot: None,
+        left: 0,
+        top: 0,
+    };
+
+    let media_config = MediaConfig {
+        frame_rate: 60,
+        scale_percent: 100,
+        ..Default::default()
+    };
+
+    let limits = EndPointMediaLimits {
+        max_frame_rate: 30,
+        max_width: 1920,
+        max_height: 1200,
+    };
+
+    let fitted = fit_media_config(media_config.clone(), &monitor, &limits);
+    assert_eq!(fitted.frame_rate, 30);
+    assert_eq!(fitted.scale_percent, 50);
+
+    // a config within the limits stays as it is
+    let fitted = fit_media_config(media_config.clone(), &monitor, &local_media_limits());
+    assert_eq!(fitted, media_config.sanitize());
+}
+
+#[test]
+fn test_agree_profile_of_same_version() {
+    let profile = agree_profile(&local_capabilities(), &local_capabilities());
+
+    // HEVC first when this build has it, H264 always
+    assert_eq!(profile.video_codecs.first(), Some(&preferred_video_codec()));
+    assert_eq!(profile.video_codecs.last(), Some(&VideoCodec::H264));
+    assert_eq!(profile.aead.as_deref(), Some(AES_256_GCM));
+    assert_eq!(profile.features, local_capabilities().features);
+    assert_eq!(profile.input_encoding, InputEncoding::Unicode);
+}
+
+#[test]
+fn test_agree_input_encoding() {
+    let encodings = |names: &[&str]| names.iter().map(|name| name.to_string()).collect();
+
+    let mut active = capabilities(&["h264"], &[AES_256_GCM], &[], 4096);
+    let mut passive = active.clone();
+
+    // the keysym of a newer version is left out
+    active.input_encodings = encodings(&["keysym", "unicode", "scancode"]);
+    passive.input_encodings = encodings(&["scancode", "keysym", "unicode"]);
+    assert_eq!(
+        agree_profile(&active, &passive).input_encoding,
+        InputEncoding::Unicode
+    );
+
+    // a side knowing only scancodes gets scancodes
+    passive.input_encodings = encodings(&["scancode"]);
+    assert_eq!(
+        agree_profile(&active, &passive).input_encoding,
+        InputEncoding::Scancode
+    );
+
+    // nothing in common falls back to scancodes, every version sends them
+    active.input_encodings = encodings(&["keysym"]);
+    passive.input_encodings = Vec::new();
+    assert_eq!(
+        agree_profile(&active, &passive).input_encoding,
+        InputEncoding::Scancode
+    );
+}
+
+/// `SessionFeature` of a newer version with one more feature.
+#[allow(dead_code)]
+#[derive(Serialize)]
+enum NewerSessionFeature {
+    Audio,
+    Input,
+    FileTransfer,
+    Chat,
+    Swap,
+    Fec,
+    Clipboard,
+    NetworkReport,
+    Recording,
+}
+
+#[test]
+fn test_unknown_feature_is_ignored() -> anyhow::Result<()> {
+    let buffer = bincode_serialize(&vec![
+        NewerSessionFeature::Recording,
+        NewerSessionFeature::Chat,
+    ])?;
+    let features: Vec<SessionFeature> = bincode_deserialize(&buffer)?;
+    assert_eq!(
+        features,
+        vec![SessionFeature::Unknown, SessionFeature::Chat]
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_profile_stored_once_capabilities_arrive() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.6.1".parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Framed::new(stream, frame_codec());
+
+    assert_eq!(client.profile(), None);
+    assert_eq!(client.remote_device_name(), None);
+    assert!(!client.supports(SessionFeature::Chat));
+
+    let remote_capabilities = capabilities(
+        &["h264"],
+        &[AES_256_GCM],
+        &[SessionFeature::Chat, SessionFeature::Unknown],
+        4096,
+    );
+    remote
+        .send(Bytes::from(serialize_packet(
+            &EndPointMessage::Capabilities(remote_capabilities),
+        )?))
+        .await?;
+
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while client.profile().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+
+    assert!(client.supports(SessionFeature::Chat));
+    assert!(!client.supports(SessionFeature::Swap));
+    assert_eq!(
+        client.profile().map(|profile| profile.max_frame_length),
+        Some(4096)
+    );
+    assert_eq!(client.remote_device_name().as_deref(), Some("Remote PC"));
+
+    client.close();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_negotiate_without_common_codec() -> anyhow::Result<()> {
+    // only the visited side shares its desktop
+    let server = Server::bind("127.0.0.1:0".parse()?, SessionLimiter::new(1)).await?;
+
+    let stream = TcpStream::connect(server.local_addr()).await?;
+    let mut remote = Framed::new(stream, frame_codec());
+
+    // a newer viewer offering only a codec this build doesn't encode
+    remote
+        .send(Bytes::from(serialize_packet(
+            &EndPointMessage::NegotiateDesktopParamsRequest(
+                EndPointNegotiateDesktopParamsRequest {
+                    video_codecs: vec![VideoCodec::VP8],
+                    media_limits: local_media_limits(),
+                    audio: None,
+                    rejoin_token: None,
+                },
+            ),
+        )?))
+        .await?;
+
+    let reply = tokio::time::timeout(Duration::from_secs(3), async {
+        loop {
+            let buffer = remote
+                .next()
+                .await
+                .ok_or_else(|| anyhow::anyhow!("connection closed"))??;
+
+            match deserialize_packet(&buffer)? {
+                message @ (EndPointMessage::Error { .. }
+                | EndPointMessage::NegotiateDesktopParamsResponse(_)) => {
+                    return anyhow::Ok(message)
+                }
+                _ => continue,
+            }
+        }
+    })
+    .await??;
+
+    assert_eq!(
+        reply,
+        EndPointMessage::Error {
+            reason: MediaErrorReason::CodecUnsupported
+        }
+    );
+
+    server.shutdown().await;
+
+    Ok(())
+}
@@ -0,0 +1,72 @@
+use crate::{
+    component::desktop::capture_error::{
+        classify_capture_error, CaptureErrorKind, CaptureTolerance, CaptureVerdict,
+        MAX_CAPTURE_RESTARTS, MAX_TRANSIENT_CAPTURE_FAILURES,
+    },
+    core_error,
+};
+
+#[test]
+fn test_capture_tolerance_skips_transient_failures() {
+    let mut tolerance = CaptureTolerance::default();
+
+    for _ in 0..3 {
+        for _ in 1..MAX_TRANSIENT_CAPTURE_FAILURES {
+            assert_eq!(
+                tolerance.record_error(CaptureErrorKind::Transient),
+                CaptureVerdict::Skip
+            );
+        }
+
+        // a captured frame ends the run of failures
+        tolerance.record_frame();
+    }
+
+    for _ in 1..MAX_TRANSIENT_CAPTURE_FAILURES {
+        tolerance.record_error(CaptureErrorKind::Transient);
+    }
+    assert_eq!(
+        tolerance.record_error(CaptureErrorKind::Transient),
+        CaptureVerdict::Restart
+    );
+
+    // the restarted capture gets a fresh budget
+    assert_eq!(
+        tolerance.record_error(CaptureErrorKind::Transient),
+        CaptureVerdict::Skip
+    );
+}
+
+#[test]
+fn test_capture_tolerance_gives_up_after_restarts() {
+    let mut tolerance = CaptureTolerance::default();
+
+    for _ in 0..MAX_CAPTURE_RESTARTS {
+        assert_eq!(
+            tolerance.record_error(CaptureErrorKind::Fatal),
+            CaptureVerdict::Restart
+        );
+    }
+    assert_eq!(
+        tolerance.record_error(CaptureErrorKind::Fatal),
+        CaptureVerdict::Fail
+    );
+
+    // restarts that produce frames again never add up
+    let mut tolerance = CaptureTolerance::default();
+    for _ in 0..MAX_CAPTURE_RESTARTS * 2 {
+        assert_eq!(
+            tolerance.record_error(CaptureErrorKind::Fatal),
+            CaptureVerdict::Restart
+        );
+        tolerance.record_frame();
+    }
+}
+
+#[test]
+fn test_capture_error_classification() {
+    assert_eq!(
+        classify_capture_error(&core_error!("copy texture failed")),
+        CaptureErrorKind::Transient
+    );
+}
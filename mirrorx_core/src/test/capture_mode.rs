@@ -0,0 +1,103 @@
+use crate::component::{
+    desktop::{
+        capture_mode::{clip_dirty_rect, crop_dirty_rects, is_dirty, scale_dirty_rects},
+        window::{crop_frame, FrameRect},
+    },
+    frame::DesktopEncodeFrame,
+    video_encoder::media_config::scale_frame,
+};
+use std::time::Duration;
+
+fn rect(left: i32, top: i32, width: i32, height: i32) -> FrameRect {
+    FrameRect {
+        left,
+        top,
+        width,
+        height,
+    }
+}
+
+fn new_frame(width: i32, height: i32, dirty_rects: Option<Vec<FrameRect>>) -> DesktopEncodeFrame {
+    DesktopEncodeFrame {
+        capture_time: Duration::ZERO,
+        width,
+        height,
+        luminance_bytes: vec![0; (width * height) as usize],
+        luminance_stride: width,
+        chrominance_bytes: vec![128; (width * height / 2) as usize],
+        chrominance_stride: width,
+        dirty_rects,
+    }
+}
+
+#[test]
+fn test_clip_dirty_rect() {
+    // edges grow to cover whole chroma blocks
+    assert_eq!(
+        clip_dirty_rect(3, 5, 10, 12, 100, 100),
+        Some(rect(2, 4, 8, 8))
+    );
+
+    // clipped to the frame
+    assert_eq!(
+        clip_dirty_rect(-20, 90, 30, 130, 100, 100),
+        Some(rect(0, 90, 30, 10))
+    );
+
+    assert_eq!(clip_dirty_rect(100, 0, 120, 10, 100, 100), None);
+    assert_eq!(clip_dirty_rect(10, 10, 10, 20, 100, 100), None);
+}
+
+#[test]
+fn test_crop_dirty_rects() {
+    let crop = rect(100, 50, 200, 100);
+    let rects = [
+        rect(0, 0, 20, 20),
+        rect(90, 40, 20, 20),
+        rect(250, 100, 100, 100),
+    ];
+
+    assert_eq!(
+        crop_dirty_rects(&rects, &crop),
+        vec![rect(0, 0, 10, 10), rect(150, 50, 50, 50)]
+    );
+}
+
+#[test]
+fn test_scale_dirty_rects() {
+    // scaled down they still cover every pixel they touched
+    assert_eq!(
+        scale_dirty_rects(&[rect(10, 10, 20, 20)], 1920, 1080, 960, 540),
+        vec![rect(4, 4, 12, 12)]
+    );
+
+    assert_eq!(
+        scale_dirty_rects(&[rect(0, 0, 1920, 1080)], 1920, 1080, 1440, 810),
+        vec![rect(0, 0, 1440, 810)]
+    );
+}
+
+#[test]
+fn test_is_dirty() {
+    let rects = [rect(64, 64, 2, 2)];
+
+    assert!(is_dirty(&rects, &(64..128), &(0..128)));
+    assert!(!is_dirty(&rects, &(0..64), &(0..128)));
+    assert!(!is_dirty(&rects, &(64..128), &(66..128)));
+    assert!(!is_dirty(&[], &(0..128), &(0..128)));
+}
+
+#[test]
+fn test_frame_operations_keep_dirty_rects() {
+    let frame = new_frame(400, 200, Some(vec![rect(120, 60, 20, 20)]));
+
+    let cropped = crop_frame(&frame, &rect(100, 50, 200, 100));
+    assert_eq!(cropped.dirty_rects, Some(vec![rect(20, 10, 20, 20)]));
+
+    let scaled = scale_frame(frame, 200, 100);
+    assert_eq!(scaled.dirty_rects, Some(vec![rect(60, 30, 10, 10)]));
+
+    // a frame the system told nothing about stays that way
+    let scaled = scale_frame(new_frame(400, 200, None), 200, 100);
+    assert_eq!(scaled.dirty_rects, None);
+}
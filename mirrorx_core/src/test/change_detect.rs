@@ -0,0 +1,127 @@
+use crate::component::{
+    desktop::window::FrameRect,
+    frame::DesktopEncodeFrame,
+    video_encoder::{
+        change_detect::{ChangeDetector, FrameChange, UNCHANGED_MARKER_INTERVAL},
+        media_config::{MediaConfig, MAX_CHANGE_THRESHOLD},
+    },
+};
+use std::time::Duration;
+
+const WIDTH: i32 = 256;
+const HEIGHT: i32 = 256;
+
+fn frame(luma: u8) -> DesktopEncodeFrame {
+    DesktopEncodeFrame {
+        capture_time: Duration::ZERO,
+        width: WIDTH,
+        height: HEIGHT,
+        luminance_bytes: vec![luma; (WIDTH * HEIGHT) as usize],
+        luminance_stride: WIDTH,
+        chrominance_bytes: vec![128; (WIDTH * HEIGHT / 2) as usize],
+        chrominance_stride: WIDTH,
+        dirty_rects: None,
+    }
+}
+
+fn paint(frame: &mut DesktopEncodeFrame, rows: std::ops::Range<usize>, luma: u8) {
+    for y in rows {
+        let row = y * frame.luminance_stride as usize;
+        frame.luminance_bytes[row + 100..row + 110].fill(luma);
+    }
+}
+
+#[test]
+fn test_change_detector_static_screen() {
+    let mut detector = ChangeDetector::new(8);
+
+    // the first frame is the whole content
+    assert_eq!(detector.observe(&frame(16)), FrameChange::Full);
+    assert_eq!(detector.observe(&frame(16)), FrameChange::Unchanged);
+
+    // capture noise within the threshold
+    assert_eq!(detector.observe(&frame(20)), FrameChange::Unchanged);
+
+    // a frame of another size starts over
+    let mut resized = frame(16);
+    resized.width = 128;
+    resized.luminance_stride = 128;
+    resized.chrominance_stride = 128;
+    assert_eq!(detector.observe(&resized), FrameChange::Full);
+}
+
+#[test]
+fn test_change_detector_changed_rows() {
+    let mut detector = ChangeDetector::new(8);
+    detector.observe(&frame(16));
+
+    // a few rows come back as the rows of the regions they're in
+    let mut changed = frame(16);
+    paint(&mut changed, 70..72, 200);
+    assert_eq!(detector.observe(&changed), FrameChange::Rows(64..128));
+
+    // the change is the content of the viewer now
+    assert_eq!(detector.observe(&changed), FrameChange::Unchanged);
+
+    // a change over most of the screen goes out whole
+    let mut changed = frame(16);
+    paint(&mut changed, 0..200, 200);
+    assert_eq!(detector.observe(&changed), FrameChange::Full);
+
+    // chroma counts too
+    let mut recolored = changed;
+    recolored.chrominance_bytes[WIDTH as usize * 100 + 10] = 20;
+    assert_eq!(detector.observe(&recolored), FrameChange::Rows(192..256));
+}
+
+#[test]
+fn test_change_detector_dirty_rects() {
+    let mut detector = ChangeDetector::new(8);
+    detector.observe(&frame(16));
+
+    // only the regions the system reports changed are looked at
+    let mut changed = frame(16);
+    paint(&mut changed, 70..72, 200);
+    paint(&mut changed, 200..202, 200);
+    changed.dirty_rects = Some(vec![FrameRect {
+        left: 100,
+        top: 70,
+        width: 10,
+        height: 2,
+    }]);
+    assert_eq!(detector.observe(&changed), FrameChange::Rows(64..128));
+
+    // nothing reported, nothing compared
+    changed.dirty_rects = Some(Vec::new());
+    assert_eq!(detector.observe(&changed), FrameChange::Unchanged);
+
+    // a frame without rects is compared whole
+    changed.dirty_rects = None;
+    assert_eq!(detector.observe(&changed), FrameChange::Rows(192..256));
+}
+
+#[test]
+fn test_change_detector_marker_interval() {
+    let mut detector = ChangeDetector::new(8);
+    let start = Duration::from_secs(10);
+
+    detector.frame_sent(start);
+    assert!(!detector.marker_due(start + Duration::from_millis(500)));
+    assert!(detector.marker_due(start + UNCHANGED_MARKER_INTERVAL));
+    assert!(!detector.marker_due(start + UNCHANGED_MARKER_INTERVAL + Duration::from_millis(500)));
+    assert!(detector.marker_due(start + UNCHANGED_MARKER_INTERVAL * 2));
+}
+
+#[test]
+fn test_change_only_config() {
+    let config = MediaConfig::default();
+    assert!(!config.change_only);
+    assert_eq!(config.change_threshold, 8);
+
+    let config = MediaConfig {
+        change_threshold: 255,
+        ..MediaConfig::default()
+    }
+    .sanitize();
+    assert_eq!(config.change_threshold, MAX_CHANGE_THRESHOLD);
+}
@@ -0,0 +1,84 @@
+use crate::api::{
+    config::{LocalStorage, Storage},
+    endpoint::{
+        chat::{subscribe_chat_message, Chat, ChatDirection, ChatEntry, MAX_CHAT_MESSAGE_LEN},
+        id::EndPointID,
+    },
+};
+
+const ENDPOINT_ID: EndPointID = EndPointID::DeviceID {
+    local_device_id: 1,
+    remote_device_id: 1234567890,
+};
+
+fn entry(direction: ChatDirection, text: &str, timestamp: i64) -> ChatEntry {
+    ChatEntry {
+        remote: String::from("1234567890"),
+        direction,
+        text: text.to_string(),
+        timestamp,
+    }
+}
+
+#[test]
+fn test_chat_history_is_bounded() {
+    let chat = Chat::new(3);
+
+    for timestamp in 0..5 {
+        chat.record(ENDPOINT_ID, entry(ChatDirection::Sent, "hello", timestamp));
+    }
+
+    let timestamps: Vec<i64> = chat
+        .history(&ENDPOINT_ID)
+        .iter()
+        .map(|entry| entry.timestamp)
+        .collect();
+    assert_eq!(timestamps, vec![2, 3, 4]);
+
+    chat.clear(&ENDPOINT_ID);
+    assert!(chat.history(&ENDPOINT_ID).is_empty());
+}
+
+#[test]
+fn test_chat_receive() {
+    let chat = Chat::new(8);
+    let mut rx = subscribe_chat_message();
+
+    chat.receive(ENDPOINT_ID, String::from("can you see my screen?"), 100);
+    chat.receive(ENDPOINT_ID, String::from("  "), 101);
+    chat.receive(ENDPOINT_ID, "x".repeat(MAX_CHAT_MESSAGE_LEN + 1), 102);
+
+    let expected = entry(ChatDirection::Received, "can you see my screen?", 100);
+    assert_eq!(chat.history(&ENDPOINT_ID), vec![expected.clone()]);
+    assert_eq!(rx.try_recv().ok(), Some(expected));
+    assert!(rx.try_recv().is_err());
+}
+
+#[test]
+fn test_chat_records() -> anyhow::Result<()> {
+    let storage = LocalStorage::memory()?;
+
+    storage
+        .chat()
+        .create(&entry(ChatDirection::Sent, "first", 1))?;
+    storage
+        .chat()
+        .create(&entry(ChatDirection::Received, "second", 2))?;
+    storage
+        .chat()
+        .create(&entry(ChatDirection::Sent, "third", 3))?;
+
+    let records = storage.chat().query("1234567890", 2)?;
+    assert_eq!(
+        records,
+        vec![
+            entry(ChatDirection::Received, "second", 2),
+            entry(ChatDirection::Sent, "third", 3)
+        ]
+    );
+
+    storage.chat().delete("1234567890")?;
+    assert!(storage.chat().query("1234567890", 2)?.is_empty());
+
+    Ok(())
+}
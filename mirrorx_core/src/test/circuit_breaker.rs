@@ -0,0 +1,146 @@
+use crate::{
+    api::signaling::{
+        circuit_breaker::{CircuitBreaker, CircuitBreakerConfig, CircuitState},
+        http_message::Response,
+        SignalingClient,
+    },
+    error::CoreError,
+};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+const IDENTITY: &str = r#"{"domain":"mirrorx.test","min_client_version":"1.0.0","signaling_port":28000,"subscribe_port":28001}"#;
+
+#[test]
+fn test_circuit_breaker_transitions() {
+    let now = Instant::now();
+    let mut breaker = CircuitBreaker::new(CircuitBreakerConfig {
+        failure_threshold: 3,
+        cooldown_secs: 10,
+    });
+
+    for _ in 0..2 {
+        assert!(breaker.acquire(now).is_ok());
+        breaker.record::<()>(&Err(CoreError::Timeout), now);
+    }
+    assert_eq!(breaker.state(now), CircuitState::Closed);
+
+    // an answer the server gave on purpose breaks the streak
+    assert!(breaker.acquire(now).is_ok());
+    breaker.record::<()>(&Err(CoreError::VisitInvalidPassword), now);
+    assert_eq!(breaker.stats(now).consecutive_failures, 0);
+
+    for _ in 0..3 {
+        assert!(breaker.acquire(now).is_ok());
+        breaker.record::<()>(&Err(CoreError::VisitServerBusy), now);
+    }
+    assert_eq!(breaker.state(now), CircuitState::Open);
+
+    let later = now + Duration::from_secs(4);
+    assert!(matches!(
+        breaker.acquire(later),
+        Err(CoreError::SignalingUnavailable)
+    ));
+    let stats = breaker.stats(later);
+    assert_eq!(stats.retry_in_secs, Some(6));
+    assert_eq!(stats.rejected, 1);
+
+    // one probe after the cooldown, the others keep failing until it reports
+    let cooled = now + Duration::from_secs(10);
+    assert_eq!(breaker.state(cooled), CircuitState::HalfOpen);
+    assert!(breaker.acquire(cooled).is_ok());
+    assert!(breaker.acquire(cooled).is_err());
+    assert_eq!(breaker.state(cooled), CircuitState::HalfOpen);
+
+    // a failed probe opens it for another cooldown
+    breaker.record::<()>(&Err(CoreError::Timeout), cooled);
+    assert_eq!(breaker.state(cooled), CircuitState::Open);
+    assert!(breaker.acquire(cooled + Duration::from_secs(9)).is_err());
+
+    let cooled = cooled + Duration::from_secs(10);
+    assert!(breaker.acquire(cooled).is_ok());
+    breaker.record(&Ok(()), cooled);
+    assert_eq!(breaker.state(cooled), CircuitState::Closed);
+    assert!(breaker.acquire(cooled).is_ok());
+}
+
+#[test]
+fn test_circuit_breaker_config_sanitize() {
+    let config = CircuitBreakerConfig {
+        failure_threshold: 0,
+        cooldown_secs: 0,
+    }
+    .sanitize();
+
+    assert_eq!(config.failure_threshold, 1);
+    assert_eq!(config.cooldown_secs, 1);
+}
+
+#[tokio::test]
+async fn test_signaling_client_fails_fast_while_open() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    // drops the first two connections, answers the ones after
+    let server_connections = connections.clone();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            if server_connections.fetch_add(1, Ordering::SeqCst) < 2 {
+                continue;
+            }
+
+            let mut buffer = [0u8; 1024];
+            let _ = stream.read(&mut buffer).await;
+
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                IDENTITY.len(),
+                IDENTITY
+            );
+            let _ = stream.write_all(response.as_bytes()).await;
+        }
+    });
+
+    let client = SignalingClient::new(format!("http://{addr}"))?;
+    client.set_circuit_breaker_config(CircuitBreakerConfig {
+        failure_threshold: 2,
+        cooldown_secs: 1,
+    });
+
+    for _ in 0..2 {
+        assert!(matches!(
+            client.identity().await,
+            Err(CoreError::ReqwestError(_))
+        ));
+    }
+    assert_eq!(client.circuit_breaker_stats().state, CircuitState::Open);
+
+    // the server doesn't see the call
+    assert!(matches!(
+        client.identity().await,
+        Err(CoreError::SignalingUnavailable)
+    ));
+    assert_eq!(connections.load(Ordering::SeqCst), 2);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(client.circuit_breaker_stats().state, CircuitState::HalfOpen);
+
+    let identity = match client.identity().await? {
+        Response::Message(identity) => identity,
+        Response::Error(err) => anyhow::bail!("unexpected error response {:?}", err),
+    };
+    assert_eq!(identity.domain, "mirrorx.test");
+    assert_eq!(client.circuit_breaker_stats().state, CircuitState::Closed);
+
+    Ok(())
+}
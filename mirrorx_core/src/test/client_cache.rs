@@ -0,0 +1,227 @@
+use crate::{
+    api::endpoint::{
+        client::EndPointClient,
+        client_cache::{
+            subscribe_connection_lost, ConnectionState, EndPointClientCache, Reconnect,
+            ReconnectFuture, ReconnectPolicy,
+        },
+        id::EndPointID,
+        EndPointStream,
+    },
+    core_error,
+    error::{CoreError, CoreResult},
+};
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::net::TcpListener;
+
+async fn connect(addr: SocketAddr) -> CoreResult<Arc<EndPointClient>> {
+    let endpoint_id = EndPointID::LANID {
+        local_ip: IpAddr::from([127, 0, 0, 1]),
+        remote_ip: IpAddr::from([10, 0, 5, 1]),
+    };
+
+    EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(addr),
+        None,
+    )
+    .await
+}
+
+#[tokio::test]
+async fn test_client_cache_reconnects_closed_client_once() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    // keeps the remote ends open
+    tokio::spawn(async move {
+        let mut streams = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            streams.push(stream);
+        }
+    });
+
+    let reconnects = Arc::new(AtomicUsize::new(0));
+    let reconnect: Reconnect = {
+        let reconnects = reconnects.clone();
+        Arc::new(move || -> ReconnectFuture {
+            let reconnects = reconnects.clone();
+            Box::pin(async move {
+                reconnects.fetch_add(1, Ordering::SeqCst);
+                // give the concurrent caller time to line up
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                connect(addr).await
+            })
+        })
+    };
+
+    let cache = EndPointClientCache::new(8);
+    let client = connect(addr).await?;
+    cache
+        .insert(String::from("files"), client.clone(), reconnect)
+        .await;
+
+    // a live client is handed out as it is
+    let cached = cache.get_or_reconnect("files").await?;
+    assert!(Arc::ptr_eq(&cached, &client));
+    assert_eq!(reconnects.load(Ordering::SeqCst), 0);
+
+    client.close();
+
+    let (first, second) = tokio::join!(
+        cache.get_or_reconnect("files"),
+        cache.get_or_reconnect("files")
+    );
+    let (first, second) = (first?, second?);
+
+    assert_eq!(reconnects.load(Ordering::SeqCst), 1);
+    assert!(Arc::ptr_eq(&first, &second));
+    assert!(!Arc::ptr_eq(&first, &client));
+    assert!(!first.is_closed());
+
+    assert!(cache.get_or_reconnect("unknown").await.is_err());
+
+    first.close();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_cache_gives_up_reconnecting() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        let mut streams = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            streams.push(stream);
+        }
+    });
+
+    let reachable = Arc::new(AtomicBool::new(false));
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let reconnect: Reconnect = {
+        let reachable = reachable.clone();
+        let attempts = attempts.clone();
+        Arc::new(move || -> ReconnectFuture {
+            let reachable = reachable.load(Ordering::SeqCst);
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async move {
+                if !reachable {
+                    return Err(core_error!("remote device unreachable"));
+                }
+
+                connect(addr).await
+            })
+        })
+    };
+
+    let policy = ReconnectPolicy {
+        initial_backoff: Duration::from_millis(20),
+        max_backoff: Duration::from_millis(40),
+        max_duration: Duration::from_millis(300),
+    };
+    let cache = EndPointClientCache::with_policy(8, policy);
+    let client = connect(addr).await?;
+    cache
+        .insert(String::from("unreachable"), client.clone(), reconnect)
+        .await;
+    assert_eq!(cache.state("unreachable"), Some(ConnectionState::Connected));
+
+    let mut lost_rx = subscribe_connection_lost();
+    client.close();
+    assert_eq!(
+        cache.state("unreachable"),
+        Some(ConnectionState::Reconnecting)
+    );
+
+    // attempts at 0, 20, 60, 100 .. 260ms, the next would end past the budget
+    let started_at = Instant::now();
+    assert!(cache.get_or_reconnect("unreachable").await.is_err());
+    let elapsed = started_at.elapsed();
+    assert!(elapsed >= policy.max_duration - policy.max_backoff);
+    assert!(elapsed < policy.max_duration + Duration::from_millis(200));
+    let given_up_after = attempts.load(Ordering::SeqCst);
+    assert!(given_up_after > 1);
+    assert_eq!(cache.state("unreachable"), Some(ConnectionState::Closed));
+
+    let lost = loop {
+        let lost = lost_rx.recv().await?;
+        if lost.key == "unreachable" {
+            break lost;
+        }
+    };
+    assert_eq!(lost.attempts as usize, given_up_after);
+    assert!(lost.reason.contains("remote device unreachable"));
+
+    // given up, a use doesn't try again
+    assert!(cache.get_or_reconnect("unreachable").await.is_err());
+    assert_eq!(attempts.load(Ordering::SeqCst), given_up_after);
+
+    reachable.store(true, Ordering::SeqCst);
+    let client = cache.retry("unreachable").await?;
+    assert!(!client.is_closed());
+    assert_eq!(attempts.load(Ordering::SeqCst), given_up_after + 1);
+    assert_eq!(cache.state("unreachable"), Some(ConnectionState::Connected));
+
+    client.close();
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_client_cache_gives_up_without_password() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        let mut streams = Vec::new();
+        while let Ok((stream, _)) = listener.accept().await {
+            streams.push(stream);
+        }
+    });
+
+    let attempts = Arc::new(AtomicUsize::new(0));
+    let reconnect: Reconnect = {
+        let attempts = attempts.clone();
+        Arc::new(move || -> ReconnectFuture {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Err(CoreError::ReconnectNeedsPassword) })
+        })
+    };
+
+    let cache = EndPointClientCache::new(8);
+    let client = connect(addr).await?;
+    cache
+        .insert(String::from("needs-password"), client.clone(), reconnect)
+        .await;
+
+    let mut lost_rx = subscribe_connection_lost();
+    client.close();
+
+    // the user is asked right away instead of after the reconnect budget
+    let started_at = Instant::now();
+    assert!(matches!(
+        cache.get_or_reconnect("needs-password").await,
+        Err(CoreError::ReconnectNeedsPassword)
+    ));
+    assert!(started_at.elapsed() < Duration::from_secs(1));
+    assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    assert_eq!(cache.state("needs-password"), Some(ConnectionState::Closed));
+
+    let lost = loop {
+        let lost = lost_rx.recv().await?;
+        if lost.key == "needs-password" {
+            break lost;
+        }
+    };
+    assert_eq!(lost.attempts, 1);
+
+    Ok(())
+}
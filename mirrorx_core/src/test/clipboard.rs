@@ -0,0 +1,84 @@
+use crate::{
+    api::endpoint::{
+        clipboard::{
+            clipboard_content_hash, clipboard_sync, set_clipboard_sync, ClipboardTracker,
+            CLIPBOARD_DEBOUNCE,
+        },
+        id::EndPointID,
+        message::{ClipboardContent, EndPointMessage},
+        packet::{deserialize_packet, serialize_packet},
+    },
+    error::CoreError,
+};
+use std::{net::IpAddr, time::Instant};
+
+fn hash(text: &str) -> u64 {
+    clipboard_content_hash(&ClipboardContent::Text(String::from(text)))
+}
+
+#[test]
+fn test_clipboard_tracker_debounces_changes() {
+    let mut tracker = ClipboardTracker::default();
+    let start = Instant::now();
+
+    assert!(!tracker.observe(hash("a"), start));
+    // copied again before the first change held
+    assert!(!tracker.observe(hash("ab"), start + CLIPBOARD_DEBOUNCE / 2));
+    assert!(!tracker.observe(hash("ab"), start + CLIPBOARD_DEBOUNCE));
+    assert!(tracker.observe(
+        hash("ab"),
+        start + CLIPBOARD_DEBOUNCE / 2 + CLIPBOARD_DEBOUNCE
+    ));
+
+    // sent once only
+    assert!(!tracker.observe(hash("ab"), start + CLIPBOARD_DEBOUNCE * 4));
+    assert!(!tracker.observe(hash("ab"), start + CLIPBOARD_DEBOUNCE * 8));
+}
+
+#[test]
+fn test_clipboard_tracker_doesnt_echo() {
+    let mut tracker = ClipboardTracker::default();
+    let start = Instant::now();
+
+    tracker.applied(hash("from remote"));
+    for i in 0..4 {
+        assert!(!tracker.observe(hash("from remote"), start + CLIPBOARD_DEBOUNCE * i));
+    }
+
+    // copied locally afterwards
+    assert!(!tracker.observe(hash("local"), start));
+    assert!(tracker.observe(hash("local"), start + CLIPBOARD_DEBOUNCE));
+}
+
+#[test]
+fn test_clipboard_content_hash() {
+    assert_eq!(hash("echo hello"), hash("echo hello"));
+    assert_ne!(hash("echo hello"), hash("echo hello "));
+}
+
+#[test]
+fn test_clipboard_update_round_trip() -> anyhow::Result<()> {
+    let message = EndPointMessage::ClipboardUpdate {
+        content: ClipboardContent::Text(String::from("cargo test --workspace")),
+    };
+
+    assert_eq!(deserialize_packet(&serialize_packet(&message)?)?, message);
+
+    Ok(())
+}
+
+#[test]
+fn test_clipboard_sync_needs_session() -> anyhow::Result<()> {
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.7.1".parse::<IpAddr>()?,
+    };
+
+    assert!(matches!(
+        set_clipboard_sync(&endpoint_id, true),
+        Err(CoreError::Other { .. })
+    ));
+    assert!(!clipboard_sync(&endpoint_id));
+
+    Ok(())
+}
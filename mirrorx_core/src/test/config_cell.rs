@@ -0,0 +1,17 @@
+use crate::utility::config_cell::ConfigCell;
+
+static VALUES: ConfigCell<Vec<u32>> = ConfigCell::new(|| vec![1]);
+
+#[test]
+fn test_config_cell_set_replaces_init() {
+    assert_eq!(VALUES.get(), vec![1]);
+
+    VALUES.set(vec![2, 3]);
+    assert_eq!(VALUES.get(), vec![2, 3]);
+
+    // a reader keeps the value it took
+    let taken = VALUES.get();
+    VALUES.set(Vec::new());
+    assert_eq!(taken, vec![2, 3]);
+    assert!(VALUES.get().is_empty());
+}
@@ -0,0 +1,120 @@
+use crate::{
+    api::endpoint::{
+        client::EndPointClient,
+        connect_attempt::{connect_attempts, ConnectAttempts},
+        id::EndPointID,
+        message::EndPointMessage,
+        packet::{deserialize_packet, frame_codec},
+        EndPointStream,
+    },
+    component::video_encoder::media_config::MediaConfig,
+    error::CoreError,
+};
+use futures::StreamExt;
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+use tokio::net::TcpListener;
+use tokio_util::codec::Framed;
+
+#[tokio::test]
+async fn test_connect_attempt_cancel() -> anyhow::Result<()> {
+    let attempts = ConnectAttempts::default();
+    assert!(!attempts.cancel("123456789"));
+
+    let attempt = attempts.begin("123456789");
+    assert!(attempts.is_pending("123456789"));
+
+    assert!(attempts.cancel("123456789"));
+    assert!(!attempts.is_pending("123456789"));
+
+    let result = attempt
+        .run(async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            Ok(())
+        })
+        .await;
+    assert!(matches!(result, Err(CoreError::Cancelled)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connect_attempt_replaced() -> anyhow::Result<()> {
+    let attempts = ConnectAttempts::default();
+
+    let first = attempts.begin("10.0.0.8");
+    let second = attempts.begin("10.0.0.8");
+
+    // connecting again gives up the attempt before
+    assert!(matches!(
+        first.run(std::future::pending::<Result<(), _>>()).await,
+        Err(CoreError::Cancelled)
+    ));
+
+    // the one before going away leaves the newer one cancellable
+    drop(first);
+    assert!(attempts.is_pending("10.0.0.8"));
+
+    drop(second);
+    assert!(!attempts.is_pending("10.0.0.8"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_cancel_mid_connect() -> anyhow::Result<()> {
+    // the remote side accepts but never answers, like a peer that hangs
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.11.1".parse::<IpAddr>()?,
+    };
+
+    let connect = tokio::spawn(async move {
+        let (video_frame_tx, _video_frame_rx) = tokio::sync::mpsc::channel(16);
+        let (audio_frame_tx, _audio_frame_rx) = tokio::sync::mpsc::channel(16);
+
+        let attempt = connect_attempts().begin("10.0.11.1");
+        attempt
+            .run(EndPointClient::new_desktop_active(
+                endpoint_id,
+                None,
+                EndPointStream::ActiveTCP(addr),
+                video_frame_tx,
+                audio_frame_tx,
+                None,
+                MediaConfig::default(),
+            ))
+            .await
+    });
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Framed::new(stream, frame_codec());
+
+    // waits for the capabilities of this side now
+    let capabilities = remote.next().await.unwrap()?;
+    assert!(matches!(
+        deserialize_packet(&capabilities)?,
+        EndPointMessage::Capabilities(_)
+    ));
+
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let cancelled_at = Instant::now();
+    assert!(connect_attempts().cancel("10.0.11.1"));
+
+    let result = tokio::time::timeout(Duration::from_secs(1), connect).await??;
+    assert!(matches!(result, Err(CoreError::Cancelled)));
+    assert!(cancelled_at.elapsed() < Duration::from_secs(1));
+    assert!(!connect_attempts().is_pending("10.0.11.1"));
+
+    // the half made connection is closed, not left to time out
+    let end = tokio::time::timeout(Duration::from_secs(1), remote.next()).await?;
+    assert!(end.is_none());
+
+    Ok(())
+}
@@ -0,0 +1,109 @@
+use crate::{
+    api::endpoint::{
+        connect_retry::{
+            connect_with_retry, ConnectRetryConfig, RetryPolicy, MAX_CONNECT_ATTEMPTS,
+        },
+        id::EndPointID,
+    },
+    error::CoreError,
+};
+use std::{net::IpAddr, time::Duration};
+use tokio::net::TcpListener;
+
+fn lan_id() -> EndPointID {
+    EndPointID::LANID {
+        local_ip: IpAddr::from([127, 0, 0, 1]),
+        remote_ip: IpAddr::from([10, 0, 12, 1]),
+    }
+}
+
+#[test]
+fn test_retry_delay_backs_off() {
+    let policy = RetryPolicy {
+        base_delay_ms: 100,
+        max_delay_ms: 1000,
+        attempts: 5,
+    };
+
+    assert_eq!(policy.delay(1, 0.0), Duration::from_millis(100));
+    assert_eq!(policy.delay(2, 0.0), Duration::from_millis(200));
+    assert_eq!(policy.delay(3, 0.0), Duration::from_millis(400));
+    assert_eq!(policy.delay(5, 0.0), Duration::from_millis(1000));
+    assert_eq!(policy.delay(100, 0.0), Duration::from_millis(1000));
+
+    // jitter takes off up to half
+    assert_eq!(policy.delay(2, 0.5), Duration::from_millis(150));
+    assert!(policy.delay(2, 0.999) > Duration::from_millis(100));
+}
+
+#[test]
+fn test_retry_policy_sanitized() {
+    let policy = RetryPolicy {
+        base_delay_ms: 500,
+        max_delay_ms: 100,
+        attempts: 0,
+    }
+    .sanitize();
+
+    assert_eq!(policy.max_delay_ms, 500);
+    assert_eq!(policy.attempts, 1);
+
+    let policy = RetryPolicy {
+        attempts: 1000,
+        ..RetryPolicy::INTERNET
+    };
+    assert_eq!(policy.sanitize().attempts, MAX_CONNECT_ATTEMPTS);
+
+    let config = ConnectRetryConfig::default();
+    assert_eq!(config.policy(&lan_id()), RetryPolicy::LAN);
+    assert_eq!(
+        config.policy(&EndPointID::DeviceID {
+            local_device_id: 1,
+            remote_device_id: 2,
+        }),
+        RetryPolicy::INTERNET
+    );
+}
+
+#[tokio::test]
+async fn test_connect_retries_until_listening() -> anyhow::Result<()> {
+    // a port nothing listens on for now
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+
+    let accept = tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let listener = TcpListener::bind(addr).await?;
+        listener.accept().await.map(|(stream, _)| stream)
+    });
+
+    let policy = RetryPolicy {
+        base_delay_ms: 100,
+        max_delay_ms: 200,
+        attempts: 8,
+    };
+
+    let (stream, _) = connect_with_retry(addr, &lan_id(), policy).await?;
+    assert_eq!(stream.peer_addr()?, addr);
+    accept.await??;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connect_attempts_exhausted() -> anyhow::Result<()> {
+    let addr = TcpListener::bind("127.0.0.1:0").await?.local_addr()?;
+
+    let policy = RetryPolicy {
+        base_delay_ms: 10,
+        max_delay_ms: 10,
+        attempts: 3,
+    };
+
+    let result = connect_with_retry(addr, &lan_id(), policy).await;
+    assert!(matches!(
+        result,
+        Err(CoreError::ConnectAttemptsExhausted { attempts: 3, .. })
+    ));
+
+    Ok(())
+}
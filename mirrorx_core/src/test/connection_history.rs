@@ -0,0 +1,102 @@
+use crate::{
+    api::config::{
+        entity::connection_history::{CONNECTION_HISTORY_LIMIT, NICKNAME_MAX_CHARS},
+        LocalStorage, Storage,
+    },
+    component::lan::discover::{merge_nicknames, Node},
+};
+
+#[test]
+fn test_connection_history_record() -> anyhow::Result<()> {
+    let storage = LocalStorage::memory()?;
+    let history = storage.connection_history();
+
+    history.record_at("1234567890", Some("mirrorx.cloud"), 100)?;
+    history.record_at("192.168.1.2", None, 200)?;
+    history.record_at("1234567890", Some("mirrorx.cloud"), 300)?;
+
+    let entries = history.query()?;
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].remote, "1234567890");
+    assert_eq!(entries[0].domain.as_deref(), Some("mirrorx.cloud"));
+    assert_eq!(entries[0].connect_count, 2);
+    assert_eq!(entries[0].last_connected, 300);
+    assert_eq!(entries[1].remote, "192.168.1.2");
+    assert_eq!(entries[1].domain, None);
+
+    // pinned devices stay on top
+    history.set_pinned("192.168.1.2", true)?;
+    assert_eq!(history.query()?[0].remote, "192.168.1.2");
+
+    history.delete("192.168.1.2")?;
+    assert_eq!(history.query()?.len(), 1);
+    assert!(history.set_pinned("192.168.1.2", true).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_connection_history_prunes_least_recent() -> anyhow::Result<()> {
+    let storage = LocalStorage::memory()?;
+    let history = storage.connection_history();
+
+    history.record_at("0", None, 0)?;
+    history.set_pinned("0", true)?;
+
+    for i in 1..=CONNECTION_HISTORY_LIMIT as i64 + 1 {
+        history.record_at(&i.to_string(), None, i)?;
+    }
+
+    let entries = history.query()?;
+    assert_eq!(entries.len(), CONNECTION_HISTORY_LIMIT as usize + 1);
+
+    // the oldest unpinned device went, the pinned one is older but stays
+    assert!(entries.iter().all(|entry| entry.remote != "1"));
+    assert_eq!(entries[0].remote, "0");
+
+    Ok(())
+}
+
+#[test]
+fn test_connection_history_nickname() -> anyhow::Result<()> {
+    let storage = LocalStorage::memory()?;
+    let history = storage.connection_history();
+
+    history.record_at("192.168.1.2", None, 100)?;
+    history.record_at("192.168.1.3", None, 100)?;
+
+    history.set_nickname("192.168.1.2", Some("  Office  "))?;
+    assert!(history
+        .set_nickname("192.168.1.3", Some(&"x".repeat(NICKNAME_MAX_CHARS + 1)))
+        .is_err());
+    assert!(history.set_nickname("192.168.1.4", Some("Home")).is_err());
+
+    let nicknames = history.nicknames()?;
+    assert_eq!(nicknames.len(), 1);
+    assert_eq!(nicknames["192.168.1.2"], "Office");
+
+    // LAN nodes show the nickname of known devices
+    let mut nodes = vec![lan_node("192.168.1.2"), lan_node("192.168.1.5")];
+    merge_nicknames(&mut nodes, &nicknames);
+    assert_eq!(nodes[0].nickname.as_deref(), Some("Office"));
+    assert_eq!(nodes[1].nickname, None);
+
+    // a blank nickname removes it
+    history.set_nickname("192.168.1.2", Some(" "))?;
+    assert!(history.nicknames()?.is_empty());
+
+    Ok(())
+}
+
+fn lan_node(addr: &str) -> Node {
+    Node {
+        host_name: String::from("host"),
+        device_name: None,
+        addr: addr.parse().unwrap(),
+        os: String::from("linux"),
+        os_version: String::from("6.0"),
+        nickname: None,
+        tag: None,
+        last_seen: 0,
+    }
+}
@@ -0,0 +1,103 @@
+use crate::component::{
+    frame::DesktopEncodeFrame,
+    video_encoder::{
+        content_hint::{
+            ContentHint, ContentMode, ContentTuning, CONTENT_MODE_SWITCH_INTERVAL,
+            TEXT_KEYFRAME_INTERVAL,
+        },
+        media_config::{MediaConfig, QualityPreset},
+    },
+};
+use std::time::Duration;
+
+const FRAME_INTERVAL: Duration = Duration::from_millis(33);
+
+/// A 64x64 frame, `moving` frames differ from their predecessor everywhere.
+fn frame(index: u32, moving: bool) -> DesktopEncodeFrame {
+    let luma = if moving { (index % 2 * 128) as u8 } else { 16 };
+
+    DesktopEncodeFrame {
+        capture_time: FRAME_INTERVAL * index,
+        width: 64,
+        height: 64,
+        luminance_bytes: vec![luma; 64 * 64],
+        luminance_stride: 64,
+        chrominance_bytes: vec![128; 64 * 32],
+        chrominance_stride: 64,
+        dirty_rects: None,
+    }
+}
+
+#[test]
+fn test_content_mode_tune() {
+    let config = MediaConfig::from_preset(QualityPreset::High);
+
+    let text = ContentMode::Text.tune(&config);
+    assert_eq!(text.keyframe_interval, TEXT_KEYFRAME_INTERVAL);
+    assert_eq!(text.x264_tune, "zerolatency");
+    assert_eq!(text.bitrate_kbps, config.bitrate_kbps);
+
+    let motion = ContentMode::Motion.tune(&config);
+    assert_eq!(motion.keyframe_interval, config.keyframe_interval);
+    assert_eq!(motion.x264_tune, "film,zerolatency");
+
+    // configs stored before the hint existed encode as they did
+    let stored = r#"{"preset":"low","frame_rate":20,"scale_percent":50,"bitrate_kbps":1000,"keyframe_interval":240}"#;
+    let config: MediaConfig = serde_json::from_str(stored).unwrap();
+    assert_eq!(config.content_hint, ContentHint::Off);
+}
+
+#[test]
+fn test_content_hint_switches_at_once() {
+    let config = MediaConfig::default();
+    let mut tuning = ContentTuning::default();
+
+    assert!(!tuning.update(ContentHint::Off, &frame(0, false)));
+    assert_eq!(tuning.mode(), None);
+    assert_eq!(tuning.tune(&config), config);
+
+    assert!(tuning.update(ContentHint::Motion, &frame(1, false)));
+    assert_eq!(tuning.mode(), Some(ContentMode::Motion));
+    assert!(!tuning.update(ContentHint::Motion, &frame(2, false)));
+
+    // a hint of the user doesn't wait for the switch interval
+    assert!(tuning.update(ContentHint::Text, &frame(3, true)));
+    assert_eq!(tuning.mode(), Some(ContentMode::Text));
+    assert_eq!(tuning.tune(&config), ContentMode::Text.tune(&config));
+
+    assert!(tuning.update(ContentHint::Off, &frame(4, true)));
+    assert_eq!(tuning.mode(), None);
+}
+
+#[test]
+fn test_content_hint_auto_follows_motion() {
+    let mut tuning = ContentTuning::default();
+    let mut index = 0;
+    let mut run = |tuning: &mut ContentTuning, moving: bool, duration: Duration| {
+        let mut switches = Vec::new();
+        for _ in 0..duration.as_millis() / FRAME_INTERVAL.as_millis() {
+            if tuning.update(ContentHint::Auto, &frame(index, moving)) {
+                switches.push((FRAME_INTERVAL * index, tuning.mode()));
+            }
+            index += 1;
+        }
+        switches
+    };
+
+    // detection starts out on text, and every switch holds for a while
+    let switches = run(&mut tuning, true, Duration::from_secs(5));
+    assert_eq!(switches.len(), 2);
+    assert_eq!(switches[0], (Duration::ZERO, Some(ContentMode::Text)));
+    assert_eq!(switches[1].1, Some(ContentMode::Motion));
+    assert!(switches[1].0 >= CONTENT_MODE_SWITCH_INTERVAL);
+    assert!(switches[1].0 < CONTENT_MODE_SWITCH_INTERVAL + FRAME_INTERVAL * 2);
+
+    // once the motion ends it goes back to text
+    let switches = run(&mut tuning, false, Duration::from_secs(5));
+    assert_eq!(switches.len(), 1);
+    assert_eq!(switches[0].1, Some(ContentMode::Text));
+
+    // and stays there while nothing moves
+    let switches = run(&mut tuning, false, Duration::from_secs(5));
+    assert!(switches.is_empty());
+}
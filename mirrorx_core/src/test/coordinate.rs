@@ -0,0 +1,100 @@
+use crate::component::input::coordinate::{to_virtual_desktop, CaptureArea, ViewMapping, ViewMode};
+
+#[test]
+fn test_scaled_mapping() {
+    // a 960x540 frame of a 1920x1080 monitor shown in a 1920x1200 window is
+    // scaled to 1920x1080 and centered vertically
+    let mapping = ViewMapping::scale_to_fit(1920.0, 1200.0, 960.0, 540.0);
+    assert_eq!(mapping.mode(), ViewMode::ScaleToFit);
+
+    assert_eq!(
+        mapping.normalize(960.0, 600.0, 960.0, 540.0),
+        Some((0.5, 0.5))
+    );
+    assert_eq!(mapping.normalize(0.0, 60.0, 960.0, 540.0), Some((0.0, 0.0)));
+    assert_eq!(mapping.normalize(960.0, 59.0, 960.0, 540.0), None);
+
+    let monitor = CaptureArea {
+        left: 0.0,
+        top: 0.0,
+        width: 1920.0,
+        height: 1080.0,
+    };
+
+    assert_eq!(monitor.absolute_position(0.5, 0.5), (960.0, 540.0));
+    assert_eq!(monitor.absolute_position(1.0, 1.0), (1919.0, 1079.0));
+}
+
+#[test]
+fn test_native_mapping() {
+    let mapping = ViewMapping::native(100.0, 50.0);
+    assert_eq!(mapping.mode(), ViewMode::Native);
+
+    assert_eq!(
+        mapping.normalize(380.0, 220.0, 960.0, 540.0),
+        Some((0.5, 0.5))
+    );
+    assert_eq!(mapping.normalize(900.0, 0.0, 960.0, 540.0), None);
+}
+
+#[test]
+fn test_cropped_mapping() {
+    let monitor = CaptureArea {
+        left: 0.0,
+        top: 0.0,
+        width: 1920.0,
+        height: 1080.0,
+    };
+
+    let area = monitor.crop(100.0, 200.0, 800.0, 600.0);
+    assert_eq!(area.absolute_position(0.0, 0.0), (100.0, 200.0));
+    assert_eq!(area.absolute_position(0.5, 0.5), (500.0, 500.0));
+    assert_eq!(area.absolute_position(1.0, 1.0), (899.0, 799.0));
+    assert_eq!(area.absolute_position(-1.0, 2.0), (100.0, 799.0));
+
+    // and back onto the monitor
+    assert_eq!(monitor.relative_position(480.0, 540.0), (0.25, 0.5));
+    assert_eq!(monitor.relative_position(-10.0, 2000.0), (0.0, 1.0));
+
+    // a region past the monitor is clamped into it
+    let area = monitor.crop(1800.0, 1000.0, 800.0, 600.0);
+    assert_eq!(area.width, 120.0);
+    assert_eq!(area.height, 80.0);
+    assert_eq!(area.absolute_position(1.0, 1.0), (1919.0, 1079.0));
+}
+
+#[test]
+fn test_multi_monitor_mapping() {
+    // a 1280x1024 monitor on the left of a 1920x1080 primary monitor
+    let virtual_screen = CaptureArea {
+        left: -1280.0,
+        top: 0.0,
+        width: 3200.0,
+        height: 1080.0,
+    };
+
+    let left_monitor = CaptureArea {
+        left: -1280.0,
+        top: 0.0,
+        width: 1280.0,
+        height: 1024.0,
+    };
+
+    let (x, y) = left_monitor.absolute_position(0.0, 0.0);
+    assert_eq!((x, y), (-1280.0, 0.0));
+    assert_eq!(to_virtual_desktop(x, y, &virtual_screen), (0, 0));
+
+    let (x, y) = left_monitor.absolute_position(1.0, 1.0);
+    assert_eq!((x, y), (-1.0, 1023.0));
+    assert_eq!(to_virtual_desktop(x, y, &virtual_screen), (26202, 62134));
+
+    let primary_monitor = CaptureArea {
+        left: 0.0,
+        top: 0.0,
+        width: 1920.0,
+        height: 1080.0,
+    };
+
+    let (x, y) = primary_monitor.absolute_position(1.0, 1.0);
+    assert_eq!(to_virtual_desktop(x, y, &virtual_screen), (65535, 65535));
+}
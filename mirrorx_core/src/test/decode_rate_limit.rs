@@ -0,0 +1,140 @@
+use crate::{
+    api::endpoint::{
+        handlers::video_frame::{skip_to_latest_keyframe, VideoDecodeMessage},
+        message::{EndPointVideoFrame, VideoCodec},
+    },
+    component::video_decoder::rate_limit::{
+        is_h264_disposable, is_hevc_disposable, DecodeDecision, DecodeRateLimiter,
+    },
+};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// nal unit header bytes: 0x65 idr slice, 0x41 reference slice, 0x01 non-reference slice
+const KEY_FRAME: &[u8] = &[0, 0, 0, 1, 0x67, 0x42, 0, 0, 1, 0x65, 0x88];
+const DELTA_FRAME: &[u8] = &[0, 0, 0, 1, 0x41, 0x9A, 0x00];
+const DISPOSABLE_FRAME: &[u8] = &[0, 0, 0, 1, 0x01, 0x9A, 0x00];
+
+// hevc nal unit header bytes: 0x26 idr slice, 0x02 trailing reference slice,
+// 0x00 trailing non-reference slice
+const HEVC_KEY_FRAME: &[u8] = &[0, 0, 0, 1, 0x26, 0x01, 0xAF];
+const HEVC_DELTA_FRAME: &[u8] = &[0, 0, 0, 1, 0x02, 0x01, 0xD0];
+const HEVC_DISPOSABLE_FRAME: &[u8] = &[0, 0, 0, 1, 0x00, 0x01, 0xD0];
+
+fn frame(seq: u64, buffer: &[u8]) -> VideoDecodeMessage {
+    VideoDecodeMessage::Frame(EndPointVideoFrame {
+        seq,
+        width: 1920,
+        height: 1080,
+        pts: seq as i64,
+        buffer: buffer.to_vec(),
+    })
+}
+
+fn frame_seqs(pending: &VecDeque<VideoDecodeMessage>) -> Vec<Option<u64>> {
+    pending
+        .iter()
+        .map(|message| match message {
+            VideoDecodeMessage::Frame(video_frame) => Some(video_frame.seq),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_is_h264_disposable() {
+    assert!(is_h264_disposable(DISPOSABLE_FRAME));
+    assert!(!is_h264_disposable(DELTA_FRAME));
+    assert!(!is_h264_disposable(KEY_FRAME));
+    assert!(!is_h264_disposable(&[0, 0, 1, 0x06, 0x05]));
+    assert!(!is_h264_disposable(&[]));
+}
+
+#[test]
+fn test_decode_rate_limiter() {
+    let mut limiter = DecodeRateLimiter::new(30, VideoCodec::H264);
+    let now = Instant::now();
+    let frame_interval = Duration::from_secs(1) / 60;
+
+    assert_eq!(limiter.admit(DELTA_FRAME, now), DecodeDecision::Present);
+
+    // a 60fps stream renders every other frame
+    let next = now + frame_interval;
+    assert_eq!(limiter.admit(DELTA_FRAME, next), DecodeDecision::DecodeOnly);
+    assert_eq!(limiter.admit(DISPOSABLE_FRAME, next), DecodeDecision::Drop);
+    assert_eq!(limiter.admit(KEY_FRAME, next), DecodeDecision::Present);
+
+    let next = next + Duration::from_secs(1) / 30;
+    assert_eq!(limiter.admit(DELTA_FRAME, next), DecodeDecision::Present);
+
+    let mut limiter = DecodeRateLimiter::new(0, VideoCodec::H264);
+    assert_eq!(
+        limiter.admit(DISPOSABLE_FRAME, now),
+        DecodeDecision::Present
+    );
+    assert_eq!(
+        limiter.admit(DISPOSABLE_FRAME, now),
+        DecodeDecision::Present
+    );
+}
+
+#[test]
+fn test_skip_to_latest_keyframe() {
+    let mut pending = VecDeque::from(vec![
+        frame(1, DELTA_FRAME),
+        frame(2, KEY_FRAME),
+        VideoDecodeMessage::FramesLost,
+        frame(3, DELTA_FRAME),
+        frame(4, KEY_FRAME),
+        frame(5, DELTA_FRAME),
+    ]);
+
+    assert_eq!(skip_to_latest_keyframe(&mut pending, &VideoCodec::H264), 3);
+    assert_eq!(frame_seqs(&pending), vec![None, Some(4), Some(5)]);
+
+    // without a keyframe to resume from every frame is needed
+    let mut pending = VecDeque::from(vec![frame(6, DELTA_FRAME), frame(7, DELTA_FRAME)]);
+    assert_eq!(skip_to_latest_keyframe(&mut pending, &VideoCodec::H264), 0);
+    assert_eq!(pending.len(), 2);
+}
+
+#[test]
+fn test_is_hevc_disposable() {
+    assert!(is_hevc_disposable(HEVC_DISPOSABLE_FRAME));
+    assert!(!is_hevc_disposable(HEVC_DELTA_FRAME));
+    assert!(!is_hevc_disposable(HEVC_KEY_FRAME));
+    // a prefix sei alone carries no picture
+    assert!(!is_hevc_disposable(&[0, 0, 1, 0x4E, 0x01]));
+    assert!(!is_hevc_disposable(&[]));
+}
+
+#[test]
+fn test_hevc_decode_rate_limiter() {
+    let mut limiter = DecodeRateLimiter::new(30, VideoCodec::Hevc);
+    let now = Instant::now();
+    let next = now + Duration::from_secs(1) / 60;
+
+    assert_eq!(
+        limiter.admit(HEVC_DELTA_FRAME, now),
+        DecodeDecision::Present
+    );
+    assert_eq!(
+        limiter.admit(HEVC_DELTA_FRAME, next),
+        DecodeDecision::DecodeOnly
+    );
+    assert_eq!(
+        limiter.admit(HEVC_DISPOSABLE_FRAME, next),
+        DecodeDecision::Drop
+    );
+    assert_eq!(limiter.admit(HEVC_KEY_FRAME, next), DecodeDecision::Present);
+
+    let mut pending = VecDeque::from(vec![
+        frame(1, HEVC_KEY_FRAME),
+        frame(2, HEVC_DELTA_FRAME),
+        frame(3, HEVC_KEY_FRAME),
+    ]);
+    assert_eq!(skip_to_latest_keyframe(&mut pending, &VideoCodec::Hevc), 2);
+    assert_eq!(frame_seqs(&pending), vec![Some(3)]);
+}
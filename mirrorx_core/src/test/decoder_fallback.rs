@@ -0,0 +1,114 @@
+use crate::component::video_decoder::{
+    fallback::{DecoderKind, DecoderSelection, MAX_HARDWARE_DECODE_FAILURES},
+    rate_limit::DecodeStats,
+};
+use std::time::Duration;
+
+#[test]
+fn test_decoder_selection_falls_back() {
+    let mut selection = DecoderSelection::new(true);
+    assert_eq!(selection.next(), DecoderKind::Hardware);
+
+    // the stream goes on in software, the next one tries hardware again
+    assert!(!selection.hardware_failed());
+    assert_eq!(selection.next(), DecoderKind::Software);
+
+    selection.reset();
+    assert_eq!(selection.next(), DecoderKind::Hardware);
+
+    for _ in 1..MAX_HARDWARE_DECODE_FAILURES - 1 {
+        assert!(!selection.hardware_failed());
+        selection.reset();
+    }
+
+    // failed too often, the session sticks with software
+    assert!(selection.hardware_failed());
+    selection.reset();
+    assert_eq!(selection.next(), DecoderKind::Software);
+    assert_eq!(selection.hardware_failures(), MAX_HARDWARE_DECODE_FAILURES);
+
+    assert_eq!(DecoderSelection::new(false).next(), DecoderKind::Software);
+}
+
+#[tokio::test]
+async fn test_decode_stats_track_decoder() {
+    let stats = DecodeStats::default();
+    assert_eq!(stats.snapshot().decoder, None);
+
+    stats.set_decoder(DecoderKind::Hardware);
+    assert_eq!(stats.snapshot().decoder, Some(DecoderKind::Hardware));
+
+    stats.record_hardware_fallback();
+    stats.set_decoder(DecoderKind::Software);
+    stats.request_keyframe();
+    stats.request_keyframe();
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.decoder, Some(DecoderKind::Software));
+    assert_eq!(snapshot.hardware_fallbacks, 1);
+
+    // requests made before anyone waited are folded into one
+    tokio::time::timeout(Duration::from_secs(1), stats.keyframe_requested())
+        .await
+        .unwrap();
+    assert!(
+        tokio::time::timeout(Duration::from_millis(50), stats.keyframe_requested())
+            .await
+            .is_err()
+    );
+}
+
+// there's no VideoToolbox or D3D11VA device here, opening the hardware
+// decoder fails like it would mid-stream
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+#[tokio::test]
+async fn test_video_decoder_hardware_failure() {
+    use crate::{
+        api::endpoint::{id::EndPointID, message::EndPointVideoFrame},
+        component::video_decoder::{
+            fallback::subscribe_decoder_fallback, thumbnail::Thumbnailer,
+            video_decoder::VideoDecoder,
+        },
+    };
+    use std::sync::Arc;
+
+    let endpoint_id = EndPointID::DeviceID {
+        local_device_id: 176,
+        remote_device_id: 177,
+    };
+    let stats = Arc::new(DecodeStats::default());
+    let (render_tx, _render_rx) = tokio::sync::mpsc::channel(1);
+    let mut fallbacks = subscribe_decoder_fallback();
+
+    let mut decoder = VideoDecoder::new(
+        endpoint_id,
+        render_tx,
+        true,
+        0,
+        stats.clone(),
+        Thumbnailer::new(endpoint_id),
+    );
+
+    // a delta frame, the software decoder waits for a keyframe
+    decoder
+        .decode(EndPointVideoFrame {
+            seq: 0,
+            width: 64,
+            height: 64,
+            pts: 0,
+            buffer: vec![0, 0, 0, 1, 0x41, 0x9A, 0x00],
+        })
+        .unwrap();
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.decoder, Some(DecoderKind::Software));
+    assert_eq!(snapshot.hardware_fallbacks, 1);
+
+    tokio::time::timeout(Duration::from_secs(1), stats.keyframe_requested())
+        .await
+        .unwrap();
+
+    let fallback = fallbacks.recv().await.unwrap();
+    assert_eq!(fallback.endpoint_id, endpoint_id.to_string());
+    assert!(!fallback.permanent);
+}
@@ -0,0 +1,72 @@
+use super::{connect, next_message};
+use crate::{
+    api::endpoint::{
+        message::{EndPointInput, EndPointMessage, InputEvent, MouseEvent},
+        sessions::DesktopSessions,
+    },
+    component::input::key::MouseKey,
+};
+
+fn mouse_move(x: f32) -> EndPointInput {
+    EndPointInput {
+        events: vec![InputEvent::Mouse(MouseEvent::Move(MouseKey::None, x, 0.0))],
+    }
+}
+
+#[tokio::test]
+async fn test_input_goes_to_focused_session_only() -> anyhow::Result<()> {
+    let (first, mut first_remote) = connect("10.0.0.1").await?;
+    let (second, mut second_remote) = connect("10.0.0.2").await?;
+
+    let sessions = DesktopSessions::new(2);
+    sessions.register(first.clone())?;
+    sessions.register(second.clone())?;
+    assert_eq!(sessions.len(), 2);
+
+    // the latest session gets the focus
+    assert_eq!(sessions.focused(), Some(second.endpoint_id()));
+    assert!(!sessions.send_input(first.endpoint_id(), mouse_move(1.0))?);
+    assert!(sessions.send_input(second.endpoint_id(), mouse_move(2.0))?);
+
+    assert_eq!(
+        next_message(&mut second_remote).await,
+        Some(EndPointMessage::InputCommand(mouse_move(2.0)))
+    );
+    assert_eq!(next_message(&mut first_remote).await, None);
+
+    sessions.focus(first.endpoint_id())?;
+    assert!(sessions.send_input(first.endpoint_id(), mouse_move(3.0))?);
+    assert!(!sessions.send_input(second.endpoint_id(), mouse_move(4.0))?);
+
+    assert_eq!(
+        next_message(&mut first_remote).await,
+        Some(EndPointMessage::InputCommand(mouse_move(3.0)))
+    );
+    assert_eq!(next_message(&mut second_remote).await, None);
+
+    first.close();
+    second.close();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_desktop_sessions_capacity() -> anyhow::Result<()> {
+    let (first, _first_remote) = connect("10.0.0.1").await?;
+    let (second, _second_remote) = connect("10.0.0.2").await?;
+
+    let sessions = DesktopSessions::new(1);
+    sessions.register(first.clone())?;
+    assert!(sessions.ensure_capacity().is_err());
+    assert!(sessions.register(second.clone()).is_err());
+
+    sessions.unregister(&first.endpoint_id());
+    assert_eq!(sessions.focused(), None);
+    assert!(sessions.focus(first.endpoint_id()).is_err());
+    sessions.register(second.clone())?;
+
+    first.close();
+    second.close();
+
+    Ok(())
+}
@@ -0,0 +1,195 @@
+use crate::{
+    api::signaling::{
+        identity::{active_transcript, DeviceIdentity, MutualAuthConfig, TrustedDevice},
+        key_exchange::{key_agreement_with_identity, ActiveKeyExchange, KeyAgreementError},
+    },
+    error::CoreError,
+};
+use rand::{rngs::StdRng, SeedableRng};
+
+// PKCS#1 v1.5 fits 245 bytes into a 2048 bit key, a signed reply takes
+// about 190, 1024 bits are too small for it
+const TEST_REPLY_KEY_BITS: usize = 2048;
+
+fn identity() -> DeviceIdentity {
+    DeviceIdentity::from_pkcs8(&DeviceIdentity::generate().unwrap()).unwrap()
+}
+
+fn trusting(identity: &DeviceIdentity) -> MutualAuthConfig {
+    MutualAuthConfig {
+        enabled: true,
+        trusted_devices: vec![TrustedDevice {
+            name: String::from("peer"),
+            public_key: base64::encode(identity.public_key()),
+        }],
+    }
+}
+
+fn active(
+    rng: &mut StdRng,
+    identity: Option<&DeviceIdentity>,
+    config: &MutualAuthConfig,
+) -> ActiveKeyExchange {
+    let reply_private_key = rsa::RsaPrivateKey::new(rng, TEST_REPLY_KEY_BITS).unwrap();
+    ActiveKeyExchange::with_identity(rng, reply_private_key, 1, "password", identity, config)
+        .unwrap()
+}
+
+fn agree(
+    rng: &mut StdRng,
+    active: &ActiveKeyExchange,
+    identity: Option<&DeviceIdentity>,
+    config: &MutualAuthConfig,
+) -> Result<Vec<u8>, KeyAgreementError> {
+    key_agreement_with_identity(
+        rng,
+        "password",
+        1,
+        &active.password_salt,
+        active.secret.clone(),
+        &active.secret_nonce,
+        identity,
+        config,
+    )
+    .map(|(reply, _, _)| reply)
+}
+
+#[test]
+fn test_mutual_auth_round_trip() {
+    let mut rng = StdRng::seed_from_u64(166);
+    let active_identity = identity();
+    let passive_identity = identity();
+
+    let active = active(
+        &mut rng,
+        Some(&active_identity),
+        &trusting(&passive_identity),
+    );
+    let reply = agree(
+        &mut rng,
+        &active,
+        Some(&passive_identity),
+        &trusting(&active_identity),
+    )
+    .unwrap();
+
+    assert!(active.open_reply(&reply).is_ok());
+}
+
+#[test]
+fn test_mutual_auth_untrusted_device() {
+    let mut rng = StdRng::seed_from_u64(166);
+    let active_identity = identity();
+    let passive_identity = identity();
+    let stranger = identity();
+
+    // the passive device doesn't know the active one
+    let exchange = active(
+        &mut rng,
+        Some(&active_identity),
+        &trusting(&passive_identity),
+    );
+    let err = agree(
+        &mut rng,
+        &exchange,
+        Some(&passive_identity),
+        &trusting(&stranger),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        KeyAgreementError::Identity(CoreError::VisitDeviceUntrusted)
+    ));
+
+    // the active device doesn't know the passive one
+    let exchange = active(&mut rng, Some(&active_identity), &trusting(&stranger));
+    let reply = agree(
+        &mut rng,
+        &exchange,
+        Some(&passive_identity),
+        &trusting(&active_identity),
+    )
+    .unwrap();
+    assert!(matches!(
+        exchange.open_reply(&reply),
+        Err(CoreError::VisitDeviceUntrusted)
+    ));
+}
+
+#[test]
+fn test_mutual_auth_unsigned_peer() {
+    let mut rng = StdRng::seed_from_u64(166);
+    let active_identity = identity();
+    let passive_identity = identity();
+
+    // an active device without mutual authentication sends no proof
+    let exchange = active(&mut rng, None, &MutualAuthConfig::default());
+    let err = agree(
+        &mut rng,
+        &exchange,
+        Some(&passive_identity),
+        &trusting(&active_identity),
+    )
+    .unwrap_err();
+    assert!(matches!(
+        err,
+        KeyAgreementError::Identity(CoreError::VisitDeviceUntrusted)
+    ));
+
+    // a passive device without an identity replies unsigned
+    let exchange = active(
+        &mut rng,
+        Some(&active_identity),
+        &trusting(&passive_identity),
+    );
+    let reply = agree(&mut rng, &exchange, None, &MutualAuthConfig::default()).unwrap();
+    assert!(matches!(
+        exchange.open_reply(&reply),
+        Err(CoreError::VisitDeviceUntrusted)
+    ));
+}
+
+#[test]
+fn test_mutual_auth_invalid_signature() {
+    let identity = identity();
+    let config = trusting(&identity);
+
+    let transcript = active_transcript(1, &[1; 32], &[2; 32], &[3; 12]);
+    let mut proof = identity.prove(&transcript);
+    assert!(config.verify(&proof, &transcript).is_ok());
+
+    // signed for another device id
+    let other_transcript = active_transcript(2, &[1; 32], &[2; 32], &[3; 12]);
+    assert!(matches!(
+        config.verify(&proof, &other_transcript),
+        Err(CoreError::VisitSignatureInvalid)
+    ));
+
+    proof.signature[0] ^= 1;
+    assert!(matches!(
+        config.verify(&proof, &transcript),
+        Err(CoreError::VisitSignatureInvalid)
+    ));
+}
+
+#[test]
+fn test_mutual_auth_config_sanitize() {
+    let identity = identity();
+    let config = MutualAuthConfig {
+        enabled: true,
+        trusted_devices: vec![
+            TrustedDevice {
+                name: String::from("valid"),
+                public_key: format!(" {} ", base64::encode(identity.public_key())),
+            },
+            TrustedDevice {
+                name: String::from("invalid"),
+                public_key: String::from("not a key"),
+            },
+        ],
+    }
+    .sanitize();
+
+    assert_eq!(config.trusted_devices.len(), 1);
+    assert!(config.trusts(identity.public_key()));
+}
@@ -0,0 +1,85 @@
+use crate::{
+    api::config::device_name::{sanitize_device_name, validate_device_name, MAX_DEVICE_NAME_CHARS},
+    component::lan::discover::{deserialize_broadcast_packet, BroadcastPacket, TargetLivePacket},
+};
+use serde::Serialize;
+
+#[test]
+fn test_validate_device_name() {
+    assert_eq!(
+        validate_device_name("  Office PC ").unwrap(),
+        String::from("Office PC")
+    );
+
+    // limited by characters, not bytes
+    let unicode_name = "会议室电脑".repeat(MAX_DEVICE_NAME_CHARS / 5);
+    assert!(unicode_name.len() > MAX_DEVICE_NAME_CHARS);
+    assert_eq!(validate_device_name(&unicode_name).unwrap(), unicode_name);
+
+    assert!(validate_device_name(&"a".repeat(MAX_DEVICE_NAME_CHARS + 1)).is_err());
+    assert!(validate_device_name("   ").is_err());
+    assert!(validate_device_name("Office\nPC").is_err());
+}
+
+#[test]
+fn test_sanitize_device_name() {
+    assert_eq!(
+        sanitize_device_name("Office\u{7}\nPC"),
+        Some(String::from("OfficePC"))
+    );
+    assert_eq!(
+        sanitize_device_name(&"é".repeat(MAX_DEVICE_NAME_CHARS * 2))
+            .map(|name| name.chars().count()),
+        Some(MAX_DEVICE_NAME_CHARS)
+    );
+    assert_eq!(sanitize_device_name(" \t\r\n"), None);
+}
+
+/// `BroadcastPacket` of the versions before the device name.
+#[derive(Serialize)]
+enum LegacyBroadcastPacket {
+    TargetLive {
+        host_name: String,
+        os: String,
+        os_version: String,
+    },
+}
+
+#[test]
+fn test_broadcast_packet_with_device_name() -> anyhow::Result<()> {
+    let buffer = bincode::serialize(&BroadcastPacket::TargetLive(TargetLivePacket {
+        host_name: String::from("desktop-1234"),
+        os: String::from("Linux"),
+        os_version: String::from("6.0"),
+        device_name: String::from("Office PC"),
+        tag: String::new(),
+    }))?;
+
+    let BroadcastPacket::TargetLive(live_packet) = deserialize_broadcast_packet(&buffer)? else {
+        panic!("live packet expected");
+    };
+    assert_eq!(live_packet.host_name, "desktop-1234");
+    assert_eq!(live_packet.device_name, "Office PC");
+
+    // older devices broadcast without a name
+    let buffer = bincode::serialize(&LegacyBroadcastPacket::TargetLive {
+        host_name: String::from("desktop-5678"),
+        os: String::from("Windows"),
+        os_version: String::from("10"),
+    })?;
+
+    let BroadcastPacket::TargetLive(live_packet) = deserialize_broadcast_packet(&buffer)? else {
+        panic!("live packet expected");
+    };
+    assert_eq!(live_packet.host_name, "desktop-5678");
+    assert_eq!(live_packet.os, "Windows");
+    assert_eq!(live_packet.device_name, "");
+
+    let buffer = bincode::serialize(&BroadcastPacket::TargetDead)?;
+    assert!(matches!(
+        deserialize_broadcast_packet(&buffer)?,
+        BroadcastPacket::TargetDead
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+use crate::{
+    api::diagnose::{
+        begin_diagnosis, cancel_diagnosis, Diagnosis, DiagnosticCheck, DiagnosticStatus,
+    },
+    core_error,
+    error::CoreError,
+};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+#[tokio::test]
+async fn test_diagnosis_skips_dependent_checks() {
+    let mut diagnosis = Diagnosis::new("1234", CancellationToken::new(), Duration::from_secs(5));
+
+    assert!(
+        !diagnosis
+            .run(DiagnosticCheck::SignalingReachable, &[], async {
+                Err(CoreError::VisitServerBusy)
+            })
+            .await
+    );
+    assert!(
+        !diagnosis
+            .run(
+                DiagnosticCheck::DeviceRegistered,
+                &[DiagnosticCheck::SignalingReachable],
+                async { Ok(String::from("subscribed")) },
+            )
+            .await
+    );
+    assert!(
+        diagnosis
+            .run(DiagnosticCheck::PlatformPermissions, &[], async {
+                Ok(String::from("granted"))
+            })
+            .await
+    );
+
+    let report = diagnosis.finish();
+    let statuses: Vec<_> = report.steps.iter().map(|step| step.status).collect();
+
+    assert_eq!(
+        statuses,
+        vec![
+            DiagnosticStatus::Failed,
+            DiagnosticStatus::Skipped,
+            DiagnosticStatus::Passed
+        ]
+    );
+    assert!(report.steps[0].hint.is_some());
+    assert!(report.steps[1].hint.is_none());
+    assert!(!report.passed);
+}
+
+#[tokio::test]
+async fn test_diagnosis_bounded_in_time() {
+    let mut diagnosis = Diagnosis::new(
+        "192.168.1.2",
+        CancellationToken::new(),
+        Duration::from_millis(50),
+    );
+
+    diagnosis
+        .run(DiagnosticCheck::LanServerListening, &[], async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(String::new())
+        })
+        .await;
+    diagnosis
+        .run(DiagnosticCheck::TargetReachable, &[], async {
+            Ok(String::new())
+        })
+        .await;
+
+    let report = diagnosis.finish();
+
+    assert!(report.timed_out);
+    assert!(!report.passed);
+    assert_eq!(report.steps[0].status, DiagnosticStatus::Failed);
+    assert_eq!(report.steps[0].detail, CoreError::Timeout.to_string());
+    assert_eq!(report.steps[1].status, DiagnosticStatus::Skipped);
+}
+
+#[tokio::test]
+async fn test_diagnosis_cancel() {
+    let running = begin_diagnosis();
+    let mut diagnosis = Diagnosis::new("1234", running.token(), Duration::from_secs(5));
+
+    diagnosis
+        .run(DiagnosticCheck::SignalingReachable, &[], async {
+            assert!(cancel_diagnosis());
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Err(core_error!("unreachable"))
+        })
+        .await;
+    diagnosis
+        .run(DiagnosticCheck::PlatformPermissions, &[], async {
+            Ok(String::new())
+        })
+        .await;
+
+    let report = diagnosis.finish();
+
+    assert!(report.cancelled);
+    assert!(!report.passed);
+    assert!(report
+        .steps
+        .iter()
+        .all(|step| step.status == DiagnosticStatus::Skipped));
+
+    drop(running);
+    assert!(!cancel_diagnosis());
+}
+
+#[test]
+fn test_diagnosis_passes_with_skipped_checks() {
+    let mut diagnosis = Diagnosis::new("1234", CancellationToken::new(), Duration::from_secs(5));
+    diagnosis.skip(DiagnosticCheck::TargetReachable, "reached with a visit");
+
+    assert!(diagnosis.finish().passed);
+}
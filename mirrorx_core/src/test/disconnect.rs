@@ -0,0 +1,108 @@
+use super::{connect, next_message};
+use crate::api::endpoint::{
+    disconnect::is_disconnect,
+    message::{DisconnectReason, EndPointMessage, EndPointVideoFrame},
+    packet::{deserialize_packet, serialize_packet},
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use std::time::Duration;
+
+#[test]
+fn test_is_disconnect() -> anyhow::Result<()> {
+    for reason in [
+        DisconnectReason::SessionClosed,
+        DisconnectReason::AppQuit,
+        DisconnectReason::IdleTimeout,
+        DisconnectReason::Other,
+    ] {
+        let packet = serialize_packet(&EndPointMessage::Disconnect { reason })?;
+        assert!(is_disconnect(&packet));
+    }
+
+    let keep_alive = serialize_packet(&EndPointMessage::KeepAlive)?;
+    let video_frame = serialize_packet(&EndPointMessage::VideoFrame(EndPointVideoFrame {
+        seq: 0,
+        width: 1920,
+        height: 1080,
+        pts: 0,
+        buffer: vec![0; 64],
+    }))?;
+    assert!(!is_disconnect(&keep_alive));
+    assert!(!is_disconnect(&video_frame));
+    assert!(!is_disconnect(&[]));
+
+    Ok(())
+}
+
+#[test]
+fn test_unknown_disconnect_reason_is_other() -> anyhow::Result<()> {
+    // a reason added by a newer version, its variant index is past ours
+    let mut packet = serialize_packet(&EndPointMessage::Disconnect {
+        reason: DisconnectReason::SessionClosed,
+    })?;
+    if let Some(reason) = packet.last_mut() {
+        *reason = 200;
+    }
+
+    assert_eq!(
+        deserialize_packet(&packet)?,
+        EndPointMessage::Disconnect {
+            reason: DisconnectReason::Other
+        }
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_close_with_sends_disconnect_before_closing() -> anyhow::Result<()> {
+    let (client, mut remote) = connect("10.0.11.1").await?;
+
+    client.close_with(DisconnectReason::SessionClosed);
+    assert!(client.is_closed());
+
+    assert_eq!(
+        next_message(&mut remote).await,
+        Some(EndPointMessage::Disconnect {
+            reason: DisconnectReason::SessionClosed
+        })
+    );
+
+    let end = tokio::time::timeout(Duration::from_secs(1), remote.next()).await?;
+    assert!(end.is_none());
+
+    // closing again doesn't queue another one
+    client.close_with(DisconnectReason::SessionClosed);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_received_disconnect_closes_with_reason() -> anyhow::Result<()> {
+    let (client, mut remote) = connect("10.0.11.2").await?;
+    assert_eq!(client.remote_disconnect(), None);
+
+    let packet = serialize_packet(&EndPointMessage::Disconnect {
+        reason: DisconnectReason::AppQuit,
+    })?;
+    remote.send(Bytes::from(packet)).await?;
+    drop(remote);
+
+    tokio::time::timeout(Duration::from_secs(1), client.closed()).await?;
+    assert_eq!(client.remote_disconnect(), Some(DisconnectReason::AppQuit));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_crashed_remote_leaves_no_reason() -> anyhow::Result<()> {
+    let (client, remote) = connect("10.0.11.3").await?;
+
+    drop(remote);
+
+    tokio::time::timeout(Duration::from_secs(1), client.closed()).await?;
+    assert_eq!(client.remote_disconnect(), None);
+
+    Ok(())
+}
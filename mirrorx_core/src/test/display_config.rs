@@ -0,0 +1,155 @@
+use crate::{
+    api::endpoint::message::EndPointMessage,
+    component::{
+        desktop::display_config::{DisplayChanges, DisplayConfigWatch},
+        video_decoder::keyframe::KeyFrameGate,
+    },
+    core_error,
+    error::CoreResult,
+    utility::bincode::{bincode_deserialize, bincode_serialize},
+};
+use std::sync::{Arc, Mutex};
+
+const KEY_FRAME: &[u8] = &[0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x65, 0x88];
+const DELTA_FRAME: &[u8] = &[0, 0, 0, 1, 0x41, 0x9A];
+
+#[test]
+fn test_display_config_watch() {
+    let mut watch = DisplayConfigWatch::default();
+
+    assert!(!watch.observe(1920, 1080));
+    assert!(!watch.observe(1920, 1080));
+    assert!(watch.observe(1080, 1920));
+    assert!(!watch.observe(1080, 1920));
+    assert!(watch.observe(1920, 1080));
+
+    // a shared window isn't the size the viewer was told
+    let mut watch = DisplayConfigWatch::announcing_first_frame();
+    assert!(watch.observe(800, 600));
+    assert!(!watch.observe(800, 600));
+}
+
+#[test]
+fn test_display_config_changed_mid_stream() -> anyhow::Result<()> {
+    // capture side: the display rotates after two frames
+    let captured = [(1920, 1080), (1920, 1080), (1080, 1920), (1080, 1920)];
+
+    let mut watch = DisplayConfigWatch::default();
+    let changes: Vec<EndPointMessage> = captured
+        .into_iter()
+        .filter(|(width, height)| watch.observe(*width, *height))
+        .map(|(width, height)| EndPointMessage::DisplayConfigChanged { width, height })
+        .collect();
+
+    assert_eq!(
+        changes,
+        vec![EndPointMessage::DisplayConfigChanged {
+            width: 1080,
+            height: 1920
+        }]
+    );
+
+    let buffer = bincode_serialize(&changes[0])?;
+    let message: EndPointMessage = bincode_deserialize(&buffer)?;
+    assert_eq!(message, changes[0]);
+
+    // viewer side: a delta frame still in flight after the reset must not
+    // reach the new decoder
+    let mut gate = KeyFrameGate::default();
+    assert!(gate.admit(KEY_FRAME));
+    assert!(gate.admit(DELTA_FRAME));
+
+    gate.reset();
+    assert!(!gate.admit(DELTA_FRAME));
+    assert!(gate.admit(KEY_FRAME));
+    assert!(gate.admit(DELTA_FRAME));
+
+    Ok(())
+}
+
+/// A capture that keeps the mode the display had when it was created, like
+/// a duplicator does.
+struct ModeCapture {
+    size: (i32, i32),
+    created: usize,
+}
+
+impl ModeCapture {
+    fn new(mode: &Mutex<(i32, i32)>, created: usize) -> Self {
+        Self {
+            size: *mode.lock().unwrap(),
+            created,
+        }
+    }
+
+    fn capture(&self) -> (i32, i32) {
+        self.size
+    }
+}
+
+#[test]
+fn test_display_change_recreates_capture() -> anyhow::Result<()> {
+    let display_changes = DisplayChanges::default();
+    let mode = Arc::new(Mutex::new((1920, 1080)));
+
+    let mut watch = display_changes.watch();
+    let mut capture = ModeCapture::new(&mode, 1);
+    let mut config_watch = DisplayConfigWatch::default();
+
+    let recreate = |capture: &mut ModeCapture| -> CoreResult<ModeCapture> {
+        Ok(ModeCapture::new(&mode, capture.created + 1))
+    };
+
+    // nothing changed, the capture is kept
+    assert!(!watch.recreate(&mut capture, recreate)?);
+    assert!(!config_watch.observe(capture.capture().0, capture.capture().1));
+
+    // the user picks another resolution, the old capture doesn't notice
+    *mode.lock().unwrap() = (1280, 720);
+    display_changes.notify();
+    assert_eq!(capture.capture(), (1920, 1080));
+
+    assert!(watch.recreate(&mut capture, recreate)?);
+    assert_eq!(capture.created, 2);
+
+    // frames of the new mode tell the viewer about the new size
+    let (width, height) = capture.capture();
+    assert_eq!((width, height), (1280, 720));
+    assert!(config_watch.observe(width, height));
+
+    // one notification recreates once
+    assert!(!watch.recreate(&mut capture, recreate)?);
+    assert_eq!(capture.created, 2);
+
+    Ok(())
+}
+
+#[test]
+fn test_failed_recreate_keeps_capture() {
+    let display_changes = DisplayChanges::default();
+    let mode = Mutex::new((1920, 1080));
+
+    let mut watch = display_changes.watch();
+    let mut capture = ModeCapture::new(&mode, 1);
+
+    display_changes.notify();
+    assert!(watch
+        .recreate(&mut capture, |_| Err(core_error!("display is gone")))
+        .is_err());
+    assert_eq!(capture.created, 1);
+}
+
+#[test]
+fn test_display_change_watches_start_now() {
+    let display_changes = DisplayChanges::default();
+    display_changes.notify();
+
+    // a change before the watch started was seen by the capture already
+    let mut watch = display_changes.watch();
+    assert!(!watch.changed());
+
+    display_changes.notify();
+    display_changes.notify();
+    assert!(watch.changed());
+    assert!(!watch.changed());
+}
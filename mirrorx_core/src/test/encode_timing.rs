@@ -0,0 +1,100 @@
+use crate::component::video_encoder::encode_timing::{
+    parse_quality_stats, EncodeTiming, EncodeTimings, FrameType, PendingFrame, PendingFrames,
+    MAX_PENDING_FRAMES,
+};
+use std::time::{Duration, Instant};
+
+fn timing(seq: u64, encode_ms: u64, frame_type: FrameType) -> EncodeTiming {
+    EncodeTiming {
+        seq,
+        capture_time: Duration::from_millis(seq * 33),
+        prepare: Duration::from_micros(500),
+        encode: Duration::from_millis(encode_ms),
+        bytes: 4096,
+        frame_type,
+        qp: Some(24),
+    }
+}
+
+#[test]
+fn test_parse_quality_stats() {
+    // libx264 reports qp 23 of a P frame as lambda 23 * 118
+    let mut side_data = (23u32 * 118).to_le_bytes().to_vec();
+    side_data.extend_from_slice(&[2, 0, 0, 0]);
+    assert_eq!(parse_quality_stats(&side_data), Some((23, FrameType::P)));
+
+    side_data[4] = 1;
+    assert_eq!(parse_quality_stats(&side_data), Some((23, FrameType::I)));
+
+    side_data[4] = 6;
+    assert_eq!(
+        parse_quality_stats(&side_data),
+        Some((23, FrameType::Unknown))
+    );
+
+    assert_eq!(parse_quality_stats(&side_data[..4]), None);
+}
+
+#[test]
+fn test_encode_latency_percentiles() {
+    let timings = EncodeTimings::new(100);
+    assert_eq!(timings.latency(), None);
+
+    // 1 to 100 ms, the slowest the keyframe
+    for seq in 1..=100 {
+        let frame_type = if seq == 100 {
+            FrameType::I
+        } else {
+            FrameType::P
+        };
+        timings.record(&timing(seq, seq, frame_type));
+    }
+
+    let latency = timings.latency().unwrap();
+    assert_eq!(latency.frames, 100);
+    assert_eq!(latency.p50_ms, 50.0);
+    assert_eq!(latency.p95_ms, 95.0);
+    assert_eq!(latency.p99_ms, 99.0);
+    assert_eq!(latency.keyframes, 1);
+
+    // the window moves on, the oldest and fastest frames drop out
+    for seq in 101..=150 {
+        timings.record(&timing(seq, 200, FrameType::P));
+    }
+
+    let latency = timings.latency().unwrap();
+    assert_eq!(latency.frames, 100);
+    assert_eq!(latency.p50_ms, 100.0);
+    assert_eq!(latency.p95_ms, 200.0);
+
+    timings.clear();
+    assert_eq!(timings.latency(), None);
+}
+
+#[test]
+fn test_pending_frames() {
+    let now = Instant::now();
+    let frame = |pts: i64| PendingFrame {
+        pts,
+        capture_time: Duration::from_millis(pts as u64),
+        prepare: Duration::ZERO,
+        sent_at: now,
+    };
+
+    let mut pending_frames = PendingFrames::default();
+    pending_frames.push(frame(1));
+    pending_frames.push(frame(2));
+    pending_frames.push(frame(3));
+
+    // packets may come out of order
+    assert_eq!(pending_frames.take(2).map(|frame| frame.pts), Some(2));
+    assert_eq!(pending_frames.take(2).map(|frame| frame.pts), None);
+    assert_eq!(pending_frames.len(), 2);
+
+    // frames the encoder dropped don't pile up
+    for pts in 10..10 + MAX_PENDING_FRAMES as i64 {
+        pending_frames.push(frame(pts));
+    }
+    assert_eq!(pending_frames.len(), MAX_PENDING_FRAMES);
+    assert!(pending_frames.take(1).is_none());
+}
@@ -0,0 +1,82 @@
+use super::{connect, next_message};
+use crate::{
+    api::endpoint::{
+        message::{EndPointMessage, VideoCodec},
+        packet::serialize_packet,
+    },
+    component::video_encoder::adapter::{
+        pin_graphics_card, select_encoder_config, subscribe_encoder_fallback,
+        usable_pinned_graphics_card,
+    },
+};
+use bytes::Bytes;
+use futures::SinkExt;
+use std::time::Duration;
+
+#[test]
+fn test_missing_pinned_graphics_card_falls_back() {
+    let mut fallback_rx = subscribe_encoder_fallback();
+
+    pin_graphics_card(Some(String::from("unplugged-graphics-card")));
+
+    // the session isn't negotiated with the card and encodes in software
+    assert!(usable_pinned_graphics_card(&VideoCodec::H264).is_none());
+    assert!(select_encoder_config(&VideoCodec::H264)
+        .graphics_card()
+        .is_none());
+
+    pin_graphics_card(None);
+
+    let event = std::iter::from_fn(|| fallback_rx.try_recv().ok())
+        .find(|event| event.graphics_card_id == "unplugged-graphics-card")
+        .expect("no fallback event");
+    assert_eq!(event.reason, "graphics card is not present");
+}
+
+#[tokio::test]
+async fn test_host_announces_encoder_fallback() -> anyhow::Result<()> {
+    let (client, mut remote) = connect("127.0.0.40").await?;
+
+    // what the host told the viewer when negotiating
+    client.set_encoder_graphics_card(Some(String::from("Discrete GPU")));
+
+    // the encoder came up on that card
+    client.announce_encoder_graphics_card(Some(String::from("Discrete GPU")));
+    assert_eq!(next_message(&mut remote).await, None);
+
+    // the card went away, the encoder falls back to software
+    client.announce_encoder_graphics_card(None);
+    assert_eq!(
+        next_message(&mut remote).await,
+        Some(EndPointMessage::EncoderGraphicsCard(None))
+    );
+    assert_eq!(client.encoder_graphics_card(), None);
+
+    // a later software encoder changes nothing the viewer knows
+    client.announce_encoder_graphics_card(None);
+    assert_eq!(next_message(&mut remote).await, None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_viewer_follows_encoder_graphics_card() -> anyhow::Result<()> {
+    let (client, mut remote) = connect("127.0.0.41").await?;
+
+    for graphics_card in [Some(String::from("Discrete GPU")), None] {
+        remote
+            .send(Bytes::from(serialize_packet(
+                &EndPointMessage::EncoderGraphicsCard(graphics_card.clone()),
+            )?))
+            .await?;
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while client.encoder_graphics_card() != graphics_card {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,206 @@
+use crate::{
+    api::endpoint::{
+        client::EndPointClient,
+        id::EndPointID,
+        message::EndPointMessage,
+        packet::serialize_packet,
+        stats::{
+            open_frame, EndPointStats, FrameVerdict, RateMeter, MAX_CONSECUTIVE_FRAME_FAILURES,
+        },
+        EndPointStream,
+    },
+    utility::nonce_value::NonceValue,
+};
+use bytes::Bytes;
+use futures::SinkExt;
+use ring::aead::{BoundKey, OpeningKey, SealingKey, UnboundKey, AES_256_GCM};
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+use tokio::net::TcpListener;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+fn new_key_pair() -> anyhow::Result<(SealingKey<NonceValue>, OpeningKey<NonceValue>)> {
+    key_pair_of(7)
+}
+
+fn key_pair_of(seed: u8) -> anyhow::Result<(SealingKey<NonceValue>, OpeningKey<NonceValue>)> {
+    let key = [seed; 32];
+    let nonce = [1u8; ring::aead::NONCE_LEN];
+
+    let sealing_key = SealingKey::new(
+        UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow::anyhow!("invalid key"))?,
+        NonceValue::new(nonce),
+    );
+
+    let opening_key = OpeningKey::new(
+        UnboundKey::new(&AES_256_GCM, &key).map_err(|_| anyhow::anyhow!("invalid key"))?,
+        NonceValue::new(nonce),
+    );
+
+    Ok((sealing_key, opening_key))
+}
+
+fn seal(sealing_key: &mut SealingKey<NonceValue>, content: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut buffer = content.to_vec();
+    sealing_key
+        .seal_in_place_append_tag(ring::aead::Aad::empty(), &mut buffer)
+        .map_err(|_| anyhow::anyhow!("seal failed"))?;
+    Ok(buffer)
+}
+
+#[test]
+fn test_single_corrupt_frame_is_skipped() -> anyhow::Result<()> {
+    let (mut sealing_key, mut opening_key) = new_key_pair()?;
+    let stats = EndPointStats::default();
+
+    let mut frames = [
+        seal(&mut sealing_key, b"first")?,
+        seal(&mut sealing_key, b"second")?,
+        seal(&mut sealing_key, b"third")?,
+    ];
+    frames[1][0] ^= 0xFF;
+
+    let mut opened = Vec::new();
+    for frame in frames.iter_mut() {
+        match open_frame(Some(&mut opening_key), frame, &stats) {
+            FrameVerdict::Accept(len) => opened.push(frame[..len].to_vec()),
+            FrameVerdict::Skip => {}
+            FrameVerdict::Close => anyhow::bail!("session closed on a single corrupt frame"),
+        }
+    }
+
+    assert_eq!(opened, vec![b"first".to_vec(), b"third".to_vec()]);
+    assert_eq!(stats.snapshot().aead_open_failures, 1);
+    assert_eq!(stats.snapshot().dropped_frames, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_consecutive_corrupt_frames_close_session() -> anyhow::Result<()> {
+    let (mut sealing_key, mut opening_key) = new_key_pair()?;
+    let stats = EndPointStats::default();
+
+    for i in 1..=MAX_CONSECUTIVE_FRAME_FAILURES {
+        let mut frame = seal(&mut sealing_key, b"content")?;
+        frame[0] ^= 0xFF;
+
+        let verdict = open_frame(Some(&mut opening_key), &mut frame, &stats);
+        if i < MAX_CONSECUTIVE_FRAME_FAILURES {
+            assert!(matches!(verdict, FrameVerdict::Skip));
+        } else {
+            assert!(matches!(verdict, FrameVerdict::Close));
+        }
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_loop_resets_failures_on_opened_frame() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "127.0.0.50".parse::<IpAddr>()?,
+    };
+
+    let (mut remote_sealing_key, opening_key) = key_pair_of(7)?;
+    let (sealing_key, _) = key_pair_of(9)?;
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        Some((opening_key, sealing_key)),
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Framed::new(
+        stream,
+        LengthDelimitedCodec::builder()
+            .little_endian()
+            .max_frame_length(32 * 1024 * 1024)
+            .new_codec(),
+    );
+
+    // a frame opening between bursts of broken ones keeps the session open,
+    // however late its message is handled
+    let message = serialize_packet(&EndPointMessage::CallReply(0, Vec::new()))?;
+    let bursts = 3;
+    for _ in 0..bursts {
+        for _ in 1..MAX_CONSECUTIVE_FRAME_FAILURES {
+            let mut frame = seal(&mut remote_sealing_key, &message)?;
+            frame[0] ^= 0xFF;
+            remote.feed(Bytes::from(frame)).await?;
+        }
+        remote
+            .feed(Bytes::from(seal(&mut remote_sealing_key, &message)?))
+            .await?;
+    }
+    remote.flush().await?;
+
+    // the read loop stops opening frames once it closes the session
+    let expected_failures = bursts * (MAX_CONSECUTIVE_FRAME_FAILURES as u64 - 1);
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while client.stats().aead_open_failures < expected_failures {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+
+    Ok(())
+}
+
+#[test]
+fn test_smoothed_rtt_follows_probes() {
+    let stats = EndPointStats::default();
+    assert_eq!(stats.smoothed_rtt(), None);
+
+    stats.record_rtt(Duration::from_millis(80));
+    assert_eq!(stats.smoothed_rtt(), Some(Duration::from_millis(80)));
+
+    // a single spike moves the average by an eighth
+    stats.record_rtt(Duration::from_millis(160));
+    assert_eq!(stats.smoothed_rtt(), Some(Duration::from_millis(90)));
+    assert_eq!(stats.last_rtt(), Some(Duration::from_millis(160)));
+}
+
+#[test]
+fn test_rate_meter_measures_windows() {
+    let meter = RateMeter::default();
+    assert_eq!(meter.bps(Instant::now()), 0);
+
+    let start = Instant::now();
+    meter.record(125_000, start);
+    meter.record(125_000, start + Duration::from_millis(500));
+    assert_eq!(meter.bps(start + Duration::from_millis(500)), 0);
+
+    // 250 KB over two seconds
+    meter.record(0, start + Duration::from_secs(2));
+    let bps = meter.bps(start + Duration::from_secs(2));
+    assert!((990_000..=1_010_000).contains(&bps), "bps: {bps}");
+
+    // idle for longer than two windows
+    assert_eq!(meter.bps(start + Duration::from_secs(5)), 0);
+}
+
+#[test]
+fn test_reset_link_stats_keeps_totals() {
+    let stats = EndPointStats::default();
+    stats.record_rtt(Duration::from_millis(50));
+    stats.record_sent(1000);
+    stats.record_dropped_frame();
+    stats.record_oversized_frame();
+
+    stats.reset_link_stats();
+
+    let snapshot = stats.snapshot();
+    assert_eq!(snapshot.smoothed_rtt_ms, None);
+    assert_eq!(snapshot.outbound_bps, 0);
+    assert_eq!(snapshot.dropped_frames, 0);
+    assert_eq!(snapshot.oversized_frames, 0);
+    assert_eq!(stats.sent_bytes(), 1000);
+}
@@ -0,0 +1,142 @@
+use crate::utility::error_event::{
+    ErrorEvent, ErrorEventAggregator, ErrorSource, MAX_ERROR_BURSTS, MIN_ERROR_EVENT_WINDOW,
+};
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(5);
+
+#[test]
+fn test_identical_errors_collapse() {
+    let mut aggregator = ErrorEventAggregator::new(WINDOW);
+    let start = Instant::now();
+
+    let mut events = Vec::new();
+    for i in 0..100 {
+        let now = start + Duration::from_millis(i * 10);
+        events.extend(aggregator.record(ErrorSource::PacketOpen, "a", "open frame failed", now));
+        events.extend(aggregator.flush(now));
+    }
+
+    assert_eq!(
+        events,
+        vec![ErrorEvent::Occurred {
+            source: ErrorSource::PacketOpen,
+            endpoint_id: "a".to_string(),
+            message: "open frame failed".to_string(),
+            count: 1,
+        }]
+    );
+
+    // quiet for a window, the burst is summed up once
+    let last = start + Duration::from_millis(990);
+    assert!(aggregator.flush(last + WINDOW / 2).is_empty());
+    assert_eq!(
+        aggregator.flush(last + WINDOW),
+        vec![ErrorEvent::BurstEnded {
+            source: ErrorSource::PacketOpen,
+            endpoint_id: "a".to_string(),
+            message: "open frame failed".to_string(),
+            count: 100,
+            duration_ms: 990,
+        }]
+    );
+    assert!(aggregator.is_empty());
+    assert!(aggregator.flush(last + WINDOW * 2).is_empty());
+}
+
+#[test]
+fn test_single_error_has_no_summary() {
+    let mut aggregator = ErrorEventAggregator::new(WINDOW);
+    let now = Instant::now();
+
+    assert!(aggregator
+        .record(ErrorSource::Decode, "a", "invalid data", now)
+        .is_some());
+    assert!(aggregator.flush(now + WINDOW).is_empty());
+    assert!(aggregator.is_empty());
+}
+
+#[test]
+fn test_sustained_burst_reports_per_window() {
+    let mut aggregator = ErrorEventAggregator::new(WINDOW);
+    let start = Instant::now();
+
+    assert!(aggregator
+        .record(ErrorSource::Capture, "a", "access lost", start)
+        .is_some());
+
+    for i in 1..=9 {
+        let now = start + Duration::from_millis(i * 500);
+        assert!(aggregator
+            .record(ErrorSource::Capture, "a", "access lost", now)
+            .is_none());
+    }
+
+    // the repeats held back come out a window after the first event
+    let event = aggregator.record(ErrorSource::Capture, "a", "access lost", start + WINDOW);
+    assert!(matches!(
+        event,
+        Some(ErrorEvent::Occurred { count: 10, .. })
+    ));
+
+    aggregator.record(
+        ErrorSource::Capture,
+        "a",
+        "access lost",
+        start + WINDOW + Duration::from_secs(1),
+    );
+    let events = aggregator.flush(start + WINDOW * 2);
+    assert!(matches!(
+        events.as_slice(),
+        [ErrorEvent::Occurred { count: 1, .. }]
+    ));
+}
+
+#[test]
+fn test_distinct_errors_are_separate() {
+    let mut aggregator = ErrorEventAggregator::new(WINDOW);
+    let now = Instant::now();
+
+    assert!(aggregator
+        .record(ErrorSource::Decode, "a", "x", now)
+        .is_some());
+    assert!(aggregator
+        .record(ErrorSource::Decode, "b", "x", now)
+        .is_some());
+    assert!(aggregator
+        .record(ErrorSource::Decode, "a", "y", now)
+        .is_some());
+    assert!(aggregator
+        .record(ErrorSource::Socket, "a", "x", now)
+        .is_some());
+    assert!(aggregator
+        .record(ErrorSource::Decode, "a", "x", now)
+        .is_none());
+    assert_eq!(aggregator.len(), 4);
+}
+
+#[test]
+fn test_error_bursts_bounded() {
+    let mut aggregator = ErrorEventAggregator::new(WINDOW);
+    let now = Instant::now();
+
+    for i in 0..MAX_ERROR_BURSTS {
+        assert!(aggregator
+            .record(ErrorSource::Socket, "a", &i.to_string(), now)
+            .is_some());
+    }
+
+    assert!(aggregator
+        .record(ErrorSource::Socket, "a", "one more", now)
+        .is_none());
+    assert_eq!(aggregator.len(), MAX_ERROR_BURSTS);
+}
+
+#[test]
+fn test_error_event_window_clamped() {
+    let mut aggregator = ErrorEventAggregator::new(Duration::ZERO);
+    assert_eq!(aggregator.window(), MIN_ERROR_EVENT_WINDOW);
+
+    aggregator.set_window(WINDOW);
+    assert_eq!(aggregator.window(), WINDOW);
+}
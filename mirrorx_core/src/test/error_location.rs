@@ -0,0 +1,39 @@
+use crate::{
+    bail_core, core_error,
+    error::{CoreError, CoreResult},
+};
+
+fn location(err: CoreError) -> (String, String, String) {
+    match err {
+        CoreError::Other {
+            message,
+            file,
+            line,
+        } => (message, file, line),
+        err => panic!("unexpected error {:?}", err),
+    }
+}
+
+#[test]
+fn test_core_error_captures_call_site() {
+    let expected_line = line!() + 1;
+    let err = core_error!("bad value {}", 7);
+
+    let (message, file, line) = location(err);
+    assert_eq!(message, "bad value 7");
+    assert_eq!(file, file!());
+    assert_eq!(line, expected_line.to_string());
+}
+
+#[test]
+fn test_bail_core_captures_call_site() {
+    let expected_line = line!() + 2;
+    let fail = |value: i32| -> CoreResult<()> {
+        bail_core!("bad value {}", value);
+    };
+
+    let (message, file, line) = location(fail(9).unwrap_err());
+    assert_eq!(message, "bad value 9");
+    assert_eq!(file, file!());
+    assert_eq!(line, expected_line.to_string());
+}
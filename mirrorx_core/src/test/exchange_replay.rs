@@ -0,0 +1,117 @@
+use crate::api::signaling::{
+    exchange_replay::ExchangeReplayGuard,
+    identity::MutualAuthConfig,
+    key_exchange::{key_agreement_with_identity, ActiveKeyExchange, KeyAgreementError},
+    subscribe_message::VisitFailureReason,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use std::time::{Duration, Instant};
+
+// big enough for the reply of the passive device, small enough to generate
+// quickly in debug builds
+const TEST_REPLY_KEY_BITS: usize = 1024;
+
+fn answer(
+    guard: &ExchangeReplayGuard,
+    rng: &mut StdRng,
+    active_device_id: i64,
+    password: &str,
+    exchange: &ActiveKeyExchange,
+    now: Instant,
+) -> Result<Vec<u8>, KeyAgreementError> {
+    guard.answer_once(
+        active_device_id,
+        &exchange.password_salt,
+        &exchange.secret,
+        &exchange.secret_nonce,
+        now,
+        || {
+            key_agreement_with_identity(
+                rng,
+                password,
+                active_device_id,
+                &exchange.password_salt,
+                exchange.secret.clone(),
+                &exchange.secret_nonce,
+                None,
+                &MutualAuthConfig::default(),
+            )
+            .map(|(reply, _, _)| reply)
+        },
+    )
+}
+
+#[test]
+fn test_exchange_replay_rejected() {
+    let mut rng = StdRng::seed_from_u64(174);
+    let reply_private_key = rsa::RsaPrivateKey::new(&mut rng, TEST_REPLY_KEY_BITS).unwrap();
+    let captured = ActiveKeyExchange::new(&mut rng, reply_private_key, 1, "password").unwrap();
+
+    let guard = ExchangeReplayGuard::new(Duration::from_secs(60), 4);
+    let now = Instant::now();
+
+    assert!(answer(&guard, &mut rng, 1, "password", &captured, now).is_ok());
+
+    let replayed = answer(
+        &guard,
+        &mut rng,
+        1,
+        "password",
+        &captured,
+        now + Duration::from_secs(1),
+    )
+    .unwrap_err();
+    assert!(matches!(replayed, KeyAgreementError::Replayed));
+    assert!(matches!(replayed.reason(), VisitFailureReason::Replayed));
+
+    // past the ttl the exchange is forgotten
+    let later = now + Duration::from_secs(61);
+    assert!(answer(&guard, &mut rng, 1, "password", &captured, later).is_ok());
+}
+
+#[test]
+fn test_exchange_replay_failed_not_remembered() {
+    let mut rng = StdRng::seed_from_u64(175);
+    let reply_private_key = rsa::RsaPrivateKey::new(&mut rng, TEST_REPLY_KEY_BITS).unwrap();
+    let exchange = ActiveKeyExchange::new(&mut rng, reply_private_key, 1, "password").unwrap();
+
+    let guard = ExchangeReplayGuard::new(Duration::from_secs(60), 4);
+    let now = Instant::now();
+
+    // tried with another password first, like a one-time code after the
+    // device password
+    let wrong = answer(&guard, &mut rng, 1, "wrong", &exchange, now).unwrap_err();
+    assert!(matches!(
+        wrong,
+        KeyAgreementError::Failure(VisitFailureReason::InvalidPassword)
+    ));
+    assert!(guard.is_empty());
+
+    assert!(answer(&guard, &mut rng, 1, "password", &exchange, now).is_ok());
+    assert_eq!(guard.len(), 1);
+}
+
+#[test]
+fn test_exchange_replay_capacity() {
+    let guard = ExchangeReplayGuard::new(Duration::from_secs(60), 2);
+    let now = Instant::now();
+
+    for nonce in 0u8..3 {
+        assert!(guard
+            .answer_once(1, b"salt", b"secret", &[nonce], now, || Ok(()))
+            .is_ok());
+    }
+    assert_eq!(guard.len(), 2);
+
+    // the same exchange from another device isn't a replay
+    assert!(guard
+        .answer_once(2, b"salt", b"secret", &[2], now, || Ok(()))
+        .is_ok());
+    assert!(matches!(
+        guard.answer_once(1, b"salt", b"secret", &[2], now, || Ok(())),
+        Err(KeyAgreementError::Replayed)
+    ));
+
+    assert_eq!(guard.purge(now + Duration::from_secs(60)), 3);
+    assert!(guard.is_empty());
+}
@@ -0,0 +1,113 @@
+use crate::{
+    api::endpoint::message::EndPointVideoFrame,
+    component::fec::{fec_group_size, FecDecoder, FecEncoder, FecEvent},
+};
+
+fn video_frame(seq: u64, length: usize) -> EndPointVideoFrame {
+    EndPointVideoFrame {
+        seq,
+        width: 1920,
+        height: 1080,
+        pts: seq as i64 * 33,
+        buffer: (0..length).map(|i| (i as u64 + seq * 7) as u8).collect(),
+    }
+}
+
+fn delivered(events: &[FecEvent]) -> Vec<u64> {
+    events
+        .iter()
+        .filter_map(|event| match event {
+            FecEvent::Frame(frame) => Some(frame.seq),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_restore_lost_frame_from_parity() {
+    let mut encoder = FecEncoder::new(2).unwrap();
+    let mut decoder = FecDecoder::new(2);
+    let frames: Vec<_> = (0..4)
+        .map(|seq| video_frame(seq, 100 + seq as usize * 13))
+        .collect();
+
+    let mut parity = None;
+    for frame in &frames {
+        parity = encoder.push(frame);
+    }
+    let parity = parity.expect("group of 4 is complete");
+
+    let mut events = Vec::new();
+    for frame in frames.iter().filter(|frame| frame.seq != 1) {
+        events.extend(decoder.push_frame(frame.clone()));
+    }
+
+    // frames after the gap wait for the parity
+    assert_eq!(delivered(&events), vec![0]);
+
+    let events = decoder.push_parity(parity);
+    assert_eq!(events[0], FecEvent::Recovered(1));
+    assert_eq!(delivered(&events), vec![1, 2, 3]);
+    assert_eq!(events[1], FecEvent::Frame(frames[1].clone()));
+}
+
+#[test]
+fn test_unrecoverable_loss_skips_to_next_group() {
+    let mut encoder = FecEncoder::new(2).unwrap();
+    let mut decoder = FecDecoder::new(2);
+
+    let mut parity = None;
+    let mut events = Vec::new();
+    for seq in 0..4 {
+        let frame = video_frame(seq, 64);
+        parity = encoder.push(&frame);
+        if seq != 1 && seq != 2 {
+            events.extend(decoder.push_frame(frame));
+        }
+    }
+
+    assert_eq!(delivered(&events), vec![0]);
+
+    let events = decoder.push_parity(parity.unwrap());
+    assert_eq!(events[0], FecEvent::Lost(2));
+    assert_eq!(delivered(&events), vec![3]);
+
+    assert_eq!(delivered(&decoder.push_frame(video_frame(4, 64))), vec![4]);
+}
+
+#[test]
+fn test_gap_without_fec_is_reported_at_once() {
+    let mut decoder = FecDecoder::new(0);
+
+    assert_eq!(delivered(&decoder.push_frame(video_frame(0, 8))), vec![0]);
+
+    let events = decoder.push_frame(video_frame(2, 8));
+    assert_eq!(events[0], FecEvent::Lost(1));
+    assert_eq!(delivered(&events), vec![2]);
+
+    // late frames are dropped
+    assert!(decoder.push_frame(video_frame(1, 8)).is_empty());
+}
+
+#[test]
+fn test_parity_overhead() {
+    for fec_level in 1..=3 {
+        let group_size = fec_group_size(fec_level).unwrap() as u64;
+        let mut encoder = FecEncoder::new(fec_level).unwrap();
+
+        let (mut frame_bytes, mut parity_bytes) = (0, 0);
+        for seq in 0..group_size * 10 {
+            let frame = video_frame(seq, 1000 + (seq % 3) as usize * 100);
+            frame_bytes += frame.buffer.len();
+            if let Some(parity) = encoder.push(&frame) {
+                parity_bytes += parity.buffer.len();
+            }
+        }
+
+        // one parity as large as the largest frame per group
+        let overhead = parity_bytes as f64 / frame_bytes as f64;
+        assert!(overhead <= 1.2 / group_size as f64, "{overhead}");
+    }
+
+    assert!(FecEncoder::new(0).is_none());
+}
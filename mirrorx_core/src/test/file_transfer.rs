@@ -0,0 +1,272 @@
+use super::connect;
+use crate::{
+    api::endpoint::{
+        message::{EndPointFileTransferCancel, EndPointMessage, FileTransferCancelReason},
+        packet::{deserialize_packet, serialize_packet},
+    },
+    component::fs::{
+        transfer::{
+            cancel_file_transfer, create_file_append_session, file_content_hash,
+            finish_received_file, partial_file_path, partial_file_state, partial_hash_path,
+            resume_offset, send_file_to_remote, subscribe_file_transfer_cancelled,
+            subscribe_file_transfer_progress, ProgressReporter, APPEND_FILES,
+            FILE_TRANSFER_PROGRESS_INTERVAL, SEND_FILES,
+        },
+        window::{file_chunk_size, INITIAL_WINDOW_CHUNKS},
+    },
+    error::CoreError,
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::io::{AsyncWriteExt, BufWriter};
+
+fn transfer_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("mirrorx_transfer_{}_{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+#[test]
+fn test_partial_file_path() {
+    assert_eq!(
+        partial_file_path(&PathBuf::from("/tmp/report.pdf")),
+        PathBuf::from("/tmp/report.pdf.mirrorx-part")
+    );
+}
+
+#[tokio::test]
+async fn test_finish_received_file() -> anyhow::Result<()> {
+    let dir = transfer_dir("finish");
+    let path = dir.join("report.pdf");
+    let partial_path = partial_file_path(&path);
+
+    let mut writer = BufWriter::new(tokio::fs::File::create(&partial_path).await?);
+    writer.write_all(&[0xAB; 1000]).await?;
+
+    // the data is still buffered, nothing under the final name yet
+    assert!(!path.exists());
+
+    finish_received_file(writer, &partial_path, &path).await?;
+
+    assert_eq!(std::fs::read(&path)?, vec![0xAB; 1000]);
+    assert!(!partial_path.exists());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_finish_received_file_failed() -> anyhow::Result<()> {
+    let dir = transfer_dir("failed");
+    let partial_path = partial_file_path(&dir.join("report.pdf"));
+    let path = dir.join("missing").join("report.pdf");
+
+    let mut writer = BufWriter::new(tokio::fs::File::create(&partial_path).await?);
+    writer.write_all(&[0xAB; 1000]).await?;
+
+    let result = finish_received_file(writer, &partial_path, &path).await;
+
+    // flushed, but without a final name the transfer failed
+    assert!(matches!(result, Err(CoreError::IO(_))));
+    assert!(!path.exists());
+    assert_eq!(std::fs::read(&partial_path)?, vec![0xAB; 1000]);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_partial_hash_path() {
+    assert_eq!(
+        partial_hash_path(&PathBuf::from("/tmp/report.pdf")),
+        PathBuf::from("/tmp/report.pdf.mirrorx-part-hash")
+    );
+}
+
+#[tokio::test]
+async fn test_file_content_hash() -> anyhow::Result<()> {
+    let dir = transfer_dir("hash");
+    let path = dir.join("report.pdf");
+    std::fs::write(&path, b"abc")?;
+
+    assert_eq!(
+        hex::encode(file_content_hash(&path).await?),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_resume_offset() -> anyhow::Result<()> {
+    let dir = transfer_dir("resume");
+    let path = dir.join("report.pdf");
+    let hash = vec![0x11; 32];
+
+    // nothing left by an earlier transfer
+    assert_eq!(resume_offset(&path, &hash, 4096).await, 0);
+
+    std::fs::write(partial_file_path(&path), [0xAB; 1000])?;
+    std::fs::write(partial_hash_path(&path), &hash)?;
+    assert_eq!(partial_file_state(&path).await, Some((hash.clone(), 1000)));
+    assert_eq!(resume_offset(&path, &hash, 4096).await, 1000);
+
+    // the file changed since, or shrank below what was received
+    assert_eq!(resume_offset(&path, &[0x22; 32], 4096).await, 0);
+    assert_eq!(resume_offset(&path, &hash, 999).await, 0);
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_progress_reporter_throttles() {
+    let mut rx = subscribe_file_transfer_progress();
+    let reporter = ProgressReporter::new(String::from("progress"), 1000);
+    let now = Instant::now();
+
+    assert!(reporter.report(100, now));
+    assert!(!reporter.report(200, now + Duration::from_millis(10)));
+    assert!(reporter.report(300, now + FILE_TRANSFER_PROGRESS_INTERVAL));
+
+    // the last one always goes out
+    assert!(reporter.report(1000, now + FILE_TRANSFER_PROGRESS_INTERVAL));
+
+    let transferred: Vec<u64> = std::iter::from_fn(|| rx.try_recv().ok())
+        .filter(|event| event.id == "progress")
+        .map(|event| {
+            assert_eq!(event.total, 1000);
+            event.transferred
+        })
+        .collect();
+    assert_eq!(transferred, vec![100, 300, 1000]);
+}
+
+#[tokio::test]
+async fn test_cancel_transfer_mid_stream() -> anyhow::Result<()> {
+    let dir = transfer_dir("cancel");
+    let source = dir.join("source.bin");
+    let path = dir.join("report.pdf");
+
+    let chunk_size = file_chunk_size() as u64;
+    let total = chunk_size * INITIAL_WINDOW_CHUNKS * 4;
+    std::fs::write(&source, vec![0xAB; total as usize])?;
+    let hash = file_content_hash(&source).await?;
+
+    let (sender, mut sender_remote) = connect("127.0.0.32").await?;
+    let (receiver, receiver_remote) = connect("127.0.0.33").await?;
+    let (mut receiver_sink, mut receiver_stream) = receiver_remote.split();
+
+    // both sides share the transfer caches of this process, the ids keep
+    // them apart like two devices would
+    let send_id = String::from("cancel-send");
+    let append_id = String::from("cancel-append");
+    let mut cancelled_rx = subscribe_file_transfer_cancelled();
+
+    create_file_append_session(receiver.clone(), append_id.clone(), &path, &hash, 0, total).await?;
+    send_file_to_remote(send_id.clone(), sender.clone(), &source, 0).await?;
+
+    // blocks and the cancel go from the sender to the receiver, the acks
+    // are dropped so the sender stays mid-stream once its window is full
+    let blocks_sent = Arc::new(AtomicU64::new(0));
+    let relay = tokio::spawn({
+        let blocks_sent = blocks_sent.clone();
+        let append_id = append_id.clone();
+        async move {
+            while let Some(Ok(buffer)) = sender_remote.next().await {
+                let message = match deserialize_packet(&buffer)? {
+                    EndPointMessage::FileTransferBlock(mut block) => {
+                        blocks_sent.fetch_add(1, Ordering::SeqCst);
+                        block.id = append_id.clone();
+                        EndPointMessage::FileTransferBlock(block)
+                    }
+                    EndPointMessage::FileTransferCancel(mut cancel) => {
+                        cancel.id = append_id.clone();
+                        EndPointMessage::FileTransferCancel(cancel)
+                    }
+                    _ => continue,
+                };
+
+                receiver_sink
+                    .send(Bytes::from(serialize_packet(&message)?))
+                    .await?;
+            }
+
+            anyhow::Ok(())
+        }
+    });
+
+    // the receiver acks what it wrote until the window of the sender is
+    // full
+    let mut acked_offset = 0;
+    loop {
+        match tokio::time::timeout(Duration::from_millis(300), receiver_stream.next()).await {
+            Ok(Some(buffer)) => {
+                if let EndPointMessage::FileTransferAck(ack) = deserialize_packet(&buffer?)? {
+                    acked_offset = ack.offset;
+                }
+            }
+            Ok(None) => anyhow::bail!("receiver closed"),
+            Err(_) if acked_offset > 0 => break,
+            Err(_) => continue,
+        }
+    }
+
+    let blocks = blocks_sent.load(Ordering::SeqCst);
+    assert!(blocks > 0);
+    assert!(acked_offset < total);
+    assert!(partial_file_path(&path).exists());
+
+    // what the cancel command does on the sending side
+    assert!(cancel_file_transfer(&send_id, FileTransferCancelReason::UserCancelled, false).await);
+    sender
+        .send(&EndPointMessage::FileTransferCancel(
+            EndPointFileTransferCancel {
+                id: send_id.clone(),
+                reason: FileTransferCancelReason::UserCancelled,
+                keep_partial: false,
+            },
+        ))
+        .await?;
+
+    let mut cancelled = Vec::new();
+    while cancelled.len() < 2 {
+        let event = tokio::time::timeout(Duration::from_secs(1), cancelled_rx.recv()).await??;
+        if event.id == send_id || event.id == append_id {
+            assert_eq!(event.reason, FileTransferCancelReason::UserCancelled);
+            cancelled.push(event.id);
+        }
+    }
+    assert_eq!(cancelled, vec![send_id.clone(), append_id.clone()]);
+
+    // the receiver removes what it wrote
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while partial_file_path(&path).exists() || partial_hash_path(&path).exists() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+    assert!(!path.exists());
+
+    // and neither side goes on
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    assert_eq!(blocks_sent.load(Ordering::SeqCst), blocks);
+    assert!(SEND_FILES.get(&send_id).is_none());
+    assert!(APPEND_FILES.get(&append_id).is_none());
+    assert!(!cancel_file_transfer(&send_id, FileTransferCancelReason::UserCancelled, false).await);
+
+    relay.abort();
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,213 @@
+use crate::{
+    api::endpoint::message::EndPointFileTransferBlock,
+    component::fs::{
+        transfer::{advance_acked_offset, send_file_blocks},
+        window::{window_for, ReorderBuffer, SendWindow, MAX_IN_FLIGHT_BYTES},
+    },
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+
+#[test]
+fn test_window_for_bandwidth_delay_product() {
+    // 80 Mbps over 100ms holds 1 MB on the way
+    let window = window_for(80_000_000, Duration::from_millis(100), 64 * 1024);
+    assert_eq!(window, 2_000_000);
+
+    assert_eq!(window_for(0, Duration::ZERO, 64 * 1024), 64 * 1024);
+    assert_eq!(
+        window_for(u64::MAX, Duration::from_secs(1), 64 * 1024),
+        MAX_IN_FLIGHT_BYTES
+    );
+}
+
+#[test]
+fn test_send_window_ignores_stale_acks() {
+    let now = Instant::now();
+    let mut window = SendWindow::fixed(1024, 4096);
+
+    for _ in 0..4 {
+        assert!(!window.is_full());
+        window.on_sent(1024, now);
+    }
+    assert!(window.is_full());
+
+    assert!(window.on_ack(3072, now));
+    assert_eq!(window.in_flight_bytes(), 1024);
+
+    // an ack overtaken by a later one
+    assert!(!window.on_ack(2048, now));
+    // an ack of bytes never sent
+    assert!(!window.on_ack(8192, now));
+    assert_eq!(window.acked_offset(), 3072);
+}
+
+#[test]
+fn test_reorder_buffer() {
+    let mut reorder_buffer = ReorderBuffer::default();
+
+    assert!(reorder_buffer.push(4, vec![4, 5]).unwrap().is_empty());
+    assert!(reorder_buffer.push(2, vec![2, 3]).unwrap().is_empty());
+    assert_eq!(reorder_buffer.pending_bytes(), 4);
+
+    let ready = reorder_buffer.push(0, vec![0, 1]).unwrap();
+    assert_eq!(ready, vec![vec![0, 1], vec![2, 3], vec![4, 5]]);
+    assert_eq!(reorder_buffer.next_offset(), 6);
+    assert_eq!(reorder_buffer.pending_bytes(), 0);
+
+    // a block written already
+    assert!(reorder_buffer.push(2, vec![2, 3]).unwrap().is_empty());
+    assert_eq!(reorder_buffer.next_offset(), 6);
+
+    assert!(reorder_buffer
+        .push(6 + MAX_IN_FLIGHT_BYTES, vec![0])
+        .is_err());
+}
+
+#[tokio::test]
+async fn test_resumed_transfer_sends_from_offset() {
+    const CHUNK_SIZE: usize = 1024;
+    const OFFSET: u64 = 3 * 1024 + 100;
+
+    let data: Vec<u8> = (0..10 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let (acked_tx, mut acked_rx) = watch::channel(OFFSET);
+    let mut window = SendWindow::fixed(CHUNK_SIZE, CHUNK_SIZE as u64 * 4).starting_at(OFFSET);
+    let mut reorder_buffer = ReorderBuffer::starting_at(OFFSET);
+    let mut file = data[..OFFSET as usize].to_vec();
+    let mut finish_offset = None;
+
+    let mut reader = &data[OFFSET as usize..];
+    send_file_blocks(
+        "resume",
+        &mut reader,
+        &mut window,
+        &mut acked_rx,
+        &CancellationToken::new(),
+        |block| {
+            assert!(block.offset >= OFFSET);
+
+            match block.data {
+                Some(data) => {
+                    for ready in reorder_buffer.push(block.offset, data).unwrap() {
+                        file.extend(ready);
+                    }
+                    advance_acked_offset(&acked_tx, reorder_buffer.next_offset());
+                }
+                None => finish_offset = Some(block.offset),
+            }
+
+            async { Ok(()) }
+        },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(finish_offset, Some(data.len() as u64));
+    assert_eq!(file, data);
+}
+
+#[tokio::test]
+async fn test_windowed_transfer_outpaces_stop_and_wait() {
+    const CHUNK_SIZE: usize = 16 * 1024;
+    const LATENCY: Duration = Duration::from_millis(20);
+
+    let data: Vec<u8> = (0..512 * 1024).map(|i| (i % 251) as u8).collect();
+
+    let (stop_and_wait_elapsed, file, _) = transfer_over_link(
+        &data,
+        SendWindow::fixed(CHUNK_SIZE, CHUNK_SIZE as u64),
+        LATENCY,
+    )
+    .await;
+    assert_eq!(file, data);
+
+    let initial_bytes = CHUNK_SIZE as u64 * 8;
+    let (windowed_elapsed, file, window) = transfer_over_link(
+        &data,
+        SendWindow::adaptive(CHUNK_SIZE, initial_bytes),
+        LATENCY,
+    )
+    .await;
+    assert_eq!(file, data);
+    assert!(window.limit_bytes() > initial_bytes);
+
+    assert!(
+        windowed_elapsed * 3 < stop_and_wait_elapsed,
+        "windowed {:?}, stop-and-wait {:?}",
+        windowed_elapsed,
+        stop_and_wait_elapsed
+    );
+}
+
+/// Sends the data over a link delaying every block and ack on its own, so
+/// they may overtake each other, and returns what the receiver wrote.
+async fn transfer_over_link(
+    data: &[u8],
+    mut window: SendWindow,
+    latency: Duration,
+) -> (Duration, Vec<u8>, SendWindow) {
+    let (acked_tx, mut acked_rx) = watch::channel(0);
+    let acked_tx = Arc::new(acked_tx);
+    let (block_tx, mut block_rx) =
+        tokio::sync::mpsc::unbounded_channel::<EndPointFileTransferBlock>();
+
+    let receiver = tokio::spawn(async move {
+        let mut reorder_buffer = ReorderBuffer::default();
+        let mut file = Vec::new();
+        let mut finish_offset = None;
+
+        while finish_offset != Some(reorder_buffer.next_offset()) {
+            let block = block_rx.recv().await.unwrap();
+
+            let Some(data) = block.data else {
+                finish_offset = Some(block.offset);
+                continue;
+            };
+
+            for ready in reorder_buffer.push(block.offset, data).unwrap() {
+                file.extend(ready);
+            }
+
+            let acked_tx = acked_tx.clone();
+            let offset = reorder_buffer.next_offset();
+            tokio::spawn(async move {
+                tokio::time::sleep(latency).await;
+                advance_acked_offset(&acked_tx, offset);
+            });
+        }
+
+        file
+    });
+
+    let started_at = Instant::now();
+    let mut reader = data;
+
+    send_file_blocks(
+        "link",
+        &mut reader,
+        &mut window,
+        &mut acked_rx,
+        &CancellationToken::new(),
+        |block| {
+            let block_tx = block_tx.clone();
+            async move {
+                tokio::spawn(async move {
+                    tokio::time::sleep(latency).await;
+                    let _ = block_tx.send(block);
+                });
+                Ok(())
+            }
+        },
+    )
+    .await
+    .unwrap();
+
+    let file = receiver.await.unwrap();
+
+    (started_at.elapsed(), file, window)
+}
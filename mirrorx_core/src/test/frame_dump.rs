@@ -0,0 +1,144 @@
+use crate::{
+    api::endpoint::{
+        id::EndPointID,
+        message::{EndPointVideoFrame, VideoCodec},
+    },
+    component::video_decoder::frame_dump::{
+        start_frame_dump, stop_frame_dump, FrameDump, FrameDumpEnd, FrameDumpLimits,
+    },
+};
+use std::{
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+fn dump_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "mirrorx_frame_dump_{}_{}",
+        name,
+        std::process::id()
+    ))
+}
+
+fn endpoint_id() -> EndPointID {
+    EndPointID::LANID {
+        local_ip: "127.0.0.1".parse().unwrap(),
+        remote_ip: "10.0.9.1".parse().unwrap(),
+    }
+}
+
+fn frame(width: i32, keyframe: bool, len: usize) -> EndPointVideoFrame {
+    // an IDR or a non-IDR slice behind an Annex B start code
+    let nal_unit_type = if keyframe { 0x65 } else { 0x41 };
+    let mut buffer = vec![0, 0, 0, 1, nal_unit_type];
+    buffer.resize(len, 0xAB);
+
+    EndPointVideoFrame {
+        seq: 0,
+        width,
+        height: 1080,
+        pts: 0,
+        buffer,
+    }
+}
+
+#[test]
+fn test_frame_dump_starts_at_keyframe() -> anyhow::Result<()> {
+    let dir = dump_dir("keyframe");
+    let mut dump = FrameDump::create(
+        &dir,
+        "dump",
+        &endpoint_id(),
+        VideoCodec::H264,
+        FrameDumpLimits::default(),
+    )?;
+
+    let frames = [
+        frame(1920, false, 64),
+        frame(1920, true, 128),
+        frame(1920, false, 64),
+        frame(1280, true, 96),
+    ];
+
+    for frame in frames.iter() {
+        assert_eq!(dump.write(frame), None);
+    }
+
+    let summary = dump.finish(FrameDumpEnd::Stopped)?;
+    assert_eq!(summary.frames, 3);
+    assert_eq!(summary.bytes, 128 + 64 + 96);
+    assert_eq!(summary.resolutions, vec![(1920, 1080), (1280, 1080)]);
+    assert_eq!(summary.end, Some(FrameDumpEnd::Stopped));
+
+    // the stream is the payloads back to back, the frame before the first
+    // keyframe left out
+    let expected: Vec<u8> = frames[1..]
+        .iter()
+        .flat_map(|frame| frame.buffer.clone())
+        .collect();
+    assert_eq!(std::fs::read(&summary.stream_path)?, expected);
+    assert!(summary.stream_path.with_extension("json").exists());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_frame_dump_stops_at_limits() -> anyhow::Result<()> {
+    let dir = dump_dir("limits");
+    let limits = FrameDumpLimits {
+        max_bytes: 300,
+        max_secs: 10,
+    };
+
+    let mut dump = FrameDump::create(&dir, "size", &endpoint_id(), VideoCodec::H264, limits)?;
+    assert_eq!(dump.write(&frame(1920, true, 200)), None);
+    assert_eq!(
+        dump.write(&frame(1920, false, 200)),
+        Some(FrameDumpEnd::SizeLimit)
+    );
+    assert_eq!(dump.finish(FrameDumpEnd::SizeLimit)?.bytes, 200);
+
+    let mut dump = FrameDump::create(&dir, "time", &endpoint_id(), VideoCodec::H264, limits)?;
+    let later = Instant::now() + Duration::from_secs(11);
+    assert_eq!(
+        dump.write_at(&frame(1920, true, 10), later),
+        Some(FrameDumpEnd::TimeLimit)
+    );
+    drop(dump);
+
+    // frames without a start code to find keyframes by aren't supported
+    assert!(FrameDump::create(&dir, "vp9", &endpoint_id(), VideoCodec::VP9, limits).is_err());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
+
+#[test]
+fn test_frame_dump_one_per_session() -> anyhow::Result<()> {
+    let dir = dump_dir("session");
+
+    let stream_path = start_frame_dump(
+        endpoint_id(),
+        VideoCodec::H264,
+        &dir,
+        FrameDumpLimits::default(),
+    )?;
+    assert!(stream_path.starts_with(&dir));
+
+    assert!(start_frame_dump(
+        endpoint_id(),
+        VideoCodec::H264,
+        &dir,
+        FrameDumpLimits::default()
+    )
+    .is_err());
+
+    let summary = stop_frame_dump(&endpoint_id())?.unwrap();
+    assert_eq!(summary.stream_path, stream_path);
+    assert_eq!(summary.frames, 0);
+    assert!(stop_frame_dump(&endpoint_id())?.is_none());
+
+    std::fs::remove_dir_all(&dir)?;
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use crate::{
+    api::endpoint::{
+        client::EndPointClient,
+        handshake::{check_handshake_response, handshake_device_ids, HandshakeFailure},
+        handshake_token::handshake_tokens,
+        id::EndPointID,
+        message::EndPointHandshakeResponse,
+        packet::frame_codec,
+        EndPointStream,
+    },
+    error::CoreError,
+    utility::bincode::bincode_serialize,
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use std::{net::IpAddr, time::Instant};
+use tokio::net::TcpListener;
+use tokio_util::codec::Framed;
+
+#[test]
+fn test_check_handshake_response() -> anyhow::Result<()> {
+    let response = bincode_serialize(&EndPointHandshakeResponse {
+        remote_device_id: 42,
+    })?;
+
+    assert!(check_handshake_response(&response, 42).is_ok());
+    assert!(matches!(
+        check_handshake_response(&response, 43),
+        Err(CoreError::HandshakeFailed(
+            HandshakeFailure::MismatchedReply
+        ))
+    ));
+    assert!(matches!(
+        check_handshake_response(&[], 42),
+        Err(CoreError::HandshakeFailed(HandshakeFailure::MalformedReply))
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_lan_connection_has_no_handshake() -> anyhow::Result<()> {
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.13.1".parse::<IpAddr>()?,
+    };
+
+    assert!(matches!(
+        handshake_device_ids(endpoint_id),
+        Err(CoreError::HandshakeFailed(HandshakeFailure::NotRelayed))
+    ));
+
+    let endpoint_id = EndPointID::DeviceID {
+        local_device_id: 1,
+        remote_device_id: 2,
+    };
+    assert_eq!(handshake_device_ids(endpoint_id)?, (1, 2));
+
+    Ok(())
+}
+
+#[test]
+fn test_handshake_failed_message() {
+    let err = CoreError::from(HandshakeFailure::MismatchedReply);
+    assert_eq!(
+        err.to_string(),
+        "handshake with the endpoints server failed (reply for another device)"
+    );
+}
+
+/// Connects through a fake endpoints server that reads the handshake
+/// request, then answers it with `response` or closes the connection.
+async fn handshake(
+    visit_credentials: &[u8],
+    response: Option<EndPointHandshakeResponse>,
+) -> anyhow::Result<Result<(), CoreError>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let mut framed = Framed::new(stream, frame_codec());
+        let _request = framed.next().await;
+
+        if let Some(response) = response {
+            framed
+                .send(Bytes::from(bincode_serialize(&response)?))
+                .await?;
+            let _ = framed.next().await;
+        }
+
+        anyhow::Ok(())
+    });
+
+    assert!(handshake_tokens().issue(visit_credentials, Instant::now()));
+
+    let result = EndPointClient::new_file_manager_active(
+        EndPointID::DeviceID {
+            local_device_id: 1,
+            remote_device_id: 2,
+        },
+        None,
+        EndPointStream::ActiveTCP(addr),
+        Some(visit_credentials.to_vec()),
+    )
+    .await
+    .map(|client| client.close());
+
+    server.abort();
+    Ok(result)
+}
+
+#[tokio::test]
+async fn test_handshake_closed_by_server() -> anyhow::Result<()> {
+    let result = handshake(b"handshake-closed", None).await?;
+    assert!(matches!(
+        result,
+        Err(CoreError::HandshakeFailed(HandshakeFailure::Closed))
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_handshake_reply_for_another_device() -> anyhow::Result<()> {
+    let result = handshake(
+        b"handshake-mismatched",
+        Some(EndPointHandshakeResponse {
+            remote_device_id: 3,
+        }),
+    )
+    .await?;
+    assert!(matches!(
+        result,
+        Err(CoreError::HandshakeFailed(
+            HandshakeFailure::MismatchedReply
+        ))
+    ));
+
+    Ok(())
+}
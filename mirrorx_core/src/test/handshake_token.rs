@@ -0,0 +1,61 @@
+use crate::{
+    api::endpoint::handshake_token::{HandshakeTokenStatus, HandshakeTokens},
+    error::CoreError,
+};
+use std::time::{Duration, Instant};
+
+#[test]
+fn test_handshake_token_single_use() {
+    let tokens = HandshakeTokens::new(Duration::from_secs(30));
+    let now = Instant::now();
+
+    assert!(tokens.issue(b"credentials", now));
+    assert!(!tokens.issue(b"credentials", now));
+
+    let later = now + Duration::from_secs(1);
+    assert_eq!(
+        tokens.consume(b"credentials", later),
+        HandshakeTokenStatus::Accepted
+    );
+    assert_eq!(
+        tokens.consume(b"credentials", later),
+        HandshakeTokenStatus::Repeated
+    );
+    assert_eq!(
+        tokens.consume(b"other", later),
+        HandshakeTokenStatus::Unknown
+    );
+
+    assert!(matches!(
+        HandshakeTokenStatus::Repeated.into_result(),
+        Err(CoreError::HandshakeTokenRepeated)
+    ));
+}
+
+#[test]
+fn test_handshake_token_expiry() {
+    let tokens = HandshakeTokens::new(Duration::from_secs(30));
+    let now = Instant::now();
+
+    tokens.issue(b"stale", now);
+    tokens.issue(b"used", now);
+    tokens.issue(b"fresh", now + Duration::from_secs(20));
+    tokens.consume(b"used", now);
+
+    let later = now + Duration::from_secs(31);
+    assert_eq!(
+        tokens.consume(b"stale", later),
+        HandshakeTokenStatus::Expired
+    );
+
+    assert_eq!(tokens.purge(later), 2);
+    assert_eq!(tokens.len(), 1);
+    assert_eq!(
+        tokens.consume(b"stale", later),
+        HandshakeTokenStatus::Unknown
+    );
+    assert_eq!(
+        tokens.consume(b"fresh", later),
+        HandshakeTokenStatus::Accepted
+    );
+}
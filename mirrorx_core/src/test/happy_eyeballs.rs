@@ -0,0 +1,115 @@
+use crate::{
+    core_error,
+    error::{CoreError, CoreResult},
+    utility::happy_eyeballs::{connect_first, interleave_addrs, CONNECTION_ATTEMPT_DELAY},
+};
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Sets the flag once the attempt holding it is dropped.
+struct DropFlag(Arc<AtomicBool>);
+
+impl Drop for DropFlag {
+    fn drop(&mut self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// What a dual stack host resolves to, an IPv6 address first.
+fn resolve_dual_stack(port: u16) -> anyhow::Result<Vec<SocketAddr>> {
+    Ok(vec![
+        format!("[2001:db8::1]:{port}").parse()?,
+        format!("127.0.0.1:{port}").parse()?,
+    ])
+}
+
+#[test]
+fn test_interleave_addrs() -> anyhow::Result<()> {
+    let v6a: SocketAddr = "[2001:db8::1]:80".parse()?;
+    let v6b: SocketAddr = "[2001:db8::2]:80".parse()?;
+    let v4a: SocketAddr = "192.0.2.1:80".parse()?;
+    let v4b: SocketAddr = "192.0.2.2:80".parse()?;
+
+    assert_eq!(
+        interleave_addrs(&[v6a, v6b, v4a, v4b]),
+        vec![v6a, v4a, v6b, v4b]
+    );
+    assert_eq!(interleave_addrs(&[v4a, v6a, v6b]), vec![v4a, v6a, v6b]);
+    assert_eq!(interleave_addrs(&[v4a, v4b]), vec![v4a, v4b]);
+    assert!(interleave_addrs(&[]).is_empty());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_dead_family_doesnt_hold_up_connect() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addrs = resolve_dual_stack(listener.local_addr()?.port())?;
+
+    let v6_dropped = Arc::new(AtomicBool::new(false));
+    let started_at = Instant::now();
+
+    let stream = connect_first(&addrs, Duration::from_secs(10), |addr| {
+        let drop_flag = addr.is_ipv6().then(|| DropFlag(v6_dropped.clone()));
+        async move {
+            // a black holed address family never answers
+            if let Some(_drop_flag) = drop_flag {
+                std::future::pending::<()>().await;
+            }
+
+            Ok(TcpStream::connect(addr).await?)
+        }
+    })
+    .await?;
+
+    assert!(stream.peer_addr()?.is_ipv4());
+    assert!(started_at.elapsed() >= CONNECTION_ATTEMPT_DELAY);
+    assert!(started_at.elapsed() < Duration::from_secs(2));
+    assert!(v6_dropped.load(Ordering::SeqCst));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_failed_attempt_starts_next_at_once() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addrs = resolve_dual_stack(listener.local_addr()?.port())?;
+    let started_at = Instant::now();
+
+    let stream = connect_first(&addrs, Duration::from_secs(10), |addr| async move {
+        if addr.is_ipv6() {
+            return Err::<TcpStream, _>(core_error!("network unreachable"));
+        }
+
+        Ok(TcpStream::connect(addr).await?)
+    })
+    .await?;
+
+    assert!(stream.peer_addr()?.is_ipv4());
+    assert!(started_at.elapsed() < CONNECTION_ATTEMPT_DELAY);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_connect_first_keeps_overall_timeout() -> anyhow::Result<()> {
+    let addrs = resolve_dual_stack(80)?;
+    let started_at = Instant::now();
+
+    let result: CoreResult<()> = connect_first(&addrs, Duration::from_millis(400), |_| {
+        std::future::pending()
+    })
+    .await;
+
+    assert!(matches!(result, Err(CoreError::Timeout)));
+    assert!(started_at.elapsed() < Duration::from_secs(2));
+
+    Ok(())
+}
@@ -0,0 +1,141 @@
+use crate::api::endpoint::{
+    client::EndPointClient,
+    heartbeat::{
+        spawn_heartbeat, HeartbeatConfig, HeartbeatMonitor, HeartbeatState, MAX_HEARTBEAT_FAILURES,
+        MIN_HEARTBEAT_INTERVAL_MS,
+    },
+    id::EndPointID,
+    message::EndPointMessage,
+    packet::serialize_packet,
+    EndPointStream,
+};
+use bytes::Bytes;
+use futures::SinkExt;
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+use tokio::net::TcpListener;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+#[test]
+fn test_heartbeat_monitor_degrades_then_disconnects() {
+    let mut monitor = HeartbeatMonitor::new(3);
+
+    assert_eq!(monitor.check(true), HeartbeatState::Connected);
+    assert_eq!(monitor.check(false), HeartbeatState::Degraded);
+    assert_eq!(monitor.check(false), HeartbeatState::Degraded);
+    assert_eq!(monitor.missed(), 2);
+    assert_eq!(monitor.check(false), HeartbeatState::Disconnected);
+}
+
+#[test]
+fn test_heartbeat_monitor_reply_resets() {
+    let mut monitor = HeartbeatMonitor::new(2);
+
+    assert_eq!(monitor.check(false), HeartbeatState::Degraded);
+    assert_eq!(monitor.check(true), HeartbeatState::Connected);
+    assert_eq!(monitor.missed(), 0);
+    assert_eq!(monitor.check(false), HeartbeatState::Degraded);
+}
+
+#[test]
+fn test_heartbeat_config_sanitize() {
+    let config = HeartbeatConfig {
+        interval_ms: 0,
+        failure_threshold: 0,
+    }
+    .sanitize();
+
+    assert_eq!(config.interval_ms, MIN_HEARTBEAT_INTERVAL_MS);
+    assert_eq!(config.failure_threshold, 1);
+
+    let config = HeartbeatConfig {
+        interval_ms: 3000,
+        failure_threshold: 1000,
+    }
+    .sanitize();
+
+    assert_eq!(config.interval_ms, 3000);
+    assert_eq!(config.failure_threshold, MAX_HEARTBEAT_FAILURES);
+}
+
+async fn connect(listener: &TcpListener) -> anyhow::Result<std::sync::Arc<EndPointClient>> {
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.4.1".parse::<IpAddr>()?,
+    };
+
+    Ok(EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?)
+}
+
+#[tokio::test]
+async fn test_heartbeat_closes_silent_connection() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let client = connect(&listener).await?;
+
+    // the remote side keeps the socket open but never answers
+    let (_stream, _) = listener.accept().await?;
+
+    let mut state = client.watch_heartbeat_state();
+    spawn_heartbeat(
+        &client,
+        HeartbeatConfig {
+            interval_ms: 500,
+            failure_threshold: 2,
+        },
+    );
+
+    let started_at = Instant::now();
+    tokio::time::timeout(Duration::from_secs(3), client.closed()).await?;
+    assert!(started_at.elapsed() >= Duration::from_millis(900));
+
+    assert_eq!(client.heartbeat_state(), HeartbeatState::Disconnected);
+    assert!(state.has_changed()?);
+    assert_eq!(*state.borrow_and_update(), HeartbeatState::Disconnected);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_heartbeat_keeps_answering_connection() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let client = connect(&listener).await?;
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Framed::new(
+        stream,
+        LengthDelimitedCodec::builder()
+            .little_endian()
+            .max_frame_length(32 * 1024 * 1024)
+            .new_codec(),
+    );
+
+    spawn_heartbeat(
+        &client,
+        HeartbeatConfig {
+            interval_ms: 500,
+            failure_threshold: 2,
+        },
+    );
+
+    // the remote side probes in turn
+    let probe = Bytes::from(serialize_packet(&EndPointMessage::RttProbe(0))?);
+    let started_at = Instant::now();
+    while started_at.elapsed() < Duration::from_secs(2) {
+        remote.send(probe.clone()).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert_eq!(client.heartbeat_state(), HeartbeatState::Connected);
+
+    client.close();
+
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use crate::api::endpoint::{
+    client::EndPointClient,
+    id::EndPointID,
+    idle::{IdleAction, IdleTimeoutPolicy, IdleTracker, IdleWatch},
+    message::{EndPointInput, EndPointMessage},
+    packet::serialize_packet,
+    EndPointStream,
+};
+use bytes::Bytes;
+use futures::SinkExt;
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+use tokio::net::TcpListener;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+#[test]
+fn test_idle_timeout_disabled_by_default() {
+    let policy = IdleTimeoutPolicy::default();
+    let mut watch = IdleWatch::default();
+
+    assert!(policy.thresholds().is_none());
+    assert_eq!(
+        watch.check(&policy, Duration::from_secs(24 * 60 * 60)),
+        IdleAction::Keep
+    );
+}
+
+#[test]
+fn test_idle_timeout_warns_once_then_closes() {
+    let policy = IdleTimeoutPolicy {
+        timeout_secs: Some(300),
+        warning_secs: 60,
+    };
+    let mut watch = IdleWatch::default();
+
+    assert_eq!(
+        watch.check(&policy, Duration::from_secs(100)),
+        IdleAction::Keep
+    );
+    assert_eq!(
+        watch.check(&policy, Duration::from_secs(250)),
+        IdleAction::Warn {
+            remaining: Duration::from_secs(50)
+        }
+    );
+    assert_eq!(
+        watch.check(&policy, Duration::from_secs(260)),
+        IdleAction::Keep
+    );
+    assert_eq!(
+        watch.check(&policy, Duration::from_secs(300)),
+        IdleAction::Close
+    );
+}
+
+#[test]
+fn test_idle_timeout_activity_rearms_warning() {
+    let policy = IdleTimeoutPolicy {
+        timeout_secs: Some(300),
+        warning_secs: 60,
+    };
+    let mut watch = IdleWatch::default();
+
+    assert!(matches!(
+        watch.check(&policy, Duration::from_secs(250)),
+        IdleAction::Warn { .. }
+    ));
+
+    // the user kept the session alive
+    assert_eq!(
+        watch.check(&policy, Duration::from_secs(1)),
+        IdleAction::Keep
+    );
+    assert!(matches!(
+        watch.check(&policy, Duration::from_secs(250)),
+        IdleAction::Warn { .. }
+    ));
+}
+
+#[test]
+fn test_idle_tracker_touch_resets() {
+    let tracker = IdleTracker::default();
+
+    std::thread::sleep(Duration::from_millis(50));
+    assert!(tracker.idle_for() >= Duration::from_millis(50));
+
+    tracker.touch();
+    assert!(tracker.idle_for() < Duration::from_millis(50));
+}
+
+#[test]
+fn test_message_is_activity() {
+    assert!(EndPointMessage::InputCommand(EndPointInput { events: Vec::new() }).is_activity());
+    assert!(EndPointMessage::KeepAlive.is_activity());
+    assert!(EndPointMessage::ChatMessage {
+        text: String::from("hi"),
+        timestamp: 0,
+    }
+    .is_activity());
+
+    assert!(!EndPointMessage::RttProbe(0).is_activity());
+    assert!(!EndPointMessage::RttProbeReply(0).is_activity());
+    assert!(!EndPointMessage::IncludeCursor(true).is_activity());
+}
+
+#[tokio::test]
+async fn test_heartbeat_doesnt_keep_session_alive() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.3.1".parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Framed::new(
+        stream,
+        LengthDelimitedCodec::builder()
+            .little_endian()
+            .max_frame_length(32 * 1024 * 1024)
+            .new_codec(),
+    );
+
+    let policy = IdleTimeoutPolicy {
+        timeout_secs: Some(1),
+        warning_secs: 0,
+    };
+    let mut watch = IdleWatch::default();
+
+    // the remote side probes and this side answers, but nobody works
+    let heartbeat = Bytes::from(serialize_packet(&EndPointMessage::RttProbe(0))?);
+    let started_at = Instant::now();
+    let closed_after = loop {
+        remote.send(heartbeat.clone()).await?;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        if watch.check(&policy, client.idle_for()) == IdleAction::Close {
+            break started_at.elapsed();
+        }
+
+        assert!(started_at.elapsed() < Duration::from_secs(3));
+    };
+    assert!(closed_after >= Duration::from_millis(900));
+    assert!(closed_after < Duration::from_millis(1500));
+
+    // the user keeping it alive does count
+    remote
+        .send(Bytes::from(serialize_packet(&EndPointMessage::KeepAlive)?))
+        .await?;
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    assert!(client.idle_for() < Duration::from_millis(500));
+
+    client.close();
+
+    Ok(())
+}
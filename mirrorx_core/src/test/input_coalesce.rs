@@ -0,0 +1,86 @@
+use crate::{
+    api::endpoint::message::{EndPointInput, InputEvent, KeyboardEvent, MouseEvent},
+    component::input::{coalesce::InputCoalescer, key::MouseKey},
+};
+use std::time::{Duration, Instant};
+
+fn mouse_move(x: f32) -> InputEvent {
+    InputEvent::Mouse(MouseEvent::Move(MouseKey::None, x, 0.0))
+}
+
+fn mouse_down(x: f32) -> InputEvent {
+    InputEvent::Mouse(MouseEvent::Down(MouseKey::Left, x, 0.0))
+}
+
+#[test]
+fn test_moves_coalesce_within_window() {
+    let mut coalescer = InputCoalescer::new(Duration::from_millis(8));
+    let start = Instant::now();
+
+    for i in 0..7 {
+        let now = start + Duration::from_millis(i);
+        assert_eq!(coalescer.push([mouse_move(i as f32)], now), None);
+    }
+
+    assert_eq!(
+        coalescer.push([mouse_move(8.0)], start + Duration::from_millis(8)),
+        Some(EndPointInput {
+            events: vec![mouse_move(8.0)]
+        })
+    );
+
+    // nothing left to send
+    assert_eq!(coalescer.push([], start + Duration::from_millis(20)), None);
+}
+
+#[test]
+fn test_click_keeps_order_with_coalesced_moves() {
+    let mut coalescer = InputCoalescer::new(Duration::from_millis(8));
+    let now = Instant::now();
+
+    let input = coalescer.push(
+        [
+            mouse_move(1.0),
+            mouse_move(2.0),
+            mouse_down(2.0),
+            mouse_move(3.0),
+            mouse_move(4.0),
+            InputEvent::Keyboard(KeyboardEvent::KeyDown(tao::keyboard::KeyCode::KeyA)),
+        ],
+        now,
+    );
+
+    // the click flushes at once, the moves collapse only between other events
+    assert_eq!(
+        input,
+        Some(EndPointInput {
+            events: vec![
+                mouse_move(2.0),
+                mouse_down(2.0),
+                mouse_move(4.0),
+                InputEvent::Keyboard(KeyboardEvent::KeyDown(tao::keyboard::KeyCode::KeyA)),
+            ]
+        })
+    );
+
+    // a move held back before the click goes out ahead of it
+    assert_eq!(coalescer.push([mouse_move(5.0)], now), None);
+    assert_eq!(
+        coalescer.push([mouse_down(5.0)], now),
+        Some(EndPointInput {
+            events: vec![mouse_move(5.0), mouse_down(5.0)]
+        })
+    );
+}
+
+#[test]
+fn test_zero_window_sends_at_once() {
+    let mut coalescer = InputCoalescer::new(Duration::ZERO);
+
+    assert_eq!(
+        coalescer.push([mouse_move(1.0)], Instant::now()),
+        Some(EndPointInput {
+            events: vec![mouse_move(1.0)]
+        })
+    );
+}
@@ -0,0 +1,112 @@
+use crate::{
+    api::endpoint::message::KeyboardEvent,
+    component::input::encoding::{InputEncoding, KeyEncoder},
+};
+use tao::keyboard::KeyCode;
+
+fn down(key: KeyCode) -> KeyboardEvent {
+    KeyboardEvent::KeyDown(key)
+}
+
+fn up(key: KeyCode) -> KeyboardEvent {
+    KeyboardEvent::KeyUp(key)
+}
+
+fn text(text: &str) -> KeyboardEvent {
+    KeyboardEvent::Text(text.to_string())
+}
+
+#[test]
+fn test_scancode_sends_physical_keys() {
+    let mut encoder = KeyEncoder::new(InputEncoding::Scancode);
+
+    // a German viewer types `z` with the key a US host knows as `Y`
+    assert_eq!(encoder.key(KeyCode::KeyY, true), vec![down(KeyCode::KeyY)]);
+    assert!(encoder.text("z").is_empty());
+    assert_eq!(encoder.key(KeyCode::KeyY, false), vec![up(KeyCode::KeyY)]);
+    assert!(encoder.flush().is_empty());
+}
+
+#[test]
+fn test_unicode_sends_typed_text() {
+    let mut encoder = KeyEncoder::new(InputEncoding::Unicode);
+
+    // the same key arrives as the character the viewer layout produced
+    assert!(encoder.key(KeyCode::KeyY, true).is_empty());
+    assert_eq!(encoder.text("z"), vec![text("z")]);
+    assert!(encoder.key(KeyCode::KeyY, false).is_empty());
+
+    // shifted characters of a French layout, the modifier goes as scancode
+    assert_eq!(
+        encoder.key(KeyCode::ShiftLeft, true),
+        vec![down(KeyCode::ShiftLeft)]
+    );
+    assert!(encoder.key(KeyCode::Digit2, true).is_empty());
+    assert_eq!(encoder.text("é"), vec![text("é")]);
+    assert!(encoder.key(KeyCode::Digit2, false).is_empty());
+    assert_eq!(
+        encoder.key(KeyCode::ShiftLeft, false),
+        vec![up(KeyCode::ShiftLeft)]
+    );
+
+    // a held key repeats its text and is released once
+    assert!(encoder.key(KeyCode::KeyA, true).is_empty());
+    assert_eq!(encoder.text("a"), vec![text("a")]);
+    assert!(encoder.key(KeyCode::KeyA, true).is_empty());
+    assert_eq!(encoder.text("a"), vec![text("a")]);
+    assert!(encoder.key(KeyCode::KeyA, false).is_empty());
+    assert!(encoder.flush().is_empty());
+}
+
+#[test]
+fn test_unicode_keeps_scancodes_for_shortcuts() {
+    let mut encoder = KeyEncoder::new(InputEncoding::Unicode);
+
+    // Command+C on a macOS viewer produces no text, the key goes out as is
+    assert_eq!(
+        encoder.key(KeyCode::SuperLeft, true),
+        vec![down(KeyCode::SuperLeft)]
+    );
+    assert!(encoder.key(KeyCode::KeyC, true).is_empty());
+    assert_eq!(
+        encoder.key(KeyCode::KeyC, false),
+        vec![down(KeyCode::KeyC), up(KeyCode::KeyC)]
+    );
+    assert_eq!(
+        encoder.key(KeyCode::SuperLeft, false),
+        vec![up(KeyCode::SuperLeft)]
+    );
+
+    // held over the end of a frame
+    assert!(encoder.key(KeyCode::KeyV, true).is_empty());
+    assert_eq!(encoder.flush(), vec![down(KeyCode::KeyV)]);
+    assert_eq!(encoder.key(KeyCode::KeyV, false), vec![up(KeyCode::KeyV)]);
+
+    // function and navigation keys never wait for text
+    assert_eq!(encoder.key(KeyCode::F5, true), vec![down(KeyCode::F5)]);
+    assert_eq!(
+        encoder.key(KeyCode::ArrowLeft, true),
+        vec![down(KeyCode::ArrowLeft)]
+    );
+    assert_eq!(
+        encoder.key(KeyCode::Enter, true),
+        vec![down(KeyCode::Enter)]
+    );
+}
+
+#[test]
+fn test_unicode_passes_composed_text() {
+    let mut encoder = KeyEncoder::new(InputEncoding::Unicode);
+
+    // text committed by an input method comes without a key
+    assert_eq!(encoder.text("日本"), vec![text("日本")]);
+
+    // switching encodings sends the waiting key first
+    assert!(encoder.key(KeyCode::KeyQ, true).is_empty());
+    assert_eq!(
+        encoder.set_encoding(InputEncoding::Scancode),
+        vec![down(KeyCode::KeyQ)]
+    );
+    assert_eq!(encoder.encoding(), InputEncoding::Scancode);
+    assert_eq!(encoder.key(KeyCode::KeyQ, false), vec![up(KeyCode::KeyQ)]);
+}
@@ -0,0 +1,74 @@
+use crate::api::config::{
+    integrity::{check_storage_integrity, repair_storage},
+    LocalStorage, Storage,
+};
+
+#[test]
+fn test_repair_storage_keeps_rows() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("mirrorx_integrity_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let db_path = dir.join("mirrorx.db");
+
+    let storage = LocalStorage::new(&db_path)?;
+    storage.kv().set_language("en")?;
+    storage.history().create(1234567890, "mirrorx.cloud")?;
+    drop(storage);
+
+    assert!(check_storage_integrity(&db_path)?.ok);
+
+    let report = repair_storage(&db_path)?;
+    assert!(report.backup_path.exists());
+    for table in report.tables.iter() {
+        assert!(table.error.is_none());
+        assert_eq!(table.lost, 0);
+    }
+
+    let storage = LocalStorage::new(&db_path)?;
+    assert_eq!(storage.kv().get_language()?, Some(String::from("en")));
+    assert_eq!(storage.history().query(None)?.len(), 1);
+    drop(storage);
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+fn test_repair_storage_other_schema() -> anyhow::Result<()> {
+    let dir = std::env::temp_dir().join(format!("mirrorx_integrity_schema_{}", std::process::id()));
+    std::fs::create_dir_all(&dir)?;
+    let db_path = dir.join("mirrorx.db");
+
+    let storage = LocalStorage::new(&db_path)?;
+    storage.kv().set_language("en")?;
+    storage.history().create(1234567890, "mirrorx.cloud")?;
+    drop(storage);
+
+    // a column the current schema dropped and a table it added since
+    let conn = rusqlite::Connection::open(&db_path)?;
+    conn.execute("ALTER TABLE history ADD COLUMN obsolete TEXT", [])?;
+    conn.execute("DROP TABLE allow_list", [])?;
+    drop(conn);
+
+    let report = repair_storage(&db_path)?;
+    for table in report.tables.iter() {
+        assert!(table.error.is_none(), "{table:?}");
+        assert_eq!(table.lost, 0);
+    }
+
+    let history = report
+        .tables
+        .iter()
+        .find(|table| table.table == "history")
+        .unwrap();
+    assert_eq!(history.recovered, 1);
+
+    let storage = LocalStorage::new(&db_path)?;
+    assert_eq!(storage.kv().get_language()?, Some(String::from("en")));
+    assert_eq!(storage.history().query(None)?.len(), 1);
+    drop(storage);
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
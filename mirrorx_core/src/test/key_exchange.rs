@@ -0,0 +1,246 @@
+use crate::api::signaling::{
+    key_exchange::{
+        derive_session_key, key_agreement, ActiveKeyExchange, KeyDerivation, KeyDirection,
+    },
+    subscribe_message::VisitFailureReason,
+};
+use rand::{rngs::StdRng, SeedableRng};
+use ring::aead::Aad;
+
+// big enough for the reply of the passive device, small enough to generate
+// quickly in debug builds
+const TEST_REPLY_KEY_BITS: usize = 1024;
+
+fn reply_private_key(rng: &mut StdRng) -> rsa::RsaPrivateKey {
+    rsa::RsaPrivateKey::new(rng, TEST_REPLY_KEY_BITS).unwrap()
+}
+
+#[test]
+fn test_derive_session_key_vector() {
+    let key_material = [0x0b; 32];
+    let active_nonce: Vec<u8> = (1..=12).collect();
+    let passive_nonce: Vec<u8> = (13..=24).collect();
+
+    assert_eq!(
+        derive_session_key(&key_material, &active_nonce).unwrap(),
+        hex_bytes("ad0670a8d96500f9a09a19d45d740bbe1217c80af58d5c97b0a880d9b53f4adf")
+    );
+    assert_eq!(
+        derive_session_key(&key_material, &passive_nonce).unwrap(),
+        hex_bytes("b88ea188ed239db4124ed8d7777299633339c3483a6d02657116c5e3bfc89120")
+    );
+}
+
+#[test]
+fn test_labeled_session_key_vector() {
+    let key_material = [0x0b; 32];
+    let active_nonce: Vec<u8> = (1..=12).collect();
+    let passive_nonce: Vec<u8> = (13..=24).collect();
+
+    assert_eq!(
+        KeyDerivation::Labeled
+            .derive(&key_material, &active_nonce, KeyDirection::ActiveToPassive)
+            .unwrap(),
+        hex_bytes("10055ecb9245ea8d4fb960560520b2e0bbed02f315b6d997479b00d48b5de807")
+    );
+    assert_eq!(
+        KeyDerivation::Labeled
+            .derive(&key_material, &passive_nonce, KeyDirection::PassiveToActive)
+            .unwrap(),
+        hex_bytes("a41da6ea0f802f901042eb414fbb9a7775d972365b336339987f01552338e4dc")
+    );
+
+    // devices that don't negotiate keep deriving what they did
+    assert_eq!(
+        KeyDerivation::Unlabeled
+            .derive(&key_material, &active_nonce, KeyDirection::ActiveToPassive)
+            .unwrap(),
+        derive_session_key(&key_material, &active_nonce).unwrap()
+    );
+
+    assert_eq!(KeyDerivation::negotiate(0), KeyDerivation::Unlabeled);
+    assert_eq!(KeyDerivation::negotiate(1), KeyDerivation::Labeled);
+    assert_eq!(KeyDerivation::negotiate(9), KeyDerivation::LATEST);
+}
+
+#[test]
+fn test_key_exchange_seeded_rng() {
+    let mut rng = StdRng::seed_from_u64(146);
+    let reply_private_key = reply_private_key(&mut rng);
+
+    let first = ActiveKeyExchange::new(
+        &mut StdRng::seed_from_u64(7),
+        reply_private_key.clone(),
+        1,
+        "password",
+    )
+    .unwrap();
+    let second = ActiveKeyExchange::new(
+        &mut StdRng::seed_from_u64(7),
+        reply_private_key,
+        1,
+        "password",
+    )
+    .unwrap();
+
+    assert_eq!(first.password_salt, second.password_salt);
+    assert_eq!(first.secret_nonce, second.secret_nonce);
+    assert_ne!(first.password_salt, [0u8; 16]);
+}
+
+#[test]
+fn test_key_exchange_round_trip() {
+    let mut rng = StdRng::seed_from_u64(146);
+    let reply_private_key = reply_private_key(&mut rng);
+
+    let active = ActiveKeyExchange::new(&mut rng, reply_private_key, 1, "password").unwrap();
+
+    let (reply, mut passive_sealing_key, mut passive_opening_key) = key_agreement(
+        &mut rng,
+        "password",
+        1,
+        &active.password_salt,
+        active.secret.clone(),
+        &active.secret_nonce,
+    )
+    .unwrap();
+
+    let (mut active_opening_key, mut active_sealing_key) = active.open_reply(&reply).unwrap();
+
+    let mut buffer = b"active to passive".to_vec();
+    active_sealing_key
+        .seal_in_place_append_tag(Aad::empty(), &mut buffer)
+        .unwrap();
+    let opened = passive_opening_key
+        .open_in_place(Aad::empty(), &mut buffer)
+        .unwrap();
+    assert_eq!(opened, b"active to passive");
+
+    let mut buffer = b"passive to active".to_vec();
+    passive_sealing_key
+        .seal_in_place_append_tag(Aad::empty(), &mut buffer)
+        .unwrap();
+    let opened = active_opening_key
+        .open_in_place(Aad::empty(), &mut buffer)
+        .unwrap();
+    assert_eq!(opened, b"passive to active");
+}
+
+#[test]
+fn test_key_exchange_unlabeled_offer() {
+    let mut rng = StdRng::seed_from_u64(158);
+    let reply_private_key = reply_private_key(&mut rng);
+
+    let labeled = ActiveKeyExchange::new(
+        &mut StdRng::seed_from_u64(7),
+        reply_private_key.clone(),
+        1,
+        "password",
+    )
+    .unwrap();
+    let unlabeled = ActiveKeyExchange::with_key_derivation(
+        &mut StdRng::seed_from_u64(7),
+        reply_private_key,
+        1,
+        "password",
+        KeyDerivation::Unlabeled,
+    )
+    .unwrap();
+
+    // the plain secret older passive devices understand, without the version
+    assert_eq!(labeled.secret.len(), unlabeled.secret.len() + 1);
+
+    let (reply, mut passive_sealing_key, mut passive_opening_key) = key_agreement(
+        &mut rng,
+        "password",
+        1,
+        &unlabeled.password_salt,
+        unlabeled.secret.clone(),
+        &unlabeled.secret_nonce,
+    )
+    .unwrap();
+
+    let (mut active_opening_key, mut active_sealing_key) = unlabeled.open_reply(&reply).unwrap();
+
+    let mut buffer = b"active to passive".to_vec();
+    active_sealing_key
+        .seal_in_place_append_tag(Aad::empty(), &mut buffer)
+        .unwrap();
+    let opened = passive_opening_key
+        .open_in_place(Aad::empty(), &mut buffer)
+        .unwrap();
+    assert_eq!(opened, b"active to passive");
+
+    let mut buffer = b"passive to active".to_vec();
+    passive_sealing_key
+        .seal_in_place_append_tag(Aad::empty(), &mut buffer)
+        .unwrap();
+    let opened = active_opening_key
+        .open_in_place(Aad::empty(), &mut buffer)
+        .unwrap();
+    assert_eq!(opened, b"passive to active");
+}
+
+#[test]
+fn test_key_agreement_wrong_password() {
+    let mut rng = StdRng::seed_from_u64(146);
+    let reply_private_key = reply_private_key(&mut rng);
+
+    let active = ActiveKeyExchange::new(&mut rng, reply_private_key, 1, "password").unwrap();
+
+    assert!(matches!(
+        key_agreement(
+            &mut rng,
+            "wrong password",
+            1,
+            &active.password_salt,
+            active.secret.clone(),
+            &active.secret_nonce,
+        ),
+        Err(VisitFailureReason::InvalidPassword)
+    ));
+
+    // the secret is bound to the id of the active device
+    assert!(matches!(
+        key_agreement(
+            &mut rng,
+            "password",
+            2,
+            &active.password_salt,
+            active.secret,
+            &active.secret_nonce,
+        ),
+        Err(VisitFailureReason::InvalidPassword)
+    ));
+}
+
+#[test]
+fn test_key_agreement_rejects_password_prefixes() {
+    let mut rng = StdRng::seed_from_u64(262);
+    let reply_private_key = reply_private_key(&mut rng);
+
+    let active = ActiveKeyExchange::new(&mut rng, reply_private_key, 1, "password").unwrap();
+
+    // the password only keys the seal, a guess sharing a longer prefix
+    // fails the same tag check as any other
+    for guess in ["", "p", "pass", "passwor", "password ", "passwordpassword"] {
+        assert!(matches!(
+            key_agreement(
+                &mut rng,
+                guess,
+                1,
+                &active.password_salt,
+                active.secret.clone(),
+                &active.secret_nonce,
+            ),
+            Err(VisitFailureReason::InvalidPassword)
+        ));
+    }
+}
+
+fn hex_bytes(hex: &str) -> Vec<u8> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+        .collect()
+}
@@ -0,0 +1,66 @@
+use crate::api::signaling::key_pool::KeyPool;
+use rsa::{rand_core::OsRng, RsaPrivateKey};
+use std::time::{Duration, Instant};
+use tokio_util::sync::CancellationToken;
+
+const TEST_KEY_BITS: usize = 1024;
+const TEST_TTL: Duration = Duration::from_secs(60);
+
+fn generate_key() -> RsaPrivateKey {
+    RsaPrivateKey::new(&mut OsRng, TEST_KEY_BITS).unwrap()
+}
+
+#[tokio::test]
+async fn test_take_or_generate_uses_pooled_key() {
+    let pool = KeyPool::new(TEST_KEY_BITS, 2, TEST_TTL);
+    let key = generate_key();
+    assert!(pool.put(Instant::now(), key.clone()));
+
+    let taken = pool.take_or_generate().await.unwrap();
+    assert_eq!(taken.to_public_key(), key.to_public_key());
+
+    let stats = pool.stats(Instant::now());
+    assert_eq!((stats.ready, stats.hits, stats.misses), (0, 1, 0));
+
+    // an empty pool generates a key of its own
+    let generated = pool.take_or_generate().await.unwrap();
+    assert_ne!(generated.to_public_key(), key.to_public_key());
+
+    let stats = pool.stats(Instant::now());
+    assert_eq!((stats.hits, stats.misses), (1, 1));
+}
+
+#[test]
+fn test_key_pool_bounded() {
+    let pool = KeyPool::new(TEST_KEY_BITS, 1, TEST_TTL);
+    let now = Instant::now();
+
+    assert!(pool.put(now, generate_key()));
+    assert!(!pool.put(now, generate_key()));
+    assert_eq!(pool.missing(), 0);
+}
+
+#[test]
+fn test_key_pool_expires_keys() {
+    let pool = KeyPool::new(TEST_KEY_BITS, 2, TEST_TTL);
+    let now = Instant::now();
+
+    pool.put(now, generate_key());
+    pool.put(now + TEST_TTL / 2, generate_key());
+
+    assert_eq!(pool.purge(now + TEST_TTL), 1);
+    assert!(pool.take(now + TEST_TTL * 2).is_none());
+    assert_eq!(pool.missing(), 2);
+}
+
+#[tokio::test]
+async fn test_key_pool_fill() {
+    let pool = KeyPool::new(TEST_KEY_BITS, 1, TEST_TTL);
+
+    let cancelled = CancellationToken::new();
+    cancelled.cancel();
+    assert_eq!(pool.fill(&cancelled).await.unwrap(), 0);
+
+    assert_eq!(pool.fill(&CancellationToken::new()).await.unwrap(), 1);
+    assert_eq!(pool.stats(Instant::now()).ready, 1);
+}
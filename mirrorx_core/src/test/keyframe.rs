@@ -0,0 +1,78 @@
+use crate::{
+    api::endpoint::message::VideoCodec,
+    component::video_decoder::keyframe::{
+        is_h264_keyframe, is_hevc_keyframe, is_keyframe, KeyFrameGate,
+    },
+};
+
+// nal unit header bytes: 0x67 sps, 0x68 pps, 0x65 idr slice, 0x41 non-idr slice
+const KEY_FRAME: &[u8] = &[
+    0, 0, 0, 1, 0x67, 0x42, 0, 0, 0, 1, 0x68, 0xCE, 0, 0, 1, 0x65, 0x88,
+];
+const DELTA_FRAME: &[u8] = &[0, 0, 0, 1, 0x41, 0x9A, 0x00];
+
+// hevc nal unit header bytes: 0x40 vps, 0x42 sps, 0x44 pps, 0x26 idr slice,
+// 0x02 trailing slice
+const HEVC_KEY_FRAME: &[u8] = &[
+    0, 0, 0, 1, 0x40, 0x01, 0, 0, 1, 0x42, 0x01, 0, 0, 1, 0x44, 0x01, 0, 0, 1, 0x26, 0x01,
+];
+const HEVC_DELTA_FRAME: &[u8] = &[0, 0, 0, 1, 0x02, 0x01, 0xD0];
+
+#[test]
+fn test_is_h264_keyframe() {
+    assert!(is_h264_keyframe(KEY_FRAME));
+    assert!(is_h264_keyframe(&[0, 0, 1, 0x65, 0x88]));
+    assert!(!is_h264_keyframe(DELTA_FRAME));
+    assert!(!is_h264_keyframe(&[]));
+    assert!(!is_h264_keyframe(&[0, 0, 1]));
+}
+
+#[test]
+fn test_keyframe_gate_starts_from_keyframe() {
+    let stream = [
+        DELTA_FRAME,
+        DELTA_FRAME,
+        KEY_FRAME,
+        DELTA_FRAME,
+        DELTA_FRAME,
+    ];
+
+    let mut gate = KeyFrameGate::default();
+    let admitted: Vec<&[u8]> = stream
+        .into_iter()
+        .filter(|buffer| gate.admit(buffer))
+        .collect();
+
+    assert_eq!(admitted, vec![KEY_FRAME, DELTA_FRAME, DELTA_FRAME]);
+
+    // a recreated decoder waits for the next keyframe again
+    gate.reset();
+    assert!(!gate.admit(DELTA_FRAME));
+    assert!(gate.admit(KEY_FRAME));
+    assert!(gate.admit(DELTA_FRAME));
+}
+
+#[test]
+fn test_is_hevc_keyframe() {
+    assert!(is_hevc_keyframe(HEVC_KEY_FRAME));
+    // a CRA picture without parameter sets in front
+    assert!(is_hevc_keyframe(&[0, 0, 1, 0x2A, 0x01]));
+    assert!(!is_hevc_keyframe(HEVC_DELTA_FRAME));
+    assert!(!is_hevc_keyframe(&[]));
+
+    // the nal unit headers of either codec mean something else in the other
+    assert!(!is_hevc_keyframe(KEY_FRAME));
+    assert!(!is_h264_keyframe(HEVC_KEY_FRAME));
+    assert!(is_keyframe(&VideoCodec::Hevc, HEVC_KEY_FRAME));
+    assert!(is_keyframe(&VideoCodec::H264, KEY_FRAME));
+}
+
+#[test]
+fn test_keyframe_gate_of_hevc() {
+    let mut gate = KeyFrameGate::new(VideoCodec::Hevc);
+
+    assert!(!gate.admit(HEVC_DELTA_FRAME));
+    assert!(!gate.admit(KEY_FRAME));
+    assert!(gate.admit(HEVC_KEY_FRAME));
+    assert!(gate.admit(HEVC_DELTA_FRAME));
+}
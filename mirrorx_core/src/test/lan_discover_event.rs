@@ -0,0 +1,93 @@
+use crate::component::lan::{
+    discover::Node,
+    discover_event::{DiscoverEvent, NodeTracker},
+};
+use std::time::{Duration, Instant};
+
+fn lan_node(addr: &str, device_name: &str, last_seen: i64) -> Node {
+    Node {
+        host_name: String::from("host"),
+        device_name: Some(String::from(device_name)),
+        addr: addr.parse().unwrap(),
+        os: String::from("Linux"),
+        os_version: String::from("6.0"),
+        nickname: None,
+        tag: None,
+        last_seen,
+    }
+}
+
+#[test]
+fn test_node_tracker_added_and_updated() {
+    let mut tracker = NodeTracker::new(Duration::from_secs(17));
+    let now = Instant::now();
+
+    assert!(matches!(
+        tracker.observe(lan_node("192.168.1.2", "Office PC", 1), now),
+        Some(DiscoverEvent::Added { node }) if node.last_seen == 1
+    ));
+
+    // broadcasting again only keeps the device listed
+    assert!(tracker
+        .observe(
+            lan_node("192.168.1.2", "Office PC", 2),
+            now + Duration::from_secs(11)
+        )
+        .is_none());
+
+    assert!(matches!(
+        tracker.observe(
+            lan_node("192.168.1.2", "Meeting Room", 3),
+            now + Duration::from_secs(22)
+        ),
+        Some(DiscoverEvent::Updated { node })
+            if node.device_name.as_deref() == Some("Meeting Room") && node.last_seen == 3
+    ));
+
+    assert_eq!(tracker.snapshot(now + Duration::from_secs(22)).len(), 1);
+}
+
+#[test]
+fn test_node_tracker_removed_after_ttl() {
+    let mut tracker = NodeTracker::new(Duration::from_secs(17));
+    let now = Instant::now();
+
+    tracker.observe(lan_node("192.168.1.2", "Office PC", 1), now);
+    tracker.observe(
+        lan_node("192.168.1.3", "Meeting Room", 2),
+        now + Duration::from_secs(10),
+    );
+
+    assert!(tracker.expire(now + Duration::from_secs(16)).is_empty());
+
+    // not swept yet but not listed either
+    assert_eq!(tracker.snapshot(now + Duration::from_secs(17)).len(), 1);
+
+    let events = tracker.expire(now + Duration::from_secs(17));
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        &events[0],
+        DiscoverEvent::Removed { node } if node.last_seen == 1
+    ));
+
+    assert!(matches!(
+        tracker.remove("192.168.1.3".parse().unwrap()),
+        Some(DiscoverEvent::Removed { node }) if node.last_seen == 2
+    ));
+    assert!(tracker.remove("192.168.1.3".parse().unwrap()).is_none());
+    assert!(tracker.clear().is_empty());
+}
+
+#[test]
+fn test_discover_event_serialize() -> anyhow::Result<()> {
+    let event = DiscoverEvent::Removed {
+        node: lan_node("192.168.1.2", "Office PC", 1000),
+    };
+
+    let value = serde_json::to_value(&event)?;
+    assert_eq!(value["kind"], "removed");
+    assert_eq!(value["node"]["addr"], "192.168.1.2");
+    assert_eq!(value["node"]["last_seen"], 1000);
+
+    Ok(())
+}
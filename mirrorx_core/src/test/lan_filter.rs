@@ -0,0 +1,138 @@
+use crate::{
+    api::config::device_tag::{sanitize_device_tag, validate_device_tag, MAX_DEVICE_TAG_CHARS},
+    component::lan::discover::{
+        deserialize_broadcast_packet, filter_nodes, BroadcastPacket, Node, NodeFilter,
+        TargetLivePacket,
+    },
+};
+use serde::Serialize;
+
+#[test]
+fn test_validate_device_tag() {
+    assert_eq!(validate_device_tag(" floor-3 ").unwrap(), "floor-3");
+    assert!(validate_device_tag("").is_err());
+    assert!(validate_device_tag("floor 3").is_err());
+    assert!(validate_device_tag(&"a".repeat(MAX_DEVICE_TAG_CHARS + 1)).is_err());
+}
+
+#[test]
+fn test_sanitize_device_tag() {
+    assert_eq!(sanitize_device_tag(""), None);
+    assert_eq!(sanitize_device_tag(" \t\n"), None);
+    assert_eq!(
+        sanitize_device_tag("lab\u{7} 2"),
+        Some(String::from("lab2"))
+    );
+    assert_eq!(
+        sanitize_device_tag(&"x".repeat(MAX_DEVICE_TAG_CHARS * 2)).map(|tag| tag.len()),
+        Some(MAX_DEVICE_TAG_CHARS)
+    );
+}
+
+/// `BroadcastPacket` of the versions with a device name but before the tag.
+#[derive(Serialize)]
+enum UntaggedBroadcastPacket {
+    TargetLive {
+        host_name: String,
+        os: String,
+        os_version: String,
+        device_name: String,
+    },
+}
+
+#[test]
+fn test_broadcast_packet_with_tag() -> anyhow::Result<()> {
+    let buffer = bincode::serialize(&BroadcastPacket::TargetLive(TargetLivePacket {
+        host_name: String::from("desktop-1234"),
+        os: String::from("Linux"),
+        os_version: String::from("6.0"),
+        device_name: String::from("Office PC"),
+        tag: String::from("floor-3"),
+    }))?;
+
+    let BroadcastPacket::TargetLive(live_packet) = deserialize_broadcast_packet(&buffer)? else {
+        panic!("live packet expected");
+    };
+    assert_eq!(live_packet.device_name, "Office PC");
+    assert_eq!(live_packet.tag, "floor-3");
+
+    let buffer = bincode::serialize(&UntaggedBroadcastPacket::TargetLive {
+        host_name: String::from("desktop-5678"),
+        os: String::from("Windows"),
+        os_version: String::from("10"),
+        device_name: String::from("Meeting Room"),
+    })?;
+
+    let BroadcastPacket::TargetLive(live_packet) = deserialize_broadcast_packet(&buffer)? else {
+        panic!("live packet expected");
+    };
+    assert_eq!(live_packet.host_name, "desktop-5678");
+    assert_eq!(live_packet.device_name, "Meeting Room");
+    assert_eq!(live_packet.tag, "");
+
+    Ok(())
+}
+
+fn lan_node(host_name: &str, device_name: Option<&str>, tag: Option<&str>) -> Node {
+    Node {
+        host_name: String::from(host_name),
+        device_name: device_name.map(String::from),
+        addr: "192.168.1.2".parse().unwrap(),
+        os: String::from("Linux"),
+        os_version: String::from("6.0"),
+        nickname: None,
+        tag: tag.map(String::from),
+        last_seen: 0,
+    }
+}
+
+#[test]
+fn test_node_filter() {
+    let office = lan_node("desktop-1234", Some("Office PC"), Some("floor-3"));
+    let meeting = lan_node("desktop-5678", Some("Meeting Room"), None);
+    let mut nicknamed = lan_node("laptop", None, Some("Floor-3"));
+    nicknamed.nickname = Some(String::from("Alice"));
+
+    assert!(NodeFilter::default().matches(&meeting));
+
+    let filter = NodeFilter {
+        name_prefix: Some(String::from("office")),
+        tag: None,
+    };
+    assert!(filter.matches(&office));
+    assert!(!filter.matches(&meeting));
+
+    // the host name and the nickname count as names as well
+    let filter = NodeFilter {
+        name_prefix: Some(String::from("DESKTOP-56")),
+        tag: None,
+    };
+    assert!(filter.matches(&meeting));
+    let filter = NodeFilter {
+        name_prefix: Some(String::from("ali")),
+        tag: None,
+    };
+    assert!(filter.matches(&nicknamed));
+
+    // only the start of a name matches
+    let filter = NodeFilter {
+        name_prefix: Some(String::from("PC")),
+        tag: None,
+    };
+    assert!(!filter.matches(&office));
+
+    let filter = NodeFilter {
+        name_prefix: None,
+        tag: Some(String::from("FLOOR-3")),
+    };
+    let nodes = filter_nodes(vec![office, meeting, nicknamed], &filter);
+    assert_eq!(nodes.len(), 2);
+    assert!(nodes.iter().all(|node| node.tag.is_some()));
+
+    // a blank filter matches every device
+    let filter = NodeFilter {
+        name_prefix: Some(String::from(" ")),
+        tag: Some(String::new()),
+    };
+    assert!(filter.matches(&lan_node("host", None, None)));
+}
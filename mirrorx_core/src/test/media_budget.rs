@@ -0,0 +1,109 @@
+use crate::{
+    api::endpoint::{
+        handlers::video_frame::VideoDecodeMessage,
+        message::{EndPointVideoFrame, VideoCodec},
+    },
+    component::video_decoder::budget::{
+        auto_budget_bytes, queued_bytes, MediaBudget, MIN_AUTO_BUDGET_BYTES,
+    },
+};
+use std::{collections::VecDeque, sync::Arc};
+
+fn frame(seq: u64, keyframe: bool, bytes: usize) -> VideoDecodeMessage {
+    let nal_header = if keyframe { 0x65 } else { 0x41 };
+    let mut buffer = vec![0, 0, 1, nal_header];
+    buffer.resize(bytes, 0xAA);
+
+    VideoDecodeMessage::Frame(EndPointVideoFrame {
+        seq,
+        width: 1920,
+        height: 1080,
+        pts: seq as i64,
+        buffer,
+    })
+}
+
+fn seqs(pending: &VecDeque<VideoDecodeMessage>) -> Vec<u64> {
+    pending
+        .iter()
+        .filter_map(|message| match message {
+            VideoDecodeMessage::Frame(video_frame) => Some(video_frame.seq),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_auto_budget_follows_resolution() {
+    assert_eq!(auto_budget_bytes(640, 480), MIN_AUTO_BUDGET_BYTES);
+    assert!(auto_budget_bytes(3840, 2160) > auto_budget_bytes(1920, 1080));
+
+    let budget = MediaBudget::new(0);
+    assert_eq!(budget.limit_bytes(), 0);
+    budget.set_frame_size(3840, 2160);
+    assert_eq!(budget.limit_bytes(), auto_budget_bytes(3840, 2160));
+
+    // a configured budget stays whatever the resolution
+    let budget = MediaBudget::new(1024);
+    budget.set_frame_size(3840, 2160);
+    assert_eq!(budget.limit_bytes(), 1024);
+}
+
+#[test]
+fn test_media_budget_sheds_oldest_non_keyframes() {
+    let budget = MediaBudget::new(1000);
+
+    let mut pending: VecDeque<VideoDecodeMessage> = VecDeque::new();
+    pending.push_back(frame(0, true, 300));
+    pending.push_back(frame(1, false, 200));
+    pending.push_back(VideoDecodeMessage::FramesLost);
+    pending.push_back(frame(2, false, 200));
+    pending.push_back(frame(3, false, 200));
+    pending.push_back(frame(4, false, 200));
+
+    // 1100 bytes, the oldest delta frame goes and the keyframe stays
+    assert_eq!(budget.shed(&mut pending, &VideoCodec::H264), 1);
+    assert_eq!(seqs(&pending), vec![0, 2, 3, 4]);
+    assert!(matches!(pending[1], VideoDecodeMessage::FramesLost));
+    assert_eq!(budget.buffered_bytes(), queued_bytes(&pending));
+    assert_eq!(budget.buffered_bytes(), 900);
+
+    // only keyframes left over budget, the oldest of them goes as well
+    let mut pending: VecDeque<VideoDecodeMessage> =
+        (0..3).map(|seq| frame(seq, true, 600)).collect();
+    assert_eq!(budget.shed(&mut pending, &VideoCodec::H264), 2);
+    assert_eq!(seqs(&pending), vec![2]);
+    assert_eq!(budget.dropped_frames(), 3);
+}
+
+#[test]
+fn test_media_budget_stalled_renderer() {
+    let budget = Arc::new(MediaBudget::new(1000));
+    budget.set_reassembly_bytes(100);
+
+    // the renderer takes nothing, decoded frames pile up until the budget
+    let mut held = Vec::new();
+    while let Some(charge) = budget.admit_render(200) {
+        held.push(charge);
+        assert!(held.len() <= 4);
+    }
+
+    assert_eq!(held.len(), 4);
+    assert_eq!(budget.buffered_bytes(), 900);
+    assert_eq!(budget.dropped_renders(), 1);
+
+    // encoded frames waiting meanwhile are shed down to what's left
+    let mut pending: VecDeque<VideoDecodeMessage> =
+        (0..4).map(|seq| frame(seq, seq == 0, 100)).collect();
+    assert_eq!(budget.shed(&mut pending, &VideoCodec::H264), 3);
+    assert_eq!(seqs(&pending), vec![0]);
+    assert!(budget.buffered_bytes() <= 1000);
+
+    // the renderer catches up and releases the frames with their clones
+    let shared = held[0].clone();
+    held.clear();
+    assert_eq!(budget.buffered_bytes(), 100 + 200 + 100);
+    drop(shared);
+    assert_eq!(budget.buffered_bytes(), 100 + 100);
+    assert!(budget.admit_render(200).is_some());
+}
@@ -0,0 +1,85 @@
+use crate::component::{
+    frame::DesktopEncodeFrame,
+    video_encoder::media_config::{scale_frame, MediaConfig, QualityPreset},
+};
+use std::time::Duration;
+
+#[test]
+fn test_preset_values() {
+    let low = MediaConfig::from_preset(QualityPreset::Low);
+    assert_eq!(low.frame_rate, 20);
+    assert_eq!(low.scale_percent, 50);
+
+    let sharp_text = MediaConfig::from_preset(QualityPreset::SharpText);
+    assert_eq!(sharp_text.scale_percent, 100);
+    assert!(sharp_text.keyframe_interval < MediaConfig::default().keyframe_interval);
+
+    assert_eq!(MediaConfig::default().preset, QualityPreset::Balanced);
+}
+
+#[test]
+fn test_scale_frame() {
+    let config = MediaConfig::from_preset(QualityPreset::Low);
+    let (width, height) = config.scaled_size(1921, 1080);
+    assert_eq!((width, height), (960, 540));
+
+    let frame = DesktopEncodeFrame {
+        capture_time: Duration::ZERO,
+        width: 4,
+        height: 4,
+        luminance_bytes: (0..16).collect(),
+        luminance_stride: 4,
+        chrominance_bytes: (0..8).collect(),
+        chrominance_stride: 4,
+        dirty_rects: None,
+    };
+
+    let frame = scale_frame(frame, 2, 2);
+    assert_eq!(frame.luminance_bytes, vec![0, 2, 8, 10]);
+    assert_eq!(frame.chrominance_bytes, vec![0, 1]);
+    assert_eq!(frame.luminance_stride, 2);
+}
+
+#[test]
+fn test_include_cursor_defaults_to_true() {
+    assert!(MediaConfig::default().include_cursor);
+
+    // configs stored before the field existed keep showing the pointer
+    let stored = r#"{"preset":"low","frame_rate":20,"scale_percent":50,"bitrate_kbps":1000,"keyframe_interval":240}"#;
+    let config: MediaConfig = serde_json::from_str(stored).unwrap();
+    assert!(config.include_cursor);
+    assert_eq!(config, MediaConfig::from_preset(QualityPreset::Low));
+
+    let config = MediaConfig {
+        include_cursor: false,
+        ..MediaConfig::default()
+    };
+    let json = serde_json::to_string(&config).unwrap();
+    assert_eq!(serde_json::from_str::<MediaConfig>(&json).unwrap(), config);
+}
+
+#[test]
+fn test_x264_settings() {
+    let config = MediaConfig::default();
+    assert_eq!(config.x264_preset, "ultrafast");
+    assert_eq!(config.x264_tune, "zerolatency");
+
+    let config = MediaConfig {
+        x264_preset: String::from("medium"),
+        x264_tune: String::from("none"),
+        ..MediaConfig::default()
+    }
+    .sanitize();
+    assert_eq!(config.x264_preset, "medium");
+    assert_eq!(config.x264_tune, "none");
+
+    // unknown values fall back instead of failing the encoder
+    let config = MediaConfig {
+        x264_preset: String::from("placebo"),
+        x264_tune: String::from("ZeroLatency"),
+        ..MediaConfig::default()
+    }
+    .sanitize();
+    assert_eq!(config.x264_preset, "ultrafast");
+    assert_eq!(config.x264_tune, "zerolatency");
+}
@@ -0,0 +1,91 @@
+use crate::{
+    api::endpoint::message::{EndPointMessage, MediaErrorReason},
+    component::desktop::capture_error::capture_failure_reason,
+    error::CoreError,
+    utility::bincode::{bincode_deserialize, bincode_serialize},
+};
+
+#[test]
+fn test_media_error_reason_round_trip() -> anyhow::Result<()> {
+    let reasons = [
+        MediaErrorReason::PermissionDenied,
+        MediaErrorReason::NoDisplay,
+        MediaErrorReason::CodecUnsupported,
+        MediaErrorReason::WindowClosed,
+        MediaErrorReason::CaptureFailed,
+        MediaErrorReason::Other,
+    ];
+
+    for reason in reasons {
+        let buffer = bincode_serialize(&EndPointMessage::Error { reason })?;
+        let message: EndPointMessage = bincode_deserialize(&buffer)?;
+        assert_eq!(message, EndPointMessage::Error { reason });
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_media_error_reason_maps_to_core_error() {
+    assert!(matches!(
+        CoreError::from(MediaErrorReason::PermissionDenied),
+        CoreError::MediaPermissionDenied
+    ));
+    assert!(matches!(
+        CoreError::from(MediaErrorReason::NoDisplay),
+        CoreError::MediaNoDisplay
+    ));
+    assert!(matches!(
+        CoreError::from(MediaErrorReason::CodecUnsupported),
+        CoreError::MediaCodecUnsupported
+    ));
+    assert!(matches!(
+        CoreError::from(MediaErrorReason::WindowClosed),
+        CoreError::MediaWindowClosed
+    ));
+    assert!(matches!(
+        CoreError::from(MediaErrorReason::CaptureFailed),
+        CoreError::MediaCaptureFailed
+    ));
+    assert!(matches!(
+        CoreError::from(MediaErrorReason::Other),
+        CoreError::MediaOtherError
+    ));
+}
+
+#[test]
+fn test_media_error_reason_unknown_is_other() -> anyhow::Result<()> {
+    // variant 0 of EndPointMessage followed by a reason index no version knows
+    let message: EndPointMessage = bincode_deserialize(&[0, 200])?;
+    assert_eq!(
+        message,
+        EndPointMessage::Error {
+            reason: MediaErrorReason::Other
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_capture_failure_reason() {
+    assert_eq!(
+        capture_failure_reason(&CoreError::ScreenCapturePermissionDenied),
+        MediaErrorReason::PermissionDenied
+    );
+    assert_eq!(
+        capture_failure_reason(&CoreError::NoDisplayAvailable),
+        MediaErrorReason::NoDisplay
+    );
+    assert_eq!(
+        capture_failure_reason(&CoreError::WindowClosed),
+        MediaErrorReason::WindowClosed
+    );
+
+    // the permission is granted or not needed here, a driver failure isn't
+    // reported as a denied permission
+    assert_eq!(
+        capture_failure_reason(&CoreError::Timeout),
+        MediaErrorReason::CaptureFailed
+    );
+}
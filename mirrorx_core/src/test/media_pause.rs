@@ -0,0 +1,82 @@
+use super::{connect, next_message, Remote};
+use crate::api::endpoint::{
+    media_pause::MediaPause, message::EndPointMessage, packet::serialize_packet,
+};
+use bytes::Bytes;
+use futures::SinkExt;
+use std::time::Duration;
+
+#[test]
+fn test_media_pause_drops_frames_until_keyframe_on_resume() {
+    let media_pause = MediaPause::default();
+    assert_eq!(media_pause.admit_frame(), Some(false));
+
+    assert!(media_pause.pause());
+    assert!(!media_pause.pause());
+    for _ in 0..10 {
+        assert_eq!(media_pause.admit_frame(), None);
+    }
+
+    assert!(media_pause.resume());
+    assert!(!media_pause.resume());
+    assert_eq!(media_pause.admit_frame(), Some(true));
+    assert_eq!(media_pause.admit_frame(), Some(false));
+
+    // a pause forgotten before resuming asks for no keyframe
+    media_pause.pause();
+    media_pause.reset();
+    assert_eq!(media_pause.admit_frame(), Some(false));
+}
+
+#[tokio::test]
+async fn test_media_pause_over_connection() -> anyhow::Result<()> {
+    let (client, mut remote) = connect("10.0.8.1").await?;
+
+    // the viewer side asks and remembers
+    client.pause_media()?;
+    assert!(client.media_paused());
+    assert!(client.network_info().media_paused);
+    assert!(matches!(
+        next_message(&mut remote).await,
+        Some(EndPointMessage::PauseMedia)
+    ));
+
+    client.resume_media()?;
+    assert!(!client.media_paused());
+    assert!(matches!(
+        next_message(&mut remote).await,
+        Some(EndPointMessage::ResumeMedia)
+    ));
+
+    // the host side holds back its frames while asked to
+    let _ = client.media_pause().admit_frame();
+
+    send(&mut remote, EndPointMessage::PauseMedia).await?;
+    wait_until(|| client.media_paused()).await;
+    assert_eq!(client.media_pause().admit_frame(), None);
+
+    send(&mut remote, EndPointMessage::ResumeMedia).await?;
+    wait_until(|| !client.media_paused()).await;
+    assert_eq!(client.media_pause().admit_frame(), Some(true));
+    assert_eq!(client.media_pause().admit_frame(), Some(false));
+
+    client.close();
+    Ok(())
+}
+
+async fn send(remote: &mut Remote, message: EndPointMessage) -> anyhow::Result<()> {
+    remote
+        .send(Bytes::from(serialize_packet(&message)?))
+        .await?;
+    Ok(())
+}
+
+async fn wait_until(condition: impl Fn() -> bool) {
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while !condition() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .expect("condition not met in time");
+}
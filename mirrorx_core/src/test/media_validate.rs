@@ -0,0 +1,104 @@
+use crate::component::video_encoder::{
+    media_config::{MediaConfig, QualityPreset},
+    validate::{check_media_config, MediaConfigIssue, MediaConfigProblem},
+};
+
+#[test]
+fn test_check_media_config_default() {
+    assert!(check_media_config(&MediaConfig::default(), 1920, 1080).is_empty());
+}
+
+#[test]
+fn test_check_media_config_out_of_range() {
+    let media_config = MediaConfig {
+        frame_rate: 120,
+        x264_preset: String::from("fastest"),
+        ..Default::default()
+    };
+
+    let problems = check_media_config(&media_config, 1920, 1080);
+
+    assert_eq!(
+        problems,
+        vec![
+            MediaConfigProblem::OutOfRange {
+                field: String::from("frame_rate"),
+                value: String::from("120"),
+                applied: String::from("60"),
+            },
+            MediaConfigProblem::OutOfRange {
+                field: String::from("x264_preset"),
+                value: String::from("fastest"),
+                applied: String::from("ultrafast"),
+            },
+        ]
+    );
+    assert!(!problems.iter().any(MediaConfigProblem::is_fatal));
+}
+
+#[test]
+fn test_check_media_config_frame_limits() {
+    let media_config = MediaConfig::from_preset(QualityPreset::High);
+
+    let problems = check_media_config(&media_config, 7680, 4320);
+    assert!(problems.contains(&MediaConfigProblem::FrameTooLarge {
+        width: 7680,
+        height: 4320,
+    }));
+    assert!(problems.iter().any(MediaConfigProblem::is_fatal));
+
+    // 4096x2304 is the largest frame, but not at 60 frames
+    let problems = check_media_config(&media_config, 4096, 2304);
+    assert_eq!(
+        problems,
+        vec![MediaConfigProblem::FrameRateTooHigh {
+            frame_rate: 60,
+            max_frame_rate: 56,
+        }]
+    );
+    assert!(!problems[0].is_fatal());
+}
+
+#[test]
+fn test_check_media_config_bitrate() {
+    let media_config = MediaConfig {
+        bitrate_kbps: 100,
+        ..MediaConfig::from_preset(QualityPreset::High)
+    };
+    assert_eq!(
+        check_media_config(&media_config, 1920, 1080),
+        vec![MediaConfigProblem::BitrateTooLow {
+            bitrate_kbps: 100,
+            min_kbps: 1245,
+        }]
+    );
+
+    let media_config = MediaConfig {
+        frame_rate: 1,
+        scale_percent: 100,
+        bitrate_kbps: 50 * 1000,
+        ..Default::default()
+    };
+    assert_eq!(
+        check_media_config(&media_config, 320, 240),
+        vec![MediaConfigProblem::BitrateTooHigh {
+            bitrate_kbps: 50 * 1000,
+            max_kbps: 76,
+        }]
+    );
+}
+
+#[test]
+fn test_media_config_issue_serialize() {
+    let issue = MediaConfigIssue::from(MediaConfigProblem::FrameTooLarge {
+        width: 7680,
+        height: 4320,
+    });
+
+    let value = serde_json::to_value(&issue).unwrap();
+
+    assert_eq!(value["kind"], "frame_too_large");
+    assert_eq!(value["fatal"], true);
+    assert_eq!(value["width"], 7680);
+    assert!(value["message"].as_str().unwrap().contains("7680x4320"));
+}
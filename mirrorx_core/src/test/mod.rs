@@ -1,6 +1,151 @@
+mod access_code;
+mod adaptive_bitrate;
+mod allow_list;
 mod audio;
+mod audio_stream;
+mod bandwidth;
+mod browse;
+mod cache;
+mod call_store;
+mod call_timeout;
+mod capabilities;
+mod capture_error;
+mod capture_mode;
+mod change_detect;
+mod chat;
+mod circuit_breaker;
+mod clipboard;
+mod client_cache;
+mod config_cell;
+mod connect_attempt;
+mod connect_retry;
+mod connection_history;
+mod content_hint;
+mod coordinate;
 mod decode;
+mod decode_rate_limit;
+mod decoder_fallback;
+mod desktop_sessions;
+mod device_identity;
+mod device_name;
+mod diagnose;
+mod disconnect;
 mod display;
+mod display_config;
 mod duplicator;
 mod encode;
+mod encode_timing;
+mod encoder_graphics_card;
+mod endpoint_stats;
+mod error_event;
+mod error_location;
+mod exchange_replay;
+mod fec;
+mod file_transfer;
+mod file_window;
+mod frame_dump;
+mod handshake;
+mod handshake_token;
+mod happy_eyeballs;
+mod heartbeat;
+mod idle;
+mod input_coalesce;
+mod input_encoding;
+mod integrity;
+mod key_exchange;
+mod key_pool;
+mod keyframe;
+mod lan_discover_event;
+mod lan_filter;
+mod media_config;
+mod media_budget;
+mod media_error;
+mod media_pause;
+mod media_validate;
 mod mouse;
+mod network_info;
+mod nonce_value;
+mod outbound_queue;
+mod packet;
+mod parameter_sets;
+mod path_preference;
+mod permission;
+mod preprocess;
+mod rekey;
+mod safe_path;
+mod send_queue;
+mod session_limit;
+mod session_token;
+mod settings_bundle;
+mod shutdown;
+mod signaling_transport;
+mod slice;
+mod socket_buffer;
+mod storage;
+mod swap;
+mod thread_priority;
+mod thumbnail;
+mod video_frame_seq;
+mod virtual_display;
+mod visit_error;
+mod visit_failure;
+mod window_capture;
+
+use crate::api::endpoint::{
+    client::EndPointClient,
+    id::EndPointID,
+    message::EndPointMessage,
+    packet::{deserialize_packet, frame_codec},
+    EndPointStream,
+};
+use futures::StreamExt;
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+/// The raw connection of the peer a test plays.
+type Remote = Framed<TcpStream, LengthDelimitedCodec>;
+
+/// A file manager client connected to a peer the test plays, `remote_ip`
+/// tells the connections of the tests apart.
+async fn connect(remote_ip: &str) -> anyhow::Result<(Arc<EndPointClient>, Remote)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: remote_ip.parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    let (stream, _) = listener.accept().await?;
+    Ok((client, Framed::new(stream, frame_codec())))
+}
+
+/// The next message the client sent, past the capabilities, probes and
+/// network reports it sends on its own. `None` when nothing else came
+/// within a second or the stream ended.
+async fn next_message(remote: &mut Remote) -> Option<EndPointMessage> {
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(1);
+
+    loop {
+        let buffer = tokio::time::timeout_at(deadline, remote.next())
+            .await
+            .ok()??
+            .ok()?;
+
+        match deserialize_packet(&buffer).ok()? {
+            EndPointMessage::Capabilities(_)
+            | EndPointMessage::RttProbe(_)
+            | EndPointMessage::RttProbeReply(_)
+            | EndPointMessage::NetworkReport(_) => continue,
+            message => return Some(message),
+        }
+    }
+}
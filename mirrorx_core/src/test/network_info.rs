@@ -0,0 +1,72 @@
+use crate::api::endpoint::{
+    client::EndPointClient,
+    id::EndPointID,
+    network_info::{NetworkInfo, NetworkPath, Transport},
+    path_preference::PathReason,
+    EndPointStream,
+};
+use std::{net::IpAddr, time::Duration};
+use tokio::net::TcpListener;
+
+#[test]
+fn test_network_path_follows_endpoint_id() {
+    let device_id = EndPointID::DeviceID {
+        local_device_id: 1,
+        remote_device_id: 2,
+    };
+    let lan_id = EndPointID::LANID {
+        local_ip: "192.168.1.2".parse().unwrap(),
+        remote_ip: "192.168.1.3".parse().unwrap(),
+    };
+
+    assert_eq!(NetworkPath::from(&device_id), NetworkPath::Relayed);
+    assert_eq!(NetworkPath::from(&lan_id), NetworkPath::Direct);
+}
+
+#[tokio::test]
+async fn test_network_info_of_lan_connection() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.4.1".parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+    let (_stream, _) = listener.accept().await?;
+
+    assert_eq!(
+        client.network_info(),
+        NetworkInfo {
+            path: NetworkPath::Direct,
+            path_reason: PathReason::Auto,
+            transport: Transport::Tcp,
+            aead: None,
+            rtt_ms: None,
+            video_codec: None,
+            capture_mode: None,
+            estimated_bps: 0,
+            sent_bytes: 0,
+            video_frame_bytes: 0,
+            media_paused: false,
+            encode_latency: None,
+        }
+    );
+
+    // the bandwidth estimator takes the round trip, the info keeps it
+    client.raw_stats().record_rtt(Duration::from_millis(20));
+    assert_eq!(
+        client.raw_stats().take_rtt(),
+        Some(Duration::from_millis(20))
+    );
+    assert_eq!(client.network_info().rtt_ms, Some(20.0));
+
+    client.close();
+    Ok(())
+}
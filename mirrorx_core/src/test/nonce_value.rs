@@ -0,0 +1,43 @@
+use crate::utility::nonce_value::NonceValue;
+use ring::aead::{Aad, BoundKey, NonceSequence, SealingKey, UnboundKey, AES_256_GCM};
+
+#[test]
+fn test_nonce_value_advances() {
+    let mut nonce = NonceValue::new([0; ring::aead::NONCE_LEN]);
+
+    let mut expected = [0; ring::aead::NONCE_LEN];
+    expected[0] = 1;
+    assert_eq!(nonce.advance().unwrap().as_ref(), &expected);
+
+    expected[0] = 2;
+    assert_eq!(nonce.advance().unwrap().as_ref(), &expected);
+}
+
+#[test]
+fn test_nonce_value_exhausted_doesnt_wrap() {
+    let mut initial_nonce = [0xFF; ring::aead::NONCE_LEN];
+    initial_nonce[0] = 0xFE;
+    let mut nonce = NonceValue::new(initial_nonce);
+
+    // the last nonce is still handed out
+    assert_eq!(
+        nonce.advance().unwrap().as_ref(),
+        &[0xFF; ring::aead::NONCE_LEN]
+    );
+
+    // then it stays exhausted instead of starting over
+    assert!(nonce.advance().is_err());
+    assert!(nonce.advance().is_err());
+}
+
+#[test]
+fn test_sealing_fails_once_nonce_exhausted() {
+    let unbound_key = UnboundKey::new(&AES_256_GCM, &[0x42; 32]).unwrap();
+    let mut sealing_key =
+        SealingKey::new(unbound_key, NonceValue::new([0xFF; ring::aead::NONCE_LEN]));
+
+    let mut buffer = b"frame".to_vec();
+    assert!(sealing_key
+        .seal_in_place_append_tag(Aad::empty(), &mut buffer)
+        .is_err());
+}
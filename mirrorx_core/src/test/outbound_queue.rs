@@ -0,0 +1,162 @@
+use crate::{
+    api::{
+        config::LocalStorage,
+        signaling::{
+            outbound_queue::OutboundQueue,
+            subscribe_message::{ClientMessage, ServerMessage},
+            SignalingClient,
+        },
+    },
+    error::CoreError,
+    utility::bincode::{bincode_deserialize, bincode_serialize},
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use std::{net::SocketAddr, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+type Server = Framed<TcpStream, LengthDelimitedCodec>;
+
+/// A client subscribed to a fake signaling server, and the server side of
+/// its subscribe connection past the subscription.
+async fn subscribed() -> anyhow::Result<(SignalingClient, Server)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr: SocketAddr = listener.local_addr()?;
+
+    let mut client = SignalingClient::new(format!("http://{addr}"))?;
+    let accept = tokio::spawn(async move { listener.accept().await });
+
+    client
+        .subscribe(vec![addr], 1, "finger_print", LocalStorage::memory()?)
+        .await?;
+
+    let (stream, _) = accept.await??;
+    let mut server = Framed::new(
+        stream,
+        LengthDelimitedCodec::builder()
+            .length_field_length(2)
+            .little_endian()
+            .new_codec(),
+    );
+
+    // the subscription
+    assert!(server.next().await.is_some());
+
+    Ok((client, server))
+}
+
+#[test]
+fn test_outbound_queue_capacity_and_ttl() {
+    let mut queue = OutboundQueue::new(2, Duration::from_millis(50));
+
+    assert!(queue.push(Bytes::from_static(b"first")).is_ok());
+    assert!(queue.push(Bytes::from_static(b"second")).is_ok());
+    assert!(matches!(
+        queue.push(Bytes::from_static(b"third")),
+        Err(CoreError::OutgoingMessageChannelFull)
+    ));
+
+    std::thread::sleep(Duration::from_millis(100));
+
+    let stats = queue.stats();
+    assert_eq!(stats.depth, 0);
+    assert_eq!(stats.expired, 2);
+    assert!(queue.drain().is_empty());
+    assert!(queue.push(Bytes::from_static(b"fourth")).is_ok());
+}
+
+#[tokio::test]
+async fn test_outbound_queue_flush_on_subscribe() -> anyhow::Result<()> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let mut client = SignalingClient::new(format!("http://{addr}"))?;
+
+    // not subscribed yet, the message waits in the queue
+    client.send(Bytes::from_static(b"queued")).await?;
+    assert_eq!(client.outbound_queue_stats().await.depth, 1);
+
+    let accept = tokio::spawn(async move { listener.accept().await });
+
+    client
+        .subscribe(vec![addr], 1, "finger_print", LocalStorage::memory()?)
+        .await?;
+
+    assert_eq!(client.outbound_queue_stats().await.depth, 0);
+
+    let (stream, _) = accept.await??;
+    let mut framed_stream = Framed::new(
+        stream,
+        LengthDelimitedCodec::builder()
+            .length_field_length(2)
+            .little_endian()
+            .new_codec(),
+    );
+
+    // the subscription comes first, then the flushed message
+    assert!(framed_stream.next().await.is_some());
+    let flushed = framed_stream.next().await.unwrap()?;
+    assert_eq!(flushed.as_ref(), b"queued");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_visit_response_goes_out_through_client() -> anyhow::Result<()> {
+    let (client, mut server) = subscribed().await?;
+
+    let request = ServerMessage::VisitRequest {
+        active_device_id: 2,
+        passive_device_id: 1,
+        visit_desktop: true,
+        endpoint_addr: String::from("127.0.0.1:1"),
+        password_salt: Vec::new(),
+        secret: Vec::new(),
+        secret_nonce: Vec::new(),
+        passive_visit_credentials: b"outbound visit".to_vec(),
+    };
+    server
+        .send(Bytes::from(bincode_serialize(&request)?))
+        .await?;
+
+    // the heartbeat may go out ahead of the response
+    let response = tokio::time::timeout(Duration::from_secs(10), async {
+        loop {
+            let buffer = server.next().await.unwrap()?;
+            match bincode_deserialize::<ClientMessage>(&buffer)? {
+                ClientMessage::Ping(_) => continue,
+                message => return anyhow::Ok(message),
+            }
+        }
+    })
+    .await??;
+
+    match response {
+        ClientMessage::VisitResponse {
+            active_device_id,
+            passive_device_id,
+            result,
+        } => {
+            assert_eq!((active_device_id, passive_device_id), (2, 1));
+            assert!(result.is_err());
+        }
+        message => panic!("unexpected message {:?}", message),
+    }
+
+    assert_eq!(client.outbound_queue_stats().await.depth, 0);
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_send_queues_after_connection_broke() -> anyhow::Result<()> {
+    let (client, server) = subscribed().await?;
+
+    drop(server);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    client.send(Bytes::from_static(b"later")).await?;
+    assert_eq!(client.outbound_queue_stats().await.depth, 1);
+
+    Ok(())
+}
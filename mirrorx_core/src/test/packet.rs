@@ -0,0 +1,249 @@
+use crate::{
+    api::endpoint::{
+        capabilities::local_capabilities,
+        client::EndPointClient,
+        id::EndPointID,
+        message::{EndPointMessage, EndPointVideoFrame, MediaErrorReason},
+        packet::{
+            deserialize_packet, frame_codec, sanitize_max_frame_length, serialize_packet,
+            CodecConfig, CODEC_CONFIG, MAX_FRAME_LENGTH_LIMIT, MIN_FRAME_LENGTH_LIMIT,
+            PACKET_FORMAT_VERSION,
+        },
+        EndPointStream,
+    },
+    error::CoreError,
+    utility::bincode::bincode_serialize,
+};
+use bytes::{Bytes, BytesMut};
+use futures::SinkExt;
+use std::{net::IpAddr, time::Duration};
+use tokio::net::TcpListener;
+use tokio_util::codec::{Encoder, Framed, LengthDelimitedCodec};
+
+#[test]
+fn test_packet_round_trip() -> anyhow::Result<()> {
+    let messages = [
+        EndPointMessage::KeepAlive,
+        EndPointMessage::Error {
+            reason: MediaErrorReason::NoDisplay,
+        },
+        EndPointMessage::VideoFrame(EndPointVideoFrame {
+            seq: 7,
+            width: 1920,
+            height: 1080,
+            pts: 7,
+            buffer: vec![1, 2, 3],
+        }),
+    ];
+
+    for message in messages {
+        let buffer = serialize_packet(&message)?;
+        assert_eq!(buffer[0], PACKET_FORMAT_VERSION);
+        assert_eq!(deserialize_packet(&buffer)?, message);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_packet_rejects_unknown_version() -> anyhow::Result<()> {
+    let mut buffer = serialize_packet(&EndPointMessage::KeepAlive)?;
+    buffer[0] = PACKET_FORMAT_VERSION + 1;
+
+    assert!(matches!(
+        deserialize_packet(&buffer),
+        Err(CoreError::ProtocolMismatch { version, codec: None }) if version == PACKET_FORMAT_VERSION + 1
+    ));
+
+    // a packet without the version is broken rather than from another version
+    assert!(matches!(
+        deserialize_packet(&[]),
+        Err(CoreError::Other { .. })
+    ));
+
+    Ok(())
+}
+
+#[test]
+fn test_codec_config_matches_encoders() -> anyhow::Result<()> {
+    // 300 takes the two byte varint form, little endian after the marker
+    assert_eq!(bincode_serialize(&300u16)?, vec![251, 0x2C, 0x01]);
+
+    let mut frame = BytesMut::new();
+    frame_codec().encode(Bytes::from_static(&[7, 7, 7]), &mut frame)?;
+    assert_eq!(&frame[..], &[3, 0, 0, 0, 7, 7, 7]);
+
+    const {
+        assert!(CODEC_CONFIG.bincode_varint && CODEC_CONFIG.bincode_little_endian);
+        assert!(CODEC_CONFIG.frame_little_endian);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_packet_rejects_other_codec_config() -> anyhow::Result<()> {
+    let big_endian = CodecConfig {
+        bincode_little_endian: false,
+        ..CODEC_CONFIG
+    };
+    assert_ne!(big_endian.fingerprint(), CODEC_CONFIG.fingerprint());
+
+    let mut buffer = serialize_packet(&EndPointMessage::KeepAlive)?;
+    assert_eq!(buffer[1], CODEC_CONFIG.fingerprint());
+    buffer[1] = big_endian.fingerprint();
+
+    assert!(matches!(
+        deserialize_packet(&buffer),
+        Err(CoreError::ProtocolMismatch {
+            version: PACKET_FORMAT_VERSION,
+            codec: Some(codec),
+        }) if codec == big_endian.fingerprint()
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_other_codec_config_closes_connection() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.2.2".parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Framed::new(stream, frame_codec());
+
+    // a peer that writes fixed size integers
+    let mut buffer = serialize_packet(&EndPointMessage::KeepAlive)?;
+    buffer[1] = CodecConfig {
+        bincode_varint: false,
+        ..CODEC_CONFIG
+    }
+    .fingerprint();
+    remote.send(Bytes::from(buffer)).await?;
+
+    tokio::time::timeout(Duration::from_secs(1), client.closed()).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_unknown_packet_version_closes_connection() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.2.1".parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Framed::new(
+        stream,
+        LengthDelimitedCodec::builder()
+            .little_endian()
+            .max_frame_length(32 * 1024 * 1024)
+            .new_codec(),
+    );
+
+    let mut buffer = serialize_packet(&EndPointMessage::KeepAlive)?;
+    buffer[0] = PACKET_FORMAT_VERSION + 1;
+    remote.send(Bytes::from(buffer)).await?;
+
+    tokio::time::timeout(Duration::from_secs(1), client.closed()).await?;
+
+    Ok(())
+}
+
+#[test]
+fn test_max_frame_length_sanitize() {
+    assert_eq!(sanitize_max_frame_length(0), MIN_FRAME_LENGTH_LIMIT);
+    assert_eq!(
+        sanitize_max_frame_length(64 * 1024 * 1024),
+        64 * 1024 * 1024
+    );
+    assert_eq!(
+        sanitize_max_frame_length(usize::MAX),
+        MAX_FRAME_LENGTH_LIMIT
+    );
+}
+
+#[tokio::test]
+async fn test_oversized_packet_is_refused() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.2.2".parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Framed::new(stream, frame_codec());
+
+    // the remote side accepts smaller frames than this side
+    let mut capabilities = local_capabilities();
+    capabilities.max_frame_length = 1024 * 1024;
+    remote
+        .send(Bytes::from(serialize_packet(
+            &EndPointMessage::Capabilities(capabilities),
+        )?))
+        .await?;
+
+    tokio::time::timeout(Duration::from_secs(1), async {
+        while client.profile().is_none() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await?;
+
+    let oversized = EndPointMessage::VideoFrame(EndPointVideoFrame {
+        seq: 1,
+        width: 7680,
+        height: 2160,
+        pts: 0,
+        buffer: vec![0; 2 * 1024 * 1024],
+    });
+
+    assert!(matches!(
+        client.send(&oversized).await,
+        Err(CoreError::FrameTooLarge { len, max }) if len > max && max == 1024 * 1024
+    ));
+    assert!(matches!(
+        client.try_send(&oversized),
+        Err(CoreError::FrameTooLarge { .. })
+    ));
+
+    // the connection is still usable
+    assert!(!client.is_closed());
+    client.send(&EndPointMessage::KeepAlive).await?;
+
+    client.close();
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use crate::{
+    api::endpoint::message::EndPointVideoFrame,
+    component::{
+        parameter_sets::{extract_h264_parameter_sets, prepend_parameter_sets, ParameterSetCache},
+        video_decoder::keyframe::KeyFrameGate,
+    },
+};
+
+const SPS: [u8; 8] = [0, 0, 0, 1, 0x67, 0x42, 0xC0, 0x1F];
+const PPS: [u8; 7] = [0, 0, 1, 0x68, 0xCE, 0x3C, 0x80];
+const IDR: [u8; 8] = [0, 0, 0, 1, 0x65, 0x88, 0x84, 0x21];
+const NON_IDR: [u8; 8] = [0, 0, 0, 1, 0x41, 0x9A, 0x02, 0x04];
+
+fn frame(width: i32, nal_units: &[&[u8]]) -> EndPointVideoFrame {
+    EndPointVideoFrame {
+        seq: 0,
+        width,
+        height: 1080,
+        pts: 0,
+        buffer: nal_units.concat(),
+    }
+}
+
+#[test]
+fn test_extract_h264_parameter_sets() {
+    let keyframe = [&SPS[..], &PPS, &IDR].concat();
+    assert_eq!(
+        extract_h264_parameter_sets(&keyframe),
+        Some([&SPS[..], &PPS].concat())
+    );
+
+    assert_eq!(extract_h264_parameter_sets(&IDR), None);
+    assert_eq!(extract_h264_parameter_sets(&NON_IDR), None);
+}
+
+#[test]
+fn test_parameter_set_cache_follows_encoder() {
+    let cache = ParameterSetCache::default();
+
+    let parameter_sets = cache
+        .observe(&mut frame(1920, &[&SPS[..], &PPS, &IDR]))
+        .unwrap();
+    assert_eq!(parameter_sets.width, 1920);
+    assert_eq!(parameter_sets.buffer, [&SPS[..], &PPS].concat());
+
+    // sent once, the viewer keeps them
+    assert!(cache
+        .observe(&mut frame(1920, &[&SPS[..], &PPS, &IDR]))
+        .is_none());
+
+    // a keyframe the encoder sent without them gets them back
+    let mut keyframe = frame(1920, &[&IDR[..]]);
+    assert!(cache.observe(&mut keyframe).is_none());
+    assert_eq!(keyframe.buffer, [&SPS[..], &PPS, &IDR].concat());
+
+    let mut delta_frame = frame(1920, &[&NON_IDR[..]]);
+    cache.observe(&mut delta_frame);
+    assert_eq!(delta_frame.buffer, NON_IDR);
+
+    // a new size comes with parameter sets of its own
+    let resized_sps: [u8; 8] = [0, 0, 0, 1, 0x67, 0x42, 0xC0, 0x28];
+    let resized = cache
+        .observe(&mut frame(1280, &[&resized_sps[..], &PPS, &IDR]))
+        .unwrap();
+    assert_eq!(resized.width, 1280);
+    assert_eq!(cache.current(), Some(resized));
+
+    // they don't apply to frames of another size
+    let mut stale_keyframe = frame(1920, &[&IDR[..]]);
+    cache.observe(&mut stale_keyframe);
+    assert_eq!(stale_keyframe.buffer, IDR);
+
+    cache.clear();
+    assert_eq!(cache.current(), None);
+}
+
+#[test]
+fn test_decoder_starts_from_cached_parameter_sets() {
+    let cache = ParameterSetCache::default();
+    let parameter_sets = cache
+        .observe(&mut frame(1920, &[&SPS[..], &PPS, &IDR]))
+        .unwrap();
+
+    // the decoder starts over, e.g. after a reconnect, and the next keyframe
+    // of the encoder comes without parameter sets
+    let mut gate = KeyFrameGate::default();
+
+    let mut delta_frame = frame(1920, &[&NON_IDR[..]]);
+    assert!(!prepend_parameter_sets(&parameter_sets, &mut delta_frame));
+    assert!(!gate.admit(&delta_frame.buffer));
+
+    let mut keyframe = frame(1920, &[&IDR[..]]);
+    assert!(prepend_parameter_sets(&parameter_sets, &mut keyframe));
+    assert!(gate.admit(&keyframe.buffer));
+
+    // the first frame the decoder opens with carries all it needs
+    assert_eq!(
+        extract_h264_parameter_sets(&keyframe.buffer),
+        Some(parameter_sets.buffer.clone())
+    );
+
+    // a keyframe bringing its own isn't touched
+    let mut full_keyframe = frame(1920, &[&SPS[..], &PPS, &IDR]);
+    assert!(!prepend_parameter_sets(&parameter_sets, &mut full_keyframe));
+    assert_eq!(full_keyframe.buffer, [&SPS[..], &PPS, &IDR].concat());
+}
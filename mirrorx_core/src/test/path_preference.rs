@@ -0,0 +1,49 @@
+use crate::api::endpoint::{
+    network_info::NetworkPath,
+    path_preference::{select_path, PathPreference, PathReason},
+};
+
+#[test]
+fn test_auto_path_prefers_direct() {
+    let both = [NetworkPath::Relayed, NetworkPath::Direct];
+
+    assert_eq!(
+        select_path(PathPreference::Auto, &both).unwrap(),
+        (NetworkPath::Direct, PathReason::Auto)
+    );
+    assert_eq!(
+        select_path(PathPreference::Auto, &[NetworkPath::Relayed]).unwrap(),
+        (NetworkPath::Relayed, PathReason::Auto)
+    );
+    assert!(select_path(PathPreference::Auto, &[]).is_err());
+}
+
+#[test]
+fn test_force_direct_skips_relay() {
+    assert_eq!(
+        select_path(PathPreference::ForceDirect, &[NetworkPath::Direct]).unwrap(),
+        (NetworkPath::Direct, PathReason::Forced)
+    );
+
+    // only the relay is available, it fails instead of falling back
+    assert!(select_path(PathPreference::ForceDirect, &[NetworkPath::Relayed]).is_err());
+}
+
+#[test]
+fn test_force_relay_skips_direct() {
+    assert_eq!(
+        select_path(PathPreference::ForceRelay, &[NetworkPath::Relayed]).unwrap(),
+        (NetworkPath::Relayed, PathReason::Forced)
+    );
+    assert_eq!(
+        select_path(
+            PathPreference::ForceRelay,
+            &[NetworkPath::Direct, NetworkPath::Relayed]
+        )
+        .unwrap(),
+        (NetworkPath::Relayed, PathReason::Forced)
+    );
+
+    // only the direct path is available
+    assert!(select_path(PathPreference::ForceRelay, &[NetworkPath::Direct]).is_err());
+}
@@ -0,0 +1,42 @@
+use crate::{
+    error::CoreError,
+    utility::permission::{PermissionStatus, PlatformPermissions},
+};
+
+#[test]
+fn test_permission_status_serialize() {
+    let permissions = PlatformPermissions {
+        screen_capture: PermissionStatus::Granted,
+        accessibility: PermissionStatus::NotRequired,
+    };
+
+    assert_eq!(
+        serde_json::to_string(&permissions).unwrap(),
+        r#"{"screen_capture":"granted","accessibility":"not_required"}"#
+    );
+}
+
+#[test]
+fn test_denied_permission_maps_to_distinct_error() {
+    let permissions = PlatformPermissions {
+        screen_capture: PermissionStatus::Denied,
+        accessibility: PermissionStatus::Denied,
+    };
+
+    assert!(matches!(
+        permissions.ensure_screen_capture(),
+        Err(CoreError::ScreenCapturePermissionDenied)
+    ));
+    assert!(matches!(
+        permissions.ensure_accessibility(),
+        Err(CoreError::AccessibilityPermissionDenied)
+    ));
+
+    let permissions = PlatformPermissions {
+        screen_capture: PermissionStatus::Granted,
+        accessibility: PermissionStatus::NotRequired,
+    };
+
+    assert!(permissions.ensure_screen_capture().is_ok());
+    assert!(permissions.ensure_accessibility().is_ok());
+}
@@ -0,0 +1,158 @@
+use crate::component::{
+    frame::DesktopEncodeFrame,
+    video_encoder::preprocess::{
+        apply_privacy_region, DisplayPlacement, PrivacyRegion, PrivacyRegionKind,
+    },
+};
+use std::time::Duration;
+
+fn new_frame(width: i32, height: i32) -> DesktopEncodeFrame {
+    DesktopEncodeFrame {
+        capture_time: Duration::ZERO,
+        width,
+        height,
+        luminance_bytes: vec![200; (width * height) as usize],
+        luminance_stride: width,
+        chrominance_bytes: vec![60; (width * height / 2) as usize],
+        chrominance_stride: width,
+        dirty_rects: None,
+    }
+}
+
+#[test]
+fn test_opaque_region_is_clamped() {
+    let mut frame = new_frame(8, 8);
+
+    apply_privacy_region(
+        &mut frame,
+        &PrivacyRegion {
+            left: 4,
+            top: -10,
+            width: 100,
+            height: 12,
+            kind: PrivacyRegionKind::Opaque,
+        },
+    );
+
+    // rows 0..2 are covered, columns 4..8
+    assert_eq!(
+        &frame.luminance_bytes[0..8],
+        &[200, 200, 200, 200, 0, 0, 0, 0]
+    );
+    assert_eq!(
+        &frame.luminance_bytes[8..16],
+        &[200, 200, 200, 200, 0, 0, 0, 0]
+    );
+    assert!(frame.luminance_bytes[16..].iter().all(|v| *v == 200));
+    assert_eq!(
+        &frame.chrominance_bytes[0..8],
+        &[60, 60, 60, 60, 128, 128, 128, 128]
+    );
+    assert!(frame.chrominance_bytes[8..].iter().all(|v| *v == 60));
+}
+
+#[test]
+fn test_blur_region_averages_blocks() {
+    let mut frame = new_frame(4, 2);
+    frame.luminance_bytes = vec![0, 100, 0, 100, 100, 0, 100, 0];
+
+    apply_privacy_region(
+        &mut frame,
+        &PrivacyRegion {
+            left: 0,
+            top: 0,
+            width: 4,
+            height: 2,
+            kind: PrivacyRegionKind::Blur,
+        },
+    );
+
+    assert!(frame.luminance_bytes.iter().all(|v| *v == 50));
+}
+
+#[test]
+fn test_region_maps_through_display_origin_and_scale() {
+    // a display left of the primary one, scaled 2x
+    let placement = DisplayPlacement {
+        left: -1440,
+        top: 0,
+        width: 1440,
+        height: 900,
+    };
+
+    let region = placement.map_region(
+        &PrivacyRegion {
+            left: -1400,
+            top: 10,
+            width: 100,
+            height: 50,
+            kind: PrivacyRegionKind::Opaque,
+        },
+        2880,
+        1800,
+    );
+
+    assert_eq!(
+        region,
+        PrivacyRegion {
+            left: 80,
+            top: 20,
+            width: 200,
+            height: 100,
+            kind: PrivacyRegionKind::Opaque,
+        }
+    );
+}
+
+#[test]
+fn test_mapped_region_covers_partial_pixels() {
+    // a display at 150%, the region edges fall between frame pixels
+    let placement = DisplayPlacement {
+        left: 100,
+        top: 100,
+        width: 4,
+        height: 4,
+    };
+    let mut frame = new_frame(6, 6);
+
+    let region = PrivacyRegion {
+        left: 101,
+        top: 100,
+        width: 1,
+        height: 1,
+        kind: PrivacyRegionKind::Opaque,
+    };
+    let mapped = placement.map_region(&region, frame.width, frame.height);
+    assert_eq!((mapped.left, mapped.top), (1, 0));
+    assert_eq!((mapped.width, mapped.height), (2, 2));
+
+    apply_privacy_region(&mut frame, &mapped);
+
+    // widened to the chroma blocks, columns 0..4 of rows 0..2
+    assert_eq!(&frame.luminance_bytes[0..6], &[0, 0, 0, 0, 200, 200]);
+    assert_eq!(&frame.luminance_bytes[6..12], &[0, 0, 0, 0, 200, 200]);
+    assert!(frame.luminance_bytes[12..].iter().all(|v| *v == 200));
+}
+
+#[test]
+fn test_region_of_another_display_is_dropped() {
+    let placement = DisplayPlacement {
+        left: 1920,
+        top: 0,
+        width: 1920,
+        height: 1080,
+    };
+    let mut frame = new_frame(8, 8);
+
+    // on the primary display, left of the captured one
+    let region = PrivacyRegion {
+        left: 0,
+        top: 0,
+        width: 1920,
+        height: 1080,
+        kind: PrivacyRegionKind::Opaque,
+    };
+    apply_privacy_region(&mut frame, &placement.map_region(&region, 8, 8));
+
+    assert!(frame.luminance_bytes.iter().all(|v| *v == 200));
+}
@@ -0,0 +1,243 @@
+use crate::{
+    api::endpoint::{
+        client::EndPointClient,
+        id::EndPointID,
+        message::EndPointMessage,
+        packet::{deserialize_packet, frame_codec, serialize_packet},
+        rekey::{is_rekey_switch, KeyRotation, RekeyOffer, RekeyPolicy, MIN_REKEY_BYTES},
+        EndPointStream,
+    },
+    utility::nonce_value::NonceValue,
+};
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use ring::aead::{Aad, BoundKey, OpeningKey, SealingKey, UnboundKey, AES_256_GCM};
+use std::{net::IpAddr, time::Duration};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+fn key(seed: u8) -> anyhow::Result<UnboundKey> {
+    UnboundKey::new(&AES_256_GCM, &[seed; 32]).map_err(|_| anyhow::anyhow!("invalid key"))
+}
+
+/// The keys of both directions, for this side and the remote one.
+#[allow(clippy::type_complexity)]
+fn key_pairs() -> anyhow::Result<(
+    (OpeningKey<NonceValue>, SealingKey<NonceValue>),
+    (OpeningKey<NonceValue>, SealingKey<NonceValue>),
+)> {
+    let to_remote = [1u8; ring::aead::NONCE_LEN];
+    let from_remote = [2u8; ring::aead::NONCE_LEN];
+
+    Ok((
+        (
+            OpeningKey::new(key(2)?, NonceValue::new(from_remote)),
+            SealingKey::new(key(1)?, NonceValue::new(to_remote)),
+        ),
+        (
+            OpeningKey::new(key(1)?, NonceValue::new(to_remote)),
+            SealingKey::new(key(2)?, NonceValue::new(from_remote)),
+        ),
+    ))
+}
+
+/// The passive side of an encrypted connection, driven by the test.
+struct Remote {
+    framed: Framed<TcpStream, LengthDelimitedCodec>,
+    opening_key: OpeningKey<NonceValue>,
+    sealing_key: SealingKey<NonceValue>,
+}
+
+impl Remote {
+    async fn send(&mut self, message: EndPointMessage) -> anyhow::Result<()> {
+        let mut buffer = serialize_packet(&message)?;
+        self.sealing_key
+            .seal_in_place_append_tag(Aad::empty(), &mut buffer)
+            .map_err(|_| anyhow::anyhow!("seal failed"))?;
+        self.framed.send(Bytes::from(buffer)).await?;
+        Ok(())
+    }
+
+    /// The next message, heartbeat probes skipped.
+    async fn recv(&mut self) -> anyhow::Result<EndPointMessage> {
+        loop {
+            let mut buffer = tokio::time::timeout(Duration::from_secs(3), self.framed.next())
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("connection closed"))??;
+
+            let plaintext = self
+                .opening_key
+                .open_in_place(Aad::empty(), &mut buffer)
+                .map_err(|_| anyhow::anyhow!("open failed"))?;
+
+            match deserialize_packet(plaintext)? {
+                EndPointMessage::RttProbe(_) => continue,
+                message => return Ok(message),
+            }
+        }
+    }
+
+    async fn assert_round_trip(&mut self, value: u64) -> anyhow::Result<()> {
+        self.send(EndPointMessage::RttProbe(value)).await?;
+        assert_eq!(self.recv().await?, EndPointMessage::RttProbeReply(value));
+        Ok(())
+    }
+}
+
+#[test]
+fn test_rekey_switch_marker() -> anyhow::Result<()> {
+    assert!(is_rekey_switch(&serialize_packet(
+        &EndPointMessage::RekeySwitch
+    )?));
+    assert!(!is_rekey_switch(&serialize_packet(
+        &EndPointMessage::KeepAlive
+    )?));
+    assert!(!is_rekey_switch(&[]));
+
+    Ok(())
+}
+
+#[test]
+fn test_rekey_offers_agree_on_keys() -> anyhow::Result<()> {
+    let active_offer = RekeyOffer::generate()?;
+    let passive_offer = RekeyOffer::generate()?;
+    let active_message = active_offer.message();
+    let passive_message = passive_offer.message();
+
+    let (mut active_opening, mut active_sealing) = active_offer.agree(&passive_message, true)?;
+    let (mut passive_opening, mut passive_sealing) = passive_offer.agree(&active_message, false)?;
+
+    for (sealing_key, opening_key) in [
+        (&mut active_sealing, &mut passive_opening),
+        (&mut passive_sealing, &mut active_opening),
+    ] {
+        let mut buffer = b"after rekey".to_vec();
+        sealing_key
+            .seal_in_place_append_tag(Aad::empty(), &mut buffer)
+            .map_err(|_| anyhow::anyhow!("seal failed"))?;
+
+        let plaintext = opening_key
+            .open_in_place(Aad::empty(), &mut buffer)
+            .map_err(|_| anyhow::anyhow!("open failed"))?;
+        assert_eq!(plaintext, b"after rekey");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn test_rekey_offer_rejects_bad_nonce() -> anyhow::Result<()> {
+    let mut remote = RekeyOffer::generate()?.message();
+    remote.nonce.truncate(4);
+
+    assert!(RekeyOffer::generate()?.agree(&remote, true).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_rekey_policy() {
+    let policy = RekeyPolicy {
+        interval_secs: 0,
+        max_bytes: 0,
+    }
+    .sanitize();
+    assert_eq!(policy.max_bytes, MIN_REKEY_BYTES);
+
+    let policy = RekeyPolicy {
+        interval_secs: 600,
+        max_bytes: 1 << 30,
+    };
+    assert!(!policy.is_due(Duration::from_secs(599), (1 << 30) - 1));
+    assert!(policy.is_due(Duration::from_secs(600), 0));
+    assert!(policy.is_due(Duration::from_secs(1), 1 << 30));
+}
+
+#[tokio::test]
+async fn test_rekey_crossing_requests() -> anyhow::Result<()> {
+    let ((active_opening, active_sealing), (passive_opening, passive_sealing)) = key_pairs()?;
+    // the loops would take the next keys, `_` would drop them right away
+    let (active, _active_opening_rx, _active_sealing_rx) =
+        KeyRotation::new(true, active_opening, active_sealing);
+    let (passive, _passive_opening_rx, _passive_sealing_rx) =
+        KeyRotation::new(false, passive_opening, passive_sealing);
+
+    let (active_request, _active_done) = active.start()?;
+    let (passive_request, passive_done) = passive.start()?;
+
+    // the side that opened the connection goes on with its own rotation
+    assert!(active.accept(&passive_request)?.is_none());
+
+    let reply = passive.accept(&active_request)?.expect("answered");
+    assert!(passive_done.await.is_err());
+
+    active.finish(&reply)?;
+    assert!(active.finish(&reply).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_rekey_round_trip() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let (key_pair, (opening_key, sealing_key)) = key_pairs()?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.5.1".parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        Some(key_pair),
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Remote {
+        framed: Framed::new(stream, frame_codec()),
+        opening_key,
+        sealing_key,
+    };
+
+    assert!(matches!(
+        remote.recv().await?,
+        EndPointMessage::Capabilities(_)
+    ));
+    remote.assert_round_trip(1).await?;
+
+    let rekey = tokio::spawn({
+        let client = client.clone();
+        async move { client.rekey().await }
+    });
+
+    let EndPointMessage::RekeyRequest(request) = remote.recv().await? else {
+        anyhow::bail!("expected rekey request");
+    };
+
+    let offer = RekeyOffer::generate()?;
+    let reply = offer.message();
+    let (opening_key, sealing_key) = offer.agree(&request, false)?;
+
+    remote.send(EndPointMessage::RekeyReply(reply)).await?;
+    remote.send(EndPointMessage::RekeySwitch).await?;
+    remote.sealing_key = sealing_key;
+
+    // the last frame sealed with the old keys, the client switched after it
+    assert_eq!(remote.recv().await?, EndPointMessage::RekeySwitch);
+    remote.opening_key = opening_key;
+
+    rekey.await??;
+
+    remote.assert_round_trip(2).await?;
+    remote.assert_round_trip(3).await?;
+
+    assert_eq!(client.stats().aead_open_failures, 0);
+    assert!(!client.is_closed());
+
+    client.close();
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+use crate::{
+    component::fs::safe_path::{confine_path, is_normalized_absolute_path},
+    error::CoreError,
+};
+use std::path::Path;
+
+#[test]
+fn test_confine_path_rejects_adversarial_paths() {
+    let root = std::env::temp_dir().join("mirrorx_downloads");
+
+    let adversarial = [
+        "../../etc/passwd",
+        "..",
+        "docs/../../secret",
+        "/etc/passwd",
+        "C:\\Windows\\System32\\drivers\\etc\\hosts",
+        "C:/Windows/win.ini",
+        "D:relative.txt",
+        "..\\..\\boot.ini",
+        "\\\\server\\share\\file",
+        "",
+        ".",
+    ];
+
+    for path in adversarial {
+        assert!(
+            matches!(
+                confine_path(&root, Path::new(path)),
+                Err(CoreError::UnsafePath(_))
+            ),
+            "{path} should be rejected"
+        );
+    }
+}
+
+#[test]
+fn test_confine_path_accepts_relative_paths() {
+    let root = std::env::temp_dir().join("mirrorx_downloads");
+
+    assert_eq!(
+        confine_path(&root, Path::new("report.pdf")).unwrap(),
+        root.join("report.pdf")
+    );
+    assert_eq!(
+        confine_path(&root, Path::new("./photos/cat.png")).unwrap(),
+        root.join("photos").join("cat.png")
+    );
+}
+
+#[test]
+fn test_is_normalized_absolute_path() {
+    let root = std::env::temp_dir();
+
+    assert!(is_normalized_absolute_path(&root));
+    assert!(!is_normalized_absolute_path(&root.join("..").join("etc")));
+    assert!(!is_normalized_absolute_path(Path::new("relative/dir")));
+}
@@ -0,0 +1,79 @@
+use crate::{
+    api::endpoint::{
+        client::EndPointClient,
+        id::EndPointID,
+        message::{AudioSampleFormat, EndPointAudioFrame, EndPointMessage, EndPointVideoFrame},
+        EndPointStream,
+    },
+    error::CoreError,
+};
+use std::net::IpAddr;
+use tokio::net::TcpListener;
+
+fn video_frame(seq: u64, len: usize) -> EndPointMessage {
+    EndPointMessage::VideoFrame(EndPointVideoFrame {
+        seq,
+        width: 1920,
+        height: 1080,
+        pts: 0,
+        buffer: vec![0; len],
+    })
+}
+
+#[test]
+fn test_message_is_media_frame() {
+    assert!(video_frame(0, 16).is_media_frame());
+    assert!(EndPointMessage::AudioFrame(EndPointAudioFrame {
+        channels: 2,
+        sample_format: AudioSampleFormat::F32,
+        sample_rate: 48000,
+        seq: 0,
+        pts: 0,
+        buffer: Vec::new(),
+    })
+    .is_media_frame());
+
+    assert!(!EndPointMessage::RttProbe(0).is_media_frame());
+    assert!(!EndPointMessage::KeepAlive.is_media_frame());
+    assert!(!EndPointMessage::RequestKeyFrame.is_media_frame());
+}
+
+#[tokio::test]
+async fn test_full_send_queue_drops_media_frames() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "10.0.3.2".parse::<IpAddr>()?,
+    };
+
+    let client = EndPointClient::new_file_manager_active(
+        endpoint_id,
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    // the remote side never reads, the socket and then the queue fill up
+    let (_stream, _) = listener.accept().await?;
+
+    let mut full_at = None;
+    for seq in 0..4000 {
+        match client.send_media_frame(&video_frame(seq, 64 * 1024)) {
+            Ok(()) => tokio::task::yield_now().await,
+            Err(CoreError::OutgoingMessageChannelFull) => {
+                full_at = Some(seq);
+                break;
+            }
+            Err(err) => anyhow::bail!("unexpected error: {err}"),
+        }
+    }
+
+    assert!(full_at.is_some(), "send queue never filled up");
+    assert_eq!(client.stats().dropped_frames, 1);
+
+    client.close();
+
+    Ok(())
+}
@@ -0,0 +1,73 @@
+use crate::{
+    api::endpoint::{
+        client::EndPointClient, id::EndPointID, session_limit::SessionLimiter, EndPointStream,
+    },
+    component::{lan::server::Server, video_encoder::media_config::MediaConfig},
+    error::CoreError,
+};
+use std::{net::IpAddr, time::Duration};
+
+#[test]
+fn test_incoming_session_limit() {
+    let limiter = SessionLimiter::new(2);
+
+    let first = limiter.try_acquire();
+    let second = limiter.try_acquire();
+    assert!(first.is_some());
+    assert!(second.is_some());
+    assert!(limiter.try_acquire().is_none());
+    assert_eq!(limiter.active(), 2);
+
+    drop(first);
+    assert_eq!(limiter.active(), 1);
+    assert!(limiter.try_acquire().is_some());
+}
+
+#[test]
+fn test_lower_incoming_session_limit() {
+    let limiter = SessionLimiter::new(2);
+    let _first = limiter.try_acquire();
+    let _second = limiter.try_acquire();
+
+    // running sessions are kept, new ones wait for them
+    limiter.set_max(1);
+    assert_eq!(limiter.active(), 2);
+    assert!(limiter.try_acquire().is_none());
+
+    limiter.set_max(0);
+    assert_eq!(limiter.max(), 1);
+}
+
+#[tokio::test]
+async fn test_lan_server_replies_busy() -> anyhow::Result<()> {
+    let limiter = SessionLimiter::new(1);
+    let _running_session = limiter.try_acquire();
+
+    let server = Server::bind("127.0.0.1:0".parse()?, limiter.clone()).await?;
+
+    let (video_frame_tx, _video_frame_rx) = tokio::sync::mpsc::channel(16);
+    let (audio_frame_tx, _audio_frame_rx) = tokio::sync::mpsc::channel(16);
+
+    let result = tokio::time::timeout(
+        Duration::from_secs(10),
+        EndPointClient::new_desktop_active(
+            EndPointID::LANID {
+                local_ip: "127.0.0.1".parse::<IpAddr>()?,
+                remote_ip: "127.0.0.1".parse::<IpAddr>()?,
+            },
+            None,
+            EndPointStream::ActiveTCP(server.local_addr()),
+            video_frame_tx,
+            audio_frame_tx,
+            None,
+            MediaConfig::default(),
+        ),
+    )
+    .await?;
+
+    assert!(matches!(result, Err(CoreError::VisitServerBusy)));
+    assert_eq!(limiter.active(), 1);
+
+    server.shutdown().await;
+    Ok(())
+}
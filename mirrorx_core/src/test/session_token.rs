@@ -0,0 +1,199 @@
+use crate::{
+    api::endpoint::{
+        capabilities::local_media_limits,
+        id::EndPointID,
+        message::{
+            EndPointMessage, EndPointNegotiateDesktopParamsRequest,
+            EndPointNegotiateDesktopParamsResponse, EndPointNegotiateVisitDesktopParams,
+            VideoCodec,
+        },
+        packet::{deserialize_packet, frame_codec, serialize_packet},
+        session_limit::SessionLimiter,
+        session_token::{session_tokens, SessionTokens},
+    },
+    component::{desktop::monitor::Monitor, lan::server::Server},
+};
+use futures::{SinkExt, StreamExt};
+use std::{
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+use tokio::net::TcpStream;
+use tokio_util::codec::Framed;
+
+const VIEWER: EndPointID = EndPointID::DeviceID {
+    local_device_id: 1,
+    remote_device_id: 2,
+};
+
+fn desktop_params() -> EndPointNegotiateVisitDesktopParams {
+    EndPointNegotiateVisitDesktopParams {
+        video_codec: VideoCodec::H264,
+        media_limits: local_media_limits(),
+        audio: None,
+        os_type: String::from(""),
+        os_version: String::from(""),
+        primary_monitor: Monitor {
+            id: String::from("1"),
+            name: String::from("primary"),
+            refresh_rate: 60,
+            width: 1920,
+            height: 1080,
+            is_primary: true,
+            screen_shot: None,
+            left: 0,
+            top: 0,
+        },
+        encoder_graphics_card: None,
+        session_token: None,
+    }
+}
+
+#[test]
+fn test_rejoin_within_window() -> anyhow::Result<()> {
+    let tokens = SessionTokens::new(Duration::from_secs(30));
+    let now = Instant::now();
+
+    let token = tokens.issue(VIEWER, desktop_params(), now)?;
+
+    // the host may not have noticed the old connection is gone yet
+    assert_eq!(
+        tokens.rejoin(&token, VIEWER, now + Duration::from_secs(5)),
+        Some(desktop_params())
+    );
+
+    // every token rejoins once
+    assert_eq!(tokens.rejoin(&token, VIEWER, now), None);
+
+    let token = tokens.issue(VIEWER, desktop_params(), now)?;
+    tokens.disconnect(&token, now + Duration::from_secs(60));
+    assert_eq!(
+        tokens.rejoin(&token, VIEWER, now + Duration::from_secs(80)),
+        Some(desktop_params())
+    );
+
+    Ok(())
+}
+
+#[test]
+fn test_rejoin_after_expiry() -> anyhow::Result<()> {
+    let tokens = SessionTokens::new(Duration::from_secs(30));
+    let now = Instant::now();
+
+    let token = tokens.issue(VIEWER, desktop_params(), now)?;
+    tokens.disconnect(&token, now);
+    assert_eq!(
+        tokens.rejoin(&token, VIEWER, now + Duration::from_secs(31)),
+        None
+    );
+
+    // expired tokens are dropped once the next one is issued
+    let expired = tokens.issue(VIEWER, desktop_params(), now)?;
+    tokens.disconnect(&expired, now);
+    tokens.issue(VIEWER, desktop_params(), now + Duration::from_secs(31))?;
+    tokens.set_window(Duration::from_secs(3600));
+    assert_eq!(
+        tokens.rejoin(&expired, VIEWER, now + Duration::from_secs(32)),
+        None
+    );
+
+    // a window of zero turns rejoining off
+    tokens.set_window(Duration::ZERO);
+    let token = tokens.issue(VIEWER, desktop_params(), now)?;
+    assert_eq!(tokens.rejoin(&token, VIEWER, now), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_rejoin_from_other_endpoint() -> anyhow::Result<()> {
+    let tokens = SessionTokens::new(Duration::from_secs(30));
+    let now = Instant::now();
+
+    let token = tokens.issue(VIEWER, desktop_params(), now)?;
+    let other = EndPointID::DeviceID {
+        local_device_id: 1,
+        remote_device_id: 3,
+    };
+
+    assert_eq!(tokens.rejoin(&token, other, now), None);
+
+    Ok(())
+}
+
+#[test]
+fn test_received_token() -> anyhow::Result<()> {
+    let tokens = SessionTokens::new(Duration::from_secs(30));
+    let now = Instant::now();
+
+    let token = tokens.issue(VIEWER, desktop_params(), now)?;
+    tokens.remember(VIEWER, token);
+    assert_eq!(tokens.take_received(&VIEWER), Some(token));
+    assert_eq!(tokens.take_received(&VIEWER), None);
+
+    tokens.remember(VIEWER, token);
+    tokens.forget(&VIEWER);
+    assert_eq!(tokens.take_received(&VIEWER), None);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_host_answers_rejoin_with_earlier_params() -> anyhow::Result<()> {
+    let server = Server::bind("127.0.0.1:0".parse()?, SessionLimiter::new(1)).await?;
+    let viewer = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: "127.0.0.1".parse::<IpAddr>()?,
+    };
+
+    // a display the host doesn't have, only the issued params name it
+    let mut params = desktop_params();
+    params.primary_monitor.id = String::from("rejoin");
+    let token = session_tokens().issue(viewer, params.clone(), Instant::now())?;
+
+    let stream = TcpStream::connect(server.local_addr()).await?;
+    let mut host = Framed::new(stream, frame_codec());
+    host.send(
+        serialize_packet(&EndPointMessage::NegotiateDesktopParamsRequest(
+            EndPointNegotiateDesktopParamsRequest {
+                video_codecs: vec![VideoCodec::H264],
+                media_limits: local_media_limits(),
+                audio: None,
+                rejoin_token: Some(token),
+            },
+        ))?
+        .into(),
+    )
+    .await?;
+
+    let resp = tokio::time::timeout(Duration::from_secs(10), async {
+        while let Some(buffer) = host.next().await {
+            if let EndPointMessage::NegotiateDesktopParamsResponse(resp) =
+                deserialize_packet(&buffer?)?
+            {
+                return Ok(resp);
+            }
+        }
+
+        anyhow::bail!("host closed the connection")
+    })
+    .await??;
+
+    let EndPointNegotiateDesktopParamsResponse::Params(rejoined) = resp else {
+        anyhow::bail!("rejoin not answered with params: {:?}", resp);
+    };
+
+    // every rejoin is answered with a new token
+    assert!(rejoined.session_token.is_some());
+    assert_ne!(rejoined.session_token, Some(token));
+    assert_eq!(
+        EndPointNegotiateVisitDesktopParams {
+            session_token: None,
+            ..rejoined
+        },
+        params
+    );
+
+    server.shutdown().await;
+    Ok(())
+}
@@ -0,0 +1,202 @@
+use crate::{
+    api::{
+        config::{
+            bundle::{export_settings, import_settings, ImportMode},
+            entity::{domain::Domain, kv::Theme},
+            LocalStorage, Storage,
+        },
+        signaling::identity::{MutualAuthConfig, TrustedDevice},
+    },
+    error::CoreError,
+};
+
+fn source_storage() -> anyhow::Result<LocalStorage> {
+    let storage = LocalStorage::memory()?;
+    storage.kv().set_language("en")?;
+    storage.kv().set_theme(Theme::Dark)?;
+    storage.kv().set_max_incoming_sessions(2)?;
+    storage.kv().set_encoder_graphics_card(Some("gpu-0"))?;
+    storage.domain().add_domain(Domain {
+        id: 0,
+        name: String::from("primary"),
+        addr: String::from("http://127.0.0.1:28000"),
+        signaling_port: 28001,
+        subscribe_port: 28002,
+        is_primary: true,
+        device_id: 1234567890,
+        password: String::from("device-password"),
+        finger_print: String::from("finger_print"),
+        remarks: String::default(),
+    })?;
+
+    Ok(storage)
+}
+
+#[test]
+fn test_import_settings_replace() -> anyhow::Result<()> {
+    let bundle = export_settings(&source_storage()?, "passphrase")?;
+    assert!(!bundle.contains("device-password"));
+
+    let storage = LocalStorage::memory()?;
+    storage.kv().set_language("zh")?;
+    storage.kv().set_input_coalesce_window_ms(16)?;
+
+    let summary = import_settings(&storage, &bundle, "passphrase", ImportMode::Replace)?;
+    assert_eq!(summary.app_version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(
+        summary.imported,
+        vec!["language", "max_incoming_sessions", "theme"]
+    );
+    assert_eq!(summary.reset, vec!["input_coalesce_window_ms"]);
+    assert!(summary.kept.is_empty());
+    assert!(summary.skipped.is_empty());
+
+    assert_eq!(storage.kv().get_language()?.as_deref(), Some("en"));
+    assert_eq!(storage.kv().get_max_incoming_sessions()?, Some(2));
+    assert_eq!(storage.kv().get_input_coalesce_window_ms()?, None);
+
+    // nothing tied to the exporting device comes along
+    assert_eq!(storage.kv().get_encoder_graphics_card()?, None);
+    assert_eq!(storage.domain().get_domain_count()?, 0);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_settings_merge() -> anyhow::Result<()> {
+    let bundle = export_settings(&source_storage()?, "passphrase")?;
+
+    let storage = LocalStorage::memory()?;
+    storage.kv().set_language("zh")?;
+    storage.kv().set_input_coalesce_window_ms(16)?;
+
+    let summary = import_settings(&storage, &bundle, "passphrase", ImportMode::Merge)?;
+    assert_eq!(summary.imported, vec!["max_incoming_sessions", "theme"]);
+    assert_eq!(summary.kept, vec!["language"]);
+    assert!(summary.reset.is_empty());
+
+    assert_eq!(storage.kv().get_language()?.as_deref(), Some("zh"));
+    assert_eq!(storage.kv().get_input_coalesce_window_ms()?, Some(16));
+
+    Ok(())
+}
+
+#[test]
+fn test_import_settings_trusted_devices() -> anyhow::Result<()> {
+    let config = MutualAuthConfig {
+        enabled: true,
+        trusted_devices: vec![TrustedDevice {
+            name: String::from("laptop"),
+            public_key: base64::encode([7u8; 32]),
+        }],
+    };
+
+    let source = source_storage()?;
+    source.kv().set_mutual_auth_config(&config)?;
+    source.kv().set_device_identity_key(&[9u8; 83])?;
+    let bundle = export_settings(&source, "passphrase")?;
+
+    let storage = LocalStorage::memory()?;
+    let summary = import_settings(&storage, &bundle, "passphrase", ImportMode::Replace)?;
+    assert!(summary
+        .imported
+        .iter()
+        .any(|key| key == "mutual_auth_config"));
+    assert_eq!(storage.kv().get_mutual_auth_config()?, config);
+
+    // the key the device signs with stays with it
+    assert_eq!(storage.kv().get_device_identity_key()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_settings_allow_list() -> anyhow::Result<()> {
+    let source = source_storage()?;
+    source.kv().set_allow_list_enabled(true)?;
+    source.allow_list().add(111)?;
+    source.allow_list().add(222)?;
+    let bundle = export_settings(&source, "passphrase")?;
+
+    let allowed_devices = |storage: &LocalStorage| -> anyhow::Result<Vec<i64>> {
+        let mut device_ids: Vec<i64> = storage
+            .allow_list()
+            .query()?
+            .into_iter()
+            .map(|device| device.device_id)
+            .collect();
+        device_ids.sort();
+        Ok(device_ids)
+    };
+
+    // merged, the devices of both lists are allowed
+    let storage = LocalStorage::memory()?;
+    storage.kv().set_allow_list_enabled(false)?;
+    storage.allow_list().add(333)?;
+    let summary = import_settings(&storage, &bundle, "passphrase", ImportMode::Merge)?;
+    assert!(summary.imported.iter().any(|key| key == "allow_list"));
+    assert!(summary.kept.iter().any(|key| key == "allow_list_enabled"));
+    assert_eq!(allowed_devices(&storage)?, vec![111, 222, 333]);
+    assert_eq!(storage.kv().get_allow_list_enabled()?, Some(false));
+
+    // replaced, only the devices of the bundle are
+    let summary = import_settings(&storage, &bundle, "passphrase", ImportMode::Replace)?;
+    assert!(summary
+        .imported
+        .iter()
+        .any(|key| key == "allow_list_enabled"));
+    assert_eq!(allowed_devices(&storage)?, vec![111, 222]);
+    assert_eq!(storage.kv().get_allow_list_enabled()?, Some(true));
+
+    // a bundle without an allow list empties it
+    let bundle = export_settings(&source_storage()?, "passphrase")?;
+    let summary = import_settings(&storage, &bundle, "passphrase", ImportMode::Replace)?;
+    assert!(summary.reset.iter().any(|key| key == "allow_list"));
+    assert!(allowed_devices(&storage)?.is_empty());
+    assert_eq!(storage.kv().get_allow_list_enabled()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_settings_wrong_passphrase() -> anyhow::Result<()> {
+    let bundle = export_settings(&source_storage()?, "passphrase")?;
+    let storage = LocalStorage::memory()?;
+
+    assert!(matches!(
+        import_settings(&storage, &bundle, "other", ImportMode::Replace),
+        Err(CoreError::SettingsBundleInvalidPassphrase)
+    ));
+    assert_eq!(storage.kv().get_language()?, None);
+
+    Ok(())
+}
+
+#[test]
+fn test_import_settings_of_other_versions() -> anyhow::Result<()> {
+    // a value written by an app this one can't read
+    let source = source_storage()?;
+    source.kv().set("theme", "sepia")?;
+
+    let bundle = export_settings(&source, "passphrase")?;
+    let storage = LocalStorage::memory()?;
+
+    let summary = import_settings(&storage, &bundle, "passphrase", ImportMode::Replace)?;
+    assert_eq!(summary.skipped, vec!["theme"]);
+    assert!(storage.kv().get_theme()?.is_none());
+
+    let mut newer_bundle: serde_json::Value = serde_json::from_str(&bundle)?;
+    newer_bundle["version"] = serde_json::Value::from(99);
+
+    assert!(matches!(
+        import_settings(
+            &storage,
+            &newer_bundle.to_string(),
+            "passphrase",
+            ImportMode::Replace
+        ),
+        Err(CoreError::SettingsBundleUnsupportedVersion(99))
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,44 @@
+use super::{connect, next_message};
+use crate::api::endpoint::{
+    client::OpenClients,
+    message::{DisconnectReason, EndPointMessage},
+};
+use futures::StreamExt;
+use std::time::{Duration, Instant};
+
+#[tokio::test]
+async fn test_close_all_clients_closes_streams() -> anyhow::Result<()> {
+    let (first, mut first_remote) = connect("10.0.10.1").await?;
+    let (second, mut second_remote) = connect("10.0.10.2").await?;
+    let (closed, _closed_remote) = connect("10.0.10.3").await?;
+
+    // an own registry, the global one holds the clients of other tests
+    let open_clients = OpenClients::default();
+    open_clients.register(&first);
+    open_clients.register(&second);
+    open_clients.register(&closed);
+    closed.close();
+
+    let started_at = Instant::now();
+    assert_eq!(open_clients.close_all(Duration::from_secs(1)).await, 2);
+    assert!(started_at.elapsed() < Duration::from_secs(1));
+    assert!(first.is_closed() && second.is_closed());
+
+    // the remote sides learn why and see an orderly end of the stream, not
+    // a reset
+    for remote in [&mut first_remote, &mut second_remote] {
+        assert_eq!(
+            next_message(remote).await,
+            Some(EndPointMessage::Disconnect {
+                reason: DisconnectReason::AppQuit
+            })
+        );
+
+        let end = tokio::time::timeout(Duration::from_secs(1), remote.next()).await?;
+        assert!(end.is_none());
+    }
+
+    assert_eq!(open_clients.close_all(Duration::from_secs(1)).await, 0);
+
+    Ok(())
+}
@@ -0,0 +1,110 @@
+use crate::{
+    api::{
+        config::LocalStorage,
+        signaling::{
+            subscribe_message::Subscription,
+            transport::{websocket_url, SignalingTransport},
+            SignalingClient,
+        },
+    },
+    utility::bincode::bincode_deserialize,
+};
+use futures::StreamExt;
+use tokio::net::TcpListener;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use url::Url;
+
+#[test]
+fn test_websocket_url() -> anyhow::Result<()> {
+    let url = websocket_url(&Url::parse("http://mirrorx.cloud:28000")?)?;
+    assert_eq!(url.as_str(), "ws://mirrorx.cloud:28000/subscribe");
+
+    let url = websocket_url(&Url::parse("https://mirrorx.cloud/api?v=1")?)?;
+    assert_eq!(url.as_str(), "wss://mirrorx.cloud/subscribe");
+
+    assert!(websocket_url(&Url::parse("ftp://mirrorx.cloud")?).is_err());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_subscribe_over_tcp() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let mut framed = Framed::new(
+            stream,
+            LengthDelimitedCodec::builder()
+                .length_field_length(2)
+                .little_endian()
+                .new_codec(),
+        );
+
+        let buffer = framed
+            .next()
+            .await
+            .ok_or_else(|| anyhow::anyhow!("connection closed"))??;
+        anyhow::Ok(bincode_deserialize::<Subscription>(&buffer)?)
+    });
+
+    let mut client = SignalingClient::new(format!("http://{addr}"))?;
+    client
+        .subscribe(
+            vec![addr],
+            1234567890,
+            "finger_print",
+            LocalStorage::memory()?,
+        )
+        .await?;
+    assert_eq!(client.active_transport(), Some(SignalingTransport::Tcp));
+
+    let subscription = server.await??;
+    assert_eq!(subscription.device_id, 1234567890);
+    assert_eq!(subscription.device_finger_print, "finger_print");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_subscribe_over_websocket() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let addr = listener.local_addr()?;
+
+    let server = tokio::spawn(async move {
+        let (stream, _) = listener.accept().await?;
+        let mut websocket = tokio_tungstenite::accept_async(stream).await?;
+
+        match websocket.next().await {
+            Some(Ok(Message::Binary(buffer))) => {
+                anyhow::Ok(bincode_deserialize::<Subscription>(&buffer)?)
+            }
+            message => Err(anyhow::anyhow!("unexpected message {:?}", message)),
+        }
+    });
+
+    let mut client = SignalingClient::new(format!("http://{addr}"))?;
+    client.set_transport(SignalingTransport::WebSocket);
+    assert_eq!(client.active_transport(), None);
+
+    // the subscribe port isn't used, everything goes through the domain address
+    client
+        .subscribe(
+            Vec::new(),
+            1234567890,
+            "finger_print",
+            LocalStorage::memory()?,
+        )
+        .await?;
+    assert_eq!(
+        client.active_transport(),
+        Some(SignalingTransport::WebSocket)
+    );
+
+    let subscription = server.await??;
+    assert_eq!(subscription.device_id, 1234567890);
+
+    Ok(())
+}
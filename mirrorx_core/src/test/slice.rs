@@ -0,0 +1,146 @@
+use crate::{
+    api::endpoint::message::{EndPointVideoFrame, EndPointVideoFrameSlice},
+    component::{
+        slice::{retain_h264_slices, split_h264_slices, SliceAssembler, MAX_SLICES_PER_FRAME},
+        video_encoder::media_config::MediaConfig,
+    },
+};
+
+const SPS: [u8; 8] = [0, 0, 0, 1, 0x67, 0x42, 0xC0, 0x1F];
+const PPS: [u8; 7] = [0, 0, 1, 0x68, 0xCE, 0x3C, 0x80];
+const IDR_1: [u8; 8] = [0, 0, 0, 1, 0x65, 0x88, 0x84, 0x21];
+const IDR_2: [u8; 7] = [0, 0, 1, 0x65, 0x00, 0x42, 0x11];
+const IDR_3: [u8; 7] = [0, 0, 1, 0x65, 0x00, 0x21, 0x33];
+const NON_IDR: [u8; 8] = [0, 0, 0, 1, 0x41, 0x9A, 0x02, 0x04];
+// first_mb_in_slice 2400 and 4800, macroblock rows 20 and 40 of 1920 pixels
+const NON_IDR_ROW_20: [u8; 7] = [0, 0, 1, 0x41, 0x00, 0x12, 0xC3];
+const NON_IDR_ROW_40: [u8; 8] = [0, 0, 1, 0x41, 0x00, 0x09, 0x60, 0xC0];
+
+fn frame(seq: u64, nal_units: &[&[u8]]) -> EndPointVideoFrame {
+    EndPointVideoFrame {
+        seq,
+        width: 1920,
+        height: 1080,
+        pts: seq as i64,
+        buffer: nal_units.concat(),
+    }
+}
+
+#[test]
+fn test_split_h264_slices() {
+    let keyframe = frame(7, &[&SPS[..], &PPS, &IDR_1, &IDR_2, &IDR_3]);
+    let slices = split_h264_slices(&keyframe);
+
+    assert_eq!(slices.len(), 3);
+    // the parameter sets go with the first slice
+    assert_eq!(slices[0].buffer, [&SPS[..], &PPS, &IDR_1].concat());
+    assert_eq!(slices[1].buffer, IDR_2);
+    assert_eq!(slices[2].buffer, IDR_3);
+
+    for (index, slice) in slices.iter().enumerate() {
+        assert_eq!(slice.seq, 7);
+        assert_eq!(slice.slice_index, index as u8);
+        assert_eq!(slice.slice_count, 3);
+        assert_eq!((slice.width, slice.height, slice.pts), (1920, 1080, 7));
+    }
+
+    // a single slice stays the whole frame
+    let delta_frame = frame(8, &[&NON_IDR[..]]);
+    let slices = split_h264_slices(&delta_frame);
+    assert_eq!(slices.len(), 1);
+    assert_eq!(slices[0].buffer, delta_frame.buffer);
+}
+
+#[test]
+fn test_slice_assembler_reassembles_in_order() {
+    let keyframe = frame(0, &[&SPS[..], &PPS, &IDR_1, &IDR_2, &IDR_3]);
+    let mut slices = split_h264_slices(&keyframe);
+    let mut assembler = SliceAssembler::default();
+
+    // slices may arrive out of order, the frame is put together by index
+    slices.swap(0, 2);
+    assert_eq!(assembler.push(slices[0].clone()), None);
+    assert_eq!(assembler.push(slices[1].clone()), None);
+    assert_eq!(assembler.push(slices[2].clone()), Some(keyframe));
+
+    // a late duplicate doesn't produce the frame twice
+    assert_eq!(assembler.push(slices[1].clone()), None);
+    assert_eq!(assembler.dropped(), 0);
+}
+
+#[test]
+fn test_slice_assembler_drops_incomplete_frame() {
+    let mut assembler = SliceAssembler::default();
+
+    let first = split_h264_slices(&frame(1, &[&IDR_1[..], &IDR_2]));
+    let second = frame(2, &[&NON_IDR[..], &NON_IDR]);
+    let second_slices = split_h264_slices(&second);
+
+    assert_eq!(assembler.push(first[0].clone()), None);
+
+    // the next frame starts before the first one completed
+    assert_eq!(assembler.push(second_slices[0].clone()), None);
+    assert_eq!(assembler.dropped(), 1);
+
+    // the rest of the given up frame is ignored
+    assert_eq!(assembler.push(first[1].clone()), None);
+    assert_eq!(assembler.push(second_slices[1].clone()), Some(second));
+
+    // malformed slices are dropped
+    let slice = EndPointVideoFrameSlice {
+        seq: 3,
+        slice_index: 2,
+        slice_count: 2,
+        width: 1920,
+        height: 1080,
+        pts: 3,
+        buffer: NON_IDR.to_vec(),
+    };
+    assert_eq!(assembler.push(slice), None);
+}
+
+#[test]
+fn test_retain_h264_slices() {
+    let delta_frame = frame(4, &[&NON_IDR[..], &NON_IDR_ROW_20, &NON_IDR_ROW_40]);
+
+    // rows 400 to 464 are in the macroblock rows of the second slice
+    let mut partial = delta_frame.clone();
+    assert!(retain_h264_slices(&mut partial, 400..464));
+    assert_eq!(partial.buffer, NON_IDR_ROW_20);
+
+    // rows 300 to 340 span the first two slices
+    let mut partial = delta_frame.clone();
+    assert!(retain_h264_slices(&mut partial, 300..340));
+    assert_eq!(partial.buffer, [&NON_IDR[..], &NON_IDR_ROW_20].concat());
+
+    let mut partial = delta_frame;
+    assert!(retain_h264_slices(&mut partial, 1000..1080));
+    assert_eq!(partial.buffer, NON_IDR_ROW_40);
+
+    // keyframes and single slices go out whole
+    let mut keyframe = frame(5, &[&SPS[..], &PPS, &IDR_1, &IDR_2]);
+    assert!(!retain_h264_slices(&mut keyframe, 0..16));
+    assert_eq!(keyframe, frame(5, &[&SPS[..], &PPS, &IDR_1, &IDR_2]));
+
+    let mut single = frame(6, &[&NON_IDR[..]]);
+    assert!(!retain_h264_slices(&mut single, 0..16));
+}
+
+#[test]
+fn test_slices_per_frame_sanitize() {
+    assert_eq!(MediaConfig::default().slices_per_frame, 1);
+
+    let config = MediaConfig {
+        slices_per_frame: 0,
+        ..MediaConfig::default()
+    }
+    .sanitize();
+    assert_eq!(config.slices_per_frame, 1);
+
+    let config = MediaConfig {
+        slices_per_frame: 100,
+        ..MediaConfig::default()
+    }
+    .sanitize();
+    assert_eq!(config.slices_per_frame, MAX_SLICES_PER_FRAME);
+}
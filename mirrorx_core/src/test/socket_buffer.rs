@@ -0,0 +1,99 @@
+use crate::api::endpoint::{
+    id::EndPointID,
+    socket_buffer::{
+        buffer_for_bdp, measured_bdp, record_bdp, set_socket_buffers, SocketBufferConfig,
+        MAX_SOCKET_BUFFER_BYTES, MIN_SOCKET_BUFFER_BYTES,
+    },
+};
+use socket2::SockRef;
+use std::time::Duration;
+use tokio::net::{TcpListener, TcpStream};
+
+#[test]
+fn test_socket_buffer_config_sanitize() {
+    let config = SocketBufferConfig {
+        recv_buffer_bytes: Some(1024),
+        send_buffer_bytes: Some(usize::MAX),
+    }
+    .sanitize();
+
+    assert_eq!(config.recv_buffer_bytes, Some(MIN_SOCKET_BUFFER_BYTES));
+    assert_eq!(config.send_buffer_bytes, Some(MAX_SOCKET_BUFFER_BYTES));
+    assert_eq!(
+        SocketBufferConfig::default().sanitize(),
+        SocketBufferConfig::default()
+    );
+}
+
+#[test]
+fn test_socket_buffer_config_resolve() {
+    // nothing set and nothing measured keeps the system defaults
+    assert_eq!(SocketBufferConfig::default().resolve(None), (None, None));
+
+    // 50 Mbps at 80 ms
+    let bdp = 500 * 1000;
+    assert_eq!(
+        SocketBufferConfig::default().resolve(Some(bdp)),
+        (Some(1000 * 1000), Some(1000 * 1000))
+    );
+
+    // set sizes win over measured ones
+    let config = SocketBufferConfig {
+        recv_buffer_bytes: Some(4 * 1024 * 1024),
+        send_buffer_bytes: None,
+    };
+    assert_eq!(
+        config.resolve(Some(bdp)),
+        (Some(4 * 1024 * 1024), Some(1000 * 1000))
+    );
+}
+
+#[test]
+fn test_buffer_for_bdp() {
+    assert_eq!(buffer_for_bdp(0), MIN_SOCKET_BUFFER_BYTES);
+    assert_eq!(buffer_for_bdp(1024 * 1024), 2 * 1024 * 1024);
+    assert_eq!(buffer_for_bdp(u64::MAX), MAX_SOCKET_BUFFER_BYTES);
+}
+
+#[test]
+fn test_record_bdp() {
+    let endpoint_id = EndPointID::DeviceID {
+        local_device_id: 173,
+        remote_device_id: 371,
+    };
+
+    assert_eq!(measured_bdp(&endpoint_id), None);
+
+    record_bdp(endpoint_id, 8 * 1000 * 1000, Duration::from_millis(100));
+    assert_eq!(measured_bdp(&endpoint_id), Some(100 * 1000));
+}
+
+#[tokio::test]
+async fn test_set_socket_buffers() -> anyhow::Result<()> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let stream = TcpStream::connect(listener.local_addr()?).await?;
+    let _ = listener.accept().await?;
+
+    // nothing asked for, nothing changes
+    let defaults = set_socket_buffers(SockRef::from(&stream), None, None);
+    assert_eq!(
+        set_socket_buffers(SockRef::from(&stream), None, None),
+        defaults
+    );
+
+    // systems round the sizes up or double them, none goes below
+    let buffers = set_socket_buffers(
+        SockRef::from(&stream),
+        Some(MIN_SOCKET_BUFFER_BYTES),
+        Some(MIN_SOCKET_BUFFER_BYTES),
+    );
+
+    if let Some(recv_buffer_bytes) = buffers.recv_buffer_bytes {
+        assert!(recv_buffer_bytes >= MIN_SOCKET_BUFFER_BYTES);
+    }
+    if let Some(send_buffer_bytes) = buffers.send_buffer_bytes {
+        assert!(send_buffer_bytes >= MIN_SOCKET_BUFFER_BYTES);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use crate::api::config::{entity::domain::Domain, LocalStorage, Storage};
+
+fn new_domain(name: &str, is_primary: bool) -> Domain {
+    Domain {
+        id: 0,
+        name: name.to_string(),
+        addr: String::from("http://127.0.0.1:28000"),
+        signaling_port: 28001,
+        subscribe_port: 28002,
+        is_primary,
+        device_id: 1234567890,
+        password: String::from("password"),
+        finger_print: String::from("finger_print"),
+        remarks: String::default(),
+    }
+}
+
+#[test]
+fn test_memory_storage_domain() -> anyhow::Result<()> {
+    let storage = LocalStorage::memory()?;
+
+    storage.domain().add_domain(new_domain("first", true))?;
+    let second = storage.domain().add_domain(new_domain("second", false))?;
+
+    assert_eq!(storage.domain().get_domain_count()?, 2);
+    assert_eq!(storage.domain().get_primary_domain()?.name, "first");
+
+    storage.domain().set_domain_is_primary(second.id)?;
+    assert_eq!(storage.domain().get_primary_domain()?.name, "second");
+
+    Ok(())
+}
+
+#[test]
+fn test_domain_debug_hides_password() {
+    let domain = new_domain("first", true);
+    let debug = format!("{:?}", domain);
+
+    assert!(debug.contains("first"));
+    assert!(!debug.contains(&domain.password));
+}
+
+#[test]
+fn test_memory_storage_is_isolated() -> anyhow::Result<()> {
+    let first = LocalStorage::memory()?;
+    let second = LocalStorage::memory()?;
+
+    first.kv().set_language("en")?;
+
+    assert_eq!(first.kv().get_language()?, Some(String::from("en")));
+    assert_eq!(second.kv().get_language()?, None);
+
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use super::{next_message, Remote};
+use crate::{
+    api::endpoint::{
+        capabilities::{local_capabilities, local_media_limits},
+        client::EndPointClient,
+        handlers::video_frame::VideoDecodeMessage,
+        id::EndPointID,
+        idle::SessionRole,
+        message::{
+            EndPointMessage, EndPointNegotiateDesktopParamsRequest,
+            EndPointNegotiateDesktopParamsResponse, EndPointNegotiateFinishedRequest,
+            EndPointNegotiateVisitDesktopParams, EndPointVideoFrame, VideoCodec,
+        },
+        packet::{frame_codec, serialize_packet},
+        swap::{request_swap, respond_swap, subscribe_swap_request, SwapOutcome},
+        EndPointStream,
+    },
+    component::{desktop::monitor::Monitor, video_encoder::media_config::MediaConfig},
+};
+use bytes::Bytes;
+use futures::SinkExt;
+use std::{net::IpAddr, sync::Arc, time::Duration};
+use tokio::{net::TcpListener, sync::mpsc::Receiver};
+use tokio_util::codec::Framed;
+
+/// A viewer on this side and the raw connection of the host it watches.
+async fn loopback_viewer(
+    remote_ip: &str,
+) -> anyhow::Result<(Arc<EndPointClient>, Receiver<VideoDecodeMessage>, Remote)> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let endpoint_id = EndPointID::LANID {
+        local_ip: "127.0.0.1".parse::<IpAddr>()?,
+        remote_ip: remote_ip.parse::<IpAddr>()?,
+    };
+
+    let (video_frame_tx, video_frame_rx) = tokio::sync::mpsc::channel(16);
+    let (audio_frame_tx, _audio_frame_rx) = tokio::sync::mpsc::channel(16);
+    let addr = listener.local_addr()?;
+
+    let client = tokio::spawn(async move {
+        EndPointClient::new_desktop_active(
+            endpoint_id,
+            None,
+            EndPointStream::ActiveTCP(addr),
+            video_frame_tx,
+            audio_frame_tx,
+            None,
+            MediaConfig::default(),
+        )
+        .await
+    });
+
+    let (stream, _) = listener.accept().await?;
+    let mut remote = Framed::new(stream, frame_codec());
+
+    send(
+        &mut remote,
+        EndPointMessage::Capabilities(local_capabilities()),
+    )
+    .await?;
+
+    answer_negotiation(&mut remote).await?;
+
+    Ok((client.await??, video_frame_rx, remote))
+}
+
+/// Plays the host side of the media negotiation.
+async fn answer_negotiation(remote: &mut Remote) -> anyhow::Result<()> {
+    assert!(matches!(
+        next_message(remote).await,
+        Some(EndPointMessage::NegotiateDesktopParamsRequest(_))
+    ));
+
+    send(
+        remote,
+        EndPointMessage::NegotiateDesktopParamsResponse(
+            EndPointNegotiateDesktopParamsResponse::Params(desktop_params()),
+        ),
+    )
+    .await?;
+
+    assert!(matches!(
+        next_message(remote).await,
+        Some(EndPointMessage::NegotiateFinishedRequest(_))
+    ));
+
+    Ok(())
+}
+
+async fn send(remote: &mut Remote, message: EndPointMessage) -> anyhow::Result<()> {
+    remote
+        .send(Bytes::from(serialize_packet(&message)?))
+        .await?;
+    Ok(())
+}
+
+/// Skips the codec the decoder is told about on every negotiation.
+async fn next_frame_seq(video_frame_rx: &mut Receiver<VideoDecodeMessage>) -> Option<u64> {
+    loop {
+        match tokio::time::timeout(Duration::from_millis(300), video_frame_rx.recv()).await {
+            Ok(Some(VideoDecodeMessage::Frame(frame))) => return Some(frame.seq),
+            Ok(Some(VideoDecodeMessage::Codec(_))) => continue,
+            _ => return None,
+        }
+    }
+}
+
+fn video_frame(seq: u64) -> EndPointMessage {
+    EndPointMessage::VideoFrame(EndPointVideoFrame {
+        seq,
+        width: 1920,
+        height: 1080,
+        pts: seq as i64,
+        buffer: vec![0; 16],
+    })
+}
+
+fn desktop_params() -> EndPointNegotiateVisitDesktopParams {
+    EndPointNegotiateVisitDesktopParams {
+        video_codec: VideoCodec::H264,
+        media_limits: local_media_limits(),
+        audio: None,
+        os_type: String::from(""),
+        os_version: String::from(""),
+        primary_monitor: Monitor {
+            id: String::from("1"),
+            name: String::from("primary"),
+            refresh_rate: 60,
+            width: 1920,
+            height: 1080,
+            is_primary: true,
+            screen_shot: None,
+            left: 0,
+            top: 0,
+        },
+        encoder_graphics_card: None,
+        session_token: None,
+    }
+}
+
+#[tokio::test]
+async fn test_swap_reverses_media_flow() -> anyhow::Result<()> {
+    let (client, mut video_frame_rx, mut remote) = loopback_viewer("10.0.1.1").await?;
+    let endpoint_id = client.endpoint_id();
+    let mut swap_request_rx = subscribe_swap_request();
+
+    send(&mut remote, video_frame(0)).await?;
+    assert_eq!(next_frame_seq(&mut video_frame_rx).await, Some(0));
+
+    // the host asks, this side starts sharing once its user agrees
+    send(&mut remote, EndPointMessage::SwapDirectionRequest).await?;
+    loop {
+        let event = tokio::time::timeout(Duration::from_secs(1), swap_request_rx.recv()).await??;
+        if event.remote == "10.0.1.1" {
+            assert!(event.share_screen);
+            break;
+        }
+    }
+
+    let outcome = respond_swap(endpoint_id, true, false, MediaConfig::default()).await?;
+    assert!(matches!(outcome, SwapOutcome::Hosting));
+    assert_eq!(client.role(), Some(SessionRole::Host));
+    assert!(!client.input_allowed());
+    assert_eq!(
+        next_message(&mut remote).await,
+        Some(EndPointMessage::SwapDirectionReply { accepted: true })
+    );
+
+    // frames of the former host are no longer shown
+    send(&mut remote, video_frame(1)).await?;
+    assert_eq!(next_frame_seq(&mut video_frame_rx).await, None);
+
+    // and this side asks to watch again
+    let swap_back = tokio::spawn(request_swap(endpoint_id, true, MediaConfig::default()));
+
+    assert_eq!(
+        next_message(&mut remote).await,
+        Some(EndPointMessage::SwapDirectionRequest)
+    );
+    send(
+        &mut remote,
+        EndPointMessage::SwapDirectionReply { accepted: true },
+    )
+    .await?;
+    answer_negotiation(&mut remote).await?;
+
+    assert!(matches!(swap_back.await??, SwapOutcome::Viewing(None)));
+    assert_eq!(client.role(), Some(SessionRole::Viewer));
+
+    // the renegotiated stream starts over and reaches the same viewer
+    send(&mut remote, video_frame(0)).await?;
+    assert_eq!(next_frame_seq(&mut video_frame_rx).await, Some(0));
+
+    client.close();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_swap_needs_consent() -> anyhow::Result<()> {
+    let (client, mut video_frame_rx, mut remote) = loopback_viewer("10.0.1.2").await?;
+    let endpoint_id = client.endpoint_id();
+
+    let swap = tokio::spawn(request_swap(endpoint_id, false, MediaConfig::default()));
+
+    assert_eq!(
+        next_message(&mut remote).await,
+        Some(EndPointMessage::SwapDirectionRequest)
+    );
+    send(
+        &mut remote,
+        EndPointMessage::SwapDirectionReply { accepted: false },
+    )
+    .await?;
+
+    assert!(matches!(swap.await??, SwapOutcome::Declined));
+    assert_eq!(client.role(), Some(SessionRole::Viewer));
+
+    send(&mut remote, video_frame(0)).await?;
+    assert_eq!(next_frame_seq(&mut video_frame_rx).await, Some(0));
+
+    // there is no request of the remote side to accept
+    assert!(
+        respond_swap(endpoint_id, true, true, MediaConfig::default())
+            .await
+            .is_err()
+    );
+    assert_eq!(client.role(), Some(SessionRole::Viewer));
+    assert_eq!(next_message(&mut remote).await, None);
+
+    client.close();
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_negotiation_needs_agreed_swap() -> anyhow::Result<()> {
+    let (client, mut video_frame_rx, mut remote) = loopback_viewer("10.0.1.3").await?;
+
+    // the host can't start this side sharing without asking first
+    send(
+        &mut remote,
+        EndPointMessage::NegotiateDesktopParamsRequest(EndPointNegotiateDesktopParamsRequest {
+            video_codecs: vec![VideoCodec::H264],
+            media_limits: local_media_limits(),
+            audio: None,
+            rejoin_token: None,
+        }),
+    )
+    .await?;
+    assert_eq!(next_message(&mut remote).await, None);
+
+    send(
+        &mut remote,
+        EndPointMessage::NegotiateFinishedRequest(EndPointNegotiateFinishedRequest {
+            media_config: MediaConfig::default(),
+        }),
+    )
+    .await?;
+    send(&mut remote, video_frame(0)).await?;
+
+    assert_eq!(next_frame_seq(&mut video_frame_rx).await, Some(0));
+    assert_eq!(client.role(), Some(SessionRole::Viewer));
+    assert!(!client.input_allowed());
+
+    client.close();
+
+    Ok(())
+}
@@ -0,0 +1,27 @@
+use crate::utility::thread_priority::{
+    elevate_media_thread, media_thread_priority, set_media_thread_priority,
+    subscribe_thread_priority,
+};
+
+#[test]
+fn test_media_thread_priority_elevation_reported() {
+    assert!(!media_thread_priority());
+
+    let mut rx = subscribe_thread_priority();
+
+    // off by default, nothing is raised or reported
+    assert!(elevate_media_thread(String::from("test"), "capture").is_none());
+    assert!(rx.try_recv().is_err());
+
+    set_media_thread_priority(true);
+    let guard = elevate_media_thread(String::from("test"), "encode");
+    set_media_thread_priority(false);
+
+    let event = rx.try_recv().unwrap();
+    assert_eq!(event.thread, "encode");
+    assert_eq!(event.elevated, guard.is_some());
+    assert_eq!(event.reason.is_none(), event.elevated);
+
+    #[cfg(not(any(target_os = "windows", target_os = "macos")))]
+    assert!(!event.elevated);
+}
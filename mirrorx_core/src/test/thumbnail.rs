@@ -0,0 +1,120 @@
+use crate::{
+    api::endpoint::id::EndPointID,
+    component::{
+        frame::{DesktopDecodeFrame, DesktopDecodeFrameFormat},
+        video_decoder::thumbnail::{
+            downsample_frame, encode_jpeg, set_thumbnail_config, ThumbnailConfig, Thumbnailer,
+        },
+    },
+};
+use std::time::{Duration, Instant};
+
+fn nv12_frame(width: i32, height: i32, luma: u8) -> DesktopDecodeFrame {
+    DesktopDecodeFrame {
+        width,
+        height,
+        plane_data: vec![
+            vec![luma; (width * height) as usize],
+            vec![128; (width * height / 2) as usize],
+        ],
+        line_sizes: vec![width, width],
+        format: DesktopDecodeFrameFormat::NV12,
+        budget_charge: None,
+    }
+}
+
+#[test]
+fn test_downsample_keeps_aspect_ratio() {
+    let frame = nv12_frame(64, 32, 235);
+
+    let image = downsample_frame(&frame, 16, 16).unwrap();
+    assert_eq!(image.dimensions(), (16, 8));
+    assert!(image.pixels().all(|pixel| pixel.0 == [255, 255, 255]));
+
+    // never scaled up
+    let image = downsample_frame(&frame, 640, 640).unwrap();
+    assert_eq!(image.dimensions(), (64, 32));
+}
+
+#[test]
+fn test_downsample_yuv420p() {
+    let (width, height) = (32, 16);
+
+    // black left half, white right half
+    let luma: Vec<u8> = (0..width * height)
+        .map(|index| if index % width < width / 2 { 16 } else { 235 })
+        .collect();
+
+    let frame = DesktopDecodeFrame {
+        width,
+        height,
+        plane_data: vec![
+            luma,
+            vec![128; (width * height / 4) as usize],
+            vec![128; (width * height / 4) as usize],
+        ],
+        line_sizes: vec![width, width / 2, width / 2],
+        format: DesktopDecodeFrameFormat::YUV420P,
+        budget_charge: None,
+    };
+
+    let image = downsample_frame(&frame, 8, 8).unwrap();
+    assert_eq!(image.dimensions(), (8, 4));
+    assert_eq!(image.get_pixel(0, 0).0, [0, 0, 0]);
+    assert_eq!(image.get_pixel(7, 3).0, [255, 255, 255]);
+}
+
+#[test]
+fn test_downsample_rejects_short_planes() {
+    let mut frame = nv12_frame(64, 32, 235);
+    frame.plane_data[1].truncate(16);
+
+    assert!(downsample_frame(&frame, 16, 16).is_none());
+}
+
+#[test]
+fn test_thumbnail_interval() {
+    let frame = nv12_frame(64, 32, 128);
+    let mut thumbnailer = Thumbnailer::new(EndPointID::DeviceID {
+        local_device_id: 1,
+        remote_device_id: 2,
+    });
+
+    set_thumbnail_config(ThumbnailConfig {
+        interval_secs: 2,
+        ..ThumbnailConfig::default()
+    });
+
+    let now = Instant::now();
+    let thumbnail = thumbnailer.offer_at(&frame, now).unwrap();
+    assert_eq!((thumbnail.width, thumbnail.height), (64, 32));
+
+    let jpeg = base64::decode(thumbnail.jpeg).unwrap();
+    assert_eq!(&jpeg[..2], &[0xFF, 0xD8]);
+
+    assert!(thumbnailer
+        .offer_at(&frame, now + Duration::from_secs(1))
+        .is_none());
+    assert!(thumbnailer
+        .offer_at(&frame, now + Duration::from_secs(2))
+        .is_some());
+
+    set_thumbnail_config(ThumbnailConfig {
+        enabled: false,
+        ..ThumbnailConfig::default()
+    });
+    assert!(thumbnailer
+        .offer_at(&frame, now + Duration::from_secs(10))
+        .is_none());
+
+    set_thumbnail_config(ThumbnailConfig::default());
+}
+
+#[test]
+fn test_encode_jpeg() {
+    let image = downsample_frame(&nv12_frame(16, 16, 16), 16, 16).unwrap();
+    let jpeg = encode_jpeg(&image, 70).unwrap();
+
+    assert_eq!(&jpeg[..2], &[0xFF, 0xD8]);
+    assert_eq!(&jpeg[jpeg.len() - 2..], &[0xFF, 0xD9]);
+}
@@ -0,0 +1,69 @@
+use crate::{
+    api::endpoint::{client::EndPointClient, id::EndPointID, EndPointStream},
+    component::{
+        fec::{FecDecoder, FecEvent},
+        video_encoder::{
+            config::libx264::Libx264Config, media_config::MediaConfig, preprocess::CapturedDisplay,
+            video_encoder::VideoEncoder,
+        },
+    },
+};
+use std::{net::IpAddr, sync::Arc};
+use tokio::net::TcpListener;
+
+async fn loopback_client() -> anyhow::Result<Arc<EndPointClient>> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+
+    let client = EndPointClient::new_file_manager_active(
+        EndPointID::LANID {
+            local_ip: "127.0.0.1".parse::<IpAddr>()?,
+            remote_ip: "10.0.0.1".parse::<IpAddr>()?,
+        },
+        None,
+        EndPointStream::ActiveTCP(listener.local_addr()?),
+        None,
+    )
+    .await?;
+
+    Ok(client)
+}
+
+fn new_encoder(client: &Arc<EndPointClient>) -> anyhow::Result<VideoEncoder<Libx264Config>> {
+    Ok(VideoEncoder::new(
+        Libx264Config::new(),
+        MediaConfig::default(),
+        client.clone(),
+        CapturedDisplay::default(),
+    )?)
+}
+
+#[tokio::test]
+async fn test_recreated_encoder_continues_frame_seq() -> anyhow::Result<()> {
+    let client = loopback_client().await?;
+    let mut decoder = FecDecoder::new(0);
+    let mut events = Vec::new();
+
+    let encoder = new_encoder(&client)?;
+    for pts in 0..3 {
+        events.extend(decoder.push_frame(encoder.video_frame(1920, 1080, pts, vec![1; 16])));
+    }
+
+    // the hardware encoder failed and the software one takes over
+    drop(encoder);
+    let encoder = new_encoder(&client)?;
+    for pts in 3..5 {
+        events.extend(decoder.push_frame(encoder.video_frame(1920, 1080, pts, vec![2; 16])));
+    }
+
+    let delivered: Vec<u64> = events
+        .iter()
+        .filter_map(|event| match event {
+            FecEvent::Frame(frame) => Some(frame.seq),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(delivered, vec![0, 1, 2, 3, 4]);
+
+    client.close();
+    Ok(())
+}
@@ -0,0 +1,89 @@
+use crate::{
+    component::desktop::{
+        monitor::{select_primary_monitor, Monitor},
+        virtual_display::resolve_primary_monitor,
+    },
+    core_error,
+    error::CoreError,
+};
+use std::{
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+fn monitor(id: &str, is_primary: bool) -> Monitor {
+    Monitor {
+        id: String::from(id),
+        name: String::from(id),
+        refresh_rate: 60,
+        width: 1920,
+        height: 1080,
+        is_primary,
+        screen_shot: None,
+        left: 0,
+        top: 0,
+    }
+}
+
+#[test]
+fn test_select_primary_monitor() {
+    assert!(matches!(
+        select_primary_monitor(Vec::new()),
+        Err(CoreError::NoDisplayAvailable)
+    ));
+
+    let primary = select_primary_monitor(vec![monitor("1", false), monitor("2", true)]).unwrap();
+    assert_eq!(primary.id, "2");
+
+    // displays without a primary one are an error, not a headless machine
+    assert!(matches!(
+        select_primary_monitor(vec![monitor("1", false)]),
+        Err(CoreError::Other { .. })
+    ));
+}
+
+#[test]
+fn test_headless_without_virtual_display() {
+    let result = resolve_primary_monitor(|| Ok(Vec::new()), || Ok(false), Duration::ZERO);
+    assert!(matches!(result, Err(CoreError::NoDisplayAvailable)));
+
+    let result = resolve_primary_monitor(
+        || Ok(Vec::new()),
+        || Err(core_error!("driver not installed")),
+        Duration::ZERO,
+    );
+    assert!(matches!(result, Err(CoreError::NoDisplayAvailable)));
+}
+
+#[test]
+fn test_headless_with_virtual_display() {
+    let added = AtomicBool::new(false);
+
+    let result = resolve_primary_monitor(
+        || {
+            if added.load(Ordering::SeqCst) {
+                Ok(vec![monitor("virtual", true)])
+            } else {
+                Ok(Vec::new())
+            }
+        },
+        || {
+            added.store(true, Ordering::SeqCst);
+            Ok(true)
+        },
+        Duration::from_secs(1),
+    );
+
+    assert_eq!(result.unwrap().id, "virtual");
+}
+
+#[test]
+fn test_attached_display_skips_virtual_display() {
+    let result = resolve_primary_monitor(
+        || Ok(vec![monitor("1", true)]),
+        || panic!("virtual display added with a display attached"),
+        Duration::ZERO,
+    );
+
+    assert_eq!(result.unwrap().id, "1");
+}
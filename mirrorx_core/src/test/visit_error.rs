@@ -0,0 +1,45 @@
+use crate::{
+    api::signaling::{http_message::HttpError, subscribe_message::VisitFailureReason},
+    error::CoreError,
+};
+
+#[test]
+fn test_visit_failures_map_to_distinct_errors() {
+    assert!(matches!(
+        CoreError::from(HttpError::RemoteOffline),
+        CoreError::VisitRemoteOffline
+    ));
+    assert!(matches!(
+        CoreError::from(HttpError::Timeout),
+        CoreError::VisitTimeout
+    ));
+    assert!(matches!(
+        CoreError::from(HttpError::ResourceExhausted),
+        CoreError::VisitServerBusy
+    ));
+    assert!(matches!(
+        CoreError::from(VisitFailureReason::RemoteReject),
+        CoreError::VisitRemoteRejected
+    ));
+    assert!(matches!(
+        CoreError::from(VisitFailureReason::InvalidPassword),
+        CoreError::VisitInvalidPassword
+    ));
+    assert!(matches!(
+        CoreError::from(VisitFailureReason::NotAllowed),
+        CoreError::VisitNotAllowed
+    ));
+}
+
+#[test]
+fn test_visit_error_retryable() {
+    assert!(CoreError::VisitRemoteOffline.is_retryable());
+    assert!(CoreError::VisitTimeout.is_retryable());
+    assert!(CoreError::VisitServerBusy.is_retryable());
+
+    // asking again gets the same answer
+    assert!(!CoreError::VisitRemoteRejected.is_retryable());
+    assert!(!CoreError::VisitInvalidPassword.is_retryable());
+    assert!(!CoreError::VisitKeyGenerationFailed.is_retryable());
+    assert!(!CoreError::VisitKeyExchangeFailed.is_retryable());
+}
@@ -0,0 +1,71 @@
+use crate::{
+    api::{
+        endpoint::handshake::HandshakeFailure,
+        signaling::visit_failure::{visit_retry_delay, VisitFailure, VisitRecovery},
+    },
+    error::CoreError,
+};
+use std::time::Duration;
+
+#[test]
+fn test_visit_failure_recovery() {
+    let cases = [
+        (CoreError::VisitRemoteOffline, VisitRecovery::RetryLater),
+        (CoreError::VisitRemoteRejected, VisitRecovery::None),
+        (
+            CoreError::VisitInvalidPassword,
+            VisitRecovery::ReenterPassword,
+        ),
+        (CoreError::VisitTimeout, VisitRecovery::RetryLater),
+        (CoreError::VisitServerBusy, VisitRecovery::RetryLater),
+        (CoreError::VisitInvalidArgs, VisitRecovery::None),
+        (CoreError::VisitKeyExchangeFailed, VisitRecovery::None),
+        (CoreError::VisitSignatureInvalid, VisitRecovery::None),
+        (CoreError::VisitDeviceUntrusted, VisitRecovery::None),
+        (CoreError::VisitReplayed, VisitRecovery::None),
+        (CoreError::HandshakeTokenExpired, VisitRecovery::RetryLater),
+        (
+            CoreError::HandshakeFailed(HandshakeFailure::Closed),
+            VisitRecovery::RetryLater,
+        ),
+        (
+            CoreError::HandshakeFailed(HandshakeFailure::MismatchedReply),
+            VisitRecovery::None,
+        ),
+        (
+            CoreError::ProtocolMismatch {
+                version: 2,
+                codec: None,
+            },
+            VisitRecovery::None,
+        ),
+    ];
+
+    for (err, recovery) in cases {
+        let failure = VisitFailure::from(err);
+        assert_eq!(failure.recovery, recovery);
+        assert!(!failure.message.is_empty());
+    }
+
+    let failure =
+        serde_json::to_value(VisitFailure::from(CoreError::VisitInvalidPassword)).unwrap();
+    assert_eq!(failure["recovery"], "reenter_password");
+    assert_eq!(failure["message"], "The password is incorrect.");
+}
+
+#[test]
+fn test_visit_retry_delay() {
+    assert_eq!(
+        visit_retry_delay(&CoreError::VisitTimeout, 1),
+        Some(Duration::from_secs(1))
+    );
+    assert_eq!(
+        visit_retry_delay(&CoreError::VisitServerBusy, 2),
+        Some(Duration::from_secs(2))
+    );
+    assert_eq!(visit_retry_delay(&CoreError::VisitTimeout, 3), None);
+
+    // these don't pass within a few seconds
+    assert_eq!(visit_retry_delay(&CoreError::VisitRemoteOffline, 1), None);
+    assert_eq!(visit_retry_delay(&CoreError::VisitInvalidPassword, 1), None);
+}
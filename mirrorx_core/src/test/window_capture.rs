@@ -0,0 +1,242 @@
+use crate::{
+    api::endpoint::{handlers::input::map_to_window, message::MouseEvent},
+    component::{
+        desktop::{
+            monitor::Monitor,
+            window::{
+                crop_frame, display_of_window, window_frame_rect, CapturedWindow, FrameRect,
+                WindowChange, WindowFollower, WindowInfo,
+            },
+        },
+        frame::DesktopEncodeFrame,
+        input::{coordinate::CaptureArea, key::MouseKey},
+    },
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+fn area(left: f64, top: f64, width: f64, height: f64) -> CaptureArea {
+    CaptureArea {
+        left,
+        top,
+        width,
+        height,
+    }
+}
+
+fn displays() -> Vec<(String, CaptureArea)> {
+    vec![
+        (String::from("primary"), area(0.0, 0.0, 1920.0, 1080.0)),
+        (String::from("right"), area(1920.0, 0.0, 1280.0, 1024.0)),
+    ]
+}
+
+fn window(left: i32, top: i32, width: i32, height: i32) -> WindowInfo {
+    WindowInfo {
+        id: 42,
+        title: String::from("notes"),
+        owner_process: String::from("editor"),
+        owner_pid: 7,
+        left,
+        top,
+        width,
+        height,
+        visible: true,
+    }
+}
+
+/// Every luma byte holds its column, every chroma byte its row.
+fn new_frame(width: i32, height: i32) -> DesktopEncodeFrame {
+    let luminance_bytes = (0..height)
+        .flat_map(|_| (0..width).map(|x| x as u8))
+        .collect();
+    let chrominance_bytes = (0..height / 2)
+        .flat_map(|y| (0..width).map(move |_| y as u8))
+        .collect();
+
+    DesktopEncodeFrame {
+        capture_time: Duration::ZERO,
+        width,
+        height,
+        luminance_bytes,
+        luminance_stride: width,
+        chrominance_bytes,
+        chrominance_stride: width,
+        dirty_rects: None,
+    }
+}
+
+#[test]
+fn test_window_frame_rect() {
+    let display = area(1920.0, 0.0, 1280.0, 1024.0);
+
+    assert_eq!(
+        window_frame_rect(&area(2021.0, 51.0, 300.0, 201.0), &display, 1280, 1024),
+        Some(FrameRect {
+            left: 100,
+            top: 50,
+            width: 300,
+            height: 202,
+        })
+    );
+
+    // the part hanging over the edges is left out
+    assert_eq!(
+        window_frame_rect(&area(1820.0, -20.0, 300.0, 200.0), &display, 1280, 1024),
+        Some(FrameRect {
+            left: 0,
+            top: 0,
+            width: 200,
+            height: 180,
+        })
+    );
+
+    // points on a HiDPI display, the frame is in pixels
+    assert_eq!(
+        window_frame_rect(
+            &area(10.0, 20.0, 100.0, 50.0),
+            &area(0.0, 0.0, 1440.0, 900.0),
+            2880,
+            1800
+        ),
+        Some(FrameRect {
+            left: 20,
+            top: 40,
+            width: 200,
+            height: 100,
+        })
+    );
+
+    // a minimized window on Windows sits far off every display
+    assert_eq!(
+        window_frame_rect(&area(-32000.0, -32000.0, 160.0, 28.0), &display, 1280, 1024),
+        None
+    );
+}
+
+#[test]
+fn test_display_of_window() {
+    let displays = displays();
+
+    let on_primary = display_of_window(&area(100.0, 100.0, 800.0, 600.0), &displays).unwrap();
+    assert_eq!(on_primary.0, "primary");
+
+    // across both, the one showing more of it wins
+    let across = display_of_window(&area(1800.0, 100.0, 800.0, 600.0), &displays).unwrap();
+    assert_eq!(across.0, "right");
+
+    assert!(display_of_window(&area(-32000.0, -32000.0, 160.0, 28.0), &displays).is_none());
+}
+
+#[test]
+fn test_crop_frame() {
+    let frame = new_frame(16, 8);
+    let rect = FrameRect {
+        left: 4,
+        top: 2,
+        width: 6,
+        height: 4,
+    };
+
+    let cropped = crop_frame(&frame, &rect);
+    assert_eq!((cropped.width, cropped.height), (6, 4));
+    assert_eq!(cropped.luminance_stride, 6);
+    assert_eq!(cropped.luminance_bytes, [4, 5, 6, 7, 8, 9].repeat(4));
+    assert_eq!(cropped.chrominance_bytes, [[1; 6], [2; 6]].concat());
+}
+
+#[test]
+fn test_window_follower() {
+    let now = Instant::now();
+    let mut follower = WindowFollower::new(&window(100, 100, 640, 480), &displays(), now).unwrap();
+    assert_eq!(follower.display_id(), "primary");
+    assert!(!follower.due(now));
+    assert!(follower.due(now + Duration::from_secs(1)));
+
+    // moving and resizing on the display, the displays aren't listed again
+    let change = follower.update(
+        Some(&window(300, 200, 800, 600)),
+        || panic!("displays listed"),
+        now,
+    );
+    assert_eq!(change, WindowChange::Unchanged);
+
+    let cropped = follower.crop(new_frame(1920, 1080)).unwrap();
+    assert_eq!((cropped.width, cropped.height), (800, 600));
+    assert_eq!(cropped.luminance_bytes[0], (300 % 256) as u8);
+
+    let change = follower.update(Some(&window(2200, 100, 640, 480)), displays, now);
+    assert_eq!(change, WindowChange::Display(String::from("right")));
+    assert_eq!(follower.display_id(), "right");
+
+    let cropped = follower.crop(new_frame(1280, 1024)).unwrap();
+    assert_eq!((cropped.width, cropped.height), (640, 480));
+
+    // nothing leaves while it's minimized
+    let minimized = WindowInfo {
+        visible: false,
+        ..window(2200, 100, 640, 480)
+    };
+    follower.update(Some(&minimized), displays, now);
+    assert!(follower.crop(new_frame(1280, 1024)).is_none());
+
+    assert_eq!(follower.update(None, displays, now), WindowChange::Closed);
+}
+
+fn monitor(id: &str, area: &CaptureArea) -> Arc<Monitor> {
+    Arc::new(Monitor {
+        id: String::from(id),
+        name: String::from(id),
+        refresh_rate: 60,
+        width: area.width as u16,
+        height: area.height as u16,
+        is_primary: id == "primary",
+        screen_shot: None,
+        left: area.left as i32,
+        top: area.top as i32,
+    })
+}
+
+#[test]
+fn test_input_lands_on_shared_window() {
+    let now = Instant::now();
+    let displays = displays();
+    let captured_window = CapturedWindow::default();
+
+    let click = MouseEvent::Down(MouseKey::Left, 0.5, 0.5);
+    assert_eq!(map_to_window(&captured_window, &click), None);
+
+    let follower = WindowFollower::new(&window(2240, 112, 640, 400), &displays, now).unwrap();
+    let right = monitor("right", &displays[1].1);
+    captured_window.set(right.clone(), &follower);
+
+    // the center of the window, not of its display
+    let (target, event) = map_to_window(&captured_window, &click).unwrap();
+    assert_eq!(target, right);
+    assert_eq!(
+        event,
+        MouseEvent::Down(MouseKey::Left, 640.0 / 1280.0, 312.0 / 1024.0)
+    );
+
+    // a window hanging over the edge shows only its part on the display
+    let follower = WindowFollower::new(&window(-320, 540, 640, 540), &displays, now).unwrap();
+    let primary = monitor("primary", &displays[0].1);
+    captured_window.set(primary.clone(), &follower);
+
+    let corner = MouseEvent::Move(MouseKey::None, 0.0, 0.0);
+    assert_eq!(
+        map_to_window(&captured_window, &corner),
+        Some((primary.clone(), MouseEvent::Move(MouseKey::None, 0.0, 0.5)))
+    );
+
+    let scroll = MouseEvent::ScrollWheel(3.0);
+    assert_eq!(
+        map_to_window(&captured_window, &scroll),
+        Some((primary, scroll.clone()))
+    );
+
+    captured_window.clear();
+    assert_eq!(map_to_window(&captured_window, &scroll), None);
+}
@@ -5,6 +5,8 @@ use bincode::{
 };
 use once_cell::sync::Lazy;
 
+/// Endpoint packets announce this config in their codec fingerprint, keep
+/// `packet::CODEC_CONFIG` in step when changing it.
 static SERIALIZER: Lazy<
     WithOtherIntEncoding<WithOtherEndian<DefaultOptions, LittleEndian>, VarintEncoding>,
 > = Lazy::new(|| {
@@ -21,6 +23,15 @@ where
     Ok(buffer)
 }
 
+pub fn bincode_serialize_into<W, S>(writer: W, t: &S) -> CoreResult<()>
+where
+    W: std::io::Write,
+    S: ?Sized + serde::Serialize,
+{
+    SERIALIZER.serialize_into(writer, t)?;
+    Ok(())
+}
+
 pub fn bincode_deserialize<'a, T>(bytes: &'a [u8]) -> CoreResult<T>
 where
     T: serde::Deserialize<'a>,
@@ -0,0 +1,34 @@
+use once_cell::sync::OnceCell;
+use std::sync::{PoisonError, RwLock};
+
+/// A process wide setting, `init` gives its value until the first `set`.
+/// Connections and sessions read it once when they start, a new value
+/// applies to those started afterwards.
+pub struct ConfigCell<T> {
+    value: OnceCell<RwLock<T>>,
+    init: fn() -> T,
+}
+
+impl<T: Clone> ConfigCell<T> {
+    pub const fn new(init: fn() -> T) -> Self {
+        Self {
+            value: OnceCell::new(),
+            init,
+        }
+    }
+
+    pub fn set(&self, value: T) {
+        *self.value().write().unwrap_or_else(PoisonError::into_inner) = value;
+    }
+
+    pub fn get(&self) -> T {
+        self.value()
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn value(&self) -> &RwLock<T> {
+        self.value.get_or_init(|| RwLock::new((self.init)()))
+    }
+}
@@ -0,0 +1,250 @@
+use crate::api::endpoint::id::EndPointID;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// How long repeats of an error are folded into one event.
+pub const DEFAULT_ERROR_EVENT_WINDOW: Duration = Duration::from_secs(5);
+
+pub const MIN_ERROR_EVENT_WINDOW: Duration = Duration::from_secs(1);
+
+pub const MAX_ERROR_EVENT_WINDOW: Duration = Duration::from_secs(5 * 60);
+
+/// Distinct errors followed at once, those beyond are only logged.
+pub const MAX_ERROR_BURSTS: usize = 256;
+
+static ERROR_EVENTS: Lazy<Mutex<ErrorEventAggregator>> =
+    Lazy::new(|| Mutex::new(ErrorEventAggregator::new(DEFAULT_ERROR_EVENT_WINDOW)));
+
+static ERROR_EVENT_TX: Lazy<tokio::sync::broadcast::Sender<ErrorEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+/// Where an error reported to the UI came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorSource {
+    /// A frame failed authentication or decryption.
+    PacketOpen,
+    /// A message couldn't be deserialized.
+    MessageDeserialize,
+    /// Reading from the connection of a session failed.
+    Socket,
+    /// The screen capture returned an error.
+    Capture,
+    /// A video frame couldn't be decoded.
+    Decode,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ErrorEvent {
+    /// The first occurrence of an error, or the repeats of an ongoing burst
+    /// since its last event.
+    Occurred {
+        source: ErrorSource,
+        endpoint_id: String,
+        message: String,
+        count: u64,
+    },
+    /// An error didn't repeat for a window, the burst is over.
+    BurstEnded {
+        source: ErrorSource,
+        endpoint_id: String,
+        message: String,
+        /// Occurrences over the whole burst.
+        count: u64,
+        duration_ms: u64,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct ErrorKey {
+    source: ErrorSource,
+    endpoint_id: String,
+    message: String,
+}
+
+#[derive(Debug)]
+struct ErrorBurst {
+    started_at: Instant,
+    last_seen: Instant,
+    last_emitted: Instant,
+    count: u64,
+    unreported: u64,
+}
+
+/// Folds identical errors, those of the same source, session and message,
+/// into one event per window. A burst that ends is summed up once.
+#[derive(Debug)]
+pub struct ErrorEventAggregator {
+    window: Duration,
+    bursts: HashMap<ErrorKey, ErrorBurst>,
+}
+
+impl ErrorEventAggregator {
+    pub fn new(window: Duration) -> Self {
+        Self {
+            window: window.clamp(MIN_ERROR_EVENT_WINDOW, MAX_ERROR_EVENT_WINDOW),
+            bursts: HashMap::new(),
+        }
+    }
+
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    pub fn set_window(&mut self, window: Duration) {
+        self.window = window.clamp(MIN_ERROR_EVENT_WINDOW, MAX_ERROR_EVENT_WINDOW);
+    }
+
+    /// Records an occurrence of an error, returns the event to emit right
+    /// away: the first of a burst, or the repeats since the last event once
+    /// a window passed.
+    pub fn record(
+        &mut self,
+        source: ErrorSource,
+        endpoint_id: &str,
+        message: &str,
+        now: Instant,
+    ) -> Option<ErrorEvent> {
+        let key = ErrorKey {
+            source,
+            endpoint_id: endpoint_id.to_string(),
+            message: message.to_string(),
+        };
+
+        if let Some(burst) = self.bursts.get_mut(&key) {
+            burst.last_seen = now;
+            burst.count += 1;
+            burst.unreported += 1;
+
+            if now.saturating_duration_since(burst.last_emitted) < self.window {
+                return None;
+            }
+
+            burst.last_emitted = now;
+            let count = std::mem::take(&mut burst.unreported);
+            return Some(occurred(key, count));
+        }
+
+        if self.bursts.len() >= MAX_ERROR_BURSTS {
+            return None;
+        }
+
+        self.bursts.insert(
+            key.clone(),
+            ErrorBurst {
+                started_at: now,
+                last_seen: now,
+                last_emitted: now,
+                count: 1,
+                unreported: 0,
+            },
+        );
+
+        Some(occurred(key, 1))
+    }
+
+    /// Ends the bursts that didn't repeat for a window and emits the repeats
+    /// the ongoing ones held back for a window. A single occurrence ends
+    /// without a summary, its event said it all.
+    pub fn flush(&mut self, now: Instant) -> Vec<ErrorEvent> {
+        let mut events = Vec::new();
+        let window = self.window;
+
+        self.bursts.retain(|key, burst| {
+            if now.saturating_duration_since(burst.last_seen) >= window {
+                if burst.count > 1 {
+                    events.push(ErrorEvent::BurstEnded {
+                        source: key.source,
+                        endpoint_id: key.endpoint_id.clone(),
+                        message: key.message.clone(),
+                        count: burst.count,
+                        duration_ms: burst
+                            .last_seen
+                            .saturating_duration_since(burst.started_at)
+                            .as_millis() as u64,
+                    });
+                }
+
+                return false;
+            }
+
+            if burst.unreported > 0 && now.saturating_duration_since(burst.last_emitted) >= window {
+                burst.last_emitted = now;
+                let count = std::mem::take(&mut burst.unreported);
+                events.push(occurred(key.clone(), count));
+            }
+
+            true
+        });
+
+        events
+    }
+
+    pub fn len(&self) -> usize {
+        self.bursts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bursts.is_empty()
+    }
+}
+
+fn occurred(key: ErrorKey, count: u64) -> ErrorEvent {
+    ErrorEvent::Occurred {
+        source: key.source,
+        endpoint_id: key.endpoint_id,
+        message: key.message,
+        count,
+    }
+}
+
+/// Applies to the bursts under way too.
+pub fn set_error_event_window(window: Duration) {
+    error_events().set_window(window);
+}
+
+pub fn error_event_window() -> Duration {
+    error_events().window()
+}
+
+pub fn subscribe_error_event() -> tokio::sync::broadcast::Receiver<ErrorEvent> {
+    ERROR_EVENT_TX.subscribe()
+}
+
+/// Reports an error of a session to the UI, repeats are folded by
+/// [`ErrorEventAggregator`]. The message should be the same for the same
+/// failure, details like counters make every occurrence distinct.
+pub(crate) fn report_error_event(source: ErrorSource, endpoint_id: &EndPointID, message: &str) {
+    let event = error_events().record(source, &endpoint_id.to_string(), message, Instant::now());
+
+    if let Some(event) = event {
+        let _ = ERROR_EVENT_TX.send(event);
+    }
+}
+
+/// Emits the repeats and summaries of the error bursts as they come due.
+pub fn serve_error_events() {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(MIN_ERROR_EVENT_WINDOW);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            let events = error_events().flush(Instant::now());
+            for event in events {
+                let _ = ERROR_EVENT_TX.send(event);
+            }
+        }
+    });
+}
+
+fn error_events() -> MutexGuard<'static, ErrorEventAggregator> {
+    ERROR_EVENTS.lock().unwrap_or_else(PoisonError::into_inner)
+}
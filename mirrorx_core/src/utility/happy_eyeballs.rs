@@ -0,0 +1,99 @@
+use crate::{
+    bail_core,
+    error::{CoreError, CoreResult},
+};
+use futures::{stream::FuturesUnordered, StreamExt};
+use std::{future::Future, net::SocketAddr, time::Duration};
+
+/// How long an attempt has to itself before the next address is tried
+/// alongside, see RFC 8305.
+pub const CONNECTION_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Orders the addresses of a host alternating between IPv6 and IPv4,
+/// starting with the family listed first. A dead family then costs one
+/// attempt delay rather than all of its addresses.
+pub fn interleave_addrs(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+    let Some(first) = addrs.first() else {
+        return Vec::new();
+    };
+
+    let (mut preferred, mut other): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs
+        .iter()
+        .partition(|addr| addr.is_ipv6() == first.is_ipv6());
+    preferred.reverse();
+    other.reverse();
+
+    let mut interleaved = Vec::with_capacity(addrs.len());
+    while let Some(addr) = preferred.pop() {
+        interleaved.push(addr);
+        interleaved.extend(other.pop());
+    }
+    interleaved.extend(other.into_iter().rev());
+
+    interleaved
+}
+
+/// Connects to the first of `addrs` that answers. Attempts start one
+/// [`CONNECTION_ATTEMPT_DELAY`] apart, or right away once the previous ones
+/// failed, and run alongside each other. The first to connect wins, the
+/// others are dropped with their sockets. Fails with [`CoreError::Timeout`]
+/// once `timeout` passed for all of them together.
+pub async fn connect_first<T, F, Fut>(
+    addrs: &[SocketAddr],
+    timeout: Duration,
+    mut connect: F,
+) -> CoreResult<T>
+where
+    F: FnMut(SocketAddr) -> Fut,
+    Fut: Future<Output = CoreResult<T>>,
+{
+    if addrs.is_empty() {
+        bail_core!("no address to connect to");
+    }
+
+    let mut next_addrs = interleave_addrs(addrs).into_iter();
+    let mut start = |addr: SocketAddr| {
+        let attempt = connect(addr);
+        async move { (addr, attempt.await) }
+    };
+
+    let race = async {
+        let mut attempts = FuturesUnordered::new();
+        let mut last_err = None;
+
+        loop {
+            if attempts.is_empty() {
+                match next_addrs.next() {
+                    Some(addr) => attempts.push(start(addr)),
+                    None => return Err(last_err.unwrap_or(CoreError::Timeout)),
+                }
+            }
+
+            tokio::select! {
+                Some((addr, result)) = attempts.next() => match result {
+                    Ok(connected) => {
+                        tracing::info!(%addr, "connected");
+                        return Ok(connected);
+                    }
+                    Err(err) => {
+                        tracing::warn!(%addr, ?err, "connect failed");
+                        last_err = Some(err);
+
+                        if let Some(addr) = next_addrs.next() {
+                            attempts.push(start(addr));
+                        }
+                    }
+                },
+                _ = tokio::time::sleep(CONNECTION_ATTEMPT_DELAY), if !next_addrs.as_slice().is_empty() => {
+                    if let Some(addr) = next_addrs.next() {
+                        attempts.push(start(addr));
+                    }
+                }
+            }
+        }
+    };
+
+    tokio::time::timeout(timeout, race)
+        .await
+        .map_err(|_| CoreError::Timeout)?
+}
@@ -10,6 +10,8 @@ macro_rules! HRESULT {
     };
 }
 
+/// Builds a [`CoreError::Other`](crate::error::CoreError::Other) with the
+/// file and line of the call.
 #[macro_export]
 macro_rules! core_error {
     ($($arg:tt)*) => {
@@ -21,9 +23,17 @@ macro_rules! core_error {
     };
 }
 
+/// Returns a [`core_error!`] early, `return Err(core_error!(..))` in short.
+#[macro_export]
+macro_rules! bail_core {
+    ($($arg:tt)*) => {
+        return Err($crate::core_error!($($arg)*))
+    };
+}
+
 #[macro_export]
 macro_rules! call {
     ($exp:expr) => {
-        bincode_serialize(&$exp.map_err(|err| err.to_string()))
+        $crate::utility::bincode::bincode_serialize(&$exp.map_err(|err| err.to_string()))
     };
 }
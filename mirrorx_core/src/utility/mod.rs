@@ -1,6 +1,11 @@
 pub mod bincode;
+pub mod config_cell;
+pub mod error_event;
+pub mod happy_eyeballs;
 pub mod lan_ip;
 pub mod macros;
 pub mod nonce_value;
 pub mod os;
+pub mod permission;
 pub mod rand;
+pub mod thread_priority;
@@ -17,11 +17,14 @@ impl NonceValue {
 }
 
 impl NonceSequence for NonceValue {
+    /// Fails for good once the nonce reached its maximum, wrapping around
+    /// would use nonces of the key again. The stream has to close then.
     fn advance(&mut self) -> Result<ring::aead::Nonce, ring::error::Unspecified> {
-        self.0 += 1;
-        if self.0 > NONCE_MAX {
-            self.0 = 1;
+        if self.0 >= NONCE_MAX {
+            error!("nonce sequence exhausted");
+            return Err(ring::error::Unspecified);
         }
+        self.0 += 1;
 
         unsafe {
             let nonce_bytes = self.0.to_le_bytes();
@@ -1,10 +1,40 @@
 use crate::error::CoreResult;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphicsCardVendor {
+    Nvidia,
+    Amd,
+    Intel,
+    Apple,
+    Unknown,
+}
+
+impl GraphicsCardVendor {
+    pub fn from_pci_vendor_id(vendor_id: u32) -> Self {
+        match vendor_id {
+            0x10DE => GraphicsCardVendor::Nvidia,
+            0x1002 | 0x1022 => GraphicsCardVendor::Amd,
+            0x8086 => GraphicsCardVendor::Intel,
+            0x106B => GraphicsCardVendor::Apple,
+            _ => GraphicsCardVendor::Unknown,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub struct GraphicsCards {
-    name: String,
-    is_default: bool,
+    /// Stable identifier of the card, the PnP device id on Windows and the
+    /// registry id on macOS.
+    pub id: String,
+    pub name: String,
+    pub vendor: GraphicsCardVendor,
+    /// Position of the card among the cards of the same vendor, hardware
+    /// encoders like nvenc address devices by this index.
+    pub vendor_index: u32,
+    pub is_default: bool,
 }
 
 pub fn enum_graphics_cards() -> CoreResult<Vec<GraphicsCards>> {
@@ -13,16 +43,29 @@ pub fn enum_graphics_cards() -> CoreResult<Vec<GraphicsCards>> {
     #[cfg(target_os = "macos")]
     {
         let default_device = metal::Device::system_default();
-        let default_device_name =
-            default_device.map_or(String::default(), |device| device.name().to_string());
+        let default_device_id = default_device.map_or(0, |device| device.registry_id());
 
         let devices = metal::Device::all();
         for device in devices {
             let device_name = device.name().to_string();
-            let is_default = device_name == default_device_name;
+            let vendor = if device_name.contains("Apple") {
+                GraphicsCardVendor::Apple
+            } else if device_name.contains("AMD") || device_name.contains("Radeon") {
+                GraphicsCardVendor::Amd
+            } else if device_name.contains("Intel") {
+                GraphicsCardVendor::Intel
+            } else if device_name.contains("NVIDIA") {
+                GraphicsCardVendor::Nvidia
+            } else {
+                GraphicsCardVendor::Unknown
+            };
+
             graphics_cards.push(GraphicsCards {
+                id: device.registry_id().to_string(),
                 name: device_name,
-                is_default,
+                vendor,
+                vendor_index: 0,
+                is_default: device.registry_id() == default_device_id,
             });
         }
     }
@@ -36,6 +79,8 @@ pub fn enum_graphics_cards() -> CoreResult<Vec<GraphicsCards>> {
         struct VideoControllerInfo {
             #[serde(rename(deserialize = "Name"))]
             name: String,
+            #[serde(rename(deserialize = "PNPDeviceID"))]
+            pnp_device_id: String,
         }
 
         let com_con = unsafe { wmi::COMLibrary::assume_initialized() };
@@ -46,12 +91,30 @@ pub fn enum_graphics_cards() -> CoreResult<Vec<GraphicsCards>> {
             .map_err(|err| core_error!("wmi query error ({})", err))?;
 
         for info in result {
+            // PnP device id looks like "PCI\VEN_10DE&DEV_2484&..."
+            let vendor_id = info
+                .pnp_device_id
+                .find("VEN_")
+                .and_then(|pos| info.pnp_device_id.get(pos + 4..pos + 8))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .unwrap_or_default();
+
             graphics_cards.push(GraphicsCards {
+                id: info.pnp_device_id,
                 name: info.name,
+                vendor: GraphicsCardVendor::from_pci_vendor_id(vendor_id),
+                vendor_index: 0,
                 is_default: false,
             });
         }
     }
 
+    let mut vendor_counts = HashMap::new();
+    for card in graphics_cards.iter_mut() {
+        let count = vendor_counts.entry(card.vendor).or_insert(0);
+        card.vendor_index = *count;
+        *count += 1;
+    }
+
     Ok(graphics_cards)
 }
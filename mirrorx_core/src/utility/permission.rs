@@ -0,0 +1,94 @@
+use crate::error::{CoreError, CoreResult};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum PermissionStatus {
+    Granted,
+    Denied,
+    /// The platform doesn't gate this capability behind a permission.
+    NotRequired,
+}
+
+impl PermissionStatus {
+    pub fn is_usable(&self) -> bool {
+        !matches!(self, PermissionStatus::Denied)
+    }
+}
+
+/// Permissions a hosted desktop session depends on. Screen capture is
+/// required to share the screen, accessibility to inject remote input.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct PlatformPermissions {
+    pub screen_capture: PermissionStatus,
+    pub accessibility: PermissionStatus,
+}
+
+impl PlatformPermissions {
+    pub fn ensure_screen_capture(&self) -> CoreResult<()> {
+        if self.screen_capture.is_usable() {
+            Ok(())
+        } else {
+            Err(CoreError::ScreenCapturePermissionDenied)
+        }
+    }
+
+    pub fn ensure_accessibility(&self) -> CoreResult<()> {
+        if self.accessibility.is_usable() {
+            Ok(())
+        } else {
+            Err(CoreError::AccessibilityPermissionDenied)
+        }
+    }
+}
+
+/// Checks the permissions of this process. With `prompt` the system asks the
+/// user for the missing ones, the answer only applies after a restart of the
+/// app on macOS, so the returned status still reflects the current process.
+#[cfg(target_os = "macos")]
+pub fn check_platform_permissions(prompt: bool) -> PlatformPermissions {
+    use core_foundation::{
+        base::TCFType, boolean::CFBoolean, dictionary::CFDictionary, string::CFString,
+    };
+    use mirrorx_native::os::macos::{application_services::*, core_graphics::*};
+
+    unsafe {
+        let mut screen_capture = CGPreflightScreenCaptureAccess();
+        if !screen_capture && prompt {
+            screen_capture = CGRequestScreenCaptureAccess();
+        }
+
+        let accessibility = if prompt {
+            let options = CFDictionary::from_CFType_pairs(&[(
+                CFString::wrap_under_get_rule(kAXTrustedCheckOptionPrompt),
+                CFBoolean::true_value(),
+            )]);
+
+            AXIsProcessTrustedWithOptions(options.as_concrete_TypeRef())
+        } else {
+            AXIsProcessTrusted()
+        };
+
+        PlatformPermissions {
+            screen_capture: status_from_granted(screen_capture),
+            accessibility: status_from_granted(accessibility),
+        }
+    }
+}
+
+#[cfg(not(target_os = "macos"))]
+pub fn check_platform_permissions(_prompt: bool) -> PlatformPermissions {
+    PlatformPermissions {
+        screen_capture: PermissionStatus::NotRequired,
+        accessibility: PermissionStatus::NotRequired,
+    }
+}
+
+#[cfg(target_os = "macos")]
+fn status_from_granted(granted: bool) -> PermissionStatus {
+    if granted {
+        PermissionStatus::Granted
+    } else {
+        PermissionStatus::Denied
+    }
+}
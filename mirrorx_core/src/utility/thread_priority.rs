@@ -0,0 +1,170 @@
+use crate::{error::CoreResult, utility::config_cell::ConfigCell};
+use once_cell::sync::Lazy;
+use serde::Serialize;
+
+static MEDIA_THREAD_PRIORITY: ConfigCell<bool> = ConfigCell::new(|| false);
+
+static THREAD_PRIORITY_TX: Lazy<tokio::sync::broadcast::Sender<ThreadPriorityEvent>> =
+    Lazy::new(|| tokio::sync::broadcast::channel(16).0);
+
+/// Whether the capture and encode threads of a shared screen run at the
+/// highest priority. Off by default, the media threads may starve other
+/// applications on a busy machine.
+pub fn set_media_thread_priority(enabled: bool) {
+    MEDIA_THREAD_PRIORITY.set(enabled);
+}
+
+pub fn media_thread_priority() -> bool {
+    MEDIA_THREAD_PRIORITY.get()
+}
+
+/// Outcome of raising the priority of a media thread, it may need
+/// privileges the process doesn't have.
+#[derive(Debug, Clone, Serialize)]
+pub struct ThreadPriorityEvent {
+    pub endpoint_id: String,
+    /// `capture` or `encode`.
+    pub thread: &'static str,
+    pub elevated: bool,
+    pub reason: Option<String>,
+}
+
+pub fn subscribe_thread_priority() -> tokio::sync::broadcast::Receiver<ThreadPriorityEvent> {
+    THREAD_PRIORITY_TX.subscribe()
+}
+
+fn report_thread_priority(event: ThreadPriorityEvent) {
+    if event.elevated {
+        tracing::info!(?event, "media thread priority raised");
+    } else {
+        tracing::warn!(?event, "raise media thread priority failed");
+    }
+
+    let _ = THREAD_PRIORITY_TX.send(event);
+}
+
+/// Restores the priority the thread had before, pooled threads go back to
+/// normal work once the session ends.
+pub struct ThreadPriorityGuard {
+    #[cfg(target_os = "windows")]
+    previous: i32,
+    #[cfg(target_os = "macos")]
+    previous: (libc::qos_class_t, libc::c_int),
+}
+
+/// Raises the priority of the calling thread when the setting is on and
+/// reports the outcome. Keep the guard for as long as the thread works for
+/// the session.
+pub fn elevate_media_thread(
+    endpoint_id: String,
+    thread: &'static str,
+) -> Option<ThreadPriorityGuard> {
+    if !media_thread_priority() {
+        return None;
+    }
+
+    let (guard, reason) = match elevate_current_thread() {
+        Ok(guard) => (Some(guard), None),
+        Err(err) => (None, Some(err.to_string())),
+    };
+
+    report_thread_priority(ThreadPriorityEvent {
+        endpoint_id,
+        thread,
+        elevated: guard.is_some(),
+        reason,
+    });
+
+    guard
+}
+
+/// THREAD_PRIORITY_TIME_CRITICAL on Windows.
+#[cfg(target_os = "windows")]
+pub fn elevate_current_thread() -> CoreResult<ThreadPriorityGuard> {
+    use crate::core_error;
+    use windows::Win32::System::Threading::{
+        GetCurrentThread, GetThreadPriority, SetThreadPriority, THREAD_PRIORITY_TIME_CRITICAL,
+    };
+
+    unsafe {
+        let thread = GetCurrentThread();
+
+        let previous = GetThreadPriority(thread);
+        // THREAD_PRIORITY_ERROR_RETURN
+        if previous == i32::MAX {
+            return Err(core_error!("GetThreadPriority failed"));
+        }
+
+        if !SetThreadPriority(thread, THREAD_PRIORITY_TIME_CRITICAL).as_bool() {
+            return Err(core_error!(
+                "SetThreadPriority failed: {}",
+                std::io::Error::last_os_error()
+            ));
+        }
+
+        Ok(ThreadPriorityGuard { previous })
+    }
+}
+
+/// The user interactive QoS class on macOS.
+#[cfg(target_os = "macos")]
+pub fn elevate_current_thread() -> CoreResult<ThreadPriorityGuard> {
+    use crate::core_error;
+
+    unsafe {
+        let mut class = libc::qos_class_t::QOS_CLASS_UNSPECIFIED;
+        let mut relative_priority = 0;
+
+        let ret = libc::pthread_get_qos_class_np(
+            libc::pthread_self(),
+            &mut class,
+            &mut relative_priority,
+        );
+        if ret != 0 {
+            return Err(core_error!("pthread_get_qos_class_np failed ({})", ret));
+        }
+
+        let ret =
+            libc::pthread_set_qos_class_self_np(libc::qos_class_t::QOS_CLASS_USER_INTERACTIVE, 0);
+        if ret != 0 {
+            return Err(core_error!(
+                "pthread_set_qos_class_self_np failed ({})",
+                ret
+            ));
+        }
+
+        Ok(ThreadPriorityGuard {
+            previous: (class, relative_priority),
+        })
+    }
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub fn elevate_current_thread() -> CoreResult<ThreadPriorityGuard> {
+    Err(crate::core_error!(
+        "raising thread priority is unsupported on this platform"
+    ))
+}
+
+impl Drop for ThreadPriorityGuard {
+    fn drop(&mut self) {
+        #[cfg(target_os = "windows")]
+        unsafe {
+            use windows::Win32::System::Threading::{
+                GetCurrentThread, SetThreadPriority, THREAD_PRIORITY,
+            };
+
+            if !SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY(self.previous)).as_bool() {
+                tracing::warn!("restore thread priority failed");
+            }
+        }
+
+        #[cfg(target_os = "macos")]
+        unsafe {
+            let (class, relative_priority) = self.previous;
+            if libc::pthread_set_qos_class_self_np(class, relative_priority) != 0 {
+                tracing::warn!("restore thread qos class failed");
+            }
+        }
+    }
+}
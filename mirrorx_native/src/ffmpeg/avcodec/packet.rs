@@ -35,6 +35,10 @@ pub const AV_PKT_DATA_DOVI_CONF: AVPacketSideDataType = 29;
 pub const AV_PKT_DATA_S12M_TIMECODE: AVPacketSideDataType = 30;
 pub const AV_PKT_DATA_DYNAMIC_HDR10_PLUS: AVPacketSideDataType = 31;
 
+pub const AV_PKT_FLAG_KEY: i32 = 0x0001;
+pub const AV_PKT_FLAG_CORRUPT: i32 = 0x0002;
+pub const AV_PKT_FLAG_DISCARD: i32 = 0x0004;
+
 #[repr(C)]
 pub struct AVPacketSideData {
     pub data: *mut u8,
@@ -72,4 +76,9 @@ extern "C" {
     pub fn av_new_packet(pkt: *mut AVPacket, size: i32) -> i32;
     pub fn av_packet_unref(pkt: *mut AVPacket);
     pub fn av_packet_rescale_ts(pkt: *mut AVPacket, tb_src: AVRational, tb_dst: AVRational);
+    pub fn av_packet_get_side_data(
+        pkt: *const AVPacket,
+        typ: AVPacketSideDataType,
+        size: *mut usize,
+    ) -> *mut u8;
 }
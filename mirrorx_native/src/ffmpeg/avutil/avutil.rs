@@ -19,6 +19,8 @@ pub const AV_PICTURE_TYPE_SI: AVPictureType = 5;
 pub const AV_PICTURE_TYPE_SP: AVPictureType = 6;
 pub const AV_PICTURE_TYPE_BI: AVPictureType = 7;
 
+pub const FF_QP2LAMBDA: i32 = 118;
+
 pub type AVMediaType = i32;
 pub const AVMEDIA_TYPE_UNKNOWN: AVMediaType = -1;
 pub const AVMEDIA_TYPE_VIDEO: AVMediaType = 0;
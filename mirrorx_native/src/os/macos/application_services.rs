@@ -0,0 +1,10 @@
+use core_foundation::{dictionary::CFDictionaryRef, string::CFStringRef};
+
+extern "C" {
+    pub static kAXTrustedCheckOptionPrompt: CFStringRef;
+}
+
+extern "C" {
+    pub fn AXIsProcessTrusted() -> bool;
+    pub fn AXIsProcessTrustedWithOptions(options: CFDictionaryRef) -> bool;
+}
@@ -14,6 +14,12 @@ pub const kCGDisplayStreamFrameStatusFrameIdle: CGDisplayStreamFrameStatus = 1;
 pub const kCGDisplayStreamFrameStatusFrameBlank: CGDisplayStreamFrameStatus = 2;
 pub const kCGDisplayStreamFrameStatusStopped: CGDisplayStreamFrameStatus = 3;
 
+pub type CGDisplayStreamUpdateRectType = i32;
+pub const kCGDisplayStreamUpdateRefreshedRects: CGDisplayStreamUpdateRectType = 0;
+pub const kCGDisplayStreamUpdateMovedRects: CGDisplayStreamUpdateRectType = 1;
+pub const kCGDisplayStreamUpdateDirtyRects: CGDisplayStreamUpdateRectType = 2;
+pub const kCGDisplayStreamUpdateReducedDirtyRects: CGDisplayStreamUpdateRectType = 3;
+
 pub type CGDisplayStreamFrameAvailableHandler<'a> = &'a block::Block<
     (
         CGDisplayStreamFrameStatus,
@@ -24,8 +30,18 @@ pub type CGDisplayStreamFrameAvailableHandler<'a> = &'a block::Block<
     (),
 >;
 
+pub type CGDisplayChangeSummaryFlags = u32;
+pub const kCGDisplayBeginConfigurationFlag: CGDisplayChangeSummaryFlags = 1 << 0;
+
+pub type CGDisplayReconfigurationCallBack = extern "C" fn(
+    display: core_graphics::display::CGDirectDisplayID,
+    flags: CGDisplayChangeSummaryFlags,
+    user_info: *mut c_void,
+);
+
 extern "C" {
     pub static kUTTypePNG: CFStringRef;
+    pub static kCGDisplayStreamShowCursor: CFStringRef;
 }
 
 extern "C" {
@@ -43,6 +59,11 @@ extern "C" {
         -> core_graphics::base::CGError;
     pub fn CGDisplayStreamStop(display_stream: CGDisplayStreamRef) -> core_graphics::base::CGError;
     pub fn CGDisplayStreamUpdateGetDropCount(update_ref: CGDisplayStreamUpdateRef) -> u32;
+    pub fn CGDisplayStreamUpdateGetRects(
+        update_ref: CGDisplayStreamUpdateRef,
+        rect_type: CGDisplayStreamUpdateRectType,
+        rect_count: *mut usize,
+    ) -> *const core_graphics::geometry::CGRect;
     pub fn CGImageDestinationCreateWithData(
         data: CFMutableDataRef,
         typ: CFStringRef,
@@ -56,4 +77,10 @@ extern "C" {
     );
     pub fn CGImageDestinationFinalize(idst: CGImageDestinationRef) -> bool;
     pub fn CGImageRelease(image: CGImageRef);
+    pub fn CGDisplayRegisterReconfigurationCallback(
+        callback: CGDisplayReconfigurationCallBack,
+        user_info: *mut c_void,
+    ) -> core_graphics::base::CGError;
+    pub fn CGPreflightScreenCaptureAccess() -> bool;
+    pub fn CGRequestScreenCaptureAccess() -> bool;
 }
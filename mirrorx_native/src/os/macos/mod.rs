@@ -1,3 +1,4 @@
+pub mod application_services;
 pub mod core_foundation;
 pub mod core_graphics;
 pub mod core_media;